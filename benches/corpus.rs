@@ -0,0 +1,244 @@
+//! Generators for representative Markdown documents.
+//!
+//! Each generator is deterministic, so that benchmark results are comparable
+//! between runs, and takes a size parameter so that the same shapes can be
+//! produced at different scales.  The module is shared by the benchmark
+//! suite and the integration tests, and is not part of the library.
+
+#![allow(dead_code)]
+
+use std::fmt::Write;
+
+/// A small deterministic pseudo-random number generator (a 64-bit LCG).
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+const WORDS: &[&str] = &[
+    "the",
+    "formatter",
+    "document",
+    "paragraph",
+    "consistent",
+    "style",
+    "a",
+    "of",
+    "and",
+    "to",
+    "Markdown",
+    "line",
+    "width",
+    "wrap",
+    "is",
+    "should",
+    "reference",
+    "output",
+    "input",
+    "heading",
+    "list",
+    "table",
+    "code",
+    "block",
+    "every",
+    "when",
+    "with",
+    "without",
+    "readable",
+    "source",
+];
+
+const INLINES: &[&str] = &[
+    "*emphasis*",
+    "**strong text**",
+    "`inline_code()`",
+    "[a link](https://example.com/path)",
+    "snake_case_name",
+    "2 * 3 = 6",
+    "<not a tag>",
+    "[brackets]",
+    "\"quoted\"",
+    "it's",
+    "wait...",
+    "a -- b",
+    "#hash",
+    "100%",
+];
+
+const CJK_PHRASES: &[&str] = &[
+    "마크다운 문서를",
+    "일관된 형식으로",
+    "정리하는 도구입니다",
+    "한국어와 English가",
+    "섞인 문장도",
+    "日本語の文章も",
+    "正しく折り返されます",
+    "中文段落也",
+    "按照显示宽度换行",
+    "「括弧」や『引用』",
+    "（全角括号）",
+    "、",
+    "。",
+];
+
+/// Writes a sentence of roughly `words` words to `out`.
+fn sentence(rng: &mut Rng, out: &mut String, words: usize) {
+    for i in 0..words {
+        if i > 0 {
+            out.push(' ');
+        }
+        if rng.below(6) == 0 {
+            out.push_str(rng.pick(INLINES));
+        } else {
+            let word = rng.pick(WORDS);
+            if i == 0 {
+                let mut chars = word.chars();
+                if let Some(first) = chars.next() {
+                    out.extend(first.to_uppercase());
+                    out.push_str(chars.as_str());
+                }
+            } else {
+                out.push_str(word);
+            }
+        }
+    }
+    out.push('.');
+}
+
+/// Long prose paragraphs with inline markup and characters that need
+/// escaping, written as long unwrapped lines.
+pub fn prose(paragraphs: usize) -> String {
+    let mut rng = Rng::new(1);
+    let mut out = String::from("Prose\n=====\n\n");
+    for _ in 0..paragraphs {
+        let sentences = 3 + rng.below(6);
+        for i in 0..sentences {
+            if i > 0 {
+                out.push(' ');
+            }
+            let words = 8 + rng.below(16);
+            sentence(&mut rng, &mut out, words);
+        }
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// Deeply nested lists mixing unordered and ordered items, written with
+/// non-canonical markers and indentation.
+pub fn nested_lists(items: usize, depth: usize) -> String {
+    let mut rng = Rng::new(2);
+    let mut out = String::from("# Lists\n\n");
+    for i in 0..items {
+        let level = i % depth;
+        let indent = "  ".repeat(level * 2);
+        if level.is_multiple_of(2) {
+            out.push_str(&indent);
+            out.push_str(["* ", "- ", "+ "][level % 3]);
+        } else {
+            let _ = write!(out, "{}{}. ", indent, i % 9 + 1);
+        }
+        let words = 4 + rng.below(20);
+        sentence(&mut rng, &mut out, words);
+        out.push('\n');
+    }
+    out
+}
+
+/// A table with `rows` rows of uneven cells, including escaped pipes,
+/// inline code, and wide characters.
+pub fn table(rows: usize) -> String {
+    let mut rng = Rng::new(3);
+    let mut out =
+        String::from("# Table\n\n| Name | Type | Default | Description |\n|---|:---:|--:|:--|\n");
+    for i in 0..rows {
+        let name = format!("`option_{}`", i);
+        let kind = rng.pick(&["string", "int", "bool", "a \\| b", "설정"]);
+        let default = rng.pick(&["`\"\"`", "0", "true", "none", "[]"]);
+        let mut description = String::new();
+        let words = 3 + rng.below(12);
+        sentence(&mut rng, &mut description, words);
+        let _ = writeln!(out, "|{}|{}|{}|{}|", name, kind, default, description);
+    }
+    out
+}
+
+/// An API reference: many short sections with signatures, parameter lists,
+/// and fenced code blocks in several languages.
+pub fn api_reference(functions: usize) -> String {
+    let mut rng = Rng::new(4);
+    let mut out = String::from("API reference\n=============\n\n");
+    for i in 0..functions {
+        let _ = writeln!(out, "### `function_{}()`\n", i);
+        let words = 10 + rng.below(20);
+        sentence(&mut rng, &mut out, words);
+        out.push_str("\n\nParameters:\n\n");
+        for p in 0..1 + rng.below(4) {
+            let _ = write!(out, "*   `param{}`: ", p);
+            let words = 3 + rng.below(10);
+            sentence(&mut rng, &mut out, words);
+            out.push('\n');
+        }
+        let lang = rng.pick(&["rust", "typescript", "python", ""]);
+        let _ = writeln!(out, "\n```{}", lang);
+        for line in 0..3 + rng.below(12) {
+            let _ = writeln!(
+                out,
+                "    let value_{} = function_{}(param{}, \"*not* markdown\");",
+                line, i, line
+            );
+        }
+        out.push_str("```\n\n");
+    }
+    out
+}
+
+/// A document dominated by CJK text, with wide punctuation and mixed
+/// scripts, written as long unwrapped lines.
+pub fn cjk(paragraphs: usize) -> String {
+    let mut rng = Rng::new(5);
+    let mut out = String::from("# 한국어 문서\n\n");
+    for p in 0..paragraphs {
+        if p % 5 == 4 {
+            let _ = writeln!(out, "## 절 {}\n", p / 5 + 1);
+        }
+        for i in 0..20 + rng.below(20) {
+            if i > 0 && rng.below(3) > 0 {
+                out.push(' ');
+            }
+            out.push_str(rng.pick(CJK_PHRASES));
+        }
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// All corpus documents at their default benchmark sizes, with names.
+pub fn all() -> Vec<(&'static str, String)> {
+    vec![
+        ("prose", prose(200)),
+        ("nested_lists", nested_lists(400, 6)),
+        ("table", table(500)),
+        ("api_reference", api_reference(100)),
+        ("cjk", cjk(200)),
+    ]
+}
@@ -0,0 +1,91 @@
+//! Benchmarks for Hongdown's formatting pipeline.
+//!
+//! Run with `cargo bench`.  To compare a change against a baseline, save one
+//! first and then compare to it:
+//!
+//! ~~~~ bash
+//! cargo bench -- --save-baseline main
+//! # ...apply changes...
+//! cargo bench -- --baseline main
+//! ~~~~
+
+use std::hint::black_box;
+
+use comrak::Arena;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use hongdown::{Options, bench, format};
+
+mod corpus;
+
+/// Parsing and formatting each corpus document end to end.
+fn bench_end_to_end(c: &mut Criterion) {
+    let options = Options::default();
+    let mut group = c.benchmark_group("end_to_end");
+    for (name, doc) in corpus::all() {
+        group.throughput(Throughput::Bytes(doc.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| format(black_box(&doc), &options).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Serializing a pre-parsed AST for each corpus document.
+fn bench_serialize(c: &mut Criterion) {
+    let options = Options::default();
+    let mut group = c.benchmark_group("serialize");
+    for (name, doc) in corpus::all() {
+        let arena = Arena::new();
+        let root = bench::parse(&arena, &doc);
+        group.throughput(Throughput::Bytes(doc.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| bench::serialize(black_box(root), &doc, &options))
+        });
+    }
+    group.finish();
+}
+
+/// A single very large table, which stresses per-cell allocations.
+fn bench_large_table(c: &mut Criterion) {
+    let options = Options::default();
+    let doc = corpus::table(5000);
+    let mut group = c.benchmark_group("large_table");
+    group.throughput(Throughput::Bytes(doc.len() as u64));
+    group.sample_size(20);
+    group.bench_function("rows_5000", |b| {
+        b.iter(|| format(black_box(&doc), &options).unwrap())
+    });
+    group.finish();
+}
+
+/// The per-character escaping helpers, on plain ASCII and CJK text (the
+/// common case, which needs no allocation) and on text full of Markdown
+/// special characters.
+fn bench_escape(c: &mut Criterion) {
+    let plain = "The formatter wraps every paragraph of the document at the \
+                 configured line width without changing its meaning";
+    let special = "Use *stars*, _underscores_, [brackets], <angles>, `ticks`, \
+                   #hashes, 1. numbers, and a | pipe \\ backslash";
+    let cjk = "마크다운 문서를 일관된 형식으로 정리하는 도구입니다。\
+               日本語の文章も正しく折り返されます";
+    let mut group = c.benchmark_group("escape");
+    for (name, text) in [("plain", plain), ("cjk", cjk), ("special", special)] {
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_function(format!("escape_text/{}", name), |b| {
+            b.iter(|| bench::escape_text(black_box(text)))
+        });
+        group.bench_function(format!("escape_table_cell/{}", name), |b| {
+            b.iter(|| bench::escape_table_cell(black_box(text)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_end_to_end,
+    bench_serialize,
+    bench_large_table,
+    bench_escape
+);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+//! Formats arbitrary documents, and checks that the output is stable and has
+//! the structure of the input; see `hongdown::fuzzing::check`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    hongdown::fuzzing::check(data);
+});
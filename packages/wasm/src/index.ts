@@ -167,5 +167,12 @@ export type {
   FormatWithCodeFormatterOptions,
   Warning,
   OrderedListPad,
+  ListSeparation,
+  ListContinuation,
+  ReferenceSyntax,
+  UrlEncoding,
+  WrapMode,
+  LineEnding,
+  EntityMode,
   DashSetting,
 } from "./types.js";
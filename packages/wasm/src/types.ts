@@ -6,6 +6,287 @@
  */
 export type OrderedListPad = "start" | "end";
 
+/**
+ * How the items of ordered lists are numbered.
+ *
+ * - `"sequential"`: `1.`, `2.`, `3.`, ... (default)
+ * - `"ones"`: `1.` for every item, so that adding an item doesn't renumber
+ *   the ones after it
+ */
+export type OrderedNumbering = "sequential" | "ones";
+
+/**
+ * How adjacent lists of the same kind are kept apart.
+ *
+ * - `"comment"`: Put an empty HTML comment (`<!-- -->`) between them (default)
+ * - `"alternate_marker"`: Use a different marker for every other list
+ */
+export type ListSeparation = "comment" | "alternate_marker";
+
+/**
+ * Whether lists have blank lines between their items.
+ *
+ * - `"preserve"`: Keep lists tight or loose as they are (default)
+ * - `"tight"`: Write all lists without blank lines between items
+ * - `"loose"`: Write all lists with blank lines between items
+ */
+export type ListSpacing = "preserve" | "tight" | "loose";
+
+/**
+ * Where the wrapped lines of a list item's text start.
+ *
+ * - `"align_text"`: Align them with the text after the marker (default)
+ * - `"fixed"`: Indent them by `listFixedWidth` columns from the marker
+ */
+export type ListContinuation = "align_text" | "fixed";
+
+/**
+ * Syntax for reference links and images whose text matches their label.
+ *
+ * - `"shortcut"`: `[text]`, or `[text][]` before `[` or `(` (default)
+ * - `"collapsed"`: `[text][]`
+ * - `"full"`: `[text][text]`
+ * - `"preserve"`: Keep the syntax used in the source
+ */
+export type ReferenceSyntax = "shortcut" | "collapsed" | "full" | "preserve";
+
+/**
+ * How characters in link and image URLs are percent-encoded.  Existing
+ * `%XX` escapes are never encoded again.
+ *
+ * - `"preserve"`: Keep URLs as written (default)
+ * - `"encode"`: Percent-encode spaces, double quotes, non-ASCII characters,
+ *   and other characters not allowed in URLs
+ * - `"decode_unreserved"`: Decode needless escapes such as `%41` for `A`
+ */
+export type UrlEncoding = "preserve" | "encode" | "decode_unreserved";
+
+/**
+ * How links to external URLs with empty text, like `[](url)`, are written.
+ * Links with a title are always kept as they are.
+ *
+ * - `"autolink"`: Write the link as an autolink, `<url>` (default)
+ * - `"preserve"`: Keep the link as `[](url)`
+ */
+export type EmptyLinkText = "autolink" | "preserve";
+
+/**
+ * The order reference definitions or footnote definitions are written in.
+ *
+ * - `"none"`: The order they are first used in, with two or more numeric
+ *   labels, like `[1]` and `[2]`, last and sorted by number (default)
+ * - `"alpha"`: Alphabetical order, ignoring case, with numbers compared by
+ *   value, so that `[2]` comes before `[10]`
+ * - `"usage"`: The order they are first used in
+ */
+export type DefinitionOrder = "none" | "alpha" | "usage";
+
+/**
+ * How paragraphs are wrapped.
+ *
+ * - `"width"`: Rewrap lines longer than `lineWidth` (default)
+ * - `"none"`: Join each paragraph into a single line
+ * - `"preserve"`: Keep the original line breaks
+ * - `"sentence"`: Put each sentence on a line of its own
+ */
+export type WrapMode = "width" | "none" | "preserve" | "sentence";
+
+/**
+ * Line ending style for the output.
+ *
+ * - `"lf"`: Unix-style line feeds (default)
+ * - `"crlf"`: Windows-style carriage return and line feed pairs
+ * - `"auto"`: Use the style of the first line ending in the input
+ */
+export type LineEnding = "lf" | "crlf" | "auto";
+
+/**
+ * What is done with a UTF-8 byte order mark at the start of the input.
+ *
+ * - `"preserve"`: Write the mark at the start of the output (default)
+ * - `"remove"`: Leave the mark out of the output
+ */
+export type ByteOrderMark = "preserve" | "remove";
+
+/**
+ * How hard line breaks are written at the end of their lines.
+ *
+ * - `"spaces"`: Two spaces (default)
+ * - `"backslash"`: A backslash
+ */
+export type HardBreak = "spaces" | "backslash";
+
+/**
+ * How the tags of HTML void elements, like `<br>`, are closed.
+ *
+ * - `"html"`: `<br>` (default)
+ * - `"xhtml"`: `<br />`
+ */
+export type VoidTagStyle = "html" | "xhtml";
+
+/**
+ * How `<details>` blocks with Markdown inside are handled.
+ *
+ * - `"format"`: keep the tags, with blank lines around the Markdown between
+ *   them, and format it (default)
+ * - `"preserve"`: keep the whole block as it is
+ */
+export type DetailsHandling = "format" | "preserve";
+
+/**
+ * Which literal backslashes in text, like those of `C:\Users`, are escaped.
+ *
+ * - `"always"`: every one, like `C:\\Users` (default)
+ * - `"lazy"`: only those before ASCII punctuation or whitespace, or at the
+ *   end of the text, which would read back differently without one
+ */
+export type BackslashEscape = "always" | "lazy";
+
+/**
+ * How the text of table cells wider than `tableMaxColumnWidth` is broken
+ * into lines.
+ *
+ * - `"none"`: kept on one line (default)
+ * - `"br"`: `<br>` between words, so that no line is wider than the maximum;
+ *   code spans and links are never broken
+ */
+export type CellWrap = "none" | "br";
+
+/**
+ * Whether the cells of tables are padded to the width of their column.
+ *
+ * - `"aligned"`: padded, so that the pipes line up (default)
+ * - `"compact"`: not padded, with the shortest delimiters, so that changing
+ *   a cell doesn't change the other rows
+ */
+export type TablePadding = "aligned" | "compact";
+
+/**
+ * Whether tables are formatted.
+ *
+ * - `"align"`: format the cells and align the columns (default)
+ * - `"preserve"`: keep each table as it is written
+ */
+export type TableFormat = "align" | "preserve";
+
+/**
+ * How wide the delimiters of the delimiter row of tables are.
+ *
+ * - `"full"`: as wide as their columns, like `:-------` (default)
+ * - `"minimal"`: the shortest ones, like `:--`, so that a wider column
+ *   doesn't change the delimiter row
+ */
+export type TableSeparator = "full" | "minimal";
+
+/**
+ * How the widths of the columns of tables follow their contents.
+ *
+ * - `"exact"`: as wide as the widest cell (default)
+ * - `"sticky"`: rounded up to a multiple of `tableWidthStep`, and never
+ *   narrower than the delimiter row already is, so that a small change to
+ *   a cell doesn't pad every other row again
+ */
+export type TableWidthGrowth = "exact" | "sticky";
+
+/**
+ * A language whose code blocks Hongdown can format itself.
+ *
+ * - `"json"`: re-indented to 2 spaces, with one value per line
+ * - `"toml"`: whitespace normalized, with comments kept
+ */
+export type EmbeddedLanguage = "json" | "toml";
+
+/**
+ * The order of the keys of objects in JSON code blocks formatted by
+ * Hongdown.
+ *
+ * - `"preserve"`: the order they are written in (default)
+ * - `"sort"`: sorted
+ */
+export type JsonKeyOrder = "preserve" | "sort";
+
+/**
+ * How adjacent block quotes are kept apart.
+ *
+ * - `"blank"`: Put a blank line without `>` between them (default)
+ * - `"comment"`: Put an empty HTML comment (`<!-- -->`) between them too
+ */
+export type BlockQuoteSeparation = "blank" | "comment";
+
+/**
+ * What is done with Pandoc-style inline footnotes, like `^[a note]`.
+ *
+ * - `"preserve"`: Keep them inline, with their brackets unescaped (default)
+ * - `"expand"`: Turn them into references to numbered footnotes
+ */
+export type InlineFootnotes = "preserve" | "expand";
+
+/**
+ * How character entity references, like `&nbsp;`, in text are written.
+ *
+ * - `"preserve"`: Keep entities as they are written (default)
+ * - `"decode"`: Write the characters entities stand for
+ * - `"encode_invisible"`: Like `"decode"`, but write characters that can't
+ *   be told apart visually, like a no-break space, as entities
+ */
+export type EntityMode = "preserve" | "decode" | "encode_invisible";
+
+/**
+ * The language whose rules paragraphs are written by.
+ *
+ * - `"none"`: No rules of a particular language (default)
+ * - `"ko"`: Korean: a sentence ending in Hangul ends even if the next one
+ *   starts with a lowercase word, and a single syllable isn't an initial
+ * - `"auto"`: The rules of the language of each paragraph, told by the
+ *   script most of its letters are in
+ */
+export type Locale = "none" | "ko" | "auto";
+
+/**
+ * The Markdown dialect of the output.
+ *
+ * - `"gfm"`: GitHub Flavored Markdown (default)
+ * - `"commonmark"`: Plain CommonMark, with tables, strikethrough, and task
+ *   list checkboxes written without the GitHub extensions
+ */
+export type Dialect = "gfm" | "commonmark";
+
+/**
+ * How tables are written in the `"commonmark"` dialect.
+ *
+ * - `"html"`: As an HTML `<table>` block (default)
+ * - `"text"`: As the table's source text
+ */
+export type TableFallback = "html" | "text";
+
+/**
+ * A template syntax whose tags are kept as they are.
+ *
+ * - `"none"`: No template tags (default)
+ * - `"liquid"`: Liquid, as in Jekyll: `{{ variable }}` and `{% tag %}`
+ * - `"hugo"`: Hugo shortcodes and `{{ variable }}`
+ * - `"jinja"`: Jinja: `{{ variable }}`, `{% tag %}`, and `{# comment #}`
+ */
+export type TemplateSyntax = "none" | "liquid" | "hugo" | "jinja";
+
+/**
+ * A lint rule whose problems are fixed while formatting.
+ *
+ * - `"heading-increment"`: Headings more than one level deeper than the
+ *   heading they are under are moved up
+ * - `"no-trailing-punctuation"`: Trailing punctuation is removed from
+ *   headings
+ * - `"no-empty-links"`: Links with an empty destination are replaced with
+ *   their text
+ * - `"code-language"`: Code blocks without a language are given
+ *   `defaultLanguage`
+ */
+export type FixRule =
+  | "heading-increment"
+  | "no-trailing-punctuation"
+  | "no-empty-links"
+  | "code-language";
+
 /**
  * Dash transformation setting.
  *
@@ -26,6 +307,98 @@ export interface FormatOptions {
    */
   lineWidth?: number;
 
+  /**
+   * How paragraphs are wrapped.
+   * @default "width"
+   */
+  wrap?: WrapMode;
+
+  /**
+   * The most blank lines written between two blocks; with 1, sections are
+   * separated by one blank line instead of two.
+   * @default 2
+   */
+  maxConsecutiveBlankLines?: number;
+
+  /**
+   * The language whose rules paragraphs are written by, like where
+   * sentences end with `wrap: "sentence"`.
+   * @default "none"
+   */
+  locale?: Locale;
+
+  /**
+   * Line ending style for the output.
+   * @default "lf"
+   */
+  lineEnding?: LineEnding;
+
+  /**
+   * Whether a byte order mark at the start of the input is kept.
+   * @default "preserve"
+   */
+  bom?: ByteOrderMark;
+
+  /**
+   * How hard line breaks are written.
+   * @default "spaces"
+   */
+  hardBreak?: HardBreak;
+
+  /**
+   * How character entity references in text are written.
+   * @default "preserve"
+   */
+  entities?: EntityMode;
+
+  /**
+   * The Markdown dialect of the output.  Input is always parsed as GitHub
+   * Flavored Markdown.
+   * @default "gfm"
+   */
+  dialect?: Dialect;
+
+  /**
+   * How tables are written in the `"commonmark"` dialect.
+   * @default "html"
+   */
+  commonmarkTables?: TableFallback;
+
+  /**
+   * Recognize `:::` container blocks, like Pandoc's fenced divs, and format
+   * the Markdown inside them.
+   * @default false
+   */
+  containers?: boolean;
+
+  /**
+   * Recognize `$` inline math and `$$` display math, and escape dollar signs
+   * in text so that they aren't read back as math.
+   * @default false
+   */
+  math?: boolean;
+
+  /**
+   * The template syntax whose tags are kept as they are, never escaped or
+   * broken across lines.
+   * @default "none"
+   */
+  templates?: TemplateSyntax;
+
+  /**
+   * The lint rules whose problems are fixed while formatting.  Each fix is
+   * reported with a warning whose `fixed` is `true`.
+   * @default []
+   */
+  fix?: FixRule[];
+
+  /**
+   * Keep MDX syntax, like JSX components, JavaScript expressions in braces,
+   * and `import` and `export` statements, as it is.
+   * @default false
+   */
+  mdx?: boolean;
+
   /**
    * Use setext-style (underlined) for h1 headings.
    * @default true
@@ -38,6 +411,13 @@ export interface FormatOptions {
    */
   setextH2?: boolean;
 
+  /**
+   * Keep hard line breaks in headings, which forces setext style for them.
+   * When disabled, hard line breaks in headings are replaced with spaces.
+   * @default true
+   */
+  headingAllowMultiline?: boolean;
+
   /**
    * Convert headings to sentence case.
    * When enabled, headings like "Getting Started With HONGDOWN" become
@@ -62,6 +442,28 @@ export interface FormatOptions {
    */
   headingCommonNouns?: string[];
 
+  /**
+   * Trailing characters to strip from headings, such as a trailing colon.
+   * Runs of them are stripped together.
+   * @example [":", "."]
+   * @default []
+   */
+  headingStripTrailingPunctuation?: string[];
+
+  /**
+   * Number the headings at the top level of the document, replacing the
+   * numbers they already start with, like `3.2` in `3.2 Configuration`.
+   * @default false
+   */
+  headingNumbering?: boolean;
+
+  /**
+   * Heading levels that get numbers when `headingNumbering` is on.  Headings
+   * at a higher level restart the numbering.
+   * @default [2, 3]
+   */
+  headingNumberingLevels?: number[];
+
   /**
    * Marker character for unordered lists: `"-"`, `"*"`, or `"+"`.
    * @default "-"
@@ -106,12 +508,53 @@ export interface FormatOptions {
    */
   orderedListPad?: OrderedListPad;
 
+  /**
+   * How the items of ordered lists are numbered.
+   * @default "sequential"
+   */
+  orderedListNumbering?: OrderedNumbering;
+
   /**
    * Indentation width for nested ordered list items.
    * @default 4
    */
   orderedListIndentWidth?: number;
 
+  /**
+   * How adjacent lists of the same kind are kept apart, so that they are not
+   * read back as a single list.
+   * @default "comment"
+   */
+  listSeparation?: ListSeparation;
+
+  /**
+   * Whether lists have blank lines between their items.  With `"tight"` and
+   * `"loose"`, all lists are written that way.
+   * @default "preserve"
+   */
+  listSpacing?: ListSpacing;
+
+  /**
+   * Where the wrapped lines of a list item's text start.
+   * @default "align_text"
+   */
+  listContinuation?: ListContinuation;
+
+  /**
+   * Indentation of wrapped list item lines from the marker when
+   * `listContinuation` is `"fixed"`.
+   * @default 2
+   */
+  listFixedWidth?: number;
+
+  /**
+   * Whether a paragraph with two or more lines in a row that start like
+   * the items of a lettered or roman-numbered list, like `a.` or `iv)`,
+   * keeps its line breaks instead of being wrapped.
+   * @default false
+   */
+  listPreservePseudoLists?: boolean;
+
   /**
    * Fence character for code blocks: `"~"` or `` "`" ``.
    * @default "~"
@@ -137,6 +580,41 @@ export interface FormatOptions {
    */
   defaultLanguage?: string;
 
+  /**
+   * Strip trailing whitespace from the lines of code blocks.
+   * @default false
+   */
+  codeBlockTrimTrailingWhitespace?: boolean;
+
+  /**
+   * Languages whose code blocks Hongdown formats itself, without an
+   * external formatter.  Code that doesn't parse is left as it is, with a
+   * warning.
+   * @default []
+   */
+  codeBlockFormatEmbedded?: EmbeddedLanguage[];
+
+  /**
+   * The order of the keys of objects in JSON code blocks formatted with
+   * `codeBlockFormatEmbedded`.
+   * @default "preserve"
+   */
+  codeBlockJsonKeyOrder?: JsonKeyOrder;
+
+  /**
+   * Keep the spaces after `>` on block quote lines that are otherwise
+   * unchanged, instead of writing a single space.
+   * @default false
+   */
+  blockQuotePreserveMarkerSpacing?: boolean;
+
+  /**
+   * How adjacent block quotes are kept apart, so that they are not read
+   * back as a single block quote.
+   * @default "blank"
+   */
+  blockQuoteSeparation?: BlockQuoteSeparation;
+
   /**
    * The style string for thematic breaks.
    * @default "- - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -"
@@ -149,6 +627,166 @@ export interface FormatOptions {
    */
   thematicBreakLeadingSpaces?: number;
 
+  /**
+   * The display width of the text of a table cell above which a warning is
+   * reported, and which `tableWrapCells` breaks it at.  No limit by default.
+   */
+  tableMaxColumnWidth?: number;
+
+  /**
+   * How table cells wider than `tableMaxColumnWidth` are broken into lines.
+   * @default "none"
+   */
+  tableWrapCells?: CellWrap;
+
+  /**
+   * Whether table cells are padded to the width of their column.
+   * @default "aligned"
+   */
+  tablePadding?: TablePadding;
+
+  /**
+   * Whether tables are formatted, or kept as they are written, while
+   * everything around them is formatted.
+   * @default "align"
+   */
+  tableFormat?: TableFormat;
+
+  /**
+   * How wide the delimiters of the delimiter row of tables are.
+   * @default "full"
+   */
+  tableSeparator?: TableSeparator;
+
+  /**
+   * How the widths of the columns of tables follow their contents.
+   * @default "exact"
+   */
+  tableWidthGrowth?: TableWidthGrowth;
+
+  /**
+   * The multiple that sticky table columns are rounded up to.
+   * @default 4
+   */
+  tableWidthStep?: number;
+
+  /**
+   * Write the tags of HTML void elements, like `<BR/>`, in the
+   * `htmlVoidTagStyle`, in lowercase.
+   * @default false
+   */
+  htmlNormalizeVoidTags?: boolean;
+
+  /**
+   * How the tags of HTML void elements are closed when normalized.
+   * @default "html"
+   */
+  htmlVoidTagStyle?: VoidTagStyle;
+
+  /**
+   * Write a `<br>` at the end of a line of a paragraph as a hard line break,
+   * except in table cells.
+   * @default false
+   */
+  htmlBrToHardBreak?: boolean;
+
+  /**
+   * Write an `<hr>` that is a block of its own as a thematic break.
+   * @default false
+   */
+  htmlHrToThematicBreak?: boolean;
+
+  /**
+   * How `<details>` blocks with Markdown inside are handled: with `"format"`,
+   * a blank line is kept after the `<summary>` and before the closing tag,
+   * and what is between them is formatted.
+   * @default "format"
+   */
+  htmlDetails?: DetailsHandling;
+
+  /**
+   * Which literal backslashes in text are escaped.
+   * @default "always"
+   */
+  escapeBackslash?: BackslashEscape;
+
+  /**
+   * The GitHub repository, as `"owner/name"`, that bare references in prose,
+   * like `#123`, `GH-123`, `owner/name#123`, and `@user`, are turned into
+   * links to.  When unset, they are left as they are.
+   */
+  autolinkGithubRepo?: string;
+
+  /**
+   * Turn links to GitHub whose text is a reference to what they link to back
+   * into bare references, for documents rendered by GitHub.
+   * @default false
+   */
+  autolinkGithubStrip?: boolean;
+
+  /**
+   * Drop reference definitions that no link or image uses.  When false,
+   * unused single-line definitions are kept at the end of the document.
+   * @default true
+   */
+  linkRemoveUnusedDefinitions?: boolean;
+
+  /**
+   * Syntax for reference links and images whose text matches their label.
+   * @default "shortcut"
+   */
+  linkReferenceSyntax?: ReferenceSyntax;
+
+  /**
+   * Turn inline links to external URLs into reference links.  When false,
+   * they are kept inline.
+   * @default true
+   */
+  linkExternalReferences?: boolean;
+
+  /**
+   * How characters in link and image URLs are percent-encoded.
+   * @default "preserve"
+   */
+  linkUrlEncoding?: UrlEncoding;
+
+  /**
+   * The order reference definitions are written in.
+   * @default "none"
+   */
+  linkSortDefinitions?: DefinitionOrder;
+
+  /**
+   * How links to external URLs with empty text are written.
+   * @default "autolink"
+   */
+  linkEmptyText?: EmptyLinkText;
+
+  /**
+   * The order footnote definitions are written in.
+   * @default "none"
+   */
+  footnoteSortDefinitions?: DefinitionOrder;
+
+  /**
+   * What is done with inline footnotes, like `^[a note]`.
+   * @default "preserve"
+   */
+  footnoteInline?: InlineFootnotes;
+
+  /**
+   * The shallowest heading level listed in a table of contents generated
+   * between `<!-- toc -->` and `<!-- /toc -->` markers (1-6).
+   * @default 2
+   */
+  tocMinLevel?: number;
+
+  /**
+   * The deepest heading level listed in a table of contents (1-6).
+   * @default 3
+   */
+  tocMaxLevel?: number;
+
   /**
    * Convert straight double quotes to curly quotes.
    * `"text"` becomes `"text"` (U+201C and U+201D).
@@ -257,6 +895,12 @@ export interface Warning {
    * Warning message.
    */
   message: string;
+
+  /**
+   * Whether this reports a fix made because of the `fix` option, rather
+   * than a problem.
+   */
+  fixed: boolean;
 }
 
 /**
@@ -0,0 +1,266 @@
+//! Atomic writes for in-place formatting.
+//!
+//! Truncating a file and writing it again leaves it empty or cut short if
+//! Hongdown is killed or the machine loses power halfway.  Instead, the new
+//! content is written to a temporary file next to the original, flushed to
+//! disk, and renamed over it, so the file always holds either its old or its
+//! new content.  Files that are already formatted are never written, so
+//! their modification times are kept and build systems don't rebuild them.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The filesystem operations an atomic write is made of, so that tests can
+/// make them fail.
+pub trait Filesystem {
+    /// Creates the file at `path`, which must not exist yet, with `contents`
+    /// and the permissions of the file at `like`, and flushes it to disk.
+    fn create(&self, path: &Path, contents: &[u8], like: &Path) -> io::Result<()>;
+
+    /// Renames `from` to `to`, replacing `to`.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Overwrites the file at `path` with `contents`.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+}
+
+/// The real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFilesystem;
+
+impl Filesystem for RealFilesystem {
+    fn create(&self, path: &Path, contents: &[u8], like: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+        file.write_all(contents)?;
+        file.set_permissions(fs::metadata(like)?.permissions())?;
+        file.sync_all()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)?;
+        // Make the rename itself survive a power loss
+        #[cfg(unix)]
+        if let Some(dir) = to.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let _ = File::open(dir).and_then(|dir| dir.sync_all());
+        }
+        Ok(())
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+}
+
+/// How a file was written by [`write_atomic`].
+#[derive(Debug)]
+pub enum Written {
+    /// The file was replaced with a temporary file holding its new content.
+    Atomically,
+    /// The file could not be replaced, for the given reason, so it was
+    /// overwritten directly instead.
+    Directly(io::Error),
+}
+
+/// Writes `contents` to the existing file at `path` atomically, keeping its
+/// permissions.
+///
+/// If no temporary file can be created in its directory, or it can't be
+/// renamed over the original (as on some network filesystems), the file is
+/// overwritten directly instead, which is reported by [`Written::Directly`]
+/// so that the caller can warn about it.  If writing the temporary file
+/// fails otherwise, as when the disk is full, the original is left as it is.
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<Written> {
+    write_atomic_with(&RealFilesystem, path, contents)
+}
+
+/// Does what [`write_atomic`] does, through `filesystem`.
+pub fn write_atomic_with(
+    filesystem: &impl Filesystem,
+    path: &Path,
+    contents: &str,
+) -> io::Result<Written> {
+    // Replace the target of a symbolic link, not the link itself
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let temp = temp_path(&path);
+    // A temporary file left behind by a run that was killed is stale
+    let _ = fs::remove_file(&temp);
+    match filesystem.create(&temp, contents.as_bytes(), &path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            let _ = fs::remove_file(&temp);
+            filesystem.write(&path, contents.as_bytes())?;
+            return Ok(Written::Directly(e));
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+    }
+    match filesystem.rename(&temp, &path) {
+        Ok(()) => Ok(Written::Atomically),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            filesystem.write(&path, contents.as_bytes())?;
+            Ok(Written::Directly(e))
+        }
+    }
+}
+
+/// The temporary file the new content of `path` is written to: a hidden
+/// file next to it, named after it so that a stale one is found again.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".hongdown-tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// The real filesystem, with an operation failing on demand.
+    #[derive(Default)]
+    struct FailingFilesystem {
+        create: Option<io::ErrorKind>,
+        rename: Option<io::ErrorKind>,
+        written_directly: Cell<bool>,
+    }
+
+    impl Filesystem for FailingFilesystem {
+        fn create(&self, path: &Path, contents: &[u8], like: &Path) -> io::Result<()> {
+            if let Some(kind) = self.create {
+                // Fail partway, after a part of the content is on disk
+                fs::write(path, &contents[..contents.len() / 2])?;
+                return Err(io::Error::from(kind));
+            }
+            RealFilesystem.create(path, contents, like)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            match self.rename {
+                Some(kind) => Err(io::Error::from(kind)),
+                None => RealFilesystem.rename(from, to),
+            }
+        }
+
+        fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            self.written_directly.set(true);
+            RealFilesystem.write(path, contents)
+        }
+    }
+
+    fn entries(dir: &Path) -> Vec<OsString> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("README.md");
+        fs::write(&path, "Old\n").unwrap();
+        let written = write_atomic(&path, "New\n").unwrap();
+        assert!(matches!(written, Written::Atomically));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "New\n");
+        assert_eq!(entries(dir.path()), ["README.md"]);
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_stale_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("README.md");
+        fs::write(&path, "Old\n").unwrap();
+        fs::write(temp_path(&path), "Stale").unwrap();
+        write_atomic(&path, "New\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "New\n");
+        assert_eq!(entries(dir.path()), ["README.md"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("README.md");
+        fs::write(&path, "Old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic(&path, "New\n").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_through_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.md");
+        let link = dir.path().join("link.md");
+        fs::write(&target, "Old\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_atomic(&link, "New\n").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "New\n");
+    }
+
+    #[test]
+    fn test_write_atomic_failing_before_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("README.md");
+        fs::write(&path, "Old\n").unwrap();
+        let filesystem = FailingFilesystem {
+            create: Some(io::ErrorKind::StorageFull),
+            ..Default::default()
+        };
+        let error = write_atomic_with(&filesystem, &path, "New content\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        assert!(!filesystem.written_directly.get());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Old\n");
+        assert_eq!(entries(dir.path()), ["README.md"]);
+    }
+
+    #[test]
+    fn test_write_atomic_falls_back_when_rename_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("README.md");
+        fs::write(&path, "Old\n").unwrap();
+        let filesystem = FailingFilesystem {
+            rename: Some(io::ErrorKind::CrossesDevices),
+            ..Default::default()
+        };
+        let written = write_atomic_with(&filesystem, &path, "New\n").unwrap();
+        let Written::Directly(error) = written else {
+            panic!("expected a direct write, got {:?}", written);
+        };
+        assert_eq!(error.kind(), io::ErrorKind::CrossesDevices);
+        assert!(filesystem.written_directly.get());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "New\n");
+        assert_eq!(entries(dir.path()), ["README.md"]);
+    }
+
+    #[test]
+    fn test_write_atomic_falls_back_when_temp_file_is_denied() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("README.md");
+        fs::write(&path, "Old\n").unwrap();
+        let filesystem = FailingFilesystem {
+            create: Some(io::ErrorKind::PermissionDenied),
+            ..Default::default()
+        };
+        let written = write_atomic_with(&filesystem, &path, "New\n").unwrap();
+        assert!(matches!(written, Written::Directly(_)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "New\n");
+    }
+}
@@ -0,0 +1,180 @@
+//! Content-hash cache for skipping files that are already formatted.
+//!
+//! The cache maps each file path to a hash of the file content, the resolved
+//! formatting options, and the Hongdown version.  A file whose current hash
+//! matches its cache entry is known to be formatted and can be skipped.
+//! Changing any option or upgrading Hongdown changes every hash, so the whole
+//! cache is invalidated automatically.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use hongdown::Options;
+
+use super::FileOptions;
+use serde::{Deserialize, Serialize};
+
+/// Default cache file name, used when `--cache` is given without a path.
+pub const DEFAULT_CACHE_FILE: &str = ".hongdown-cache.json";
+
+/// On-disk representation of the cache.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    /// Hongdown version that wrote the cache.
+    version: String,
+    /// Content hashes keyed by file path.
+    entries: BTreeMap<String, String>,
+}
+
+/// A formatting cache loaded from (and saved back to) a JSON file.
+#[derive(Debug)]
+pub struct Cache {
+    path: PathBuf,
+    fingerprint: String,
+    entries: Mutex<HashMap<String, String>>,
+}
+
+impl Cache {
+    /// Loads the cache at `path`, keyed to the given formatting options for
+    /// each kind of file.
+    ///
+    /// A missing cache file yields an empty cache.  A corrupt cache file is
+    /// reported on stderr and also yields an empty cache, so a damaged cache
+    /// never prevents formatting.
+    pub fn load(path: &Path, options: &FileOptions) -> Self {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str::<CacheFile>(&content) {
+                Ok(file) if file.version == env!("CARGO_PKG_VERSION") => {
+                    file.entries.into_iter().collect()
+                }
+                Ok(_) => HashMap::new(),
+                Err(e) => {
+                    eprintln!(
+                        "Warning: ignoring corrupt cache file {}: {}.",
+                        path.display(),
+                        e
+                    );
+                    HashMap::new()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                eprintln!(
+                    "Warning: failed to read cache file {}: {}.",
+                    path.display(),
+                    e
+                );
+                HashMap::new()
+            }
+        };
+        Self {
+            path: path.to_path_buf(),
+            fingerprint: format!(
+                "{}{}",
+                options_fingerprint(&options.markdown),
+                options_fingerprint(&options.mdx)
+            ),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Removes the cache file at `path`, if any.
+    pub fn clear(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `true` if `content` of `file` is recorded as already formatted.
+    pub fn is_fresh(&self, file: &Path, content: &str) -> bool {
+        let hash = self.hash(content);
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&cache_key(file))
+            .is_some_and(|cached| *cached == hash)
+    }
+
+    /// Records `content` as the formatted content of `file`.
+    pub fn record(&self, file: &Path, content: &str) {
+        let hash = self.hash(content);
+        self.entries.lock().unwrap().insert(cache_key(file), hash);
+    }
+
+    /// Forgets any entry for `file`.
+    pub fn forget(&self, file: &Path) {
+        self.entries.lock().unwrap().remove(&cache_key(file));
+    }
+
+    /// Writes the cache back to its file.
+    pub fn save(&self) -> io::Result<()> {
+        let entries = self.entries.lock().unwrap();
+        let file = CacheFile {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            entries: entries
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        };
+        let json = serde_json::to_string_pretty(&file).map_err(io::Error::other)?;
+        fs::write(&self.path, json + "\n")
+    }
+
+    /// Hashes file content together with the options fingerprint and version.
+    fn hash(&self, content: &str) -> String {
+        let mut hasher = Fnv1a::new();
+        hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.write(&[0]);
+        hasher.write(self.fingerprint.as_bytes());
+        hasher.write(&[0]);
+        hasher.write(content.as_bytes());
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+/// Returns the key under which `file` is stored in the cache.
+fn cache_key(file: &Path) -> String {
+    fs::canonicalize(file)
+        .unwrap_or_else(|_| file.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Builds a stable textual fingerprint of the resolved formatting options.
+///
+/// Code formatters are kept in a [`HashMap`] whose iteration order varies
+/// between runs, so they are sorted before being included.
+fn options_fingerprint(options: &Options) -> String {
+    let mut rest = options.clone();
+    let formatters = std::mem::take(&mut rest.code_formatters);
+    let mut formatters: Vec<_> = formatters.into_iter().collect();
+    formatters.sort_by(|a, b| a.0.cmp(&b.0));
+    format!("{:?}{:?}", rest, formatters)
+}
+
+/// The 64-bit FNV-1a hash.
+///
+/// Used instead of [`std::hash::DefaultHasher`] because its output must stay
+/// stable across builds for the cache file to remain valid.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
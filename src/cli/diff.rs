@@ -0,0 +1,202 @@
+//! Structured comparison of original and formatted documents.
+//!
+//! A [`CheckResult`] records the hunks that differ between a file and its
+//! formatted output.  It backs both `--check` (which only needs to know
+//! whether any hunk exists) and `--diff` (which renders the hunks as a
+//! unified diff).
+
+use std::fmt::Write;
+use std::io::IsTerminal;
+
+use similar::{ChangeTag, TextDiff};
+
+/// Number of unchanged context lines shown around each change.
+pub const CONTEXT_LINES: usize = 3;
+
+/// When to colorize diff output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Colorize only when stdout is a terminal and `NO_COLOR` is unset.
+    #[default]
+    Auto,
+    /// Always colorize.
+    Always,
+    /// Never colorize.
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns `true` if output written to stdout should be colorized.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// The kind of a line within a hunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// A line present in both versions.
+    Context,
+    /// A line only present in the original.
+    Removed,
+    /// A line only present in the formatted output.
+    Added,
+}
+
+/// A single line within a hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    /// Whether the line is context, removed, or added.
+    pub kind: LineKind,
+    /// The line content, without its trailing newline.
+    pub text: String,
+    /// Whether the line is the last one in its file and lacks a newline.
+    pub missing_newline: bool,
+}
+
+/// A contiguous group of changes with surrounding context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// Zero-based index of the first original line covered by the hunk.
+    pub old_start: usize,
+    /// Number of original lines covered by the hunk.
+    pub old_len: usize,
+    /// Zero-based index of the first formatted line covered by the hunk.
+    pub new_start: usize,
+    /// Number of formatted lines covered by the hunk.
+    pub new_len: usize,
+    /// The lines of the hunk, in order.
+    pub lines: Vec<DiffLine>,
+}
+
+/// The result of comparing a file with its formatted output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// Display name of the file.
+    pub name: String,
+    /// Hunks that differ; empty when the file is already formatted.
+    pub hunks: Vec<Hunk>,
+}
+
+impl CheckResult {
+    /// Compares `original` with `formatted` and collects the differing hunks.
+    pub fn new(name: &str, original: &str, formatted: &str) -> Self {
+        let mut hunks = Vec::new();
+        if original != formatted {
+            let diff = TextDiff::from_lines(original, formatted);
+            for group in diff.grouped_ops(CONTEXT_LINES) {
+                let (Some(first), Some(last)) = (group.first(), group.last()) else {
+                    continue;
+                };
+                let old_start = first.old_range().start;
+                let new_start = first.new_range().start;
+                let mut lines = Vec::new();
+                for op in &group {
+                    for change in diff.iter_changes(op) {
+                        let kind = match change.tag() {
+                            ChangeTag::Equal => LineKind::Context,
+                            ChangeTag::Delete => LineKind::Removed,
+                            ChangeTag::Insert => LineKind::Added,
+                        };
+                        let value = change.value();
+                        lines.push(DiffLine {
+                            kind,
+                            text: value.strip_suffix('\n').unwrap_or(value).to_string(),
+                            missing_newline: !value.ends_with('\n'),
+                        });
+                    }
+                }
+                hunks.push(Hunk {
+                    old_start,
+                    old_len: last.old_range().end - old_start,
+                    new_start,
+                    new_len: last.new_range().end - new_start,
+                    lines,
+                });
+            }
+        }
+        Self {
+            name: name.to_string(),
+            hunks,
+        }
+    }
+
+    /// Returns `true` if the file is already formatted.
+    pub fn is_formatted(&self) -> bool {
+        self.hunks.is_empty()
+    }
+
+    /// Returns the number of lines removed or added by formatting.
+    pub fn changed_lines(&self) -> usize {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter(|line| line.kind != LineKind::Context)
+            .count()
+    }
+
+    /// Renders the result as a unified diff with `a/` and `b/` path prefixes.
+    ///
+    /// Returns an empty string when the file is already formatted.
+    pub fn to_unified_diff(&self, color: bool) -> String {
+        let mut out = String::new();
+        if self.is_formatted() {
+            return out;
+        }
+        let name = self.name.strip_prefix("./").unwrap_or(&self.name);
+        let paint = |out: &mut String, style: &str, line: &str| {
+            if color {
+                let _ = writeln!(out, "\x1b[{}m{}\x1b[0m", style, line);
+            } else {
+                let _ = writeln!(out, "{}", line);
+            }
+        };
+        paint(&mut out, "1", &format!("--- a/{}", name));
+        paint(&mut out, "1", &format!("+++ b/{}", name));
+        for hunk in &self.hunks {
+            let header = format!(
+                "@@ -{} +{} @@",
+                format_range(hunk.old_start, hunk.old_len),
+                format_range(hunk.new_start, hunk.new_len)
+            );
+            paint(&mut out, "36", &header);
+            for line in &hunk.lines {
+                let (sign, style) = match line.kind {
+                    LineKind::Context => (' ', None),
+                    LineKind::Removed => ('-', Some("31")),
+                    LineKind::Added => ('+', Some("32")),
+                };
+                let text = format!("{}{}", sign, line.text);
+                match style {
+                    Some(style) => paint(&mut out, style, &text),
+                    None => {
+                        let _ = writeln!(out, "{}", text);
+                    }
+                }
+                if line.missing_newline {
+                    out.push_str("\\ No newline at end of file\n");
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Formats a hunk range the way `diff -u` does.
+///
+/// A single-line range omits the length, and an empty range refers to the
+/// line just before the insertion point.
+fn format_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
@@ -0,0 +1,199 @@
+//! Exit statuses and per-file failure isolation.
+//!
+//! A run over many files should not be aborted by one bad file, so each file
+//! is formatted through [`format_isolated`], which turns both formatting
+//! errors and panics into an error message.  The driver then reports the
+//! failure with the file's path and carries on with the rest.  With
+//! `--timeout`, a file that takes too long to format is given up on the same
+//! way, so that a pathological file can't hang a CI run.
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::process::ExitCode;
+use std::sync::{OnceLock, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use hongdown::{FormatSession, Options, SessionResult};
+
+/// Some files are not formatted (in `--check`, `--diff`, and
+/// `--list-different` modes), or warnings were reported with
+/// `--deny-warnings`.
+pub const UNFORMATTED: u8 = 1;
+
+/// Some files could not be read, formatted, or written.
+pub const FILE_ERROR: u8 = 2;
+
+/// The command line or the configuration is invalid.
+pub const USAGE_ERROR: u8 = 3;
+
+thread_local! {
+    /// Whether the current thread is inside [`format_isolated`], in which
+    /// case panics are reported by it instead of by the panic hook.
+    static ISOLATING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// How long formatting a file may take, set by [`set_timeout`].
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Makes [`format_isolated`] give up on a file once formatting it has taken
+/// longer than `timeout`.
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+/// Installs a panic hook that stays silent for panics caught by
+/// [`format_isolated`], and defers to the default hook otherwise.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !ISOLATING.with(Cell::get) {
+            default_hook(info);
+        }
+    }));
+}
+
+/// Formats `input` in `session`, converting errors and panics into an error
+/// message, and, if a timeout is set, giving up once it has passed.
+pub fn format_isolated<'s>(
+    session: &'s mut FormatSession,
+    input: &str,
+    options: &Options,
+) -> Result<SessionResult<'s>, String> {
+    match TIMEOUT.get() {
+        Some(&timeout) => format_within(session, input, options, timeout),
+        None => isolate(|| {
+            session
+                .format_with_warnings(input, options)
+                .map_err(|e| e.to_string())
+        }),
+    }
+}
+
+/// Formats `input` in `session` on a thread of its own, giving up with an
+/// error message if it hasn't finished within `timeout`.
+///
+/// A thread can't be stopped from outside, so one that is given up on keeps
+/// running until it finishes or the process exits, and `session` starts
+/// over with empty buffers.
+fn format_within<'s>(
+    session: &'s mut FormatSession,
+    input: &str,
+    options: &Options,
+    timeout: Duration,
+) -> Result<SessionResult<'s>, String> {
+    let mut owned = std::mem::take(session);
+    let input = input.to_owned();
+    let options = options.clone();
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("hongdown-format".to_string())
+        .spawn(move || {
+            let result = isolate(|| {
+                owned
+                    .format_with_warnings(&input, &options)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            });
+            let _ = sender.send((owned, result));
+        })
+        .map_err(|e| format!("failed to start formatting: {}", e))?;
+    match receiver.recv_timeout(timeout) {
+        Ok((owned, result)) => {
+            *session = owned;
+            result.map(|()| session.last_result())
+        }
+        Err(_) => Err(format!(
+            "gave up after {} seconds (--timeout)",
+            timeout.as_secs()
+        )),
+    }
+}
+
+/// Runs `f`, converting a panic into an error message.
+///
+/// The message for a panic includes the Hongdown version and asks the user
+/// to report the bug along with the offending file.
+fn isolate<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    ISOLATING.with(|flag| flag.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    ISOLATING.with(|flag| flag.set(false));
+    result.unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(format!(
+            "hongdown {} panicked: {}\n\
+             This is a bug in Hongdown.  Please report it at {}/issues \
+             and attach the file that triggered it.",
+            env!("CARGO_PKG_VERSION"),
+            message,
+            env!("CARGO_PKG_REPOSITORY")
+        ))
+    })
+}
+
+/// Returns the exit code summarizing a run over several files.
+///
+/// File errors take precedence over unformatted files.
+pub fn summarize(any_error: bool, any_unformatted: bool) -> ExitCode {
+    if any_error {
+        ExitCode::from(FILE_ERROR)
+    } else if any_unformatted {
+        ExitCode::from(UNFORMATTED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_isolated_ok() {
+        let mut session = FormatSession::new();
+        let result = format_isolated(&mut session, "# Title\n", &Options::default()).unwrap();
+        assert_eq!(result.output, "Title\n=====\n");
+    }
+
+    #[test]
+    fn test_format_within() {
+        let mut session = FormatSession::new();
+        let options = Options::default();
+        let timeout = Duration::from_secs(60);
+        let result = format_within(&mut session, "# Title\n", &options, timeout).unwrap();
+        assert_eq!(result.output, "Title\n=====\n");
+
+        // Nothing this large is formatted in no time at all
+        let input = "A sentence that goes on. ".repeat(100_000);
+        let error = format_within(&mut session, &input, &options, Duration::ZERO).unwrap_err();
+        assert_eq!(error, "gave up after 0 seconds (--timeout)");
+        // The session still works
+        let result = format_within(&mut session, "# Title\n", &options, timeout).unwrap();
+        assert_eq!(result.output, "Title\n=====\n");
+    }
+
+    #[test]
+    fn test_isolate_catches_panic() {
+        install_panic_hook();
+        let result: Result<(), String> = isolate(|| panic!("boom at {}", 42));
+        let message = result.unwrap_err();
+        assert!(message.starts_with(&format!(
+            "hongdown {} panicked: boom at 42\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(message.contains("/issues"));
+        // The flag is reset so later panics reach the default hook
+        assert!(!ISOLATING.with(Cell::get));
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize(false, false), ExitCode::SUCCESS);
+        assert_eq!(summarize(false, true), ExitCode::from(UNFORMATTED));
+        assert_eq!(summarize(true, true), ExitCode::from(FILE_ERROR));
+    }
+}
@@ -0,0 +1,428 @@
+//! Generating a Hongdown configuration from the configuration of Prettier or
+//! markdownlint, for `--migrate-config`.
+//!
+//! Only the options that have an equivalent in Hongdown are translated; the
+//! others are listed, with why, in the comments at the top of the generated
+//! file and in the report printed to stderr.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hongdown::config::{Config, LineWidth, ThematicBreakStyle};
+use serde_json::Value;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// The tool whose configuration is migrated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MigrateFrom {
+    /// Prettier: *.prettierrc*, or the `prettier` key of *package.json*.
+    Prettier,
+    /// markdownlint: *.markdownlint.json*, *.markdownlint.yaml*, or
+    /// *.markdownlint.yml*.
+    Markdownlint,
+}
+
+impl MigrateFrom {
+    /// The names of the configuration files of the tool, in the order they
+    /// are looked for in a directory.
+    fn file_names(self) -> &'static [&'static str] {
+        match self {
+            Self::Prettier => &[
+                ".prettierrc",
+                ".prettierrc.json",
+                ".prettierrc.yaml",
+                ".prettierrc.yml",
+                ".prettierrc.toml",
+                "package.json",
+            ],
+            Self::Markdownlint => &[
+                ".markdownlint.json",
+                ".markdownlint.yaml",
+                ".markdownlint.yml",
+            ],
+        }
+    }
+
+    /// The name of the option given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Prettier => "prettier",
+            Self::Markdownlint => "markdownlint",
+        }
+    }
+}
+
+/// A configuration file of another tool, translated.
+#[derive(Debug)]
+pub struct Migration {
+    /// The file translated.
+    pub source: PathBuf,
+    /// The options translated, as the keys of the generated configuration.
+    pub table: toml::Table,
+    /// The options translated, and what they were translated into, like
+    /// `("printWidth", "line_width = 100")`.
+    pub mapped: Vec<(String, String)>,
+    /// The options that couldn't be translated, and why.
+    pub unmapped: Vec<(String, String)>,
+}
+
+impl Migration {
+    /// The generated `.hongdown.toml`, with a comment listing the options
+    /// that weren't translated.
+    pub fn to_toml(&self, from: MigrateFrom) -> String {
+        let name = self
+            .source
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut out = format!(
+            "# Generated by `hongdown --migrate-config {}` from {}.\n",
+            from.name(),
+            name
+        );
+        if !self.unmapped.is_empty() {
+            out.push_str("#\n# These options were not translated:\n#\n");
+            for (key, reason) in &self.unmapped {
+                out.push_str(&format!("#  -  {}: {}\n", key, reason));
+            }
+        }
+        let body = toml::to_string(&self.table).unwrap_or_default();
+        if !body.is_empty() {
+            out.push('\n');
+            out.push_str(&body);
+        }
+        out
+    }
+}
+
+/// Find the configuration file of `from` in `dir` or the nearest of its
+/// ancestors that has one.
+///
+/// A *package.json* only counts if it has a `prettier` key.
+pub fn find(from: MigrateFrom, dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().find_map(|dir| {
+        from.file_names().iter().find_map(|name| {
+            let path = dir.join(name);
+            if !path.is_file() {
+                return None;
+            }
+            if *name == "package.json" {
+                let content = fs::read_to_string(&path).ok()?;
+                let package: Value = serde_json::from_str(&content).ok()?;
+                package.get("prettier")?;
+            }
+            Some(path)
+        })
+    })
+}
+
+/// Read the configuration file of `from` at `path` and translate it.
+pub fn migrate(from: MigrateFrom, path: &Path) -> Result<Migration, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut value =
+        parse_config(path, &content).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if path.file_name().is_some_and(|name| name == "package.json") {
+        value = value.get("prettier").cloned().unwrap_or(Value::Null);
+    }
+    let Value::Object(options) = value else {
+        return Err(format!("{}: expected a map of options", path.display()));
+    };
+    let mut migration = Migration {
+        source: path.to_path_buf(),
+        table: toml::Table::new(),
+        mapped: Vec::new(),
+        unmapped: Vec::new(),
+    };
+    match from {
+        MigrateFrom::Prettier => migration.prettier(&options),
+        MigrateFrom::Markdownlint => migration.markdownlint(&options),
+    }
+    // Never write a configuration that Hongdown would reject
+    let generated = toml::to_string(&migration.table).map_err(|e| e.to_string())?;
+    Config::from_toml(&generated).map_err(|e| e.to_string())?;
+    Ok(migration)
+}
+
+/// Parse a configuration file as JSON, YAML, or TOML, by its extension;
+/// a file without one, like *.prettierrc*, may be JSON or YAML.
+fn parse_config(path: &Path, content: &str) -> Result<Value, String> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(content).map_err(|e| e.to_string()),
+        Some("toml") => toml::from_str(content).map_err(|e| e.to_string()),
+        Some("yaml" | "yml") => parse_yaml(content),
+        _ => serde_json::from_str(content).or_else(|_| parse_yaml(content)),
+    }
+}
+
+/// Parse the first document of the YAML `content`.
+fn parse_yaml(content: &str) -> Result<Value, String> {
+    let documents = YamlLoader::load_from_str(content).map_err(|e| e.to_string())?;
+    Ok(documents.first().map(yaml_to_json).unwrap_or(Value::Null))
+}
+
+fn yaml_to_json(yaml: &Yaml) -> Value {
+    match yaml {
+        Yaml::Real(real) => real
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(Value::Null, Value::Number),
+        Yaml::Integer(integer) => Value::from(*integer),
+        Yaml::String(string) => Value::from(string.as_str()),
+        Yaml::Boolean(boolean) => Value::from(*boolean),
+        Yaml::Array(items) => Value::Array(items.iter().map(yaml_to_json).collect()),
+        Yaml::Hash(hash) => Value::Object(
+            hash.iter()
+                .filter_map(|(key, value)| {
+                    let key = match key {
+                        Yaml::String(key) => key.clone(),
+                        Yaml::Integer(key) => key.to_string(),
+                        _ => return None,
+                    };
+                    Some((key, yaml_to_json(value)))
+                })
+                .collect(),
+        ),
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Value::Null,
+    }
+}
+
+/// The (ID, alias) of the markdownlint rules that are translated.
+const MARKDOWNLINT_RULES: &[(&str, &str)] = &[
+    ("MD003", "heading-style"),
+    ("MD004", "ul-style"),
+    ("MD013", "line-length"),
+    ("MD035", "hr-style"),
+    ("MD046", "code-block-style"),
+    ("MD048", "code-fence-style"),
+];
+
+impl Migration {
+    /// Set `path`, like `["heading", "setext_h1"]`, to `value`, recording
+    /// that it is what `source` was translated into.
+    fn set(&mut self, source: &str, path: &[&str], value: impl Into<toml::Value>) {
+        let value = value.into();
+        let (key, sections) = path.split_last().unwrap();
+        let mut table = &mut self.table;
+        for section in sections {
+            table = table
+                .entry(section.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .unwrap();
+        }
+        self.mapped.push((
+            source.to_string(),
+            format!("{} = {}", path.join("."), value),
+        ));
+        table.insert(key.to_string(), value);
+    }
+
+    fn unmapped(&mut self, source: &str, reason: impl Into<String>) {
+        self.unmapped.push((source.to_string(), reason.into()));
+    }
+
+    /// Translate the Prettier `options`.
+    fn prettier(&mut self, options: &serde_json::Map<String, Value>) {
+        for (key, value) in options {
+            match (key.as_str(), value) {
+                ("printWidth", Value::Number(width)) => {
+                    match width.as_u64().map(|width| LineWidth::new(width as usize)) {
+                        Some(Ok(width)) => {
+                            self.set(key, &["line_width"], width.get() as i64);
+                        }
+                        Some(Err(e)) => self.unmapped(key, e),
+                        None => self.unmapped(key, "not a line width"),
+                    }
+                }
+                ("proseWrap", Value::String(wrap)) => match wrap.as_str() {
+                    "always" => self.set(key, &["wrap"], "width"),
+                    "never" => self.set(key, &["wrap"], "none"),
+                    "preserve" => self.set(key, &["wrap"], "preserve"),
+                    _ => self.unmapped(key, format!("unknown value {:?}", wrap)),
+                },
+                ("endOfLine", Value::String(ending)) => match ending.as_str() {
+                    "lf" | "crlf" | "auto" => self.set(key, &["line_ending"], ending.as_str()),
+                    _ => self.unmapped(
+                        key,
+                        format!("Hongdown can't write {:?} line endings", ending),
+                    ),
+                },
+                ("tabWidth" | "useTabs", _) => self.unmapped(
+                    key,
+                    "Hongdown indents with spaces, by unordered_list.indent_width and \
+                     ordered_list.indent_width",
+                ),
+                ("overrides", _) => self.unmapped(
+                    key,
+                    "use a .hongdown.toml in the directory the override is for",
+                ),
+                ("$schema", _) => {}
+                _ => self.unmapped(key, "no equivalent in Hongdown"),
+            }
+        }
+        // Prettier keeps line breaks unless told otherwise, unlike Hongdown
+        if !options.contains_key("proseWrap") {
+            self.set("proseWrap (default)", &["wrap"], "preserve");
+        }
+    }
+
+    /// Translate the markdownlint `options`, whose rules may be given by
+    /// their IDs or their aliases.
+    fn markdownlint(&mut self, options: &serde_json::Map<String, Value>) {
+        for (key, value) in options {
+            if matches!(key.as_str(), "default" | "$schema") {
+                continue;
+            }
+            let rule = MARKDOWNLINT_RULES
+                .iter()
+                .find(|(id, alias)| key.eq_ignore_ascii_case(id) || key == alias)
+                .map(|(id, _)| *id);
+            let Some(rule) = rule else {
+                self.unmapped(key, "no equivalent in Hongdown");
+                continue;
+            };
+            let setting = |name: &str| value.get(name).and_then(Value::as_str);
+            match rule {
+                "MD003" => match setting("style") {
+                    Some("atx") => {
+                        self.set(key, &["heading", "setext_h1"], false);
+                        self.set(key, &["heading", "setext_h2"], false);
+                    }
+                    Some("setext" | "setext_with_atx") => {
+                        self.set(key, &["heading", "setext_h1"], true);
+                        self.set(key, &["heading", "setext_h2"], true);
+                    }
+                    _ => self.unmapped(
+                        key,
+                        "Hongdown writes setext headings for levels 1 and 2 and ATX \
+                         headings for the others, or ATX headings only",
+                    ),
+                },
+                "MD004" => match setting("style") {
+                    Some("dash") => self.set(key, &["unordered_list", "unordered_marker"], "-"),
+                    Some("asterisk") => self.set(key, &["unordered_list", "unordered_marker"], "*"),
+                    Some("plus") => self.set(key, &["unordered_list", "unordered_marker"], "+"),
+                    _ => self.unmapped(key, "Hongdown uses the same marker for all lists"),
+                },
+                "MD013" => match value.get("line_length").and_then(Value::as_u64) {
+                    Some(width) => match LineWidth::new(width as usize) {
+                        Ok(width) => self.set(key, &["line_width"], width.get() as i64),
+                        Err(e) => self.unmapped(key, e),
+                    },
+                    None => self.unmapped(key, "no line_length to translate"),
+                },
+                "MD035" => match setting("style").filter(|style| *style != "consistent") {
+                    Some(style) => match ThematicBreakStyle::new(style.to_string()) {
+                        Ok(_) => self.set(key, &["thematic_break", "style"], style),
+                        Err(e) => self.unmapped(key, e),
+                    },
+                    None => self.unmapped(key, "Hongdown uses the same style for all breaks"),
+                },
+                "MD046" => self.unmapped(key, "Hongdown always writes fenced code blocks"),
+                "MD048" => match setting("style") {
+                    Some("backtick") => self.set(key, &["code_block", "fence_char"], "`"),
+                    Some("tilde") => self.set(key, &["code_block", "fence_char"], "~"),
+                    _ => self.unmapped(key, "Hongdown uses the same fence for all code blocks"),
+                },
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn migrate_file(from: MigrateFrom, name: &str, content: &str) -> (Migration, Config) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, content).unwrap();
+        let migration = migrate(from, &path).unwrap();
+        let config = Config::from_toml(&migration.to_toml(from)).unwrap();
+        (migration, config)
+    }
+
+    #[test]
+    fn test_migrate_prettier() {
+        let (migration, config) = migrate_file(
+            MigrateFrom::Prettier,
+            ".prettierrc.json",
+            r#"{"printWidth": 100, "proseWrap": "always", "endOfLine": "crlf", "tabWidth": 4, "semi": false}"#,
+        );
+        assert_eq!(config.line_width.get(), 100);
+        assert_eq!(config.wrap, hongdown::WrapMode::Width);
+        assert_eq!(config.line_ending, hongdown::LineEnding::Crlf);
+        let unmapped: Vec<_> = migration.unmapped.iter().map(|(key, _)| key).collect();
+        assert_eq!(unmapped, ["semi", "tabWidth"]);
+        let toml = migration.to_toml(MigrateFrom::Prettier);
+        assert!(toml.contains("#  -  tabWidth: "), "{}", toml);
+    }
+
+    #[test]
+    fn test_migrate_prettier_yaml_without_extension() {
+        let (migration, config) = migrate_file(
+            MigrateFrom::Prettier,
+            ".prettierrc",
+            "printWidth: 72\nsingleQuote: true\n",
+        );
+        assert_eq!(config.line_width.get(), 72);
+        // Prettier keeps line breaks by default
+        assert_eq!(config.wrap, hongdown::WrapMode::Preserve);
+        assert_eq!(migration.unmapped.len(), 1);
+    }
+
+    #[test]
+    fn test_migrate_prettier_package_json() {
+        let dir = TempDir::new().unwrap();
+        let package = dir.path().join("package.json");
+        fs::write(&package, r#"{"name": "x"}"#).unwrap();
+        assert_eq!(find(MigrateFrom::Prettier, dir.path()), None);
+        fs::write(
+            &package,
+            r#"{"name": "x", "prettier": {"proseWrap": "never"}}"#,
+        )
+        .unwrap();
+        let sub = dir.path().join("docs");
+        fs::create_dir(&sub).unwrap();
+        assert_eq!(find(MigrateFrom::Prettier, &sub), Some(package.clone()));
+        let migration = migrate(MigrateFrom::Prettier, &package).unwrap();
+        assert_eq!(migration.table["wrap"].as_str(), Some("none"));
+    }
+
+    #[test]
+    fn test_migrate_markdownlint() {
+        let (migration, config) = migrate_file(
+            MigrateFrom::Markdownlint,
+            ".markdownlint.yaml",
+            "default: true\nMD004:\n  style: asterisk\nline-length:\n  line_length: 120\n\
+             MD003:\n  style: atx\nhr-style:\n  style: '---'\nMD048:\n  style: backtick\n\
+             MD033: false\nMD046:\n  style: fenced\n",
+        );
+        assert_eq!(
+            config.unordered_list.unordered_marker,
+            hongdown::UnorderedMarker::Asterisk
+        );
+        assert_eq!(config.line_width.get(), 120);
+        assert!(!config.heading.setext_h1);
+        assert!(!config.heading.setext_h2);
+        assert_eq!(config.thematic_break.style.as_str(), "---");
+        assert_eq!(config.code_block.fence_char, hongdown::FenceChar::Backtick);
+        let unmapped: Vec<_> = migration.unmapped.iter().map(|(key, _)| key).collect();
+        assert_eq!(unmapped, ["MD033", "MD046"]);
+    }
+
+    #[test]
+    fn test_migrate_markdownlint_invalid_values() {
+        let (migration, config) = migrate_file(
+            MigrateFrom::Markdownlint,
+            ".markdownlint.json",
+            r#"{"MD013": {"line_length": 4}, "ul-style": {"style": "consistent"}}"#,
+        );
+        assert_eq!(config.line_width.get(), 80);
+        assert!(migration.mapped.is_empty());
+        assert_eq!(migration.unmapped.len(), 2);
+    }
+}
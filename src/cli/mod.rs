@@ -0,0 +1,183 @@
+//! Support modules for the Hongdown command-line interface.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use hongdown::Options;
+
+pub mod atomic;
+pub mod cache;
+pub mod diff;
+pub mod exit;
+pub mod migrate;
+pub mod progress;
+pub mod trace;
+pub mod watch;
+
+/// Returns `true` if `path` has a Markdown extension (`.md`, `.markdown`, or
+/// `.mdx`).
+pub fn is_markdown_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ext.eq_ignore_ascii_case("md")
+            || ext.eq_ignore_ascii_case("markdown")
+            || ext.eq_ignore_ascii_case("mdx")
+    })
+}
+
+/// Returns `true` if `path` has the MDX extension (`.mdx`).
+pub fn is_mdx_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mdx"))
+}
+
+/// Reads the Markdown file at `path`, which must be UTF-8 (see
+/// [`decode_input`]).
+pub fn read_input(path: &Path) -> io::Result<String> {
+    decode_input(fs::read(path)?)
+}
+
+/// Decodes `bytes` as UTF-8, failing with an error naming the encoding if
+/// they are UTF-16, which would otherwise be read as garbage or not at all.
+pub fn decode_input(bytes: Vec<u8>) -> io::Result<String> {
+    if let Some(encoding) = utf16_encoding(&bytes) {
+        let message = format!("the content is {encoding}, not UTF-8");
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    String::from_utf8(bytes).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    })
+}
+
+/// How many bytes at the start of a file without a byte order mark
+/// [`utf16_encoding`] looks at.
+const UTF16_PREFIX_LENGTH: usize = 64;
+
+/// The UTF-16 encoding of `bytes`, if they are UTF-16: told by the byte
+/// order mark, or else by the zero bytes of ASCII characters across the
+/// first [`UTF16_PREFIX_LENGTH`] bytes.  Those have to start with an ASCII
+/// character, as text doesn't start with a null character, have zero bytes
+/// in every other place only, and have them in at least a quarter of the
+/// characters, so that binary data isn't taken for UTF-16.
+fn utf16_encoding(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xFF, 0xFE, ..] => return Some("UTF-16LE"),
+        [0xFE, 0xFF, ..] => return Some("UTF-16BE"),
+        _ => {}
+    }
+    let prefix = &bytes[..bytes.len().min(UTF16_PREFIX_LENGTH) & !1];
+    let zeros = |offset: usize| {
+        prefix
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&byte| byte == 0)
+            .count()
+    };
+    let units = prefix.len() / 2;
+    match (prefix, zeros(0), zeros(1)) {
+        ([first, 0, ..], 0, odd) if *first != 0 && odd * 4 >= units => Some("UTF-16LE"),
+        ([0, second, ..], even, 0) if *second != 0 && even * 4 >= units => Some("UTF-16BE"),
+        _ => None,
+    }
+}
+
+/// Formatting options for each kind of file: MDX mode is on for `.mdx` files
+/// and off for the others, unless the configuration sets `mdx` for all of
+/// them.
+#[derive(Debug, Clone)]
+pub struct FileOptions {
+    /// The options for Markdown files.
+    pub markdown: Options,
+    /// The options for MDX files.
+    pub mdx: Options,
+}
+
+impl FileOptions {
+    /// Creates the options for each kind of file from `options`, with MDX
+    /// mode turned on or off for all files by `mdx` if it is set.
+    pub fn new(options: Options, mdx: Option<bool>) -> Self {
+        let markdown = Options {
+            mdx: mdx.unwrap_or(false),
+            ..options
+        };
+        let mdx = Options {
+            mdx: mdx.unwrap_or(true),
+            ..markdown.clone()
+        };
+        Self { markdown, mdx }
+    }
+
+    /// The options for the file at `path`, or for standard input if there
+    /// is no path.
+    pub fn for_path(&self, path: Option<&Path>) -> &Options {
+        if path.is_some_and(is_mdx_path) {
+            &self.mdx
+        } else {
+            &self.markdown
+        }
+    }
+}
+
+impl From<Options> for FileOptions {
+    fn from(options: Options) -> Self {
+        Self::new(options, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_input() {
+        assert_eq!(decode_input(b"# Title\n".to_vec()).unwrap(), "# Title\n");
+        assert_eq!(
+            decode_input("\u{FEFF}# Title\n".into()).unwrap(),
+            "\u{FEFF}# Title\n"
+        );
+        assert!(decode_input(vec![b'#', 0xFF]).is_err());
+    }
+
+    #[test]
+    fn test_decode_input_utf16() {
+        let le: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("# Title\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let error = decode_input(le).unwrap_err();
+        assert_eq!(error.to_string(), "the content is UTF-16LE, not UTF-8");
+
+        let be: Vec<u8> = "# Title\n"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let error = decode_input(be).unwrap_err();
+        assert_eq!(error.to_string(), "the content is UTF-16BE, not UTF-8");
+
+        let korean: Vec<u8> = "# 제목\n\n본문입니다.\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let error = decode_input(korean).unwrap_err();
+        assert_eq!(error.to_string(), "the content is UTF-16LE, not UTF-8");
+    }
+
+    #[test]
+    fn test_decode_input_binary_not_utf16() {
+        // Zero bytes as the second byte, but not in every other place
+        let blob = vec![
+            0x01, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x10, 0x80, 0x00, 0x00, 0x00, 0xC3, 0x28,
+            0x00, 0x00, 0x7F, 0x00, 0x00, 0x01,
+        ];
+        let error = decode_input(blob).unwrap_err();
+        assert_eq!(error.to_string(), "stream did not contain valid UTF-8");
+
+        let mostly_binary = vec![b'A', 0x00, 0xFF, 0xFE, 0xFD, 0xFC, 0xFB, 0xFA, 0xF9, 0xF8];
+        let error = decode_input(mostly_binary).unwrap_err();
+        assert_eq!(error.to_string(), "stream did not contain valid UTF-8");
+    }
+}
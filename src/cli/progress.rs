@@ -0,0 +1,205 @@
+//! Per-file output and the progress indicator for large runs.
+//!
+//! Files are processed in parallel, so everything they report goes through
+//! a [`Reporter`].  When the progress line is shown, messages are funneled
+//! through a channel to a single rendering thread, which clears the progress
+//! line before printing each message and redraws it afterwards; otherwise
+//! messages are printed directly.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Minimum number of files for which the progress line is shown.
+pub const PROGRESS_THRESHOLD: usize = 50;
+
+/// Minimum interval between redraws of the progress line.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How much is reported about each file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors.
+    Quiet,
+    /// Errors, warnings, and files that changed or need formatting.
+    Normal,
+    /// Everything, including files that were already formatted.
+    Verbose,
+}
+
+/// A message on its way to the rendering thread.
+enum Event {
+    /// A line for stdout.
+    Stdout(String),
+    /// A line for stderr.
+    Stderr(String),
+    /// A file has been processed.
+    Done(String),
+}
+
+/// Reports per-file results, optionally with a progress line on stderr.
+pub struct Reporter {
+    verbosity: Verbosity,
+    progress: Option<(Sender<Event>, JoinHandle<()>)>,
+}
+
+impl Reporter {
+    /// Creates a reporter for a run over `total` files.
+    ///
+    /// The progress line is shown only if `progress_allowed` is set, stderr
+    /// is a terminal, the run is not quiet, and there are more than
+    /// [`PROGRESS_THRESHOLD`] files.
+    pub fn new(verbosity: Verbosity, total: usize, progress_allowed: bool) -> Self {
+        let show_progress = progress_allowed
+            && verbosity != Verbosity::Quiet
+            && total > PROGRESS_THRESHOLD
+            && io::stderr().is_terminal();
+        let progress = show_progress.then(|| {
+            let (tx, rx) = channel();
+            let handle = std::thread::spawn(move || render(rx, total, io::stderr()));
+            (tx, handle)
+        });
+        Self {
+            verbosity,
+            progress,
+        }
+    }
+
+    /// Reports an error, regardless of verbosity.
+    pub fn error(&self, message: String) {
+        self.send(Event::Stderr(message));
+    }
+
+    /// Reports a warning or notice on stderr, unless quiet.
+    pub fn warn(&self, message: String) {
+        if self.verbosity >= Verbosity::Normal {
+            self.send(Event::Stderr(message));
+        }
+    }
+
+    /// Reports a result on stdout, unless quiet.
+    pub fn stdout(&self, message: String) {
+        if self.verbosity >= Verbosity::Normal {
+            self.send(Event::Stdout(message));
+        }
+    }
+
+    /// Reports a detail on stderr, only if verbose.
+    pub fn info(&self, message: String) {
+        if self.verbosity >= Verbosity::Verbose {
+            self.send(Event::Stderr(message));
+        }
+    }
+
+    /// Advances the progress line past `path`.
+    pub fn file_done(&self, path: String) {
+        if let Some((tx, _)) = &self.progress {
+            let _ = tx.send(Event::Done(path));
+        }
+    }
+
+    /// Waits for pending messages to be printed and clears the progress
+    /// line.
+    pub fn finish(self) {
+        if let Some((tx, handle)) = self.progress {
+            drop(tx);
+            let _ = handle.join();
+        }
+    }
+
+    fn send(&self, event: Event) {
+        match &self.progress {
+            Some((tx, _)) => {
+                let _ = tx.send(event);
+            }
+            None => match event {
+                Event::Stdout(line) => println!("{}", line),
+                Event::Stderr(line) => eprintln!("{}", line),
+                Event::Done(_) => {}
+            },
+        }
+    }
+}
+
+/// Formats the progress line for the `done`-th of `total` files.
+fn progress_line(done: usize, total: usize, path: &str) -> String {
+    format!("{}/{} {}", done, total, path)
+}
+
+/// Prints events until the channel is closed, keeping a progress line at the
+/// bottom of `out`.
+fn render(rx: Receiver<Event>, total: usize, mut out: impl Write) {
+    const CLEAR_LINE: &str = "\r\x1b[2K";
+    let mut done = 0;
+    let mut line = String::new();
+    let mut last_draw: Option<Instant> = None;
+    for event in rx {
+        let redraw = match event {
+            Event::Stdout(message) => {
+                let _ = write!(out, "{}", CLEAR_LINE);
+                let _ = out.flush();
+                println!("{}", message);
+                true
+            }
+            Event::Stderr(message) => {
+                let _ = writeln!(out, "{}{}", CLEAR_LINE, message);
+                true
+            }
+            Event::Done(path) => {
+                done += 1;
+                line = progress_line(done, total, &path);
+                done == total || last_draw.is_none_or(|t| t.elapsed() >= REDRAW_INTERVAL)
+            }
+        };
+        if redraw && !line.is_empty() {
+            let _ = write!(out, "{}{}", CLEAR_LINE, line);
+            let _ = out.flush();
+            last_draw = Some(Instant::now());
+        }
+    }
+    let _ = write!(out, "{}", CLEAR_LINE);
+    let _ = out.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_line() {
+        assert_eq!(
+            progress_line(1234, 3000, "docs/api/foo.md"),
+            "1234/3000 docs/api/foo.md"
+        );
+    }
+
+    #[test]
+    fn test_render_redraws_after_messages_and_clears_at_end() {
+        let (tx, rx) = channel();
+        tx.send(Event::Done("a.md".to_string())).unwrap();
+        tx.send(Event::Stderr("b.md: not formatted".to_string()))
+            .unwrap();
+        tx.send(Event::Done("b.md".to_string())).unwrap();
+        drop(tx);
+
+        let mut out = Vec::new();
+        render(rx, 2, &mut out);
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            "\r\x1b[2K1/2 a.md\
+             \r\x1b[2Kb.md: not formatted\n\
+             \r\x1b[2K1/2 a.md\
+             \r\x1b[2K2/2 b.md\
+             \r\x1b[2K"
+        );
+    }
+
+    #[test]
+    fn test_reporter_without_progress_for_small_runs() {
+        let reporter = Reporter::new(Verbosity::Normal, PROGRESS_THRESHOLD, true);
+        assert!(reporter.progress.is_none());
+        reporter.finish();
+    }
+}
@@ -0,0 +1,36 @@
+//! Logging with the `trace` feature: with `--log-level`, the spans and
+//! events Hongdown emits at that level or above are written to stderr.
+
+use std::path::Path;
+
+/// The span of a file being processed, which stays entered until it is
+/// dropped.  Without the `trace` feature, this is nothing.
+#[must_use]
+pub struct FileSpan {
+    #[cfg(feature = "trace")]
+    _span: tracing::span::EnteredSpan,
+}
+
+/// Enters the span of the file at `path`, which the events emitted while
+/// formatting it are logged in.
+pub fn file_span(path: &Path) -> FileSpan {
+    #[cfg(feature = "trace")]
+    return FileSpan {
+        _span: tracing::debug_span!("file", path = %path.display()).entered(),
+    };
+    #[cfg(not(feature = "trace"))]
+    {
+        let _ = path;
+        FileSpan {}
+    }
+}
+
+/// Installs a subscriber that writes the spans and events at `level` or
+/// above to stderr.
+#[cfg(feature = "trace")]
+pub fn init(level: tracing::Level) {
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .init();
+}
@@ -0,0 +1,433 @@
+//! Watch mode: reformat Markdown files whenever they change on disk.
+//!
+//! Filesystem notifications are collected into debounced batches (a single
+//! save often fires several events), and each batch is handed to
+//! [`WatchState::handle_changes`], which does the actual work.  That function
+//! is independent of the notification backend so it can be driven directly
+//! with synthetic events.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hongdown::FormatSession;
+use hongdown::config::CONFIG_FILE_NAME;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use super::atomic::{Written, write_atomic};
+use super::{FileOptions, exit, is_markdown_path};
+
+/// How long to wait for further events before handling a batch.
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A message delivered to the watch loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// The given paths were created or modified.
+    Changed(Vec<PathBuf>),
+    /// The user pressed Ctrl-C.
+    Interrupt,
+}
+
+/// What happened to a single path in a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The file was reformatted and written back.
+    Formatted(PathBuf),
+    /// The file was already formatted.
+    Unchanged(PathBuf),
+    /// The event was caused by our own write, so the file was left alone.
+    OwnWrite(PathBuf),
+    /// The configuration file changed and was reloaded.
+    ConfigReloaded(PathBuf),
+    /// The file could not be read, formatted, or written, or the
+    /// configuration could not be reloaded.
+    Error(PathBuf, String),
+}
+
+/// Loads formatting options; called again whenever the configuration changes.
+pub type OptionsLoader = Box<dyn FnMut() -> Result<FileOptions, String>>;
+
+/// State kept across batches of filesystem events.
+pub struct WatchState {
+    options: FileOptions,
+    loader: OptionsLoader,
+    config_path: Option<PathBuf>,
+    written: HashMap<PathBuf, u64>,
+    session: FormatSession,
+}
+
+impl WatchState {
+    /// Creates a watch state, loading the initial options with `loader`.
+    ///
+    /// `config_path` is the configuration file in effect, if any.  It is
+    /// watched for changes, and treated as a configuration file even if it
+    /// is not named *.hongdown.toml* (as with `--config`).
+    pub fn new(mut loader: OptionsLoader, config_path: Option<PathBuf>) -> Result<Self, String> {
+        let options = loader()?;
+        Ok(Self {
+            options,
+            loader,
+            config_path: config_path.map(|p| fs::canonicalize(&p).unwrap_or(p)),
+            written: HashMap::new(),
+            session: FormatSession::new(),
+        })
+    }
+
+    /// Handles one debounced batch of changed paths.
+    ///
+    /// Configuration changes are applied first, so that Markdown files in
+    /// the same batch are formatted with the new options.  Each path is
+    /// handled at most once per batch, and paths that are neither Markdown
+    /// nor configuration files are ignored.
+    pub fn handle_changes(&mut self, paths: &[PathBuf]) -> Vec<Outcome> {
+        let paths: BTreeSet<&PathBuf> = paths.iter().collect();
+        let mut outcomes = Vec::new();
+
+        if let Some(path) = paths.iter().find(|p| self.is_config_path(p)) {
+            match (self.loader)() {
+                Ok(options) => {
+                    self.options = options;
+                    // Files we wrote under the old options must be reformatted
+                    self.written.clear();
+                    outcomes.push(Outcome::ConfigReloaded((*path).clone()));
+                }
+                Err(e) => outcomes.push(Outcome::Error((*path).clone(), e)),
+            }
+        }
+
+        for path in paths.iter().filter(|p| is_markdown_path(p)) {
+            outcomes.push(self.format_file(path));
+        }
+        outcomes
+    }
+
+    /// Formats a single Markdown file in place.
+    fn format_file(&mut self, path: &Path) -> Outcome {
+        let _span = super::trace::file_span(path);
+        let input = match super::read_input(path) {
+            Ok(input) => input,
+            Err(e) => return Outcome::Error(path.to_path_buf(), e.to_string()),
+        };
+
+        // Our own write fires an event too; recognize it by its content
+        let hash = content_hash(&input);
+        if self.written.get(path) == Some(&hash) {
+            return Outcome::OwnWrite(path.to_path_buf());
+        }
+
+        let options = self.options.for_path(Some(path));
+        let output = match exit::format_isolated(&mut self.session, &input, options) {
+            Ok(result) => result.output,
+            Err(e) => return Outcome::Error(path.to_path_buf(), e),
+        };
+        if output == input {
+            self.written.remove(path);
+            return Outcome::Unchanged(path.to_path_buf());
+        }
+        match write_atomic(path, output) {
+            Ok(Written::Atomically) => {}
+            Ok(Written::Directly(e)) => eprintln!(
+                "Warning: could not replace {} atomically ({}), so it was overwritten in place.",
+                path.display(),
+                e
+            ),
+            Err(e) => return Outcome::Error(path.to_path_buf(), e.to_string()),
+        }
+        self.written
+            .insert(path.to_path_buf(), content_hash(output));
+        Outcome::Formatted(path.to_path_buf())
+    }
+
+    /// Returns `true` if `path` is a configuration file.
+    fn is_config_path(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| name == CONFIG_FILE_NAME)
+            || self.config_path.as_deref() == Some(path)
+    }
+}
+
+/// Waits for the next batch of changed paths.
+///
+/// Blocks until at least one change arrives, then keeps collecting changes
+/// until none arrive for `debounce`.  Returns `None` on interrupt or when
+/// the sender is gone.
+pub fn next_batch(rx: &Receiver<Message>, debounce: Duration) -> Option<Vec<PathBuf>> {
+    let mut batch = match rx.recv().ok()? {
+        Message::Changed(paths) => paths,
+        Message::Interrupt => return None,
+    };
+    loop {
+        match rx.recv_timeout(debounce) {
+            Ok(Message::Changed(paths)) => batch.extend(paths),
+            Ok(Message::Interrupt) | Err(RecvTimeoutError::Disconnected) => return None,
+            Err(RecvTimeoutError::Timeout) => return Some(batch),
+        }
+    }
+}
+
+/// Watches `paths` (and the configuration file, if any) until Ctrl-C.
+pub fn run(paths: &[PathBuf], mut state: WatchState) -> ExitCode {
+    let (tx, rx) = channel();
+
+    let fs_tx = tx.clone();
+    let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        if let Ok(event) = result
+            && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        {
+            let _ = fs_tx.send(Message::Changed(event.paths));
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error: failed to start watching: {}.", e);
+            return ExitCode::from(exit::FILE_ERROR);
+        }
+    };
+
+    // Canonical watch paths make the paths in events canonical as well
+    let paths: Vec<PathBuf> = paths
+        .iter()
+        .map(|p| fs::canonicalize(p).unwrap_or_else(|_| p.clone()))
+        .collect();
+    let config = state
+        .config_path
+        .iter()
+        .map(|p| (p, RecursiveMode::NonRecursive));
+    let watched = paths.iter().map(|p| (p, RecursiveMode::Recursive));
+    for (path, mode) in watched.chain(config) {
+        if let Err(e) = watcher.watch(path, mode) {
+            eprintln!("Error: failed to watch {}: {}.", path.display(), e);
+            return ExitCode::from(exit::FILE_ERROR);
+        }
+    }
+
+    if let Err(e) = ctrlc::set_handler(move || {
+        let _ = tx.send(Message::Interrupt);
+    }) {
+        eprintln!("Error: failed to install Ctrl-C handler: {}.", e);
+        return ExitCode::from(exit::FILE_ERROR);
+    }
+
+    let cwd = std::env::current_dir().and_then(fs::canonicalize).ok();
+    let relative = |path: &Path| -> String {
+        cwd.as_deref()
+            .and_then(|cwd| path.strip_prefix(cwd).ok())
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+
+    eprintln!("Watching for changes; press Ctrl-C to stop.");
+    while let Some(batch) = next_batch(&rx, DEBOUNCE) {
+        for outcome in state.handle_changes(&batch) {
+            match outcome {
+                Outcome::Formatted(path) => {
+                    println!("[{}] formatted {}", timestamp(), relative(&path));
+                }
+                Outcome::ConfigReloaded(path) => {
+                    println!("[{}] reloaded {}", timestamp(), relative(&path));
+                }
+                Outcome::Error(path, e) => {
+                    eprintln!("[{}] error {}: {}", timestamp(), relative(&path), e);
+                }
+                Outcome::Unchanged(_) | Outcome::OwnWrite(_) => {}
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Hashes file content for recognizing our own writes.
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Formats the current UTC time of day as `HH:MM:SS`.
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let secs = secs % 86_400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use hongdown::{LineWidth, Options};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn state_with_defaults() -> WatchState {
+        WatchState::new(Box::new(|| Ok(Options::default().into())), None).unwrap()
+    }
+
+    #[test]
+    fn test_formats_changed_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("doc.md");
+        fs::write(&path, "# Title\n").unwrap();
+
+        let mut state = state_with_defaults();
+        let outcomes = state.handle_changes(std::slice::from_ref(&path));
+
+        assert_eq!(outcomes, vec![Outcome::Formatted(path.clone())]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Title\n=====\n");
+    }
+
+    #[test]
+    fn test_own_write_does_not_retrigger() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("doc.md");
+        fs::write(&path, "# Title\n").unwrap();
+
+        let mut state = state_with_defaults();
+        state.handle_changes(std::slice::from_ref(&path));
+        // The notification for our own write arrives afterwards
+        let outcomes = state.handle_changes(std::slice::from_ref(&path));
+
+        assert_eq!(outcomes, vec![Outcome::OwnWrite(path.clone())]);
+    }
+
+    #[test]
+    fn test_duplicate_events_handled_once() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("doc.md");
+        fs::write(&path, "# Title\n").unwrap();
+
+        let mut state = state_with_defaults();
+        let outcomes = state.handle_changes(&[path.clone(), path.clone(), path.clone()]);
+
+        assert_eq!(outcomes, vec![Outcome::Formatted(path)]);
+    }
+
+    #[test]
+    fn test_ignores_non_markdown_files() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "# Title\n").unwrap();
+
+        let mut state = state_with_defaults();
+        let outcomes = state.handle_changes(std::slice::from_ref(&path));
+
+        assert!(outcomes.is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Title\n");
+    }
+
+    #[test]
+    fn test_config_change_reloads_options() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join(CONFIG_FILE_NAME);
+        let doc = dir.path().join("doc.md");
+        fs::write(
+            &doc,
+            "Some words that fit in eighty columns but not in twenty.\n",
+        )
+        .unwrap();
+
+        let loads = Rc::new(Cell::new(0));
+        let counter = loads.clone();
+        let loader: OptionsLoader = Box::new(move || {
+            counter.set(counter.get() + 1);
+            let width = if counter.get() == 1 { 80 } else { 20 };
+            Ok(Options {
+                line_width: LineWidth::new(width).unwrap(),
+                ..Options::default()
+            }
+            .into())
+        });
+        let mut state = WatchState::new(loader, None).unwrap();
+        assert_eq!(
+            state.handle_changes(std::slice::from_ref(&doc)),
+            vec![Outcome::Unchanged(doc.clone())]
+        );
+
+        let outcomes = state.handle_changes(&[doc.clone(), config.clone()]);
+
+        assert_eq!(loads.get(), 2);
+        assert_eq!(state.options.markdown.line_width.get(), 20);
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::ConfigReloaded(config),
+                Outcome::Formatted(doc.clone()),
+            ]
+        );
+        assert!(
+            fs::read_to_string(&doc)
+                .unwrap()
+                .lines()
+                .all(|line| line.len() <= 20)
+        );
+    }
+
+    #[test]
+    fn test_failed_reload_keeps_options() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("custom.toml");
+
+        let loads = Rc::new(Cell::new(0));
+        let counter = loads.clone();
+        let loader: OptionsLoader = Box::new(move || {
+            counter.set(counter.get() + 1);
+            if counter.get() == 1 {
+                Ok(Options::default().into())
+            } else {
+                Err("invalid configuration.".to_string())
+            }
+        });
+        let mut state = WatchState::new(loader, Some(config.clone())).unwrap();
+
+        let outcomes = state.handle_changes(std::slice::from_ref(&config));
+
+        assert_eq!(
+            outcomes,
+            vec![Outcome::Error(config, "invalid configuration.".to_string())]
+        );
+        assert_eq!(state.options.markdown.line_width.get(), 80);
+    }
+
+    #[test]
+    fn test_next_batch_debounces_events() {
+        let (tx, rx) = channel();
+        tx.send(Message::Changed(vec![PathBuf::from("a.md")]))
+            .unwrap();
+        tx.send(Message::Changed(vec![PathBuf::from("a.md")]))
+            .unwrap();
+        tx.send(Message::Changed(vec![PathBuf::from("b.md")]))
+            .unwrap();
+
+        let batch = next_batch(&rx, Duration::from_millis(10)).unwrap();
+
+        assert_eq!(
+            batch,
+            vec![
+                PathBuf::from("a.md"),
+                PathBuf::from("a.md"),
+                PathBuf::from("b.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_next_batch_stops_on_interrupt() {
+        let (tx, rx) = channel();
+        tx.send(Message::Changed(vec![PathBuf::from("a.md")]))
+            .unwrap();
+        tx.send(Message::Interrupt).unwrap();
+
+        assert_eq!(next_batch(&rx, Duration::from_millis(10)), None);
+    }
+}
@@ -8,28 +8,144 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+
+use crate::trace;
 
 /// The default configuration file name.
 pub const CONFIG_FILE_NAME: &str = ".hongdown.toml";
 
+/// How [`Config::discover`] and [`Config::load_cascading`] look for
+/// configuration files.
+///
+/// The default stops at the root of the repository and loads the user
+/// configuration files.  To search all the way up to the filesystem root,
+/// as Hongdown did before, turn `stop_at_repository` off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveryOptions {
+    /// Stop looking for *.hongdown.toml* at the root of the repository, the
+    /// first directory with a `.git` or `.hg` in it, instead of going on up
+    /// to the filesystem root.  Default: true.
+    pub stop_at_repository: bool,
+
+    /// Load the user configuration files, *~/.hongdown.toml* and
+    /// *$XDG_CONFIG_HOME/hongdown/config.toml*.  When false, the home
+    /// directory is passed over while looking for *.hongdown.toml* too.
+    /// Default: true.
+    pub user_config: bool,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            stop_at_repository: true,
+            user_config: true,
+        }
+    }
+}
+
+/// The names of the entries that mark the root of a repository.
+const REPOSITORY_MARKERS: [&str; 2] = [".git", ".hg"];
+
+/// The name of the files, in gitignore syntax, listing the files to skip in
+/// the directory they are in and below it.
+pub const IGNORE_FILE_NAME: &str = ".hongdownignore";
+
 /// Default value for `git_aware` (true).
 fn default_git_aware() -> bool {
     true
 }
 
+/// Log each option that `content`, the TOML of the configuration file at
+/// `path`, sets, so that it can be told which file an option comes from.
+#[cfg(feature = "trace")]
+fn trace_options(path: &Path, content: &str) {
+    fn walk(path: &Path, prefix: &str, table: &toml::Table) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match value {
+                toml::Value::Table(table) => walk(path, &key, table),
+                _ => {
+                    trace::debug!(source = %path.display(), key = %key, value = %value, "option set")
+                }
+            }
+        }
+    }
+    if let Ok(table) = toml::from_str::<toml::Table>(content) {
+        walk(path, "", &table);
+    }
+}
+
 /// Configuration for the Hongdown formatter.
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(default)]
 pub struct Config {
     /// Skip inheriting from parent configurations (default: false).
     /// When true, only this config file and Hongdown's defaults are used.
-    #[serde(default)]
+    /// Also read as `root`, the name EditorConfig gives it.
+    #[serde(default, alias = "root")]
     pub no_inherit: bool,
 
+    /// A named set of settings to start from, which the other settings in
+    /// the same file override (default: none).
+    pub preset: Option<Preset>,
+
     /// Maximum line width for wrapping (default: 80).
     pub line_width: LineWidth,
 
+    /// How paragraphs are wrapped (default: `width`).
+    pub wrap: WrapMode,
+
+    /// The most blank lines written between two blocks (default: 2).  With
+    /// 1, sections are separated by one blank line instead of two.
+    pub max_consecutive_blank_lines: MaxBlankLines,
+
+    /// The language whose rules paragraphs are written by (default: `none`).
+    pub locale: Locale,
+
+    /// Line ending style for the output (default: `lf`).
+    pub line_ending: LineEnding,
+
+    /// Whether a byte order mark at the start of the input is kept in the
+    /// output (default: `preserve`).
+    pub bom: ByteOrderMark,
+
+    /// How hard line breaks are written (default: `spaces`).
+    pub hard_break: HardBreak,
+
+    /// How character entity references in text are written (default:
+    /// `preserve`).
+    pub entities: EntityMode,
+
+    /// The Markdown dialect of the output (default: `gfm`).
+    pub dialect: Dialect,
+
+    /// Recognize `:::` container blocks, like Pandoc's fenced divs, and
+    /// format the Markdown inside them (default: false).
+    pub containers: bool,
+
+    /// Recognize `$` inline math and `$$` display math, and escape dollar
+    /// signs in text so that they aren't read back as math (default: false).
+    pub math: bool,
+
+    /// The template syntax whose tags are kept as they are (default:
+    /// `none`).
+    pub templates: TemplateSyntax,
+
+    /// The lint rules whose problems are fixed while formatting (default:
+    /// none).
+    pub fix: Vec<FixRule>,
+
+    /// Keep JSX components, `{` expressions, and `import` and `export`
+    /// statements as they are (default: unset, meaning only in `.mdx`
+    /// files).  When set, it applies to all files.
+    pub mdx: Option<bool>,
+
     /// Glob patterns for files to include (default: empty, meaning all files
     /// must be specified on command line).
     pub include: Vec<String>,
@@ -50,12 +166,45 @@ pub struct Config {
     /// Ordered list formatting options.
     pub ordered_list: OrderedListConfig,
 
+    /// Options common to all lists.
+    pub list: ListConfig,
+
     /// Code block formatting options.
     pub code_block: CodeBlockConfig,
 
+    /// Block quote formatting options.
+    pub block_quote: BlockQuoteConfig,
+
     /// Thematic break (horizontal rule) formatting options.
     pub thematic_break: ThematicBreakConfig,
 
+    /// Table formatting options.
+    pub table: TableConfig,
+
+    /// Options for HTML void elements, like `<br>` and `<hr>`.
+    pub html: HtmlConfig,
+
+    /// Options for backslash escapes in text.
+    pub escape: EscapeConfig,
+
+    /// Options for turning references to issues and users into links.
+    pub autolink: AutolinkConfig,
+
+    /// Link reference definition options.
+    pub link: LinkConfig,
+
+    /// Footnote options.
+    pub footnote: FootnoteConfig,
+
+    /// Table of contents options.
+    pub toc: TocConfig,
+
+    /// Options for the `commonmark` dialect.
+    pub commonmark: CommonMarkConfig,
+
+    /// Checks that go beyond formatting.
+    pub lint: LintConfig,
+
     /// Punctuation transformation options (SmartyPants-style).
     pub punctuation: PunctuationConfig,
 }
@@ -64,15 +213,40 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             no_inherit: false,
+            preset: None,
             line_width: LineWidth::default(),
+            wrap: WrapMode::default(),
+            max_consecutive_blank_lines: MaxBlankLines::default(),
+            locale: Locale::default(),
+            line_ending: LineEnding::default(),
+            bom: ByteOrderMark::default(),
+            hard_break: HardBreak::default(),
+            entities: EntityMode::default(),
+            dialect: Dialect::default(),
+            containers: false,
+            math: false,
+            templates: TemplateSyntax::default(),
+            fix: Vec::new(),
+            mdx: None,
             include: Vec::new(),
             exclude: Vec::new(),
             git_aware: true,
             heading: HeadingConfig::default(),
             unordered_list: UnorderedListConfig::default(),
             ordered_list: OrderedListConfig::default(),
+            list: ListConfig::default(),
             code_block: CodeBlockConfig::default(),
+            block_quote: BlockQuoteConfig::default(),
             thematic_break: ThematicBreakConfig::default(),
+            table: TableConfig::default(),
+            html: HtmlConfig::default(),
+            escape: EscapeConfig::default(),
+            autolink: AutolinkConfig::default(),
+            link: LinkConfig::default(),
+            footnote: FootnoteConfig::default(),
+            toc: TocConfig::default(),
+            commonmark: CommonMarkConfig::default(),
+            lint: LintConfig::default(),
             punctuation: PunctuationConfig::default(),
         }
     }
@@ -87,12 +261,54 @@ impl Default for Config {
 #[serde(default)]
 pub struct ConfigLayer {
     /// Skip inheriting from parent configurations (default: false).
-    #[serde(default)]
+    #[serde(default, alias = "root")]
     pub no_inherit: bool,
 
+    /// A named set of settings to start from.
+    pub preset: Option<Preset>,
+
     /// Maximum line width for wrapping.
     pub line_width: Option<LineWidth>,
 
+    /// How paragraphs are wrapped.
+    pub wrap: Option<WrapMode>,
+
+    /// The most blank lines written between two blocks.
+    pub max_consecutive_blank_lines: Option<MaxBlankLines>,
+
+    /// The language whose rules paragraphs are written by.
+    pub locale: Option<Locale>,
+
+    /// Line ending style for the output.
+    pub line_ending: Option<LineEnding>,
+
+    /// Whether a byte order mark at the start of the input is kept.
+    pub bom: Option<ByteOrderMark>,
+
+    /// How hard line breaks are written.
+    pub hard_break: Option<HardBreak>,
+
+    /// How character entity references in text are written.
+    pub entities: Option<EntityMode>,
+
+    /// The Markdown dialect of the output.
+    pub dialect: Option<Dialect>,
+
+    /// Recognize `:::` container blocks.
+    pub containers: Option<bool>,
+
+    /// Recognize `$` and `$$` math.
+    pub math: Option<bool>,
+
+    /// The template syntax whose tags are kept as they are.
+    pub templates: Option<TemplateSyntax>,
+
+    /// The lint rules whose problems are fixed while formatting.
+    pub fix: Option<Vec<FixRule>>,
+
+    /// Keep JSX components and expressions as they are.
+    pub mdx: Option<bool>,
+
     /// Glob patterns for files to include.
     pub include: Option<Vec<String>>,
 
@@ -111,12 +327,45 @@ pub struct ConfigLayer {
     /// Ordered list formatting options.
     pub ordered_list: Option<OrderedListConfig>,
 
+    /// Options common to all lists.
+    pub list: Option<ListConfig>,
+
     /// Code block formatting options.
     pub code_block: Option<CodeBlockConfig>,
 
+    /// Block quote formatting options.
+    pub block_quote: Option<BlockQuoteConfig>,
+
     /// Thematic break (horizontal rule) formatting options.
     pub thematic_break: Option<ThematicBreakConfig>,
 
+    /// Table formatting options.
+    pub table: Option<TableConfig>,
+
+    /// Options for HTML void elements, like `<br>` and `<hr>`.
+    pub html: Option<HtmlConfig>,
+
+    /// Options for backslash escapes in text.
+    pub escape: Option<EscapeConfig>,
+
+    /// Options for turning references to issues and users into links.
+    pub autolink: Option<AutolinkConfig>,
+
+    /// Link reference definition options.
+    pub link: Option<LinkConfig>,
+
+    /// Footnote options.
+    pub footnote: Option<FootnoteConfig>,
+
+    /// Table of contents options.
+    pub toc: Option<TocConfig>,
+
+    /// Options for the `commonmark` dialect.
+    pub commonmark: Option<CommonMarkConfig>,
+
+    /// Checks that go beyond formatting.
+    pub lint: Option<LintConfig>,
+
     /// Punctuation transformation options (SmartyPants-style).
     pub punctuation: Option<PunctuationConfig>,
 }
@@ -126,9 +375,21 @@ impl ConfigLayer {
     ///
     /// Returns an error if the file cannot be read or the TOML is invalid.
     pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
-        let content =
-            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
-        toml::from_str(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+        let content = ConfigError::read(path)?;
+        #[cfg(feature = "trace")]
+        trace_options(path, &content);
+        Self::from_toml(&content)
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), content, Box::new(e)))
+    }
+
+    /// Parse a ConfigLayer from a TOML string, with the settings of its
+    /// preset, if any, under the ones it sets itself (see [`Preset`]).
+    pub fn from_toml(toml_str: &str) -> Result<Self, toml::de::Error> {
+        let layer: Self = toml::from_str(toml_str)?;
+        match layer.preset {
+            Some(preset) => preset.apply_under(toml::from_str(toml_str)?),
+            None => Ok(layer),
+        }
     }
 
     /// Merge this layer on top of a base Config.
@@ -140,9 +401,51 @@ impl ConfigLayer {
         // Always update no_inherit from the layer
         base.no_inherit = self.no_inherit;
 
+        if let Some(preset) = self.preset {
+            base.preset = Some(preset);
+        }
         if let Some(line_width) = self.line_width {
             base.line_width = line_width;
         }
+        if let Some(wrap) = self.wrap {
+            base.wrap = wrap;
+        }
+        if let Some(max) = self.max_consecutive_blank_lines {
+            base.max_consecutive_blank_lines = max;
+        }
+        if let Some(locale) = self.locale {
+            base.locale = locale;
+        }
+        if let Some(line_ending) = self.line_ending {
+            base.line_ending = line_ending;
+        }
+        if let Some(bom) = self.bom {
+            base.bom = bom;
+        }
+        if let Some(hard_break) = self.hard_break {
+            base.hard_break = hard_break;
+        }
+        if let Some(entities) = self.entities {
+            base.entities = entities;
+        }
+        if let Some(dialect) = self.dialect {
+            base.dialect = dialect;
+        }
+        if let Some(containers) = self.containers {
+            base.containers = containers;
+        }
+        if let Some(math) = self.math {
+            base.math = math;
+        }
+        if let Some(templates) = self.templates {
+            base.templates = templates;
+        }
+        if let Some(fix) = self.fix {
+            base.fix = fix;
+        }
+        if let Some(mdx) = self.mdx {
+            base.mdx = Some(mdx);
+        }
         if let Some(include) = self.include {
             base.include = include;
         }
@@ -161,12 +464,45 @@ impl ConfigLayer {
         if let Some(ordered_list) = self.ordered_list {
             base.ordered_list = ordered_list;
         }
+        if let Some(list) = self.list {
+            base.list = list;
+        }
         if let Some(code_block) = self.code_block {
             base.code_block = code_block;
         }
+        if let Some(block_quote) = self.block_quote {
+            base.block_quote = block_quote;
+        }
         if let Some(thematic_break) = self.thematic_break {
             base.thematic_break = thematic_break;
         }
+        if let Some(table) = self.table {
+            base.table = table;
+        }
+        if let Some(html) = self.html {
+            base.html = html;
+        }
+        if let Some(escape) = self.escape {
+            base.escape = escape;
+        }
+        if let Some(autolink) = self.autolink {
+            base.autolink = autolink;
+        }
+        if let Some(link) = self.link {
+            base.link = link;
+        }
+        if let Some(footnote) = self.footnote {
+            base.footnote = footnote;
+        }
+        if let Some(toc) = self.toc {
+            base.toc = toc;
+        }
+        if let Some(commonmark) = self.commonmark {
+            base.commonmark = commonmark;
+        }
+        if let Some(lint) = self.lint {
+            base.lint = lint;
+        }
         if let Some(punctuation) = self.punctuation {
             base.punctuation = punctuation;
         }
@@ -174,8 +510,330 @@ impl ConfigLayer {
     }
 }
 
+/// Field-level overrides of individual options.
+///
+/// Unlike [`ConfigLayer`], which replaces whole sections, each field here
+/// overrides a single option and leaves the rest of its section alone.
+/// This is what environment variables and command-line flags produce; they
+/// are applied over the file configuration with
+/// [`PartialConfig::merge_over`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PartialConfig {
+    /// Overrides `line_width`.
+    pub line_width: Option<LineWidth>,
+
+    /// Overrides `wrap`.
+    pub wrap: Option<WrapMode>,
+
+    /// Overrides `unordered_list.unordered_marker`.
+    pub unordered_marker: Option<UnorderedMarker>,
+
+    /// Overrides `code_block.fence_char`.
+    pub fence_char: Option<FenceChar>,
+
+    /// Overrides both `heading.setext_h1` and `heading.setext_h2`.
+    pub setext: Option<bool>,
+
+    /// Overrides `line_ending`.
+    pub line_ending: Option<LineEnding>,
+
+    /// Overrides `list.spacing`.
+    pub list_spacing: Option<ListSpacing>,
+
+    /// Overrides `link.external_references`.
+    pub external_references: Option<bool>,
+
+    /// Overrides `link.sort_definitions`.
+    pub sort_definitions: Option<DefinitionOrder>,
+
+    /// Overrides `ordered_list.numbering`.
+    pub ordered_numbering: Option<OrderedNumbering>,
+
+    /// Overrides `table.padding`.
+    pub table_padding: Option<TablePadding>,
+
+    /// Overrides `max_consecutive_blank_lines`.
+    pub max_consecutive_blank_lines: Option<MaxBlankLines>,
+}
+
+impl PartialConfig {
+    /// Read overrides from `HONGDOWN_*` environment variables.
+    ///
+    /// The recognized variables are `HONGDOWN_LINE_WIDTH`, `HONGDOWN_WRAP`,
+    /// `HONGDOWN_UNORDERED_MARKER`, `HONGDOWN_FENCE_CHAR`, `HONGDOWN_SETEXT`,
+    /// and `HONGDOWN_LINE_ENDING`.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// Read overrides from the given `HONGDOWN_*` variables.
+    ///
+    /// Unrecognized variables are ignored.  Values are validated the same
+    /// way as in configuration files, and errors name the variable.
+    pub fn from_vars<I>(vars: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut partial = Self::default();
+        for (name, value) in vars {
+            let parse_error = |e: String| format!("invalid value for {}: {}", name, e);
+            match name.as_str() {
+                "HONGDOWN_LINE_WIDTH" => {
+                    partial.line_width = Some(Self::parse_value(&value).map_err(parse_error)?);
+                }
+                "HONGDOWN_WRAP" => {
+                    partial.wrap = Some(Self::parse_value(&value).map_err(parse_error)?);
+                }
+                "HONGDOWN_UNORDERED_MARKER" => {
+                    partial.unordered_marker =
+                        Some(Self::parse_value(&value).map_err(parse_error)?);
+                }
+                "HONGDOWN_FENCE_CHAR" => {
+                    partial.fence_char = Some(Self::parse_value(&value).map_err(parse_error)?);
+                }
+                "HONGDOWN_SETEXT" => {
+                    partial.setext = Some(Self::parse_value(&value).map_err(parse_error)?);
+                }
+                "HONGDOWN_LINE_ENDING" => {
+                    partial.line_ending = Some(Self::parse_value(&value).map_err(parse_error)?);
+                }
+                _ => continue,
+            }
+            trace::debug!(source = "environment", key = %name, value = %value, "option set");
+        }
+        Ok(partial)
+    }
+
+    /// Parse a single option value given as a string.
+    ///
+    /// The string is interpreted as a TOML integer or boolean if it looks
+    /// like one, and as a TOML string otherwise, so that validation and
+    /// error messages match those for configuration files.
+    pub fn parse_value<T>(value: &str) -> Result<T, String>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let value = if let Ok(n) = value.parse::<i64>() {
+            toml::Value::Integer(n)
+        } else if let Ok(b) = value.parse::<bool>() {
+            toml::Value::Boolean(b)
+        } else {
+            toml::Value::String(value.to_string())
+        };
+        value.try_into().map_err(|e: toml::de::Error| {
+            let message = e.message().trim_end().to_string();
+            if message.ends_with('.') {
+                message
+            } else {
+                format!("{}.", message)
+            }
+        })
+    }
+
+    /// Combine two sets of overrides, with `self` taking precedence.
+    pub fn or(self, lower: Self) -> Self {
+        Self {
+            line_width: self.line_width.or(lower.line_width),
+            wrap: self.wrap.or(lower.wrap),
+            unordered_marker: self.unordered_marker.or(lower.unordered_marker),
+            fence_char: self.fence_char.or(lower.fence_char),
+            setext: self.setext.or(lower.setext),
+            line_ending: self.line_ending.or(lower.line_ending),
+            list_spacing: self.list_spacing.or(lower.list_spacing),
+            external_references: self.external_references.or(lower.external_references),
+            sort_definitions: self.sort_definitions.or(lower.sort_definitions),
+            ordered_numbering: self.ordered_numbering.or(lower.ordered_numbering),
+            table_padding: self.table_padding.or(lower.table_padding),
+            max_consecutive_blank_lines: self
+                .max_consecutive_blank_lines
+                .or(lower.max_consecutive_blank_lines),
+        }
+    }
+
+    /// Apply these overrides on top of a base Config.
+    pub fn merge_over(&self, mut base: Config) -> Config {
+        if let Some(line_width) = self.line_width {
+            base.line_width = line_width;
+        }
+        if let Some(wrap) = self.wrap {
+            base.wrap = wrap;
+        }
+        if let Some(marker) = self.unordered_marker {
+            base.unordered_list.unordered_marker = marker;
+        }
+        if let Some(fence_char) = self.fence_char {
+            base.code_block.fence_char = fence_char;
+        }
+        if let Some(setext) = self.setext {
+            base.heading.setext_h1 = setext;
+            base.heading.setext_h2 = setext;
+        }
+        if let Some(line_ending) = self.line_ending {
+            base.line_ending = line_ending;
+        }
+        if let Some(spacing) = self.list_spacing {
+            base.list.spacing = spacing;
+        }
+        if let Some(external_references) = self.external_references {
+            base.link.external_references = external_references;
+        }
+        if let Some(order) = self.sort_definitions {
+            base.link.sort_definitions = order;
+        }
+        if let Some(numbering) = self.ordered_numbering {
+            base.ordered_list.numbering = numbering;
+        }
+        if let Some(padding) = self.table_padding {
+            base.table.padding = padding;
+        }
+        if let Some(max) = self.max_consecutive_blank_lines {
+            base.max_consecutive_blank_lines = max;
+        }
+        base
+    }
+
+    /// These overrides as a TOML table of the keys they set, in the sections
+    /// of a configuration file.
+    fn to_toml_table(&self) -> toml::Table {
+        fn set<T: Serialize>(table: &mut toml::Table, path: &[&str], value: Option<T>) {
+            let Some(value) = value.and_then(|v| toml::Value::try_from(v).ok()) else {
+                return;
+            };
+            let (key, sections) = path.split_last().unwrap();
+            let mut table = table;
+            for section in sections {
+                table = table
+                    .entry(section.to_string())
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                    .as_table_mut()
+                    .unwrap();
+            }
+            table.insert(key.to_string(), value);
+        }
+
+        let mut table = toml::Table::new();
+        set(&mut table, &["line_width"], self.line_width);
+        set(&mut table, &["wrap"], self.wrap);
+        set(
+            &mut table,
+            &["unordered_list", "unordered_marker"],
+            self.unordered_marker,
+        );
+        set(&mut table, &["code_block", "fence_char"], self.fence_char);
+        set(&mut table, &["heading", "setext_h1"], self.setext);
+        set(&mut table, &["heading", "setext_h2"], self.setext);
+        set(&mut table, &["line_ending"], self.line_ending);
+        set(&mut table, &["list", "spacing"], self.list_spacing);
+        set(
+            &mut table,
+            &["link", "external_references"],
+            self.external_references,
+        );
+        set(
+            &mut table,
+            &["link", "sort_definitions"],
+            self.sort_definitions,
+        );
+        set(
+            &mut table,
+            &["ordered_list", "numbering"],
+            self.ordered_numbering,
+        );
+        set(&mut table, &["table", "padding"], self.table_padding);
+        set(
+            &mut table,
+            &["max_consecutive_blank_lines"],
+            self.max_consecutive_blank_lines,
+        );
+        table
+    }
+}
+
+/// A named set of settings for a kind of document, set with the `preset`
+/// key.
+///
+/// The settings of a preset act as if they were written in the file that
+/// names it, under the keys the file sets itself, so that any of them can
+/// still be changed.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// For changelogs in the style of [Keep a Changelog]: entries are never
+    /// wrapped, lists are tight, headings are in ATX style, and inline links
+    /// are kept inline.
+    ///
+    /// [Keep a Changelog]: https://keepachangelog.com/
+    Changelog,
+    /// For READMEs in the style common on GitHub: headings are in ATX style,
+    /// code blocks are fenced with backticks, and inline links are kept
+    /// inline.
+    Readme,
+    /// For documents kept in version control, whose diffs should show only
+    /// what changed: each sentence goes on a line of its own, every item of
+    /// an ordered list is numbered `1.`, the definitions of reference links
+    /// are in the order they are used, table cells aren't padded, and
+    /// sections are separated by a single blank line.  Editing a sentence,
+    /// adding a list item, or changing a table cell touches a single line.
+    #[serde(rename = "diff-friendly")]
+    DiffFriendly,
+}
+
+impl Preset {
+    /// The settings of this preset.
+    pub fn partial_config(self) -> PartialConfig {
+        match self {
+            Self::Changelog => PartialConfig {
+                wrap: Some(WrapMode::None),
+                setext: Some(false),
+                list_spacing: Some(ListSpacing::Tight),
+                external_references: Some(false),
+                ..PartialConfig::default()
+            },
+            Self::Readme => PartialConfig {
+                fence_char: Some(FenceChar::Backtick),
+                setext: Some(false),
+                external_references: Some(false),
+                ..PartialConfig::default()
+            },
+            Self::DiffFriendly => PartialConfig {
+                wrap: Some(WrapMode::Sentence),
+                external_references: Some(true),
+                sort_definitions: Some(DefinitionOrder::Usage),
+                ordered_numbering: Some(OrderedNumbering::Ones),
+                table_padding: Some(TablePadding::Compact),
+                max_consecutive_blank_lines: Some(MaxBlankLines(1)),
+                ..PartialConfig::default()
+            },
+        }
+    }
+
+    /// Deserialize the configuration `table` with the settings of this
+    /// preset under the ones it sets itself.
+    fn apply_under<T: serde::de::DeserializeOwned>(
+        self,
+        table: toml::Table,
+    ) -> Result<T, toml::de::Error> {
+        fn merge(base: &mut toml::Table, over: toml::Table) {
+            for (key, value) in over {
+                match (base.get_mut(&key), value) {
+                    (Some(toml::Value::Table(base)), toml::Value::Table(over)) => {
+                        merge(base, over);
+                    }
+                    (_, value) => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+
+        let mut merged = self.partial_config().to_toml_table();
+        merge(&mut merged, table);
+        merged.try_into()
+    }
+}
+
 /// Heading formatting options.
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(default)]
 pub struct HeadingConfig {
     /// Use `===` underline for h1 (default: true).
@@ -184,6 +842,11 @@ pub struct HeadingConfig {
     /// Use `---` underline for h2 (default: true).
     pub setext_h2: bool,
 
+    /// Keep hard line breaks in headings, which makes the heading span
+    /// several lines and so forces setext style (default: true).  When
+    /// false, hard line breaks in headings are replaced with spaces.
+    pub allow_multiline: bool,
+
     /// Convert headings to sentence case (default: false).
     pub sentence_case: bool,
 
@@ -196,6 +859,19 @@ pub struct HeadingConfig {
     /// Useful for words like "Go" which can be either a programming language
     /// or a common verb depending on context.
     pub common_nouns: Vec<String>,
+
+    /// Trailing characters to strip from headings, such as `":"` or `"."`
+    /// (default: none).  Runs of them are stripped together.
+    pub strip_trailing_punctuation: Vec<String>,
+
+    /// Number the headings at the top level of the document, replacing the
+    /// numbers they already start with (default: false).
+    pub numbering: bool,
+
+    /// Heading levels that get numbers when `numbering` is on (default:
+    /// `[2, 3]`).  Headings at a higher level, like level 1 by default,
+    /// restart the numbering.
+    pub numbering_levels: Vec<HeadingLevel>,
 }
 
 impl Default for HeadingConfig {
@@ -203,15 +879,19 @@ impl Default for HeadingConfig {
         Self {
             setext_h1: true,
             setext_h2: true,
+            allow_multiline: true,
             sentence_case: false,
             proper_nouns: Vec::new(),
             common_nouns: Vec::new(),
+            strip_trailing_punctuation: Vec::new(),
+            numbering: false,
+            numbering_levels: vec![HeadingLevel(2), HeadingLevel(3)],
         }
     }
 }
 
 /// Marker character for unordered lists.
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
 pub enum UnorderedMarker {
     /// Hyphen marker (`-`)
     #[default]
@@ -237,7 +917,7 @@ impl UnorderedMarker {
 }
 
 /// Leading spaces before a list marker or thematic break (0-3).
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct LeadingSpaces(usize);
 
 impl LeadingSpaces {
@@ -282,7 +962,7 @@ impl<'de> serde::Deserialize<'de> for LeadingSpaces {
 }
 
 /// Trailing spaces after a list marker (0-3).
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct TrailingSpaces(usize);
 
 impl TrailingSpaces {
@@ -327,7 +1007,7 @@ impl<'de> serde::Deserialize<'de> for TrailingSpaces {
 }
 
 /// Indentation width for nested list items (must be at least 1).
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct IndentWidth(usize);
 
 impl IndentWidth {
@@ -372,7 +1052,7 @@ impl<'de> serde::Deserialize<'de> for IndentWidth {
 }
 
 /// Maximum line width for text wrapping (must be at least 8).
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct LineWidth(usize);
 
 impl LineWidth {
@@ -424,79 +1104,371 @@ impl<'de> serde::Deserialize<'de> for LineWidth {
     }
 }
 
-/// Unordered list formatting options.
-#[derive(Debug, Clone, Deserialize, PartialEq, Default)]
-#[serde(default)]
-pub struct UnorderedListConfig {
-    /// Marker character: `-`, `*`, or `+` (default: `-`).
-    pub unordered_marker: UnorderedMarker,
-
-    /// Spaces before the marker (default: 1).
-    pub leading_spaces: LeadingSpaces,
-
-    /// Spaces after the marker (default: 2).
-    pub trailing_spaces: TrailingSpaces,
-
-    /// Indentation width for nested items (default: 4).
-    pub indent_width: IndentWidth,
+/// How paragraphs are wrapped.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapMode {
+    /// Wrap lines longer than `line_width`, keeping short lines as they are
+    /// (default).
+    #[default]
+    Width,
+    /// Never wrap: join each paragraph into a single line.
+    None,
+    /// Keep the original line breaks, even for lines longer than
+    /// `line_width`.
+    Preserve,
+    /// Put each sentence on a line of its own, even if it is longer than
+    /// `line_width`.
+    Sentence,
 }
 
-/// Marker character for ordered lists.
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Default)]
-pub enum OrderedMarker {
-    /// Period marker (`.`) - `1.`, `2.`, etc.
+/// Line ending style for the output.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// Unix-style line feeds (`\n`) (default).
     #[default]
-    #[serde(rename = ".")]
-    Period,
-    /// Parenthesis marker (`)`) - `1)`, `2)`, etc.
-    #[serde(rename = ")")]
-    Parenthesis,
+    Lf,
+    /// Windows-style carriage return and line feed pairs (`\r\n`).
+    Crlf,
+    /// Use the style of the first line ending in the input.
+    Auto,
 }
 
-impl OrderedMarker {
-    /// Get the character representation of this marker.
-    pub fn as_char(self) -> char {
+impl LineEnding {
+    /// Resolve the line ending string to use for the given input.
+    pub fn resolve(self, input: &str) -> &'static str {
         match self {
-            Self::Period => '.',
-            Self::Parenthesis => ')',
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Auto => match input.find('\n') {
+                Some(i) if input[..i].ends_with('\r') => "\r\n",
+                _ => "\n",
+            },
         }
     }
 }
 
-/// Padding style for ordered list numbers.
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Default)]
+/// What is done with a UTF-8 byte order mark at the start of the input.
+///
+/// The mark is never parsed as part of the first block either way.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
-pub enum OrderedListPad {
-    /// Pad before the number (default): `  1.`, `  2.`, ..., ` 10.`
+pub enum ByteOrderMark {
+    /// Write the mark at the start of the output if the input has one
+    /// (default).
     #[default]
-    Start,
-    /// Pad after the number: `1. `, `2. `, ..., `10.`
-    End,
+    Preserve,
+    /// Leave the mark out of the output.
+    Remove,
 }
 
-/// Ordered list formatting options.
-#[derive(Debug, Clone, Deserialize, PartialEq)]
-#[serde(default)]
-pub struct OrderedListConfig {
-    /// Marker style at odd nesting levels: `.` for `1.` (default: `.`).
-    pub odd_level_marker: OrderedMarker,
-
-    /// Marker style at even nesting levels: `)` for `1)` (default: `)`).
-    pub even_level_marker: OrderedMarker,
-
-    /// Padding style for aligning numbers of different widths (default: `start`).
-    pub pad: OrderedListPad,
-
-    /// Indentation width for nested ordered list items (default: 4).
-    pub indent_width: IndentWidth,
+/// How hard line breaks are written at the end of their lines.
+///
+/// Other trailing whitespace is always stripped from the output.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HardBreak {
+    /// Two spaces (default).
+    #[default]
+    Spaces,
+    /// A backslash, which, unlike spaces, can be seen.
+    Backslash,
 }
 
-impl Default for OrderedListConfig {
+/// How character entity references, like `&nbsp;` or `&#215;`, in text are
+/// written.
+///
+/// Whatever the mode, an `&` in text that would start an entity reference
+/// is escaped, and entities for ASCII punctuation, like `&lt;`, are kept, as
+/// the character would need escaping in their place.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityMode {
+    /// Keep entities as they are written in the source (default).
+    #[default]
+    Preserve,
+    /// Write the characters entities stand for.
+    Decode,
+    /// Like `decode`, but write characters that can't be told apart
+    /// visually, like a no-break space, a soft hyphen, or a zero-width
+    /// joiner, as entities.
+    EncodeInvisible,
+}
+
+/// A template syntax, like that of Jekyll or Hugo, whose tags in the
+/// Markdown source are kept as they are.
+///
+/// Tags in text are never escaped or broken across lines, and a paragraph
+/// with a line that is nothing but a tag, like `{% if page.draft %}`, is
+/// written as it is in the source.  Tags aren't parsed: they are found by
+/// their delimiters only.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateSyntax {
+    /// No template tags (default).
+    #[default]
+    None,
+    /// Liquid, as in Jekyll: `{{ variable }}` and `{% tag %}`.
+    Liquid,
+    /// Hugo: `{{< shortcode >}}`, `{{% shortcode %}}`, and `{{ variable }}`.
+    Hugo,
+    /// Jinja, as in MkDocs macros: `{{ variable }}`, `{% tag %}`, and
+    /// `{# comment #}`.
+    Jinja,
+}
+
+/// A lint rule whose problems are fixed while formatting, rather than only
+/// reported.
+///
+/// The rules are named after the [markdownlint] rules they follow.
+///
+/// [markdownlint]: https://github.com/DavidAnson/markdownlint
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FixRule {
+    /// Headings more than one level deeper than the heading they are under
+    /// are moved up, along with the headings under them.
+    HeadingIncrement,
+    /// The trailing punctuation in `heading.strip_trailing_punctuation`, or
+    /// `.,;:!` and their full-width forms if it is empty, is removed from
+    /// headings.
+    NoTrailingPunctuation,
+    /// Links with an empty destination, like `[text]()` or `[text](#)`, are
+    /// replaced with their text.
+    NoEmptyLinks,
+    /// Code blocks without a language are given `code_block.default_language`.
+    CodeLanguage,
+}
+
+impl FixRule {
+    /// The name of the rule, as it is written in the configuration.
+    pub fn name(self) -> &'static str {
+        match self {
+            FixRule::HeadingIncrement => "heading-increment",
+            FixRule::NoTrailingPunctuation => "no-trailing-punctuation",
+            FixRule::NoEmptyLinks => "no-empty-links",
+            FixRule::CodeLanguage => "code-language",
+        }
+    }
+}
+
+/// The Markdown dialect of the output.
+///
+/// Input is always parsed as GitHub Flavored Markdown, so that documents
+/// using its extensions are understood whatever the dialect.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    /// GitHub Flavored Markdown (default).
+    #[default]
+    Gfm,
+    /// Plain CommonMark, for renderers without the GitHub extensions:
+    /// tables are written as HTML or as text (see
+    /// [`CommonMarkConfig::tables`]), strikethrough as `<del>` elements, and
+    /// task list checkboxes as escaped text.
+    #[serde(rename = "commonmark")]
+    CommonMark,
+}
+
+/// The language whose rules paragraphs are written by, for what the rules
+/// of English don't fit.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// No rules of a particular language (default).
+    #[default]
+    None,
+    /// Korean: a sentence that ends in Hangul, like `합니다.` or `할까?`, ends
+    /// there even if the next one starts with a lowercase word, like `npm`,
+    /// and a word of a single syllable, like `네.`, isn't an initial.
+    Ko,
+    /// The rules of the language of each paragraph, told by the script most
+    /// of its letters are in: Korean for Hangul, none otherwise.
+    Auto,
+}
+
+/// How tables are written in the `commonmark` dialect, which has none.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFallback {
+    /// As an HTML `<table>` block (default).
+    #[default]
+    Html,
+    /// As the table's source text, which CommonMark reads as a paragraph.
+    Text,
+}
+
+/// Unordered list formatting options.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(default)]
+pub struct UnorderedListConfig {
+    /// Marker character: `-`, `*`, or `+` (default: `-`).
+    pub unordered_marker: UnorderedMarker,
+
+    /// Spaces before the marker (default: 1).
+    pub leading_spaces: LeadingSpaces,
+
+    /// Spaces after the marker (default: 2).
+    pub trailing_spaces: TrailingSpaces,
+
+    /// Indentation width for nested items (default: 4).
+    pub indent_width: IndentWidth,
+}
+
+/// How two adjacent lists of the same kind are kept apart.
+///
+/// Since all bullet lists use the same marker, and all ordered lists at the
+/// same level use the same delimiter, two such lists written one after the
+/// other would be read back as a single list.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ListSeparation {
+    /// Put an empty HTML comment (`<!-- -->`) between the lists (default).
+    #[default]
+    Comment,
+    /// Use a different marker for every other list: `*` instead of `-` (or
+    /// `-` instead of `*` and `+`) for bullet lists, and the other delimiter
+    /// for ordered lists.
+    AlternateMarker,
+}
+
+/// Whether lists have blank lines between their items.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ListSpacing {
+    /// Keep lists tight or loose as they are (default).
+    #[default]
+    Preserve,
+    /// Write all lists without blank lines between their items.  Blank
+    /// lines between the blocks inside an item are kept.
+    Tight,
+    /// Write all lists with blank lines between their items.
+    Loose,
+}
+
+/// Where the wrapped lines of a list item's text start.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ListContinuation {
+    /// Align the wrapped lines with the text after the marker (default).
+    #[default]
+    AlignText,
+    /// Indent the wrapped lines by a fixed number of columns from the
+    /// marker, however wide the marker is.
+    Fixed,
+}
+
+/// Options common to all lists.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ListConfig {
+    /// How adjacent lists of the same kind are kept apart (default:
+    /// `comment`).
+    pub separation: ListSeparation,
+
+    /// Whether there are blank lines between items (default: `preserve`).
+    pub spacing: ListSpacing,
+
+    /// Where the wrapped lines of an item's text start (default:
+    /// `align_text`).
+    pub continuation: ListContinuation,
+
+    /// The indentation of wrapped lines from the marker when `continuation`
+    /// is `fixed` (default: 2).
+    pub fixed_width: usize,
+
+    /// Whether paragraphs with lines that start like the items of a list
+    /// lettered or numbered in roman numerals, like `a.` or `iv)`, keep their
+    /// line breaks (default: false).
+    pub preserve_pseudo_lists: bool,
+}
+
+impl Default for ListConfig {
+    fn default() -> Self {
+        Self {
+            separation: ListSeparation::default(),
+            spacing: ListSpacing::default(),
+            continuation: ListContinuation::default(),
+            fixed_width: 2,
+            preserve_pseudo_lists: false,
+        }
+    }
+}
+
+/// Marker character for ordered lists.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+pub enum OrderedMarker {
+    /// Period marker (`.`) - `1.`, `2.`, etc.
+    #[default]
+    #[serde(rename = ".")]
+    Period,
+    /// Parenthesis marker (`)`) - `1)`, `2)`, etc.
+    #[serde(rename = ")")]
+    Parenthesis,
+}
+
+impl OrderedMarker {
+    /// Get the character representation of this marker.
+    pub fn as_char(self) -> char {
+        match self {
+            Self::Period => '.',
+            Self::Parenthesis => ')',
+        }
+    }
+}
+
+/// Padding style for ordered list numbers.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderedListPad {
+    /// Pad before the number (default): `  1.`, `  2.`, ..., ` 10.`
+    #[default]
+    Start,
+    /// Pad after the number: `1. `, `2. `, ..., `10.`
+    End,
+}
+
+/// How the items of ordered lists are numbered.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderedNumbering {
+    /// `1.`, `2.`, `3.`, ... (default).
+    #[default]
+    Sequential,
+    /// `1.` for every item, which Markdown renders as numbered in order all
+    /// the same, so that adding or removing an item doesn't renumber the
+    /// ones after it.
+    Ones,
+}
+
+/// Ordered list formatting options.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct OrderedListConfig {
+    /// Marker style at odd nesting levels: `.` for `1.` (default: `.`).
+    pub odd_level_marker: OrderedMarker,
+
+    /// Marker style at even nesting levels: `)` for `1)` (default: `)`).
+    pub even_level_marker: OrderedMarker,
+
+    /// Padding style for aligning numbers of different widths (default: `start`).
+    pub pad: OrderedListPad,
+
+    /// How the items are numbered (default: `sequential`).
+    pub numbering: OrderedNumbering,
+
+    /// Indentation width for nested ordered list items (default: 4).
+    pub indent_width: IndentWidth,
+}
+
+impl Default for OrderedListConfig {
     fn default() -> Self {
         Self {
             odd_level_marker: OrderedMarker::default(),
             even_level_marker: OrderedMarker::Parenthesis,
             pad: OrderedListPad::Start,
+            numbering: OrderedNumbering::default(),
             indent_width: IndentWidth::default(),
         }
     }
@@ -504,7 +1476,7 @@ impl Default for OrderedListConfig {
 
 /// Default timeout for external formatters in seconds.
 fn default_formatter_timeout() -> u64 {
-    5
+    10
 }
 
 /// External formatter configuration for a single language.
@@ -512,7 +1484,7 @@ fn default_formatter_timeout() -> u64 {
 /// Can be specified in two formats:
 /// - Simple: `["command", "arg1", "arg2"]`
 /// - Full: `{ command = ["command", "arg1"], timeout = 10 }`
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(untagged)]
 pub enum FormatterConfig {
     /// Simple array format: `["deno", "fmt", "-"]`
@@ -521,7 +1493,7 @@ pub enum FormatterConfig {
     Full {
         /// Command and arguments as a vector.
         command: Vec<String>,
-        /// Timeout in seconds (default: 5).
+        /// Timeout in seconds (default: 10).
         #[serde(default = "default_formatter_timeout")]
         timeout: u64,
     },
@@ -556,7 +1528,7 @@ impl FormatterConfig {
 }
 
 /// Fence character for code blocks.
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
 pub enum FenceChar {
     /// Tilde fence (`~`)
     #[default]
@@ -578,7 +1550,7 @@ impl FenceChar {
 }
 
 /// Minimum fence length for code blocks (must be at least 3).
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct MinFenceLength(usize);
 
 impl MinFenceLength {
@@ -622,8 +1594,46 @@ impl<'de> serde::Deserialize<'de> for MinFenceLength {
     }
 }
 
+/// The most blank lines written between two blocks (must be at least 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MaxBlankLines(usize);
+
+impl MaxBlankLines {
+    /// Create a new MaxBlankLines.
+    ///
+    /// Returns an error if the value is 0, which would join blocks.
+    pub fn new(value: usize) -> Result<Self, String> {
+        if value == 0 {
+            Err("max_consecutive_blank_lines must be at least 1, got 0.".to_string())
+        } else {
+            Ok(Self(value))
+        }
+    }
+
+    /// Get the inner value.
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl Default for MaxBlankLines {
+    fn default() -> Self {
+        Self(2)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for MaxBlankLines {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = usize::deserialize(deserializer)?;
+        Self::new(value).map_err(serde::de::Error::custom)
+    }
+}
+
 /// Code block formatting options.
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(default)]
 pub struct CodeBlockConfig {
     /// Fence character: `~` or `` ` `` (default: `~`).
@@ -640,10 +1650,23 @@ pub struct CodeBlockConfig {
     /// Set to e.g. "text" to add a default language identifier.
     pub default_language: String,
 
+    /// Strip trailing whitespace from the lines of code blocks (default:
+    /// false).
+    pub trim_trailing_whitespace: bool,
+
+    /// Languages whose code blocks Hongdown formats itself, without an
+    /// external formatter: `json`, `toml`, or both (default: none).
+    pub format_embedded: Vec<EmbeddedLanguage>,
+
+    /// The order of the keys of objects in `json` code blocks formatted
+    /// with `format_embedded` (default: `preserve`).
+    pub json_key_order: JsonKeyOrder,
+
     /// External formatters for code blocks by language.
     ///
     /// Key: language identifier (exact match only).
     /// Value: formatter configuration.
+    #[serde(serialize_with = "serialize_sorted")]
     pub formatters: HashMap<String, FormatterConfig>,
 }
 
@@ -654,17 +1677,54 @@ impl Default for CodeBlockConfig {
             min_fence_length: MinFenceLength::default(),
             space_after_fence: true,
             default_language: String::new(),
+            trim_trailing_whitespace: false,
+            format_embedded: Vec::new(),
+            json_key_order: JsonKeyOrder::default(),
             formatters: HashMap::new(),
         }
     }
 }
 
+/// A language whose code blocks Hongdown can format itself, as listed in
+/// `code_block.format_embedded`.  A code block is in the language if the
+/// first word of its info string is the name of the language.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddedLanguage {
+    /// Re-indent JSON to 2 spaces, with one value per line.
+    Json,
+    /// Normalize the whitespace of TOML, keeping its comments.
+    Toml,
+}
+
+/// The order of the keys of objects in JSON code blocks formatted by
+/// Hongdown.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonKeyOrder {
+    /// Keep the keys in the order they are written (default).
+    #[default]
+    Preserve,
+    /// Sort the keys.
+    Sort,
+}
+
+/// Serialize a map with its keys sorted, so that output is deterministic.
+fn serialize_sorted<S, V>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: Serialize,
+{
+    let sorted: std::collections::BTreeMap<_, _> = map.iter().collect();
+    sorted.serialize(serializer)
+}
+
 /// Thematic break style string (must be a valid CommonMark thematic break pattern).
 ///
 /// A valid thematic break consists of:
 /// - At least 3 of the same character: `*`, `-`, or `_`
 /// - Optional spaces between the characters
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ThematicBreakStyle(String);
 
 impl ThematicBreakStyle {
@@ -765,8 +1825,35 @@ impl<'de> serde::Deserialize<'de> for ThematicBreakStyle {
     }
 }
 
+/// Block quote formatting options.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct BlockQuoteConfig {
+    /// Keep the spaces after `>` on lines that are otherwise unchanged,
+    /// instead of writing a single space (default: false).
+    pub preserve_marker_spacing: bool,
+
+    /// How two adjacent block quotes are kept apart (default: `blank`).
+    pub separation: BlockQuoteSeparation,
+}
+
+/// How two adjacent block quotes are kept apart.
+///
+/// A blank line ends a block quote, but a tool that takes a blank line in
+/// a block quote for a lazy continuation, or that drops blank lines, would
+/// read two adjacent block quotes back as one.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockQuoteSeparation {
+    /// Put a blank line without `>` between the block quotes (default).
+    #[default]
+    Blank,
+    /// Put an empty HTML comment (`<!-- -->`) between the block quotes too.
+    Comment,
+}
+
 /// Thematic break (horizontal rule) formatting options.
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(default)]
 pub struct ThematicBreakConfig {
     /// The style string for thematic breaks (default: `*  *  *`).
@@ -786,86 +1873,626 @@ impl Default for ThematicBreakConfig {
     }
 }
 
-/// Dash pattern for en-dash or em-dash transformation.
-/// Must be a non-empty string of valid characters.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct DashPattern(String);
+/// Table formatting options.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TableConfig {
+    /// The display width of the text of a table cell above which a warning
+    /// is reported, and which `wrap_cells` breaks it at (default: none).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_column_width: Option<usize>,
+
+    /// How cells wider than `max_column_width` are broken into lines
+    /// (default: `none`).
+    pub wrap_cells: CellWrap,
+
+    /// Whether cells are padded to the width of their column (default:
+    /// `aligned`).
+    pub padding: TablePadding,
+
+    /// Whether tables are formatted or kept as they are written (default:
+    /// `align`).
+    pub format: TableFormat,
+
+    /// Whether the delimiter row is as wide as the columns (default:
+    /// `full`).
+    pub separator: TableSeparator,
+
+    /// Whether the widths of columns are rounded up and kept from the
+    /// previous formatting (default: `exact`).
+    pub width_growth: TableWidthGrowth,
+
+    /// The multiple that `sticky` columns are rounded up to (default: 4).
+    pub width_step: TableWidthStep,
+}
 
-impl DashPattern {
-    /// Create a new DashPattern.
-    ///
-    /// Returns an error if the pattern is empty or contains invalid characters.
-    pub fn new(pattern: String) -> Result<Self, String> {
-        if pattern.is_empty() {
-            return Err("dash pattern cannot be empty.".to_string());
-        }
+/// How the widths of the columns of tables follow their contents.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TableWidthGrowth {
+    /// Make each column exactly as wide as its widest cell (default).
+    #[default]
+    Exact,
+    /// Round the width of each column up to a multiple of `width_step`, and
+    /// never make it narrower than the delimiter row of the table already
+    /// is, so that a small change to a cell doesn't pad every other row
+    /// again.  Columns only grow; they are made narrower by writing the
+    /// delimiter row shorter by hand.
+    Sticky,
+}
 
-        // Check for non-printable or whitespace characters
-        if pattern.chars().any(|c| !c.is_ascii_graphic()) {
-            return Err(format!(
-                "dash pattern must only contain printable ASCII characters (no whitespace), got {:?}.",
-                pattern
-            ));
-        }
+/// The multiple that the widths of `sticky` table columns are rounded up to
+/// (must be at least 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TableWidthStep(usize);
 
-        Ok(Self(pattern))
+impl TableWidthStep {
+    /// Create a new TableWidthStep.
+    ///
+    /// Returns an error if the value is 0.
+    pub fn new(value: usize) -> Result<Self, String> {
+        if value == 0 {
+            Err("width_step must be at least 1, got 0.".to_string())
+        } else {
+            Ok(Self(value))
+        }
     }
 
     /// Get the inner value.
-    pub fn as_str(&self) -> &str {
-        &self.0
+    pub fn get(self) -> usize {
+        self.0
     }
 }
 
-impl<'de> serde::Deserialize<'de> for DashPattern {
+impl Default for TableWidthStep {
+    fn default() -> Self {
+        Self(4)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for TableWidthStep {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let value = String::deserialize(deserializer)?;
+        let value = usize::deserialize(deserializer)?;
         Self::new(value).map_err(serde::de::Error::custom)
     }
 }
 
-/// Dash transformation setting.
-/// Can be `false` (disabled) or a string pattern to match.
-#[derive(Debug, Clone, PartialEq, Default)]
-pub enum DashSetting {
-    /// Dash transformation is disabled.
+/// How wide the delimiters of the delimiter row of tables are.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TableSeparator {
+    /// Make each delimiter as wide as its column, like `:-------` (default).
     #[default]
-    Disabled,
-    /// Transform the given pattern to a dash character.
-    Pattern(DashPattern),
+    Full,
+    /// Write the shortest delimiters, like `---`, `:--`, `--:`, and `:-:`,
+    /// whatever the widths of the columns, so that a wider column doesn't
+    /// change the delimiter row.  The other rows are still padded unless
+    /// `padding` is `compact`.
+    Minimal,
 }
 
-impl<'de> Deserialize<'de> for DashSetting {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        use serde::de::{self, Visitor};
+/// Whether tables are formatted.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    /// Format the cells and align the columns of tables (default).
+    #[default]
+    Align,
+    /// Keep each table as it is written in the source, for hand-aligned
+    /// tables whose spacing carries meaning, such as groups of columns.
+    /// Everything around it is still formatted.
+    Preserve,
+}
 
-        struct DashSettingVisitor;
+/// Whether the cells of tables are padded to the width of their column.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TablePadding {
+    /// Pad the cells, so that the pipes of the rows line up (default).
+    #[default]
+    Aligned,
+    /// Write each cell with a single space on each side, and the delimiter
+    /// row with the shortest delimiters, like `---` and `:-:`, so that
+    /// changing a cell doesn't change the other rows.
+    Compact,
+}
 
-        impl<'de> Visitor<'de> for DashSettingVisitor {
-            type Value = DashSetting;
+/// How the text of table cells wider than `table.max_column_width` is broken
+/// into lines.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CellWrap {
+    /// Keep the text on one line (default).
+    #[default]
+    None,
+    /// Put `<br>` between words, so that no line of the text is wider than
+    /// `table.max_column_width`, unless a single word, code span, or link
+    /// is.  Code spans and links are never broken.
+    Br,
+}
 
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("false or a string pattern")
-            }
+/// Options for the HTML void elements, like `<br>` and `<hr>`, that are used
+/// for layout, and for `<details>` blocks.  All other HTML is kept as it is.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct HtmlConfig {
+    /// Write the tags of void elements, like `<BR/>`, in the
+    /// `void_tag_style`, in lowercase (default: false).
+    pub normalize_void_tags: bool,
+
+    /// How the tags of void elements are closed when normalized (default:
+    /// `html`).
+    pub void_tag_style: VoidTagStyle,
+
+    /// Write a `<br>` at the end of a line of a paragraph as a hard line
+    /// break, in the `hard_break` style (default: false).  Those in table
+    /// cells are kept.
+    pub br_to_hard_break: bool,
+
+    /// Write an `<hr>` that is a block of its own as a thematic break
+    /// (default: false).
+    pub hr_to_thematic_break: bool,
+
+    /// How `<details>` blocks with Markdown inside are handled (default:
+    /// `format`).
+    pub details: DetailsHandling,
+}
 
-            fn visit_bool<E>(self, value: bool) -> Result<DashSetting, E>
-            where
-                E: de::Error,
-            {
-                if value {
-                    Err(de::Error::custom(
-                        "expected false or a string pattern, got true",
-                    ))
-                } else {
-                    Ok(DashSetting::Disabled)
-                }
-            }
+/// How the tags of HTML void elements are closed.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VoidTagStyle {
+    /// `<br>` (default).
+    #[default]
+    Html,
+    /// `<br />`.
+    Xhtml,
+}
+
+/// How `<details>` blocks with Markdown inside are handled.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DetailsHandling {
+    /// Keep the tags as they are, with a blank line after the `<summary>`
+    /// and before the closing tag, so that what is between them is
+    /// Markdown, and format it (default).
+    #[default]
+    Format,
+    /// Keep the whole block as it is, like any other HTML.
+    Preserve,
+}
+
+/// Options for backslash escapes in text.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct EscapeConfig {
+    /// Which literal backslashes in text are escaped (default: `always`).
+    pub backslash: BackslashEscape,
+}
+
+/// Options for turning references to issues and users into links.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct AutolinkConfig {
+    /// References to GitHub issues, pull requests, and users (default:
+    /// off).
+    pub github: GitHubAutolinkConfig,
+}
+
+/// Options for references to GitHub issues, pull requests, and users, like
+/// `#123`, `GH-123`, `owner/name#123`, and `@user`, which GitHub links by
+/// itself but other sites don't.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct GitHubAutolinkConfig {
+    /// The repository `#123` and `GH-123` refer to, as `owner/name`
+    /// (default: none).  When set, bare references in prose are turned into
+    /// links to GitHub.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<GitHubRepo>,
+
+    /// Turn links to GitHub whose text is a reference to what they link
+    /// to, like `[#123](https://github.com/owner/name/issues/123)`, back
+    /// into bare references, for documents rendered by GitHub (default:
+    /// false).
+    pub strip: bool,
+}
+
+/// A GitHub repository, as `owner/name`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GitHubRepo(String);
+
+impl GitHubRepo {
+    /// Create a new GitHubRepo.
+    ///
+    /// Returns an error if the repository is not written as `owner/name`.
+    pub fn new(repo: String) -> Result<Self, String> {
+        let valid = repo.split_once('/').is_some_and(|(owner, name)| {
+            !owner.is_empty()
+                && !name.is_empty()
+                && owner.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+        if valid {
+            Ok(Self(repo))
+        } else {
+            Err(format!(
+                "autolink.github.repo must be written as owner/name, got {:?}.",
+                repo
+            ))
+        }
+    }
+
+    /// Get the repository as `owner/name`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for GitHubRepo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Self::new(value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which literal backslashes in text, like those of `C:\Users`, are escaped.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BackslashEscape {
+    /// Escape every literal backslash, like `C:\\Users` (default).
+    #[default]
+    Always,
+    /// Escape a literal backslash only where it would escape what comes
+    /// after it otherwise, which is before ASCII punctuation, or make a hard
+    /// line break, which is before whitespace and at the end of a line.
+    /// `C:\Users` is kept as it is, as `\U` is not an escape.
+    Lazy,
+}
+
+/// Link reference definition options.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct LinkConfig {
+    /// Drop reference definitions that no link or image uses (default:
+    /// true).  When false, unused single-line definitions are kept at the
+    /// end of the document.
+    pub remove_unused_definitions: bool,
+
+    /// Which syntax reference links and images are written in when their
+    /// text matches their label (default: `shortcut`).
+    pub reference_syntax: ReferenceSyntax,
+
+    /// Turn inline links to external URLs into reference links (default:
+    /// true).  When false, they are kept inline.
+    pub external_references: bool,
+
+    /// How characters in link and image URLs are percent-encoded (default:
+    /// `preserve`).
+    pub url_encoding: UrlEncoding,
+
+    /// The order reference definitions are written in (default: `none`).
+    pub sort_definitions: DefinitionOrder,
+
+    /// How links to external URLs with empty text, like `[](url)`, are
+    /// written (default: `autolink`).
+    pub empty_text: EmptyLinkText,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            remove_unused_definitions: true,
+            reference_syntax: ReferenceSyntax::default(),
+            external_references: true,
+            url_encoding: UrlEncoding::default(),
+            sort_definitions: DefinitionOrder::default(),
+            empty_text: EmptyLinkText::default(),
+        }
+    }
+}
+
+/// Footnote options.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct FootnoteConfig {
+    /// The order footnote definitions are written in (default: `none`).
+    pub sort_definitions: DefinitionOrder,
+
+    /// What is done with inline footnotes, like `^[a note]` (default:
+    /// `preserve`).
+    pub inline: InlineFootnotes,
+}
+
+/// What is done with Pandoc-style inline footnotes, like `^[a note]`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InlineFootnotes {
+    /// Keep them inline, with their brackets unescaped (default).
+    #[default]
+    Preserve,
+    /// Turn them into references to numbered footnotes, whose definitions
+    /// are placed like those of the other footnotes.
+    Expand,
+}
+
+/// Table of contents options.
+///
+/// A table of contents is generated between `<!-- toc -->` and
+/// `<!-- /toc -->` (or `<!-- hongdown-toc -->` and `<!-- /hongdown-toc -->`)
+/// markers, listing the document's headings of these levels.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TocConfig {
+    /// The shallowest heading level listed (1-6, default: 2).
+    pub min_level: HeadingLevel,
+
+    /// The deepest heading level listed (1-6, default: 3).
+    pub max_level: HeadingLevel,
+}
+
+impl Default for TocConfig {
+    fn default() -> Self {
+        Self {
+            min_level: HeadingLevel::new(2).unwrap(),
+            max_level: HeadingLevel::new(3).unwrap(),
+        }
+    }
+}
+
+/// Options for the `commonmark` dialect.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct CommonMarkConfig {
+    /// How tables are written (default: `html`).
+    pub tables: TableFallback,
+}
+
+/// Checks that go beyond formatting.
+///
+/// These produce warnings only; they never change how a document is
+/// formatted.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct LintConfig {
+    /// Report links to missing fragments of the document, relative links to
+    /// missing files, and reference links without a definition, like
+    /// `--check-links` (default: false).
+    pub links: bool,
+
+    /// Report zero-width and bidirectional formatting characters, which
+    /// can't be seen and may make text read differently from how it looks
+    /// (default: false).
+    pub invisible_characters: bool,
+
+    /// Report heading level jumps, level 1 headings after the first, headings
+    /// with the same text as an earlier one at the same level, and empty
+    /// headings (default: false).
+    pub headings: bool,
+
+    /// Report code blocks without a language.  Unless the `code-language`
+    /// rule is in `fix`, they are then written without one instead of being
+    /// given `code_block.default_language` (default: false).
+    pub code_language: bool,
+
+    /// The languages `code_language` accepts, such as `"text"`; an empty
+    /// string lets code blocks have none (default: empty).
+    pub code_language_exceptions: Vec<String>,
+}
+
+/// Heading level (1-6).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HeadingLevel(u8);
+
+impl HeadingLevel {
+    /// Maximum heading level.
+    pub const MAX: u8 = 6;
+
+    /// Create a new HeadingLevel.
+    ///
+    /// Returns an error if the value is not between 1 and 6.
+    pub fn new(value: u8) -> Result<Self, String> {
+        if value == 0 || value > Self::MAX {
+            Err(format!(
+                "heading level must be between 1 and {}, got {}.",
+                Self::MAX,
+                value
+            ))
+        } else {
+            Ok(Self(value))
+        }
+    }
+
+    /// Get the inner value.
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for HeadingLevel {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = u8::deserialize(deserializer)?;
+        Self::new(value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Syntax for reference links and images whose text matches their label.
+///
+/// References whose text differs from their label other than in case are
+/// always written in full, as `[text][label]`, with the label in lowercase.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceSyntax {
+    /// `[text]` (default).  Where the next character is `[` or `(`, which
+    /// would make it read as a longer link, `[text][]` is used instead.
+    #[default]
+    Shortcut,
+    /// `[text][]`.
+    Collapsed,
+    /// `[text][text]`.
+    Full,
+    /// Keep the syntax the reference was written in.  External links
+    /// converted to references use the shortcut syntax.
+    Preserve,
+}
+
+/// How characters in link and image URLs are percent-encoded.
+///
+/// Existing `%XX` escapes are never encoded again, and links to fragments of
+/// the document (`#section`) and `mailto:` and `tel:` URLs are always kept
+/// as they are.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlEncoding {
+    /// Keep URLs as they are written (default).
+    #[default]
+    Preserve,
+    /// Percent-encode the characters that are not allowed in URLs, such as
+    /// spaces, double quotes, and non-ASCII characters.  The host name is
+    /// left alone.
+    Encode,
+    /// Decode escapes of characters that never need one, such as `%41` for
+    /// `A`, so that URLs are easier to read.
+    DecodeUnreserved,
+}
+
+/// How links to external URLs with empty text, like `[](url)`, are written.
+///
+/// Such links can't become reference links, since a label can't be empty.
+/// Links with a title, and those whose URL can't be written as an autolink,
+/// are always kept as they are.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyLinkText {
+    /// Write the link as an autolink, `<url>`, whose text is its URL
+    /// (default).
+    #[default]
+    Autolink,
+    /// Keep the link as it is, `[](url)`.
+    Preserve,
+}
+
+/// The order reference definitions or footnote definitions are written in,
+/// within each block of them.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DefinitionOrder {
+    /// The order they are first used in, except that when two or more have
+    /// numeric labels, like `[1]` and `[2]`, those come last, sorted by
+    /// number (default).
+    #[default]
+    None,
+    /// Alphabetical order of their labels, ignoring case, with runs of
+    /// digits compared by their numeric value, so that `[2]` comes before
+    /// `[10]`.
+    Alpha,
+    /// The order they are first used in.
+    Usage,
+}
+
+/// Dash pattern for en-dash or em-dash transformation.
+/// Must be a non-empty string of valid characters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DashPattern(String);
+
+impl DashPattern {
+    /// Create a new DashPattern.
+    ///
+    /// Returns an error if the pattern is empty or contains invalid characters.
+    pub fn new(pattern: String) -> Result<Self, String> {
+        if pattern.is_empty() {
+            return Err("dash pattern cannot be empty.".to_string());
+        }
+
+        // Check for non-printable or whitespace characters
+        if pattern.chars().any(|c| !c.is_ascii_graphic()) {
+            return Err(format!(
+                "dash pattern must only contain printable ASCII characters (no whitespace), got {:?}.",
+                pattern
+            ));
+        }
+
+        Ok(Self(pattern))
+    }
+
+    /// Get the inner value.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for DashPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Self::new(value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Dash transformation setting.
+/// Can be `false` (disabled) or a string pattern to match.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum DashSetting {
+    /// Dash transformation is disabled.
+    #[default]
+    Disabled,
+    /// Transform the given pattern to a dash character.
+    Pattern(DashPattern),
+}
+
+impl Serialize for DashSetting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            DashSetting::Disabled => serializer.serialize_bool(false),
+            DashSetting::Pattern(pattern) => serializer.serialize_str(pattern.as_str()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DashSetting {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::{self, Visitor};
+
+        struct DashSettingVisitor;
+
+        impl<'de> Visitor<'de> for DashSettingVisitor {
+            type Value = DashSetting;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("false or a string pattern")
+            }
+
+            fn visit_bool<E>(self, value: bool) -> Result<DashSetting, E>
+            where
+                E: de::Error,
+            {
+                if value {
+                    Err(de::Error::custom(
+                        "expected false or a string pattern, got true",
+                    ))
+                } else {
+                    Ok(DashSetting::Disabled)
+                }
+            }
 
             fn visit_str<E>(self, value: &str) -> Result<DashSetting, E>
             where
@@ -891,7 +2518,7 @@ impl<'de> Deserialize<'de> for DashSetting {
 }
 
 /// Punctuation transformation options (SmartyPants-style).
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(default)]
 pub struct PunctuationConfig {
     /// Convert straight double quotes to curly quotes (default: true).
@@ -936,35 +2563,50 @@ impl Default for PunctuationConfig {
 
 impl Config {
     /// Parse a configuration from a TOML string.
+    ///
+    /// The settings of the preset, if any, are put under the ones the string
+    /// sets itself (see [`Preset`]).
     pub fn from_toml(toml_str: &str) -> Result<Self, toml::de::Error> {
-        toml::from_str(toml_str)
+        let config: Self = toml::from_str(toml_str)?;
+        match config.preset {
+            Some(preset) => preset.apply_under(toml::from_str(toml_str)?),
+            None => Ok(config),
+        }
+    }
+
+    /// Serialize the configuration to a TOML string.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
     }
 
     /// Load configuration from a file.
     pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
-        let content =
-            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
-        Self::from_toml(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+        let content = ConfigError::read(path)?;
+        #[cfg(feature = "trace")]
+        trace_options(path, &content);
+        Self::from_toml(&content)
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), content, Box::new(e)))
     }
 
     /// Discover and load configuration by searching up the directory tree.
     ///
     /// Starting from `start_dir`, searches for `.hongdown.toml` in each parent
-    /// directory until the filesystem root is reached. Returns `None` if no
-    /// configuration file is found.
-    pub fn discover(start_dir: &Path) -> Result<Option<(PathBuf, Self)>, ConfigError> {
-        let mut current = start_dir.to_path_buf();
-        loop {
-            let config_path = current.join(CONFIG_FILE_NAME);
-            if config_path.exists() {
+    /// directory until the root of the repository or the filesystem root is
+    /// reached, as `options` say. Returns `None` if no configuration file is
+    /// found.  A directory that can't be searched, like one without
+    /// permission, is skipped, and reported to `warn`.
+    pub fn discover(
+        start_dir: &Path,
+        options: DiscoveryOptions,
+        mut warn: impl FnMut(ConfigError),
+    ) -> Result<Option<(PathBuf, Self)>, ConfigError> {
+        match find_upward(start_dir, options, &mut warn) {
+            Some(config_path) => {
                 let config = Self::from_file(&config_path)?;
-                return Ok(Some((config_path, config)));
-            }
-            if !current.pop() {
-                break;
+                Ok(Some((config_path, config)))
             }
+            None => Ok(None),
         }
-        Ok(None)
     }
 
     /// Load cascading configuration from all sources.
@@ -974,11 +2616,18 @@ impl Config {
     /// 1. System config (`/etc/hongdown/config.toml`)
     /// 2. User legacy config (`~/.hongdown.toml`)
     /// 3. User XDG config (`$XDG_CONFIG_HOME/hongdown/config.toml`)
-    /// 4. Project config (`.hongdown.toml` in `start_dir` or parent directories)
+    /// 4. Project config (`.hongdown.toml` in `start_dir` or parent directories
+    ///    up to the root of the repository)
     ///
     /// If the project config has `no_inherit = true`, all parent configs are
-    /// ignored.
-    pub fn load_cascading(start_dir: &Path) -> Result<(Self, Option<PathBuf>), ConfigError> {
+    /// ignored.  The user configs are left out if `options` say so.
+    /// Directories that can't be searched for the project config are skipped,
+    /// and reported to `warn`; see [`Config::discover`].
+    pub fn load_cascading(
+        start_dir: &Path,
+        options: DiscoveryOptions,
+        mut warn: impl FnMut(ConfigError),
+    ) -> Result<(Self, Option<PathBuf>), ConfigError> {
         let mut layers = Vec::new();
         let mut project_config_path = None;
 
@@ -987,25 +2636,30 @@ impl Config {
             layers.push(layer);
         }
 
-        // 2. Try user legacy config: ~/.hongdown.toml
-        if let Some(layer) = Self::load_user_legacy_config()? {
-            layers.push(layer);
-        }
+        if options.user_config {
+            // 2. Try user legacy config: ~/.hongdown.toml
+            if let Some(layer) = Self::load_user_legacy_config()? {
+                layers.push(layer);
+            }
 
-        // 3. Try XDG user config: $XDG_CONFIG_HOME/hongdown/config.toml
-        if let Some(layer) = Self::load_user_xdg_config()? {
-            layers.push(layer);
+            // 3. Try XDG user config: $XDG_CONFIG_HOME/hongdown/config.toml
+            if let Some(layer) = Self::load_user_xdg_config()? {
+                layers.push(layer);
+            }
         }
 
         // 4. Try project config: search upward from start_dir
-        if let Some((path, layer)) = Self::discover_project_config(start_dir)? {
-            project_config_path = Some(path);
-
+        if let Some((path, layer)) = Self::discover_project_config(start_dir, options, &mut warn)? {
             // If no_inherit is true, skip all parent layers
             if layer.no_inherit {
+                trace::debug!(
+                    source = %path.display(),
+                    "no_inherit is set, so other configuration files are ignored"
+                );
                 layers.clear();
             }
 
+            project_config_path = Some(path);
             layers.push(layer);
         }
 
@@ -1044,19 +2698,16 @@ impl Config {
     /// Discover project config by searching upward from start_dir.
     fn discover_project_config(
         start_dir: &Path,
+        options: DiscoveryOptions,
+        warn: &mut impl FnMut(ConfigError),
     ) -> Result<Option<(PathBuf, ConfigLayer)>, ConfigError> {
-        let mut current = start_dir.to_path_buf();
-        loop {
-            let config_path = current.join(CONFIG_FILE_NAME);
-            if config_path.exists() {
+        match find_upward(start_dir, options, warn) {
+            Some(config_path) => {
                 let layer = ConfigLayer::from_file(&config_path)?;
-                return Ok(Some((config_path, layer)));
-            }
-            if !current.pop() {
-                break;
+                Ok(Some((config_path, layer)))
             }
+            None => Ok(None),
         }
-        Ok(None)
     }
 
     /// Try to load a config layer from a path. Returns None if file doesn't exist.
@@ -1074,29 +2725,30 @@ impl Config {
     /// Returns an empty list if no include patterns are configured.
     pub fn collect_files(&self, base_dir: &Path) -> Result<Vec<PathBuf>, ConfigError> {
         use ignore::WalkBuilder;
+        use ignore::gitignore::GitignoreBuilder;
         use ignore::overrides::OverrideBuilder;
 
         if self.include.is_empty() {
             return Ok(Vec::new());
         }
 
-        // Build override patterns: include patterns are whitelist,
-        // exclude patterns are prefixed with ! to negate them
+        // Build override patterns from the include patterns, which are
+        // whitelist
         let mut override_builder = OverrideBuilder::new(base_dir);
-
-        // Add include patterns
         for pattern in &self.include {
             override_builder.add(pattern).map_err(ConfigError::Ignore)?;
         }
+        let overrides = override_builder.build().map_err(ConfigError::Ignore)?;
 
-        // Add exclude patterns with ! prefix (negation)
+        // Exclude patterns are matched against each file rather than given to
+        // the walker, as .hongdownignore files take precedence over them
+        let mut exclude_builder = GitignoreBuilder::new(base_dir);
         for pattern in &self.exclude {
-            override_builder
-                .add(&format!("!{}", pattern))
+            exclude_builder
+                .add_line(None, pattern)
                 .map_err(ConfigError::Ignore)?;
         }
-
-        let overrides = override_builder.build().map_err(ConfigError::Ignore)?;
+        let exclude = exclude_builder.build().map_err(ConfigError::Ignore)?;
 
         // Build walker with overrides
         // This efficiently skips directories that don't match patterns
@@ -1109,11 +2761,18 @@ impl Config {
             .build();
 
         // Collect matching files
+        let mut ignore_files = IgnoreFiles::new();
         let mut files = Vec::new();
         for entry in walker {
             let entry = entry.map_err(ConfigError::Ignore)?;
             let path = entry.path();
-            if path.is_file() {
+            if !path.is_file() {
+                continue;
+            }
+            let ignored = ignore_files
+                .matched(path)
+                .unwrap_or_else(|| exclude.matched_path_or_any_parents(path, false).is_ignore());
+            if !ignored {
                 files.push(path.to_path_buf());
             }
         }
@@ -1125,13 +2784,114 @@ impl Config {
     }
 }
 
+/// The [`IGNORE_FILE_NAME`] files read so far, by the directory they are in.
+#[derive(Debug, Default)]
+pub struct IgnoreFiles {
+    by_dir: HashMap<PathBuf, Option<ignore::gitignore::Gitignore>>,
+}
+
+impl IgnoreFiles {
+    /// Create an empty set of ignore files, which are read as they are needed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the ignore files in the directories above `path` ignore it:
+    /// `Some(true)` if they do, `Some(false)` if a negated pattern brings it
+    /// back, and `None` if none of them mention it.
+    ///
+    /// The ignore file nearest to `path` that mentions it wins.  Unlike in
+    /// Git, a negated pattern brings back a file even if the directory it is
+    /// in is ignored, so that `docs/generated/` can be ignored while
+    /// `!docs/generated/INDEX.md` is not.
+    pub fn matched(&mut self, path: &Path) -> Option<bool> {
+        let path = std::path::absolute(path).ok()?;
+        for dir in path.ancestors().skip(1) {
+            let Some(ignore) = self.load(dir) else {
+                continue;
+            };
+            let matched = ignore.matched(&path, false);
+            if matched.is_whitelist() {
+                return Some(false);
+            }
+            if matched.is_ignore() || ignore.matched_path_or_any_parents(&path, false).is_ignore() {
+                return Some(true);
+            }
+        }
+        None
+    }
+
+    /// The ignore file in `dir`, if there is one.  Lines that aren't valid
+    /// patterns are skipped, as Git does.
+    fn load(&mut self, dir: &Path) -> Option<&ignore::gitignore::Gitignore> {
+        self.by_dir
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let path = dir.join(IGNORE_FILE_NAME);
+                if !path.is_file() {
+                    return None;
+                }
+                let mut builder = ignore::gitignore::GitignoreBuilder::new(dir);
+                builder.add(path);
+                builder.build().ok().filter(|ignore| !ignore.is_empty())
+            })
+            .as_ref()
+    }
+}
+
+/// The nearest `.hongdown.toml` in `start_dir` or its parent directories,
+/// up to the root of the repository if `options` say so.
+///
+/// A directory that can't be searched, like one without permission, is
+/// skipped with an [`ConfigError::Io`] passed to `warn`, instead of ending
+/// the search.
+fn find_upward(
+    start_dir: &Path,
+    options: DiscoveryOptions,
+    warn: &mut impl FnMut(ConfigError),
+) -> Option<PathBuf> {
+    // Without the user configuration, ~/.hongdown.toml isn't a project's
+    let home = (!options.user_config).then(dirs::home_dir).flatten();
+    let mut current = start_dir.to_path_buf();
+    loop {
+        let config_path = current.join(CONFIG_FILE_NAME);
+        match std::fs::metadata(&config_path) {
+            Ok(_) if home.as_ref() != Some(&current) => return Some(config_path),
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory
+                ) => {}
+            Err(e) => warn(ConfigError::Io(current.clone(), e)),
+        }
+        if options.stop_at_repository
+            && REPOSITORY_MARKERS
+                .iter()
+                .any(|marker| current.join(marker).exists())
+        {
+            trace::debug!(
+                root = %current.display(),
+                "no configuration file in the repository"
+            );
+            return None;
+        }
+        if !current.pop() {
+            return None;
+        }
+    }
+}
+
 /// Errors that can occur when loading configuration.
 #[derive(Debug)]
 pub enum ConfigError {
+    /// The configuration file doesn't exist.
+    NotFound(PathBuf),
     /// I/O error reading the configuration file.
     Io(PathBuf, std::io::Error),
-    /// Error parsing the TOML configuration.
-    Parse(PathBuf, toml::de::Error),
+    /// Error parsing the TOML configuration, with the TOML it was parsed
+    /// from, so that the error can be shown in context.
+    Parse(PathBuf, String, Box<toml::de::Error>),
     /// Error parsing a glob pattern.
     Glob(String, glob::PatternError),
     /// I/O error during glob iteration.
@@ -1140,15 +2900,65 @@ pub enum ConfigError {
     Ignore(ignore::Error),
 }
 
+impl ConfigError {
+    /// Read the configuration file at `path`, telling a missing file apart
+    /// from one that can't be read.
+    fn read(path: &Path) -> Result<String, ConfigError> {
+        std::fs::read_to_string(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ConfigError::NotFound(path.to_path_buf()),
+            _ => ConfigError::Io(path.to_path_buf(), e),
+        })
+    }
+}
+
+/// Write the TOML error `err` in `content` of the file at `path`, as
+/// `path:line:column: message` followed by the line, with the span of the
+/// error underlined with carets.
+fn fmt_parse_error(
+    f: &mut std::fmt::Formatter<'_>,
+    path: &Path,
+    content: &str,
+    err: &toml::de::Error,
+) -> std::fmt::Result {
+    let Some(span) = err.span().filter(|span| span.start <= content.len()) else {
+        return write!(f, "failed to parse {}: {}", path.display(), err.message());
+    };
+    let start = content.floor_char_boundary(span.start);
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |i| start + i);
+    let line = content[line_start..line_end].trim_end_matches('\r');
+    let line_number = content[..start].matches('\n').count() + 1;
+    let before = &content[line_start..start];
+    let end = content.floor_char_boundary(span.end.clamp(start, line_start + line.len()));
+    let gutter = " ".repeat(line_number.to_string().len());
+    write!(
+        f,
+        "failed to parse {}:{}:{}: {}\n{} |\n{} | {}\n{} | {}{}",
+        path.display(),
+        line_number,
+        before.chars().count() + 1,
+        err.message().trim_end(),
+        gutter,
+        line_number,
+        line,
+        gutter,
+        " ".repeat(before.width()),
+        "^".repeat(content[start..end].width().max(1)),
+    )
+}
+
 impl std::fmt::Display for ConfigError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ConfigError::NotFound(path) => {
+                write!(f, "configuration file not found: {}", path.display())
+            }
             ConfigError::Io(path, err) => {
                 write!(f, "failed to read {}: {}", path.display(), err)
             }
-            ConfigError::Parse(path, err) => {
-                write!(f, "failed to parse {}: {}", path.display(), err)
-            }
+            ConfigError::Parse(path, content, err) => fmt_parse_error(f, path, content, err),
             ConfigError::Glob(pattern, err) => {
                 write!(f, "invalid glob pattern '{}': {}", pattern, err)
             }
@@ -1165,8 +2975,9 @@ impl std::fmt::Display for ConfigError {
 impl std::error::Error for ConfigError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
+            ConfigError::NotFound(_) => None,
             ConfigError::Io(_, err) => Some(err),
-            ConfigError::Parse(_, err) => Some(err),
+            ConfigError::Parse(_, _, err) => Some(&**err),
             ConfigError::Glob(_, err) => Some(err),
             ConfigError::GlobIo(err) => Some(err),
             ConfigError::Ignore(err) => Some(err),
@@ -1237,23 +3048,36 @@ setext_h2 = false
     }
 
     #[test]
-    fn test_parse_heading_sentence_case() {
+    fn test_parse_heading_allow_multiline() {
+        assert!(Config::default().heading.allow_multiline);
         let config = Config::from_toml(
             r#"
 [heading]
-sentence_case = true
+allow_multiline = false
 "#,
         )
         .unwrap();
-        assert!(config.heading.sentence_case);
+        assert!(!config.heading.allow_multiline);
     }
 
     #[test]
-    fn test_parse_heading_proper_nouns() {
+    fn test_parse_heading_sentence_case() {
         let config = Config::from_toml(
             r#"
 [heading]
-proper_nouns = ["Hongdown", "MyCompany", "MyProduct"]
+sentence_case = true
+"#,
+        )
+        .unwrap();
+        assert!(config.heading.sentence_case);
+    }
+
+    #[test]
+    fn test_parse_heading_proper_nouns() {
+        let config = Config::from_toml(
+            r#"
+[heading]
+proper_nouns = ["Hongdown", "MyCompany", "MyProduct"]
 "#,
         )
         .unwrap();
@@ -1289,6 +3113,56 @@ common_nouns = ["Go", "Swift"]
         assert_eq!(config.heading.common_nouns, vec!["Go", "Swift"]);
     }
 
+    #[test]
+    fn test_parse_heading_strip_trailing_punctuation() {
+        assert!(
+            Config::default()
+                .heading
+                .strip_trailing_punctuation
+                .is_empty()
+        );
+        let config = Config::from_toml(
+            r#"
+[heading]
+strip_trailing_punctuation = [":", "."]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.heading.strip_trailing_punctuation, vec![":", "."]);
+    }
+
+    #[test]
+    fn test_parse_heading_numbering() {
+        let config = Config::default();
+        assert!(!config.heading.numbering);
+        let levels: Vec<u8> = config
+            .heading
+            .numbering_levels
+            .iter()
+            .map(|l| l.get())
+            .collect();
+        assert_eq!(levels, vec![2, 3]);
+
+        let config = Config::from_toml(
+            r#"
+[heading]
+numbering = true
+numbering_levels = [1, 2]
+"#,
+        )
+        .unwrap();
+        assert!(config.heading.numbering);
+        let levels: Vec<u8> = config
+            .heading
+            .numbering_levels
+            .iter()
+            .map(|l| l.get())
+            .collect();
+        assert_eq!(levels, vec![1, 2]);
+
+        assert!(Config::from_toml("[heading]\nnumbering_levels = [7]").is_err());
+    }
+
     #[test]
     fn test_parse_heading_with_proper_and_common_nouns() {
         let config = Config::from_toml(
@@ -1344,6 +3218,62 @@ even_level_marker = "."
         assert_eq!(config.ordered_list.pad, OrderedListPad::Start); // default
     }
 
+    #[test]
+    fn test_parse_link_config() {
+        let config = Config::from_toml("").unwrap();
+        assert!(config.link.remove_unused_definitions);
+
+        let config = Config::from_toml(
+            r#"
+[link]
+remove_unused_definitions = false
+"#,
+        )
+        .unwrap();
+        assert!(!config.link.remove_unused_definitions);
+    }
+
+    #[test]
+    fn test_parse_link_reference_syntax() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.link.reference_syntax, ReferenceSyntax::Shortcut);
+
+        for (value, expected) in [
+            ("shortcut", ReferenceSyntax::Shortcut),
+            ("collapsed", ReferenceSyntax::Collapsed),
+            ("full", ReferenceSyntax::Full),
+            ("preserve", ReferenceSyntax::Preserve),
+        ] {
+            let config =
+                Config::from_toml(&format!("[link]\nreference_syntax = \"{}\"\n", value)).unwrap();
+            assert_eq!(config.link.reference_syntax, expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_link_url_encoding() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.link.url_encoding, UrlEncoding::Preserve);
+
+        for (value, expected) in [
+            ("preserve", UrlEncoding::Preserve),
+            ("encode", UrlEncoding::Encode),
+            ("decode_unreserved", UrlEncoding::DecodeUnreserved),
+        ] {
+            let config =
+                Config::from_toml(&format!("[link]\nurl_encoding = \"{}\"\n", value)).unwrap();
+            assert_eq!(config.link.url_encoding, expected);
+        }
+        assert!(Config::from_toml("[link]\nurl_encoding = \"decode\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_link_external_references() {
+        assert!(Config::default().link.external_references);
+        let config = Config::from_toml("[link]\nexternal_references = false\n").unwrap();
+        assert!(!config.link.external_references);
+    }
+
     #[test]
     fn test_parse_ordered_list_pad_end() {
         let config = Config::from_toml(
@@ -1368,6 +3298,62 @@ pad = "start"
         assert_eq!(config.ordered_list.pad, OrderedListPad::Start);
     }
 
+    #[test]
+    fn test_parse_list_separation() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.list.separation, ListSeparation::Comment);
+        let config = Config::from_toml(
+            r#"
+[list]
+separation = "alternate_marker"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.list.separation, ListSeparation::AlternateMarker);
+        assert!(Config::from_toml("[list]\nseparation = \"blank\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_list_spacing() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.list.spacing, ListSpacing::Preserve);
+        for (value, expected) in [
+            ("preserve", ListSpacing::Preserve),
+            ("tight", ListSpacing::Tight),
+            ("loose", ListSpacing::Loose),
+        ] {
+            let config = Config::from_toml(&format!("[list]\nspacing = \"{}\"\n", value)).unwrap();
+            assert_eq!(config.list.spacing, expected);
+        }
+        assert!(Config::from_toml("[list]\nspacing = \"compact\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_list_continuation() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.list.continuation, ListContinuation::AlignText);
+        assert_eq!(config.list.fixed_width, 2);
+        let config = Config::from_toml(
+            r#"
+[list]
+continuation = "fixed"
+fixed_width = 3
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.list.continuation, ListContinuation::Fixed);
+        assert_eq!(config.list.fixed_width, 3);
+        assert_eq!(config.list.separation, ListSeparation::Comment);
+    }
+
+    #[test]
+    fn test_parse_list_preserve_pseudo_lists() {
+        let config = Config::from_toml("").unwrap();
+        assert!(!config.list.preserve_pseudo_lists);
+        let config = Config::from_toml("[list]\npreserve_pseudo_lists = true\n").unwrap();
+        assert!(config.list.preserve_pseudo_lists);
+    }
+
     #[test]
     fn test_parse_code_block_config() {
         let config = Config::from_toml(
@@ -1383,6 +3369,13 @@ space_after_fence = false
         assert_eq!(config.code_block.min_fence_length.get(), 3);
         assert!(!config.code_block.space_after_fence);
         assert_eq!(config.code_block.default_language, ""); // Default is empty
+        assert!(!config.code_block.trim_trailing_whitespace);
+    }
+
+    #[test]
+    fn test_parse_code_block_trim_trailing_whitespace() {
+        let config = Config::from_toml("[code_block]\ntrim_trailing_whitespace = true").unwrap();
+        assert!(config.code_block.trim_trailing_whitespace);
     }
 
     #[test]
@@ -1431,6 +3424,145 @@ leading_spaces = 3
         assert_eq!(config, Config::default());
     }
 
+    #[test]
+    fn test_parse_block_quote_config() {
+        let config = Config::from_toml("").unwrap();
+        assert!(!config.block_quote.preserve_marker_spacing);
+        let config = Config::from_toml("[block_quote]\npreserve_marker_spacing = true\n").unwrap();
+        assert!(config.block_quote.preserve_marker_spacing);
+        assert_eq!(config.block_quote.separation, BlockQuoteSeparation::Blank);
+        let config = Config::from_toml("[block_quote]\nseparation = \"comment\"\n").unwrap();
+        assert_eq!(config.block_quote.separation, BlockQuoteSeparation::Comment);
+    }
+
+    #[test]
+    fn test_parse_table_config() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.table.max_column_width, None);
+        assert_eq!(config.table.wrap_cells, CellWrap::None);
+        let config = Config::from_toml(
+            r#"
+[table]
+max_column_width = 40
+wrap_cells = "br"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.table.max_column_width, Some(40));
+        assert_eq!(config.table.wrap_cells, CellWrap::Br);
+    }
+
+    #[test]
+    fn test_parse_table_padding() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.table.padding, TablePadding::Aligned);
+        let config = Config::from_toml("[table]\npadding = \"compact\"\n").unwrap();
+        assert_eq!(config.table.padding, TablePadding::Compact);
+        assert!(Config::from_toml("[table]\npadding = \"none\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_table_separator() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.table.separator, TableSeparator::Full);
+        let config = Config::from_toml("[table]\nseparator = \"minimal\"\n").unwrap();
+        assert_eq!(config.table.separator, TableSeparator::Minimal);
+        assert!(Config::from_toml("[table]\nseparator = \"short\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_table_width_growth() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.table.width_growth, TableWidthGrowth::Exact);
+        assert_eq!(config.table.width_step.get(), 4);
+        let config =
+            Config::from_toml("[table]\nwidth_growth = \"sticky\"\nwidth_step = 8\n").unwrap();
+        assert_eq!(config.table.width_growth, TableWidthGrowth::Sticky);
+        assert_eq!(config.table.width_step.get(), 8);
+        assert!(Config::from_toml("[table]\nwidth_growth = \"loose\"\n").is_err());
+        assert!(Config::from_toml("[table]\nwidth_step = 0\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_table_format() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.table.format, TableFormat::Align);
+        let config = Config::from_toml("[table]\nformat = \"preserve\"\n").unwrap();
+        assert_eq!(config.table.format, TableFormat::Preserve);
+        assert!(Config::from_toml("[table]\nformat = \"none\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_ordered_list_numbering() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.ordered_list.numbering, OrderedNumbering::Sequential);
+        let config = Config::from_toml("[ordered_list]\nnumbering = \"ones\"\n").unwrap();
+        assert_eq!(config.ordered_list.numbering, OrderedNumbering::Ones);
+    }
+
+    #[test]
+    fn test_parse_max_consecutive_blank_lines() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.max_consecutive_blank_lines.get(), 2);
+        let config = Config::from_toml("max_consecutive_blank_lines = 1\n").unwrap();
+        assert_eq!(config.max_consecutive_blank_lines.get(), 1);
+        let error = Config::from_toml("max_consecutive_blank_lines = 0\n").unwrap_err();
+        assert!(
+            error.to_string().contains("must be at least 1"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_parse_format_embedded() {
+        let config = Config::from_toml("").unwrap();
+        assert!(config.code_block.format_embedded.is_empty());
+        assert_eq!(config.code_block.json_key_order, JsonKeyOrder::Preserve);
+        let config = Config::from_toml(
+            r#"
+[code_block]
+format_embedded = ["json", "toml"]
+json_key_order = "sort"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.code_block.format_embedded,
+            vec![EmbeddedLanguage::Json, EmbeddedLanguage::Toml]
+        );
+        assert_eq!(config.code_block.json_key_order, JsonKeyOrder::Sort);
+        assert!(Config::from_toml("[code_block]\nformat_embedded = [\"yaml\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_autolink_config() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.autolink.github, GitHubAutolinkConfig::default());
+        let config = Config::from_toml(
+            "[autolink]\ngithub = { repo = \"dahlia/hongdown\", strip = true }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.autolink.github.repo.as_ref().map(GitHubRepo::as_str),
+            Some("dahlia/hongdown")
+        );
+        assert!(config.autolink.github.strip);
+        for repo in ["hongdown", "/hongdown", "dahlia/", "a/b/c", "dah lia/x"] {
+            let toml = format!("[autolink.github]\nrepo = {:?}\n", repo);
+            assert!(Config::from_toml(&toml).is_err(), "{}", repo);
+        }
+    }
+
+    #[test]
+    fn test_parse_escape_config() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.escape.backslash, BackslashEscape::Always);
+        let config = Config::from_toml("[escape]\nbackslash = \"lazy\"\n").unwrap();
+        assert_eq!(config.escape.backslash, BackslashEscape::Lazy);
+        assert!(Config::from_toml("[escape]\nbackslash = \"never\"\n").is_err());
+    }
+
     #[test]
     fn test_parse_thematic_break_config() {
         let config = Config::from_toml(
@@ -1443,6 +3575,34 @@ style = "---"
         assert_eq!(config.thematic_break.style.as_str(), "---");
     }
 
+    #[test]
+    fn test_parse_html_config() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.html, HtmlConfig::default());
+        assert!(!config.html.normalize_void_tags);
+        assert_eq!(config.html.void_tag_style, VoidTagStyle::Html);
+
+        let config = Config::from_toml(
+            r#"
+[html]
+normalize_void_tags = true
+void_tag_style = "xhtml"
+br_to_hard_break = true
+hr_to_thematic_break = true
+details = "preserve"
+"#,
+        )
+        .unwrap();
+        assert!(config.html.normalize_void_tags);
+        assert_eq!(config.html.void_tag_style, VoidTagStyle::Xhtml);
+        assert!(config.html.br_to_hard_break);
+        assert!(config.html.hr_to_thematic_break);
+        assert_eq!(config.html.details, DetailsHandling::Preserve);
+
+        assert!(Config::from_toml("[html]\nvoid_tag_style = \"xml\"").is_err());
+        assert!(Config::from_toml("[html]\ndetails = \"strip\"").is_err());
+    }
+
     #[test]
     fn test_parse_invalid_toml() {
         let result = Config::from_toml("line_width = \"not a number\"");
@@ -1458,7 +3618,8 @@ style = "---"
         let config_path = temp_dir.join(CONFIG_FILE_NAME);
         std::fs::write(&config_path, "line_width = 90").unwrap();
 
-        let result = Config::discover(&sub_dir).unwrap();
+        let result =
+            Config::discover(&sub_dir, DiscoveryOptions::default(), |e| panic!("{}", e)).unwrap();
         assert!(result.is_some());
         let (path, config) = result.unwrap();
         assert_eq!(path, config_path);
@@ -1567,6 +3728,73 @@ exclude = ["vendor/**"]
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
+    #[test]
+    fn test_collect_files_with_ignore_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let base = temp_dir.path();
+        for file in [
+            "README.md",
+            "CHANGES.md",
+            "docs/guide.md",
+            "docs/draft.md",
+            "docs/generated/INDEX.md",
+            "docs/generated/api.md",
+            "vendor/lib/README.md",
+            "vendor/lib/NOTES.md",
+        ] {
+            let path = base.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "# Test").unwrap();
+        }
+        std::fs::write(
+            base.join(IGNORE_FILE_NAME),
+            "CHANGES.md\ndocs/generated/\n!docs/generated/INDEX.md\n",
+        )
+        .unwrap();
+        std::fs::write(base.join("docs").join(IGNORE_FILE_NAME), "draft.md\n").unwrap();
+        std::fs::write(
+            base.join("vendor/lib").join(IGNORE_FILE_NAME),
+            "!README.md\n",
+        )
+        .unwrap();
+
+        let config = Config {
+            include: vec!["**/*.md".to_string()],
+            exclude: vec!["CHANGES.md".to_string(), "vendor/".to_string()],
+            ..Config::default()
+        };
+        let files = config.collect_files(base).unwrap();
+        let files: Vec<_> = files
+            .iter()
+            .map(|f| f.strip_prefix(base).unwrap().to_str().unwrap())
+            .collect();
+
+        assert_eq!(
+            files,
+            [
+                "README.md",
+                "docs/generated/INDEX.md",
+                "docs/guide.md",
+                "vendor/lib/README.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ignore_files_matched() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let base = temp_dir.path();
+        std::fs::create_dir_all(base.join("a/b")).unwrap();
+        std::fs::write(base.join(IGNORE_FILE_NAME), "*.md\n!keep.md\n").unwrap();
+        std::fs::write(base.join("a/b").join(IGNORE_FILE_NAME), "keep.md\n").unwrap();
+
+        let mut ignore_files = IgnoreFiles::new();
+        assert_eq!(ignore_files.matched(&base.join("a/x.md")), Some(true));
+        assert_eq!(ignore_files.matched(&base.join("a/keep.md")), Some(false));
+        assert_eq!(ignore_files.matched(&base.join("a/b/keep.md")), Some(true));
+        assert_eq!(ignore_files.matched(&base.join("a/x.txt")), None);
+    }
+
     #[test]
     fn test_default_punctuation_config() {
         let config = PunctuationConfig::default();
@@ -1673,7 +3901,7 @@ javascript = ["deno", "fmt", "-"]
         .unwrap();
         let formatter = config.code_block.formatters.get("javascript").unwrap();
         assert_eq!(formatter.command(), &["deno", "fmt", "-"]);
-        assert_eq!(formatter.timeout(), 5);
+        assert_eq!(formatter.timeout(), 10);
     }
 
     #[test]
@@ -1702,7 +3930,7 @@ command = ["rustfmt"]
         .unwrap();
         let formatter = config.code_block.formatters.get("rust").unwrap();
         assert_eq!(formatter.command(), &["rustfmt"]);
-        assert_eq!(formatter.timeout(), 5);
+        assert_eq!(formatter.timeout(), 10);
     }
 
     #[test]
@@ -2448,6 +4676,118 @@ indent_width = 0
     }
 }
 
+#[cfg(test)]
+mod heading_level_tests {
+    use super::*;
+
+    #[test]
+    fn test_heading_level_valid() {
+        assert_eq!(HeadingLevel::new(1).unwrap().get(), 1);
+        assert_eq!(HeadingLevel::new(6).unwrap().get(), 6);
+    }
+
+    #[test]
+    fn test_heading_level_invalid() {
+        assert_eq!(
+            HeadingLevel::new(0).unwrap_err(),
+            "heading level must be between 1 and 6, got 0."
+        );
+        assert!(HeadingLevel::new(7).is_err());
+    }
+
+    #[test]
+    fn test_toc_config_parse() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.toc.min_level.get(), 2);
+        assert_eq!(config.toc.max_level.get(), 3);
+
+        let config = Config::from_toml(
+            r#"
+[toc]
+min_level = 1
+max_level = 4
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.toc.min_level.get(), 1);
+        assert_eq!(config.toc.max_level.get(), 4);
+    }
+
+    #[test]
+    fn test_parse_link_empty_text() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.link.empty_text, EmptyLinkText::Autolink);
+        let config = Config::from_toml("[link]\nempty_text = \"preserve\"\n").unwrap();
+        assert_eq!(config.link.empty_text, EmptyLinkText::Preserve);
+        assert!(Config::from_toml("[link]\nempty_text = \"drop\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_sort_definitions() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.link.sort_definitions, DefinitionOrder::None);
+        assert_eq!(config.footnote.sort_definitions, DefinitionOrder::None);
+
+        let config = Config::from_toml(
+            r#"
+[link]
+sort_definitions = "alpha"
+
+[footnote]
+sort_definitions = "usage"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.link.sort_definitions, DefinitionOrder::Alpha);
+        assert_eq!(config.footnote.sort_definitions, DefinitionOrder::Usage);
+        assert!(Config::from_toml("[link]\nsort_definitions = \"random\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_footnote_inline() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.footnote.inline, InlineFootnotes::Preserve);
+
+        let config = Config::from_toml("[footnote]\ninline = \"expand\"").unwrap();
+        assert_eq!(config.footnote.inline, InlineFootnotes::Expand);
+        assert!(Config::from_toml("[footnote]\ninline = \"drop\"").is_err());
+    }
+
+    #[test]
+    fn test_lint_config_parse() {
+        let config = Config::from_toml("").unwrap();
+        assert!(!config.lint.links);
+
+        let config = Config::from_toml("[lint]\nlinks = true\n").unwrap();
+        assert!(config.lint.links);
+        assert!(!config.lint.invisible_characters);
+        let config = Config::from_toml("[lint]\ninvisible_characters = true\n").unwrap();
+        assert!(config.lint.invisible_characters);
+        assert!(!config.lint.headings);
+        let config = Config::from_toml("[lint]\nheadings = true\n").unwrap();
+        assert!(config.lint.headings);
+        assert!(!config.lint.code_language);
+        let config = Config::from_toml(
+            "[lint]\ncode_language = true\ncode_language_exceptions = [\"text\", \"\"]\n",
+        )
+        .unwrap();
+        assert!(config.lint.code_language);
+        assert_eq!(config.lint.code_language_exceptions, ["text", ""]);
+    }
+
+    #[test]
+    fn test_toc_config_parse_invalid() {
+        let result = Config::from_toml(
+            r#"
+[toc]
+max_level = 7
+"#,
+        );
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("heading level must be between 1 and 6"));
+    }
+}
+
 #[cfg(test)]
 mod config_layer_tests {
     use super::*;
@@ -2497,10 +4837,53 @@ line_width = 100
         .unwrap();
 
         let layer = ConfigLayer::from_file(&config_path).unwrap();
-        assert_eq!(layer.no_inherit, true);
+        assert!(layer.no_inherit);
         assert_eq!(layer.line_width, Some(LineWidth::new(100).unwrap()));
     }
 
+    #[test]
+    fn test_root_is_no_inherit() {
+        assert!(ConfigLayer::from_toml("root = true").unwrap().no_inherit);
+        assert!(Config::from_toml("root = true").unwrap().no_inherit);
+        assert!(!Config::from_toml("root = false").unwrap().no_inherit);
+    }
+
+    #[test]
+    fn test_discover_stops_at_repository() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        let start = repo.join("docs");
+        std::fs::create_dir_all(&start).unwrap();
+        let outer_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&outer_path, "line_width = 100").unwrap();
+        let discover = |options| {
+            Config::discover(&start, options, |e| panic!("{}", e))
+                .unwrap()
+                .map(|(path, _)| path)
+        };
+        let unbounded = DiscoveryOptions {
+            stop_at_repository: false,
+            ..DiscoveryOptions::default()
+        };
+
+        assert_eq!(
+            discover(DiscoveryOptions::default()),
+            Some(outer_path.clone())
+        );
+        for marker in [".git", ".hg"] {
+            std::fs::create_dir(repo.join(marker)).unwrap();
+            assert_eq!(discover(DiscoveryOptions::default()), None, "{}", marker);
+            assert_eq!(discover(unbounded), Some(outer_path.clone()), "{}", marker);
+            std::fs::remove_dir(repo.join(marker)).unwrap();
+        }
+
+        // A worktree's .git is a file, and the repository's own config counts
+        std::fs::write(repo.join(".git"), "gitdir: elsewhere\n").unwrap();
+        let repo_path = repo.join(CONFIG_FILE_NAME);
+        std::fs::write(&repo_path, "line_width = 60").unwrap();
+        assert_eq!(discover(DiscoveryOptions::default()), Some(repo_path));
+    }
+
     #[test]
     fn test_config_layer_from_file_not_found() {
         let result = ConfigLayer::from_file(Path::new("/nonexistent/.hongdown.toml"));
@@ -2523,7 +4906,7 @@ line_width = 100
 
         let merged = layer.merge_over(base);
         assert_eq!(merged.line_width.get(), 100);
-        assert_eq!(merged.git_aware, false);
+        assert!(!merged.git_aware);
     }
 
     #[test]
@@ -2543,7 +4926,7 @@ line_width = 100
 
         let merged = layer.merge_over(base);
         assert_eq!(merged.line_width.get(), 100);
-        assert_eq!(merged.git_aware, false); // Preserved
+        assert!(!merged.git_aware); // Preserved
         assert_eq!(merged.include, vec!["*.md".to_string()]); // Preserved
     }
 
@@ -2553,9 +4936,12 @@ line_width = 100
             heading: HeadingConfig {
                 setext_h1: true,
                 setext_h2: true,
+                allow_multiline: true,
                 sentence_case: false,
                 proper_nouns: vec!["Rust".to_string()],
                 common_nouns: Vec::new(),
+                strip_trailing_punctuation: Vec::new(),
+                ..HeadingConfig::default()
             },
             ..Config::default()
         };
@@ -2564,17 +4950,20 @@ line_width = 100
             heading: Some(HeadingConfig {
                 setext_h1: false,
                 setext_h2: false,
+                allow_multiline: true,
                 sentence_case: true,
                 proper_nouns: vec!["Python".to_string()],
                 common_nouns: Vec::new(),
+                strip_trailing_punctuation: Vec::new(),
+                ..HeadingConfig::default()
             }),
             ..ConfigLayer::default()
         };
 
         let merged = layer.merge_over(base);
-        assert_eq!(merged.heading.setext_h1, false);
-        assert_eq!(merged.heading.setext_h2, false);
-        assert_eq!(merged.heading.sentence_case, true);
+        assert!(!merged.heading.setext_h1);
+        assert!(!merged.heading.setext_h2);
+        assert!(merged.heading.sentence_case);
         assert_eq!(merged.heading.proper_nouns, vec!["Python".to_string()]);
     }
 
@@ -2639,7 +5028,11 @@ line_width = 100
         let config_path = parent.join(".hongdown.toml");
         std::fs::write(&config_path, "line_width = 100").unwrap();
 
-        let result = Config::discover_project_config(&child).unwrap();
+        let result =
+            Config::discover_project_config(&child, DiscoveryOptions::default(), &mut |e| {
+                panic!("{}", e)
+            })
+            .unwrap();
         assert!(result.is_some());
         let (path, layer) = result.unwrap();
         assert_eq!(path, config_path);
@@ -2649,17 +5042,100 @@ line_width = 100
     #[test]
     fn test_discover_project_config_not_found() {
         let temp_dir = TempDir::new().unwrap();
-        let result = Config::discover_project_config(temp_dir.path()).unwrap();
+        let result = Config::discover_project_config(
+            temp_dir.path(),
+            DiscoveryOptions::default(),
+            &mut |e| panic!("{}", e),
+        )
+        .unwrap();
         assert!(result.is_none());
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_discover_skips_unreadable_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let locked = temp_dir.path().join("locked");
+        let start = locked.join("project");
+        std::fs::create_dir_all(&start).unwrap();
+        std::fs::write(start.join(CONFIG_FILE_NAME), "line_width = 60").unwrap();
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&config_path, "line_width = 100").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions don't stop the superuser
+        let unreadable = std::fs::metadata(start.join(CONFIG_FILE_NAME)).is_err();
+
+        let mut warnings = Vec::new();
+        let result = Config::discover(&start, DiscoveryOptions::default(), |e| {
+            warnings.push(e.to_string())
+        });
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        if !unreadable {
+            return;
+        }
+        let (path, config) = result.unwrap().unwrap();
+        assert_eq!(path, config_path);
+        assert_eq!(config.line_width.get(), 100);
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(
+            warnings[0].starts_with(&format!("failed to read {}: ", start.display())),
+            "{:?}",
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_from_file_not_found() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(CONFIG_FILE_NAME);
+        let error = Config::from_file(&path).unwrap_err();
+        assert!(matches!(&error, ConfigError::NotFound(p) if *p == path));
+        assert_eq!(
+            error.to_string(),
+            format!("configuration file not found: {}", path.display())
+        );
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(
+            &path,
+            "# Project style\n\
+             \n\
+             [heading]\n\
+             setext_h1 = true\n\
+             \n\
+             [unordered_list]\n\
+             leading_spaces = \"one\"\n",
+        )
+        .unwrap();
+        let error = Config::from_file(&path).unwrap_err();
+        assert!(matches!(error, ConfigError::Parse(..)));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "failed to parse {}:7:18: invalid type: string \"one\", expected usize\n  |\n\
+                 7 | leading_spaces = \"one\"\n  |                  ^^^^^",
+                path.display()
+            )
+        );
+    }
+
     #[test]
     fn test_load_cascading_project_only() {
         let temp_dir = TempDir::new().unwrap();
         let config_path = temp_dir.path().join(".hongdown.toml");
         std::fs::write(&config_path, "line_width = 100").unwrap();
 
-        let (config, path) = Config::load_cascading(temp_dir.path()).unwrap();
+        let (config, path) =
+            Config::load_cascading(temp_dir.path(), DiscoveryOptions::default(), |e| {
+                panic!("{}", e)
+            })
+            .unwrap();
         assert_eq!(path, Some(config_path));
         assert_eq!(config.line_width.get(), 100);
     }
@@ -2667,7 +5143,11 @@ line_width = 100
     #[test]
     fn test_load_cascading_no_config() {
         let temp_dir = TempDir::new().unwrap();
-        let (config, path) = Config::load_cascading(temp_dir.path()).unwrap();
+        let (config, path) =
+            Config::load_cascading(temp_dir.path(), DiscoveryOptions::default(), |e| {
+                panic!("{}", e)
+            })
+            .unwrap();
         assert_eq!(path, None);
         assert_eq!(config, Config::default());
     }
@@ -2687,11 +5167,15 @@ line_width = 100
         )
         .unwrap();
 
-        let (config, _) = Config::load_cascading(temp_dir.path()).unwrap();
+        let (config, _) =
+            Config::load_cascading(temp_dir.path(), DiscoveryOptions::default(), |e| {
+                panic!("{}", e)
+            })
+            .unwrap();
 
         // Should use config's values, ignoring any system/user configs
         assert_eq!(config.line_width.get(), 100);
-        assert_eq!(config.no_inherit, true);
+        assert!(config.no_inherit);
     }
 
     #[test]
@@ -2707,7 +5191,9 @@ line_width = 100
         // Child config: line_width = 100
         std::fs::write(child.join(".hongdown.toml"), "line_width = 100").unwrap();
 
-        let (config, path) = Config::load_cascading(&child).unwrap();
+        let (config, path) =
+            Config::load_cascading(&child, DiscoveryOptions::default(), |e| panic!("{}", e))
+                .unwrap();
 
         // Should use nearest (child) config, parent config is ignored
         assert_eq!(config.line_width.get(), 100);
@@ -2731,11 +5217,442 @@ git_aware = false
         )
         .unwrap();
 
-        let (config, path) = Config::load_cascading(&child).unwrap();
+        let (config, path) =
+            Config::load_cascading(&child, DiscoveryOptions::default(), |e| panic!("{}", e))
+                .unwrap();
 
         // Should find parent's config when searching from child
         assert_eq!(config.line_width.get(), 120);
-        assert_eq!(config.git_aware, false);
+        assert!(!config.git_aware);
         assert_eq!(path, Some(parent.join(".hongdown.toml")));
     }
+
+    #[test]
+    fn test_parse_wrap_and_line_ending() {
+        let config = Config::from_toml(
+            r#"
+wrap = "preserve"
+line_ending = "crlf"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.wrap, WrapMode::Preserve);
+        assert_eq!(config.line_ending, LineEnding::Crlf);
+
+        let config = Config::from_toml(r#"wrap = "sentence""#).unwrap();
+        assert_eq!(config.wrap, WrapMode::Sentence);
+
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.wrap, WrapMode::Width);
+        assert_eq!(config.line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn test_parse_bom() {
+        let config = Config::from_toml(r#"bom = "remove""#).unwrap();
+        assert_eq!(config.bom, ByteOrderMark::Remove);
+
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.bom, ByteOrderMark::Preserve);
+
+        assert!(Config::from_toml(r#"bom = "keep""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_hard_break() {
+        let config = Config::from_toml(r#"hard_break = "backslash""#).unwrap();
+        assert_eq!(config.hard_break, HardBreak::Backslash);
+
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.hard_break, HardBreak::Spaces);
+
+        assert!(Config::from_toml(r#"hard_break = "html""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_entities() {
+        let config = Config::from_toml(r#"entities = "encode_invisible""#).unwrap();
+        assert_eq!(config.entities, EntityMode::EncodeInvisible);
+
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.entities, EntityMode::Preserve);
+
+        assert!(Config::from_toml(r#"entities = "encode""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_containers() {
+        let config = Config::from_toml("containers = true").unwrap();
+        assert!(config.containers);
+
+        let config = Config::from_toml("").unwrap();
+        assert!(!config.containers);
+    }
+
+    #[test]
+    fn test_parse_templates() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.templates, TemplateSyntax::None);
+
+        for (value, syntax) in [
+            ("none", TemplateSyntax::None),
+            ("liquid", TemplateSyntax::Liquid),
+            ("hugo", TemplateSyntax::Hugo),
+            ("jinja", TemplateSyntax::Jinja),
+        ] {
+            let config = Config::from_toml(&format!("templates = \"{value}\"")).unwrap();
+            assert_eq!(config.templates, syntax);
+        }
+        assert!(Config::from_toml("templates = \"erb\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_fix() {
+        let config = Config::from_toml("").unwrap();
+        assert!(config.fix.is_empty());
+
+        let config = Config::from_toml(
+            "fix = [\"heading-increment\", \"no-trailing-punctuation\", \"no-empty-links\", \"code-language\"]",
+        )
+        .unwrap();
+        assert_eq!(
+            config.fix,
+            [
+                FixRule::HeadingIncrement,
+                FixRule::NoTrailingPunctuation,
+                FixRule::NoEmptyLinks,
+                FixRule::CodeLanguage
+            ]
+        );
+        for rule in &config.fix {
+            let config = Config::from_toml(&format!("fix = [\"{}\"]", rule.name())).unwrap();
+            assert_eq!(config.fix, [*rule]);
+        }
+        assert!(Config::from_toml("fix = [\"single-h1\"]").is_err());
+    }
+
+    #[test]
+    fn test_parse_math() {
+        let config = Config::from_toml("math = true").unwrap();
+        assert!(config.math);
+
+        let config = Config::from_toml("").unwrap();
+        assert!(!config.math);
+    }
+
+    #[test]
+    fn test_parse_locale() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.locale, Locale::None);
+        let config = Config::from_toml("locale = \"ko\"").unwrap();
+        assert_eq!(config.locale, Locale::Ko);
+        let config = Config::from_toml("locale = \"auto\"").unwrap();
+        assert_eq!(config.locale, Locale::Auto);
+        assert!(Config::from_toml("locale = \"ko-KR\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_dialect() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.dialect, Dialect::Gfm);
+        assert_eq!(config.commonmark.tables, TableFallback::Html);
+
+        let config = Config::from_toml(
+            r#"
+dialect = "commonmark"
+
+[commonmark]
+tables = "text"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.dialect, Dialect::CommonMark);
+        assert_eq!(config.commonmark.tables, TableFallback::Text);
+        assert!(Config::from_toml("dialect = \"markdown\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_mdx() {
+        let config = Config::from_toml("mdx = false").unwrap();
+        assert_eq!(config.mdx, Some(false));
+
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.mdx, None);
+    }
+
+    #[test]
+    fn test_parse_invalid_wrap() {
+        let result = Config::from_toml(r#"wrap = "soft""#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_line_ending_resolve() {
+        assert_eq!(LineEnding::Lf.resolve("a\r\nb"), "\n");
+        assert_eq!(LineEnding::Crlf.resolve("a\nb"), "\r\n");
+        assert_eq!(LineEnding::Auto.resolve("a\r\nb\n"), "\r\n");
+        assert_eq!(LineEnding::Auto.resolve("a\nb\r\n"), "\n");
+        assert_eq!(LineEnding::Auto.resolve("no newline"), "\n");
+    }
+
+    #[test]
+    fn test_partial_config_parse_value() {
+        let width: LineWidth = PartialConfig::parse_value("100").unwrap();
+        assert_eq!(width.get(), 100);
+        let marker: UnorderedMarker = PartialConfig::parse_value("*").unwrap();
+        assert_eq!(marker, UnorderedMarker::Asterisk);
+        let setext: bool = PartialConfig::parse_value("false").unwrap();
+        assert!(!setext);
+
+        // Same validation as configuration files
+        let err = PartialConfig::parse_value::<LineWidth>("5").unwrap_err();
+        assert_eq!(err, "line_width must be at least 8, got 5.");
+        assert!(PartialConfig::parse_value::<FenceChar>("x").is_err());
+    }
+
+    #[test]
+    fn test_partial_config_from_vars() {
+        let vars = [
+            ("HONGDOWN_LINE_WIDTH", "60"),
+            ("HONGDOWN_SETEXT", "false"),
+            ("HONGDOWN_LINE_ENDING", "auto"),
+            ("PATH", "/usr/bin"),
+        ];
+        let partial =
+            PartialConfig::from_vars(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+                .unwrap();
+        assert_eq!(partial.line_width.map(|w| w.get()), Some(60));
+        assert_eq!(partial.setext, Some(false));
+        assert_eq!(partial.line_ending, Some(LineEnding::Auto));
+        assert_eq!(partial.wrap, None);
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_trace_config_resolution() {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        /// A writer for the subscriber that keeps what it is given.
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&path, "line_width = 60\n\n[heading]\nsetext_h1 = false\n").unwrap();
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            ConfigLayer::from_file(&path).unwrap();
+            let vars = [("HONGDOWN_WRAP".to_string(), "sentence".to_string())];
+            PartialConfig::from_vars(vars).unwrap();
+        });
+
+        let log = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let source = format!("source={}", path.display());
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains(&source) && line.contains("key=line_width value=60")),
+            "{}",
+            log
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains(&source)
+                    && line.contains("key=heading.setext_h1 value=false")),
+            "{}",
+            log
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("source=\"environment\"")
+                    && line.contains("key=HONGDOWN_WRAP value=sentence")),
+            "{}",
+            log
+        );
+    }
+
+    #[test]
+    fn test_partial_config_from_vars_invalid() {
+        let vars = [("HONGDOWN_WRAP".to_string(), "soft".to_string())];
+        let err = PartialConfig::from_vars(vars).unwrap_err();
+        assert!(err.starts_with("invalid value for HONGDOWN_WRAP: "));
+    }
+
+    #[test]
+    fn test_partial_config_merge_over_keeps_other_fields() {
+        let base = Config::from_toml(
+            r#"
+line_width = 100
+
+[heading]
+setext_h1 = true
+sentence_case = true
+
+[code_block]
+fence_char = "~"
+min_fence_length = 5
+"#,
+        )
+        .unwrap();
+        let partial = PartialConfig {
+            setext: Some(false),
+            fence_char: Some(FenceChar::Backtick),
+            ..PartialConfig::default()
+        };
+        let config = partial.merge_over(base);
+        assert_eq!(config.line_width.get(), 100);
+        assert!(!config.heading.setext_h1);
+        assert!(!config.heading.setext_h2);
+        assert!(config.heading.sentence_case);
+        assert_eq!(config.code_block.fence_char, FenceChar::Backtick);
+        assert_eq!(config.code_block.min_fence_length.get(), 5);
+    }
+
+    #[test]
+    fn test_partial_config_or_precedence() {
+        let cli = PartialConfig {
+            line_width: Some(LineWidth::new(100).unwrap()),
+            ..PartialConfig::default()
+        };
+        let env = PartialConfig {
+            line_width: Some(LineWidth::new(60).unwrap()),
+            wrap: Some(WrapMode::None),
+            ..PartialConfig::default()
+        };
+        let merged = cli.or(env);
+        assert_eq!(merged.line_width.map(|w| w.get()), Some(100));
+        assert_eq!(merged.wrap, Some(WrapMode::None));
+    }
+
+    #[test]
+    fn test_preset_changelog() {
+        let config = Config::from_toml("preset = \"changelog\"\n").unwrap();
+        assert_eq!(config.preset, Some(Preset::Changelog));
+        assert_eq!(config.wrap, WrapMode::None);
+        assert_eq!(config.list.spacing, ListSpacing::Tight);
+        assert!(!config.heading.setext_h1);
+        assert!(!config.heading.setext_h2);
+        assert!(!config.link.external_references);
+        // Settings the preset doesn't touch keep their defaults
+        assert_eq!(config.line_width.get(), 80);
+        assert_eq!(config.code_block.fence_char, FenceChar::Tilde);
+    }
+
+    #[test]
+    fn test_preset_diff_friendly() {
+        let config = Config::from_toml("preset = \"diff-friendly\"\n").unwrap();
+        assert_eq!(config.preset, Some(Preset::DiffFriendly));
+        assert_eq!(config.wrap, WrapMode::Sentence);
+        assert_eq!(config.ordered_list.numbering, OrderedNumbering::Ones);
+        assert!(config.link.external_references);
+        assert_eq!(config.link.sort_definitions, DefinitionOrder::Usage);
+        assert_eq!(config.table.padding, TablePadding::Compact);
+        assert_eq!(config.max_consecutive_blank_lines.get(), 1);
+    }
+
+    #[test]
+    fn test_preset_readme() {
+        let config = Config::from_toml("preset = \"readme\"\n").unwrap();
+        assert_eq!(config.wrap, WrapMode::Width);
+        assert_eq!(config.code_block.fence_char, FenceChar::Backtick);
+        assert!(!config.heading.setext_h1);
+        assert!(!config.link.external_references);
+    }
+
+    #[test]
+    fn test_preset_explicit_keys_override() {
+        let config = Config::from_toml(
+            r#"
+preset = "changelog"
+wrap = "preserve"
+
+[heading]
+setext_h1 = true
+
+[list]
+separation = "alternate_marker"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.wrap, WrapMode::Preserve);
+        assert!(config.heading.setext_h1);
+        // Keys of the same sections that aren't set come from the preset
+        assert!(!config.heading.setext_h2);
+        assert_eq!(config.list.separation, ListSeparation::AlternateMarker);
+        assert_eq!(config.list.spacing, ListSpacing::Tight);
+        assert!(!config.link.external_references);
+    }
+
+    #[test]
+    fn test_preset_unknown() {
+        let err = Config::from_toml("preset = \"blog\"\n").unwrap_err();
+        assert!(err.message().contains("unknown variant `blog`"), "{}", err);
+    }
+
+    #[test]
+    fn test_preset_in_config_layer() {
+        let base = Config::from_toml("line_width = 100\n\n[list]\nfixed_width = 3\n").unwrap();
+        let layer =
+            ConfigLayer::from_toml("preset = \"changelog\"\nline_ending = \"crlf\"\n").unwrap();
+        let config = layer.merge_over(base);
+        assert_eq!(config.preset, Some(Preset::Changelog));
+        assert_eq!(config.line_width.get(), 100);
+        assert_eq!(config.line_ending, LineEnding::Crlf);
+        assert_eq!(config.wrap, WrapMode::None);
+        assert_eq!(config.list.spacing, ListSpacing::Tight);
+        // The preset sets the list section, which replaces the one below
+        assert_eq!(config.list.fixed_width, 2);
+    }
+
+    #[test]
+    fn test_partial_config_under_env_overrides() {
+        let config = Config::from_toml("preset = \"changelog\"\n").unwrap();
+        let env = PartialConfig {
+            wrap: Some(WrapMode::Width),
+            ..PartialConfig::default()
+        };
+        let config = env.merge_over(config);
+        assert_eq!(config.wrap, WrapMode::Width);
+        assert_eq!(config.list.spacing, ListSpacing::Tight);
+    }
+
+    #[test]
+    fn test_to_toml_round_trip() {
+        let config = Config::from_toml(
+            r#"
+line_width = 72
+wrap = "none"
+
+[code_block.formatters]
+python = ["ruff", "format", "-"]
+javascript = { command = ["deno", "fmt", "-"], timeout = 10 }
+
+[punctuation]
+en_dash = "--"
+em_dash = false
+"#,
+        )
+        .unwrap();
+        let toml = config.to_toml().unwrap();
+        assert_eq!(Config::from_toml(&toml).unwrap(), config);
+    }
 }
@@ -0,0 +1,303 @@
+//! Checks for fuzzing the formatter with whole documents.
+//!
+//! [`check`] is what the `cargo fuzz` targets in the *fuzz/* directory run on
+//! each input: formatting it must not panic, formatting the output again must
+//! not change it, and the output must have the same structure as the input,
+//! as [`structure`] describes it.  This module is only built with the
+//! `fuzzing` feature, and is not a stable API.
+//!
+//! The tests of this module run [`check`] on the seed corpus of the fuzz
+//! targets, which has the inputs of the unit tests, and on the minimized
+//! inputs of the failures fuzzing has found, in *tests/regressions/*.
+
+use comrak::nodes::{AstNode, ListType, NodeValue};
+use comrak::{Arena, parse_document};
+
+use crate::serializer::{Directive, details};
+use crate::{DashSetting, Options, format};
+
+/// The options documents are formatted with for fuzzing: the defaults, but
+/// without the punctuation transformations, which change the text on
+/// purpose.
+pub fn options() -> Options {
+    Options {
+        curly_double_quotes: false,
+        curly_single_quotes: false,
+        curly_apostrophes: false,
+        ellipsis: false,
+        en_dash: DashSetting::Disabled,
+        em_dash: DashSetting::Disabled,
+        ..Options::default()
+    }
+}
+
+/// Format `data`, if it is UTF-8, and panic if the output isn't stable or
+/// doesn't have the structure of the input.
+///
+/// The structure of documents with formatting directives, like
+/// `<!-- hongdown-disable -->` or `<!-- toc -->`, isn't checked, as those
+/// keep text as it is, or generate it.
+pub fn check(data: &[u8]) {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let options = options();
+    let Ok(output) = format(input, &options) else {
+        return;
+    };
+    let again = format(&output, &options).expect("formatting the output failed");
+    assert_eq!(again, output, "formatting is not idempotent");
+    if has_directives(input) {
+        return;
+    }
+    assert_eq!(
+        structure(&output),
+        structure(input),
+        "the structure changed; output:\n{}",
+        output
+    );
+}
+
+/// Whether `input` has formatting directives.
+fn has_directives(input: &str) -> bool {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &crate::base_comrak_options());
+    root.descendants()
+        .any(|node| match &node.data.borrow().value {
+            NodeValue::HtmlBlock(html) => Directive::parse(&html.literal).is_some(),
+            NodeValue::HtmlInline(html) => Directive::parse(html).is_some(),
+            _ => false,
+        })
+}
+
+/// A description of the structure of a Markdown document, which is the same
+/// for two documents that differ only in ways the formatter may change.
+///
+/// It lists the blocks and the inline elements in the document, with their
+/// text, but not how they are written: the list markers and numbers, the heading and
+/// code fence styles, the emphasis delimiters, escapes, and whether links
+/// are inline or reference links.  Whitespace in text and at the end of
+/// lines of HTML is ignored, since lines are rewrapped and may be joined
+/// without a space, and so are empty HTML comments, which the formatter
+/// puts between adjacent lists.  Emphasis around nothing but whitespace is
+/// ignored too, since it is dropped, and a link without text is taken to
+/// have its URL as text, since it may become an autolink.  The Markdown
+/// inside `<details>` blocks is read as Markdown whether or not there are
+/// blank lines around it, since the formatter puts them there.  Footnote
+/// definitions are listed at the end, by name, since the formatter moves
+/// them.
+pub fn structure(input: &str) -> String {
+    let (input, _) = details::prepare(input, options().html_details);
+    let arena = Arena::new();
+    let root = parse_document(&arena, &input, &crate::base_comrak_options());
+    let mut description = Description::default();
+    for child in root.children() {
+        description.block(child, 0);
+    }
+    description.footnotes.sort();
+    let mut out = description.out;
+    for (_, footnote) in description.footnotes {
+        out.push_str(&footnote);
+    }
+    out
+}
+
+/// The description being made by [`structure`].
+#[derive(Default)]
+struct Description {
+    out: String,
+    /// The names and descriptions of the footnote definitions.
+    footnotes: Vec<(String, String)>,
+}
+
+impl Description {
+    fn line(&mut self, depth: usize, text: &str) {
+        self.out.push_str(&"  ".repeat(depth));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn block<'a>(&mut self, node: &'a AstNode<'a>, depth: usize) {
+        let label = match &node.data.borrow().value {
+            NodeValue::FootnoteDefinition(footnote) => {
+                let mut definition = Self::default();
+                definition.line(0, &format!("footnote {}", footnote.name));
+                for child in node.children() {
+                    definition.block(child, 1);
+                }
+                self.footnotes.push((footnote.name.clone(), definition.out));
+                self.footnotes.extend(definition.footnotes);
+                return;
+            }
+            NodeValue::Paragraph => format!("paragraph {}", inline_text(node)),
+            NodeValue::Heading(heading) => {
+                format!("heading {} {}", heading.level, inline_text(node))
+            }
+            NodeValue::TableCell => format!("cell {}", inline_text(node)),
+            NodeValue::CodeBlock(code) => {
+                let info = code.info.split_whitespace().next().unwrap_or("");
+                format!("code {} {:?}", info, code.literal.trim_end_matches('\n'))
+            }
+            NodeValue::HtmlBlock(html) if html.literal.trim_end() == "<!-- -->" => return,
+            NodeValue::HtmlBlock(html) => {
+                let lines: Vec<_> = html.literal.lines().map(str::trim_end).collect();
+                format!("html {:?}", lines.join("\n").trim_end())
+            }
+            NodeValue::List(list) => match list.list_type {
+                ListType::Bullet => "bullet list".to_string(),
+                ListType::Ordered => "ordered list".to_string(),
+            },
+            NodeValue::TaskItem(task) => format!("task {}", task.symbol.is_some()),
+            NodeValue::FrontMatter(content) => format!("front matter {:?}", content.trim()),
+            NodeValue::ThematicBreak => "thematic break".to_string(),
+            value => value.xml_node_name().to_string(),
+        };
+        self.line(depth, &label);
+        if !matches!(
+            node.data.borrow().value,
+            NodeValue::Paragraph | NodeValue::Heading(_) | NodeValue::TableCell
+        ) {
+            for child in node.children() {
+                self.block(child, depth + 1);
+            }
+        }
+    }
+}
+
+/// The inline content of `node`, with the inline elements in it marked, and
+/// without whitespace.
+fn inline_text<'a>(node: &'a AstNode<'a>) -> String {
+    fn walk<'a>(node: &'a AstNode<'a>, out: &mut String) {
+        let (open, close) = match &node.data.borrow().value {
+            NodeValue::Text(text) => {
+                out.push_str(text);
+                return;
+            }
+            NodeValue::SoftBreak => {
+                out.push(' ');
+                return;
+            }
+            NodeValue::LineBreak => {
+                out.push_str("<br>");
+                return;
+            }
+            NodeValue::Code(code) => {
+                out.push_str(&format!("<code>{}</code>", code.literal));
+                return;
+            }
+            NodeValue::HtmlInline(html) => {
+                out.push_str(&format!("<html>{}</html>", html));
+                return;
+            }
+            NodeValue::FootnoteReference(footnote) => {
+                out.push_str(&format!("<footnote {}>", footnote.name));
+                return;
+            }
+            NodeValue::Emph => ("<em>".to_string(), "</em>"),
+            NodeValue::Strong => ("<strong>".to_string(), "</strong>"),
+            NodeValue::Strikethrough => ("<del>".to_string(), "</del>"),
+            NodeValue::Link(link) => (format!("<a {:?} {:?}>", link.url, link.title), "</a>"),
+            NodeValue::Image(image) => {
+                (format!("<img {:?} {:?}>", image.url, image.title), "</img>")
+            }
+            _ => (String::new(), ""),
+        };
+        let mut inner = String::new();
+        for child in node.children() {
+            walk(child, &mut inner);
+        }
+        if inner.trim().is_empty() {
+            match &node.data.borrow().value {
+                NodeValue::Emph | NodeValue::Strong => {
+                    out.push_str(&inner);
+                    return;
+                }
+                NodeValue::Link(link) => inner = link.url.clone(),
+                _ => {}
+            }
+        }
+        out.push_str(&open);
+        out.push_str(&inner);
+        out.push_str(close);
+    }
+    let mut out = String::new();
+    for child in node.children() {
+        walk(child, &mut out);
+    }
+    out.split_whitespace().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_structure_ignores_style() {
+        assert_eq!(
+            structure("Title\n=====\n\n* *a*  b\n* [c](/d)\n\n```rust\nx\n```\n"),
+            structure("# Title\n\n -  _a_ b\n -  [c]\n\n~~~~ rust\nx\n~~~~\n\n[c]: /d\n")
+        );
+        assert_ne!(structure("*a*\n"), structure("**a**\n"));
+        assert_eq!(
+            structure("- a\n\n* b\n"),
+            structure("- a\n\n<!-- -->\n\n- b\n")
+        );
+        assert_eq!(structure("[](https://a/)\n"), structure("<https://a/>\n"));
+        assert_eq!(structure("a _&nbsp;_ b\n"), structure("a \u{a0} b\n"));
+        assert_ne!(structure("> a\n\n> b\n"), structure("> a\n>\n> b\n"));
+        assert_eq!(
+            structure("> a\n\n> b\n"),
+            structure("> a\n\n<!-- -->\n\n> b\n")
+        );
+        assert_ne!(
+            structure("- a\n- b\n"),
+            structure("- a\n\n<!-- -->\n\n- b\n")
+        );
+    }
+
+    #[test]
+    fn test_structure_reads_details_as_markdown() {
+        let tight = "<details>\n<summary>S</summary>\n- a\n- *b*\n</details>\n";
+        let loose = "<details>\n<summary>S</summary>\n\n - a\n - _b_\n\n</details>\n";
+        assert_eq!(structure(tight), structure(loose));
+        assert_ne!(
+            structure(tight),
+            structure("<details>\n<summary>S</summary>\n\n- a\n\n</details>\n")
+        );
+        check(tight.as_bytes());
+        check(b"<details>\n<summary>Outer</summary>\n<details>\n<summary>Inner</summary>\n1. x\n</details>\n</details>\n");
+    }
+
+    /// Run [`check`] on each file in the directory at `path`, relative to
+    /// the root of the crate.
+    fn check_files(path: &str) {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let data = std::fs::read(&path).unwrap();
+            if let Err(e) = std::panic::catch_unwind(|| check(&data)) {
+                panic!(
+                    "{} failed: {:?}",
+                    path.display(),
+                    e.downcast_ref::<String>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_regressions() {
+        check_files("tests/regressions");
+    }
+
+    #[test]
+    fn test_seed_corpus() {
+        check_files("fuzz/corpus/format");
+    }
+
+    #[test]
+    fn test_check() {
+        check(b"Hello, *world*!\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n[^1]: Note.\n");
+        check(b"\xff\xfe invalid UTF-8 is skipped");
+    }
+}
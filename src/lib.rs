@@ -11,22 +11,45 @@
 //! let output = format(input, &options).unwrap();
 //! ```
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::ops::Range;
 
 pub mod config;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
+pub mod lint;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+pub mod report;
 mod serializer;
+mod session;
+mod slug;
+mod stats;
+mod trace;
 
 #[cfg(feature = "wasm")]
 mod wasm;
 
 pub use config::{
-    DashPattern, DashSetting, FenceChar, IndentWidth, LeadingSpaces, LineWidth, MinFenceLength,
-    OrderedListPad, OrderedMarker, ThematicBreakStyle, TrailingSpaces, UnorderedMarker,
+    BackslashEscape, BlockQuoteSeparation, ByteOrderMark, CellWrap, DashPattern, DashSetting,
+    DefinitionOrder, DetailsHandling, Dialect, EmbeddedLanguage, EmptyLinkText, EntityMode,
+    FenceChar, FixRule, GitHubRepo, HardBreak, HeadingLevel, IndentWidth, InlineFootnotes,
+    JsonKeyOrder, LeadingSpaces, LineEnding, LineWidth, ListContinuation, ListSeparation,
+    ListSpacing, Locale, MaxBlankLines, MinFenceLength, OrderedListPad, OrderedMarker,
+    OrderedNumbering, ReferenceSyntax, TableFallback, TableFormat, TablePadding, TableSeparator,
+    TableWidthGrowth, TableWidthStep, TemplateSyntax, ThematicBreakStyle, TrailingSpaces,
+    UnorderedMarker, UrlEncoding, VoidTagStyle, WrapMode,
 };
 pub use serializer::Warning;
+pub use serializer::plain_text_of;
 pub use serializer::punctuation::{PunctuationError, validate_dash_settings};
+pub use session::{FormatSession, SessionResult};
+pub use stats::DocStats;
 
+use comrak::nodes::{AstNode, NodeValue};
 use comrak::{Arena, Options as ComrakOptions, parse_document};
+use unicode_width::UnicodeWidthStr;
 
 /// External code formatter configuration.
 #[derive(Debug, Clone)]
@@ -43,12 +66,71 @@ pub struct Options {
     /// Line width for wrapping. Default: 80.
     pub line_width: LineWidth,
 
+    /// How paragraphs are wrapped. Default: `WrapMode::Width`.
+    pub wrap: WrapMode,
+
+    /// The most blank lines written between two blocks; with 1, sections
+    /// are separated by one blank line instead of two. Default: 2.
+    pub max_consecutive_blank_lines: MaxBlankLines,
+
+    /// The language whose rules paragraphs are written by, like where
+    /// sentences end with `WrapMode::Sentence`.  Default: `Locale::None`.
+    pub locale: Locale,
+
+    /// Line ending style for the output. Default: `LineEnding::Lf`.
+    pub line_ending: LineEnding,
+
+    /// Whether a byte order mark at the start of the input is kept in the
+    /// output.  Default: `ByteOrderMark::Preserve`.
+    pub bom: ByteOrderMark,
+
+    /// How hard line breaks are written.  Default: `HardBreak::Spaces`.
+    pub hard_break: HardBreak,
+
+    /// How character entity references in text are written.
+    /// Default: `EntityMode::Preserve`.
+    pub entities: EntityMode,
+
+    /// The Markdown dialect of the output.  Input is always parsed as GitHub
+    /// Flavored Markdown.  Default: `Dialect::Gfm`.
+    pub dialect: Dialect,
+
+    /// How tables are written in the `commonmark` dialect.
+    /// Default: `TableFallback::Html`.
+    pub commonmark_tables: TableFallback,
+
+    /// Recognize `:::` container blocks, like Pandoc's fenced divs, and
+    /// format the Markdown inside them. Default: false.
+    pub containers: bool,
+
+    /// Recognize `$` inline math and `$$` display math, which are kept as
+    /// they are, and escape dollar signs in text so that they aren't read
+    /// back as math. Default: false.
+    pub math: bool,
+
+    /// The template syntax whose tags are kept as they are, never escaped or
+    /// broken across lines. Default: `TemplateSyntax::None`.
+    pub templates: TemplateSyntax,
+
+    /// The lint rules whose problems are fixed before the document is
+    /// written, each fix being reported with a warning whose
+    /// [`Warning::fixed`] is true. Default: none.
+    pub fix: Vec<FixRule>,
+
+    /// Keep JSX components, `{` expressions, and `import` and `export`
+    /// statements as they are, for MDX documents. Default: false.
+    pub mdx: bool,
+
     /// Use setext-style (underlined) for h1 headings. Default: true.
     pub setext_h1: bool,
 
     /// Use setext-style (underlined) for h2 headings. Default: true.
     pub setext_h2: bool,
 
+    /// Keep hard line breaks in headings, forcing setext style for them.
+    /// When false, hard line breaks in headings become spaces. Default: true.
+    pub heading_allow_multiline: bool,
+
     /// Convert headings to sentence case. Default: false.
     pub heading_sentence_case: bool,
 
@@ -60,6 +142,18 @@ pub struct Options {
     /// These are excluded from built-in proper nouns.
     pub heading_common_nouns: Vec<String>,
 
+    /// Trailing characters to strip from headings, such as `":"`.
+    /// Default: none.
+    pub heading_strip_trailing_punctuation: Vec<String>,
+
+    /// Number the headings at the top level of the document, replacing the
+    /// numbers they already start with.  Default: `false`.
+    pub heading_numbering: bool,
+
+    /// Heading levels that get numbers with `heading_numbering`.  Headings
+    /// at a higher level restart the numbering.  Default: `[2, 3]`.
+    pub heading_numbering_levels: Vec<HeadingLevel>,
+
     /// Marker character for unordered lists: `-`, `*`, or `+`. Default: `-`.
     pub unordered_marker: UnorderedMarker,
 
@@ -83,9 +177,36 @@ pub struct Options {
     /// Padding style for ordered list numbers. Default: `Start`.
     pub ordered_list_pad: OrderedListPad,
 
+    /// How the items of ordered lists are numbered; with
+    /// `OrderedNumbering::Ones`, every item is `1.`. Default:
+    /// `OrderedNumbering::Sequential`.
+    pub ordered_list_numbering: OrderedNumbering,
+
     /// Indentation width for nested ordered list items. Default: 4.
     pub ordered_list_indent_width: IndentWidth,
 
+    /// How adjacent lists of the same kind are kept apart, so that they are
+    /// not read back as a single list. Default: `Comment`.
+    pub list_separation: ListSeparation,
+
+    /// Whether lists have blank lines between their items.
+    /// Default: `ListSpacing::Preserve`.
+    pub list_spacing: ListSpacing,
+
+    /// Where the wrapped lines of a list item's text start.
+    /// Default: `ListContinuation::AlignText`.
+    pub list_continuation: ListContinuation,
+
+    /// The indentation of wrapped list item lines from the marker when
+    /// `list_continuation` is `ListContinuation::Fixed`. Default: 2.
+    pub list_fixed_width: usize,
+
+    /// Whether a paragraph with two or more lines in a row that start like
+    /// the items of a lettered or roman-numbered list, like `a.`, `B)`, or
+    /// `iv.`, which CommonMark doesn't have, keeps its line breaks instead
+    /// of being wrapped. Default: `false`.
+    pub list_preserve_pseudo_lists: bool,
+
     /// Fence character for code blocks: `~` or `` ` ``. Default: `~`.
     pub fence_char: FenceChar,
 
@@ -100,6 +221,30 @@ pub struct Options {
     /// Set to e.g. "text" to add a default language identifier.
     pub default_language: String,
 
+    /// Strip trailing whitespace from the lines of code blocks.
+    /// Default: false.
+    pub code_block_trim_trailing_whitespace: bool,
+
+    /// Languages whose code blocks are formatted by Hongdown itself, without
+    /// an external formatter.  Code that doesn't parse is left as it is,
+    /// with a warning.  An external formatter in `code_formatters` for the
+    /// same language takes precedence.
+    /// Default: empty.
+    pub code_block_format_embedded: Vec<EmbeddedLanguage>,
+
+    /// The order of the keys of objects in JSON code blocks formatted with
+    /// `code_block_format_embedded`.
+    /// Default: [`JsonKeyOrder::Preserve`].
+    pub code_block_json_key_order: JsonKeyOrder,
+
+    /// Keep the spaces after `>` on block quote lines that are otherwise
+    /// unchanged, instead of writing a single space. Default: false.
+    pub block_quote_preserve_marker_spacing: bool,
+
+    /// How adjacent block quotes are kept apart, so that they are not read
+    /// back as a single block quote. Default: `Blank`.
+    pub block_quote_separation: BlockQuoteSeparation,
+
     /// The style string for thematic breaks. Default: 37 spaced dashes.
     pub thematic_break_style: ThematicBreakStyle,
 
@@ -107,6 +252,117 @@ pub struct Options {
     /// CommonMark allows 0-3 leading spaces for thematic breaks.
     pub thematic_break_leading_spaces: LeadingSpaces,
 
+    /// The display width of the text of a table cell above which a warning
+    /// is reported, and which `table_wrap_cells` breaks it at.
+    /// Default: `None`.
+    pub table_max_column_width: Option<usize>,
+
+    /// How table cells wider than `table_max_column_width` are broken into
+    /// lines. Default: `CellWrap::None`.
+    pub table_wrap_cells: CellWrap,
+
+    /// Whether table cells are padded to the width of their column; with
+    /// `TablePadding::Compact`, they aren't, and the delimiter row has the
+    /// shortest delimiters. Default: `TablePadding::Aligned`.
+    pub table_padding: TablePadding,
+
+    /// Whether tables are formatted; with `TableFormat::Preserve`, each
+    /// table is copied from the source as it is. Default:
+    /// `TableFormat::Align`.
+    pub table_format: TableFormat,
+
+    /// How wide the delimiters of the delimiter row of tables are; with
+    /// `TableSeparator::Minimal`, they are the shortest ones, like `:--`,
+    /// whatever the widths of the columns. Default: `TableSeparator::Full`.
+    pub table_separator: TableSeparator,
+
+    /// How the widths of table columns follow their contents; with
+    /// `TableWidthGrowth::Sticky`, they are rounded up to a multiple of
+    /// `table_width_step`, and never narrower than the delimiter row of the
+    /// source. Default: `TableWidthGrowth::Exact`.
+    pub table_width_growth: TableWidthGrowth,
+
+    /// The multiple that sticky table columns are rounded up to. Default: 4.
+    pub table_width_step: TableWidthStep,
+
+    /// Write the tags of HTML void elements, like `<BR/>`, in the
+    /// `html_void_tag_style`, in lowercase. Default: false.
+    pub html_normalize_void_tags: bool,
+
+    /// How the tags of HTML void elements are closed when normalized.
+    /// Default: `VoidTagStyle::Html`.
+    pub html_void_tag_style: VoidTagStyle,
+
+    /// Write a `<br>` at the end of a line of a paragraph as a hard line
+    /// break, except in table cells. Default: false.
+    pub html_br_to_hard_break: bool,
+
+    /// Write an `<hr>` that is a block of its own as a thematic break.
+    /// Default: false.
+    pub html_hr_to_thematic_break: bool,
+
+    /// How `<details>` blocks with Markdown inside are handled; with
+    /// `DetailsHandling::Format`, a blank line is kept after the
+    /// `<summary>` and before the closing tag, and what is between them is
+    /// formatted. Default: `DetailsHandling::Format`.
+    pub html_details: DetailsHandling,
+
+    /// Which literal backslashes in text are escaped; with
+    /// `BackslashEscape::Lazy`, `C:\\Users` is written as it is, rather than
+    /// as `C:\\\\Users`. Default: `BackslashEscape::Always`.
+    pub escape_backslash: BackslashEscape,
+
+    /// The GitHub repository, as `owner/name`, that bare references in
+    /// prose, like `#123`, `GH-123`, `owner/name#123`, and `@user`, are
+    /// turned into links to.  Default: `None`, which leaves them as they
+    /// are.
+    pub autolink_github_repo: Option<GitHubRepo>,
+
+    /// Turn links to GitHub whose text is a reference to what they link to
+    /// back into bare references, instead of the other way around.
+    /// Default: false.
+    pub autolink_github_strip: bool,
+
+    /// Drop reference definitions that no link or image uses. Default: true.
+    /// When false, unused single-line definitions are kept at the end of
+    /// the document.
+    pub link_remove_unused_definitions: bool,
+
+    /// Syntax for reference links and images whose text matches their
+    /// label. Default: `Shortcut`.
+    pub link_reference_syntax: ReferenceSyntax,
+
+    /// Turn inline links to external URLs into reference links.  When
+    /// false, they are kept inline.  Default: `true`.
+    pub link_external_references: bool,
+
+    /// How characters in link and image URLs are percent-encoded.
+    /// Default: `Preserve`.
+    pub link_url_encoding: UrlEncoding,
+
+    /// The order reference definitions are written in.
+    /// Default: `DefinitionOrder::None`.
+    pub link_sort_definitions: DefinitionOrder,
+
+    /// How links to external URLs with empty text, like `[](url)`, are
+    /// written.  Default: `EmptyLinkText::Autolink`.
+    pub link_empty_text: EmptyLinkText,
+
+    /// The order footnote definitions are written in.
+    /// Default: `DefinitionOrder::None`.
+    pub footnote_sort_definitions: DefinitionOrder,
+
+    /// What is done with inline footnotes, like `^[a note]`.
+    /// Default: `InlineFootnotes::Preserve`.
+    pub footnote_inline: InlineFootnotes,
+
+    /// The shallowest heading level listed in a table of contents.
+    /// Default: 2.
+    pub toc_min_level: HeadingLevel,
+
+    /// The deepest heading level listed in a table of contents. Default: 3.
+    pub toc_max_level: HeadingLevel,
+
     /// Convert straight double quotes to curly quotes. Default: true.
     /// `"text"` becomes `"text"` (U+201C and U+201D).
     pub curly_double_quotes: bool,
@@ -151,11 +407,32 @@ impl Default for Options {
     fn default() -> Self {
         Self {
             line_width: LineWidth::default(),
+            wrap: WrapMode::default(),
+            max_consecutive_blank_lines: MaxBlankLines::default(),
+            locale: Locale::default(),
+            line_ending: LineEnding::default(),
+            bom: ByteOrderMark::default(),
+            hard_break: HardBreak::default(),
+            entities: EntityMode::default(),
+            dialect: Dialect::default(),
+            commonmark_tables: TableFallback::default(),
+            containers: false,
+            math: false,
+            templates: TemplateSyntax::default(),
+            fix: Vec::new(),
+            mdx: false,
             setext_h1: true,
             setext_h2: true,
+            heading_allow_multiline: true,
             heading_sentence_case: false,
             heading_proper_nouns: Vec::new(),
             heading_common_nouns: Vec::new(),
+            heading_strip_trailing_punctuation: Vec::new(),
+            heading_numbering: false,
+            heading_numbering_levels: vec![
+                HeadingLevel::new(2).unwrap(),
+                HeadingLevel::new(3).unwrap(),
+            ],
             unordered_marker: UnorderedMarker::default(),
             leading_spaces: LeadingSpaces::default(),
             trailing_spaces: TrailingSpaces::default(),
@@ -163,13 +440,49 @@ impl Default for Options {
             odd_level_marker: OrderedMarker::default(),
             even_level_marker: OrderedMarker::Parenthesis,
             ordered_list_pad: OrderedListPad::Start,
+            ordered_list_numbering: OrderedNumbering::default(),
             ordered_list_indent_width: IndentWidth::default(),
+            list_separation: ListSeparation::default(),
+            list_spacing: ListSpacing::default(),
+            list_continuation: ListContinuation::default(),
+            list_fixed_width: 2,
+            list_preserve_pseudo_lists: false,
             fence_char: FenceChar::default(),
             min_fence_length: MinFenceLength::default(),
             space_after_fence: true,
             default_language: String::new(),
+            code_block_trim_trailing_whitespace: false,
+            code_block_format_embedded: Vec::new(),
+            code_block_json_key_order: JsonKeyOrder::Preserve,
+            block_quote_preserve_marker_spacing: false,
+            block_quote_separation: BlockQuoteSeparation::default(),
             thematic_break_style: ThematicBreakStyle::default(),
             thematic_break_leading_spaces: LeadingSpaces::new(3).unwrap(),
+            table_max_column_width: None,
+            table_wrap_cells: CellWrap::default(),
+            table_padding: TablePadding::default(),
+            table_format: TableFormat::default(),
+            table_separator: TableSeparator::default(),
+            table_width_growth: TableWidthGrowth::default(),
+            table_width_step: TableWidthStep::default(),
+            html_normalize_void_tags: false,
+            html_void_tag_style: VoidTagStyle::default(),
+            html_br_to_hard_break: false,
+            html_hr_to_thematic_break: false,
+            html_details: DetailsHandling::default(),
+            escape_backslash: BackslashEscape::default(),
+            autolink_github_repo: None,
+            autolink_github_strip: false,
+            link_remove_unused_definitions: true,
+            link_reference_syntax: ReferenceSyntax::default(),
+            link_external_references: true,
+            link_url_encoding: UrlEncoding::default(),
+            link_sort_definitions: DefinitionOrder::default(),
+            link_empty_text: EmptyLinkText::default(),
+            footnote_sort_definitions: DefinitionOrder::default(),
+            footnote_inline: InlineFootnotes::default(),
+            toc_min_level: HeadingLevel::new(2).unwrap(),
+            toc_max_level: HeadingLevel::new(3).unwrap(),
             curly_double_quotes: true,
             curly_single_quotes: true,
             curly_apostrophes: false,
@@ -209,19 +522,7 @@ pub fn format(input: &str, options: &Options) -> Result<String, FormatError> {
         return Ok(String::new());
     }
 
-    let arena = Arena::new();
-    let mut comrak_options = ComrakOptions::default();
-    comrak_options.extension.front_matter_delimiter = Some("---".to_string());
-    comrak_options.extension.table = true;
-    comrak_options.extension.description_lists = true;
-    comrak_options.extension.alerts = true;
-    comrak_options.extension.footnotes = true;
-    comrak_options.extension.tasklist = true;
-
-    let root = parse_document(&arena, input, &comrak_options);
-    let output = serializer::serialize_with_source(root, options, Some(input));
-
-    Ok(output)
+    Ok(run_pipeline(input, options, &mut NoHooks)?.output)
 }
 
 /// Result of formatting with warnings.
@@ -254,35 +555,693 @@ pub fn format_with_warnings(input: &str, options: &Options) -> Result<FormatResu
         });
     }
 
-    let arena = Arena::new();
-    let mut comrak_options = ComrakOptions::default();
-    comrak_options.extension.front_matter_delimiter = Some("---".to_string());
-    comrak_options.extension.table = true;
-    comrak_options.extension.description_lists = true;
-    comrak_options.extension.alerts = true;
-    comrak_options.extension.footnotes = true;
-    comrak_options.extension.tasklist = true;
-
-    let root = parse_document(&arena, input, &comrak_options);
-    let result = serializer::serialize_with_source_and_warnings(root, options, Some(input));
-
+    let result = run_pipeline(input, options, &mut NoHooks)?;
     Ok(FormatResult {
         output: result.output,
         warnings: result.warnings,
     })
 }
 
+/// Formats a Markdown document, copying the blocks in the given byte ranges
+/// of it as they are.
+///
+/// This is for documents with regions other tools maintain, like badges or
+/// generated tables, which must not be touched.  Each range is widened to
+/// the top-level blocks it touches, so a range anywhere in a list protects
+/// the whole list; the blocks around the ranges are formatted as usual.
+///
+/// # Errors
+///
+/// Returns [`FormatError::InvalidRange`] if a range reaches past the end of
+/// `input`, ends before it starts, or overlaps another range.
+///
+/// # Example
+///
+/// ```
+/// use hongdown::{Options, format_with_protected_ranges};
+///
+/// let input = "# Title\n\n| a |  b |\n|-|-|\n";
+/// let table = input.find('|').unwrap()..input.len();
+/// let output = format_with_protected_ranges(input, &[table], &Options::default()).unwrap();
+/// assert_eq!(output, "Title\n=====\n\n| a |  b |\n|-|-|\n");
+/// ```
+pub fn format_with_protected_ranges(
+    input: &str,
+    ranges: &[Range<usize>],
+    options: &Options,
+) -> Result<String, FormatError> {
+    let protected =
+        serializer::protect::line_ranges(input, ranges).map_err(FormatError::InvalidRange)?;
+    if input.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut hooks = ProtectHooks {
+        source: input,
+        protected,
+    };
+    Ok(run_pipeline(input, options, &mut hooks)?.output)
+}
+
+/// The hooks of [`format_with_protected_ranges`], which replace the
+/// `protected` lines of `source` with raw nodes right after parsing.
+struct ProtectHooks<'s> {
+    source: &'s str,
+    protected: Vec<(usize, usize)>,
+}
+
+impl PipelineHooks for ProtectHooks<'_> {
+    fn parsed<'a>(
+        &mut self,
+        root: &'a AstNode<'a>,
+        _input: &str,
+        inserted: &[usize],
+    ) -> Result<(), FormatError> {
+        serializer::protect::protect_blocks(root, self.source, &self.protected, inserted);
+        Ok(())
+    }
+}
+
+/// Formats a Markdown pipe table, for tools that only want tables aligned.
+///
+/// # Errors
+///
+/// Returns [`FormatError::UnexpectedBlock`] unless `input` is a single
+/// table.
+///
+/// # Example
+///
+/// ```
+/// use hongdown::{Options, format_table};
+///
+/// let output = format_table("|a|b|\n|:-|-:|\n|long|1|\n", &Options::default()).unwrap();
+/// assert_eq!(output, "| a    |   b |\n| :--- | --: |\n| long |   1 |\n");
+/// ```
+pub fn format_table(input: &str, options: &Options) -> Result<String, FormatError> {
+    format_single_block(input, options, "table", |value| {
+        matches!(value, NodeValue::Table(_))
+    })
+}
+
+/// Formats a Markdown list, bulleted or numbered, with its nested blocks.
+///
+/// # Errors
+///
+/// Returns [`FormatError::UnexpectedBlock`] unless `input` is a single list.
+pub fn format_list(input: &str, options: &Options) -> Result<String, FormatError> {
+    format_single_block(input, options, "list", |value| {
+        matches!(value, NodeValue::List(_))
+    })
+}
+
+/// Formats a Markdown code block, fenced or indented.
+///
+/// # Errors
+///
+/// Returns [`FormatError::UnexpectedBlock`] unless `input` is a single code
+/// block.
+pub fn format_code_block(input: &str, options: &Options) -> Result<String, FormatError> {
+    format_single_block(input, options, "code block", |value| {
+        matches!(value, NodeValue::CodeBlock(_))
+    })
+}
+
+/// Formats `input`, which must be a single block that `is_kind`, named
+/// `kind`, accepts.  Footnote definitions, like those inline footnotes are
+/// expanded into, don't count as blocks.
+fn format_single_block(
+    input: &str,
+    options: &Options,
+    kind: &'static str,
+    is_kind: fn(&NodeValue) -> bool,
+) -> Result<String, FormatError> {
+    let mut hooks = SingleBlockHooks { kind, is_kind };
+    Ok(run_pipeline(input, options, &mut hooks)?.output)
+}
+
+/// The hooks of [`format_single_block`], which make sure the document is
+/// a single block of the kind it formats right after parsing.
+struct SingleBlockHooks {
+    kind: &'static str,
+    is_kind: fn(&NodeValue) -> bool,
+}
+
+impl PipelineHooks for SingleBlockHooks {
+    fn parsed<'a>(
+        &mut self,
+        root: &'a AstNode<'a>,
+        _input: &str,
+        _inserted: &[usize],
+    ) -> Result<(), FormatError> {
+        let blocks: Vec<_> = root
+            .children()
+            .filter(|node| !matches!(node.data.borrow().value, NodeValue::FootnoteDefinition(_)))
+            .collect();
+        let found = match blocks[..] {
+            [block] if (self.is_kind)(&block.data.borrow().value) => return Ok(()),
+            [block] => format!(
+                "a {}",
+                block.data.borrow().value.xml_node_name().replace('_', " ")
+            ),
+            [] => "nothing".to_string(),
+            _ => format!("{} blocks", blocks.len()),
+        };
+        Err(FormatError::UnexpectedBlock(format!(
+            "expected a single {}, but found {}",
+            self.kind, found
+        )))
+    }
+}
+
+/// Returns the level, plain text, and anchor of every heading in a Markdown
+/// document, in document order.
+///
+/// The document is read the way [`format`] reads it with `options`, so
+/// that, for example, `math` and `heading_numbering` count.  Anchors are
+/// generated the way GitHub generates them: the heading text, without any
+/// emphasis or code span markers, is lowercased, punctuation and symbols
+/// are removed, and spaces become hyphens.  A heading whose anchor is
+/// already taken by an earlier one gets `-1`, `-2`, and so on appended.
+///
+/// # Example
+///
+/// ```
+/// let options = hongdown::Options::default();
+/// let headings = hongdown::slugs("# Hello, *World*\n\n## Usage\n\n## Usage\n", &options);
+/// assert_eq!(
+///     headings,
+///     vec![
+///         (1, "Hello, World".to_string(), "hello-world".to_string()),
+///         (2, "Usage".to_string(), "usage".to_string()),
+///         (2, "Usage".to_string(), "usage-1".to_string()),
+///     ]
+/// );
+/// ```
+pub fn slugs(input: &str, options: &Options) -> Vec<(u8, String, String)> {
+    read_document(input, options, |root, _| slug::heading_slugs(root))
+}
+
+/// A code block of a Markdown document, as [`code_blocks`] returns it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CodeBlockRef {
+    /// The info string of a fenced code block, like `rust no_run`, or an
+    /// empty string for an indented code block.
+    pub info: String,
+    /// The content of the code block, without its fences or indentation.
+    pub literal: String,
+    /// Whether the code block is fenced, rather than indented.
+    pub fenced: bool,
+    /// The 1-indexed line the code block starts on, which is that of the
+    /// opening fence of a fenced code block.
+    pub start_line: usize,
+    /// The 1-indexed line the code block ends on, which is that of the
+    /// closing fence of a fenced code block, if it has one.
+    pub end_line: usize,
+}
+
+impl CodeBlockRef {
+    /// The language of the code block: the first word of its info string,
+    /// which is what code formatters are looked up by.
+    pub fn language(&self) -> &str {
+        self.info.split_whitespace().next().unwrap_or("")
+    }
+}
+
+/// Returns every code block of a Markdown document, fenced or indented, in
+/// document order, including those in lists, block quotes, footnotes, and
+/// `<details>` blocks.
+///
+/// The document is read the way [`format`] reads it with `options`, so
+/// that, for example, the code blocks in `<details>` blocks are found
+/// unless `html_details` is `DetailsHandling::Preserve`.  Their lines are
+/// those of `input` all the same.
+///
+/// # Example
+///
+/// ```
+/// let input = "Text.\n\n- Item:\n\n  ~~~~ rust\n  fn main() {}\n  ~~~~\n";
+/// let blocks = hongdown::code_blocks(input, &hongdown::Options::default());
+/// assert_eq!(blocks.len(), 1);
+/// assert_eq!(blocks[0].language(), "rust");
+/// assert_eq!(blocks[0].literal, "fn main() {}\n");
+/// assert_eq!((blocks[0].start_line, blocks[0].end_line), (5, 7));
+/// ```
+pub fn code_blocks(input: &str, options: &Options) -> Vec<CodeBlockRef> {
+    read_document(input, options, |root, inserted| {
+        let mut blocks: Vec<_> = root
+            .descendants()
+            .filter_map(|node| {
+                let data = node.data.borrow();
+                let NodeValue::CodeBlock(code) = &data.value else {
+                    return None;
+                };
+                let start_line = data.sourcepos.start.line;
+                // The source position of an indented code block takes in the
+                // blank lines after it
+                let end_line = if code.fenced {
+                    data.sourcepos.end.line
+                } else {
+                    start_line + code.literal.lines().count().max(1) - 1
+                };
+                Some(CodeBlockRef {
+                    info: code.info.clone(),
+                    literal: code.literal.clone(),
+                    fenced: code.fenced,
+                    start_line: serializer::details::original_line(start_line, inserted),
+                    end_line: serializer::details::original_line(end_line, inserted),
+                })
+            })
+            .collect();
+        // Footnote definitions are moved to the end of the document when
+        // parsed
+        blocks.sort_by_key(|block| block.start_line);
+        blocks
+    })
+}
+
+/// The kind of a link, as [`LinkRef`] tells it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A link with its destination right after its text, like
+    /// `[text](url)`.
+    Inline,
+    /// A link whose destination is in a reference definition, like
+    /// `[text][label]`, `[text][]`, or `[text]`.
+    Reference,
+    /// An autolink, like `<https://example.com/>`.
+    Autolink,
+    /// An image, inline or by reference, like `![alt](src)`.
+    Image,
+}
+
+/// A link or image of a Markdown document, as [`rewrite_links`] passes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkRef {
+    /// The destination of the link, or the source of the image.
+    pub destination: String,
+    /// The title of the link, or an empty string if it has none.
+    pub title: String,
+    /// How the link is written.
+    pub kind: LinkKind,
+    /// The 1-indexed line the link starts on.
+    pub start_line: usize,
+    /// The 1-indexed column the link starts at, in bytes.
+    pub start_column: usize,
+    /// The 1-indexed line the link ends on.
+    pub end_line: usize,
+    /// The 1-indexed column the link ends at, in bytes.
+    pub end_column: usize,
+}
+
+/// Formats a Markdown document with the destinations of its links and
+/// images rewritten by `rewrite`, for moving documents to another place or
+/// domain.
+///
+/// Each link and image is passed to `rewrite` in document order, and gets
+/// the destination it returns, or keeps its own if it returns `None`.
+/// Links that use the same reference definition are passed once, as the
+/// first of them, so that the definition is rewritten once.  An autolink
+/// stays one if its new destination can be written as one.
+///
+/// Links in regions where formatting is disabled are copied as they are,
+/// and so are unused reference definitions kept by
+/// `link_remove_unused_definitions`, which are no links.
+///
+/// # Example
+///
+/// ```
+/// use hongdown::{Options, rewrite_links};
+///
+/// let input = "See [the guide](guide.md) and [the API][api].\n\n[api]: api.md\n";
+/// let output = rewrite_links(input, &Options::default(), |link| {
+///     link.destination
+///         .strip_suffix(".md")
+///         .map(|stem| format!("{stem}.html"))
+/// })
+/// .unwrap();
+/// assert_eq!(
+///     output,
+///     "See [the guide](guide.html) and [the API][api].\n\n[api]: api.html\n"
+/// );
+/// ```
+pub fn rewrite_links(
+    input: &str,
+    options: &Options,
+    mut rewrite: impl FnMut(LinkRef) -> Option<String>,
+) -> Result<String, FormatError> {
+    if input.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut hooks = RewriteHooks {
+        options,
+        rewrite: &mut rewrite,
+    };
+    Ok(run_pipeline(input, options, &mut hooks)?.output)
+}
+
+/// The hooks of [`rewrite_links`], which rewrite the destinations of the
+/// links right after parsing.
+struct RewriteHooks<'r> {
+    options: &'r Options,
+    rewrite: &'r mut dyn FnMut(LinkRef) -> Option<String>,
+}
+
+impl PipelineHooks for RewriteHooks<'_> {
+    fn parsed<'a>(
+        &mut self,
+        root: &'a AstNode<'a>,
+        input: &str,
+        inserted: &[usize],
+    ) -> Result<(), FormatError> {
+        serializer::rewrite::rewrite_links(root, self.options, input, inserted, self.rewrite);
+        Ok(())
+    }
+}
+
+/// Returns statistics about a Markdown document: its words of prose, its
+/// headings of each level, its links, images, code blocks, and tables, how
+/// long it takes to read, and how long its longest line is once formatted
+/// with `options`.
+///
+/// The document is parsed only once, both to count its nodes and to format
+/// it.  See [`DocStats`] for how words are counted.
+///
+/// # Example
+///
+/// ```
+/// let options = hongdown::Options::default();
+/// let stats = hongdown::stats("# Title\n\nSome *prose*, and `code`.\n", &options);
+/// assert_eq!(stats.words, 4);
+/// assert_eq!(stats.headings, [1, 0, 0, 0, 0, 0]);
+/// assert_eq!(stats.longest_line, 25);
+/// ```
+pub fn stats(input: &str, options: &Options) -> DocStats {
+    if input.is_empty() {
+        return DocStats::default();
+    }
+    let mut hooks = StatsHooks::default();
+    let Ok(result) = run_pipeline(input, options, &mut hooks) else {
+        return DocStats::default();
+    };
+    DocStats {
+        longest_line: result
+            .output
+            .lines()
+            .map(UnicodeWidthStr::width)
+            .max()
+            .unwrap_or(0),
+        ..hooks.stats
+    }
+}
+
+/// The hooks of [`stats`], which count the nodes of the document right
+/// after parsing.
+#[derive(Default)]
+struct StatsHooks {
+    stats: DocStats,
+}
+
+impl PipelineHooks for StatsHooks {
+    fn parsed<'a>(
+        &mut self,
+        root: &'a AstNode<'a>,
+        _input: &str,
+        _inserted: &[usize],
+    ) -> Result<(), FormatError> {
+        self.stats = stats::collect(root);
+        Ok(())
+    }
+}
+
+/// Returns the comrak options Hongdown parses Markdown with for `config`.
+///
+/// Renderers that use comrak can take these, so that they read documents
+/// the same way the formatter does: with the same extensions, like
+/// footnotes and tables, and with those that depend on the configuration,
+/// like `math` and `dialect`, set the same way.  Note that with
+/// `containers` on, Hongdown also turns the `:::` fences of container
+/// blocks into those of multiline block quotes before parsing.
+///
+/// # Example
+///
+/// ```
+/// let config = hongdown::config::Config::from_toml("math = true").unwrap();
+/// let options = hongdown::comrak_options(&config);
+/// assert!(options.extension.math_dollars);
+/// assert!(options.extension.footnotes);
+/// ```
+pub fn comrak_options(config: &config::Config) -> ComrakOptions<'static> {
+    configured_comrak_options(config.dialect, config.math, config.containers)
+}
+
+/// The comrak options for the given dialect and `math` and `containers`
+/// options; see [`comrak_options`].
+fn configured_comrak_options(
+    dialect: Dialect,
+    math: bool,
+    containers: bool,
+) -> ComrakOptions<'static> {
+    let mut comrak = base_comrak_options();
+    comrak.extension.strikethrough = dialect == Dialect::CommonMark;
+    comrak.extension.math_dollars = math;
+    comrak.extension.multiline_block_quotes = containers;
+    comrak
+}
+
+/// The comrak options Hongdown parses Markdown with, whatever the options.
+pub(crate) fn base_comrak_options() -> ComrakOptions<'static> {
+    let mut options = ComrakOptions::default();
+    options.extension.front_matter_delimiter = Some("---".to_string());
+    options.extension.table = true;
+    options.extension.description_lists = true;
+    options.extension.alerts = true;
+    options.extension.footnotes = true;
+    options.extension.tasklist = true;
+    options
+}
+
+/// The steps an entry point takes besides those [`run_pipeline`] takes for
+/// every one of them.
+pub(crate) trait PipelineHooks {
+    /// How many nodes to make room for in the parse arena up front, if not
+    /// the arena's default.
+    fn node_capacity(&self) -> Option<usize> {
+        None
+    }
+
+    /// Called with the parsed document, before it is serialized, along
+    /// with the input it is parsed from and the lines blank lines were put
+    /// in at; see [`prepare_input`].  An error stops the pipeline.
+    fn parsed<'a>(
+        &mut self,
+        _root: &'a AstNode<'a>,
+        _input: &str,
+        _inserted: &[usize],
+    ) -> Result<(), FormatError> {
+        Ok(())
+    }
+
+    /// Serializes the parsed document.
+    fn serialize<'a>(
+        &mut self,
+        root: &'a AstNode<'a>,
+        options: &Options,
+        input: &str,
+    ) -> serializer::SerializeResult {
+        serializer::serialize_with_source_and_warnings(root, options, Some(input))
+    }
+
+    /// Replaces the `\n` line endings of `output` with `ending`.
+    fn convert_line_endings(&mut self, output: String, ending: &str) -> String {
+        let mut converted = String::with_capacity(output.len() + output.len() / 16);
+        push_with_line_ending(&mut converted, &output, ending);
+        converted
+    }
+}
+
+/// The hooks of an entry point that takes no steps of its own.
+pub(crate) struct NoHooks;
+
+impl PipelineHooks for NoHooks {}
+
+/// What [`run_pipeline`] returns.
+pub(crate) struct PipelineOutput {
+    /// The formatted Markdown output, with the configured line endings.
+    pub(crate) output: String,
+    /// The warnings of parsing and serializing, with lines of the input as
+    /// it was given.
+    pub(crate) warnings: Vec<Warning>,
+    /// The number of nodes the parse arena ended up with.
+    pub(crate) nodes: usize,
+}
+
+/// Formats `input` the way every entry point does: prepares it (see
+/// [`prepare_input`]), parses it (see [`parse`]), serializes it, and puts
+/// in the configured line endings, with `hooks` taking the steps that the
+/// entry point takes besides.
+pub(crate) fn run_pipeline(
+    input: &str,
+    options: &Options,
+    hooks: &mut impl PipelineHooks,
+) -> Result<PipelineOutput, FormatError> {
+    let (input, inserted) = prepare_input(input, options);
+    let input = &*input;
+    let arena = match hooks.node_capacity() {
+        Some(capacity) => Arena::with_capacity(capacity),
+        None => Arena::new(),
+    };
+    let (root, parse_warnings) = parse(&arena, input, options);
+    hooks.parsed(root, input, &inserted)?;
+    let result = hooks.serialize(root, options, input);
+    let mut warnings = result.warnings;
+    warnings.splice(..0, parse_warnings);
+    for warning in &mut warnings {
+        warning.line = serializer::details::original_line(warning.line, &inserted);
+    }
+    let output = match options.line_ending.resolve(input) {
+        "\n" => result.output,
+        ending => hooks.convert_line_endings(result.output, ending),
+    };
+    Ok(PipelineOutput {
+        output,
+        warnings,
+        nodes: arena.len(),
+    })
+}
+
+/// Prepares and parses `input` the way [`run_pipeline`] does, for the
+/// functions that read a document instead of formatting it, and returns
+/// what `read` makes of the document, given the lines blank lines were put
+/// in at.
+fn read_document<T>(
+    input: &str,
+    options: &Options,
+    read: impl for<'a> FnOnce(&'a AstNode<'a>, &[usize]) -> T,
+) -> T {
+    let (input, inserted) = prepare_input(input, options);
+    let arena = Arena::new();
+    let (root, _) = parse(&arena, &input, options);
+    read(root, &inserted)
+}
+
+/// `input` made ready to be parsed: with its inline footnotes expanded or
+/// marked (see [`serializer::inline_footnote`]), and with blank lines put
+/// in around the Markdown in `<details>` blocks (see
+/// [`serializer::details`]), whose line numbers are returned too, so that
+/// those of warnings can be turned back into lines of `input`.
+pub(crate) fn prepare_input<'a>(input: &'a str, options: &Options) -> (Cow<'a, str>, Vec<usize>) {
+    match serializer::inline_footnote::prepare(input, options.footnote_inline) {
+        Cow::Borrowed(input) => serializer::details::prepare(input, options.html_details),
+        Cow::Owned(input) => {
+            let (prepared, inserted) = serializer::details::prepare(&input, options.html_details);
+            (Cow::Owned(prepared.into_owned()), inserted)
+        }
+    }
+}
+
+/// Parses `input` into an AST allocated in `arena`, with the comrak options
+/// `options` call for.
+///
+/// Top-level blocks nested too deeply to format are replaced with their
+/// source right away, with a warning; see [`serializer::limits`].
+/// With `containers` on, the fences of `:::` container blocks are parsed as
+/// those of multiline block quotes; see [`serializer::container`].  With
+/// `heading_numbering` on, the headings are numbered right after parsing, so
+/// that their anchors and the table of contents have the new numbers, and
+/// with `autolink_github_repo` set, bare references to GitHub become links
+/// then too.  In the `commonmark` dialect, strikethrough is parsed too, so
+/// that it can be written as HTML.
+pub(crate) fn parse<'a>(
+    arena: &'a Arena<'a>,
+    input: &str,
+    options: &Options,
+) -> (&'a AstNode<'a>, Vec<Warning>) {
+    // Source positions don't count the byte order mark, so the fixes and
+    // heading numbers must not see it either
+    let input = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+    let comrak = configured_comrak_options(options.dialect, options.math, options.containers);
+    let root = if options.containers {
+        parse_document(arena, &serializer::container::mark_fences(input), &comrak)
+    } else {
+        parse_document(arena, input, &comrak)
+    };
+    let mut warnings = serializer::limits::flatten_deep_blocks(root, input);
+    warnings.extend(serializer::fix::apply_fixes(root, input, options));
+    if options.heading_numbering {
+        serializer::heading::number_headings(arena, root, input, &options.heading_numbering_levels);
+    }
+    let repo = options
+        .autolink_github_repo
+        .as_ref()
+        .map(GitHubRepo::as_str);
+    if repo.is_some() || options.autolink_github_strip {
+        serializer::autolink::link_github_references(
+            arena,
+            root,
+            repo,
+            options.autolink_github_strip,
+        );
+    }
+    (root, warnings)
+}
+
+/// Appends `text` to `out`, replacing each `\n` or `\r\n` with `ending`.
+pub(crate) fn push_with_line_ending(out: &mut String, text: &str, ending: &str) {
+    let mut rest = text;
+    while let Some(pos) = rest.find('\n') {
+        let line = &rest[..pos];
+        out.push_str(line.strip_suffix('\r').unwrap_or(line));
+        out.push_str(ending);
+        rest = &rest[pos + 1..];
+    }
+    out.push_str(rest);
+}
+
+/// Internal entry points for the benchmark suite.
+///
+/// These expose the parsing and serialization stages separately, along with
+/// hot helper functions, so that they can be measured in isolation.  They are
+/// not part of the public API and may change at any time.
+#[doc(hidden)]
+pub mod bench {
+    use comrak::nodes::AstNode;
+    use comrak::{Arena, parse_document};
+
+    use crate::Options;
+
+    pub use crate::serializer::escape::{escape_table_cell, escape_text};
+
+    /// Parses `input` into an AST allocated in `arena`.
+    pub fn parse<'a>(arena: &'a Arena<'a>, input: &str) -> &'a AstNode<'a> {
+        parse_document(arena, input, &super::base_comrak_options())
+    }
+
+    /// Serializes a pre-parsed AST.
+    pub fn serialize<'a>(root: &'a AstNode<'a>, input: &str, options: &Options) -> String {
+        crate::serializer::serialize_with_source(root, options, Some(input))
+    }
+}
+
 /// Errors that can occur during formatting.
 #[derive(Debug)]
 pub enum FormatError {
     /// An error occurred during parsing.
     ParseError(String),
+    /// A range given to [`format_with_protected_ranges`] is out of bounds
+    /// or overlaps another.
+    InvalidRange(String),
+    /// The input given to [`format_table`], [`format_list`], or
+    /// [`format_code_block`] isn't a single block of the kind it formats.
+    UnexpectedBlock(String),
 }
 
 impl std::fmt::Display for FormatError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FormatError::ParseError(msg) => write!(f, "Parse error: {}", msg),
+            FormatError::InvalidRange(msg) => write!(f, "Invalid range: {}", msg),
+            FormatError::UnexpectedBlock(msg) => write!(f, "Unexpected block: {}", msg),
         }
     }
 }
@@ -300,6 +1259,189 @@ mod tests {
         assert_eq!(result, "");
     }
 
+    /// A document in English, Korean, and Japanese, with some of everything
+    /// [`stats`] counts.
+    const MIXED_LANGUAGE_DOCUMENT: &str = "\
+Hongdown
+========
+
+Hongdown is a **Markdown** formatter, written in Rust: see [the guide](docs/guide.md).
+It doesn't touch `inline code` or ![the logo](logo.png).
+
+마크다운 포매터
+---------------
+
+홍다운은 Rust로 만든 formatter입니다.
+
+### 日本語
+
+日本語の文章です。
+
+~~~~ rust
+fn main() {}
+~~~~
+
+| Term | Meaning       |
+| ---- | ------------- |
+| CJK  | 中文, 日本語, 한국어 |
+
+<div>Not counted</div>
+";
+
+    #[test]
+    fn test_code_blocks_positions() {
+        let input = "\
+Intro.
+
+    indented
+
+    code
+
+ -  Item:
+
+    ~~~~ rust
+    fn a() {}
+    ~~~~
+
+     -  Nested:
+
+            nested indented
+
+> Quote:
+>
+>  -  Item in quote:
+>
+>     ```py title=\"x.py\"
+>     print(1)
+>     ```
+
+Text.[^1]
+
+[^1]: Note:
+
+    ~~~~
+    note
+    ~~~~
+
+~~~~ sh
+unclosed
+";
+        let blocks = code_blocks(input, &Options::default());
+        let summary: Vec<_> = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.language(),
+                    block.fenced,
+                    block.start_line,
+                    block.end_line,
+                    block.literal.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("", false, 3, 5, "indented\n\ncode\n"),
+                ("rust", true, 9, 11, "fn a() {}\n"),
+                ("", false, 15, 15, "nested indented\n"),
+                ("py", true, 21, 23, "print(1)\n"),
+                ("", true, 29, 31, "note\n"),
+                ("sh", true, 33, 34, "unclosed\n"),
+            ]
+        );
+        assert_eq!(blocks[3].info, "py title=\"x.py\"");
+        // The lines are those of the source
+        let lines: Vec<_> = input.lines().collect();
+        for block in &blocks {
+            if block.fenced {
+                assert!(
+                    lines[block.start_line - 1]
+                        .trim_start_matches([' ', '>'])
+                        .starts_with(['~', '`'])
+                );
+            }
+            let first = block.literal.lines().next().unwrap();
+            let first_line = block.start_line + usize::from(block.fenced);
+            assert!(lines[first_line - 1].ends_with(first), "{:?}", block);
+        }
+    }
+
+    #[test]
+    fn test_code_blocks_with_options() {
+        let input = "\
+<details>
+<summary>Example</summary>
+~~~~ rust
+fn a() {}
+~~~~
+</details>
+
+::: note
+```sh
+b
+```
+:::
+";
+        let lines = |options: &Options| -> Vec<_> {
+            code_blocks(input, options)
+                .into_iter()
+                .map(|block| {
+                    (
+                        block.language().to_string(),
+                        block.start_line,
+                        block.end_line,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            lines(&Options::default()),
+            [("rust".to_string(), 3, 5), ("sh".to_string(), 9, 11)]
+        );
+        let options = Options {
+            containers: true,
+            html_details: DetailsHandling::Preserve,
+            ..Options::default()
+        };
+        assert_eq!(lines(&options), [("sh".to_string(), 9, 11)]);
+    }
+
+    #[test]
+    fn test_stats_mixed_languages() {
+        let stats = stats(MIXED_LANGUAGE_DOCUMENT, &Options::default());
+        assert_eq!(
+            stats,
+            DocStats {
+                // 21 words, and 36 CJK characters
+                words: 57,
+                headings: [1, 1, 1, 0, 0, 0],
+                links: 1,
+                images: 1,
+                code_blocks: 1,
+                tables: 1,
+                reading_minutes: 1,
+                // The first paragraph is rewrapped, so the longest line is
+                // its second
+                longest_line: 61,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stats_reading_time() {
+        let options = Options::default();
+        assert_eq!(stats("", &options), DocStats::default());
+        assert_eq!(stats("word\n", &options).reading_minutes, 1);
+        let prose = "word ".repeat(401);
+        assert_eq!(stats(&prose, &options).reading_minutes, 3);
+        let prose = "글".repeat(1000);
+        assert_eq!(stats(&prose, &options).reading_minutes, 2);
+        // Code isn't read as prose
+        let code = format!("~~~~\n{}\n~~~~\n", "word ".repeat(401));
+        assert_eq!(stats(&code, &options).reading_minutes, 0);
+    }
+
     #[test]
     fn test_format_plain_text() {
         let input = "Hello, world!";
@@ -307,6 +1449,259 @@ mod tests {
         assert_eq!(result, "Hello, world!\n");
     }
 
+    #[test]
+    fn test_format_with_protected_ranges() {
+        let options = Options {
+            line_width: LineWidth::new(40).unwrap(),
+            ..Options::default()
+        };
+        let table = "|Version|Released|\n|---|:-:|\n|0.4.0|*soon*|\n|0.3.1|  2025  |\n\n\n";
+        let input = format!(
+            "# Versions\n\nThe versions released so far, with the dates they were released on:\n\n\
+             {}Versions before 0.3 are no longer supported, so upgrade.\n",
+            table
+        );
+        let start = input.find(table).unwrap();
+        // Part of the table is enough to protect all of it
+        let range = start + 10..start + 20;
+        let output = format_with_protected_ranges(&input, &[range], &options).unwrap();
+        assert_eq!(
+            output,
+            "Versions\n========\n\n\
+             The versions released so far, with the\ndates they were released on:\n\n\
+             |Version|Released|\n|---|:-:|\n|0.4.0|*soon*|\n|0.3.1|  2025  |\n\n\
+             Versions before 0.3 are no longer\nsupported, so upgrade.\n"
+        );
+    }
+
+    #[test]
+    fn test_format_with_protected_ranges_runs_of_blocks() {
+        let input = "# Title\n\n*a*  \n\n\n*b*\n\n## End\n";
+        let range = input.find('*').unwrap()..input.find("*b*").unwrap() + 1;
+        let output = format_with_protected_ranges(input, &[range], &Options::default()).unwrap();
+        assert_eq!(output, "Title\n=====\n\n*a*  \n\n\n*b*\n\n\nEnd\n---\n");
+    }
+
+    #[test]
+    fn test_format_with_protected_ranges_invalid() {
+        let options = Options::default();
+        let past_end = 0..7;
+        let error = format_with_protected_ranges("Text.\n", &[past_end], &options).unwrap_err();
+        assert!(matches!(error, FormatError::InvalidRange(_)));
+        assert_eq!(
+            error.to_string(),
+            "Invalid range: range 0..7 is out of bounds of the input, which is 6 bytes long"
+        );
+        let error = format_with_protected_ranges("Text.\n", &[0..3, 2..4], &options).unwrap_err();
+        assert!(matches!(error, FormatError::InvalidRange(_)));
+    }
+
+    #[test]
+    fn test_format_table() {
+        let options = Options::default();
+        let input = "Name|Count|Note\n:--|--:|:-:\nHongdown|1|ok\nx|200|\n";
+        assert_eq!(
+            format_table(input, &options).unwrap(),
+            "| Name     | Count | Note |\n\
+             | :------- | ----: | :--: |\n\
+             | Hongdown |     1 |  ok  |\n\
+             | x        |   200 |      |\n"
+        );
+    }
+
+    #[test]
+    fn test_format_list_and_code_block() {
+        let options = Options::default();
+        assert_eq!(
+            format_list("* one\n* two\n    1. nested\n", &options).unwrap(),
+            " -  one\n -  two\n    1)  nested\n"
+        );
+        assert_eq!(
+            format_code_block("```rust\nfn main() {}\n```\n", &options).unwrap(),
+            "~~~~ rust\nfn main() {}\n~~~~\n"
+        );
+        assert_eq!(
+            format_code_block("    indented\n", &options).unwrap(),
+            "~~~~\nindented\n~~~~\n"
+        );
+    }
+
+    #[test]
+    fn test_format_single_block_same_as_format() {
+        let options = Options::default();
+        for input in [
+            "* An item with an inline footnote.^[With *a* note.]\n* Another.\n",
+            "| a | b^[note] |\n|---|---|\n| 1 | 2 |\n",
+        ] {
+            let expected = format(input, &options).unwrap();
+            let output = match input.starts_with('|') {
+                true => format_table(input, &options),
+                false => format_list(input, &options),
+            };
+            assert_eq!(output.unwrap(), expected);
+        }
+        let options = Options {
+            footnote_inline: InlineFootnotes::Expand,
+            ..Options::default()
+        };
+        let input = "1. One.^[A note.]\n2. Two.\n";
+        assert_eq!(
+            format_list(input, &options).unwrap(),
+            format(input, &options).unwrap()
+        );
+    }
+
+    /// Rewrite the destinations that end in `.md` to end in `.html`.
+    fn md_to_html(link: &LinkRef) -> Option<String> {
+        link.destination
+            .strip_suffix(".md")
+            .map(|stem| format!("{}.html", stem))
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let input = "\
+Read [the guide](guide.md \"Guide\"), [the API][api], [the API][API]
+again, and [api][].  ![Flow](flow.md) ![Logo][logo] [Site](https://example.com/)
+
+[api]: reference/api.md
+[logo]: img/logo.md
+";
+        let mut links = Vec::new();
+        let output = rewrite_links(input, &Options::default(), |link| {
+            let rewritten = md_to_html(&link);
+            links.push(link);
+            rewritten
+        })
+        .unwrap();
+        assert_eq!(
+            output,
+            "\
+Read [the guide](guide.html \"Guide\"), [the API][api], [the API][api]
+again, and [api].  ![Flow](flow.html) ![Logo] [Site]
+
+[api]: reference/api.html
+[logo]: img/logo.html
+[site]: https://example.com/
+"
+        );
+        let kinds: Vec<_> = links
+            .iter()
+            .map(|link| (link.destination.as_str(), link.kind))
+            .collect();
+        // The three links to [api] share one definition, rewritten once
+        assert_eq!(
+            kinds,
+            [
+                ("guide.md", LinkKind::Inline),
+                ("reference/api.md", LinkKind::Reference),
+                ("flow.md", LinkKind::Image),
+                ("img/logo.md", LinkKind::Image),
+                ("https://example.com/", LinkKind::Inline),
+            ]
+        );
+        assert_eq!(links[0].title, "Guide");
+        assert_eq!(
+            (links[1].start_line, links[1].start_column),
+            (1, 37),
+            "the first link to [api]"
+        );
+        assert_eq!((links[2].start_line, links[2].end_column), (2, 37));
+    }
+
+    #[test]
+    fn test_rewrite_links_autolinks() {
+        let input = "<https://example.com/a.md> and <https://example.com/b.md>\n";
+        let output = rewrite_links(input, &Options::default(), |link| {
+            assert_eq!(link.kind, LinkKind::Autolink);
+            match link.destination.as_str() {
+                "https://example.com/a.md" => md_to_html(&link),
+                _ => Some("b.html".to_string()),
+            }
+        })
+        .unwrap();
+        // A destination that can't be an autolink makes an inline link
+        assert_eq!(
+            output,
+            "<https://example.com/a.html> and [https://example.com/b.md](b.html)\n"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_links_unchanged() {
+        let input = "A [link](a.md) and [another][b].\n\n[b]: b.md\n";
+        let options = Options::default();
+        let output = rewrite_links(input, &options, |_| None).unwrap();
+        assert_eq!(output, format(input, &options).unwrap());
+        assert_eq!(rewrite_links("", &options, |_| None).unwrap(), "");
+    }
+
+    #[test]
+    fn test_format_single_block_wrong_kind() {
+        let options = Options::default();
+        let error = format_table("Just a paragraph.\n", &options).unwrap_err();
+        assert!(matches!(error, FormatError::UnexpectedBlock(_)));
+        assert_eq!(
+            error.to_string(),
+            "Unexpected block: expected a single table, but found a paragraph"
+        );
+        let error = format_list("~~~~\ncode\n~~~~\n", &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected block: expected a single list, but found a code block"
+        );
+        let error = format_code_block("```\na\n```\n\n```\nb\n```\n", &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected block: expected a single code block, but found 2 blocks"
+        );
+        let error = format_table("", &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected block: expected a single table, but found nothing"
+        );
+    }
+
+    #[test]
+    fn test_slugs_strip_markup() {
+        let input = "Title\n=====\n\n## The `format()` *function*\n\n### [Linked](x.md) **text**\n";
+        let slugs: Vec<_> = slugs(input, &Options::default())
+            .into_iter()
+            .map(|(_, _, slug)| slug)
+            .collect();
+        assert_eq!(slugs, vec!["title", "the-format-function", "linked-text"]);
+    }
+
+    #[test]
+    fn test_slugs_unicode_and_duplicates() {
+        let input = "## 설치\n\n## Über uns\n\n## 설치\n";
+        assert_eq!(
+            slugs(input, &Options::default()),
+            vec![
+                (2, "설치".to_string(), "설치".to_string()),
+                (2, "Über uns".to_string(), "über-uns".to_string()),
+                (2, "설치".to_string(), "설치-1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slugs_with_options() {
+        let input = "## Intro\n\n## The $a*b*c$ sum\n";
+        let options = Options {
+            math: true,
+            heading_numbering: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            slugs(input, &options),
+            vec![
+                (2, "1 Intro".to_string(), "1-intro".to_string()),
+                (2, "2 The a*b*c sum".to_string(), "2-the--sum".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_options_default_punctuation() {
         let options = Options::default();
@@ -320,4 +1715,53 @@ mod tests {
             DashSetting::Pattern(DashPattern::new("--".to_string()).unwrap())
         );
     }
+
+    #[test]
+    fn test_format_line_ending_crlf() {
+        let options = Options {
+            line_ending: LineEnding::Crlf,
+            ..Options::default()
+        };
+        let input = "Hello\nworld.\n\n```\ncode\n```\n";
+        let result = format(input, &options).unwrap();
+        assert_eq!(result, "Hello\r\nworld.\r\n\r\n~~~~\r\ncode\r\n~~~~\r\n");
+    }
+
+    #[test]
+    fn test_format_line_ending_auto() {
+        let options = Options {
+            line_ending: LineEnding::Auto,
+            ..Options::default()
+        };
+        let result = format("Hello\r\nworld.\r\n\r\n~~~~\r\ncode\r\n~~~~\r\n", &options).unwrap();
+        assert!(!result.contains("\r\r"));
+        assert_eq!(result, "Hello\r\nworld.\r\n\r\n~~~~\r\ncode\r\n~~~~\r\n");
+        let result = format("Hello\nworld.\n", &options).unwrap();
+        assert_eq!(result, "Hello\nworld.\n");
+    }
+
+    #[test]
+    fn test_comrak_options() {
+        let options = comrak_options(&config::Config::default());
+        assert!(options.extension.table);
+        assert!(options.extension.footnotes);
+        assert!(options.extension.description_lists);
+        assert!(options.extension.alerts);
+        assert!(options.extension.tasklist);
+        assert_eq!(
+            options.extension.front_matter_delimiter.as_deref(),
+            Some("---")
+        );
+        assert!(!options.extension.strikethrough);
+        assert!(!options.extension.math_dollars);
+        assert!(!options.extension.multiline_block_quotes);
+
+        let config =
+            config::Config::from_toml("dialect = \"commonmark\"\nmath = true\ncontainers = true")
+                .unwrap();
+        let options = comrak_options(&config);
+        assert!(options.extension.strikethrough);
+        assert!(options.extension.math_dollars);
+        assert!(options.extension.multiline_block_quotes);
+    }
 }
@@ -0,0 +1,508 @@
+//! Checks that go beyond formatting.
+//!
+//! Formatting never depends on these, and the command-line interface only
+//! runs them when asked to (`--check-links`, `lint.links = true`,
+//! `lint.invisible_characters = true`, `lint.headings = true`, or
+//! `lint.code_language = true`).
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use comrak::nodes::NodeValue;
+use comrak::{Arena, parse_document};
+use regex::Regex;
+
+use crate::report::{Diagnostic, Severity};
+use crate::serializer::{Serializer, limits};
+
+/// The rules that `hongdown-disable` and `hongdown-enable` directives can
+/// name, with the IDs of the markdownlint rules they correspond to, if any,
+/// which they can be named by as well.
+pub(crate) const RULES: &[(&str, Option<&str>)] = &[
+    ("heading-increment", Some("MD001")),
+    ("line-length", Some("MD013")),
+    ("no-duplicate-heading", Some("MD024")),
+    ("single-h1", Some("MD025")),
+    ("no-trailing-punctuation", Some("MD026")),
+    ("no-empty-links", Some("MD042")),
+    ("code-language", Some("MD040")),
+    ("no-empty-heading", None),
+];
+
+/// The name of the rule in [`RULES`] that `name` names, either by its name
+/// or by its markdownlint ID, ignoring case.
+pub(crate) fn rule_name(name: &str) -> Option<&'static str> {
+    RULES
+        .iter()
+        .find(|(rule, id)| {
+            rule.eq_ignore_ascii_case(name) || id.is_some_and(|id| id.eq_ignore_ascii_case(name))
+        })
+        .map(|(rule, _)| *rule)
+}
+
+/// A URL scheme, like `https:` or `mailto:`.
+static URL_SCHEME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9+.\-]*:").unwrap());
+
+/// Checks the links in a Markdown document.
+///
+/// The following are reported, each with the line and column where the link
+/// starts:
+///
+///  -  Links to fragments of the document, like `[usage](#usage)`, that
+///     match no heading anchor and no HTML `id` or `name`.
+///  -  Reference links, like `[text][label]`, without a matching definition.
+///  -  Relative links and images whose target doesn't exist, resolved
+///     against `base_dir`.  These are only checked if `base_dir` is given.
+///
+/// URLs with a scheme, like `https://example.com/` or `mailto:`, are not
+/// checked, and neither are absolute paths, since what they point to depends
+/// on where the document is published.  Regions where formatting is
+/// disabled by a directive are skipped as well.
+///
+/// # Example
+///
+/// ```
+/// let diagnostics = hongdown::lint::check_links("See [usage](#usage).\n", None);
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].line, Some(1));
+/// assert_eq!(diagnostics[0].column, Some(5));
+/// assert_eq!(diagnostics[0].message, "undefined link fragment: #usage");
+/// ```
+pub fn check_links(input: &str, base_dir: Option<&Path>) -> Vec<Diagnostic> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &crate::base_comrak_options());
+    // Blocks nested too deeply to format are left alone here too
+    limits::flatten_deep_blocks(root, input);
+    let source_lines: Vec<&str> = input.lines().collect();
+
+    let mut problems = Serializer::undefined_fragments(root);
+    problems.extend(Serializer::undefined_references(root, &source_lines));
+
+    if let Some(base_dir) = base_dir {
+        let disabled_ranges = Serializer::collect_disabled_line_ranges(root);
+        for node in root.descendants() {
+            let data = node.data.borrow();
+            let url = match &data.value {
+                NodeValue::Link(link) | NodeValue::Image(link) => &link.url,
+                _ => continue,
+            };
+            let Some(path) = relative_path(url) else {
+                continue;
+            };
+            let start = data.sourcepos.start;
+            if !base_dir.join(&path).exists()
+                && !Serializer::is_line_in_disabled_ranges(start.line, &disabled_ranges)
+            {
+                let message = format!("broken relative link: {}", path);
+                problems.push((start.line, start.column, message));
+            }
+        }
+    }
+
+    problems.sort_by_key(|(line, column, _)| (*line, *column));
+    problems
+        .into_iter()
+        .map(|(line, column, message)| Diagnostic {
+            severity: Severity::Warning,
+            line: Some(line),
+            column: Some(column),
+            message,
+        })
+        .collect()
+}
+
+/// Zero-width and bidirectional formatting characters, with their names.
+///
+/// The zero-width joiner and non-joiner are left out, since emoji sequences
+/// and scripts like Persian and Devanagari need them.
+const INVISIBLE_CHARACTERS: &[(char, &str)] = &[
+    ('\u{061C}', "ARABIC LETTER MARK"),
+    ('\u{200B}', "ZERO WIDTH SPACE"),
+    ('\u{200E}', "LEFT-TO-RIGHT MARK"),
+    ('\u{200F}', "RIGHT-TO-LEFT MARK"),
+    ('\u{202A}', "LEFT-TO-RIGHT EMBEDDING"),
+    ('\u{202B}', "RIGHT-TO-LEFT EMBEDDING"),
+    ('\u{202C}', "POP DIRECTIONAL FORMATTING"),
+    ('\u{202D}', "LEFT-TO-RIGHT OVERRIDE"),
+    ('\u{202E}', "RIGHT-TO-LEFT OVERRIDE"),
+    ('\u{2060}', "WORD JOINER"),
+    ('\u{2066}', "LEFT-TO-RIGHT ISOLATE"),
+    ('\u{2067}', "RIGHT-TO-LEFT ISOLATE"),
+    ('\u{2068}', "FIRST STRONG ISOLATE"),
+    ('\u{2069}', "POP DIRECTIONAL ISOLATE"),
+    ('\u{FEFF}', "ZERO WIDTH NO-BREAK SPACE"),
+];
+
+/// Finds the zero-width and bidirectional formatting characters in
+/// a Markdown document, like the right-to-left mark (U+200F) and the zero
+/// width space (U+200B).
+///
+/// These can't be seen, and can make text, including code, read differently
+/// from how it looks, so each one is reported with its line and column,
+/// code blocks included.  A byte order mark at the start of the document is
+/// not reported.  Formatting keeps all of them as they are.
+///
+/// # Example
+///
+/// ```
+/// let diagnostics = hongdown::lint::check_invisible_characters("a\u{200F}b\n");
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].column, Some(2));
+/// assert_eq!(
+///     diagnostics[0].message,
+///     "invisible character U+200F RIGHT-TO-LEFT MARK"
+/// );
+/// ```
+pub fn check_invisible_characters(input: &str) -> Vec<Diagnostic> {
+    let input = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+    let mut diagnostics = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        for (offset, c) in line.char_indices() {
+            let Some((_, name)) = INVISIBLE_CHARACTERS.iter().find(|(ch, _)| *ch == c) else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                line: Some(i + 1),
+                column: Some(offset + 1),
+                message: format!("invisible character U+{:04X} {}", c as u32, name),
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Checks the structure of the headings in a Markdown document.
+///
+/// The following are reported, each with the line and column where the
+/// heading starts and the name of the [markdownlint] rule it follows at the
+/// end of the message:
+///
+///  -  A heading more than one level deeper than the one before it, like
+///     a level 3 heading right after a level 1 one (`heading-increment`).
+///  -  A level 1 heading other than the first one (`single-h1`).
+///  -  A heading with the same text as an earlier one at the same level,
+///     whose anchor only differs from the earlier one's by a number
+///     (`no-duplicate-heading`).
+///  -  A heading with no text (`no-empty-heading`).
+///
+/// Headings in regions where formatting is disabled by a directive are
+/// skipped, and so are the problems with rules disabled by
+/// a `hongdown-disable RULE...` directive.
+///
+/// [markdownlint]: https://github.com/DavidAnson/markdownlint
+///
+/// # Example
+///
+/// ```
+/// let diagnostics = hongdown::lint::check_headings("# Title\n\n### Usage\n");
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].line, Some(3));
+/// assert_eq!(
+///     diagnostics[0].message,
+///     "heading level jumps from 1 to 3 (heading-increment)"
+/// );
+/// ```
+pub fn check_headings(input: &str) -> Vec<Diagnostic> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &crate::base_comrak_options());
+    // Blocks nested too deeply to format are left alone here too
+    limits::flatten_deep_blocks(root, input);
+    let disabled_ranges = Serializer::collect_disabled_line_ranges(root);
+    let rule_ranges = Serializer::collect_rule_ranges(root);
+
+    let mut diagnostics = Vec::new();
+    let mut previous_level = None;
+    let mut seen_h1 = false;
+    let mut seen = HashSet::new();
+    for node in root.descendants() {
+        let data = node.data.borrow();
+        let NodeValue::Heading(heading) = &data.value else {
+            continue;
+        };
+        let start = data.sourcepos.start;
+        if Serializer::is_line_in_disabled_ranges(start.line, &disabled_ranges) {
+            continue;
+        }
+        let level = heading.level;
+        let mut report = |rule: &str, message: String| {
+            if !Serializer::is_rule_disabled(rule, start.line, &rule_ranges) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    line: Some(start.line),
+                    column: Some(start.column),
+                    message: format!("{} ({})", message, rule),
+                });
+            }
+        };
+
+        if let Some(previous) = previous_level
+            && level > previous + 1
+        {
+            report(
+                "heading-increment",
+                format!("heading level jumps from {} to {}", previous, level),
+            );
+        }
+        previous_level = Some(level);
+
+        if level == 1 {
+            if seen_h1 {
+                report("single-h1", "more than one level 1 heading".into());
+            }
+            seen_h1 = true;
+        }
+
+        let text = crate::plain_text_of(node);
+        let text = text.trim();
+        if node.first_child().is_none() {
+            report("no-empty-heading", "empty heading".into());
+        } else if !seen.insert((level, text.to_string())) {
+            report(
+                "no-duplicate-heading",
+                format!("duplicate level {} heading: {}", level, text),
+            );
+        }
+    }
+    diagnostics
+}
+
+/// Checks that the code blocks in a Markdown document have a language.
+///
+/// A code block whose language, the first word of its info string, is empty
+/// is reported with the line and column where it starts and the name of
+/// the rule, `code-language`, at the end of the message.  So is an indented
+/// code block, since it is written as a fenced one without a language.
+/// A language in `exceptions` is accepted, so an empty string there turns
+/// the check off, as for a directory of notes.  Code blocks in regions where
+/// formatting is disabled by a directive are skipped, and so are those
+/// where the rule is disabled by a `hongdown-disable code-language`
+/// directive.
+///
+/// # Example
+///
+/// ```
+/// let diagnostics = hongdown::lint::check_code_languages("~~~~\nx\n~~~~\n", &[]);
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].line, Some(1));
+/// assert_eq!(
+///     diagnostics[0].message,
+///     "code block has no language (code-language)"
+/// );
+/// ```
+pub fn check_code_languages(input: &str, exceptions: &[String]) -> Vec<Diagnostic> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &crate::base_comrak_options());
+    // Blocks nested too deeply to format are left alone here too
+    limits::flatten_deep_blocks(root, input);
+    let disabled_ranges = Serializer::collect_disabled_line_ranges(root);
+    let rule_ranges = Serializer::collect_rule_ranges(root);
+    let mut diagnostics = Vec::new();
+    for node in root.descendants() {
+        let data = node.data.borrow();
+        let NodeValue::CodeBlock(code) = &data.value else {
+            continue;
+        };
+        let language = code.info.split_whitespace().next().unwrap_or("");
+        let start = data.sourcepos.start;
+        if !language.is_empty()
+            || exceptions.iter().any(|exception| exception == language)
+            || Serializer::is_line_in_disabled_ranges(start.line, &disabled_ranges)
+            || Serializer::is_rule_disabled("code-language", start.line, &rule_ranges)
+        {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            line: Some(start.line),
+            column: Some(start.column),
+            message: "code block has no language (code-language)".into(),
+        });
+    }
+    diagnostics
+}
+
+/// The file path a relative link points to, without its query and fragment
+/// and with percent-encoded bytes decoded.
+///
+/// Returns `None` for a link that is not relative to the document: one with
+/// a URL scheme, an absolute or protocol-relative path, or only a fragment.
+fn relative_path(url: &str) -> Option<String> {
+    if url.starts_with('/') || URL_SCHEME.is_match(url) {
+        return None;
+    }
+    let path = url.split(['#', '?']).next().unwrap_or_default();
+    if path.is_empty() {
+        return None;
+    }
+    Some(crate::slug::decode_fragment(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path("docs/usage.md"), Some("docs/usage.md".into()));
+        assert_eq!(
+            relative_path("../README.md#install"),
+            Some("../README.md".into())
+        );
+        assert_eq!(relative_path("a%20b.md?raw=1"), Some("a b.md".into()));
+        assert_eq!(relative_path("#usage"), None);
+        assert_eq!(relative_path("https://example.com/x.md"), None);
+        assert_eq!(relative_path("mailto:hong@minhee.org"), None);
+        assert_eq!(relative_path("/docs/usage.md"), None);
+        assert_eq!(relative_path("//example.com/x.md"), None);
+    }
+
+    #[test]
+    fn test_check_invisible_characters() {
+        let input =
+            "\u{FEFF}# Title\n\nשלום\u{200F} and a\u{200B}b\n\n~~~~\nx = \"\u{202E}\"\n~~~~\n";
+        let found: Vec<_> = check_invisible_characters(input)
+            .into_iter()
+            .map(|d| (d.line.unwrap(), d.column.unwrap(), d.message))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (3, 9, "invisible character U+200F RIGHT-TO-LEFT MARK".into()),
+                (3, 18, "invisible character U+200B ZERO WIDTH SPACE".into()),
+                (
+                    6,
+                    6,
+                    "invisible character U+202E RIGHT-TO-LEFT OVERRIDE".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_invisible_characters_keeps_joiners() {
+        assert!(check_invisible_characters("👩\u{200D}💻 می\u{200C}خواهم\n").is_empty());
+    }
+
+    fn heading_problems(input: &str) -> Vec<(usize, String)> {
+        check_headings(input)
+            .into_iter()
+            .map(|d| (d.line.unwrap(), d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_headings_valid() {
+        let input = "Title\n=====\n\n## Usage\n\n### Options\n\n## Install\n\n### Steps\n";
+        assert!(heading_problems(input).is_empty());
+    }
+
+    #[test]
+    fn test_check_headings_increment() {
+        assert_eq!(
+            heading_problems("## Start\n\n#### Deep\n\n##### Deeper\n\n# Top\n"),
+            [(
+                3,
+                "heading level jumps from 2 to 4 (heading-increment)".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_check_headings_single_h1() {
+        assert_eq!(
+            heading_problems("# One\n\nTwo\n===\n"),
+            [(3, "more than one level 1 heading (single-h1)".into())]
+        );
+    }
+
+    #[test]
+    fn test_check_headings_duplicate() {
+        assert_eq!(
+            heading_problems("# Title\n\n## *Usage*\n\n## Usage\n"),
+            [(
+                5,
+                "duplicate level 2 heading: Usage (no-duplicate-heading)".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_check_headings_empty() {
+        assert_eq!(
+            heading_problems("# Title\n\n##\n\n## ![Logo](logo.png)\n"),
+            [(3, "empty heading (no-empty-heading)".into())]
+        );
+    }
+
+    #[test]
+    fn test_check_headings_disabled_rule() {
+        let input = "# Title\n\n<!-- hongdown-disable heading-increment -->\n\n### Usage\n\n\
+                     <!-- hongdown-enable -->\n\n#### Options\n\n# Another\n";
+        assert_eq!(
+            heading_problems(input),
+            [(11, "more than one level 1 heading (single-h1)".into())]
+        );
+        let input = "<!-- hongdown-disable-file MD025 -->\n\n# One\n\n# Two\n";
+        assert!(heading_problems(input).is_empty());
+    }
+
+    #[test]
+    fn test_rule_name() {
+        assert_eq!(rule_name("line-length"), Some("line-length"));
+        assert_eq!(rule_name("MD013"), Some("line-length"));
+        assert_eq!(rule_name("md025"), Some("single-h1"));
+        assert_eq!(rule_name("no-empty-heading"), Some("no-empty-heading"));
+        assert_eq!(rule_name("MD999"), None);
+    }
+
+    #[test]
+    fn test_check_headings_skips_disabled_regions() {
+        let input =
+            "# Title\n\n<!-- hongdown-disable -->\n\n# Another\n\n<!-- hongdown-enable -->\n";
+        assert!(heading_problems(input).is_empty());
+    }
+
+    fn code_language_problems(input: &str, exceptions: &[&str]) -> Vec<(usize, usize)> {
+        let exceptions: Vec<String> = exceptions.iter().map(|e| e.to_string()).collect();
+        check_code_languages(input, &exceptions)
+            .into_iter()
+            .map(|d| (d.line.unwrap(), d.column.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_code_languages() {
+        let input = "~~~~ rust\nfn main() {}\n~~~~\n\n```\nplain\n```\n\n    indented\n\n\
+                     -  Item\n\n    ~~~\n    nested\n    ~~~\n";
+        assert_eq!(
+            code_language_problems(input, &[]),
+            [(5, 1), (9, 5), (13, 5)]
+        );
+        assert!(code_language_problems("~~~~ text\nplain\n~~~~\n", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_check_code_languages_exceptions() {
+        let input = "~~~~\nplain\n~~~~\n";
+        assert_eq!(code_language_problems(input, &["text"]), [(1, 1)]);
+        assert!(code_language_problems(input, &["text", ""]).is_empty());
+    }
+
+    #[test]
+    fn test_check_code_languages_disabled() {
+        let input = "<!-- hongdown-disable code-language -->\n\n~~~~\nplain\n~~~~\n\n\
+                     <!-- hongdown-enable -->\n\n~~~~\nplain\n~~~~\n";
+        assert_eq!(code_language_problems(input, &[]), [(9, 1)]);
+        let input = "<!-- hongdown-disable-file MD040 -->\n\n~~~~\nplain\n~~~~\n";
+        assert!(code_language_problems(input, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_check_links_without_base_dir_skips_files() {
+        let diagnostics = check_links("[missing](missing.md)\n", None);
+        assert!(diagnostics.is_empty());
+    }
+}
@@ -4,17 +4,34 @@ use std::fs;
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use clap::Parser;
-use hongdown::config::Config;
-use hongdown::{CodeFormatter, LineWidth, Options, format_with_warnings, validate_dash_settings};
+use hongdown::config::{CONFIG_FILE_NAME, Config, DiscoveryOptions, IgnoreFiles, PartialConfig};
+use hongdown::report::{
+    Diagnostic, FileReport, FileStats, FileStatus, Report, Severity, StatsReport,
+};
+use hongdown::{
+    CodeBlockRef, CodeFormatter, DocStats, FenceChar, FixRule, FormatSession, LineEnding,
+    LineWidth, Options, UnorderedMarker, Warning, WrapMode, validate_dash_settings,
+};
 use rayon::prelude::*;
-use similar::{ChangeTag, TextDiff};
 use walkdir::WalkDir;
 
+mod cli;
+
+use cli::atomic::{Written, write_atomic};
+use cli::cache::{Cache, DEFAULT_CACHE_FILE};
+use cli::diff::{CheckResult, ColorChoice};
+use cli::exit;
+use cli::migrate::MigrateFrom;
+use cli::progress::{Reporter, Verbosity};
+use cli::trace;
+use cli::watch::WatchState;
+use cli::{FileOptions, decode_input, is_markdown_path, read_input};
+
 /// A Markdown formatter that enforces Hong Minhee's Markdown style conventions.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "hongdown")]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -30,110 +47,401 @@ struct Args {
     #[arg(short, long, conflicts_with_all = ["write", "diff"])]
     check: bool,
 
-    /// Show a diff of formatting changes.
+    /// Show a unified diff of formatting changes (exit 1 if any).
     #[arg(short, long, conflicts_with_all = ["write", "check"])]
     diff: bool,
 
+    /// Print only the paths of files whose formatting would change, one per
+    /// line (exit 1 if any).
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["write", "check", "diff", "watch", "stdin", "stdin_filepath"]
+    )]
+    list_different: bool,
+
+    /// Print statistics of each file, like its word count and the width of
+    /// its longest line once formatted, instead of formatting it.
+    #[arg(
+        long,
+        conflicts_with_all = ["write", "check", "diff", "list_different", "watch"]
+    )]
+    stats: bool,
+
+    /// Print the file, lines, and language of each code block, separated by
+    /// tabs, instead of formatting the files.
+    #[arg(
+        long,
+        conflicts_with_all = ["write", "check", "diff", "list_different", "watch", "stats"]
+    )]
+    list_code_blocks: bool,
+
+    /// List only the code blocks in this language (with
+    /// --list-code-blocks).
+    #[arg(long, value_name = "LANG", requires = "list_code_blocks")]
+    lang: Option<String>,
+
+    /// Also write the content of each code block listed to a file of its own
+    /// in DIR, whose path ends its line (with --list-code-blocks).
+    #[arg(long, value_name = "DIR", requires = "list_code_blocks")]
+    code_block_dir: Option<PathBuf>,
+
+    /// When to colorize --diff output.
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Watch the given files and directories (default: the project root),
+    /// reformatting Markdown files in place whenever they change.
+    #[arg(long, conflicts_with_all = ["check", "diff", "stdin", "report"])]
+    watch: bool,
+
     /// Read input from stdin (alternative to using `-` as filename).
     #[arg(long)]
     stdin: bool,
 
+    /// Path that stdin content is assumed to come from.  Implies --stdin.
+    /// Configuration is discovered as if the content lived at this path,
+    /// which is also used to label warnings.  The file itself is never read.
+    #[arg(long, value_name = "PATH")]
+    stdin_filepath: Option<PathBuf>,
+
+    /// Read the paths of files to format from stdin, one per line.
+    #[arg(long, conflicts_with_all = ["stdin", "stdin_filepath", "watch"])]
+    stdin_filelist: bool,
+
     /// Line width for wrapping (overrides config file).
+    #[arg(long, value_name = "N", value_parser = PartialConfig::parse_value::<LineWidth>)]
+    line_width: Option<LineWidth>,
+
+    /// How paragraphs are wrapped: `width`, `none`, `preserve`, or
+    /// `sentence` (overrides config file).
+    #[arg(long, value_name = "MODE", value_parser = PartialConfig::parse_value::<WrapMode>)]
+    wrap: Option<WrapMode>,
+
+    /// Marker for unordered list items: `-`, `*`, or `+` (overrides config
+    /// file).
+    #[arg(
+        long,
+        value_name = "CHAR",
+        value_parser = PartialConfig::parse_value::<UnorderedMarker>
+    )]
+    unordered_marker: Option<UnorderedMarker>,
+
+    /// Fence character for code blocks: `~` or `` ` `` (overrides config
+    /// file).
+    #[arg(long, value_name = "CHAR", value_parser = PartialConfig::parse_value::<FenceChar>)]
+    fence_char: Option<FenceChar>,
+
+    /// Use ATX-style (`#`) headings for all levels (overrides config file).
+    #[arg(long)]
+    no_setext: bool,
+
+    /// Don't run the external formatters configured for code blocks in
+    /// `[code_block.formatters]`, leaving their code as it is.
+    #[arg(long)]
+    no_embedded_formatters: bool,
+
+    /// Line ending style: `lf`, `crlf`, or `auto` (overrides config file).
+    #[arg(
+        long,
+        value_name = "STYLE",
+        value_parser = PartialConfig::parse_value::<LineEnding>
+    )]
+    line_ending: Option<LineEnding>,
+
+    /// Also report links to missing fragments of the document, relative
+    /// links to missing files, and reference links without a definition
+    /// (overrides config file).
+    #[arg(long)]
+    check_links: bool,
+
+    /// Exit with status 1 if any warnings are reported.
     #[arg(long)]
-    line_width: Option<usize>,
+    deny_warnings: bool,
 
-    /// Path to configuration file.
-    #[arg(long, value_name = "FILE")]
+    /// Log what Hongdown does at LEVEL (`error`, `warn`, `info`, `debug`,
+    /// or `trace`) or above to stderr, like where paragraphs are wrapped
+    /// and which configuration file each option comes from.
+    #[cfg(feature = "trace")]
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<tracing::Level>,
+
+    /// Give up on a file that takes longer than SECONDS to format,
+    /// reporting it as an error instead of waiting for it.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Print the effective configuration for PATH (default: the current
+    /// directory) as TOML, after applying all configuration files,
+    /// environment variables, and command-line options, then exit.
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = "."
+    )]
+    print_config: Option<PathBuf>,
+
+    /// Translate the configuration of Prettier or markdownlint, found in the
+    /// current directory or the nearest of its ancestors, into a
+    /// `.hongdown.toml` next to it, reporting the options that couldn't be
+    /// translated, then exit.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FROM",
+        conflicts_with_all = ["write", "check", "diff", "stats", "watch", "list_code_blocks"]
+    )]
+    migrate_config: Option<MigrateFrom>,
+
+    /// Path to configuration file, or to a directory containing
+    /// `.hongdown.toml`.  Disables configuration discovery.
+    #[arg(long, value_name = "PATH")]
     config: Option<PathBuf>,
+
+    /// Ignore all configuration files and `HONGDOWN_*` environment
+    /// variables, using the defaults plus any command-line options.
+    #[arg(long, conflicts_with = "config")]
+    no_config: bool,
+
+    /// Ignore the user configuration files, `~/.hongdown.toml` and
+    /// `$XDG_CONFIG_HOME/hongdown/config.toml`, while still discovering
+    /// the project's.
+    #[arg(long, conflicts_with_all = ["config", "no_config"])]
+    no_user_config: bool,
+
+    /// Skip files that are unchanged since they were last formatted
+    /// (in --write and --check modes).  The cache is stored in FILE,
+    /// which defaults to `.hongdown-cache.json`.
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_CACHE_FILE
+    )]
+    cache: Option<PathBuf>,
+
+    /// Print a machine-readable report of the run to stdout (with --write,
+    /// --check, or --stats).  Human-readable messages go to stderr.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    report: Option<ReportFormat>,
+
+    /// Delete the cache file before running.
+    #[arg(long)]
+    cache_clear: bool,
+
+    /// Report only errors.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Report every file, including those that are already formatted.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// What is reported about each file besides its formatting.
+#[derive(Debug, Clone, Copy)]
+struct Checks<'a> {
+    /// Check links with [`hongdown::lint::check_links`].
+    links: bool,
+    /// Report invisible characters with
+    /// [`hongdown::lint::check_invisible_characters`].
+    invisible_characters: bool,
+    /// Check headings with [`hongdown::lint::check_headings`].
+    headings: bool,
+    /// Check the languages of code blocks with
+    /// [`hongdown::lint::check_code_languages`], accepting these.
+    code_language: Option<&'a [String]>,
+    /// Fail if any warnings are reported.
+    deny_warnings: bool,
+}
+
+impl Checks<'_> {
+    /// Whether any check besides formatting is made.
+    fn any_lint(&self) -> bool {
+        self.links || self.invisible_characters || self.headings || self.code_language.is_some()
+    }
+}
+
+/// Output format for `--report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ReportFormat {
+    /// A single JSON document.
+    Json,
 }
 
 fn main() -> ExitCode {
-    let args = Args::parse();
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(e) => {
+            let _ = e.print();
+            // --help and --version are reported as "errors" on stdout
+            return if e.use_stderr() {
+                ExitCode::from(exit::USAGE_ERROR)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    exit::install_panic_hook();
+    #[cfg(feature = "trace")]
+    if let Some(level) = args.log_level {
+        trace::init(level);
+    }
+    if let Some(seconds) = args.timeout {
+        exit::set_timeout(Duration::from_secs(seconds));
+    }
+
+    if let Some(from) = args.migrate_config {
+        return migrate_config(from);
+    }
 
     // Load configuration
-    let (config, config_dir) = load_config(&args);
+    let (config, config_dir) = match load_effective_config(&args) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(exit::USAGE_ERROR);
+        }
+    };
+
+    if args.print_config.is_some() {
+        return match config.to_toml() {
+            Ok(toml) => {
+                print!("{}", toml);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Error: failed to serialize configuration: {}", e);
+                ExitCode::from(exit::USAGE_ERROR)
+            }
+        };
+    }
 
     // Build options, with CLI args overriding config file
-    let options = Options {
-        line_width: args
-            .line_width
-            .map(|w| LineWidth::new(w).expect("Invalid line width"))
-            .unwrap_or(config.line_width),
-        setext_h1: config.heading.setext_h1,
-        setext_h2: config.heading.setext_h2,
-        heading_sentence_case: config.heading.sentence_case,
-        heading_proper_nouns: config.heading.proper_nouns.clone(),
-        heading_common_nouns: config.heading.common_nouns.clone(),
-        unordered_marker: config.unordered_list.unordered_marker,
-        leading_spaces: config.unordered_list.leading_spaces,
-        trailing_spaces: config.unordered_list.trailing_spaces,
-        indent_width: config.unordered_list.indent_width,
-        odd_level_marker: config.ordered_list.odd_level_marker,
-        even_level_marker: config.ordered_list.even_level_marker,
-        ordered_list_pad: config.ordered_list.pad,
-        ordered_list_indent_width: config.ordered_list.indent_width,
-        fence_char: config.code_block.fence_char,
-        min_fence_length: config.code_block.min_fence_length,
-        space_after_fence: config.code_block.space_after_fence,
-        default_language: config.code_block.default_language.clone(),
-        thematic_break_style: config.thematic_break.style.clone(),
-        thematic_break_leading_spaces: config.thematic_break.leading_spaces,
-        curly_double_quotes: config.punctuation.curly_double_quotes,
-        curly_single_quotes: config.punctuation.curly_single_quotes,
-        curly_apostrophes: config.punctuation.curly_apostrophes,
-        ellipsis: config.punctuation.ellipsis,
-        en_dash: config.punctuation.en_dash.clone(),
-        em_dash: config.punctuation.em_dash.clone(),
-        code_formatters: config
-            .code_block
-            .formatters
-            .iter()
-            .map(|(lang, cfg)| {
-                (
-                    lang.clone(),
-                    CodeFormatter {
-                        command: cfg.command().to_vec(),
-                        timeout_secs: cfg.timeout(),
-                    },
-                )
-            })
-            .collect(),
+    let options = build_options(&config);
+    let checks = Checks {
+        links: args.check_links || config.lint.links,
+        invisible_characters: config.lint.invisible_characters,
+        headings: config.lint.headings,
+        // With the fix on, code blocks without a language are reported as
+        // fixed instead
+        code_language: (config.lint.code_language && !config.fix.contains(&FixRule::CodeLanguage))
+            .then_some(config.lint.code_language_exceptions.as_slice()),
+        deny_warnings: args.deny_warnings,
+    };
+
+    let verbosity = if args.quiet {
+        Verbosity::Quiet
+    } else if args.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
     };
 
     // Validate formatter configurations
     for (lang, cfg) in &config.code_block.formatters {
         if let Err(msg) = cfg.validate() {
             eprintln!("Error: formatter for '{}': {}", lang, msg);
-            return ExitCode::FAILURE;
+            return ExitCode::from(exit::USAGE_ERROR);
         }
     }
 
     // Validate punctuation settings
-    if let Err(e) = validate_dash_settings(&options) {
+    if let Err(e) = validate_dash_settings(&options.markdown) {
         eprintln!("Error: {}", e);
-        return ExitCode::FAILURE;
+        return ExitCode::from(exit::USAGE_ERROR);
     }
 
-    // Check if stdin is explicitly requested via --stdin or `-` as filename
-    let stdin_requested = args.stdin || args.files.iter().any(|f| f.to_str() == Some("-"));
+    if args.report.is_some() && !args.write && !args.check && !args.stats {
+        eprintln!("Error: --report requires --write, --check, or --stats.");
+        return ExitCode::from(exit::USAGE_ERROR);
+    }
+
+    // Clear the cache before anything else so that this run starts fresh
+    if args.cache_clear {
+        let cache_path = args
+            .cache
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_CACHE_FILE));
+        if let Err(e) = Cache::clear(cache_path) {
+            eprintln!("Error clearing cache {}: {}", cache_path.display(), e);
+            return ExitCode::from(exit::FILE_ERROR);
+        }
+        if args.files.is_empty() && !args.stdin && !args.write && !args.check {
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    if args.watch {
+        let paths = if args.files.is_empty() {
+            vec![config_dir.clone()]
+        } else {
+            args.files.clone()
+        };
+        let loader_args = args.clone();
+        let loader = Box::new(move || {
+            let (config, _) = load_effective_config(&loader_args)?;
+            let options = build_options(&config);
+            validate_dash_settings(&options.markdown).map_err(|e| e.to_string())?;
+            Ok(options)
+        });
+        let config_path = if args.no_config {
+            None
+        } else {
+            explicit_config_path(&args)
+                .or_else(|| Some(config_dir.join(CONFIG_FILE_NAME)).filter(|p| p.is_file()))
+        };
+        return match WatchState::new(loader, config_path) {
+            Ok(state) => cli::watch::run(&paths, state),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::from(exit::USAGE_ERROR)
+            }
+        };
+    }
+
+    // Check if stdin is explicitly requested via --stdin, --stdin-filepath, or
+    // `-` as filename
+    let mut stdin_requested = args.stdin
+        || args.stdin_filepath.is_some()
+        || args.files.iter().any(|f| f.to_str() == Some("-"));
 
     // Determine files to process
-    let files: Vec<PathBuf> = if args.files.is_empty() && !stdin_requested {
+    let files: Vec<PathBuf> = if args.stdin_filelist {
+        let mut list = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut list) {
+            eprintln!("Error reading file list from stdin: {}", e);
+            return ExitCode::from(exit::FILE_ERROR);
+        }
+        let mut paths = args.files.clone();
+        paths.extend(
+            list.lines()
+                .map(str::trim_end)
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from),
+        );
+        expand_paths(&paths)
+    } else if args.files.is_empty() && !stdin_requested {
         // No files specified and stdin not requested, try to use include patterns from config
         if !config.include.is_empty() {
             match config.collect_files(&config_dir) {
                 Ok(collected) => collected,
                 Err(e) => {
                     eprintln!("Error collecting files: {}", e);
-                    return ExitCode::FAILURE;
+                    return ExitCode::from(exit::USAGE_ERROR);
                 }
             }
+        } else if stdin_is_piped() {
+            // No files and no include patterns, but input is piped in
+            stdin_requested = true;
+            Vec::new()
         } else {
             // No files, no stdin, no include patterns - error
             eprintln!("Error: no input files specified.");
             eprintln!("Use `hongdown --stdin` or `hongdown -` to read from stdin,");
             eprintln!("or specify file paths as arguments.");
-            return ExitCode::FAILURE;
+            return ExitCode::from(exit::USAGE_ERROR);
         }
     } else if stdin_requested {
         // Filter out `-` from files list since we'll handle stdin separately
@@ -148,211 +456,801 @@ fn main() -> ExitCode {
         expand_paths(&args.files)
     };
 
+    if args.stats {
+        let inputs = read_inputs(stdin_requested.then_some(&args), files);
+        return print_stats(inputs, &options, args.report.is_some());
+    }
+
+    if args.list_code_blocks {
+        let inputs = read_inputs(stdin_requested.then_some(&args), files);
+        return print_code_blocks(
+            inputs,
+            &options,
+            args.lang.as_deref(),
+            args.code_block_dir.as_deref(),
+        );
+    }
+
     if stdin_requested {
         // Read from stdin
-        let mut input = String::new();
-        if let Err(e) = io::stdin().read_to_string(&mut input) {
-            eprintln!("Error reading stdin: {}", e);
-            return ExitCode::FAILURE;
-        }
+        let name = args
+            .stdin_filepath
+            .as_ref()
+            .map_or_else(|| "<stdin>".to_string(), |p| p.display().to_string());
+        let mut bytes = Vec::new();
+        let input = match io::stdin()
+            .read_to_end(&mut bytes)
+            .and_then(|_| decode_input(bytes))
+        {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("Error reading stdin: {}", e);
+                return ExitCode::from(exit::FILE_ERROR);
+            }
+        };
 
-        match format_with_warnings(&input, &options) {
+        let mut session = FormatSession::new();
+        let options = options.for_path(args.stdin_filepath.as_deref());
+        let _span = trace::file_span(Path::new(&name));
+        match exit::format_isolated(&mut session, &input, options) {
             Ok(result) => {
+                let diagnostics = collect_diagnostics(
+                    result.warnings,
+                    &input,
+                    args.stdin_filepath.as_deref(),
+                    checks,
+                );
                 // Print warnings to stderr
-                for warning in &result.warnings {
-                    eprintln!("<stdin>:{}: warning: {}", warning.line, warning.message);
+                if verbosity > Verbosity::Quiet {
+                    for diagnostic in &diagnostics {
+                        eprintln!("{}", format_warning(&name, diagnostic));
+                    }
                 }
+                let mut failed = checks.deny_warnings && has_warnings(&diagnostics);
                 if args.diff {
-                    print_diff("<stdin>", &input, &result.output);
+                    let check = CheckResult::new(&name, &input, result.output);
+                    print!("{}", check.to_unified_diff(args.color.enabled()));
+                    failed |= !check.is_formatted();
                 } else {
                     print!("{}", result.output);
                 }
-                ExitCode::SUCCESS
+                exit::summarize(false, failed)
             }
             Err(e) => {
                 eprintln!("Error formatting: {}", e);
-                ExitCode::FAILURE
+                ExitCode::from(exit::FILE_ERROR)
             }
         }
+    } else if args.list_different {
+        process_files_list_different(&files, &options, checks, verbosity)
     } else if args.write || args.check {
         // Parallel processing for --write and --check modes
-        process_files_parallel(&files, &options, args.write, args.check)
+        let cache = args
+            .cache
+            .as_deref()
+            .map(|path| Cache::load(path, &options));
+        // Whether links are broken depends on other files as well, and lint
+        // settings are not part of the cache key, so the cache can't tell
+        // that a file needs no checking
+        let code = process_files_parallel(
+            &files,
+            &options,
+            args.write,
+            args.check,
+            cache.as_ref().filter(|_| !checks.any_lint()),
+            args.report.is_some(),
+            checks,
+            verbosity,
+        );
+        if let Some(cache) = &cache
+            && let Err(e) = cache.save()
+            && verbosity > Verbosity::Quiet
+        {
+            eprintln!("Warning: failed to write cache: {}.", e);
+        }
+        code
     } else if args.diff {
         // Diff mode for files
-        process_files_diff(&files, &options)
+        process_files_diff(&files, &options, args.color.enabled(), checks, verbosity)
     } else {
         // Sequential processing for stdout mode (order matters)
-        process_files_sequential(&files, &options)
+        process_files_sequential(&files, &options, checks, verbosity)
     }
 }
 
+/// Build formatting options from the effective configuration.
+fn build_options(config: &Config) -> FileOptions {
+    let options = Options {
+        line_width: config.line_width,
+        wrap: config.wrap,
+        max_consecutive_blank_lines: config.max_consecutive_blank_lines,
+        locale: config.locale,
+        line_ending: config.line_ending,
+        bom: config.bom,
+        hard_break: config.hard_break,
+        entities: config.entities,
+        dialect: config.dialect,
+        commonmark_tables: config.commonmark.tables,
+        containers: config.containers,
+        math: config.math,
+        templates: config.templates,
+        fix: config.fix.clone(),
+        setext_h1: config.heading.setext_h1,
+        setext_h2: config.heading.setext_h2,
+        heading_allow_multiline: config.heading.allow_multiline,
+        heading_sentence_case: config.heading.sentence_case,
+        heading_proper_nouns: config.heading.proper_nouns.clone(),
+        heading_common_nouns: config.heading.common_nouns.clone(),
+        heading_strip_trailing_punctuation: config.heading.strip_trailing_punctuation.clone(),
+        heading_numbering: config.heading.numbering,
+        heading_numbering_levels: config.heading.numbering_levels.clone(),
+        unordered_marker: config.unordered_list.unordered_marker,
+        leading_spaces: config.unordered_list.leading_spaces,
+        trailing_spaces: config.unordered_list.trailing_spaces,
+        indent_width: config.unordered_list.indent_width,
+        odd_level_marker: config.ordered_list.odd_level_marker,
+        even_level_marker: config.ordered_list.even_level_marker,
+        ordered_list_pad: config.ordered_list.pad,
+        ordered_list_numbering: config.ordered_list.numbering,
+        ordered_list_indent_width: config.ordered_list.indent_width,
+        list_separation: config.list.separation,
+        list_spacing: config.list.spacing,
+        list_continuation: config.list.continuation,
+        list_preserve_pseudo_lists: config.list.preserve_pseudo_lists,
+        list_fixed_width: config.list.fixed_width,
+        fence_char: config.code_block.fence_char,
+        min_fence_length: config.code_block.min_fence_length,
+        space_after_fence: config.code_block.space_after_fence,
+        // Code blocks without a language are reported rather than given one
+        // silently, unless the fix for them is on
+        default_language: if config.lint.code_language
+            && !config.fix.contains(&FixRule::CodeLanguage)
+        {
+            String::new()
+        } else {
+            config.code_block.default_language.clone()
+        },
+        code_block_trim_trailing_whitespace: config.code_block.trim_trailing_whitespace,
+        code_block_format_embedded: config.code_block.format_embedded.clone(),
+        code_block_json_key_order: config.code_block.json_key_order,
+        block_quote_preserve_marker_spacing: config.block_quote.preserve_marker_spacing,
+        block_quote_separation: config.block_quote.separation,
+        thematic_break_style: config.thematic_break.style.clone(),
+        thematic_break_leading_spaces: config.thematic_break.leading_spaces,
+        table_max_column_width: config.table.max_column_width,
+        table_wrap_cells: config.table.wrap_cells,
+        table_padding: config.table.padding,
+        table_format: config.table.format,
+        table_separator: config.table.separator,
+        table_width_growth: config.table.width_growth,
+        table_width_step: config.table.width_step,
+        html_normalize_void_tags: config.html.normalize_void_tags,
+        html_void_tag_style: config.html.void_tag_style,
+        html_br_to_hard_break: config.html.br_to_hard_break,
+        html_hr_to_thematic_break: config.html.hr_to_thematic_break,
+        html_details: config.html.details,
+        escape_backslash: config.escape.backslash,
+        autolink_github_repo: config.autolink.github.repo.clone(),
+        autolink_github_strip: config.autolink.github.strip,
+        link_remove_unused_definitions: config.link.remove_unused_definitions,
+        link_reference_syntax: config.link.reference_syntax,
+        link_external_references: config.link.external_references,
+        link_url_encoding: config.link.url_encoding,
+        link_sort_definitions: config.link.sort_definitions,
+        link_empty_text: config.link.empty_text,
+        footnote_sort_definitions: config.footnote.sort_definitions,
+        footnote_inline: config.footnote.inline,
+        toc_min_level: config.toc.min_level,
+        toc_max_level: config.toc.max_level,
+        curly_double_quotes: config.punctuation.curly_double_quotes,
+        curly_single_quotes: config.punctuation.curly_single_quotes,
+        curly_apostrophes: config.punctuation.curly_apostrophes,
+        ellipsis: config.punctuation.ellipsis,
+        en_dash: config.punctuation.en_dash.clone(),
+        em_dash: config.punctuation.em_dash.clone(),
+        code_formatters: config
+            .code_block
+            .formatters
+            .iter()
+            .map(|(lang, cfg)| {
+                (
+                    lang.clone(),
+                    CodeFormatter {
+                        command: cfg.command().to_vec(),
+                        timeout_secs: cfg.timeout(),
+                    },
+                )
+            })
+            .collect(),
+        ..Options::default()
+    };
+    FileOptions::new(options, config.mdx)
+}
+
 /// Process files in parallel (for --write and --check modes).
+///
+/// With `report`, a JSON [`Report`] is printed to stdout and all
+/// human-readable output goes to stderr.  Otherwise, a progress line is shown
+/// on stderr for large runs.
+#[allow(clippy::too_many_arguments)]
 fn process_files_parallel(
     files: &[PathBuf],
-    options: &Options,
+    options: &FileOptions,
     write: bool,
     check: bool,
+    cache: Option<&Cache>,
+    report: bool,
+    checks: Checks,
+    verbosity: Verbosity,
 ) -> ExitCode {
-    let has_error = AtomicBool::new(false);
-    let all_formatted = AtomicBool::new(true);
+    let reporter = Reporter::new(verbosity, files.len(), !report);
+    let reports: Vec<FileReport> = files
+        .par_iter()
+        .map_init(FormatSession::new, |session, file| {
+            let started = Instant::now();
+            let (status, diagnostics) = process_file(
+                session, file, options, write, check, cache, report, checks, &reporter,
+            );
+            reporter.file_done(file.display().to_string());
+            FileReport {
+                path: file.display().to_string(),
+                status,
+                diagnostics,
+                duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            }
+        })
+        .collect();
+    reporter.finish();
+
+    if report {
+        match serde_json::to_string_pretty(&Report::new(reports.clone())) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error serializing report: {}", e);
+                return ExitCode::from(exit::FILE_ERROR);
+            }
+        }
+    }
+
+    let has_error = reports.iter().any(|r| r.status == FileStatus::Error);
+    let any_unformatted = reports
+        .iter()
+        .any(|r| r.status == FileStatus::WouldReformat);
+    let any_warning = reports.iter().any(|r| has_warnings(&r.diagnostics));
+    exit::summarize(
+        has_error,
+        check && any_unformatted || checks.deny_warnings && any_warning,
+    )
+}
+
+/// Format or check a single file for [`process_files_parallel`], reusing the
+/// worker thread's `session`.
+///
+/// Returns the file's status and the diagnostics produced along the way.
+/// Everything is also reported through `reporter` as it happens; changed
+/// file names in --write mode go to stdout unless `report` is set.
+#[allow(clippy::too_many_arguments)]
+fn process_file(
+    session: &mut FormatSession,
+    file: &Path,
+    options: &FileOptions,
+    write: bool,
+    check: bool,
+    cache: Option<&Cache>,
+    report: bool,
+    checks: Checks,
+    reporter: &Reporter,
+) -> (FileStatus, Vec<Diagnostic>) {
+    let _span = trace::file_span(file);
+    let input = match read_input(file) {
+        Ok(content) => content,
+        Err(e) => {
+            reporter.error(format!("Error reading {}: {}", file.display(), e));
+            let message = format!("failed to read file: {}.", e);
+            return (FileStatus::Error, vec![Diagnostic::error(message)]);
+        }
+    };
+
+    // Skip files known to be formatted with the current options
+    if cache.is_some_and(|cache| cache.is_fresh(file, &input)) {
+        reporter.info(format!("{}: unchanged (cached)", file.display()));
+        return (FileStatus::Unchanged, Vec::new());
+    }
+
+    let result = match exit::format_isolated(session, &input, options.for_path(Some(file))) {
+        Ok(result) => result,
+        Err(e) => {
+            reporter.error(format!("Error formatting {}: {}", file.display(), e));
+            return (FileStatus::Error, vec![Diagnostic::error(e)]);
+        }
+    };
+
+    let name = file.display().to_string();
+    let mut diagnostics = collect_diagnostics(result.warnings, &input, Some(file), checks);
+    // Print warnings to stderr
+    for diagnostic in &diagnostics {
+        reporter.warn(format_warning(&name, diagnostic));
+    }
+
+    // Leave a file whose formatted output is identical, down to its line
+    // endings and trailing newline, untouched, so its mtime is kept
+    let check_result = CheckResult::new(&name, &input, result.output);
+    if check_result.is_formatted() {
+        if let Some(cache) = cache {
+            cache.record(file, &input);
+        }
+        reporter.info(format!("{}: unchanged", file.display()));
+        return (FileStatus::Unchanged, diagnostics);
+    }
+    let changed = check_result.changed_lines();
+    reporter.info(format!(
+        "{}: changed ({} {})",
+        file.display(),
+        changed,
+        if changed == 1 {
+            "line differs"
+        } else {
+            "lines differ"
+        }
+    ));
+
+    if check || !write {
+        reporter.warn(format!("{}: not formatted", file.display()));
+        if let Some(cache) = cache {
+            cache.forget(file);
+        }
+        return (FileStatus::WouldReformat, diagnostics);
+    }
+
+    match write_atomic(file, result.output) {
+        Ok(written) => {
+            if let Written::Directly(e) = written {
+                reporter.warn(format!(
+                    "Warning: could not replace {} atomically ({}), so it was overwritten in place.",
+                    file.display(),
+                    e
+                ));
+            }
+            if report {
+                reporter.warn(file.display().to_string());
+            } else {
+                reporter.stdout(file.display().to_string());
+            }
+            if let Some(cache) = cache {
+                cache.record(file, result.output);
+            }
+            (FileStatus::Formatted, diagnostics)
+        }
+        Err(e) => {
+            reporter.error(format!("Error writing {}: {}", file.display(), e));
+            diagnostics.push(Diagnostic::error(format!("failed to write file: {}.", e)));
+            (FileStatus::Error, diagnostics)
+        }
+    }
+}
 
-    files.par_iter().for_each(|file| {
-        let input = match fs::read_to_string(file) {
+/// Process files sequentially (for stdout mode where order matters).
+///
+/// A file that cannot be read or formatted is reported and skipped.
+fn process_files_sequential(
+    files: &[PathBuf],
+    options: &FileOptions,
+    checks: Checks,
+    verbosity: Verbosity,
+) -> ExitCode {
+    let mut session = FormatSession::new();
+    let mut any_error = false;
+    let mut any_warning = false;
+    for file in files {
+        let _span = trace::file_span(file);
+        let input = match read_input(file) {
             Ok(content) => content,
             Err(e) => {
                 eprintln!("Error reading {}: {}", file.display(), e);
-                has_error.store(true, Ordering::Relaxed);
-                return;
+                any_error = true;
+                continue;
             }
         };
 
-        match format_with_warnings(&input, options) {
+        match exit::format_isolated(&mut session, &input, options.for_path(Some(file))) {
             Ok(result) => {
+                let diagnostics = collect_diagnostics(result.warnings, &input, Some(file), checks);
+                any_warning |= has_warnings(&diagnostics);
                 // Print warnings to stderr
-                for warning in &result.warnings {
-                    eprintln!(
-                        "{}:{}: warning: {}",
-                        file.display(),
-                        warning.line,
-                        warning.message
-                    );
-                }
-
-                if check {
-                    if input != result.output {
-                        eprintln!("{}: not formatted", file.display());
-                        all_formatted.store(false, Ordering::Relaxed);
-                    }
-                } else if write && input != result.output {
-                    match fs::write(file, &result.output) {
-                        Ok(()) => {
-                            println!("{}", file.display());
-                        }
-                        Err(e) => {
-                            eprintln!("Error writing {}: {}", file.display(), e);
-                            has_error.store(true, Ordering::Relaxed);
-                        }
+                if verbosity > Verbosity::Quiet {
+                    let name = file.display().to_string();
+                    for diagnostic in &diagnostics {
+                        eprintln!("{}", format_warning(&name, diagnostic));
                     }
                 }
+                print!("{}", result.output);
             }
             Err(e) => {
                 eprintln!("Error formatting {}: {}", file.display(), e);
-                has_error.store(true, Ordering::Relaxed);
+                any_error = true;
             }
         }
-    });
+    }
 
-    if has_error.load(Ordering::Relaxed) || (check && !all_formatted.load(Ordering::Relaxed)) {
-        ExitCode::FAILURE
-    } else {
-        ExitCode::SUCCESS
+    exit::summarize(any_error, checks.deny_warnings && any_warning)
+}
+
+/// Process files in list-different mode.
+///
+/// Files are formatted in parallel, and the paths of those that would change
+/// are printed to stdout in input order, relative to the current directory.
+/// Nothing else is written to stdout.
+fn process_files_list_different(
+    files: &[PathBuf],
+    options: &FileOptions,
+    checks: Checks,
+    verbosity: Verbosity,
+) -> ExitCode {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let results: Vec<Result<(bool, bool), String>> = files
+        .par_iter()
+        .map_init(FormatSession::new, |session, file| {
+            let _span = trace::file_span(file);
+            let input =
+                read_input(file).map_err(|e| format!("Error reading {}: {}", file.display(), e))?;
+            let result = exit::format_isolated(session, &input, options.for_path(Some(file)))
+                .map_err(|e| format!("Error formatting {}: {}", file.display(), e))?;
+            let diagnostics = collect_diagnostics(result.warnings, &input, Some(file), checks);
+            if verbosity > Verbosity::Quiet {
+                let name = file.display().to_string();
+                for diagnostic in &diagnostics {
+                    eprintln!("{}", format_warning(&name, diagnostic));
+                }
+            }
+            Ok((result.output != input, has_warnings(&diagnostics)))
+        })
+        .collect();
+
+    let mut any_different = false;
+    let mut any_warning = false;
+    let mut any_error = false;
+    for (file, result) in files.iter().zip(results) {
+        match result {
+            Ok((different, warned)) => {
+                if different {
+                    println!("{}", relative_path(file, &cwd).display());
+                    any_different = true;
+                }
+                any_warning |= warned;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                any_error = true;
+            }
+        }
     }
+
+    exit::summarize(
+        any_error,
+        any_different || checks.deny_warnings && any_warning,
+    )
 }
 
-/// Process files sequentially (for stdout mode where order matters).
-fn process_files_sequential(files: &[PathBuf], options: &Options) -> ExitCode {
+/// Read standard input, if `stdin` has the arguments it is read with, and
+/// `files`, for the modes that don't format them.  Each input comes with
+/// a name to label it with, the path of its file, which standard input only
+/// has with `--stdin-filepath`, and its content.
+fn read_inputs(
+    stdin: Option<&Args>,
+    files: Vec<PathBuf>,
+) -> Vec<(String, Option<PathBuf>, io::Result<String>)> {
+    let mut inputs = Vec::new();
+    if let Some(args) = stdin {
+        let mut bytes = Vec::new();
+        let input = io::stdin()
+            .read_to_end(&mut bytes)
+            .and_then(|_| decode_input(bytes));
+        let name = args
+            .stdin_filepath
+            .as_ref()
+            .map_or_else(|| "<stdin>".to_string(), |p| p.display().to_string());
+        inputs.push((name, args.stdin_filepath.clone(), input));
+    }
     for file in files {
-        let input = match fs::read_to_string(file) {
-            Ok(content) => content,
+        let input = read_input(&file);
+        inputs.push((file.display().to_string(), Some(file), input));
+    }
+    inputs
+}
+
+/// Print a line for each code block of `inputs` (see [`read_inputs`]), read
+/// with `options`, in the language `lang`, or in any language: its file and
+/// lines, and its language, separated by tabs.
+///
+/// With `dir`, the content of each code block is also written to a file of
+/// its own in that directory, whose path ends the line, so that other tools
+/// can be run on it.
+fn print_code_blocks(
+    inputs: Vec<(String, Option<PathBuf>, io::Result<String>)>,
+    options: &FileOptions,
+    lang: Option<&str>,
+    dir: Option<&Path>,
+) -> ExitCode {
+    if let Some(dir) = dir
+        && let Err(e) = fs::create_dir_all(dir)
+    {
+        eprintln!("Error creating {}: {}", dir.display(), e);
+        return ExitCode::from(exit::FILE_ERROR);
+    }
+    let mut any_error = false;
+    let mut count = 0;
+    for (name, path, input) in inputs {
+        let input = match input {
+            Ok(input) => input,
             Err(e) => {
-                eprintln!("Error reading {}: {}", file.display(), e);
-                return ExitCode::FAILURE;
+                eprintln!("Error reading {}: {}", name, e);
+                any_error = true;
+                continue;
             }
         };
-
-        match format_with_warnings(&input, options) {
-            Ok(result) => {
-                // Print warnings to stderr
-                for warning in &result.warnings {
-                    eprintln!(
-                        "{}:{}: warning: {}",
-                        file.display(),
-                        warning.line,
-                        warning.message
-                    );
+        let stem = path
+            .as_deref()
+            .and_then(Path::file_stem)
+            .map_or_else(|| "stdin".into(), |stem| stem.to_string_lossy());
+        for block in hongdown::code_blocks(&input, options.for_path(path.as_deref())) {
+            if lang.is_some_and(|lang| block.language() != lang) {
+                continue;
+            }
+            let mut line = format!(
+                "{}:{}-{}\t{}",
+                name,
+                block.start_line,
+                block.end_line,
+                block.language()
+            );
+            if let Some(dir) = dir {
+                count += 1;
+                let path = dir.join(code_block_file_name(count, &stem, &block));
+                if let Err(e) = fs::write(&path, &block.literal) {
+                    eprintln!("Error writing {}: {}", path.display(), e);
+                    any_error = true;
+                    continue;
                 }
-                print!("{}", result.output);
+                line.push('\t');
+                line.push_str(&path.display().to_string());
+            }
+            println!("{}", line);
+        }
+    }
+    exit::summarize(any_error, false)
+}
+
+/// The name of the file the `count`th code block written by
+/// [`print_code_blocks`] is written to, for a code block of the file whose
+/// name without its extension is `stem`, like `3-README-12.rust` for one on
+/// line 12 of *README.md*.  A code block without a language gets `.txt`.
+fn code_block_file_name(count: usize, stem: &str, block: &CodeBlockRef) -> String {
+    let extension: String = block
+        .language()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+'))
+        .collect();
+    let extension = if extension.is_empty() {
+        "txt"
+    } else {
+        &extension
+    };
+    format!("{}-{}-{}.{}", count, stem, block.start_line, extension)
+}
+
+/// Print the statistics of each of `inputs` (see [`read_inputs`]), as
+/// [`format_stats`] writes them, or as a JSON [`StatsReport`] with `json`.
+fn print_stats(
+    inputs: Vec<(String, Option<PathBuf>, io::Result<String>)>,
+    options: &FileOptions,
+    json: bool,
+) -> ExitCode {
+    let mut any_error = false;
+    let mut files = Vec::new();
+    for (name, path, input) in inputs {
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", name, e);
+                any_error = true;
+                continue;
             }
+        };
+        let stats = hongdown::stats(&input, options.for_path(path.as_deref()));
+        if !json {
+            print!("{}", format_stats(&name, &stats));
+        }
+        files.push(FileStats { path: name, stats });
+    }
+    if json {
+        match serde_json::to_string_pretty(&StatsReport::new(files)) {
+            Ok(json) => println!("{}", json),
             Err(e) => {
-                eprintln!("Error formatting {}: {}", file.display(), e);
-                return ExitCode::FAILURE;
+                eprintln!("Error serializing report: {}", e);
+                return ExitCode::from(exit::FILE_ERROR);
             }
         }
     }
+    exit::summarize(any_error, false)
+}
 
-    ExitCode::SUCCESS
+/// The statistics of the file `name` as a table for `--stats`, a row for
+/// each statistic.  Heading levels without headings are left out.
+fn format_stats(name: &str, stats: &DocStats) -> String {
+    let headings: Vec<_> = stats
+        .headings
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(level, count)| format!("h{} {}", level + 1, count))
+        .collect();
+    let rows = [
+        ("Words", stats.words.to_string()),
+        ("Headings", headings.join(", ")),
+        ("Links", stats.links.to_string()),
+        ("Images", stats.images.to_string()),
+        ("Code blocks", stats.code_blocks.to_string()),
+        ("Tables", stats.tables.to_string()),
+        ("Reading time", format!("{} min", stats.reading_minutes)),
+        ("Longest line", stats.longest_line.to_string()),
+    ];
+    let mut table = format!("{}:\n", name);
+    for (label, value) in rows {
+        table.push_str(&format!("  {:<14}{}\n", label, value).replace(" \n", "\n"));
+    }
+    table
+}
+
+/// Returns `path` relative to `base` if it lies under it, stripping a leading
+/// `./` as well.
+fn relative_path<'a>(path: &'a Path, base: &Path) -> &'a Path {
+    path.strip_prefix(base)
+        .or_else(|_| path.strip_prefix("."))
+        .unwrap_or(path)
 }
 
 /// Process files in diff mode.
-fn process_files_diff(files: &[PathBuf], options: &Options) -> ExitCode {
+///
+/// Like `--check`, this fails if any file is not formatted.
+fn process_files_diff(
+    files: &[PathBuf],
+    options: &FileOptions,
+    color: bool,
+    checks: Checks,
+    verbosity: Verbosity,
+) -> ExitCode {
+    let mut all_formatted = true;
+    let mut session = FormatSession::new();
+    let mut any_error = false;
+    let mut any_warning = false;
     for file in files {
-        let input = match fs::read_to_string(file) {
+        let _span = trace::file_span(file);
+        let input = match read_input(file) {
             Ok(content) => content,
             Err(e) => {
                 eprintln!("Error reading {}: {}", file.display(), e);
-                return ExitCode::FAILURE;
+                any_error = true;
+                continue;
             }
         };
 
-        match format_with_warnings(&input, options) {
+        match exit::format_isolated(&mut session, &input, options.for_path(Some(file))) {
             Ok(result) => {
+                let diagnostics = collect_diagnostics(result.warnings, &input, Some(file), checks);
+                any_warning |= has_warnings(&diagnostics);
                 // Print warnings to stderr
-                for warning in &result.warnings {
-                    eprintln!(
-                        "{}:{}: warning: {}",
-                        file.display(),
-                        warning.line,
-                        warning.message
-                    );
+                if verbosity > Verbosity::Quiet {
+                    let name = file.display().to_string();
+                    for diagnostic in &diagnostics {
+                        eprintln!("{}", format_warning(&name, diagnostic));
+                    }
                 }
-                print_diff(&file.display().to_string(), &input, &result.output);
+                let check = CheckResult::new(&file.display().to_string(), &input, result.output);
+                print!("{}", check.to_unified_diff(color));
+                all_formatted &= check.is_formatted();
             }
             Err(e) => {
                 eprintln!("Error formatting {}: {}", file.display(), e);
-                return ExitCode::FAILURE;
+                any_error = true;
             }
         }
     }
 
-    ExitCode::SUCCESS
+    exit::summarize(
+        any_error,
+        !all_formatted || checks.deny_warnings && any_warning,
+    )
 }
 
-/// Print a unified diff between original and formatted content.
-fn print_diff(filename: &str, original: &str, formatted: &str) {
-    if original == formatted {
-        return;
+/// The diagnostics for the document read from `path`, given the `warnings`
+/// from formatting it: those warnings and, if links are checked, the
+/// problems with its links.  Relative links are resolved against the
+/// directory of `path`, or the current directory if there is none.
+///
+/// A link problem the formatter already warned about replaces the warning,
+/// since it also tells the column where the link starts.
+fn collect_diagnostics(
+    warnings: &[Warning],
+    input: &str,
+    path: Option<&Path>,
+    checks: Checks,
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = warnings.iter().map(Diagnostic::from).collect();
+    if checks.links {
+        let base_dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+        let links = hongdown::lint::check_links(input, Some(base_dir));
+        diagnostics.retain(|d| {
+            !links
+                .iter()
+                .any(|link| link.line == d.line && link.message == d.message)
+        });
+        diagnostics.extend(links);
+    }
+    if checks.invisible_characters {
+        diagnostics.extend(hongdown::lint::check_invisible_characters(input));
+    }
+    if checks.headings {
+        diagnostics.extend(hongdown::lint::check_headings(input));
     }
+    if let Some(exceptions) = checks.code_language {
+        diagnostics.extend(hongdown::lint::check_code_languages(input, exceptions));
+    }
+    if checks.any_lint() {
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+    }
+    diagnostics
+}
 
-    let diff = TextDiff::from_lines(original, formatted);
+/// Returns `true` if any of `diagnostics` is a warning, rather than a notice
+/// of a fix.
+fn has_warnings(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Warning)
+}
 
-    println!("--- {}", filename);
-    println!("+++ {}", filename);
+/// Formats a warning as `FILE:LINE:COLUMN: warning: MESSAGE`, leaving out
+/// the parts of the location that are unknown.  Notices of fixes are
+/// labeled `info` instead of `warning`.
+fn format_warning(name: &str, diagnostic: &Diagnostic) -> String {
+    let location = match (diagnostic.line, diagnostic.column) {
+        (Some(line), Some(column)) => format!(":{}:{}", line, column),
+        (Some(line), None) => format!(":{}", line),
+        _ => String::new(),
+    };
+    let label = match diagnostic.severity {
+        Severity::Info => "info",
+        Severity::Warning | Severity::Error => "warning",
+    };
+    format!("{}{}: {}: {}", name, location, label, diagnostic.message)
+}
 
-    for hunk in diff.unified_diff().iter_hunks() {
-        println!("{}", hunk.header());
-        for change in hunk.iter_changes() {
-            let sign = match change.tag() {
-                ChangeTag::Delete => '-',
-                ChangeTag::Insert => '+',
-                ChangeTag::Equal => ' ',
-            };
-            print!("{}{}", sign, change.value());
-            if !change.value().ends_with('\n') {
-                println!();
-            }
-        }
-    }
+/// Returns `true` if stdin is a pipe or a redirected file rather than a
+/// terminal or `/dev/null`.
+#[cfg(unix)]
+fn stdin_is_piped() -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata("/dev/stdin").is_ok_and(|m| m.file_type().is_fifo() || m.is_file())
+}
+
+/// Returns `true` if stdin is not a terminal.
+#[cfg(not(unix))]
+fn stdin_is_piped() -> bool {
+    use std::io::IsTerminal;
+
+    !io::stdin().is_terminal()
 }
 
 /// Expand paths, converting directories to their contained `.md` files.
 ///
-/// If a path is a directory, recursively finds all `.md` files within it.
-/// If a path is a file, it is included as-is.
+/// If a path is a directory, recursively finds all `.md` files within it
+/// that `.hongdownignore` files don't ignore.  If a path is a file, it is
+/// included as-is.
 fn expand_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut ignore_files = IgnoreFiles::new();
     let mut result = Vec::new();
     for path in paths {
         if path.is_dir() {
-            result.extend(collect_md_files(path));
+            result.extend(collect_md_files(path, &mut ignore_files));
         } else {
             result.push(path.clone());
         }
@@ -360,68 +1258,186 @@ fn expand_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
     result
 }
 
-/// Recursively collect all Markdown files (`.md` and `.markdown`) from a directory.
-fn collect_md_files(dir: &Path) -> Vec<PathBuf> {
+/// Recursively collect all Markdown files (`.md` and `.markdown`) from a
+/// directory, skipping those that `ignore_files` ignore.
+fn collect_md_files(dir: &Path, ignore_files: &mut IgnoreFiles) -> Vec<PathBuf> {
     let mut files = Vec::new();
     for entry in WalkDir::new(dir).follow_links(true) {
         let Ok(entry) = entry else { continue };
         let path = entry.path();
-        if path.is_file() {
-            let is_markdown = path.extension().is_some_and(|ext| {
-                ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown")
-            });
-            if is_markdown {
-                files.push(path.to_path_buf());
-            }
+        if path.is_file() && is_markdown_path(path) && ignore_files.matched(path) != Some(true) {
+            files.push(path.to_path_buf());
         }
     }
     files.sort();
     files
 }
 
+/// Translate the configuration of `from` found from the current directory
+/// into a `.hongdown.toml` next to it, for `--migrate-config`.
+fn migrate_config(from: MigrateFrom) -> ExitCode {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let Some(source) = cli::migrate::find(from, &cwd) else {
+        eprintln!("Error: no configuration file of {} found.", from.name());
+        return ExitCode::from(exit::USAGE_ERROR);
+    };
+    let migration = match cli::migrate::migrate(from, &source) {
+        Ok(migration) => migration,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(exit::USAGE_ERROR);
+        }
+    };
+    let target = source.with_file_name(CONFIG_FILE_NAME);
+    if target.exists() {
+        eprintln!("Error: {} already exists.", target.display());
+        return ExitCode::from(exit::USAGE_ERROR);
+    }
+    if let Err(e) = fs::write(&target, migration.to_toml(from)) {
+        eprintln!("Error writing {}: {}", target.display(), e);
+        return ExitCode::from(exit::FILE_ERROR);
+    }
+    eprintln!("Wrote {} from {}.", target.display(), source.display());
+    for (key, translated) in &migration.mapped {
+        eprintln!("  {} -> {}", key, translated);
+    }
+    if !migration.unmapped.is_empty() {
+        eprintln!("Not translated:");
+        for (key, reason) in &migration.unmapped {
+            eprintln!("  {}: {}", key, reason);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Load configuration and apply overrides from the environment and the
+/// command line, in that order of increasing precedence.
+fn load_effective_config(args: &Args) -> Result<(Config, PathBuf), String> {
+    let (config, config_dir) = load_config(args)?;
+    let env = if args.no_config {
+        PartialConfig::default()
+    } else {
+        PartialConfig::from_env()?
+    };
+    let cli = PartialConfig {
+        line_width: args.line_width,
+        wrap: args.wrap,
+        unordered_marker: args.unordered_marker,
+        fence_char: args.fence_char,
+        setext: args.no_setext.then_some(false),
+        line_ending: args.line_ending,
+        ..PartialConfig::default()
+    };
+    #[cfg(feature = "trace")]
+    for (key, set) in [
+        ("--line-width", cli.line_width.is_some()),
+        ("--wrap", cli.wrap.is_some()),
+        ("--unordered-marker", cli.unordered_marker.is_some()),
+        ("--fence-char", cli.fence_char.is_some()),
+        ("--no-setext", cli.setext.is_some()),
+        ("--line-ending", cli.line_ending.is_some()),
+        ("--no-embedded-formatters", args.no_embedded_formatters),
+    ] {
+        if set {
+            tracing::debug!(source = "command line", key, "option set");
+        }
+    }
+    let mut config = cli.or(env).merge_over(config);
+    if args.no_embedded_formatters {
+        config.code_block.formatters.clear();
+    }
+    Ok((config, config_dir))
+}
+
 /// Load configuration from file or use defaults.
 ///
 /// Returns the configuration and the base directory for glob patterns.
 ///
 /// Priority:
-/// 1. Explicit `--config` path (bypasses cascading)
-/// 2. Cascading configuration:
+/// 1. `--no-config` (bypasses all configuration files)
+/// 2. Explicit `--config` path (bypasses cascading)
+/// 3. Cascading configuration:
 ///    - System config: `/etc/hongdown/config.toml`
 ///    - User legacy config: `~/.hongdown.toml`
 ///    - User XDG config: `$XDG_CONFIG_HOME/hongdown/config.toml`
 ///    - Project config: `.hongdown.toml` in current or parent directories
-/// 3. Default configuration
-fn load_config(args: &Args) -> (Config, PathBuf) {
+///      up to the root of the repository (starting from the directory of
+///      `--stdin-filepath`, if given)
+///
+///    The user configs are left out with `--no-user-config`.
+/// 4. Default configuration
+///
+/// An explicit `--config` path that cannot be loaded is an error, while
+/// problems with discovered configuration files are only warnings.
+fn load_config(args: &Args) -> Result<(Config, PathBuf), String> {
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
 
+    if args.no_config {
+        return Ok((Config::default(), cwd));
+    }
+
+    // Discover configuration from where stdin content claims to live, or
+    // from the path whose configuration is being printed
+    let start_dir = if let Some(path) = &args.print_config {
+        let path = cwd.join(path);
+        if path.is_dir() {
+            path
+        } else {
+            path.parent().map(Path::to_path_buf).unwrap_or(path)
+        }
+    } else {
+        args.stdin_filepath
+            .as_ref()
+            .and_then(|p| cwd.join(p).parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| cwd.clone())
+    };
+
     // If explicit config path is provided, use it without cascading
-    if let Some(config_path) = &args.config {
-        match Config::from_file(config_path) {
-            Ok(config) => {
-                let config_dir = config_path
-                    .parent()
-                    .map(|p| p.to_path_buf())
-                    .unwrap_or_else(|| cwd.clone());
-                return (config, config_dir);
-            }
-            Err(e) => {
-                eprintln!("Warning: {}", e);
-                return (Config::default(), cwd);
-            }
+    if let Some(config_path) = explicit_config_path(args) {
+        if !config_path.is_file() {
+            return Err(format!(
+                "configuration file not found: {}.",
+                config_path.display()
+            ));
         }
+        let config = Config::from_file(&config_path).map_err(|e| e.to_string())?;
+        let config_dir = config_path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| cwd.clone());
+        return Ok((config, config_dir));
     }
 
     // Use cascading config loading from all sources
-    match Config::load_cascading(&cwd) {
+    let options = DiscoveryOptions {
+        user_config: !args.no_user_config,
+        ..DiscoveryOptions::default()
+    };
+    let warn = |e| eprintln!("Warning: {}, so it is skipped.", e);
+    match Config::load_cascading(&start_dir, options, warn) {
         Ok((config, maybe_project_path)) => {
             let config_dir = maybe_project_path
                 .and_then(|p| p.parent().map(|p| p.to_path_buf()))
                 .unwrap_or_else(|| cwd.clone());
-            (config, config_dir)
+            Ok((config, config_dir))
         }
         Err(e) => {
             eprintln!("Warning: {}", e);
-            (Config::default(), cwd)
+            Ok((Config::default(), cwd))
         }
     }
 }
+
+/// Resolve the `--config` argument to a configuration file path.
+///
+/// A directory refers to the `.hongdown.toml` inside it.
+fn explicit_config_path(args: &Args) -> Option<PathBuf> {
+    args.config.as_ref().map(|path| {
+        if path.is_dir() {
+            path.join(CONFIG_FILE_NAME)
+        } else {
+            path.clone()
+        }
+    })
+}
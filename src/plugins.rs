@@ -0,0 +1,256 @@
+//! Hooks for handling blocks the formatter doesn't know about.
+//!
+//! Some Markdown dialects put their own syntax in what CommonMark parses as
+//! ordinary paragraphs, like the fenced divs of Pandoc, which the formatter
+//! would reflow like any other paragraph.  A [`BlockHandler`] registered with
+//! a [`FormatterBuilder`] sees each block before the formatter does, and can
+//! write it out itself instead.
+//!
+//! This module is only available with the `plugins` feature.  Its API is not
+//! stable yet: it may change in minor releases, and which blocks handlers are
+//! given may grow as the formatter learns about more of them.
+//!
+//! # Example
+//!
+//! ```
+//! use hongdown::Options;
+//! use hongdown::plugins::{FormatterBuilder, fenced_divs};
+//!
+//! let formatter = FormatterBuilder::new(Options::default())
+//!     .with_block_handler(fenced_divs)
+//!     .build();
+//! let input = "::: warning\nDon't   reflow me.\n:::\n";
+//! assert_eq!(formatter.format(input).unwrap(), input);
+//! ```
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use crate::serializer::{self, SerializeResult};
+use crate::{FormatError, FormatResult, Options, PipelineHooks, run_pipeline};
+
+/// Whether a [`BlockHandler`] wrote out the block it was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handled {
+    /// The handler wrote the block through its [`BlockSink`], and the
+    /// formatter skips it.
+    Yes,
+    /// The handler declined the block, and the formatter formats it as
+    /// usual.  Anything the handler wrote through its sink is discarded.
+    No,
+}
+
+/// Where a [`BlockHandler`] writes the lines of a block.
+///
+/// A sink only takes whole lines, and puts the prefix of the block quotes
+/// and list items the block is in before each of them, so that a handler
+/// can't write output that leaves its containers.
+pub trait BlockSink {
+    /// The source of the block, without the block quote markers and the
+    /// list item indentation before its lines, or `None` if the formatter
+    /// was given no source.
+    fn source(&self) -> Option<&str>;
+
+    /// Write a line of the block.  Trailing whitespace is kept, and a line
+    /// containing line breaks is written as several lines.
+    fn write_line(&mut self, line: &str);
+}
+
+/// A function that gets the first chance to write each block.
+///
+/// Handlers are given paragraphs, headings, code blocks, HTML blocks,
+/// thematic breaks, tables, lists, block quotes, and alerts, but not the
+/// items, rows, and cells these are made of.
+pub type BlockHandler = for<'a> fn(&'a AstNode<'a>, &mut dyn BlockSink) -> Handled;
+
+/// Builds a [`Formatter`] with block handlers.
+#[derive(Debug, Clone)]
+pub struct FormatterBuilder {
+    options: Options,
+    block_handlers: Vec<BlockHandler>,
+}
+
+impl FormatterBuilder {
+    /// Creates a builder for a formatter with the given options and no block
+    /// handlers.
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            block_handlers: Vec::new(),
+        }
+    }
+
+    /// Adds a block handler.  Handlers are tried in the order they are
+    /// added, and the first one that handles a block wins.
+    pub fn with_block_handler(mut self, handler: BlockHandler) -> Self {
+        self.block_handlers.push(handler);
+        self
+    }
+
+    /// Builds the formatter.
+    pub fn build(self) -> Formatter {
+        Formatter {
+            options: self.options,
+            block_handlers: self.block_handlers,
+        }
+    }
+}
+
+/// A formatter with block handlers, made by a [`FormatterBuilder`].
+#[derive(Debug, Clone)]
+pub struct Formatter {
+    options: Options,
+    block_handlers: Vec<BlockHandler>,
+}
+
+impl Formatter {
+    /// Formats a Markdown document, like [`crate::format`] but with the
+    /// block handlers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input cannot be parsed or formatted.
+    pub fn format(&self, input: &str) -> Result<String, FormatError> {
+        self.format_with_warnings(input).map(|result| result.output)
+    }
+
+    /// Formats a Markdown document and returns both output and warnings,
+    /// like [`crate::format_with_warnings`] but with the block handlers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input cannot be parsed or formatted.
+    pub fn format_with_warnings(&self, input: &str) -> Result<FormatResult, FormatError> {
+        if input.is_empty() {
+            return Ok(FormatResult {
+                output: String::new(),
+                warnings: Vec::new(),
+            });
+        }
+
+        let mut hooks = BlockHandlerHooks(&self.block_handlers);
+        let result = run_pipeline(input, &self.options, &mut hooks)?;
+        Ok(FormatResult {
+            output: result.output,
+            warnings: result.warnings,
+        })
+    }
+}
+
+/// The hooks of [`Formatter::format_with_warnings`], which serialize with
+/// the block handlers.
+struct BlockHandlerHooks<'h>(&'h [BlockHandler]);
+
+impl PipelineHooks for BlockHandlerHooks<'_> {
+    fn serialize<'a>(
+        &mut self,
+        root: &'a AstNode<'a>,
+        options: &Options,
+        input: &str,
+    ) -> SerializeResult {
+        serializer::serialize_with_block_handlers(root, options, Some(input), self.0)
+    }
+}
+
+/// A block handler that keeps paragraphs with the fences of Pandoc's fenced
+/// divs as they are.
+///
+/// A fenced div starts with a line of three or more colons followed by
+/// attributes, like `::: warning`, and ends with a line of colons.  When
+/// there are no blank lines around the fences, they are part of
+/// a paragraph, which would otherwise be reflowed into a single line.  The
+/// blocks between fences that are set apart by blank lines are formatted as
+/// usual.
+pub fn fenced_divs<'a>(node: &'a AstNode<'a>, sink: &mut dyn BlockSink) -> Handled {
+    if !matches!(node.data.borrow().value, NodeValue::Paragraph) {
+        return Handled::No;
+    }
+    let Some(source) = sink.source() else {
+        return Handled::No;
+    };
+    if !source.lines().any(is_div_fence) {
+        return Handled::No;
+    }
+    let source = source.to_string();
+    for line in source.lines() {
+        sink.write_line(line);
+    }
+    Handled::Yes
+}
+
+/// Whether `line` opens or closes a fenced div.
+fn is_div_fence(line: &str) -> bool {
+    line.trim_start().starts_with(":::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_with_fenced_divs(input: &str) -> String {
+        let options = Options {
+            line_width: crate::LineWidth::new(40).unwrap(),
+            ..Options::default()
+        };
+        FormatterBuilder::new(options)
+            .with_block_handler(fenced_divs)
+            .build()
+            .format(input)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fenced_div_kept() {
+        let input =
+            "::: warning\nThis sentence is long enough to be wrapped by the formatter.\n:::\n";
+        assert_eq!(format_with_fenced_divs(input), input);
+    }
+
+    #[test]
+    fn test_fenced_div_contents_formatted() {
+        let input =
+            "::: note\n\nThis paragraph is long enough to be wrapped.\n\n:::\n\n# Heading\n";
+        assert_eq!(
+            format_with_fenced_divs(input),
+            "::: note\n\nThis paragraph is long enough to be\nwrapped.\n\n:::\n\nHeading\n=======\n"
+        );
+    }
+
+    #[test]
+    fn test_fenced_div_in_containers() {
+        let input = "> ::: tip\n> Quoted div that is long enough to be wrapped.\n> :::\n\n \
+                     -  ::: tip\n    Listed div that is long enough to be wrapped.\n    :::\n";
+        assert_eq!(format_with_fenced_divs(input), input);
+    }
+
+    #[test]
+    fn test_declined_blocks_formatted() {
+        let declined = |_: &AstNode<'_>, sink: &mut dyn BlockSink| {
+            sink.write_line("discarded");
+            Handled::No
+        };
+        let formatter = FormatterBuilder::new(Options::default())
+            .with_block_handler(declined)
+            .build();
+        assert_eq!(
+            formatter.format("# Title\n\n*  Item\n").unwrap(),
+            "Title\n=====\n\n -  Item\n"
+        );
+    }
+
+    #[test]
+    fn test_first_handler_wins() {
+        let shout = |_: &AstNode<'_>, sink: &mut dyn BlockSink| {
+            let upper = sink.source().unwrap_or_default().to_uppercase();
+            sink.write_line(&upper);
+            Handled::Yes
+        };
+        let formatter = FormatterBuilder::new(Options::default())
+            .with_block_handler(fenced_divs)
+            .with_block_handler(shout)
+            .build();
+        assert_eq!(
+            formatter.format("::: a\nb\n:::\n\nc\nd\n").unwrap(),
+            "::: a\nb\n:::\n\nC\nD\n"
+        );
+    }
+}
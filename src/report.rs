@@ -0,0 +1,144 @@
+//! Machine-readable formatting reports.
+//!
+//! These types describe the outcome of formatting a set of files, and are
+//! what the command-line interface emits with `--report json`; a
+//! [`StatsReport`] is what it emits with `--stats --report json`.  They are
+//! exposed so that tools consuming the JSON output can deserialize it into
+//! the same types.
+//!
+//! The schema is versioned by [`REPORT_VERSION`], which is bumped whenever a
+//! field is removed or changes meaning.  Adding fields does not bump it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DocStats, Warning};
+
+/// The current version of the report schema.
+pub const REPORT_VERSION: u32 = 1;
+
+/// A report covering every file processed in a single run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// Version of the report schema; see [`REPORT_VERSION`].
+    pub version: u32,
+    /// Per-file results, in the order the files were given.
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    /// Creates a report with the current schema version.
+    pub fn new(files: Vec<FileReport>) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            files,
+        }
+    }
+}
+
+/// A report of the statistics of every file given to `--stats`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsReport {
+    /// Version of the report schema; see [`REPORT_VERSION`].
+    pub version: u32,
+    /// Per-file statistics, in the order the files were given.
+    pub files: Vec<FileStats>,
+}
+
+impl StatsReport {
+    /// Creates a report with the current schema version.
+    pub fn new(files: Vec<FileStats>) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            files,
+        }
+    }
+}
+
+/// The statistics of a single file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileStats {
+    /// Path of the file as given on the command line or found by discovery,
+    /// or `<stdin>`.
+    pub path: String,
+    /// The statistics of the file.
+    pub stats: DocStats,
+}
+
+/// The result of processing a single file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileReport {
+    /// Path of the file as given on the command line or found by discovery.
+    pub path: String,
+    /// What happened to the file.
+    pub status: FileStatus,
+    /// Warnings and errors produced while processing the file.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Wall-clock time spent on the file, in milliseconds.
+    pub duration_ms: f64,
+}
+
+/// The outcome of processing a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    /// The file was reformatted and written back.
+    Formatted,
+    /// The file was already formatted.
+    Unchanged,
+    /// The file is not formatted, but was not written (e.g., `--check`).
+    WouldReformat,
+    /// The file could not be read, formatted, or written.
+    Error,
+}
+
+/// The severity of a [`Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// A fix made while formatting, like those of the `fix` option.
+    Info,
+    /// A problem that did not prevent formatting.
+    Warning,
+    /// A problem that prevented the file from being processed.
+    Error,
+}
+
+/// A single warning or error attached to a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// 1-indexed line number, if the problem has a location.
+    pub line: Option<usize>,
+    /// 1-indexed column number, if known.
+    pub column: Option<usize>,
+    /// Human-readable description of the problem.
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Creates an error diagnostic without a location.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Self {
+            severity: if warning.fixed {
+                Severity::Info
+            } else {
+                Severity::Warning
+            },
+            line: (warning.line > 0).then_some(warning.line),
+            column: None,
+            message: warning.message.clone(),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Links to GitHub issues, pull requests, and users, for the
+//! `autolink.github` options.
+//!
+//! GitHub turns `#123`, `GH-123`, `owner/name#123`, and `@user` into links
+//! by itself, but other sites that render the same documents don't.  With a
+//! repository configured, [`link_github_references`] turns these bare
+//! references in prose into explicit links right after the document is
+//! parsed, so that they are written like any other link; with `strip`, it
+//! turns such links back into bare references instead.  References in code,
+//! links, headings, and regions where formatting is disabled are left alone.
+
+use std::sync::LazyLock;
+
+use comrak::Arena;
+use comrak::nodes::{Ast, AstNode, LineColumn, NodeLink, NodeValue, Sourcepos};
+use regex::Regex;
+
+use super::Serializer;
+
+/// A bare reference: `owner/name#123` (groups 1 to 3), `#123` (group 3),
+/// `GH-123` (group 4), or `@user` (group 5).  Whether it is delimited by
+/// word boundaries is checked by [`is_bare_reference`].
+static REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?:([A-Za-z0-9-]+)/([A-Za-z0-9._-]+))?#([0-9]+)|GH-([0-9]+)|@([A-Za-z0-9](?:[A-Za-z0-9-]*[A-Za-z0-9])?)",
+    )
+    .unwrap()
+});
+
+/// A reference in prose, and where it links to.
+struct Reference {
+    /// The byte range of the reference in its text.
+    range: std::ops::Range<usize>,
+    url: String,
+}
+
+/// Whether the match from `start` to `end` of `text` is a reference of its
+/// own, rather than part of a word, a path, an email address, or a color
+/// like `#fff`.
+fn is_bare_reference(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mention = text[start..].starts_with('@');
+    if before.is_some_and(|c| is_word(c) || matches!(c, '/' | '#' | '&' | '@' | '.' | '-')) {
+        return false;
+    }
+    if after.is_some_and(|c| is_word(c) || matches!(c, '#' | '@' | '-')) {
+        return false;
+    }
+    // `@org/team`, and `@user.` with a name that goes on, like an email
+    // address
+    !(mention
+        && (after == Some('/')
+            || (after == Some('.') && text[end + 1..].starts_with(|c: char| is_word(c)))))
+}
+
+/// The bare references in `text`, linked to `repo`, as `owner/name`;
+/// without a repository, `#123` and `GH-123` aren't references.
+fn find_references(text: &str, repo: Option<&str>) -> Vec<Reference> {
+    REFERENCE
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let whole = captures.get(0).unwrap();
+            if !is_bare_reference(text, whole.start(), whole.end()) {
+                return None;
+            }
+            let url = if let Some(user) = captures.get(5) {
+                format!("https://github.com/{}", user.as_str())
+            } else {
+                let (owner_name, number) = match (captures.get(1), captures.get(3)) {
+                    (Some(owner), Some(number)) => (
+                        format!("{}/{}", owner.as_str(), &captures[2]),
+                        number.as_str(),
+                    ),
+                    (None, Some(number)) => (repo?.to_string(), number.as_str()),
+                    _ => (repo?.to_string(), captures.get(4).unwrap().as_str()),
+                };
+                format!("https://github.com/{}/issues/{}", owner_name, number)
+            };
+            Some(Reference {
+                range: whole.range(),
+                url,
+            })
+        })
+        .collect()
+}
+
+/// The bare reference the link `text` to `url` can be written as, if any:
+/// its text, if it is a reference that links to `url`, to an issue or a
+/// pull request.
+fn strippable(text: &str, url: &str, repo: Option<&str>) -> bool {
+    let references = find_references(text, repo);
+    let [reference] = references.as_slice() else {
+        return false;
+    };
+    if reference.range != (0..text.len()) {
+        return false;
+    }
+    url == reference.url || url == reference.url.replacen("/issues/", "/pull/", 1)
+}
+
+/// Turn the bare references to GitHub in the prose of `root` into links to
+/// `repo`, as `owner/name`, or with `strip`, turn links that a bare
+/// reference would be rendered as by GitHub back into bare references.
+pub(crate) fn link_github_references<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    repo: Option<&str>,
+    strip: bool,
+) {
+    let disabled_ranges = Serializer::collect_disabled_line_ranges(root);
+    let nodes: Vec<_> = root.descendants().collect();
+    for node in nodes {
+        let line = node.data.borrow().sourcepos.start.line;
+        if Serializer::is_line_in_disabled_ranges(line, &disabled_ranges) {
+            continue;
+        }
+        if strip {
+            strip_link(arena, node, repo);
+        } else if let Some(repo) = repo {
+            link_text(arena, node, repo);
+        }
+    }
+}
+
+/// Whether `node` is prose that may have bare references in it: not in a
+/// link, an image, or a heading.
+fn in_prose<'a>(node: &'a AstNode<'a>) -> bool {
+    !node.ancestors().skip(1).any(|ancestor| {
+        matches!(
+            ancestor.data.borrow().value,
+            NodeValue::Link(_) | NodeValue::Image(_) | NodeValue::Heading(_)
+        )
+    })
+}
+
+/// Split the text `node` around its bare references, putting each in a link.
+fn link_text<'a>(arena: &'a Arena<'a>, node: &'a AstNode<'a>, repo: &str) {
+    let (text, sourcepos) = match &node.data.borrow().value {
+        NodeValue::Text(text) => (text.to_string(), node.data.borrow().sourcepos),
+        _ => return,
+    };
+    let references = find_references(&text, Some(repo));
+    if references.is_empty() || !in_prose(node) {
+        return;
+    }
+    // The pieces keep their positions in the source only if the text is
+    // written as it reads, so that the escapes in them are kept
+    let maps_to_source = sourcepos.start.line > 0
+        && sourcepos.start.line == sourcepos.end.line
+        && sourcepos.end.column + 1 - sourcepos.start.column == text.len();
+    let piece = |value: NodeValue, start: usize, end: usize| {
+        let mut ast = Ast::new(value, LineColumn { line: 0, column: 0 });
+        ast.sourcepos = if maps_to_source {
+            let line = sourcepos.start.line;
+            let column = sourcepos.start.column;
+            Sourcepos::from((line, column + start, line, column + end - 1))
+        } else {
+            Sourcepos::from((0, 0, 0, 0))
+        };
+        arena.alloc(ast.into())
+    };
+    let mut last = 0;
+    for reference in references {
+        let (start, end) = (reference.range.start, reference.range.end);
+        if start > last {
+            node.insert_before(piece(
+                NodeValue::Text(text[last..start].to_string().into()),
+                last,
+                start,
+            ));
+        }
+        let link = piece(
+            NodeValue::Link(Box::new(NodeLink {
+                url: reference.url,
+                title: String::new(),
+            })),
+            start,
+            end,
+        );
+        link.append(piece(
+            NodeValue::Text(text[start..end].to_string().into()),
+            start,
+            end,
+        ));
+        node.insert_before(link);
+        last = end;
+    }
+    if last < text.len() {
+        node.insert_before(piece(
+            NodeValue::Text(text[last..].to_string().into()),
+            last,
+            text.len(),
+        ));
+    }
+    node.detach();
+}
+
+/// Replace the link `node` with its text, if the text is a bare reference
+/// that GitHub renders as the same link.
+fn strip_link<'a>(arena: &'a Arena<'a>, node: &'a AstNode<'a>, repo: Option<&str>) {
+    let url = match &node.data.borrow().value {
+        NodeValue::Link(link) if link.title.is_empty() => link.url.clone(),
+        _ => return,
+    };
+    let Some(child) = node
+        .first_child()
+        .filter(|child| child.next_sibling().is_none())
+    else {
+        return;
+    };
+    let text = match &child.data.borrow().value {
+        NodeValue::Text(text) => text.to_string(),
+        _ => return,
+    };
+    if !strippable(&text, &url, repo) || !in_prose(node) {
+        return;
+    }
+    let mut ast = Ast::new(
+        NodeValue::Text(text.into()),
+        LineColumn { line: 0, column: 0 },
+    );
+    ast.sourcepos = Sourcepos::from((0, 0, 0, 0));
+    node.insert_before(arena.alloc(ast.into()));
+    node.detach();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<(&str, String)> {
+        find_references(text, Some("owner/name"))
+            .into_iter()
+            .map(|reference| (&text[reference.range], reference.url))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_references() {
+        assert_eq!(
+            urls("Fixes #123, GH-45 (and other/repo#6) thanks to @octo-cat."),
+            [
+                (
+                    "#123",
+                    "https://github.com/owner/name/issues/123".to_string()
+                ),
+                (
+                    "GH-45",
+                    "https://github.com/owner/name/issues/45".to_string()
+                ),
+                (
+                    "other/repo#6",
+                    "https://github.com/other/repo/issues/6".to_string()
+                ),
+                ("@octo-cat", "https://github.com/octo-cat".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_references_false_positives() {
+        for text in [
+            "#123abc",
+            "color: #fff",
+            "a#1",
+            "path/to/file#12",
+            "https://example.com/a/b#12",
+            "user@example.com",
+            "@org/team",
+            "GH-12a",
+            "XGH-12",
+            "@user-",
+            "##12",
+            "issue-#12",
+        ] {
+            assert_eq!(urls(text), [], "{:?}", text);
+        }
+        assert!(find_references("#123", None).is_empty());
+        assert_eq!(find_references("a/b#1 @c", None).len(), 2);
+    }
+
+    #[test]
+    fn test_strippable() {
+        let repo = Some("owner/name");
+        assert!(strippable(
+            "#1",
+            "https://github.com/owner/name/issues/1",
+            repo
+        ));
+        assert!(strippable(
+            "#1",
+            "https://github.com/owner/name/pull/1",
+            repo
+        ));
+        assert!(!strippable(
+            "#1",
+            "https://github.com/other/repo/pull/1",
+            repo
+        ));
+        assert!(!strippable(
+            "#1",
+            "https://github.com/owner/name/pull/1",
+            None
+        ));
+        assert!(strippable("@a", "https://github.com/a", None));
+        assert!(!strippable(
+            "see #1",
+            "https://github.com/owner/name/issues/1",
+            repo
+        ));
+    }
+}
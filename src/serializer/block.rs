@@ -1,8 +1,9 @@
 //! Block quote and alert serialization logic.
 
-use comrak::nodes::{AlertType, AstNode};
+use comrak::nodes::{AlertType, AstNode, NodeValue};
 
-use super::Serializer;
+use super::{LinePrefix, Serializer};
+use crate::BlockQuoteSeparation;
 
 /// Saved state for blockquote context, used for restoration after processing.
 struct BlockquoteState {
@@ -11,10 +12,33 @@ struct BlockquoteState {
     old_blockquote_outer_indent: String,
     old_blockquote_entry_list_depth: usize,
     old_list_item_indent: String,
+    old_list_marker_overflow: usize,
     old_list_type: Option<comrak::nodes::ListType>,
     old_list_depth: usize,
-    /// The list item indent before entering blockquote context (for separator lines).
-    indent: String,
+}
+
+/// Split a line of a block quote into what comes before its first `>`, its
+/// `>` markers with the spaces between and after them, and its text.
+///
+/// Returns `None` if the line has no `>` or no text after the markers.
+fn split_quote_line(line: &str) -> Option<(&str, &str, &str)> {
+    let marker_start = line.find('>')?;
+    let rest = &line[marker_start..];
+    let markers_len = rest.len() - rest.trim_start_matches(['>', ' ']).len();
+    let text = rest[markers_len..].trim_end();
+    if text.is_empty() {
+        return None;
+    }
+    Some((&line[..marker_start], &rest[..markers_len], text))
+}
+
+/// Whether `node` is a block quote or an alert, which are both written with
+/// `>` markers.
+fn is_quote<'b>(node: &'b AstNode<'b>) -> bool {
+    matches!(
+        node.data.borrow().value,
+        NodeValue::BlockQuote | NodeValue::Alert(_)
+    )
 }
 
 impl<'a> Serializer<'a> {
@@ -22,6 +46,7 @@ impl<'a> Serializer<'a> {
     fn enter_blockquote_context(&mut self) -> BlockquoteState {
         let was_in_block_quote = self.in_block_quote;
         self.in_block_quote = true;
+        self.line_prefix.push(LinePrefix::Quote);
 
         let old_blockquote_prefix = self.blockquote_prefix.clone();
         self.blockquote_prefix.push_str("> ");
@@ -34,9 +59,8 @@ impl<'a> Serializer<'a> {
         let old_blockquote_entry_list_depth =
             std::mem::replace(&mut self.blockquote_entry_list_depth, self.list_depth);
 
-        let indent = self.list_item_indent.clone();
-
         let old_list_item_indent = std::mem::take(&mut self.list_item_indent);
+        let old_list_marker_overflow = std::mem::take(&mut self.list_marker_overflow);
         let old_list_type = self.list_type.take();
         let old_list_depth = std::mem::replace(&mut self.list_depth, 0);
 
@@ -46,9 +70,9 @@ impl<'a> Serializer<'a> {
             old_blockquote_outer_indent,
             old_blockquote_entry_list_depth,
             old_list_item_indent,
+            old_list_marker_overflow,
             old_list_type,
             old_list_depth,
-            indent,
         }
     }
 
@@ -57,36 +81,124 @@ impl<'a> Serializer<'a> {
         self.list_depth = state.old_list_depth;
         self.list_type = state.old_list_type;
         self.list_item_indent = state.old_list_item_indent;
+        self.list_marker_overflow = state.old_list_marker_overflow;
         self.blockquote_outer_indent = state.old_blockquote_outer_indent;
         self.blockquote_entry_list_depth = state.old_blockquote_entry_list_depth;
         self.blockquote_prefix = state.old_blockquote_prefix;
         self.in_block_quote = state.was_in_block_quote;
+        self.line_prefix.pop();
     }
 
     /// Serialize children within blockquote context, adding blank quote lines between them.
-    fn serialize_blockquote_children<'b>(
-        &mut self,
-        children: &[&'b AstNode<'b>],
-        state: &BlockquoteState,
-    ) {
-        for (i, child) in children.iter().enumerate() {
-            if i > 0 {
-                self.output.push_str(&state.indent);
-                self.output.push_str(&state.old_blockquote_prefix);
-                self.output.push_str(">\n");
-            }
-            self.serialize_node(child);
+    fn serialize_blockquote_children<'b>(&mut self, children: &[&'b AstNode<'b>]) {
+        let mut written = false;
+        for child in children {
+            written |= self.serialize_block(child, written.then_some(1));
+        }
+    }
+
+    /// Keep `node`, a block quote or an alert, apart from a block quote or
+    /// an alert right before it with a comment, if `block_quote.separation`
+    /// asks for one.  The blank line between them is written before either
+    /// way, and is enough for CommonMark.
+    fn write_quote_separator<'b>(&mut self, node: &'b AstNode<'b>) {
+        let separated = matches!(
+            self.options.block_quote_separation,
+            BlockQuoteSeparation::Comment
+        );
+        if separated && node.previous_sibling().is_some_and(is_quote) {
+            self.begin_line();
+            self.output.push_str("<!-- -->\n");
+            self.write_line("");
         }
     }
 
     pub(super) fn serialize_block_quote<'b>(&mut self, node: &'b AstNode<'b>) {
+        self.write_quote_separator(node);
+        let outermost = !self.in_block_quote;
+        let start = self.output.len();
         let state = self.enter_blockquote_context();
         let children: Vec<_> = node.children().collect();
-        self.serialize_blockquote_children(&children, &state);
+        // An empty block quote is still a block quote; one whose reference
+        // definitions have moved away is dropped instead
+        let empty = self
+            .extract_source(node)
+            .is_some_and(|source| source.chars().all(|c| c == '>' || c.is_whitespace()));
+        if children.is_empty() && empty {
+            self.write_line("");
+        }
+        self.serialize_blockquote_children(&children);
         self.exit_blockquote_context(state);
+        if outermost && self.options.block_quote_preserve_marker_spacing {
+            self.restore_marker_spacing(node, start);
+        }
+    }
+
+    /// Give the lines of the block quote `node`, written to the output from
+    /// `start`, the spacing after `>` they have in the source, if their text
+    /// and their number of `>` markers are unchanged.
+    ///
+    /// Only lines of paragraphs right inside a block quote are changed, as
+    /// more spaces before other blocks, like list items, would move what
+    /// belongs to them, and so are source lines with more spaces than
+    /// a paragraph line may start with.  Source lines are matched in order.
+    fn restore_marker_spacing<'b>(&mut self, node: &'b AstNode<'b>, start: usize) {
+        let sourcepos = node.data.borrow().sourcepos;
+        let first_line = sourcepos.start.line.max(1);
+        let mut source: Vec<Option<&str>> = vec![None; sourcepos.end.line + 1 - first_line];
+        for paragraph in node.descendants() {
+            let in_quote = matches!(paragraph.data.borrow().value, NodeValue::Paragraph)
+                && paragraph
+                    .parent()
+                    .is_some_and(|p| matches!(p.data.borrow().value, NodeValue::BlockQuote));
+            if !in_quote {
+                continue;
+            }
+            let lines = paragraph.data.borrow().sourcepos;
+            for line in lines.start.line..=lines.end.line {
+                source[line - first_line] = self.source_lines.get(line - 1).copied();
+            }
+        }
+        let start = self.output[..start].rfind('\n').map_or(0, |i| i + 1);
+        let mut cursor = 0;
+        let mut restored = String::with_capacity(self.output.len() - start);
+        for (i, line) in self.output[start..].split('\n').enumerate() {
+            if i > 0 {
+                restored.push('\n');
+            }
+            let quoted =
+                split_quote_line(line).filter(|(lead, _, _)| i == 0 || lead.trim().is_empty());
+            let Some((lead, markers, text)) = quoted else {
+                restored.push_str(line);
+                continue;
+            };
+            let depth = markers.matches('>').count();
+            let found = source[cursor..]
+                .iter()
+                .enumerate()
+                .find_map(|(j, source_line)| {
+                    let (_, source_markers, source_text) = split_quote_line((*source_line)?)?;
+                    let same = source_text == text
+                        && source_markers.matches('>').count() == depth
+                        && source_markers.split('>').all(|spaces| spaces.len() <= 4);
+                    same.then_some((j, source_markers))
+                });
+            match found {
+                Some((j, source_markers)) => {
+                    restored.push_str(lead);
+                    restored.push_str(source_markers);
+                    restored.push_str(text);
+                    cursor += j + 1;
+                }
+                None => restored.push_str(line),
+            }
+        }
+        self.output.truncate(start);
+        self.output.push_str(&restored);
     }
 
     pub(super) fn serialize_alert<'b>(&mut self, node: &'b AstNode<'b>, alert_type: AlertType) {
+        self.write_quote_separator(node);
         // Output the alert header
         let type_str = match alert_type {
             AlertType::Note => "NOTE",
@@ -95,11 +207,7 @@ impl<'a> Serializer<'a> {
             AlertType::Warning => "WARNING",
             AlertType::Caution => "CAUTION",
         };
-        self.output.push_str(&self.list_item_indent);
-        self.output.push_str(&self.blockquote_prefix);
-        self.output.push_str("> [!");
-        self.output.push_str(type_str);
-        self.output.push_str("]\n");
+        self.write_line(&format!("> [!{}]", type_str));
 
         // Check if original source has a blank line after the alert header
         let children: Vec<_> = node.children().collect();
@@ -112,13 +220,11 @@ impl<'a> Serializer<'a> {
         };
 
         if has_blank_after_header {
-            self.output.push_str(&self.list_item_indent);
-            self.output.push_str(&self.blockquote_prefix);
-            self.output.push_str(">\n");
+            self.write_line(">");
         }
 
         let state = self.enter_blockquote_context();
-        self.serialize_blockquote_children(&children, &state);
+        self.serialize_blockquote_children(&children);
         self.exit_blockquote_context(state);
     }
 }
@@ -1,12 +1,55 @@
 //! Code block serialization logic.
 
-use comrak::nodes::NodeCodeBlock;
+use std::borrow::Cow;
 
-use super::Serializer;
+use super::{Serializer, embedded};
 
 /// The keyword to skip code formatting for a code block.
 const NO_FORMAT_KEYWORD: &str = "hongdown-no-format";
 
+/// Tilde fences up to the longest commonly needed length, sliced by
+/// [`fence_for`] to avoid allocating.
+const TILDE_FENCES: &str = "~~~~~~~~";
+
+/// Backtick fences up to the longest commonly needed length, sliced by
+/// [`fence_for`] to avoid allocating.
+const BACKTICK_FENCES: &str = "````````";
+
+/// Return the fence for a code block containing `literal`.
+///
+/// The fence is at least `min_len` characters long, and longer than any run
+/// of `fence_char` that starts a line of the literal (after indentation), so
+/// that no line of the literal can close the block.  Runs in the middle of a
+/// line can't close a fence, so they are ignored.
+///
+/// Fence characters are ASCII, so the literal is scanned as bytes.
+pub(super) fn fence_for(literal: &str, fence_char: char, min_len: usize) -> Cow<'static, str> {
+    debug_assert!(fence_char.is_ascii());
+    let fence_byte = fence_char as u8;
+    let longest_run = literal
+        .as_bytes()
+        .split(|&b| b == b'\n')
+        .map(|line| {
+            line.iter()
+                .skip_while(|b| b.is_ascii_whitespace())
+                .take_while(|&&b| b == fence_byte)
+                .count()
+        })
+        .max()
+        .unwrap_or(0);
+    let len = min_len.max(longest_run + 1);
+
+    let cached = match fence_char {
+        '~' => TILDE_FENCES,
+        '`' => BACKTICK_FENCES,
+        _ => "",
+    };
+    match cached.get(..len) {
+        Some(fence) => Cow::Borrowed(fence),
+        None => Cow::Owned(std::iter::repeat_n(fence_char, len).collect()),
+    }
+}
+
 /// Parse the code block info string to extract language and no-format flag.
 ///
 /// The info string can contain a language identifier followed by optional
@@ -35,24 +78,56 @@ fn parse_code_info(info: &str) -> (&str, &str, bool) {
 }
 
 impl<'a> Serializer<'a> {
+    /// Try to format code in `language`: with its external formatter, if
+    /// one is configured, or else by Hongdown itself, if the language is in
+    /// `code_block_format_embedded`.
+    fn format_code(&mut self, language: &str, code: &str, line: usize) -> Option<String> {
+        if let Some(embedded) = embedded::embedded_language(language)
+            && self.options.code_block_format_embedded.contains(&embedded)
+            && !self.options.code_formatters.contains_key(language)
+        {
+            if code.trim().is_empty() {
+                return None;
+            }
+            let key_order = self.options.code_block_json_key_order;
+            return match embedded::format_embedded(embedded, code, key_order) {
+                Ok(formatted) => Some(formatted),
+                Err(e) => {
+                    self.add_warning(
+                        line,
+                        format!(
+                            "{} code block left as is, as it doesn't parse: {}",
+                            language, e
+                        ),
+                    );
+                    None
+                }
+            };
+        }
+        self.try_format_code(language, code, line)
+    }
+
     /// Try to format code using an external formatter.
     ///
     /// Returns `Some(formatted_code)` if a formatter is configured for the language
     /// and succeeds. Returns `None` if no formatter is configured or if the formatter
-    /// fails (in which case a warning is added).
+    /// fails (in which case a warning is added).  A code formatter callback,
+    /// when one is given, takes the place of the configured formatters.
     #[cfg(not(target_arch = "wasm32"))]
-    fn try_format_code(&mut self, language: &str, code: &str) -> Option<String> {
+    fn try_format_code(&mut self, language: &str, code: &str, line: usize) -> Option<String> {
         use super::formatter::run_formatter;
 
+        #[cfg(feature = "wasm")]
+        if let Some(ref callback) = self.code_formatter_callback {
+            return callback(language, code);
+        }
         let formatter = self.options.code_formatters.get(language)?;
 
         match run_formatter(&formatter.command, code, formatter.timeout_secs) {
             Ok(formatted) => Some(formatted),
             Err(e) => {
-                // Add warning with line 0 for now (we don't have source position here)
-                // This will be improved when we have access to the node's source position
                 self.add_warning(
-                    0,
+                    line,
                     format!(
                         "code formatter '{}' failed for language '{}': {}",
                         formatter.command.join(" "),
@@ -67,7 +142,7 @@ impl<'a> Serializer<'a> {
 
     /// WASM: use the callback if provided.
     #[cfg(target_arch = "wasm32")]
-    fn try_format_code(&mut self, language: &str, code: &str) -> Option<String> {
+    fn try_format_code(&mut self, language: &str, code: &str, _line: usize) -> Option<String> {
         #[cfg(feature = "wasm")]
         if let Some(ref callback) = self.code_formatter_callback {
             return callback(language, code);
@@ -75,69 +150,14 @@ impl<'a> Serializer<'a> {
         None
     }
 
-    /// Serialize a code block with indent for description list details.
-    pub(super) fn serialize_code_block_with_indent(&mut self, code: &NodeCodeBlock, indent: &str) {
-        let fence_char = self.options.fence_char.as_char();
-        let min_len = self.options.min_fence_length.get();
-        let base_fence: String = std::iter::repeat_n(fence_char, min_len).collect();
-        let long_fence: String = std::iter::repeat_n(fence_char, min_len + 1).collect();
-
-        // Parse info to get language and check for no-format flag
-        let (parsed_lang, info_output, skip_format) = parse_code_info(&code.info);
-
-        // Determine language for formatter lookup (use default if empty)
-        let language = if parsed_lang.is_empty() {
-            &self.options.default_language
-        } else {
-            parsed_lang
-        };
-
-        // Determine the info string to output
-        let output_info = if info_output.is_empty() && !self.options.default_language.is_empty() {
-            self.options.default_language.as_str()
-        } else {
-            info_output
-        };
-
-        // Try to format the code if a formatter is configured and not skipped
-        let formatted_literal = if !language.is_empty() && !skip_format {
-            self.try_format_code(language, &code.literal)
-        } else {
-            None
-        };
-        let literal = formatted_literal.as_deref().unwrap_or(&code.literal);
-
-        let fence = if literal.contains(&base_fence) {
-            &long_fence
-        } else {
-            &base_fence
-        };
-        self.output.push_str(fence);
-        if !output_info.is_empty() {
-            if self.options.space_after_fence {
-                self.output.push(' ');
-            }
-            self.output.push_str(output_info);
-        }
-        self.output.push('\n');
-        // Add indent to each line of code (skip indent for empty lines)
-        for line in literal.lines() {
-            if !line.is_empty() {
-                self.output.push_str(indent);
-                self.output.push_str(line);
-            }
-            self.output.push('\n');
-        }
-        // Handle trailing newline in literal
-        if !literal.ends_with('\n') && !literal.is_empty() {
-            self.output.push('\n');
-        }
-        self.output.push_str(indent);
-        self.output.push_str(fence);
-        self.output.push('\n');
-    }
-
-    pub(super) fn serialize_code_block(&mut self, info: &str, literal: &str) {
+    /// Serialize a fenced code block.
+    ///
+    /// Every line, including the fences, starts with the current line
+    /// prefix, so the same code serves code blocks at the top level and
+    /// inside block quotes, list items, and description details.  A warning
+    /// about the code block, like a code formatter failing, is reported at
+    /// `line`, where it starts in the source.
+    pub(super) fn serialize_code_block(&mut self, info: &str, literal: &str, line: usize) {
         // Determine the minimum fence length from options
         let min_fence_length = self.options.min_fence_length.get();
         let fence_char = self.options.fence_char.as_char();
@@ -161,34 +181,17 @@ impl<'a> Serializer<'a> {
 
         // Try to format the code if a formatter is configured and not skipped
         let formatted_literal = if !language.is_empty() && !skip_format {
-            self.try_format_code(language, literal)
+            self.format_code(language, literal, line)
         } else {
             None
         };
         let content = formatted_literal.as_deref().unwrap_or(literal);
 
-        // Find the longest sequence of fence characters in the content
-        let max_fence_in_content = content
-            .lines()
-            .filter_map(|line| {
-                let trimmed = line.trim_start();
-                if trimmed.starts_with(fence_char) {
-                    Some(trimmed.chars().take_while(|&c| c == fence_char).count())
-                } else {
-                    None
-                }
-            })
-            .max()
-            .unwrap_or(0);
-
         // Fence length must be greater than any fence sequence in content
-        let fence_length = std::cmp::max(min_fence_length, max_fence_in_content + 1);
-        let fence: String = std::iter::repeat_n(fence_char, fence_length).collect();
+        let fence = fence_for(content, fence_char, min_fence_length);
 
         // Opening fence
-        if self.in_block_quote {
-            self.output.push_str("> ");
-        }
+        self.begin_line();
         self.output.push_str(&fence);
         if !output_info.is_empty() {
             if self.options.space_after_fence {
@@ -200,108 +203,49 @@ impl<'a> Serializer<'a> {
 
         // Content lines
         for line in content.lines() {
-            if self.in_block_quote {
-                if line.is_empty() {
-                    self.output.push('>');
-                } else {
-                    self.output.push_str("> ");
-                }
-            }
-            self.output.push_str(line);
-            self.output.push('\n');
+            self.write_line(&self.code_line(line));
         }
 
         // Closing fence
-        if self.in_block_quote {
-            self.output.push_str("> ");
-        }
-        self.output.push_str(&fence);
-        self.output.push('\n');
+        self.write_line(&fence);
     }
+}
 
-    /// Serialize a code block with indentation prefix on each line.
-    /// Used for code blocks inside list items.
-    pub(super) fn serialize_code_block_indented(
-        &mut self,
-        info: &str,
-        literal: &str,
-        indent: &str,
-    ) {
-        // Determine the minimum fence length from options
-        let min_fence_length = self.options.min_fence_length.get();
-        let fence_char = self.options.fence_char.as_char();
-
-        // Parse info to get language and check for no-format flag
-        let (parsed_lang, info_output, skip_format) = parse_code_info(info);
-
-        // Use default_language if no language specified
-        let language = if parsed_lang.is_empty() {
-            &self.options.default_language
-        } else {
-            parsed_lang
-        };
-
-        // Determine the info string to output
-        let output_info = if info_output.is_empty() && !self.options.default_language.is_empty() {
-            self.options.default_language.as_str()
-        } else {
-            info_output
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Try to format the code if a formatter is configured and not skipped
-        let formatted_literal = if !language.is_empty() && !skip_format {
-            self.try_format_code(language, literal)
-        } else {
-            None
-        };
-        let content = formatted_literal.as_deref().unwrap_or(literal);
-
-        // Find the longest sequence of fence characters in the content
-        let max_fence_in_content = content
-            .lines()
-            .filter_map(|line| {
-                let trimmed = line.trim_start();
-                if trimmed.starts_with(fence_char) {
-                    Some(trimmed.chars().take_while(|&c| c == fence_char).count())
-                } else {
-                    None
-                }
-            })
-            .max()
-            .unwrap_or(0);
+    #[test]
+    fn test_fence_for_without_runs() {
+        assert_eq!(fence_for("let x = 1;\n", '~', 4), "~~~~");
+        assert_eq!(fence_for("", '`', 3), "```");
+        assert!(matches!(fence_for("code\n", '~', 4), Cow::Borrowed(_)));
+    }
 
-        // Fence length must be greater than any fence sequence in content
-        let fence_length = std::cmp::max(min_fence_length, max_fence_in_content + 1);
-        let fence: String = std::iter::repeat_n(fence_char, fence_length).collect();
+    #[test]
+    fn test_fence_for_run_at_line_start() {
+        assert_eq!(fence_for("~~~~\ncode\n~~~~\n", '~', 4), "~~~~~");
+        assert_eq!(fence_for("``````\n", '`', 3), "```````");
+        // Runs of the other fence character don't matter
+        assert_eq!(fence_for("``````\n", '~', 4), "~~~~");
+    }
 
-        // Output opening fence with optional language
-        self.output.push_str(&fence);
-        if !output_info.is_empty() {
-            if self.options.space_after_fence {
-                self.output.push(' ');
-            }
-            self.output.push_str(output_info);
-        }
-        self.output.push('\n');
+    #[test]
+    fn test_fence_for_run_after_indentation() {
+        assert_eq!(fence_for("  ~~~~~\n", '~', 4), "~~~~~~");
+        assert_eq!(fence_for("\t````\n", '`', 3), "`````");
+    }
 
-        // Output content with indentation (skip indent for empty lines)
-        for line in content.lines() {
-            if self.in_block_quote {
-                self.output.push_str("> ");
-            }
-            if !line.is_empty() {
-                self.output.push_str(indent);
-                self.output.push_str(line);
-            }
-            self.output.push('\n');
-        }
+    #[test]
+    fn test_fence_for_run_mid_line() {
+        assert_eq!(fence_for("a ~~~~~~ b\n", '~', 4), "~~~~");
+        assert_eq!(fence_for("x = ```` y\n", '`', 3), "```");
+    }
 
-        // Output closing fence with indentation
-        if self.in_block_quote {
-            self.output.push_str("> ");
-        }
-        self.output.push_str(indent);
-        self.output.push_str(&fence);
-        self.output.push('\n');
+    #[test]
+    fn test_fence_for_long_run() {
+        let fence = fence_for("~~~~~~~~~~\n", '~', 4);
+        assert_eq!(fence, "~".repeat(11));
+        assert!(matches!(fence, Cow::Owned(_)));
     }
 }
@@ -0,0 +1,182 @@
+//! Container block (`:::`) serialization logic.
+//!
+//! Container blocks, like Pandoc's fenced divs and the admonitions of
+//! Docusaurus and VitePress, are not CommonMark, so with the `containers`
+//! option on, their fences are swapped for those of comrak's multiline block
+//! quotes (`>>>`) before parsing.  What is inside a container is then parsed
+//! as blocks of its own, rather than as part of a paragraph or list item
+//! that happens to be next to a fence.  The original fences are read back
+//! from the source when the container is written.
+
+use std::borrow::Cow;
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::Serializer;
+
+/// The opening fence of a container, or `None` for a closing fence or
+/// a line that isn't a fence: up to three spaces, three or more colons, and
+/// attributes, like `::: warning` or `:::note`.  Returns what follows the
+/// colons, with the space before the attributes if there is any.
+fn opening_fence(line: &str) -> Option<&str> {
+    fence_rest(line).filter(|rest| !rest.trim().is_empty())
+}
+
+/// Whether `line` is a closing fence of a container: only colons, three or
+/// more of them, after up to three spaces.
+fn is_closing_fence(line: &str) -> bool {
+    fence_rest(line).is_some_and(|rest| rest.trim().is_empty())
+}
+
+/// What comes after the colons of a fence line, without trailing
+/// whitespace, or `None` if the line isn't one.
+fn fence_rest(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let rest = trimmed.trim_start_matches(':');
+    if trimmed.len() - rest.len() < 3 {
+        return None;
+    }
+    Some(rest.trim_end())
+}
+
+/// The code fence `line` opens or closes, as its character and length.
+pub(super) fn code_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let ch = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = trimmed.len() - trimmed.trim_start_matches(ch).len();
+    (length >= 3).then_some((ch, length))
+}
+
+/// Replace the fences of the container blocks in `input` with the fences
+/// of multiline block quotes, leaving every other line where it is.
+///
+/// Only fences outside code blocks and not inside block quotes or list
+/// items are recognized, and only those that pair up: an opening fence
+/// without a closing one is kept as it is.  Containers with others inside
+/// get longer fences, as multiline block quotes need them to nest.
+pub(crate) fn mark_fences(input: &str) -> Cow<'_, str> {
+    let lines: Vec<&str> = input.split_inclusive('\n').collect();
+    // The line index of the opening and closing fences of each container,
+    // and how many levels of containers it holds, including itself
+    let mut containers: Vec<(usize, usize, usize)> = Vec::new();
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut code: Option<(char, usize)> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if let Some((ch, length)) = code {
+            let closes = code_fence(line).is_some_and(|(c, l)| c == ch && l >= length)
+                && line.trim_start().trim_start_matches(ch).trim().is_empty();
+            if closes {
+                code = None;
+            }
+        } else if let Some(fence) = code_fence(line) {
+            code = Some(fence);
+        } else if opening_fence(line).is_some() {
+            open.push((i, 1));
+        } else if is_closing_fence(line)
+            && let Some((start, height)) = open.pop()
+        {
+            containers.push((start, i, height));
+            if let Some(parent) = open.last_mut() {
+                parent.1 = parent.1.max(height + 1);
+            }
+        }
+    }
+    if containers.is_empty() {
+        return Cow::Borrowed(input);
+    }
+    let mut fences = vec![None; lines.len()];
+    for (start, end, height) in containers {
+        let fence = ">".repeat(height + 2);
+        fences[start] = Some(fence.clone());
+        fences[end] = Some(fence);
+    }
+    let mut marked = String::with_capacity(input.len());
+    for (line, fence) in lines.iter().zip(fences) {
+        match fence {
+            Some(fence) => {
+                marked.push_str(&fence);
+                marked.push_str(&line[line.trim_end_matches(['\n', '\r']).len()..]);
+            }
+            None => marked.push_str(line),
+        }
+    }
+    Cow::Owned(marked)
+}
+
+impl<'a> Serializer<'a> {
+    /// Serialize a container block, which was parsed as a multiline block
+    /// quote (see [`mark_fences`]).
+    ///
+    /// The fences get three colons, and one more for each level of
+    /// containers inside, and what follows the colons of the opening fence
+    /// is kept as it is.  The blocks inside are set apart from the fences by
+    /// blank lines.
+    pub(super) fn serialize_container<'b>(&mut self, node: &'b AstNode<'b>) {
+        let start_line = node.data.borrow().sourcepos.start.line;
+        let attributes = start_line
+            .checked_sub(1)
+            .and_then(|i| self.source_lines.get(i))
+            .and_then(|line| opening_fence(line))
+            .unwrap_or_default()
+            .to_string();
+        let height = node
+            .descendants()
+            .map(|descendant| {
+                descendant
+                    .ancestors()
+                    .take_while(|ancestor| !std::ptr::eq(*ancestor, node))
+                    .filter(|ancestor| is_container(ancestor))
+                    .count()
+            })
+            .max()
+            .unwrap_or(0);
+        let fence = ":".repeat(height + 3);
+
+        self.write_line(&format!("{}{}", fence, attributes));
+        for child in node.children() {
+            self.write_line("");
+            self.serialize_node(child);
+        }
+        if node.first_child().is_some() {
+            self.write_line("");
+        }
+        self.write_line(&fence);
+    }
+}
+
+/// Whether `node` is a container block.
+fn is_container<'b>(node: &'b AstNode<'b>) -> bool {
+    matches!(node.data.borrow().value, NodeValue::MultilineBlockQuote(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_fences() {
+        let input = "::: warning\ntext\n:::\n\n```\n:::\n```\n";
+        assert_eq!(mark_fences(input), ">>>\ntext\n>>>\n\n```\n:::\n```\n");
+    }
+
+    #[test]
+    fn test_mark_fences_nested() {
+        let input = ":::: outer\n::: inner\ntext\n:::\n::::\n";
+        assert_eq!(mark_fences(input), ">>>>\n>>>\ntext\n>>>\n>>>>\n");
+    }
+
+    #[test]
+    fn test_mark_fences_unpaired() {
+        let input = "::: warning\ntext\n";
+        assert!(matches!(mark_fences(input), Cow::Borrowed(_)));
+        let input = "text\n:::\n";
+        assert!(matches!(mark_fences(input), Cow::Borrowed(_)));
+    }
+}
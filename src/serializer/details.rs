@@ -0,0 +1,192 @@
+//! HTML `<details>` blocks with Markdown inside.
+//!
+//! An HTML block goes on up to the next blank line, so the Markdown inside a
+//! `<details>` element is only Markdown, to GitHub and to the parser, if
+//! there are blank lines between it and the tags around it.  With the
+//! `html.details` option set to `format`, those blank lines are put in the
+//! source before it is parsed: after the `<summary>` of each `<details>`
+//! (or after its opening tag, if it has no summary), and before and after
+//! its closing tag.  The tags themselves are kept as they are, and what is
+//! between them is formatted like the rest of the document.
+
+use std::borrow::Cow;
+
+use super::inline_footnote::mask_code_blocks;
+use super::state::Directive;
+use crate::DetailsHandling;
+
+/// The tag `line` starts with, lowercased, if it is indented by no more than
+/// 3 spaces, as the tags that start HTML blocks are.
+fn tag_start(line: &str) -> Option<String> {
+    let trimmed = line.trim_start_matches(' ');
+    (line.len() - trimmed.len() <= 3 && trimmed.starts_with('<'))
+        .then(|| trimmed.trim_end().to_lowercase())
+}
+
+/// Whether `tag` starts with the opening tag of an element `name`.
+fn opens(tag: &str, name: &str) -> bool {
+    tag.strip_prefix('<')
+        .and_then(|rest| rest.strip_prefix(name))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '>']))
+}
+
+/// `input` made ready to be parsed with the `<details>` blocks in it, as
+/// `mode` says, and the numbers of the blank lines put in, from 1, in
+/// ascending order.
+pub(crate) fn prepare(input: &str, mode: DetailsHandling) -> (Cow<'_, str>, Vec<usize>) {
+    if mode == DetailsHandling::Preserve || !input.to_lowercase().contains("<details") {
+        return (Cow::Borrowed(input), Vec::new());
+    }
+    let masked = mask_code_blocks(input);
+    let mut result = String::with_capacity(input.len());
+    let mut inserted = Vec::new();
+    let mut line_number = 0;
+    // The number of `<details>` elements open
+    let mut depth = 0;
+    // Whether the line before is the opening tag of a `<details>`, whose
+    // `<summary>` may be on the next line
+    let mut after_opening = false;
+    // Whether the `<summary>` of a `<details>` is open
+    let mut in_summary = false;
+    // Whether a blank line goes after the line before
+    let mut blank_after = false;
+    let mut disabled = false;
+    let mut previous_blank = true;
+    let mut offset = 0;
+    for (line, masked_line) in input
+        .split_inclusive('\n')
+        .zip(masked.split_inclusive('\n'))
+    {
+        let blank = line.trim().is_empty();
+        let tag = tag_start(masked_line);
+        match Directive::parse(masked_line) {
+            Some(Directive::DisableFile) => {
+                result.push_str(&input[offset..]);
+                break;
+            }
+            Some(Directive::Disable) => disabled = true,
+            Some(Directive::Enable) => disabled = false,
+            _ => {}
+        }
+        let lowered = masked_line.to_lowercase();
+        let closing = depth > 0
+            && tag
+                .as_deref()
+                .is_some_and(|tag| tag.starts_with("</details"));
+        let summary = after_opening && tag.as_deref().is_some_and(|tag| opens(tag, "summary"));
+        if after_opening && !summary {
+            blank_after = true;
+        }
+        after_opening = false;
+        if (blank_after || closing) && !blank && !previous_blank && !disabled {
+            line_number += 1;
+            inserted.push(line_number);
+            result.push('\n');
+        }
+        blank_after = false;
+        previous_blank = blank;
+        line_number += 1;
+        offset += line.len();
+        result.push_str(line);
+        if disabled {
+            continue;
+        }
+        if summary {
+            in_summary = true;
+        }
+        if closing {
+            depth -= 1;
+            in_summary = false;
+            blank_after = true;
+        } else if let Some(tag) = tag.as_deref().filter(|tag| opens(tag, "details"))
+            && !tag.contains("</details")
+        {
+            depth += 1;
+            if lowered.contains("</summary>") {
+                blank_after = true;
+            } else {
+                in_summary = lowered.contains("<summary");
+                after_opening = !in_summary;
+            }
+        } else if in_summary && lowered.contains("</summary>") {
+            in_summary = false;
+            blank_after = true;
+        }
+    }
+    if inserted.is_empty() {
+        (Cow::Borrowed(input), inserted)
+    } else {
+        (Cow::Owned(result), inserted)
+    }
+}
+
+/// The number of the line `line` of the input [`prepare`] returned is in
+/// the input given to it, with the numbers of the blank lines put in.
+pub(crate) fn original_line(line: usize, inserted: &[usize]) -> usize {
+    line - inserted.partition_point(|&blank| blank < line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepared(input: &str) -> String {
+        prepare(input, DetailsHandling::Format).0.into_owned()
+    }
+
+    #[test]
+    fn test_prepare() {
+        assert_eq!(
+            prepared("<details>\n<summary>More</summary>\n- a\n</details>\ntext\n"),
+            "<details>\n<summary>More</summary>\n\n- a\n\n</details>\n\ntext\n"
+        );
+        assert_eq!(
+            prepared("<details open><summary>\nMore\n</summary>\n*a*\n</details>\n"),
+            "<details open><summary>\nMore\n</summary>\n\n*a*\n\n</details>\n"
+        );
+        assert_eq!(
+            prepared("<details>\n*a*\n</details>\n"),
+            "<details>\n\n*a*\n\n</details>\n"
+        );
+    }
+
+    #[test]
+    fn test_prepare_unchanged() {
+        for input in [
+            "<details>\n<summary>More</summary>\n\n- a\n\n</details>\n",
+            "<details><summary>More</summary>text</details>\n",
+            "```html\n<details>\n<summary>More</summary>\n- a\n</details>\n```\n",
+            "<!-- hongdown-disable -->\n<details>\n- a\n</details>\n",
+            "<detailsx>\n- a\n</details>\n",
+            "    <details>\n    - a\n    </details>\n",
+        ] {
+            let (output, inserted) = prepare(input, DetailsHandling::Format);
+            assert_eq!(output, input);
+            assert!(inserted.is_empty());
+        }
+        let input = "<details>\n- a\n</details>\n";
+        assert_eq!(prepare(input, DetailsHandling::Preserve).0, input);
+    }
+
+    #[test]
+    fn test_prepare_nested() {
+        assert_eq!(
+            prepared(
+                "<details>\n<summary>A</summary>\n<details>\n<summary>B</summary>\n- b\n</details>\n- a\n</details>\n"
+            ),
+            "<details>\n<summary>A</summary>\n\n<details>\n<summary>B</summary>\n\n- b\n\n</details>\n\n- a\n\n</details>\n"
+        );
+    }
+
+    #[test]
+    fn test_original_line() {
+        let (_, inserted) = prepare(
+            "<details>\n<summary>More</summary>\n- a\n</details>\n",
+            DetailsHandling::Format,
+        );
+        assert_eq!(inserted, [3, 5]);
+        assert_eq!(original_line(2, &inserted), 2);
+        assert_eq!(original_line(4, &inserted), 3);
+        assert_eq!(original_line(6, &inserted), 4);
+    }
+}
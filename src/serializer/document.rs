@@ -1,20 +1,36 @@
 //! Document-level serialization logic.
 
-use comrak::nodes::{AstNode, NodeValue};
+use comrak::nodes::{AstNode, ListType, NodeValue};
 use regex::Regex;
 use unicode_width::UnicodeWidthStr;
 
-use super::Serializer;
-use super::state::{Directive, FormatSkipMode};
-use super::wrap;
+use indexmap::IndexMap;
+
+use super::state::{Directive, FormatSkipMode, ReferenceLink, reference_key};
+use super::{LinePrefix, Serializer, escape, whitespace, wrap};
+use crate::TemplateSyntax;
 
 impl<'a> Serializer<'a> {
     pub(super) fn serialize_document<'b>(&mut self, node: &'b AstNode<'b>) {
         let children: Vec<_> = node.children().collect();
 
+        // Paragraphs where `line-length` is disabled keep their line breaks
+        self.no_wrap_ranges = Self::collect_rule_ranges(node)
+            .into_iter()
+            .filter(|(rule, _, _)| *rule == "line-length")
+            .map(|(_, start, end)| (start, end))
+            .collect();
+
         // Check for undefined reference links using AST
         self.check_undefined_references_ast(node);
 
+        // Check that links to fragments of this document have a target
+        self.check_fragment_links(node);
+
+        // Look at the reference definitions themselves, which comrak keeps
+        // out of the AST: report near-duplicates and remember unused ones
+        self.collect_source_definitions(node);
+
         // First pass: collect all footnote reference lines
         // This is needed because FootnoteDefinition nodes come at the end of the AST,
         // but we need to know reference lines before flushing at section boundaries
@@ -32,7 +48,17 @@ impl<'a> Serializer<'a> {
         // These should be output after reference definitions to maintain their position
         let trailing_html_start = self.find_trailing_html_blocks(&children);
 
+        // Index of the end marker of the table of contents being replaced
+        let mut toc_end = None;
+        // The last block written, which the blank lines before the next
+        // one depend on
+        let mut previous = None;
+
         for (i, child) in children.iter().enumerate() {
+            // Skip the previous content of a regenerated table of contents
+            if toc_end.is_some_and(|end| i <= end) {
+                continue;
+            }
             // Skip trailing HTML blocks for now - they'll be output after references
             if i >= trailing_html_start
                 && let NodeValue::HtmlBlock(_) = &child.data.borrow().value
@@ -43,6 +69,7 @@ impl<'a> Serializer<'a> {
             if let NodeValue::FootnoteDefinition(_) = &child.data.borrow().value {
                 continue;
             }
+            let before = previous.replace(*child);
             // Check for directives in HTML blocks
             if let NodeValue::HtmlBlock(html_block) = &child.data.borrow().value
                 && let Some(directive) = Directive::parse(&html_block.literal)
@@ -65,7 +92,8 @@ impl<'a> Serializer<'a> {
                             self.extract_source_from_line(directive_end_line + 1)
                         {
                             self.output.push('\n');
-                            self.output.push_str(&remaining);
+                            self.output
+                                .push_str(&whitespace::keep_trailing_whitespace(&remaining));
                         }
                         return;
                     }
@@ -79,9 +107,7 @@ impl<'a> Serializer<'a> {
 
                         self.skip_mode = FormatSkipMode::NextBlock;
                         // Output the directive comment
-                        if i > 0 {
-                            self.output.push('\n');
-                        }
+                        self.start_document_block(before, child);
                         self.output.push_str(&html_block.literal);
                         continue;
                     }
@@ -95,9 +121,7 @@ impl<'a> Serializer<'a> {
 
                         self.skip_mode = FormatSkipMode::UntilSection;
                         // Output the directive comment
-                        if i > 0 {
-                            self.output.push('\n');
-                        }
+                        self.start_document_block(before, child);
                         self.output.push_str(&html_block.literal);
                         continue;
                     }
@@ -111,18 +135,14 @@ impl<'a> Serializer<'a> {
 
                         self.skip_mode = FormatSkipMode::Disabled;
                         // Output the directive comment
-                        if i > 0 {
-                            self.output.push('\n');
-                        }
+                        self.start_document_block(before, child);
                         self.output.push_str(&html_block.literal);
                         continue;
                     }
                     Directive::Enable => {
                         self.skip_mode = FormatSkipMode::None;
                         // Output the directive comment
-                        if i > 0 {
-                            self.output.push('\n');
-                        }
+                        self.start_document_block(before, child);
                         self.output.push_str(&html_block.literal);
                         continue;
                     }
@@ -130,9 +150,7 @@ impl<'a> Serializer<'a> {
                         // Add to directive proper nouns list
                         self.directive_proper_nouns.extend(nouns);
                         // Output the directive comment
-                        if i > 0 {
-                            self.output.push('\n');
-                        }
+                        self.start_document_block(before, child);
                         self.output.push_str(&html_block.literal);
                         continue;
                     }
@@ -140,12 +158,53 @@ impl<'a> Serializer<'a> {
                         // Add to directive common nouns list
                         self.directive_common_nouns.extend(nouns);
                         // Output the directive comment
-                        if i > 0 {
-                            self.output.push('\n');
+                        self.start_document_block(before, child);
+                        self.output.push_str(&html_block.literal);
+                        continue;
+                    }
+                    Directive::NoWrap => {
+                        // Handled by serialize_node() for the next block;
+                        // output the directive comment
+                        self.start_document_block(before, child);
+                        self.output.push_str(&html_block.literal);
+                        continue;
+                    }
+                    Directive::DisableRules(rules)
+                    | Directive::EnableRules(rules)
+                    | Directive::DisableFileRules(rules) => {
+                        // Their ranges are collected before the document is
+                        // written; output the directive comment
+                        let line = child.data.borrow().sourcepos.start.line;
+                        for rule in rules {
+                            if crate::lint::rule_name(&rule).is_none() {
+                                self.add_warning(line, format!("unknown rule: {}", rule));
+                            }
                         }
+                        self.start_document_block(before, child);
                         self.output.push_str(&html_block.literal);
                         continue;
                     }
+                    Directive::TocStart if !self.should_skip_formatting() => {
+                        // A start marker without an end marker is left alone
+                        if let Some(end) = Self::find_toc_end(&children, i) {
+                            self.start_document_block(before, child);
+                            self.output.push_str(&html_block.literal);
+                            self.output.push('\n');
+                            let start_line = child.data.borrow().sourcepos.end.line + 1;
+                            let end_line = children[end].data.borrow().sourcepos.start.line;
+                            if self.serialize_toc(node, start_line..end_line) {
+                                self.output.push('\n');
+                            }
+                            if let NodeValue::HtmlBlock(end_block) =
+                                &children[end].data.borrow().value
+                            {
+                                self.output.push_str(&end_block.literal);
+                            }
+                            toc_end = Some(end);
+                            continue;
+                        }
+                    }
+                    Directive::TocStart | Directive::TocEnd => {}
                 }
             }
 
@@ -155,10 +214,9 @@ impl<'a> Serializer<'a> {
                 NodeValue::Heading(h) => Some(h.level),
                 _ => None,
             };
-            let is_h2 = heading_level == Some(2);
             let is_h2_or_h3 = matches!(heading_level, Some(2) | Some(3));
 
-            if is_h2_or_h3 && i > 0 {
+            if is_h2_or_h3 && before.is_some() {
                 // Get the source line of the heading to flush only earlier footnotes
                 let heading_line = child.data.borrow().sourcepos.start.line;
                 // Footnotes come before link reference definitions
@@ -167,29 +225,9 @@ impl<'a> Serializer<'a> {
                 self.flush_footnote_references_before(Some(heading_line));
             }
 
-            // Add blank line between block elements (except after front matter)
-            if i > 0 {
-                let prev_is_front_matter = matches!(
-                    &children[i - 1].data.borrow().value,
-                    NodeValue::FrontMatter(_)
-                );
-                if prev_is_front_matter {
-                    // No extra blank line needed after front matter
-                } else if is_h2 {
-                    // Check if previous element was a heading (empty section)
-                    let prev_is_heading =
-                        matches!(&children[i - 1].data.borrow().value, NodeValue::Heading(_));
-                    if prev_is_heading {
-                        // Just one blank line between consecutive headings
-                        self.output.push('\n');
-                    } else {
-                        // Two blank lines before h2 sections (one after content + one extra)
-                        self.output.push_str("\n\n");
-                    }
-                } else {
-                    self.output.push('\n');
-                }
-            }
+            let block_start = self.output.len();
+            self.start_document_block(before, child);
+            let content_start = self.output.len();
 
             // Check if this block should be output as-is (skip formatting)
             if self.should_skip_formatting() {
@@ -212,7 +250,8 @@ impl<'a> Serializer<'a> {
 
                 // Output the original source
                 if let Some(source) = self.extract_source(child) {
-                    self.output.push_str(&source);
+                    self.output
+                        .push_str(&whitespace::keep_trailing_whitespace(&source));
                     self.output.push('\n');
                 } else {
                     self.serialize_node(child);
@@ -221,15 +260,43 @@ impl<'a> Serializer<'a> {
             }
 
             self.serialize_node(child);
+            // A block that writes nothing, like a block quote with nothing
+            // but link reference definitions, doesn't get blank lines
+            if self.output.len() == content_start {
+                self.output.truncate(block_start);
+                previous = before;
+            }
         }
 
         // Footnotes come before link reference definitions
         self.flush_footnotes();
+        self.keep_unused_definitions();
         self.flush_references();
         self.flush_footnote_references();
 
         // Output trailing HTML blocks after references and footnotes
         self.output_trailing_html_blocks(&children, trailing_html_start);
+
+        if self.options.templates != TemplateSyntax::None {
+            self.restore_template_spaces();
+        }
+    }
+
+    /// Find the index of the end marker of the table of contents whose
+    /// start marker is at `start`, if there is one.
+    fn find_toc_end<'b>(children: &[&'b AstNode<'b>], start: usize) -> Option<usize> {
+        children
+            .iter()
+            .enumerate()
+            .skip(start + 1)
+            .find(|(_, child)| {
+                matches!(
+                    &child.data.borrow().value,
+                    NodeValue::HtmlBlock(html_block)
+                        if Directive::parse(&html_block.literal) == Some(Directive::TocEnd)
+                )
+            })
+            .map(|(i, _)| i)
     }
 
     /// Find the index where trailing HTML blocks start.
@@ -276,9 +343,10 @@ impl<'a> Serializer<'a> {
             }
 
             if let NodeValue::HtmlBlock(html_block) = &child.data.borrow().value {
-                // Add a blank line before the first trailing HTML block
+                // Add a blank line before the first trailing HTML block,
+                // unless it is the first block of the document
                 if is_first {
-                    if !self.output.ends_with("\n\n") {
+                    if !self.output.is_empty() && !self.output.ends_with("\n\n") {
                         if self.output.ends_with('\n') {
                             self.output.push('\n');
                         } else {
@@ -287,7 +355,7 @@ impl<'a> Serializer<'a> {
                     }
                     is_first = false;
                 }
-                self.output.push_str(&html_block.literal);
+                self.serialize_html_block(&html_block.literal);
             }
         }
     }
@@ -299,12 +367,10 @@ impl<'a> Serializer<'a> {
         let was_in_description_details = self.in_description_details;
         self.in_description_details = true;
 
-        // Determine the prefix for blockquote context
-        let blockquote_prefix = if self.in_block_quote {
-            format!("{}{}", self.blockquote_outer_indent, self.blockquote_prefix)
-        } else {
-            String::new()
-        };
+        // The prefix of the block quotes and list items the details are in;
+        // the term before them may have left its line open
+        let blockquote_prefix = self.line_prefix();
+        self.start_block(0);
 
         for (i, child) in children.iter().enumerate() {
             let child_value = &child.data.borrow().value;
@@ -318,26 +384,22 @@ impl<'a> Serializer<'a> {
                         let mut content = String::new();
                         self.collect_inline_content(child, &mut content);
                         let continuation = format!("{}    ", blockquote_prefix);
-                        let wrapped = wrap::wrap_text_first_line(
-                            content.trim(),
-                            "",
-                            &continuation,
-                            self.options.line_width.get(),
-                        );
+                        let wrapped = self.wrap_after_output(content.trim(), &continuation);
                         self.output.push_str(&wrapped);
                         self.output.push('\n');
                     }
                     NodeValue::CodeBlock(code) => {
                         // Code block as first child (unusual but possible)
                         self.output.push_str(&blockquote_prefix);
+                        // The spaces after the marker are needed for the
+                        // definition to start
                         self.output.push_str(":   ");
+                        self.output.push(whitespace::KEEP_MARKER);
                         self.output.push('\n');
-                        self.output.push_str(&blockquote_prefix);
-                        self.output.push_str("    ");
-                        self.serialize_code_block_with_indent(
-                            code,
-                            &format!("{}    ", blockquote_prefix),
-                        );
+                        self.line_prefix.push(LinePrefix::Indent(4));
+                        let line = child.data.borrow().sourcepos.start.line;
+                        self.serialize_code_block(&code.info, &code.literal, line);
+                        self.line_prefix.pop();
                     }
                     NodeValue::List(_) => {
                         // List as first child: output marker with 4 spaces, then list on same line
@@ -352,23 +414,31 @@ impl<'a> Serializer<'a> {
                     NodeValue::BlockQuote | NodeValue::Alert(_) => {
                         // Block quotes and alerts as first child: output marker, newline,
                         // then serialize with proper list_item_indent for continuation lines
+                        // As with code blocks, the spaces after the marker
+                        // are needed for the definition to start
                         self.output.push_str(&blockquote_prefix);
-                        self.output.push_str(":\n");
+                        self.output.push_str(":   ");
+                        self.output.push(whitespace::KEEP_MARKER);
+                        self.output.push('\n');
                         let old_list_item_indent =
                             std::mem::replace(&mut self.list_item_indent, "    ".to_string());
+                        self.line_prefix.push(LinePrefix::Indent(4));
                         self.serialize_node(child);
+                        self.line_prefix.pop();
                         self.list_item_indent = old_list_item_indent;
                     }
                     _ => {
                         // Other block types: serialize normally with indent
                         self.output.push_str(&blockquote_prefix);
                         self.output.push_str(":   ");
+                        self.line_prefix.push(LinePrefix::Indent(4));
                         self.serialize_node(child);
+                        self.line_prefix.pop();
                     }
                 }
             } else {
                 // Subsequent children: need blank line and 4-space indent
-                self.output.push('\n');
+                self.start_block(1);
                 match child_value {
                     NodeValue::Paragraph => {
                         self.output.push_str(&blockquote_prefix);
@@ -376,22 +446,15 @@ impl<'a> Serializer<'a> {
                         let mut content = String::new();
                         self.collect_inline_content(child, &mut content);
                         let continuation = format!("{}    ", blockquote_prefix);
-                        let wrapped = wrap::wrap_text_first_line(
-                            content.trim(),
-                            "",
-                            &continuation,
-                            self.options.line_width.get(),
-                        );
+                        let wrapped = self.wrap_after_output(content.trim(), &continuation);
                         self.output.push_str(&wrapped);
                         self.output.push('\n');
                     }
                     NodeValue::CodeBlock(code) => {
-                        self.output.push_str(&blockquote_prefix);
-                        self.output.push_str("    ");
-                        self.serialize_code_block_with_indent(
-                            code,
-                            &format!("{}    ", blockquote_prefix),
-                        );
+                        self.line_prefix.push(LinePrefix::Indent(4));
+                        let line = child.data.borrow().sourcepos.start.line;
+                        self.serialize_code_block(&code.info, &code.literal, line);
+                        self.line_prefix.pop();
                     }
                     NodeValue::List(_) => {
                         // Lists handle their own indentation via in_description_details flag
@@ -402,14 +465,17 @@ impl<'a> Serializer<'a> {
                         // so that their continuation lines are properly indented
                         let old_list_item_indent =
                             std::mem::replace(&mut self.list_item_indent, "    ".to_string());
+                        self.line_prefix.push(LinePrefix::Indent(4));
                         self.serialize_node(child);
+                        self.line_prefix.pop();
                         self.list_item_indent = old_list_item_indent;
                     }
                     _ => {
-                        // Other block types
-                        self.output.push_str(&blockquote_prefix);
-                        self.output.push_str("    ");
+                        // Other block types, whose lines are all indented
+                        self.line_prefix.push(LinePrefix::Indent(4));
+                        self.begin_line();
                         self.serialize_node(child);
+                        self.line_prefix.pop();
                     }
                 }
             }
@@ -419,42 +485,87 @@ impl<'a> Serializer<'a> {
     }
 
     pub(super) fn serialize_heading<'b>(&mut self, node: &'b AstNode<'b>, level: u8) {
-        // Collect heading text first
-        let mut heading_text = self.collect_text(node);
+        // Collect heading text first, with its whitespace normalized so that
+        // the setext underline matches the trimmed text
+        let mut heading_text = super::heading::normalize_heading(
+            &self.collect_text(node),
+            &self.options.heading_strip_trailing_punctuation,
+        );
 
         // Apply sentence case if enabled
         if self.options.heading_sentence_case {
-            // Merge config proper nouns with directive proper nouns
-            let mut proper_nouns = self.options.heading_proper_nouns.clone();
-            proper_nouns.extend(self.directive_proper_nouns.clone());
-
-            // Merge config common nouns with directive common nouns
-            let mut common_nouns = self.options.heading_common_nouns.clone();
-            common_nouns.extend(self.directive_common_nouns.clone());
+            heading_text = self.to_sentence_case(&heading_text);
+        }
 
-            heading_text =
-                super::heading::to_sentence_case(&heading_text, &proper_nouns, &common_nouns);
+        // Hard line breaks are only possible in setext headings, so a heading
+        // that keeps them is always written in setext style, except in block
+        // quotes, where its lines are joined and it is written in ATX style
+        // so that the whole heading stays on a single quoted line
+        let mut force_atx = false;
+        if heading_text.contains('\n') {
+            if self.options.heading_allow_multiline && level <= 2 && !self.in_block_quote {
+                self.serialize_multiline_setext_heading(&heading_text, level);
+                return;
+            }
+            heading_text = heading_text.replace('\n', " ");
+            force_atx = self.in_block_quote;
         }
 
-        if level == 1 && self.options.setext_h1 {
+        if heading_text.is_empty() {
+            // An empty heading has no text to underline, and would leave
+            // nothing but blank lines
+            self.write_line(&"#".repeat(level as usize));
+        } else if !force_atx && level == 1 && self.options.setext_h1 {
             // Setext-style with '='
-            self.output.push_str(&heading_text);
-            self.output.push('\n');
-            self.output.push_str(&"=".repeat(heading_text.width()));
-            self.output.push('\n');
-        } else if level == 2 && self.options.setext_h2 {
+            self.write_line(&heading_text);
+            self.write_line(&"=".repeat(heading_text.width()));
+        } else if !force_atx && level == 2 && self.options.setext_h2 {
             // Setext-style with '-'
-            self.output.push_str(&heading_text);
-            self.output.push('\n');
-            self.output.push_str(&"-".repeat(heading_text.width()));
-            self.output.push('\n');
+            self.write_line(&heading_text);
+            self.write_line(&"-".repeat(heading_text.width()));
         } else {
             // ATX-style for level 3+ or when setext is disabled
-            self.output.push_str(&"#".repeat(level as usize));
-            self.output.push(' ');
-            self.output.push_str(&heading_text);
-            self.output.push('\n');
+            self.write_line(&format!("{} {}", "#".repeat(level as usize), heading_text));
+        }
+    }
+
+    /// Convert heading text to sentence case, with the proper and common
+    /// nouns from both the options and the directives seen so far.
+    ///
+    /// With heading numbering on, the section number a heading starts with
+    /// is kept out of it, so that the word after the number is capitalized.
+    pub(super) fn to_sentence_case(&self, text: &str) -> String {
+        if self.options.heading_numbering
+            && let Some(len) = super::heading::number_prefix_len(text)
+        {
+            return format!("{}{}", &text[..len], self.to_sentence_case(&text[len..]));
+        }
+
+        // Merge config proper nouns with directive proper nouns
+        let mut proper_nouns = self.options.heading_proper_nouns.clone();
+        proper_nouns.extend(self.directive_proper_nouns.clone());
+
+        // Merge config common nouns with directive common nouns
+        let mut common_nouns = self.options.heading_common_nouns.clone();
+        common_nouns.extend(self.directive_common_nouns.clone());
+
+        super::heading::to_sentence_case(text, &proper_nouns, &common_nouns)
+    }
+
+    /// Serialize a setext heading whose lines are separated by hard line
+    /// breaks.  The underline is as wide as the widest line.
+    fn serialize_multiline_setext_heading(&mut self, heading_text: &str, level: u8) {
+        let lines: Vec<&str> = heading_text.split('\n').collect();
+        for (i, line) in lines.iter().enumerate() {
+            if i + 1 < lines.len() {
+                self.write_line(&format!("{}{}", line, self.hard_break()));
+            } else {
+                self.write_line(line);
+            }
         }
+        let width = lines.iter().map(|line| line.width()).max().unwrap_or(0);
+        let underline = if level == 1 { "=" } else { "-" };
+        self.write_line(&underline.repeat(width));
     }
 
     pub(super) fn serialize_paragraph<'b>(&mut self, node: &'b AstNode<'b>) {
@@ -469,68 +580,126 @@ impl<'a> Serializer<'a> {
             }
         }
 
+        // In MDX, JSX components and expressions are kept as they are
+        if self.options.mdx && self.serialize_opaque_paragraph(node) {
+            return;
+        }
+
+        // Paragraphs with template tags on lines of their own are kept as
+        // they are
+        if self.options.templates != TemplateSyntax::None && self.serialize_template_paragraph(node)
+        {
+            return;
+        }
+
+        // Display math on lines of its own is kept as it is
+        if self.options.math && self.serialize_display_math_paragraph(node) {
+            return;
+        }
+
         // Collect all inline content first
         let mut inline_content = String::new();
         self.collect_inline_content(node, &mut inline_content);
 
         if self.list_type.is_some() {
+            self.begin_line();
             // Inside a list item, wrap with proper continuation indent
             // First line has no prefix (marker already output)
             // Continuation lines need appropriate indent
             //
-            // There are two cases:
-            // 1. List inside blockquote: list_depth > blockquote_entry_list_depth
-            //    - Use base_indent for the inner list's continuation
-            // 2. Blockquote inside list: list_depth == blockquote_entry_list_depth
-            //    - Don't add base_indent, just use the outer prefix
-            let inner_list_depth = self
-                .list_depth
-                .saturating_sub(self.blockquote_entry_list_depth);
-            let base_indent = if self.in_description_details && inner_list_depth > 0 {
-                // Inside description details, add extra 5-space indent for `:    ` prefix.
-                // For unordered lists at top level, the marker is `-  ` (3 chars, no leading space).
-                // For nested lists, use the standard 4-char indent.
-                let first_level_marker_width = 1 + self.options.trailing_spaces.get(); // `-` + trailing
-                let nested_indent = "    ".repeat(inner_list_depth.saturating_sub(1));
-                format!(
-                    "     {}{}",
-                    " ".repeat(first_level_marker_width),
-                    nested_indent
-                )
-            } else {
-                "    ".repeat(inner_list_depth)
-            };
-            let continuation = if self.in_block_quote {
-                // Inside a blockquote, continuation lines need > prefix + indent
-                // Use blockquote_outer_indent (the outer list's indent, if any)
-                // rather than list_item_indent (which is for the list inside the blockquote)
-                format!(
-                    "{}{}{}",
-                    self.blockquote_outer_indent, self.blockquote_prefix, base_indent
+            // The line prefix already includes the indentation of every
+            // enclosing list item and block quote.  Inside description
+            // details, the extra indent for the `:    ` prefix is added here.
+            let in_item = node.parent().is_some_and(|parent| {
+                matches!(
+                    parent.data.borrow().value,
+                    NodeValue::Item(_) | NodeValue::TaskItem(_)
                 )
-            } else {
-                base_indent
+            });
+            let continuation = match self.list_hang {
+                _ if self.in_description_details => self.description_list_continuation(),
+                Some(hang) if in_item => format!("{}{}", self.container_prefix(), " ".repeat(hang)),
+                _ => self.line_prefix(),
             };
-            let wrapped = wrap::wrap_text_first_line(
-                inline_content.trim(),
-                "",
-                &continuation,
-                self.options.line_width.get(),
-            );
+            let wrapped = self.wrap_after_output(inline_content.trim(), &continuation);
+            let wrapped = escape::escape_block_lookalikes(&wrapped, "", &continuation);
             self.output.push_str(&wrapped);
         } else {
             // Not in a list - wrap the paragraph at line_width
-            let prefix = if self.in_block_quote {
-                format!("{}{}", self.blockquote_outer_indent, self.blockquote_prefix)
-            } else {
-                String::new()
-            };
-            let wrapped = wrap::wrap_text(&inline_content, &prefix, self.options.line_width.get());
-            self.output.push_str(&wrapped);
+            let prefix = self.line_prefix();
+            let wrapped = wrap::wrap_text(
+                &inline_content,
+                &prefix,
+                self.wrap_width(),
+                self.hard_break(),
+            );
+            let wrapped = escape::escape_block_lookalikes(&wrapped, &prefix, &prefix);
+            self.push_line_start(&wrapped);
             self.output.push('\n');
         }
     }
 
+    /// Wrap `text` to follow what is already on the last line of the output,
+    /// such as a list marker, with `continuation` starting the lines after
+    /// the first.
+    ///
+    /// The first line has room for what the last line of the output leaves,
+    /// and the returned text doesn't repeat that part of the line.
+    fn wrap_after_output(&self, text: &str, continuation: &str) -> String {
+        let line_start = self.output.rfind('\n').map_or(0, |i| i + 1);
+        let first_prefix = &self.output[line_start..];
+        let wrapped = wrap::wrap_text_first_line(
+            text,
+            first_prefix,
+            continuation,
+            self.wrap_width(),
+            self.hard_break(),
+        );
+        wrapped
+            .get(first_prefix.len()..)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The continuation indent for a paragraph in a list inside
+    /// description details.
+    ///
+    /// There are two cases:
+    /// 1. List inside blockquote: list_depth > blockquote_entry_list_depth
+    ///    - Use base_indent for the inner list's continuation
+    /// 2. Blockquote inside list: list_depth == blockquote_entry_list_depth
+    ///    - Don't add base_indent, just use the outer prefix
+    fn description_list_continuation(&self) -> String {
+        let inner_list_depth = self
+            .list_depth
+            .saturating_sub(self.blockquote_entry_list_depth);
+        let base_indent = if inner_list_depth > 0 {
+            // Inside description details, add extra 5-space indent for `:    ` prefix.
+            // For unordered lists at top level, the marker is `-  ` (3 chars, no leading space).
+            // For nested lists, use the standard 4-char indent.
+            let first_level_marker_width = 1 + self.bullet_trailing_spaces(); // `-` + trailing
+            let nested_indent = "    ".repeat(inner_list_depth.saturating_sub(1));
+            format!(
+                "     {}{}",
+                " ".repeat(first_level_marker_width),
+                nested_indent
+            )
+        } else {
+            String::new()
+        };
+        if self.in_block_quote {
+            // Inside a blockquote, continuation lines need > prefix + indent
+            // Use blockquote_outer_indent (the outer list's indent, if any)
+            // rather than list_item_indent (which is for the list inside the blockquote)
+            format!(
+                "{}{}{}",
+                self.blockquote_outer_indent, self.blockquote_prefix, base_indent
+            )
+        } else {
+            base_indent
+        }
+    }
+
     pub(super) fn serialize_front_matter(&mut self, content: &str) {
         // Front matter content from comrak includes the delimiters,
         // so we preserve it verbatim and add a trailing blank line
@@ -565,37 +734,111 @@ impl<'a> Serializer<'a> {
             return;
         }
 
+        // Collect warnings first to avoid borrow issues
+        let warnings = Self::undefined_references(node, &self.source_lines);
+        for (line, _, msg) in warnings {
+            self.add_warning(line, msg);
+        }
+    }
+
+    /// Find undefined reference links in the document `node` parsed from
+    /// `source_lines`, outside the regions where formatting is disabled.
+    ///
+    /// Returns a vector of (line, column, warning_message) tuples.
+    pub(crate) fn undefined_references<'b>(
+        node: &'b AstNode<'b>,
+        source_lines: &[&str],
+    ) -> Vec<(usize, usize, String)> {
         // Collect PHP Markdown Extra abbreviation definitions from source
-        let abbreviations = Self::collect_abbreviations(&self.source_lines);
+        let abbreviations = Self::collect_abbreviations(source_lines);
 
         // Collect reference definitions from source that comrak may not have parsed
         // (e.g., when they follow abbreviation definitions without a blank line)
-        let source_ref_defs = Self::collect_source_reference_definitions(&self.source_lines);
+        let source_ref_defs = Self::collect_source_reference_definitions(source_lines);
 
         // Collect disabled line ranges based on formatting directives
         let disabled_ranges = Self::collect_disabled_line_ranges(node);
 
-        // Collect warnings first to avoid borrow issues
-        let warnings = Self::find_undefined_references_in_ast(
+        let mut warnings = Self::find_undefined_references_in_ast(
             node,
-            &self.source_lines,
+            source_lines,
             &abbreviations,
             &source_ref_defs,
         );
 
         // Filter out warnings that fall within disabled regions
-        for (line, msg) in warnings {
-            if !Self::is_line_in_disabled_ranges(line, &disabled_ranges) {
-                self.add_warning(line, msg);
+        warnings.retain(|(line, _, _)| !Self::is_line_in_disabled_ranges(*line, &disabled_ranges));
+        warnings
+    }
+
+    /// Check links to fragments of this document, like `[usage](#usage)`.
+    ///
+    /// A fragment has a target if it is the anchor of a heading (see
+    /// [`crate::slug`]) or the `id` or `name` of an HTML element in the
+    /// document, compared case-insensitively after percent-decoding.  We
+    /// emit a warning for each fragment without one.
+    fn check_fragment_links<'b>(&mut self, node: &'b AstNode<'b>) {
+        for (line, _, msg) in Self::undefined_fragments(node) {
+            self.add_warning(line, msg);
+        }
+    }
+
+    /// Find links to fragments of the document `node` without a target (see
+    /// [`Self::check_fragment_links`]), outside the regions where formatting
+    /// is disabled.
+    ///
+    /// Returns a vector of (line, column, warning_message) tuples.
+    pub(crate) fn undefined_fragments<'b>(node: &'b AstNode<'b>) -> Vec<(usize, usize, String)> {
+        let mut targets: std::collections::HashSet<String> = crate::slug::heading_slugs(node)
+            .into_iter()
+            .map(|(_, _, slug)| slug)
+            .collect();
+        let html_id_pattern = Regex::new(r#"\b(?:id|name)\s*=\s*["']?([^"'\s>]+)"#).unwrap();
+
+        let mut fragment_links = Vec::new();
+        for descendant in node.descendants() {
+            let data = descendant.data.borrow();
+            let html = match &data.value {
+                NodeValue::HtmlBlock(html_block) => &html_block.literal,
+                NodeValue::HtmlInline(html) => html,
+                NodeValue::Link(link) => {
+                    if let Some(fragment) = link.url.strip_prefix('#')
+                        && !fragment.is_empty()
+                    {
+                        fragment_links.push((data.sourcepos.start, fragment.to_string()));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            for captures in html_id_pattern.captures_iter(html) {
+                targets.insert(captures[1].to_lowercase());
             }
         }
+        if fragment_links.is_empty() {
+            return Vec::new();
+        }
+
+        let disabled_ranges = Self::collect_disabled_line_ranges(node);
+        fragment_links
+            .into_iter()
+            .filter(|(start, fragment)| {
+                let target = crate::slug::decode_fragment(fragment).to_lowercase();
+                !targets.contains(&target)
+                    && !Self::is_line_in_disabled_ranges(start.line, &disabled_ranges)
+            })
+            .map(|(start, fragment)| {
+                let message = format!("undefined link fragment: #{}", fragment);
+                (start.line, start.column, message)
+            })
+            .collect()
     }
 
     /// Collect line ranges that should be excluded from warnings due to
     /// formatting directives (hongdown-disable, hongdown-disable-next-line, etc.).
     ///
     /// Returns a vector of (start_line, end_line) tuples representing disabled ranges.
-    fn collect_disabled_line_ranges<'b>(node: &'b AstNode<'b>) -> Vec<(usize, usize)> {
+    pub(crate) fn collect_disabled_line_ranges<'b>(node: &'b AstNode<'b>) -> Vec<(usize, usize)> {
         let mut ranges = Vec::new();
         let children: Vec<_> = node.children().collect();
 
@@ -660,7 +903,22 @@ impl<'a> Serializer<'a> {
                     Directive::Enable => {
                         // Enable doesn't start a new range, it ends one
                     }
-                    Directive::ProperNouns(_) | Directive::CommonNouns(_) => {
+                    Directive::TocStart => {
+                        // The table of contents is regenerated, so nothing
+                        // in it is worth a warning
+                        if let Some(end) = Self::find_toc_end(&children, i) {
+                            let start_line = child.data.borrow().sourcepos.end.line + 1;
+                            let end_line = children[end].data.borrow().sourcepos.start.line;
+                            ranges.push((start_line, end_line));
+                        }
+                    }
+                    Directive::ProperNouns(_)
+                    | Directive::CommonNouns(_)
+                    | Directive::TocEnd
+                    | Directive::NoWrap
+                    | Directive::DisableRules(_)
+                    | Directive::EnableRules(_)
+                    | Directive::DisableFileRules(_) => {
                         // These directives don't affect warning ranges
                     }
                 }
@@ -670,8 +928,68 @@ impl<'a> Serializer<'a> {
         ranges
     }
 
+    /// Collect the line ranges where rules are disabled by
+    /// `hongdown-disable RULE...` and `hongdown-disable-file RULE...`
+    /// directives, as the name of the rule and the first and last lines.
+    ///
+    /// A range ends right before the next `hongdown-enable` directive that
+    /// names its rule or no rule at all.  Unknown rules are left out.
+    pub(crate) fn collect_rule_ranges<'b>(
+        node: &'b AstNode<'b>,
+    ) -> Vec<(&'static str, usize, usize)> {
+        let mut ranges = Vec::new();
+        let children: Vec<_> = node.children().collect();
+        let directive = |child: &&'b AstNode<'b>| match &child.data.borrow().value {
+            NodeValue::HtmlBlock(html_block) => Directive::parse(&html_block.literal),
+            _ => None,
+        };
+
+        for (i, child) in children.iter().enumerate() {
+            match directive(child) {
+                Some(Directive::DisableFileRules(rules)) => {
+                    for rule in rules.iter().filter_map(|rule| crate::lint::rule_name(rule)) {
+                        ranges.push((rule, 1, usize::MAX));
+                    }
+                }
+                Some(Directive::DisableRules(rules)) => {
+                    let start_line = child.data.borrow().sourcepos.end.line + 1;
+                    for rule in rules.iter().filter_map(|rule| crate::lint::rule_name(rule)) {
+                        let end_line = children[i + 1..]
+                            .iter()
+                            .find(|future_child| match directive(future_child) {
+                                Some(Directive::Enable) => true,
+                                Some(Directive::EnableRules(rules)) => rules
+                                    .iter()
+                                    .any(|name| crate::lint::rule_name(name) == Some(rule)),
+                                _ => false,
+                            })
+                            .map_or(usize::MAX, |enable| {
+                                enable.data.borrow().sourcepos.start.line - 1
+                            });
+                        ranges.push((rule, start_line, end_line));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        ranges
+    }
+
+    /// Check if `rule` is disabled on a line by any of the `ranges` from
+    /// [`Self::collect_rule_ranges`].
+    pub(crate) fn is_rule_disabled(
+        rule: &str,
+        line: usize,
+        ranges: &[(&'static str, usize, usize)],
+    ) -> bool {
+        ranges
+            .iter()
+            .any(|(name, start, end)| *name == rule && line >= *start && line <= *end)
+    }
+
     /// Check if a line number falls within any of the disabled ranges.
-    fn is_line_in_disabled_ranges(line: usize, ranges: &[(usize, usize)]) -> bool {
+    pub(crate) fn is_line_in_disabled_ranges(line: usize, ranges: &[(usize, usize)]) -> bool {
         ranges
             .iter()
             .any(|(start, end)| line >= *start && line <= *end)
@@ -716,14 +1034,107 @@ impl<'a> Serializer<'a> {
         definitions
     }
 
+    /// Parse a single-line link reference definition from source, like
+    /// `[label]: url "title"`.
+    fn parse_source_reference_definition(line: &str, pattern: &Regex) -> Option<ReferenceLink> {
+        let caps = pattern.captures(line)?;
+        // Titles are stored as comrak reports them, with the quotes unescaped
+        let title = if let Some(title) = caps.get(3) {
+            title.as_str().replace("\\\"", "\"")
+        } else if let Some(title) = caps.get(4).or_else(|| caps.get(5)) {
+            title.as_str().to_string()
+        } else {
+            String::new()
+        };
+        Some(ReferenceLink {
+            label: caps[1].to_string(),
+            url: caps[2].to_string(),
+            title,
+        })
+    }
+
+    /// Scan the source for single-line reference definitions outside of any
+    /// block.
+    ///
+    /// Definitions whose labels match and which point to the same URL but
+    /// differ in title are reported, since only the first of them is used.
+    /// Unless `link_remove_unused_definitions` is set, the first definition
+    /// of each label is remembered so that it can be kept even if nothing
+    /// uses it.
+    fn collect_source_definitions<'b>(&mut self, node: &'b AstNode<'b>) {
+        if self.source_lines.is_empty() {
+            return;
+        }
+        let pattern = Regex::new(
+            r#"^ {0,3}\[([^\]^\\](?:[^\]\\]|\\.)*)\]:[ \t]*(<[^<>]*>|[^\s<]\S*)(?:[ \t]+(?:"((?:[^"\\]|\\.)*)"|'((?:[^'\\]|\\.)*)'|\(((?:[^()\\]|\\.)*)\)))?[ \t]*$"#,
+        )
+        .unwrap();
+
+        // Lines that belong to a block can't hold a top-level definition
+        let mut covered = vec![false; self.source_lines.len() + 1];
+        for child in node.children() {
+            let sourcepos = child.data.borrow().sourcepos;
+            let end = sourcepos.end.line.min(self.source_lines.len());
+            if let Some(lines) = covered.get_mut(sourcepos.start.line..=end) {
+                lines.fill(true);
+            }
+        }
+        let disabled_ranges = Self::collect_disabled_line_ranges(node);
+
+        let mut definitions: IndexMap<String, (usize, ReferenceLink)> = IndexMap::new();
+        let source_lines = self.source_lines.clone();
+        for (i, line) in source_lines.iter().enumerate() {
+            let line_num = i + 1;
+            if covered[line_num] || Self::is_line_in_disabled_ranges(line_num, &disabled_ranges) {
+                continue;
+            }
+            let Some(definition) = Self::parse_source_reference_definition(line, &pattern) else {
+                continue;
+            };
+            let key = reference_key(&definition.label);
+            if let Some((first_line, first)) = definitions.get(&key) {
+                if first.url == definition.url && first.title != definition.title {
+                    self.add_warning(
+                        line_num,
+                        format!(
+                            "reference definition [{}] differs from the one on line {} \
+                             only in title; only the first one is used",
+                            definition.label, first_line
+                        ),
+                    );
+                }
+                continue;
+            }
+            definitions.insert(key, (line_num, definition));
+        }
+
+        if !self.options.link_remove_unused_definitions {
+            self.unused_definitions = definitions.into_values().map(|(_, d)| d).collect();
+        }
+    }
+
+    /// Queue the remembered source definitions that nothing used, so that
+    /// they are written with the last reference definitions.
+    fn keep_unused_definitions(&mut self) {
+        for definition in std::mem::take(&mut self.unused_definitions) {
+            let key = reference_key(&definition.label);
+            if !self.emitted_references.contains_key(&key)
+                && !self.pending_references.contains_key(&key)
+                && !self.footnotes.pending_references.contains_key(&key)
+            {
+                self.pending_references.insert(key, definition);
+            }
+        }
+    }
+
     /// Find undefined references by walking the AST.
-    /// Returns a vector of (line_number, warning_message) tuples.
+    /// Returns a vector of (line_number, column, warning_message) tuples.
     fn find_undefined_references_in_ast<'b>(
         node: &'b AstNode<'b>,
         source_lines: &[&str],
         abbreviations: &std::collections::HashSet<String>,
         source_ref_defs: &std::collections::HashSet<String>,
-    ) -> Vec<(usize, String)> {
+    ) -> Vec<(usize, usize, String)> {
         let mut warnings = Vec::new();
 
         // Pattern to find [label] or [text][label] in text nodes
@@ -750,7 +1161,7 @@ impl<'a> Serializer<'a> {
         ref_pattern: &Regex,
         abbreviations: &std::collections::HashSet<String>,
         source_ref_defs: &std::collections::HashSet<String>,
-        warnings: &mut Vec<(usize, String)>,
+        warnings: &mut Vec<(usize, usize, String)>,
     ) {
         let data = node.data.borrow();
 
@@ -806,7 +1217,9 @@ impl<'a> Serializer<'a> {
                         continue;
                     }
 
-                    warnings.push((line_num, format!("undefined reference link: [{}]", label)));
+                    let column = data.sourcepos.start.column + full_match.start();
+                    let message = format!("undefined reference link: [{}]", label);
+                    warnings.push((line_num, column, message));
                 }
             }
             // Skip code blocks and inline code - they don't contain reference links
@@ -858,18 +1271,38 @@ impl<'a> Serializer<'a> {
         false
     }
 
+    /// Serialize a thematic break.
+    ///
+    /// A thematic break that is the first block of a bullet list item
+    /// follows the item's marker on the same line.  In that case it gets no
+    /// leading spaces, which would turn it into an indented code block, and
+    /// it doesn't use the list's marker character, which would turn the whole
+    /// line, marker included, into a thematic break.
     pub(super) fn serialize_thematic_break(&mut self) {
         let style = self.options.thematic_break_style.as_str();
-        let leading_spaces = self.options.thematic_break_leading_spaces.get();
-
-        // Determine the prefix based on blockquote context
-        if self.in_block_quote {
-            let prefix = format!("{}> ", self.blockquote_outer_indent);
-            self.output.push_str(&prefix);
+        let after_marker = self.list_marker_end == Some(self.output.len())
+            || !(self.output.is_empty() || self.output.ends_with('\n'));
+
+        if after_marker {
+            // The part of the line prefix past the marker, like the `> ` of
+            // a block quote the break is in
+            self.begin_line();
+            let marker = self.bullet_marker();
+            if matches!(self.list_type, Some(ListType::Bullet)) && style.contains(marker) {
+                let replacement = if marker == '*' { "-" } else { "*" };
+                let style = style.replace(marker, replacement);
+                self.output.push_str(&style);
+            } else {
+                self.output.push_str(style);
+            }
+            self.output.push('\n');
+            return;
         }
 
+        self.begin_line();
+
         // Add leading spaces
-        for _ in 0..leading_spaces {
+        for _ in 0..self.options.thematic_break_leading_spaces.get() {
             self.output.push(' ');
         }
 
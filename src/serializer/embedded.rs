@@ -0,0 +1,371 @@
+//! Built-in formatting of the code of JSON and TOML code blocks, for the
+//! languages listed in `code_block.format_embedded`.
+//!
+//! Unlike the external formatters of [`super::formatter`], these need no
+//! other program, so they work the same everywhere, WASM included.  Code that
+//! doesn't parse is an error, and is left as it is.
+
+use crate::config::{EmbeddedLanguage, JsonKeyOrder};
+
+/// The embedded language a code block in `language` is formatted as, by the
+/// first word of its info string.
+pub(super) fn embedded_language(language: &str) -> Option<EmbeddedLanguage> {
+    match language {
+        "json" => Some(EmbeddedLanguage::Json),
+        "toml" => Some(EmbeddedLanguage::Toml),
+        _ => None,
+    }
+}
+
+/// Format `code` in `language`, or return why it can't be formatted.
+pub(super) fn format_embedded(
+    language: EmbeddedLanguage,
+    code: &str,
+    key_order: JsonKeyOrder,
+) -> Result<String, String> {
+    match language {
+        EmbeddedLanguage::Json => format_json(code, key_order),
+        EmbeddedLanguage::Toml => format_toml(code),
+    }
+}
+
+/// A JSON value as written, with its strings, numbers, and literals kept
+/// as they are.
+enum Json<'a> {
+    Scalar(&'a str),
+    Array(Vec<Json<'a>>),
+    Object(Vec<(&'a str, Json<'a>)>),
+}
+
+/// Re-indent the JSON `code` to 2 spaces, one value per line, with the keys
+/// of objects in the order they are written or sorted.
+///
+/// Strings and numbers are written just as they are in `code`, so that
+/// escapes and the precision of numbers are kept.
+fn format_json(code: &str, key_order: JsonKeyOrder) -> Result<String, String> {
+    serde_json::from_str::<serde::de::IgnoredAny>(code).map_err(|e| e.to_string())?;
+    let mut parser = JsonParser { rest: code };
+    let value = parser.value();
+    let mut out = String::new();
+    write_json(&value, key_order, 0, &mut out);
+    out.push('\n');
+    Ok(out)
+}
+
+/// A parser of JSON that is known to be valid.
+struct JsonParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start_matches([' ', '\t', '\n', '\r']);
+    }
+
+    /// Skip `byte` if the rest starts with it, after whitespace.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.rest.as_bytes().first() == Some(&byte) {
+            self.rest = &self.rest[1..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Json<'a> {
+        self.skip_whitespace();
+        if self.eat(b'[') {
+            let mut items = Vec::new();
+            while !self.eat(b']') {
+                items.push(self.value());
+                self.eat(b',');
+            }
+            Json::Array(items)
+        } else if self.eat(b'{') {
+            let mut members = Vec::new();
+            while !self.eat(b'}') {
+                self.skip_whitespace();
+                let key = self.string();
+                self.eat(b':');
+                members.push((key, self.value()));
+                self.eat(b',');
+            }
+            Json::Object(members)
+        } else if self.rest.starts_with('"') {
+            Json::Scalar(self.string())
+        } else {
+            let end = self
+                .rest
+                .find(|c: char| matches!(c, ',' | ']' | '}') || c.is_ascii_whitespace())
+                .unwrap_or(self.rest.len());
+            let (scalar, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            Json::Scalar(scalar)
+        }
+    }
+
+    /// The string the rest starts with, with its quotes.
+    fn string(&mut self) -> &'a str {
+        let bytes = self.rest.as_bytes();
+        let mut end = 1;
+        while bytes[end] != b'"' {
+            end += if bytes[end] == b'\\' { 2 } else { 1 };
+        }
+        let (string, rest) = self.rest.split_at(end + 1);
+        self.rest = rest;
+        string
+    }
+}
+
+fn write_json(value: &Json, key_order: JsonKeyOrder, depth: usize, out: &mut String) {
+    let indent = |out: &mut String, depth: usize| out.push_str(&"  ".repeat(depth));
+    match value {
+        Json::Scalar(scalar) => out.push_str(scalar),
+        Json::Array(items) if items.is_empty() => out.push_str("[]"),
+        Json::Object(members) if members.is_empty() => out.push_str("{}"),
+        Json::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                indent(out, depth + 1);
+                write_json(item, key_order, depth + 1, out);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            indent(out, depth);
+            out.push(']');
+        }
+        Json::Object(members) => {
+            let mut members: Vec<_> = members.iter().collect();
+            if key_order == JsonKeyOrder::Sort {
+                members.sort_by_cached_key(|(key, _)| {
+                    serde_json::from_str::<String>(key).unwrap_or_default()
+                });
+            }
+            out.push_str("{\n");
+            for (i, (key, member)) in members.iter().enumerate() {
+                indent(out, depth + 1);
+                out.push_str(key);
+                out.push_str(": ");
+                write_json(member, key_order, depth + 1, out);
+                out.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+            }
+            indent(out, depth);
+            out.push('}');
+        }
+    }
+}
+
+/// Normalize the whitespace of the TOML `code`: strip the indentation of
+/// keys, tables, and comments, put a single space on each side of the `=`
+/// of keys, indent the lines of arrays and inline tables that span lines by
+/// 2 spaces for each level, strip trailing whitespace, and collapse runs of
+/// blank lines into one.
+///
+/// Comments and the order of keys are kept, and multi-line strings are left
+/// as they are.  If the result doesn't mean the same as `code`, which can
+/// only happen with constructs this doesn't know about, `code` is returned
+/// as it is.
+fn format_toml(code: &str) -> Result<String, String> {
+    let parse = |code: &str| {
+        toml::from_str::<toml::Table>(code).map_err(|e| {
+            let Some(span) = e.span() else {
+                return e.message().to_string();
+            };
+            let before = &code[..span.start];
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+            format!("{} at line {} column {}", e.message(), line, column)
+        })
+    };
+    let table = parse(code)?;
+
+    let mut out = String::new();
+    let mut state = TomlState::default();
+    let mut blank = false;
+    for line in code.lines() {
+        let start = state;
+        state.scan(line);
+        let mut line = if start.multiline.is_some() {
+            line.to_string()
+        } else if start.depth > 0 {
+            let trimmed = line.trim_start();
+            let closing = trimmed.starts_with([']', '}']);
+            let depth = start.depth - usize::from(closing);
+            format!("{}{}", "  ".repeat(depth), trimmed)
+        } else {
+            normalize_toml_line(line.trim_start())
+        };
+        if state.multiline.is_none() {
+            line.truncate(line.trim_end().len());
+        }
+        if line.is_empty() && start.multiline.is_none() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    match parse(&out) {
+        Ok(formatted) if formatted == table => Ok(out),
+        _ => Ok(code.to_string()),
+    }
+}
+
+/// Put a single space on each side of the `=` of the key on `line`, if it
+/// has one.
+fn normalize_toml_line(line: &str) -> String {
+    if line.starts_with(['#', '[']) {
+        return line.to_string();
+    }
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                for (_, d) in chars.by_ref() {
+                    if d == c {
+                        break;
+                    }
+                }
+            }
+            '=' => {
+                return format!("{} = {}", line[..i].trim_end(), line[i + 1..].trim_start());
+            }
+            _ => {}
+        }
+    }
+    line.to_string()
+}
+
+/// Where a line of TOML ends: in a multi-line string, or in arrays and
+/// inline tables that span lines.
+#[derive(Clone, Copy, Default)]
+struct TomlState {
+    /// The delimiter of the multi-line string the line ends in.
+    multiline: Option<&'static str>,
+    /// The number of arrays and inline tables open.
+    depth: usize,
+}
+
+impl TomlState {
+    fn scan(&mut self, mut line: &str) {
+        loop {
+            if let Some(delimiter) = self.multiline {
+                let Some(end) = find_closing(line, delimiter) else {
+                    return;
+                };
+                line = &line[end + delimiter.len()..];
+                self.multiline = None;
+            }
+            let Some(c) = line.chars().next() else {
+                return;
+            };
+            match c {
+                '#' => return,
+                '"' | '\'' => {
+                    let delimiter = if c == '"' { "\"\"\"" } else { "'''" };
+                    if let Some(rest) = line.strip_prefix(delimiter) {
+                        self.multiline = Some(delimiter);
+                        line = rest;
+                        continue;
+                    }
+                    let quote = &delimiter[..1];
+                    match find_closing(&line[1..], quote) {
+                        Some(end) => line = &line[end + 2..],
+                        None => return,
+                    }
+                    continue;
+                }
+                '[' | '{' => self.depth += 1,
+                ']' | '}' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+            line = &line[c.len_utf8()..];
+        }
+    }
+}
+
+/// The position in `text` of the `delimiter` that closes a string, skipping
+/// escapes in basic strings, which are delimited by `"`.
+fn find_closing(text: &str, delimiter: &str) -> Option<usize> {
+    let escapes = delimiter.starts_with('"');
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+        } else if text[i..].starts_with(delimiter) {
+            return Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(code: &str) -> String {
+        format_json(code, JsonKeyOrder::Preserve).unwrap()
+    }
+
+    #[test]
+    fn test_format_json() {
+        assert_eq!(
+            json(r#"{"b": [1, 2.50, {"c":null}], "a": "\"x\"", "e": [], "d": {}}"#),
+            "{\n  \"b\": [\n    1,\n    2.50,\n    {\n      \"c\": null\n    }\n  ],\n  \
+             \"a\": \"\\\"x\\\"\",\n  \"e\": [],\n  \"d\": {}\n}\n"
+        );
+        assert_eq!(json(" 42 "), "42\n");
+        assert_eq!(json("[true,false]"), "[\n  true,\n  false\n]\n");
+    }
+
+    #[test]
+    fn test_format_json_sort_keys() {
+        assert_eq!(
+            format_json(r#"{"b": {"z": 1, "y": 2}, "a": 3}"#, JsonKeyOrder::Sort).unwrap(),
+            "{\n  \"a\": 3,\n  \"b\": {\n    \"y\": 2,\n    \"z\": 1\n  }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_json_invalid() {
+        let error = format_json("{\"a\": 1,}", JsonKeyOrder::Preserve).unwrap_err();
+        assert!(error.contains("line 1"), "{}", error);
+        assert!(format_json("{} {}", JsonKeyOrder::Preserve).is_err());
+    }
+
+    #[test]
+    fn test_format_toml() {
+        assert_eq!(
+            format_toml(
+                "\n  title=\"a = b\"   \n\n\n[ server ]\n\tport  =8080 # comment\nlist = [\n1,\n    [2],\n]\n\n"
+            )
+            .unwrap(),
+            "title = \"a = b\"\n\n[ server ]\nport = 8080 # comment\nlist = [\n  1,\n  [2],\n]\n"
+        );
+    }
+
+    #[test]
+    fn test_format_toml_keeps_multiline_strings() {
+        let code = "text = \"\"\"\n  indented  \n\n\n  = not a key\n\"\"\"\n";
+        assert_eq!(format_toml(code).unwrap(), code);
+        let code = "text = '''\n  # not a comment  \n'''\nkey  = 1\n";
+        assert_eq!(
+            format_toml(code).unwrap(),
+            "text = '''\n  # not a comment  \n'''\nkey = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_format_toml_invalid() {
+        let error = format_toml("a = 1\nb = \n").unwrap_err();
+        assert!(error.contains("at line 2"), "{}", error);
+    }
+}
@@ -1,5 +1,10 @@
 //! Text escaping and formatting utilities for Markdown serialization.
 
+use std::borrow::Cow;
+
+use super::whitespace::KEEP_MARKER;
+use crate::trace;
+
 /// Normalize whitespace in text: convert newlines and multiple spaces to single space.
 pub fn normalize_whitespace(text: &str) -> String {
     text.split_whitespace().collect::<Vec<_>>().join(" ")
@@ -7,73 +12,228 @@ pub fn normalize_whitespace(text: &str) -> String {
 
 /// Escape special Markdown characters in text content.
 /// Characters that could be misinterpreted as Markdown syntax need escaping.
-pub fn escape_text(text: &str) -> String {
-    let mut result = String::with_capacity(text.len());
-    let chars: Vec<char> = text.chars().collect();
-
-    for (i, &ch) in chars.iter().enumerate() {
-        match ch {
-            // Asterisk always needs escaping (can create emphasis anywhere)
-            '*' => {
-                result.push('\\');
-                result.push(ch);
-            }
-            // Underscore always needs escaping for safety
-            // While CommonMark doesn't create emphasis for intraword underscores,
-            // escaping ensures consistent behavior across all Markdown parsers
-            '_' => {
-                result.push('\\');
-                result.push(ch);
-            }
-            // Square brackets - only escape if they could form a link
-            // A '[' at the end can't start a link, ']' at the start can't close one
-            // Adjacent brackets like '[[' or ']]' also don't need escaping
-            '[' => {
-                let next_is_bracket = i + 1 < chars.len() && chars[i + 1] == '[';
-                let at_end = i + 1 >= chars.len();
-                if next_is_bracket || at_end {
-                    result.push(ch);
-                } else {
-                    result.push('\\');
-                    result.push(ch);
-                }
-            }
-            ']' => {
-                let prev_is_bracket = i > 0 && chars[i - 1] == ']';
-                let at_start = i == 0;
-                let at_end = i + 1 >= chars.len();
-                // A ']' can only close a link if followed by '(' or '['
-                // At end of text or followed by other chars, it's just text
-                let next_could_continue_link =
-                    i + 1 < chars.len() && (chars[i + 1] == '(' || chars[i + 1] == '[');
-                if prev_is_bracket || at_start || at_end || !next_could_continue_link {
-                    result.push(ch);
-                } else {
-                    result.push('\\');
-                    result.push(ch);
+///
+/// Returns the input unchanged, without allocating, if nothing needs
+/// escaping.  All the special characters are ASCII, so the scan works on
+/// bytes; the bytes of a multibyte character never match any of them.
+pub fn escape_text(text: &str) -> Cow<'_, str> {
+    let bytes = text.as_bytes();
+    let brackets = escaped_brackets(bytes);
+    let mut result: Option<String> = None;
+    let mut copied = 0;
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        if needs_escape(byte)
+            || brackets.binary_search(&i).is_ok()
+            || (byte == b'&' && entity_len(&text[i..]).is_some())
+        {
+            trace::debug!(character = %char::from(byte), offset = i, "escaped");
+            let result = result.get_or_insert_with(|| String::with_capacity(text.len() + 8));
+            result.push_str(&text[copied..i]);
+            result.push('\\');
+            copied = i;
+        }
+    }
+
+    match result {
+        Some(mut result) => {
+            result.push_str(&text[copied..]);
+            Cow::Owned(result)
+        }
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// Check if `byte` needs a backslash in text wherever it is.  Brackets
+/// depend on the rest of the text; see [`escaped_brackets`].
+pub fn needs_escape(byte: u8) -> bool {
+    match byte {
+        // Asterisk always needs escaping (can create emphasis anywhere)
+        b'*' => true,
+        // Underscore always needs escaping for safety
+        // While CommonMark doesn't create emphasis for intraword underscores,
+        // escaping ensures consistent behavior across all Markdown parsers
+        b'_' => true,
+        // Backslash itself needs escaping
+        b'\\' => true,
+        // Backtick could start code spans
+        b'`' => true,
+        // Other characters pass through unchanged
+        _ => false,
+    }
+}
+
+/// The indices, in ascending order, of the brackets in `bytes` that need a
+/// backslash: a `[` and the `]` that closes it, if the `]` is right before
+/// `(` or `[`, which would make them a link, and a `[` right after `^`,
+/// which would start an inline footnote.  Brackets that pair up otherwise,
+/// like those of `[x]` in prose or nested in the text of a link, and those
+/// that don't pair up, can't form a link, and are left alone.
+pub fn escaped_brackets(bytes: &[u8]) -> Vec<usize> {
+    let mut brackets = Vec::new();
+    let mut open = Vec::new();
+    for (i, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'[' if i > 0 && bytes[i - 1] == b'^' => brackets.push(i),
+            b'[' => open.push(i),
+            b']' => {
+                if let Some(start) = open.pop()
+                    && matches!(bytes.get(i + 1), Some(b'(' | b'['))
+                {
+                    brackets.push(start);
+                    brackets.push(i);
                 }
             }
-            // Backslash itself needs escaping
-            '\\' => {
-                result.push('\\');
-                result.push(ch);
-            }
-            // Backtick could start code spans
-            '`' => {
-                result.push('\\');
-                result.push(ch);
-            }
-            // Other characters pass through unchanged
-            _ => result.push(ch),
+            _ => {}
+        }
+    }
+    brackets.sort_unstable();
+    brackets
+}
+
+/// Return the length of the character entity reference, like `&copy;` or
+/// `&#215;`, at the start of `text`, if there is one.  An `&` that starts
+/// one needs escaping in text, or it would be read back as the character.
+///
+/// Numeric references count whatever code point they name, as CommonMark
+/// decodes invalid ones to U+FFFD.
+pub fn entity_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('&')?;
+    let end = rest.bytes().take(32).position(|b| b == b';')?;
+    let name = &rest[..end];
+    let valid = match name.strip_prefix('#') {
+        Some(number) => match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (1..=6).contains(&hex.len()) && hex.bytes().all(|b| b.is_ascii_hexdigit()),
+            None => (1..=7).contains(&number.len()) && number.bytes().all(|b| b.is_ascii_digit()),
+        },
+        None => html_escape::NAMED_ENTITIES
+            .binary_search_by_key(&name.as_bytes(), |(n, _)| n)
+            .is_ok(),
+    };
+    valid.then_some(end + 2)
+}
+
+/// Characters that look like another character, or like nothing, with the
+/// entities `EntityMode::EncodeInvisible` writes them as.
+const INVISIBLE_ENTITIES: &[(char, &str)] = &[
+    ('\u{00A0}', "&nbsp;"),
+    ('\u{00AD}', "&shy;"),
+    ('\u{2002}', "&ensp;"),
+    ('\u{2003}', "&emsp;"),
+    ('\u{2009}', "&thinsp;"),
+    ('\u{200A}', "&hairsp;"),
+    ('\u{200B}', "&ZeroWidthSpace;"),
+    ('\u{200C}', "&zwnj;"),
+    ('\u{200D}', "&zwj;"),
+    ('\u{200E}', "&lrm;"),
+    ('\u{200F}', "&rlm;"),
+    ('\u{202F}', "&#x202F;"),
+    ('\u{2060}', "&NoBreak;"),
+    ('\u{FEFF}', "&#xFEFF;"),
+];
+
+/// Write the characters in `text` that can't be told apart visually as
+/// entities.  Returns the input unchanged, without allocating, if there are
+/// none.
+pub fn encode_invisible(text: &str) -> Cow<'_, str> {
+    let entity = |ch: char| {
+        INVISIBLE_ENTITIES
+            .iter()
+            .find(|&&(c, _)| c == ch)
+            .map(|&(_, entity)| entity)
+    };
+    if !text.chars().any(|ch| entity(ch).is_some()) {
+        return Cow::Borrowed(text);
+    }
+    let mut result = String::with_capacity(text.len() + 8);
+    for ch in text.chars() {
+        match entity(ch) {
+            Some(entity) => result.push_str(entity),
+            None => result.push(ch),
         }
     }
-    result
+    Cow::Owned(result)
+}
+
+/// Check if a character counts as punctuation for CommonMark's flanking
+/// rules.  Non-ASCII characters that are neither alphanumeric nor whitespace
+/// stand in for Unicode punctuation and symbols.
+fn is_flanking_punctuation(ch: char) -> bool {
+    ch.is_ascii_punctuation() || (!ch.is_ascii() && !ch.is_alphanumeric() && !ch.is_whitespace())
+}
+
+/// Tell whether a delimiter run between `before` and `after` is
+/// left-flanking and right-flanking.  `None` stands for the start or end of
+/// the text, which counts as whitespace, as does the soft break marker.
+fn flanking(before: Option<char>, after: Option<char>) -> (bool, bool) {
+    let is_space = |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || c == '\x00');
+    let is_punct = |c: Option<char>| c.is_some_and(is_flanking_punctuation);
+    let left = !is_space(after) && (!is_punct(after) || is_space(before) || is_punct(before));
+    let right = !is_space(before) && (!is_punct(before) || is_space(after) || is_punct(after));
+    (left, right)
+}
+
+/// Check if `inner` wrapped in `delimiter` (`*` or `_`, single or doubled)
+/// is read back as emphasis, given the characters just before and after it.
+///
+/// Under CommonMark's flanking rules, `*` can open a run that is
+/// left-flanking and close one that is right-flanking.  `_` is stricter: it
+/// can't open or close within a word, so `foo_bar_baz` is no emphasis.
+///
+/// A delimiter next to the same character joins it in one run.  That is
+/// fine for emphasis around strong emphasis and the other way around, as in
+/// `***foo***`, where the flanking of the whole run counts.  Other runs
+/// would be read differently, like `**` for emphasis within emphasis.
+/// Neither can the same character appear unescaped within the content.
+pub fn emphasis_delimiter_fits(
+    delimiter: &str,
+    before: Option<char>,
+    inner: &str,
+    after: Option<char>,
+) -> bool {
+    let Some(ch) = delimiter.chars().next() else {
+        return false;
+    };
+    if before == Some(ch) || after == Some(ch) {
+        return false;
+    }
+    let opening_run = inner.len() - inner.trim_start_matches(ch).len();
+    let rest = inner.trim_end_matches(ch);
+    let mut closing_run = inner.len() - rest.len();
+    // An escaped delimiter character doesn't join the run
+    if closing_run > 0 && (rest.len() - rest.trim_end_matches('\\').len()) % 2 == 1 {
+        closing_run -= 1;
+    }
+    let joins = |run: usize| run == 0 || run + delimiter.len() == 3;
+    if !joins(opening_run) || !joins(closing_run) {
+        return false;
+    }
+    // The same character within the content, like a delimiter of nested
+    // emphasis, could close the emphasis early
+    let middle = &inner[opening_run..inner.len() - closing_run];
+    let mut escaped = false;
+    for c in middle.chars() {
+        if c == ch && !escaped {
+            return false;
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    let first = middle.chars().next();
+    let last = middle.chars().next_back();
+
+    let is_punct = |c: Option<char>| c.is_some_and(is_flanking_punctuation);
+    let (left, right) = flanking(before, first);
+    let opens = left && (ch == '*' || !right || is_punct(before));
+    let (left, right) = flanking(last, after);
+    let closes = right && (ch == '*' || !left || is_punct(after));
+    opens && closes
 }
 
 /// Format a code span with the appropriate number of backticks.
 /// According to CommonMark spec, if the content contains N consecutive backticks,
 /// the delimiter must use at least N+1 backticks. Spaces are added if the content
-/// starts or ends with a backtick or space (and content is not all spaces).
+/// starts or ends with a backtick, or both starts and ends with a space (and
+/// content is not all spaces).
 pub fn format_code_span(content: &str) -> String {
     // Find the maximum number of consecutive backticks in the content
     let mut max_consecutive = 0;
@@ -98,14 +258,17 @@ pub fn format_code_span(content: &str) -> String {
     // Determine if we need space padding.
     // Per CommonMark: space padding is needed when content begins or ends with
     // a backtick character. This is because backticks adjacent to the delimiter
-    // would be ambiguous. Spaces at the start/end do NOT require padding -
-    // they are preserved as-is.
+    // would be ambiguous. A space at only one end is preserved as-is, but one
+    // space is stripped from both ends of content that has a space at both
+    // and isn't all spaces, so such content needs padding too.
     let needs_space = if content.is_empty() {
         false
     } else {
         let first = content.chars().next().unwrap();
         let last = content.chars().last().unwrap();
-        first == '`' || last == '`'
+        first == '`'
+            || last == '`'
+            || (first == ' ' && last == ' ' && content.bytes().any(|b| b != b' '))
     };
 
     if needs_space {
@@ -138,35 +301,560 @@ pub fn is_valid_code_span(source: &str) -> bool {
 
 /// Escape pipe characters in table cell content.
 /// Pipes must be escaped to prevent being interpreted as cell boundaries.
-pub fn escape_table_cell(content: &str) -> String {
-    // Escape unescaped pipe characters
-    // We need to be careful not to double-escape already escaped pipes
-    let mut result = String::with_capacity(content.len());
-    let chars: Vec<char> = content.chars().collect();
+///
+/// Returns the input unchanged, without allocating, if it has no unescaped
+/// pipes.
+pub fn escape_table_cell(content: &str) -> Cow<'_, str> {
+    escape_unescaped(content, b'|')
+}
+
+/// Escape dollar signs in text, which the `math` option would otherwise
+/// have read back as the delimiters of inline math, like the two in
+/// "costs $5 and $10".
+///
+/// Returns the input unchanged, without allocating, if it has no unescaped
+/// dollar signs.
+pub fn escape_dollars(text: &str) -> Cow<'_, str> {
+    escape_unescaped(text, b'$')
+}
+
+/// Remove the backslashes from the underscores between two letters or
+/// digits in the bare URLs of escaped text, like `https://example.com/a\_b`.
+///
+/// GitHub turns bare URLs into links, but ends them at a backslash, so the
+/// escape would cut the link short.  An underscore within a word can't start
+/// or end emphasis, so it reads the same without one.  A bare URL starts
+/// with `http://`, `https://`, or `www.` at the start of a word, and ends at
+/// whitespace or `<`.
+pub fn unescape_bare_urls(escaped: &str) -> Cow<'_, str> {
+    if !escaped.contains("\\_") {
+        return Cow::Borrowed(escaped);
+    }
+    let bytes = escaped.as_bytes();
+    let mut result = String::with_capacity(escaped.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let at_word_start = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        let rest = &bytes[i..];
+        if !(at_word_start
+            && (rest.starts_with(b"http://")
+                || rest.starts_with(b"https://")
+                || rest.starts_with(b"www.")))
+        {
+            i += 1;
+            continue;
+        }
+        let end = rest
+            .iter()
+            .position(|&b| b.is_ascii_whitespace() || b == b'<')
+            .map_or(bytes.len(), |len| i + len);
+        let is_word = |j: usize| j < end && bytes[j].is_ascii_alphanumeric();
+        for j in i + 1..end.saturating_sub(2) {
+            if bytes[j] == b'\\' && bytes[j + 1] == b'_' && is_word(j - 1) && is_word(j + 2) {
+                result.push_str(&escaped[copied..j]);
+                copied = j + 1;
+            }
+        }
+        i = end;
+    }
+    if copied == 0 {
+        return Cow::Borrowed(escaped);
+    }
+    result.push_str(&escaped[copied..]);
+    Cow::Owned(result)
+}
+
+/// Remove the backslashes from the escaped backslashes of escaped text that
+/// would read back the same without them, like the one in `C:\\Users`.
+///
+/// A backslash is only an escape before ASCII punctuation, and makes a hard
+/// line break at the end of a line, so one before anything else, like a
+/// letter, a digit, or a non-ASCII character, is read as it is.  Those before
+/// whitespace and at the end of the text keep theirs, as the text may be
+/// rewrapped there, or be followed by an inline element.
+pub fn unescape_lazy_backslashes(escaped: &str) -> Cow<'_, str> {
+    if !escaped.contains("\\\\") {
+        return Cow::Borrowed(escaped);
+    }
+    let bytes = escaped.as_bytes();
+    let mut result = String::with_capacity(escaped.len());
+    let mut copied = 0;
     let mut i = 0;
-    while i < chars.len() {
-        if chars[i] == '\\' && i + 1 < chars.len() {
-            // Already escaped character - preserve both
-            result.push(chars[i]);
-            result.push(chars[i + 1]);
+    while i + 1 < bytes.len() {
+        if bytes[i] != b'\\' {
+            i += 1;
+            continue;
+        }
+        if bytes[i + 1] == b'\\'
+            && bytes
+                .get(i + 2)
+                .is_some_and(|&b| !b.is_ascii_punctuation() && !b.is_ascii_whitespace())
+        {
+            result.push_str(&escaped[copied..i]);
+            copied = i + 1;
+        }
+        i += 2;
+    }
+    if copied == 0 {
+        return Cow::Borrowed(escaped);
+    }
+    result.push_str(&escaped[copied..]);
+    Cow::Owned(result)
+}
+
+/// Return the byte index of the first `]` in `text` that isn't escaped with
+/// a backslash, like the one that closes a link label.
+pub fn closing_bracket(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b']' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Put a backslash before each occurrence of the ASCII punctuation `target`
+/// in `content` that isn't escaped already.
+fn escape_unescaped(content: &str, target: u8) -> Cow<'_, str> {
+    // We need to be careful not to double-escape already escaped characters
+    let bytes = content.as_bytes();
+    let mut result: Option<String> = None;
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 1 < bytes.len() {
+            // Already escaped character - preserve both.  Skipping only the
+            // first byte of a multibyte character is fine, since the rest
+            // can't be ASCII.
             i += 2;
-        } else if chars[i] == '|' {
-            // Unescaped pipe - escape it
+        } else if bytes[i] == target {
+            // Unescaped target - escape it
+            let result = result.get_or_insert_with(|| String::with_capacity(content.len() + 4));
+            result.push_str(&content[copied..i]);
             result.push('\\');
-            result.push('|');
+            copied = i;
             i += 1;
         } else {
-            result.push(chars[i]);
             i += 1;
         }
     }
-    result
+
+    match result {
+        Some(mut result) => {
+            result.push_str(&content[copied..]);
+            Cow::Owned(result)
+        }
+        None => Cow::Borrowed(content),
+    }
+}
+
+/// Whether a line of paragraph text would be read back as a setext heading
+/// underline, a thematic break, or an empty list item instead of as text.
+///
+/// That is, whether it consists solely of `=` characters, or solely of `-`
+/// characters and spaces.  (Lines of `*` or `_` can't occur, since those
+/// characters are always escaped.)
+fn is_underline_lookalike(line: &str) -> bool {
+    let line = line.trim_end_matches(|c: char| c.is_whitespace() || c == KEEP_MARKER);
+    !line.is_empty()
+        && (line.bytes().all(|b| b == b'=') || line.bytes().all(|b| b == b'-' || b == b' '))
+}
+
+/// The number of ASCII digits in `line` before a `.` or `)` that would be
+/// read back as the marker of an ordered list item, if it is one.
+///
+/// Any item can start a paragraph, which is where the `first` line of one
+/// is.  On the lines after it, only an item numbered 1 with some text after
+/// its marker can interrupt the paragraph, like `1. Foo` after a line break
+/// that wrapping put before it.
+fn ordered_marker_lookalike(line: &str, first: bool) -> Option<usize> {
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    if !(1..=9).contains(&digits) || !matches!(line.as_bytes().get(digits), Some(b'.' | b')')) {
+        return None;
+    }
+    let rest = &line[digits + 1..];
+    let text = rest.trim_matches(|c: char| c.is_whitespace() || c == KEEP_MARKER);
+    let spaced = rest.starts_with([' ', '\t']);
+    let is_marker = if first {
+        spaced || text.is_empty()
+    } else {
+        spaced && !text.is_empty() && line[..digits].trim_start_matches('0') == "1"
+    };
+    is_marker.then_some(digits)
+}
+
+/// Whether `line` would be read back as the start of an ATX heading, a
+/// block quote, a bullet list item, or a fenced code block, all of which
+/// can interrupt a paragraph.
+///
+/// Like ordered list items, a bullet list item only interrupts a paragraph
+/// if there is text after its marker, but any item can start one, which is
+/// where the `first` line of it is.
+fn block_start_lookalike(line: &str, first: bool) -> bool {
+    let hashes = line.bytes().take_while(|&b| b == b'#').count();
+    let rest = match line.as_bytes().first() {
+        Some(b'>') => return true,
+        Some(b'#') if hashes <= 6 => &line[hashes..],
+        Some(b'-' | b'+') => &line[1..],
+        _ => return line.starts_with("~~~"),
+    };
+    let text = rest.trim_matches(|c: char| c.is_whitespace() || c == KEEP_MARKER);
+    let spaced = rest.starts_with([' ', '\t']);
+    if hashes > 0 || first {
+        spaced || text.is_empty()
+    } else {
+        spaced && !text.is_empty()
+    }
+}
+
+/// Escape the lines of a wrapped paragraph that look like a setext heading
+/// underline, a thematic break, the marker of a list item, or the start of
+/// another block, so that they stay paragraph text.
+///
+/// Wrapping may put a run of `-` or `=` from the middle of a paragraph at
+/// the start of a line, where it would turn the line before it into a
+/// heading, or become a thematic break.  The first character of such lines
+/// is escaped with a backslash.  A paragraph may also start with a number
+/// and a period, like `1991. was a good year`, or have one wrapped to the
+/// start of a line; the period or parenthesis after the number is escaped,
+/// as in `1991\.`.  A line that reads as another block, like one indented
+/// in the source as a continuation of the paragraph, such as `# bar`, gets
+/// its first character escaped.  The first line starts with `first_prefix`,
+/// and the other lines with `prefix`.
+pub fn escape_block_lookalikes<'t>(
+    wrapped: &'t str,
+    first_prefix: &str,
+    prefix: &str,
+) -> Cow<'t, str> {
+    let needs_escape = |i: usize, line: &str| {
+        let prefix = if i == 0 { first_prefix } else { prefix };
+        let rest = line.strip_prefix(prefix)?;
+        if is_underline_lookalike(rest) || block_start_lookalike(rest, i == 0) {
+            return Some(prefix.len());
+        }
+        ordered_marker_lookalike(rest, i == 0).map(|digits| prefix.len() + digits)
+    };
+    if !wrapped
+        .split('\n')
+        .enumerate()
+        .any(|(i, line)| needs_escape(i, line).is_some())
+    {
+        return Cow::Borrowed(wrapped);
+    }
+
+    let mut result = String::with_capacity(wrapped.len() + 2);
+    for (i, line) in wrapped.split('\n').enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+        match needs_escape(i, line) {
+            Some(at) => {
+                result.push_str(&line[..at]);
+                result.push('\\');
+                result.push_str(&line[at..]);
+            }
+            None => result.push_str(line),
+        }
+    }
+    Cow::Owned(result)
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    /// The original `Vec<char>`-based implementation of [`escape_text`],
+    /// kept as a reference for the property tests.
+    fn escape_text_reference(text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let chars: Vec<char> = text.chars().collect();
+        for (i, &ch) in chars.iter().enumerate() {
+            match ch {
+                '*' | '_' | '\\' | '`' => {
+                    result.push('\\');
+                    result.push(ch);
+                }
+                '&' => {
+                    let rest: String = chars[i..].iter().collect();
+                    if entity_len(&rest).is_some() {
+                        result.push('\\');
+                    }
+                    result.push(ch);
+                }
+                '[' if i > 0 && chars[i - 1] == '^' => {
+                    result.push('\\');
+                    result.push(ch);
+                }
+                '[' | ']' => {
+                    if link_bracket_reference(&chars, i) {
+                        result.push('\\');
+                    }
+                    result.push(ch);
+                }
+                _ => result.push(ch),
+            }
+        }
+        result
+    }
+
+    /// Whether the bracket at `i` of `chars` pairs up with another, the `]`
+    /// of them right before `(` or `[`, found by scanning outward from it.
+    /// A `[` after `^` doesn't pair up.
+    fn link_bracket_reference(chars: &[char], i: usize) -> bool {
+        let opens = |j: usize| chars[j] == '[' && !(j > 0 && chars[j - 1] == '^');
+        let close = if chars[i] == '[' {
+            let mut depth = 0;
+            (i..chars.len()).find(|&j| {
+                match chars[j] {
+                    '[' if opens(j) => depth += 1,
+                    ']' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+        } else {
+            // A `]` pairs with the nearest `[` before it that isn't closed yet
+            let mut depth = 0;
+            let opened = (0..i).rev().any(|j| {
+                match chars[j] {
+                    ']' => depth += 1,
+                    '[' if opens(j) && depth == 0 => return true,
+                    '[' if opens(j) => depth -= 1,
+                    _ => {}
+                }
+                false
+            });
+            opened.then_some(i)
+        };
+        close.is_some_and(|close| matches!(chars.get(close + 1), Some('(' | '[')))
+    }
+
+    /// The original `Vec<char>`-based implementation of
+    /// [`escape_table_cell`], kept as a reference for the property tests.
+    fn escape_table_cell_reference(content: &str) -> String {
+        let mut result = String::with_capacity(content.len());
+        let chars: Vec<char> = content.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '\\' && i + 1 < chars.len() {
+                result.push(chars[i]);
+                result.push(chars[i + 1]);
+                i += 2;
+            } else if chars[i] == '|' {
+                result.push_str("\\|");
+                i += 1;
+            } else {
+                result.push(chars[i]);
+                i += 1;
+            }
+        }
+        result
+    }
+
+    /// Strings dense in the characters the escaping functions look at,
+    /// mixed with arbitrary Unicode.
+    fn markdownish() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            prop_oneof![
+                3 => prop::sample::select(vec!['*', '_', '[', ']', '(', '\\', '`', '|', '&', '#', '1', ';']),
+                1 => any::<char>(),
+            ],
+            0..32,
+        )
+        .prop_map(|chars| chars.into_iter().collect())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_escape_text_matches_reference(text in markdownish()) {
+            prop_assert_eq!(escape_text(&text), escape_text_reference(&text));
+        }
+
+        #[test]
+        fn prop_escape_text_matches_reference_on_any_string(text in any::<String>()) {
+            prop_assert_eq!(escape_text(&text), escape_text_reference(&text));
+        }
+
+        #[test]
+        fn prop_escape_table_cell_matches_reference(content in markdownish()) {
+            prop_assert_eq!(escape_table_cell(&content), escape_table_cell_reference(&content));
+        }
+
+        #[test]
+        fn prop_escape_table_cell_matches_reference_on_any_string(content in any::<String>()) {
+            prop_assert_eq!(escape_table_cell(&content), escape_table_cell_reference(&content));
+        }
+    }
+
+    #[test]
+    fn test_entity_len() {
+        assert_eq!(entity_len("&copy; 2025"), Some(6));
+        assert_eq!(entity_len("&#215;"), Some(6));
+        assert_eq!(entity_len("&#x200B;"), Some(8));
+        assert_eq!(entity_len("&copy 2025"), None);
+        assert_eq!(entity_len("&bogus;"), None);
+        assert_eq!(entity_len("&#12345678;"), None);
+        assert_eq!(entity_len("&T"), None);
+    }
+
+    #[test]
+    fn test_format_code_span_spaces() {
+        // Found by prop_format_code_span_round_trips
+        assert_eq!(format_code_span(" x "), "`  x  `");
+        assert_eq!(format_code_span(" x"), "` x`");
+        assert_eq!(format_code_span("   "), "`   `");
+        assert_eq!(format_code_span("`x"), "`` `x ``");
+    }
+
+    #[test]
+    fn test_escape_text_ampersand() {
+        assert_eq!(escape_text("&copy; &#42; AT&T"), "\\&copy; \\&#42; AT&T");
+    }
+
+    #[test]
+    fn test_encode_invisible() {
+        assert_eq!(encode_invisible("a\u{a0}b\u{200d}c"), "a&nbsp;b&zwj;c");
+        assert!(matches!(encode_invisible("a b"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_unescape_lazy_backslashes() {
+        assert_eq!(
+            unescape_lazy_backslashes("C:\\\\Users\\\\\u{c5d0}\\\\1"),
+            "C:\\Users\\\u{c5d0}\\1"
+        );
+        assert_eq!(unescape_lazy_backslashes("\\\\d+\\\\.\\*"), "\\d+\\\\.\\*");
+        assert_eq!(unescape_lazy_backslashes("a\\\\ b\\\\"), "a\\\\ b\\\\");
+        assert_eq!(unescape_lazy_backslashes("\\\\\\\\x"), "\\\\\\x");
+        assert!(matches!(
+            unescape_lazy_backslashes("\\*a\\*"),
+            Cow::Borrowed("\\*a\\*")
+        ));
+    }
+
+    #[test]
+    fn test_unescape_bare_urls() {
+        assert_eq!(
+            unescape_bare_urls("See https://example.com/a\\_b\\_c and www.x.org/d\\_e."),
+            "See https://example.com/a_b_c and www.x.org/d_e."
+        );
+        // Underscores that could delimit emphasis keep their escapes
+        assert_eq!(
+            unescape_bare_urls("https://example.com/\\_a\\_ b"),
+            "https://example.com/\\_a\\_ b"
+        );
+        assert_eq!(unescape_bare_urls("not\\_a url"), "not\\_a url");
+        assert_eq!(unescape_bare_urls("xhttps://a/b\\_c"), "xhttps://a/b\\_c");
+    }
+
+    #[test]
+    fn test_escape_block_lookalikes() {
+        assert_eq!(escape_block_lookalikes("a\n---", "", ""), "a\n\\---");
+        assert_eq!(escape_block_lookalikes("a\n=", "", ""), "a\n\\=");
+        assert_eq!(escape_block_lookalikes("- - -", "", ""), "\\- - -");
+        assert_eq!(
+            escape_block_lookalikes("> a  \n> ===", "> ", "> "),
+            "> a  \n> \\==="
+        );
+        assert_eq!(
+            escape_block_lookalikes("a\n    --  ", "", "    "),
+            "a\n    \\--  "
+        );
+    }
+
+    #[test]
+    fn test_escape_ordered_marker_lookalikes() {
+        assert_eq!(escape_block_lookalikes("1991. was", "", ""), "1991\\. was");
+        assert_eq!(escape_block_lookalikes("3)", "", ""), "3\\)");
+        assert_eq!(escape_block_lookalikes("a\n1. b", "", ""), "a\n1\\. b");
+        assert_eq!(escape_block_lookalikes("a\n01) b", "", ""), "a\n01\\) b");
+        assert_eq!(
+            escape_block_lookalikes("> 12. Angry\n> 1. Men", "> ", "> "),
+            "> 12\\. Angry\n> 1\\. Men"
+        );
+        for text in [
+            "a\n2. b",
+            "a\n1.",
+            "3.14",
+            "1234567890. a",
+            "a 1. b",
+            "12\\. a",
+        ] {
+            assert!(matches!(
+                escape_block_lookalikes(text, "", ""),
+                Cow::Borrowed(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_escape_block_start_lookalikes() {
+        assert_eq!(escape_block_lookalikes("a\n# b", "", ""), "a\n\\# b");
+        assert_eq!(escape_block_lookalikes("a\n- x", "", ""), "a\n\\- x");
+        assert_eq!(escape_block_lookalikes("+", "", ""), "\\+");
+        assert_eq!(
+            escape_block_lookalikes("> a\n> > b", "> ", "> "),
+            "> a\n> \\> b"
+        );
+        assert_eq!(escape_block_lookalikes("a\n~~~", "", ""), "a\n\\~~~");
+        for text in ["a\n#b", "a\n####### b", "a\n+", "a\n~~ b", "a # b"] {
+            assert!(matches!(
+                escape_block_lookalikes(text, "", ""),
+                Cow::Borrowed(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_escape_underline_lookalikes_borrows_text() {
+        for text in ["a\nb", "a\n-- b", "a\n=-", "a\n-x", "a\n"] {
+            assert!(matches!(
+                escape_block_lookalikes(text, "", ""),
+                Cow::Borrowed(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_closing_bracket() {
+        assert_eq!(closing_bracket("foo] bar]"), Some(3));
+        assert_eq!(closing_bracket("foo\\]] bar"), Some(5));
+        assert_eq!(closing_bracket("foo\\\\] bar"), Some(5));
+        assert_eq!(closing_bracket("foo\\]"), None);
+    }
+
+    #[test]
+    fn test_escape_text_brackets() {
+        assert_eq!(escape_text("[x] and [a [b] c]"), "[x] and [a [b] c]");
+        assert_eq!(escape_text("[a](b) [c][d]"), "\\[a\\](b) \\[c\\][d]");
+        assert_eq!(escape_text("[a [b](c)"), "[a \\[b\\](c)");
+        assert_eq!(escape_text("x](y) and ^[z"), "x](y) and ^\\[z");
+    }
+
+    #[test]
+    fn test_escape_borrows_when_unchanged() {
+        assert!(matches!(escape_text("plain 한국어 text"), Cow::Borrowed(_)));
+        assert!(matches!(escape_text("no links] here ["), Cow::Borrowed(_)));
+        assert!(matches!(escape_text("a *b*"), Cow::Owned(_)));
+        assert!(matches!(escape_table_cell("a \\| b"), Cow::Borrowed(_)));
+        assert!(matches!(escape_table_cell("a | b"), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_escape_dollars() {
+        assert_eq!(escape_dollars("costs $5 and $10"), "costs \\$5 and \\$10");
+        assert_eq!(escape_dollars("kept \\$5"), "kept \\$5");
+        assert_eq!(escape_dollars("a \\\\$5"), "a \\\\\\$5");
+        assert!(matches!(escape_dollars("no dollars"), Cow::Borrowed(_)));
+    }
+
     #[test]
     fn test_is_valid_code_span() {
         // Valid code spans
@@ -0,0 +1,553 @@
+//! Fixing the problems found by some lint rules before a document is
+//! written.
+//!
+//! The rules listed in [`Options::fix`] are applied to the AST right after
+//! it is parsed, so that the rest of the formatter sees the fixed document.
+//! Each fix is reported with a [`Warning`] whose `fixed` is true, and
+//! a problem that can't be fixed, like one in a region where formatting is
+//! disabled, with an ordinary warning.
+
+use comrak::nodes::{AstNode, NodeValue, Sourcepos};
+
+use super::{Serializer, Warning};
+use crate::{FixRule, Options};
+
+/// Why a problem in a region where formatting is disabled is left as it is.
+const DISABLED: &str = "which can't be fixed where formatting is disabled";
+
+/// The trailing punctuation removed from headings by
+/// [`FixRule::NoTrailingPunctuation`] if `heading.strip_trailing_punctuation`
+/// is empty, which is what markdownlint looks for.
+const TRAILING_PUNCTUATION: &[&str] = &[".", ",", ";", ":", "!", "。", "，", "；", "：", "！"];
+
+/// Apply the fixes of `options.fix` to the document `root`, parsed from
+/// `source`, returning a warning for each fix made or problem left.
+pub(crate) fn apply_fixes<'a>(
+    root: &'a AstNode<'a>,
+    source: &str,
+    options: &Options,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    if options.fix.is_empty() {
+        return warnings;
+    }
+    let source_lines: Vec<&str> = source.lines().collect();
+    let disabled_ranges = Serializer::collect_disabled_line_ranges(root);
+    let disabled = |node: &'a AstNode<'a>| {
+        let line = node.data.borrow().sourcepos.start.line;
+        Serializer::is_line_in_disabled_ranges(line, &disabled_ranges)
+    };
+    // Rules disabled by a directive are left alone where they are
+    let rule_ranges = Serializer::collect_rule_ranges(root);
+    let rule_disabled = |rule: FixRule, node: &'a AstNode<'a>| {
+        let line = node.data.borrow().sourcepos.start.line;
+        Serializer::is_rule_disabled(rule.name(), line, &rule_ranges)
+    };
+    let nodes: Vec<_> = root.descendants().collect();
+
+    if options.fix.contains(&FixRule::HeadingIncrement) {
+        // The original and fixed levels of the headings the next one may be
+        // under, innermost last
+        let mut parents: Vec<(u8, u8)> = Vec::new();
+        for &node in &nodes {
+            let mut data = node.data.borrow_mut();
+            let line = data.sourcepos.start.line;
+            let NodeValue::Heading(ref mut heading) = data.value else {
+                continue;
+            };
+            let level = heading.level;
+            while parents
+                .last()
+                .is_some_and(|&(original, _)| original >= level)
+            {
+                parents.pop();
+            }
+            let Some(&(_, parent)) = parents.last().filter(|(_, parent)| level > parent + 1) else {
+                parents.push((level, level));
+                continue;
+            };
+            drop(data);
+            if rule_disabled(FixRule::HeadingIncrement, node) {
+                parents.push((level, level));
+                continue;
+            }
+            if disabled(node) {
+                warnings.push(unfixed(
+                    line,
+                    format!(
+                        "heading level jumps from {} to {}, {}",
+                        parent, level, DISABLED
+                    ),
+                    FixRule::HeadingIncrement,
+                ));
+                parents.push((level, level));
+                continue;
+            }
+            if let NodeValue::Heading(ref mut heading) = node.data.borrow_mut().value {
+                heading.level = parent + 1;
+            }
+            warnings.push(fixed(
+                line,
+                format!("changed heading level {} to {}", level, parent + 1),
+                FixRule::HeadingIncrement,
+            ));
+            parents.push((level, parent + 1));
+        }
+    }
+
+    if options.fix.contains(&FixRule::NoTrailingPunctuation) {
+        let punctuation = if options.heading_strip_trailing_punctuation.is_empty() {
+            TRAILING_PUNCTUATION.iter().map(|s| s.to_string()).collect()
+        } else {
+            options.heading_strip_trailing_punctuation.clone()
+        };
+        for &node in &nodes {
+            if matches!(node.data.borrow().value, NodeValue::Heading(_))
+                && !rule_disabled(FixRule::NoTrailingPunctuation, node)
+            {
+                warnings.extend(fix_trailing_punctuation(
+                    node,
+                    &punctuation,
+                    &source_lines,
+                    disabled(node),
+                ));
+            }
+        }
+    }
+
+    if options.fix.contains(&FixRule::NoEmptyLinks) {
+        for &node in &nodes {
+            let line = {
+                let data = node.data.borrow();
+                match &data.value {
+                    NodeValue::Link(link) if link.url.is_empty() || link.url == "#" => {
+                        data.sourcepos.start.line
+                    }
+                    _ => continue,
+                }
+            };
+            if rule_disabled(FixRule::NoEmptyLinks, node) {
+                continue;
+            }
+            if disabled(node) {
+                warnings.push(unfixed(
+                    line,
+                    format!("link has an empty destination, {}", DISABLED),
+                    FixRule::NoEmptyLinks,
+                ));
+                continue;
+            }
+            while let Some(child) = node.first_child() {
+                node.insert_before(child);
+            }
+            node.detach();
+            warnings.push(fixed(
+                line,
+                "replaced link with an empty destination with its text".into(),
+                FixRule::NoEmptyLinks,
+            ));
+        }
+    }
+
+    if options.fix.contains(&FixRule::CodeLanguage) {
+        let language = &options.default_language;
+        for &node in &nodes {
+            let line = {
+                let data = node.data.borrow();
+                match &data.value {
+                    NodeValue::CodeBlock(code) if code.info.trim().is_empty() => {
+                        data.sourcepos.start.line
+                    }
+                    _ => continue,
+                }
+            };
+            if rule_disabled(FixRule::CodeLanguage, node) {
+                continue;
+            }
+            if disabled(node) || language.is_empty() {
+                let reason = if language.is_empty() {
+                    "which can't be fixed without code_block.default_language"
+                } else {
+                    DISABLED
+                };
+                warnings.push(unfixed(
+                    line,
+                    format!("code block has no language, {}", reason),
+                    FixRule::CodeLanguage,
+                ));
+                continue;
+            }
+            if let NodeValue::CodeBlock(ref mut code) = node.data.borrow_mut().value {
+                code.info = language.clone();
+            }
+            warnings.push(fixed(
+                line,
+                format!("gave code block the language {}", language),
+                FixRule::CodeLanguage,
+            ));
+        }
+    }
+
+    warnings
+}
+
+/// Remove the `punctuation` the heading `node` ends with, if any, returning
+/// what to report.  The text keeps its position in the source, so that the
+/// escapes in it are kept, unless what is removed isn't written in the
+/// source as it reads.
+fn fix_trailing_punctuation<'a>(
+    node: &'a AstNode<'a>,
+    punctuation: &[String],
+    source_lines: &[&str],
+    disabled: bool,
+) -> Option<Warning> {
+    let line = node.data.borrow().sourcepos.start.line;
+    let heading_text = crate::plain_text_of(node);
+    let heading_kept = super::heading::strip_trailing_strings(&heading_text, punctuation);
+    let mut last = node.last_child()?;
+    while let Some(child) = last.last_child() {
+        last = child;
+    }
+    let mut borrowed = last.data.borrow_mut();
+    let data = &mut *borrowed;
+    let sourcepos = data.sourcepos;
+    let NodeValue::Text(ref mut text) = data.value else {
+        return None;
+    };
+    let kept = super::heading::strip_trailing_strings(text, punctuation).len();
+    if kept == text.len() {
+        return None;
+    }
+    let removed = text[kept..].trim().to_string();
+    if disabled {
+        return Some(unfixed(
+            line,
+            format!("heading ends with \"{}\", {}", removed, DISABLED),
+            FixRule::NoTrailingPunctuation,
+        ));
+    }
+    if heading_kept.is_empty() {
+        return Some(unfixed(
+            line,
+            format!("heading is nothing but \"{}\", which is kept", removed),
+            FixRule::NoTrailingPunctuation,
+        ));
+    }
+    if kept == 0 {
+        drop(borrowed);
+        last.detach();
+    } else {
+        let written = sourcepos.start.line == sourcepos.end.line
+            && sourcepos
+                .end
+                .line
+                .checked_sub(1)
+                .and_then(|i| source_lines.get(i))
+                .and_then(|line| line.get(..sourcepos.end.column))
+                .is_some_and(|source| source.ends_with(&text[kept..]));
+        if written {
+            data.sourcepos.end.column -= text.len() - kept;
+        } else {
+            data.sourcepos = Sourcepos::from((0, 0, 0, 0));
+        }
+        *text = text[..kept].to_string().into();
+    }
+    Some(fixed(
+        line,
+        format!("removed \"{}\" from the end of heading", removed),
+        FixRule::NoTrailingPunctuation,
+    ))
+}
+
+/// A notice that `rule` made the change described by `message`.
+fn fixed(line: usize, message: String, rule: FixRule) -> Warning {
+    Warning {
+        line,
+        message: format!("{} ({})", message, rule.name()),
+        fixed: true,
+    }
+}
+
+/// A warning that `rule` left the problem described by `message` as it is.
+fn unfixed(line: usize, message: String, rule: FixRule) -> Warning {
+    Warning {
+        line,
+        message: format!("{} ({})", message, rule.name()),
+        fixed: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrak::Arena;
+
+    fn options(rule: FixRule) -> Options {
+        Options {
+            fix: vec![rule],
+            ..Options::default()
+        }
+    }
+
+    /// The warnings for the fixes of `rule` in `input`, as line, message, and
+    /// whether the problem was fixed, and the document it is fixed to.
+    fn fix(input: &str, rule: FixRule) -> (Vec<(usize, String, bool)>, String) {
+        let arena = Arena::new();
+        let options = options(rule);
+        let (root, warnings) = crate::parse(&arena, input, &options);
+        let warnings = warnings
+            .into_iter()
+            .map(|w| (w.line, w.message, w.fixed))
+            .collect();
+        (
+            warnings,
+            super::super::serialize_with_source(root, &options, Some(input)),
+        )
+    }
+
+    /// The level and plain text of the headings in `input` after fixing it
+    /// with `rule`.
+    fn fixed_headings(input: &str, rule: FixRule) -> Vec<(u8, String)> {
+        let arena = Arena::new();
+        let (root, _) = crate::parse(&arena, input, &options(rule));
+        root.descendants()
+            .filter_map(|node| match node.data.borrow().value {
+                NodeValue::Heading(ref heading) => {
+                    Some((heading.level, crate::plain_text_of(node)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fix_heading_increment() {
+        let input = "# A\n\n### B\n\n#### C\n\n### D\n\n## E\n\n#### F\n";
+        assert_eq!(
+            fixed_headings(input, FixRule::HeadingIncrement),
+            [
+                (1, "A".into()),
+                (2, "B".into()),
+                (3, "C".into()),
+                (2, "D".into()),
+                (2, "E".into()),
+                (3, "F".into()),
+            ]
+        );
+        let (warnings, _) = fix(input, FixRule::HeadingIncrement);
+        assert_eq!(
+            warnings,
+            [
+                (
+                    3,
+                    "changed heading level 3 to 2 (heading-increment)".into(),
+                    true
+                ),
+                (
+                    5,
+                    "changed heading level 4 to 3 (heading-increment)".into(),
+                    true
+                ),
+                (
+                    7,
+                    "changed heading level 3 to 2 (heading-increment)".into(),
+                    true
+                ),
+                (
+                    11,
+                    "changed heading level 4 to 3 (heading-increment)".into(),
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fix_heading_increment_nothing_to_fix() {
+        let input = "## A\n\n### B\n\n# C\n\n## D\n";
+        let (warnings, output) = fix(input, FixRule::HeadingIncrement);
+        assert!(warnings.is_empty());
+        assert_eq!(output, "A\n-\n\n### B\n\nC\n=\n\nD\n-\n");
+    }
+
+    #[test]
+    fn test_fix_heading_increment_in_disabled_region() {
+        let input =
+            "# A\n\n<!-- hongdown-disable -->\n\n### B\n\n<!-- hongdown-enable -->\n\n#### C\n";
+        let (warnings, _) = fix(input, FixRule::HeadingIncrement);
+        assert_eq!(
+            warnings,
+            [(
+                5,
+                "heading level jumps from 1 to 3, which can't be fixed where formatting is \
+                 disabled (heading-increment)"
+                    .into(),
+                false
+            )]
+        );
+        assert_eq!(
+            fixed_headings(input, FixRule::HeadingIncrement),
+            [(1, "A".into()), (3, "B".into()), (4, "C".into())]
+        );
+    }
+
+    #[test]
+    fn test_fix_trailing_punctuation() {
+        let input = "# Usage:\n\n## *Note*!\n\n## Why\\?\n\n## Options *etc.*\n";
+        assert_eq!(
+            fixed_headings(input, FixRule::NoTrailingPunctuation),
+            [
+                (1, "Usage".into()),
+                (2, "Note".into()),
+                (2, "Why?".into()),
+                (2, "Options etc".into()),
+            ]
+        );
+        let (warnings, output) = fix(input, FixRule::NoTrailingPunctuation);
+        assert_eq!(
+            warnings,
+            [
+                (
+                    1,
+                    "removed \":\" from the end of heading (no-trailing-punctuation)".into(),
+                    true
+                ),
+                (
+                    3,
+                    "removed \"!\" from the end of heading (no-trailing-punctuation)".into(),
+                    true
+                ),
+                (
+                    7,
+                    "removed \".\" from the end of heading (no-trailing-punctuation)".into(),
+                    true
+                ),
+            ]
+        );
+        assert_eq!(
+            output,
+            "Usage\n=====\n\n*Note*\n------\n\nWhy\\?\n-----\n\nOptions *etc*\n-------------\n"
+        );
+    }
+
+    #[test]
+    fn test_fix_trailing_punctuation_configured() {
+        let options = Options {
+            fix: vec![FixRule::NoTrailingPunctuation],
+            heading_strip_trailing_punctuation: vec!["?".into()],
+            ..Options::default()
+        };
+        let arena = Arena::new();
+        let (_, warnings) = crate::parse(&arena, "# Why?\n\n## Usage:\n", &options);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "removed \"?\" from the end of heading (no-trailing-punctuation)"
+        );
+    }
+
+    #[test]
+    fn test_fix_trailing_punctuation_unfixable() {
+        let (warnings, output) = fix("# !!!\n", FixRule::NoTrailingPunctuation);
+        assert_eq!(
+            warnings,
+            [(
+                1,
+                "heading is nothing but \"!!!\", which is kept (no-trailing-punctuation)".into(),
+                false
+            )]
+        );
+        assert_eq!(output, "!!!\n===\n");
+    }
+
+    #[test]
+    fn test_fix_empty_links() {
+        let input = "See [the docs]() and [*this*](#), not [that](#that).\n";
+        let (warnings, output) = fix(input, FixRule::NoEmptyLinks);
+        assert_eq!(
+            warnings,
+            [
+                (
+                    1,
+                    "replaced link with an empty destination with its text (no-empty-links)".into(),
+                    true
+                ),
+                (
+                    1,
+                    "replaced link with an empty destination with its text (no-empty-links)".into(),
+                    true
+                ),
+            ]
+        );
+        assert_eq!(output, "See the docs and *this*, not [that](#that).\n");
+    }
+
+    #[test]
+    fn test_fix_empty_links_in_disabled_region() {
+        let input = "<!-- hongdown-disable -->\n\nSee [the docs]().\n";
+        let (warnings, output) = fix(input, FixRule::NoEmptyLinks);
+        assert_eq!(
+            warnings,
+            [(
+                3,
+                "link has an empty destination, which can't be fixed where formatting is \
+                 disabled (no-empty-links)"
+                    .into(),
+                false
+            )]
+        );
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_fix_code_language() {
+        let input = "~~~~\nplain\n~~~~\n\n~~~~ rust\nfn main() {}\n~~~~\n\n    indented\n";
+        let arena = Arena::new();
+        let options = Options {
+            default_language: "text".into(),
+            ..options(FixRule::CodeLanguage)
+        };
+        let (root, warnings) = crate::parse(&arena, input, &options);
+        let warnings: Vec<_> = warnings.into_iter().map(|w| (w.line, w.message)).collect();
+        assert_eq!(
+            warnings,
+            [
+                (
+                    1,
+                    "gave code block the language text (code-language)".into()
+                ),
+                (
+                    9,
+                    "gave code block the language text (code-language)".into()
+                ),
+            ]
+        );
+        assert_eq!(
+            super::super::serialize_with_source(root, &options, Some(input)),
+            "~~~~ text\nplain\n~~~~\n\n~~~~ rust\nfn main() {}\n~~~~\n\n~~~~ text\nindented\n~~~~\n"
+        );
+    }
+
+    #[test]
+    fn test_fix_code_language_unfixable() {
+        let (warnings, output) = fix("~~~~\nplain\n~~~~\n", FixRule::CodeLanguage);
+        assert_eq!(
+            warnings,
+            [(
+                1,
+                "code block has no language, which can't be fixed without \
+                 code_block.default_language (code-language)"
+                    .into(),
+                false
+            )]
+        );
+        assert_eq!(output, "~~~~\nplain\n~~~~\n");
+    }
+
+    #[test]
+    fn test_fix_nothing_without_rules() {
+        let arena = Arena::new();
+        let (_, warnings) = crate::parse(&arena, "# A\n\n### B:\n", &Options::default());
+        assert!(warnings.is_empty());
+    }
+}
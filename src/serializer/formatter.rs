@@ -4,7 +4,7 @@
 //! on code block contents. It handles process spawning, timeout management,
 //! and error handling.
 
-use std::io::Write;
+use std::io::{ErrorKind, Read, Write};
 use std::process::{Command, Stdio};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -76,6 +76,12 @@ impl std::error::Error for FormatterError {
 /// # Returns
 ///
 /// The formatted code from stdout, or an error if the formatter failed.
+///
+/// The code is written and the output read on threads of their own while
+/// the process runs, so that a formatter that writes more than a pipe holds
+/// before it has read all of its input doesn't block until it times out.
+/// A formatter that exits without reading its input isn't an error in
+/// itself; its exit status says whether it failed.
 pub fn run_formatter(
     command: &[String],
     code: &str,
@@ -95,13 +101,17 @@ pub fn run_formatter(
         .spawn()
         .map_err(FormatterError::Spawn)?;
 
-    // Write code to stdin
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin
-            .write_all(code.as_bytes())
-            .map_err(FormatterError::Stdin)?;
-        // stdin is dropped here, closing it
-    }
+    // Write code to stdin, and read stdout and stderr, while the process
+    // runs; stdin is closed once all of the code is written
+    let stdin = child.stdin.take().map(|mut stdin| {
+        let code = code.to_string();
+        thread::spawn(move || match stdin.write_all(code.as_bytes()) {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => Err(e),
+            _ => Ok(()),
+        })
+    });
+    let stdout = child.stdout.take().map(read_to_end);
+    let stderr = child.stderr.take().map(read_to_end);
 
     // Wait with timeout using polling
     let timeout = Duration::from_secs(timeout_secs);
@@ -112,13 +122,17 @@ pub fn run_formatter(
         match child.try_wait() {
             Ok(Some(status)) => {
                 // Process finished
-                let output = child.wait_with_output().map_err(FormatterError::Spawn)?;
+                if let Some(Ok(Err(e))) = stdin.map(thread::JoinHandle::join) {
+                    return Err(FormatterError::Stdin(e));
+                }
+                let stdout = join_output(stdout);
+                let stderr = join_output(stderr);
                 if status.success() {
-                    return String::from_utf8(output.stdout).map_err(FormatterError::InvalidUtf8);
+                    return String::from_utf8(stdout).map_err(FormatterError::InvalidUtf8);
                 } else {
                     return Err(FormatterError::NonZeroExit {
                         code: status.code(),
-                        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                        stderr: String::from_utf8_lossy(&stderr).to_string(),
                     });
                 }
             }
@@ -126,6 +140,7 @@ pub fn run_formatter(
                 // Still running
                 if start.elapsed() >= timeout {
                     let _ = child.kill();
+                    let _ = child.wait();
                     return Err(FormatterError::Timeout);
                 }
                 thread::sleep(poll_interval);
@@ -135,6 +150,22 @@ pub fn run_formatter(
     }
 }
 
+/// Read `pipe` to its end on a thread of its own.
+fn read_to_end(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = pipe.read_to_end(&mut output);
+        output
+    })
+}
+
+/// The output read by a thread [`read_to_end`] started, if there is one.
+fn join_output(reader: Option<thread::JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    reader
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,9 +196,35 @@ mod tests {
 
     #[test]
     fn test_run_formatter_nonzero_exit() {
-        // false always exits with code 1
-        let result = run_formatter(&["false".to_string()], "code", 5);
-        assert!(matches!(result, Err(FormatterError::NonZeroExit { .. })));
+        // false always exits with code 1, without reading its input
+        for _ in 0..20 {
+            let result = run_formatter(&["false".to_string()], &"code\n".repeat(50_000), 5);
+            assert!(matches!(result, Err(FormatterError::NonZeroExit { .. })));
+        }
+    }
+
+    #[test]
+    fn test_run_formatter_nonzero_exit_stderr() {
+        let command = [
+            "sh",
+            "-c",
+            "cat >/dev/null; echo 'syntax error' >&2; exit 3",
+        ];
+        let command: Vec<_> = command.iter().map(|arg| arg.to_string()).collect();
+        let result = run_formatter(&command, "code", 5);
+        let Err(FormatterError::NonZeroExit { code, stderr }) = result else {
+            panic!("expected a nonzero exit, got {:?}", result);
+        };
+        assert_eq!(code, Some(3));
+        assert_eq!(stderr, "syntax error\n");
+    }
+
+    #[test]
+    fn test_run_formatter_large_output() {
+        // More than a pipe holds, written before all of the input is read
+        let input = "x".repeat(1_000_000);
+        let result = run_formatter(&["cat".to_string()], &input, 5);
+        assert_eq!(result.unwrap(), input);
     }
 
     #[test]
@@ -1,6 +1,14 @@
 // SPDX-FileCopyrightText: 2025 Hong Minhee <https://hongminhee.org/>
 // SPDX-License-Identifier: GPL-3.0-or-later
-//! Heading sentence case conversion.
+//! Heading text normalization, sentence case conversion, and numbering.
+
+use std::sync::LazyLock;
+
+use comrak::Arena;
+use comrak::nodes::{AstNode, NodeValue, Sourcepos};
+use regex::Regex;
+
+use crate::HeadingLevel;
 
 // Include generated proper nouns constants
 include!(concat!(env!("OUT_DIR"), "/proper_nouns_generated.rs"));
@@ -58,6 +66,105 @@ pub fn to_sentence_case(
     result
 }
 
+/// Normalize the whitespace of serialized heading text, and strip any of the
+/// `strip_trailing` strings from its end.
+///
+/// Runs of whitespace are collapsed to a single space and trimmed from both
+/// ends of each line (lines are separated by hard line breaks), except inside
+/// code spans and link destinations, which are copied as they are.  Escaped
+/// characters are never stripped, and neither is the whole heading.
+pub fn normalize_heading(text: &str, strip_trailing: &[String]) -> String {
+    let mut lines: Vec<String> = text.split('\n').map(collapse_whitespace).collect();
+    if let Some(last) = lines.last_mut() {
+        let stripped = strip_trailing_strings(last, strip_trailing);
+        if !stripped.is_empty() {
+            last.truncate(stripped.len());
+        }
+    }
+    lines.join("\n")
+}
+
+/// Collapse runs of whitespace in a line of heading text, and trim it.
+fn collapse_whitespace(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line.trim();
+    while let Some(ch) = rest.chars().next() {
+        let verbatim = match ch {
+            '\\' => rest.char_indices().nth(2).map_or(rest.len(), |(i, _)| i),
+            '`' => code_span_len(rest),
+            ']' if rest[1..].starts_with('(') => 1 + link_destination_len(&rest[1..]),
+            _ => 0,
+        };
+        if verbatim > 0 {
+            result.push_str(&rest[..verbatim]);
+            rest = &rest[verbatim..];
+        } else if ch.is_whitespace() {
+            result.push(' ');
+            rest = rest.trim_start();
+        } else {
+            result.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    result
+}
+
+/// The length of the code span at the start of `text`, or of the run of
+/// backticks there if it is not closed.
+fn code_span_len(text: &str) -> usize {
+    let run = text.len() - text.trim_start_matches('`').len();
+    let mut offset = run;
+    while let Some(start) = text[offset..].find('`') {
+        let start = offset + start;
+        let len = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if len == run {
+            return start + len;
+        }
+        offset = start + len;
+    }
+    run
+}
+
+/// The length of the parenthesized link destination and title at the start
+/// of `text`, or of the whole text if the parentheses are not balanced.
+fn link_destination_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, ch) in text.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Strip any of `strings` from the end of `text` repeatedly, along with
+/// whitespace before them, except where one is escaped with a backslash.
+pub(super) fn strip_trailing_strings<'t>(text: &'t str, strings: &[String]) -> &'t str {
+    let mut text = text;
+    'strip: loop {
+        for suffix in strings.iter().filter(|s| !s.is_empty()) {
+            if let Some(rest) = text.strip_suffix(suffix.as_str()) {
+                let backslashes = rest.len() - rest.trim_end_matches('\\').len();
+                if backslashes % 2 == 0 {
+                    text = rest.trim_end();
+                    continue 'strip;
+                }
+            }
+        }
+        return text;
+    }
+}
+
 /// Token types for parsing heading text.
 #[derive(Debug, PartialEq)]
 enum Token {
@@ -383,7 +490,7 @@ fn collect_multiword_proper_nouns(
     }
 
     // Sort by length (longest first) to handle overlapping matches correctly
-    multiword_nouns.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+    multiword_nouns.sort_by_key(|b| std::cmp::Reverse(b.0.len()));
 
     multiword_nouns
 }
@@ -881,10 +988,155 @@ fn capitalize_first(word: &str) -> String {
     }
 }
 
+/// A section number at the start of a heading, like `3`, `3.2`, or `3.2.`,
+/// and the whitespace after it.
+static NUMBER_PREFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[0-9]+(?:\.[0-9]+)*\.?[ \t]+").unwrap());
+
+/// The length of the section number at the start of the heading text
+/// `text`, with the whitespace after it, or `None` if it doesn't start with
+/// one.
+pub(crate) fn number_prefix_len(text: &str) -> Option<usize> {
+    NUMBER_PREFIX.find(text).map(|m| m.end())
+}
+
+/// Number the headings at the top level of the document `root`, parsed from
+/// `source`, at the `levels`, in document order.
+///
+/// Any section number a heading at one of the levels already starts with is
+/// replaced, so numbering the same document again changes nothing.  Each
+/// number has a part for every numbered level up to the heading's own, like
+/// `3.2` for a level 3 heading when the levels are 2 and 3, and a heading
+/// restarts the numbers of the levels below it, including when it is at
+/// a level that isn't numbered itself, like a level 1 heading in this case.
+pub(crate) fn number_headings<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    source: &str,
+    levels: &[HeadingLevel],
+) {
+    let source_lines: Vec<&str> = source.lines().collect();
+    let mut numbered: Vec<usize> = levels.iter().map(|l| l.get() as usize).collect();
+    numbered.sort_unstable();
+    numbered.dedup();
+    let mut counters = [0usize; HeadingLevel::MAX as usize + 1];
+    for node in root.children() {
+        let level = match node.data.borrow().value {
+            NodeValue::Heading(ref heading) => heading.level as usize,
+            _ => continue,
+        };
+        for counter in &mut counters[level + 1..] {
+            *counter = 0;
+        }
+        if !numbered.contains(&level) {
+            continue;
+        }
+        counters[level] += 1;
+        let number = numbered
+            .iter()
+            .take_while(|&&l| l <= level)
+            .map(|&l| counters[l].to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+        strip_number(node, &source_lines);
+        let text = arena.alloc(NodeValue::Text(format!("{} ", number).into()).into());
+        node.prepend(text);
+    }
+}
+
+/// Remove the section number the heading `node` starts with, if any.
+///
+/// The text after the number keeps its position in the source, so that the
+/// escapes in it are kept, unless the number isn't written in the source as
+/// it reads.
+fn strip_number<'a>(node: &'a AstNode<'a>, source_lines: &[&str]) {
+    let Some(first) = node.first_child() else {
+        return;
+    };
+    let mut data = first.data.borrow_mut();
+    let sourcepos = data.sourcepos;
+    let NodeValue::Text(ref mut text) = data.value else {
+        return;
+    };
+    let Some(len) = number_prefix_len(text) else {
+        return;
+    };
+    if len == text.len() {
+        drop(data);
+        first.detach();
+        return;
+    }
+    let written = sourcepos
+        .start
+        .line
+        .checked_sub(1)
+        .and_then(|i| source_lines.get(i))
+        .and_then(|line| line.get(sourcepos.start.column.saturating_sub(1)..))
+        .is_some_and(|rest| rest.starts_with(&text[..len]));
+    *text = text[len..].to_string().into();
+    if written {
+        data.sourcepos.start.column += len;
+    } else {
+        data.sourcepos = Sourcepos::from((0, 0, 0, 0));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_number_prefix_len() {
+        assert_eq!(number_prefix_len("3 Usage"), Some(2));
+        assert_eq!(number_prefix_len("3.2 Usage"), Some(4));
+        assert_eq!(number_prefix_len("3.2.  Usage"), Some(6));
+        assert_eq!(number_prefix_len("3.2"), None);
+        assert_eq!(number_prefix_len("3rd party"), None);
+        assert_eq!(number_prefix_len("Usage"), None);
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_normalize_heading_collapses_whitespace() {
+        assert_eq!(
+            normalize_heading("  Getting   started  ", &[]),
+            "Getting started"
+        );
+        assert_eq!(
+            normalize_heading(" First \n  second ", &[]),
+            "First\nsecond"
+        );
+    }
+
+    #[test]
+    fn test_normalize_heading_keeps_code_spans_and_destinations() {
+        assert_eq!(
+            normalize_heading("The  `a   b`  and ``x ` y``", &[]),
+            "The `a   b` and ``x ` y``"
+        );
+        assert_eq!(
+            normalize_heading("A  [link](<a  b> \"t  (x)\")  here", &[]),
+            "A [link](<a  b> \"t  (x)\") here"
+        );
+        assert_eq!(normalize_heading("Unclosed  `code", &[]), "Unclosed `code");
+    }
+
+    #[test]
+    fn test_normalize_heading_strips_trailing_punctuation() {
+        let strip = strings(&[":", "."]);
+        assert_eq!(normalize_heading("Changes:", &strip), "Changes");
+        assert_eq!(normalize_heading("Notes .:", &strip), "Notes");
+        assert_eq!(normalize_heading("Changes:", &[]), "Changes:");
+        assert_eq!(normalize_heading("Version 1\\.", &strip), "Version 1\\.");
+        assert_eq!(normalize_heading("Version 1\\\\.", &strip), "Version 1\\\\");
+        assert_eq!(normalize_heading("`a:`", &strip), "`a:`");
+        assert_eq!(normalize_heading(":.", &strip), ":.");
+        assert_eq!(normalize_heading("Many:\nlines:", &strip), "Many:\nlines");
+    }
+
     #[test]
     fn test_basic_sentence_case() {
         assert_eq!(to_sentence_case("Hello World", &[], &[]), "Hello world");
@@ -0,0 +1,160 @@
+//! The HTML void elements, like `<br>` and `<hr>`, that authors use for
+//! layout.
+//!
+//! All other HTML is written as it is.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::Serializer;
+use crate::VoidTagStyle;
+
+/// The names of the HTML elements that can't have content, and so have no
+/// end tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// The name and attributes of the void element that `html` is the tag of,
+/// like `br` and an empty string for `<br />`, or `None` if it is something
+/// else.
+///
+/// A `/` right after an unquoted attribute value is taken as part of the
+/// value, so such tags aren't recognized.
+fn void_tag(html: &str) -> Option<(&str, &str)> {
+    let inner = html.trim().strip_prefix('<')?.strip_suffix('>')?;
+    let name_end = inner
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(inner.len());
+    let (name, rest) = inner.split_at(name_end);
+    if !VOID_ELEMENTS.iter().any(|v| v.eq_ignore_ascii_case(name)) {
+        return None;
+    }
+    if !rest.is_empty() && !rest.starts_with(|c: char| c.is_ascii_whitespace() || c == '/') {
+        return None;
+    }
+    let rest = rest.trim_end();
+    let attributes = match rest.strip_suffix('/') {
+        Some(attributes)
+            if attributes.is_empty()
+                || attributes
+                    .ends_with(|c: char| c.is_ascii_whitespace() || c == '"' || c == '\'') =>
+        {
+            attributes
+        }
+        Some(_) => return None,
+        None => rest,
+    };
+    if attributes.contains(['<', '>', '\n']) {
+        return None;
+    }
+    Some((name, attributes.trim()))
+}
+
+/// The tag of a void element written in `style`, keeping its attributes.
+fn normalize_void_tag(name: &str, attributes: &str, style: VoidTagStyle) -> String {
+    let mut tag = format!("<{}", name.to_ascii_lowercase());
+    if !attributes.is_empty() {
+        tag.push(' ');
+        tag.push_str(attributes);
+    }
+    tag.push_str(match style {
+        VoidTagStyle::Html => ">",
+        VoidTagStyle::Xhtml => " />",
+    });
+    tag
+}
+
+/// Whether `html` is a tag of the `tag_name` void element without
+/// attributes.
+fn is_bare_tag(html: &str, tag_name: &str) -> bool {
+    void_tag(html).is_some_and(|(name, attributes)| {
+        name.eq_ignore_ascii_case(tag_name) && attributes.is_empty()
+    })
+}
+
+impl<'a> Serializer<'a> {
+    /// Inline HTML as it is written, with the tags of void elements
+    /// normalized if the options say so.
+    pub(super) fn inline_html(&self, html: &str) -> String {
+        if self.options.html_normalize_void_tags
+            && let Some((name, attributes)) = void_tag(html)
+        {
+            return normalize_void_tag(name, attributes, self.options.html_void_tag_style);
+        }
+        html.to_string()
+    }
+
+    /// Serialize an HTML block as it is, unless it is a single void element,
+    /// which may become a thematic break or have its tag normalized.
+    pub(super) fn serialize_html_block(&mut self, literal: &str) {
+        if self.options.html_hr_to_thematic_break && is_bare_tag(literal, "hr") {
+            self.serialize_thematic_break();
+            return;
+        }
+        let normalized = match void_tag(literal) {
+            Some((name, attributes)) if self.options.html_normalize_void_tags => {
+                let style = self.options.html_void_tag_style;
+                format!("{}\n", normalize_void_tag(name, attributes, style))
+            }
+            _ => literal.to_string(),
+        };
+        // Preserve HTML blocks (like comments) as-is, apart from the prefix
+        // of the block quotes and list items they are in
+        if self.line_prefix.is_empty() {
+            self.output.push_str(&normalized);
+        } else {
+            for line in normalized.lines() {
+                self.write_line(line);
+            }
+        }
+    }
+
+    /// Whether `node` is a `<br>` at the end of a line of a paragraph that
+    /// is written as a hard line break instead.  Those in table cells and
+    /// in other inline elements aren't.
+    pub(super) fn is_br_hard_break<'b>(&self, node: &'b AstNode<'b>) -> bool {
+        if !self.options.html_br_to_hard_break {
+            return false;
+        }
+        let NodeValue::HtmlInline(html) = &node.data.borrow().value else {
+            return false;
+        };
+        is_bare_tag(html, "br")
+            && node
+                .parent()
+                .is_some_and(|p| matches!(p.data.borrow().value, NodeValue::Paragraph))
+            && node
+                .next_sibling()
+                .is_some_and(|n| matches!(n.data.borrow().value, NodeValue::SoftBreak))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_void_tag() {
+        assert_eq!(void_tag("<br>"), Some(("br", "")));
+        assert_eq!(void_tag("<BR/>"), Some(("BR", "")));
+        assert_eq!(void_tag("<br  />\n"), Some(("br", "")));
+        assert_eq!(
+            void_tag(r#"<img src="a/b.png" alt="A" />"#),
+            Some(("img", r#"src="a/b.png" alt="A""#))
+        );
+        assert_eq!(void_tag("<img src=a/>"), None);
+        assert_eq!(void_tag("<brr>"), None);
+        assert_eq!(void_tag("<div>"), None);
+        assert_eq!(void_tag("</br>"), None);
+    }
+
+    #[test]
+    fn test_normalize_void_tag() {
+        assert_eq!(normalize_void_tag("BR", "", VoidTagStyle::Html), "<br>");
+        assert_eq!(
+            normalize_void_tag("hr", r#"class="x""#, VoidTagStyle::Xhtml),
+            r#"<hr class="x" />"#
+        );
+    }
+}
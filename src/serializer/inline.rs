@@ -1,10 +1,16 @@
 //! Inline node collection and text extraction logic.
 
-use comrak::nodes::{AstNode, NodeValue};
+use comrak::nodes::{Ast, AstNode, NodeValue};
 
 use super::Serializer;
 use super::escape;
+use super::link;
+use super::locale;
 use super::punctuation;
+use super::state::{self, emphasis_delimiter};
+use super::whitespace;
+use super::wrap;
+use crate::{BackslashEscape, EntityMode, TemplateSyntax, WrapMode};
 
 impl<'a> Serializer<'a> {
     pub(super) fn collect_text<'b>(&mut self, node: &'b AstNode<'b>) -> String {
@@ -39,15 +45,7 @@ impl<'a> Serializer<'a> {
     fn collect_text_recursive<'b>(&mut self, node: &'b AstNode<'b>, text: &mut String) {
         match &node.data.borrow().value {
             NodeValue::Text(t) => {
-                // Apply punctuation transformation first
-                let transformed = punctuation::transform_punctuation(t, self.options);
-
-                // Try to preserve escapes from the original source
-                if let Some(source) = self.extract_source(node) {
-                    text.push_str(&Self::escape_text_preserving_source(&transformed, &source));
-                } else {
-                    text.push_str(&escape::escape_text(&transformed));
-                }
+                self.push_text(text, node, t);
             }
             NodeValue::Code(code) => {
                 // Try to use original source to preserve spacing, but validate it first.
@@ -65,51 +63,72 @@ impl<'a> Serializer<'a> {
                     text.push_str(&escape::format_code_span(&code.literal));
                 }
             }
-            NodeValue::Emph => {
-                let delim = self.get_emphasis_delimiter(node);
-                text.push(delim);
-                for child in node.children() {
-                    self.collect_text_recursive(child, text);
-                }
-                text.push(delim);
+            NodeValue::Emph | NodeValue::Strong => {
+                self.push_emphasis(text, node, Self::collect_text_recursive);
             }
-            NodeValue::Strong => {
-                let delim = self.get_strong_delimiter(node);
-                text.push_str(delim);
+            NodeValue::Strikethrough => {
+                // Only parsed in the `commonmark` dialect, which has no
+                // strikethrough of its own
+                text.push_str("<del>");
                 for child in node.children() {
                     self.collect_text_recursive(child, text);
                 }
-                text.push_str(delim);
+                text.push_str("</del>");
             }
             NodeValue::SoftBreak => {
-                text.push(' ');
+                if Self::soft_break_joins_with_space(text, node) {
+                    text.push(' ');
+                }
+            }
+            NodeValue::LineBreak => {
+                text.push('\n');
             }
             NodeValue::Link(link) => {
+                let url = &*self.link_url(&link.url);
                 // Handle reference-style links in headings
                 if let Some((link_text, label)) = self.get_reference_style_info(node) {
-                    self.format_reference_link(text, &link_text, &label, &link.url, &link.title);
+                    let follows_bracket = Self::next_sibling_starts_with_bracket(node);
+                    self.format_reference_link(
+                        text,
+                        &link_text,
+                        &label,
+                        url,
+                        &link.title,
+                        follows_bracket,
+                    );
                 } else {
                     // For inline links, just output plain text (or format as inline?)
                     // In headings, we typically want reference style for external links
                     let link_text = self.collect_raw_text(node);
-                    if Self::is_external_url(&link.url) {
+                    if link.title.is_empty() && !link.url.is_empty() && link_text == link.url {
+                        Self::format_autolink(text, url);
+                    } else if self.links_by_reference(url) {
                         // Headings don't have footnote references as siblings, so no need for collapsed style
                         self.format_external_link_as_reference(
                             text,
                             &link_text,
-                            &link.url,
+                            url,
                             &link.title,
                             false,
                         );
                     } else {
-                        Self::format_inline_link(text, &link_text, &link.url, &link.title);
+                        Self::format_inline_link(text, &link_text, url, &link.title);
                     }
                 }
             }
             NodeValue::Image(image) => {
                 // Preserve images in headings using inline syntax
                 let alt_text = self.collect_raw_text(node);
-                Self::format_inline_image(text, &alt_text, &image.url, &image.title);
+                let url = self.link_url(&image.url);
+                Self::format_inline_image(text, &alt_text, &url, &image.title);
+            }
+            NodeValue::Math(math) => {
+                text.push_str(&self.math_source(node, math));
+            }
+            NodeValue::FootnoteReference(footnote_ref) => {
+                text.push_str("[^");
+                text.push_str(&footnote_ref.name);
+                text.push(']');
             }
             _ => {
                 for child in node.children() {
@@ -119,6 +138,130 @@ impl<'a> Serializer<'a> {
         }
     }
 
+    /// Write the emphasis or strong emphasis `node` to `output`, serializing
+    /// its children with `collect`.  Return whether the delimiters written,
+    /// and those of any emphasis right after it, are known to be read back
+    /// as such.
+    ///
+    /// Emphasis can't start or end with whitespace, so any whitespace at
+    /// either end of the content is written outside the delimiters.
+    fn push_emphasis<'b>(
+        &mut self,
+        output: &mut String,
+        node: &'b AstNode<'b>,
+        collect: fn(&mut Self, &'b AstNode<'b>, &mut String),
+    ) -> bool {
+        let is_space = |c: char| c.is_whitespace() || c == '\x00';
+        let strong = matches!(node.data.borrow().value, NodeValue::Strong);
+        let mut preferred = self.preferred_emphasis_delimiter(node);
+        if self.enclosing_emphasis == Some(preferred) {
+            preferred = if preferred == '_' { '*' } else { '_' };
+        }
+        let last = output.chars().next_back();
+        let rest = &output[..output.len() - last.map_or(0, char::len_utf8)];
+        // An escaped character doesn't join a delimiter run, and the
+        // backslash is punctuation just like it
+        let before = if (rest.len() - rest.trim_end_matches('\\').len()) % 2 == 1 {
+            Some('\\')
+        } else {
+            last
+        };
+        let next_emphasis = node.next_sibling().filter(|next| {
+            matches!(
+                next.data.borrow().value,
+                NodeValue::Emph | NodeValue::Strong
+            )
+        });
+
+        let enclosing = self.enclosing_emphasis;
+        let mut content = String::new();
+        let mut delimiter = None;
+        let mut rest_fits = true;
+        // Nested emphasis first avoids the delimiter preferred here, but that
+        // can leave no delimiter that fits, as in `***foo***bar`
+        let other = if preferred == '_' { '*' } else { '_' };
+        // Emphasis nested right inside emphasis, as in `_____foo_____`, takes
+        // its delimiters from the one around it, so when the preferred one
+        // doesn't fit, the whole run keeps the delimiters of the source
+        let is_emphasis = |child: Option<&'b AstNode<'b>>| {
+            child.is_some_and(|c| {
+                matches!(c.data.borrow().value, NodeValue::Emph | NodeValue::Strong)
+            })
+        };
+        let run = is_emphasis(node.first_child()) || is_emphasis(node.last_child());
+        let keep = self.keep_source_emphasis;
+        for hint in [Some(preferred), Some(other), None] {
+            if run && hint != Some(preferred) {
+                self.keep_source_emphasis = true;
+            }
+            self.enclosing_emphasis = hint;
+            content.clear();
+            for child in node.children() {
+                collect(self, child, &mut content);
+            }
+            self.enclosing_emphasis = enclosing;
+            self.keep_source_emphasis = keep;
+            let trimmed = content.trim_start_matches(is_space);
+            let inner = trimmed.trim_end_matches(is_space);
+            if inner.is_empty() {
+                // Delimiters around nothing but whitespace wouldn't read as
+                // emphasis, so only the whitespace is written
+                let kind = if strong {
+                    "strong emphasis"
+                } else {
+                    "emphasis"
+                };
+                let line = node.data.borrow().sourcepos.start.line;
+                self.add_warning_once(line, format!("empty {} dropped", kind));
+                output.push_str(&content);
+                return true;
+            }
+            let before = content[..content.len() - trimmed.len()]
+                .chars()
+                .next_back()
+                .or(before);
+            let trailing = trimmed[inner.len()..].chars().next();
+            if keep || (run && hint != Some(preferred)) {
+                break;
+            }
+            let after = trailing.or_else(|| self.char_after_inline(node));
+            let mut fitting =
+                Self::fitting_emphasis_delimiters(preferred, strong, before, inner, after)
+                    .filter(|delimiter| !run || delimiter.starts_with(preferred));
+            delimiter = fitting.next();
+            // Emphasis that follows right after has to fit after this one
+            if let (Some(first), Some(next), None) = (delimiter, next_emphasis, trailing) {
+                rest_fits = self.push_emphasis(&mut first.to_owned(), next, collect);
+                if !rest_fits
+                    && let Some(second) = fitting.next()
+                    && self.push_emphasis(&mut second.to_owned(), next, collect)
+                {
+                    delimiter = Some(second);
+                    rest_fits = true;
+                }
+            }
+            if delimiter.is_some() {
+                break;
+            }
+        }
+
+        let trimmed = content.trim_start_matches(is_space);
+        output.push_str(&content[..content.len() - trimmed.len()]);
+        let inner = trimmed.trim_end_matches(is_space);
+        // Keep the delimiter of the source, which did read as emphasis, when
+        // no delimiter is known to fit
+        let written = delimiter.unwrap_or_else(|| {
+            let source = self.extract_source(node).and_then(|s| s.chars().next());
+            let ch = source.filter(|&c| c == '*' || c == '_');
+            emphasis_delimiter(ch.unwrap_or(preferred), strong)
+        });
+        output.push_str(written);
+        output.push_str(inner);
+        output.push_str(written);
+        output.push_str(&trimmed[inner.len()..]);
+        delimiter.is_some() && rest_fits
+    }
+
     pub(super) fn collect_inline_content<'b>(
         &mut self,
         node: &'b AstNode<'b>,
@@ -127,44 +270,53 @@ impl<'a> Serializer<'a> {
         for child in node.children() {
             self.collect_inline_node(child, content);
         }
+        if self.options.wrap == WrapMode::Sentence
+            && !self.no_wrap
+            && matches!(node.data.borrow().value, NodeValue::Paragraph)
+        {
+            let korean = locale::is_korean(self.options.locale, content);
+            *content = wrap::break_sentences(content, korean);
+        }
     }
 
     pub(super) fn collect_inline_node<'b>(&mut self, node: &'b AstNode<'b>, content: &mut String) {
         match &node.data.borrow().value {
             NodeValue::Text(text) => {
-                // Apply punctuation transformation first
-                let transformed = punctuation::transform_punctuation(text, self.options);
-
-                // Try to preserve escapes from the original source
-                if let Some(source) = self.extract_source(node) {
-                    content.push_str(&Self::escape_text_preserving_source(&transformed, &source));
-                } else {
-                    content.push_str(&escape::escape_text(&transformed));
-                }
+                self.push_text(content, node, text);
             }
             NodeValue::SoftBreak => {
+                if node
+                    .previous_sibling()
+                    .is_some_and(|p| self.is_br_hard_break(p))
+                {
+                    // The `<br>` before it has become a hard line break
+                    return;
+                }
                 // Use a special marker to preserve original line breaks
                 // This will be processed by wrap_text to decide whether to keep them
-                content.push('\x00');
+                if matches!(self.options.wrap, WrapMode::None | WrapMode::Sentence) && !self.no_wrap
+                {
+                    if Self::soft_break_joins_with_space(content, node) {
+                        content.push(' ');
+                    }
+                } else {
+                    content.push('\x00');
+                }
             }
             NodeValue::LineBreak => {
                 content.push('\n');
             }
-            NodeValue::Emph => {
-                let delim = self.get_emphasis_delimiter(node);
-                content.push(delim);
-                for child in node.children() {
-                    self.collect_inline_node(child, content);
-                }
-                content.push(delim);
+            NodeValue::Emph | NodeValue::Strong => {
+                self.push_emphasis(content, node, Self::collect_inline_node);
             }
-            NodeValue::Strong => {
-                let delim = self.get_strong_delimiter(node);
-                content.push_str(delim);
+            NodeValue::Strikethrough => {
+                // Only parsed in the `commonmark` dialect, which has no
+                // strikethrough of its own
+                content.push_str("<del>");
                 for child in node.children() {
                     self.collect_inline_node(child, content);
                 }
-                content.push_str(delim);
+                content.push_str("</del>");
             }
             NodeValue::Code(code) => {
                 // Try to use original source to preserve spacing, but validate it first.
@@ -190,30 +342,40 @@ impl<'a> Serializer<'a> {
 
                 // Check if this is an autolink (link text equals URL)
                 let raw_text = self.collect_raw_text(node);
-                let is_autolink = link.title.is_empty() && raw_text == link.url;
+                let is_autolink =
+                    link.title.is_empty() && !link.url.is_empty() && raw_text == link.url;
+                let url = &*self.link_url(&link.url);
 
                 // Check if original was reference style
                 if let Some((text, label)) = self.get_reference_style_info(node) {
                     // Preserve reference style
                     if contains_image {
                         // Badge-style with reference: [![alt][img-ref]][link-ref]
-                        let actual_label = label.strip_prefix('\x01').unwrap_or(&label);
+                        let actual_label = link::split_reference_label(&label).1;
                         content.push('[');
                         for child in node.children() {
                             self.collect_inline_node(child, content);
                         }
                         content.push_str("][");
-                        content.push_str(actual_label);
+                        content.push_str(&state::reference_key(actual_label));
                         content.push(']');
 
                         self.add_reference(
                             actual_label.to_string(),
-                            link.url.clone(),
+                            url.to_string(),
                             link.title.clone(),
                         );
                     } else {
                         // Non-badge reference links: use helper
-                        self.format_reference_link(content, &text, &label, &link.url, &link.title);
+                        let follows_bracket = Self::next_sibling_starts_with_bracket(node);
+                        self.format_reference_link(
+                            content,
+                            &text,
+                            &label,
+                            url,
+                            &link.title,
+                            follows_bracket,
+                        );
                     }
                 } else if contains_image {
                     // Badge-style inline: [![alt](img-url)](link-url)
@@ -222,17 +384,11 @@ impl<'a> Serializer<'a> {
                     for child in node.children() {
                         self.collect_inline_node(child, content);
                     }
-                    content.push_str("](");
-                    content.push_str(&link.url);
-                    if !link.title.is_empty() {
-                        content.push_str(" \"");
-                        content.push_str(&link.title);
-                        content.push('"');
-                    }
-                    content.push(')');
+                    content.push(']');
+                    Self::push_destination(content, url, &link.title);
                 } else if is_autolink {
-                    Self::format_autolink(content, &link.url);
-                } else if Self::is_external_url(&link.url) {
+                    Self::format_autolink(content, url);
+                } else if self.links_by_reference(url) {
                     // External URL: collect link text first
                     let mut link_text = String::new();
                     for child in node.children() {
@@ -243,7 +399,7 @@ impl<'a> Serializer<'a> {
                     self.format_external_link_as_reference(
                         content,
                         &link_text,
-                        &link.url,
+                        url,
                         &link.title,
                         use_collapsed,
                     );
@@ -253,25 +409,52 @@ impl<'a> Serializer<'a> {
                     for child in node.children() {
                         self.collect_inline_node(child, &mut link_text);
                     }
-                    Self::format_inline_link(content, &link_text, &link.url, &link.title);
+                    Self::format_inline_link(content, &link_text, url, &link.title);
                 }
             }
             NodeValue::Image(image) => {
+                let url = &*self.link_url(&image.url);
                 // Check if original was reference style
                 if let Some((text, label)) = self.get_reference_style_info(node) {
-                    self.format_reference_image(content, &text, &label, &image.url, &image.title);
+                    let follows_bracket = Self::next_sibling_starts_with_bracket(node);
+                    self.format_reference_image(
+                        content,
+                        &text,
+                        &label,
+                        url,
+                        &image.title,
+                        follows_bracket,
+                    );
                 } else {
                     // Inline style: collect alt text and use inline syntax
                     let mut alt_text = String::new();
                     for child in node.children() {
                         self.collect_inline_node(child, &mut alt_text);
                     }
-                    Self::format_inline_image(content, &alt_text, &image.url, &image.title);
+                    Self::format_inline_image(content, &alt_text, url, &image.title);
                 }
             }
             NodeValue::HtmlInline(html) => {
-                // Preserve inline HTML as-is
-                content.push_str(html);
+                if self.is_br_hard_break(node) {
+                    content.truncate(content.trim_end_matches(' ').len());
+                    content.push('\n');
+                } else {
+                    // A line break within a tag or a comment is a soft one,
+                    // which a bare newline in the content would not be, and
+                    // the whitespace before it belongs to the tag
+                    let joined = matches!(self.options.wrap, WrapMode::None | WrapMode::Sentence)
+                        && !self.no_wrap;
+                    let html = self.inline_html(html);
+                    if joined {
+                        content.push_str(&html.replace('\n', " "));
+                    } else {
+                        let html = whitespace::keep_trailing_whitespace(&html);
+                        content.push_str(&html.replace('\n', "\x00"));
+                    }
+                }
+            }
+            NodeValue::Math(math) => {
+                content.push_str(&self.math_source(node, math));
             }
             NodeValue::FootnoteReference(footnote_ref) => {
                 content.push_str("[^");
@@ -286,17 +469,121 @@ impl<'a> Serializer<'a> {
         }
     }
 
+    /// Whether the soft break `node`, after `before`, becomes a space (see
+    /// [`wrap::joins_with_space`]).  Only a text node right after the break
+    /// can start with closing punctuation, and the line goes on past it
+    /// unless another break or the end of the paragraph follows.
+    fn soft_break_joins_with_space<'b>(before: &str, node: &'b AstNode<'b>) -> bool {
+        let next = node.next_sibling();
+        let value = next.map(|next| next.data.borrow());
+        let after = match value.as_deref() {
+            Some(Ast {
+                value: NodeValue::Text(text),
+                ..
+            }) => text.as_ref(),
+            _ => "",
+        };
+        let continued = next.and_then(|next| next.next_sibling()).is_some_and(|n| {
+            !matches!(
+                n.data.borrow().value,
+                NodeValue::SoftBreak | NodeValue::LineBreak
+            )
+        });
+        wrap::joins_with_space(before, after, continued)
+    }
+
+    /// Write the text of the text node `node` to `output`, escaped.
+    pub(super) fn push_text<'b>(&self, output: &mut String, node: &'b AstNode<'b>, text: &str) {
+        // MDX evaluates what is in braces as JavaScript, so text with an
+        // expression is kept as it is in the source
+        if self.options.mdx
+            && super::mdx::has_expression(text)
+            && let Some(source) = self.extract_source(node)
+        {
+            output.push_str(&source);
+            return;
+        }
+
+        // Template tags are kept as they are, and aren't broken across lines
+        if self.options.templates != TemplateSyntax::None
+            && let Some(tagged) = self.template_text(node, text)
+        {
+            output.push_str(&tagged);
+            return;
+        }
+
+        // Apply punctuation transformation first
+        let transformed = punctuation::transform_punctuation(text, self.options);
+
+        // Try to preserve escapes from the original source
+        let escaped = match self.extract_text_source(node) {
+            Some(source) => {
+                Self::escape_text_preserving_source(&transformed, &source, self.options.entities)
+            }
+            None => escape::escape_text(&transformed).into_owned(),
+        };
+        let escaped = if escaped.contains("\\_") {
+            escape::unescape_bare_urls(&escaped).into_owned()
+        } else {
+            escaped
+        };
+        let escaped = if self.options.escape_backslash == BackslashEscape::Lazy {
+            escape::unescape_lazy_backslashes(&escaped).into_owned()
+        } else {
+            escaped
+        };
+        let escaped = if self.options.math {
+            escape::escape_dollars(&escaped).into_owned()
+        } else {
+            escaped
+        };
+        match self.options.entities {
+            EntityMode::EncodeInvisible => output.push_str(&escape::encode_invisible(&escaped)),
+            EntityMode::Preserve | EntityMode::Decode => output.push_str(&escaped),
+        }
+    }
+
+    /// Escape the character at `idx` of `chars` as [`escape::escape_text`]
+    /// would escape it within the whole text, which depends on the rest of
+    /// it for an `&` that starts an entity reference, and for brackets, whose
+    /// indices that need escaping are `brackets` (see
+    /// [`escape::escaped_brackets`]).
+    fn escape_char_at(chars: &[char], brackets: &[usize], idx: usize) -> String {
+        let ch = chars[idx];
+        let starts_entity = || {
+            let rest: String = chars[idx..].iter().take(34).collect();
+            escape::entity_len(&rest).is_some()
+        };
+        if (ch.is_ascii() && escape::needs_escape(ch as u8))
+            || brackets.binary_search(&idx).is_ok()
+            || (ch == '&' && starts_entity())
+        {
+            format!("\\{}", ch)
+        } else {
+            ch.to_string()
+        }
+    }
+
     /// Escape text while preserving escapes from the original source.
     ///
     /// When comrak parses text like `node\_modules`, it stores `node_modules` in the AST.
     /// This function compares the parsed text with the original source to detect which
     /// characters were escaped, and preserves those escapes in the output.
     ///
-    /// Also preserves HTML entities (e.g., `&lt;`, `&amp;`, `&#60;`) from the source.
-    fn escape_text_preserving_source(text: &str, source: &str) -> String {
+    /// Also preserves HTML entities (e.g., `&lt;`, `&amp;`, `&#60;`) from the source,
+    /// or with `EntityMode::Decode` or `EntityMode::EncodeInvisible` only those
+    /// for ASCII punctuation and controls, which would need escaping otherwise.
+    fn escape_text_preserving_source(text: &str, source: &str, entities: EntityMode) -> String {
         let mut result = String::with_capacity(source.len());
         let text_chars: Vec<char> = text.chars().collect();
         let source_chars: Vec<char> = source.chars().collect();
+        // Non-ASCII characters never pair up as brackets; stand in for them
+        // with a byte that matches nothing, one for each character
+        let stand_ins: Vec<u8> = text_chars
+            .iter()
+            .map(|&c| if c.is_ascii() { c as u8 } else { 0x80 })
+            .collect();
+        let brackets = escape::escaped_brackets(&stand_ins);
 
         let mut text_idx = 0;
         let mut source_idx = 0;
@@ -317,7 +604,7 @@ impl<'a> Serializer<'a> {
                     } else {
                         // Source has \ followed by non-backslash, text has \
                         // It's a literal backslash that needs escaping
-                        result.push_str(&escape::escape_text(&text_char.to_string()));
+                        result.push_str(&Self::escape_char_at(&text_chars, &brackets, text_idx));
                         text_idx += 1;
                         source_idx += 1;
                     }
@@ -326,10 +613,14 @@ impl<'a> Serializer<'a> {
                     let escaped_char = source_chars[source_idx + 1];
                     if escaped_char == text_char {
                         // The escape in source corresponds to this character in text
-                        // Check if this character actually needs escaping in Markdown
-                        let escaped_form = escape::escape_text(&text_char.to_string());
-                        let needs_escaping =
-                            escaped_form.len() > 1 && escaped_form.starts_with('\\');
+                        // Check if this character actually needs escaping in Markdown.
+                        // Whether a bracket or other punctuation needs it depends on its
+                        // neighbours, which a lone character doesn't show, so an escape of
+                        // ASCII punctuation is always kept
+                        let text_str = text_char.to_string();
+                        let escaped_form = escape::escape_text(&text_str);
+                        let needs_escaping = text_char.is_ascii_punctuation()
+                            || (escaped_form.len() > 1 && escaped_form.starts_with('\\'));
 
                         if needs_escaping {
                             // Preserve the escape from source (e.g., \_ → \_)
@@ -345,29 +636,38 @@ impl<'a> Serializer<'a> {
                         source_idx += 2;
                     } else {
                         // Escape doesn't match - use normal escaping
-                        result.push_str(&escape::escape_text(&text_char.to_string()));
+                        result.push_str(&Self::escape_char_at(&text_chars, &brackets, text_idx));
                         text_idx += 1;
                         // Don't advance source_idx - the escape might be for something else
                     }
                 }
             } else if source_char == '&' {
                 // Check for HTML entity
-                if let Some((entity, decoded_char)) =
+                if let Some((entity, decoded)) =
                     Self::try_parse_html_entity(&source_chars, source_idx)
                 {
-                    if decoded_char == text_char {
-                        // The entity decodes to this character - preserve the entity
-                        result.push_str(&entity);
-                        text_idx += 1;
-                        source_idx += entity.len();
+                    let decoded: Vec<char> = decoded.chars().collect();
+                    if text_chars[text_idx..].starts_with(&decoded) {
+                        let keep = entities == EntityMode::Preserve
+                            || matches!(decoded[..], [c] if c.is_ascii() && !c.is_ascii_alphanumeric());
+                        if keep {
+                            // The entity decodes to these characters - preserve the entity
+                            result.push_str(&entity);
+                        } else {
+                            for idx in text_idx..text_idx + decoded.len() {
+                                result.push_str(&Self::escape_char_at(&text_chars, &brackets, idx));
+                            }
+                        }
+                        text_idx += decoded.len();
+                        source_idx += entity.chars().count();
                     } else {
                         // Entity doesn't match the text character - use normal escaping
-                        result.push_str(&escape::escape_text(&text_char.to_string()));
+                        result.push_str(&Self::escape_char_at(&text_chars, &brackets, text_idx));
                         text_idx += 1;
                     }
                 } else if source_char == text_char {
                     // Not an entity, just a regular '&'
-                    result.push_str(&escape::escape_text(&text_char.to_string()));
+                    result.push_str(&Self::escape_char_at(&text_chars, &brackets, text_idx));
                     text_idx += 1;
                     source_idx += 1;
                 } else {
@@ -376,7 +676,7 @@ impl<'a> Serializer<'a> {
                 }
             } else if source_char == text_char {
                 // Characters match - apply normal escaping rules
-                result.push_str(&escape::escape_text(&text_char.to_string()));
+                result.push_str(&Self::escape_char_at(&text_chars, &brackets, text_idx));
                 text_idx += 1;
                 source_idx += 1;
             } else {
@@ -387,24 +687,24 @@ impl<'a> Serializer<'a> {
         }
 
         // Handle any remaining text characters that weren't matched
-        for ch in text_chars.iter().skip(text_idx) {
-            result.push_str(&escape::escape_text(&ch.to_string()));
+        for idx in text_idx..text_chars.len() {
+            result.push_str(&Self::escape_char_at(&text_chars, &brackets, idx));
         }
 
         result
     }
 
     /// Try to parse an HTML entity starting at the given position.
-    /// Returns the entity string and the decoded character if successful.
-    fn try_parse_html_entity(chars: &[char], start: usize) -> Option<(String, char)> {
+    /// Returns the entity string and the characters it decodes to if successful.
+    fn try_parse_html_entity(chars: &[char], start: usize) -> Option<(String, String)> {
         if start >= chars.len() || chars[start] != '&' {
             return None;
         }
 
         // Find the end of the entity (semicolon)
         let mut end = start + 1;
-        while end < chars.len() && end - start < 12 {
-            // Max entity length ~10 chars
+        while end < chars.len() && end - start < 34 {
+            // The longest entity is &CounterClockwiseContourIntegral;
             if chars[end] == ';' {
                 let entity: String = chars[start..=end].iter().collect();
                 if let Some(decoded) = Self::decode_html_entity(&entity) {
@@ -421,42 +721,27 @@ impl<'a> Serializer<'a> {
         None
     }
 
-    /// Decode a single HTML entity to its character.
-    fn decode_html_entity(entity: &str) -> Option<char> {
+    /// Decode a single HTML entity to the characters it stands for.
+    fn decode_html_entity(entity: &str) -> Option<String> {
         // Handle numeric entities
         if entity.starts_with("&#") {
             let inner = entity.trim_start_matches("&#").trim_end_matches(';');
-            if let Some(hex) = inner.strip_prefix('x').or_else(|| inner.strip_prefix('X')) {
-                // Hexadecimal: &#xNN;
-                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
-            } else {
-                // Decimal: &#NN;
-                inner.parse::<u32>().ok().and_then(char::from_u32)
-            }
+            let code =
+                if let Some(hex) = inner.strip_prefix('x').or_else(|| inner.strip_prefix('X')) {
+                    // Hexadecimal: &#xNN;
+                    u32::from_str_radix(hex, 16).ok()
+                } else {
+                    // Decimal: &#NN;
+                    inner.parse::<u32>().ok()
+                };
+            code.and_then(char::from_u32).map(String::from)
         } else {
-            // Named entities - use html_escape's complete table
-            let name = entity
-                .trim_start_matches('&')
-                .trim_end_matches(';')
-                .as_bytes();
-            html_escape::NAMED_ENTITIES
-                .binary_search_by_key(&name, |(n, _)| n)
-                .ok()
-                .and_then(|idx| {
-                    let (_, value) = &html_escape::NAMED_ENTITIES[idx];
-                    // Most entities decode to a single character
-                    let mut chars = value.chars();
-                    let first = chars.next()?;
-                    // TODO: Some entities decode to multiple characters (e.g., &fj; -> "fj").
-                    // Currently we only handle single-character entities. To support
-                    // multi-character entities, the return type would need to change from
-                    // Option<char> to Option<&str> or similar, and callers would need updating.
-                    if chars.next().is_none() {
-                        Some(first)
-                    } else {
-                        None
-                    }
-                })
+            // Named entities - use the table of HTML5, as comrak does; some,
+            // like &ngE;, stand for more than one character
+            entities::ENTITIES
+                .iter()
+                .find(|e| e.entity == entity)
+                .map(|e| e.characters.to_owned())
         }
     }
 }
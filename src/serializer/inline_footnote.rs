@@ -0,0 +1,355 @@
+//! Pandoc-style inline footnotes (`^[like this]`).
+//!
+//! Neither CommonMark nor GFM has inline footnotes, so they are found in the
+//! source before it is parsed, outside code spans and fenced code blocks.
+//! With the `footnote.inline` option set to `expand`, each of them is
+//! replaced with a reference to a numbered footnote, whose definition is
+//! added at the end of the document and moved from there like any other.
+//! With `preserve`, the `^[` and `]` around them are swapped for
+//! [`CARET_MARKER`], [`OPEN_MARKER`], and [`CLOSE_MARKER`] until the document
+//! is written, so that they are neither escaped nor taken for the brackets of
+//! a link, while the text inside is formatted with the rest of the paragraph.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use super::Serializer;
+use super::container::code_fence;
+use crate::InlineFootnotes;
+
+/// What the `^` of an inline footnote is written as until the whole
+/// document is.  Like the markers of the other brackets, it is a private use
+/// character, which documents don't use, and is as wide as what it stands
+/// for.
+const CARET_MARKER: char = '\u{10FFF9}';
+
+/// What the `[` of an inline footnote is written as (see [`CARET_MARKER`]).
+const OPEN_MARKER: char = '\u{10FFFA}';
+
+/// What the `]` of an inline footnote is written as (see [`CARET_MARKER`]).
+const CLOSE_MARKER: char = '\u{10FFFB}';
+
+/// `input`, with the bytes of the lines of fenced code blocks replaced with
+/// spaces, so that the inline footnotes found in it are outside those, and
+/// at the same places as in `input`.
+pub(super) fn mask_code_blocks(input: &str) -> String {
+    let mut masked = String::with_capacity(input.len());
+    let mut fence: Option<(char, usize)> = None;
+    for line in input.split_inclusive('\n') {
+        let found = code_fence(line.trim_start_matches([' ', '\t', '>']));
+        let in_code = match (fence, found) {
+            (None, Some(opening)) => {
+                fence = Some(opening);
+                true
+            }
+            (Some((ch, length)), Some((found_ch, found_length)))
+                if found_ch == ch && found_length >= length =>
+            {
+                fence = None;
+                true
+            }
+            (open, _) => open.is_some(),
+        };
+        if in_code {
+            masked.extend(line.bytes().map(|b| if b == b'\n' { '\n' } else { ' ' }));
+        } else {
+            masked.push_str(line);
+        }
+    }
+    masked
+}
+
+/// The index just past the code span that starts with the backticks at
+/// `start`, or just past the backticks if no run as long closes them.
+fn skip_code_span(text: &str, start: usize) -> usize {
+    let run_length = |at: usize| text[at..].len() - text[at..].trim_start_matches('`').len();
+    let opening = run_length(start);
+    let mut i = start + opening;
+    while let Some(offset) = text[i..].find('`') {
+        let run = i + offset;
+        let length = run_length(run);
+        if length == opening {
+            return run + length;
+        }
+        i = run + length;
+    }
+    start + opening
+}
+
+/// The number of `>` that open `line` as block quote markers, and the rest
+/// of it after them.
+fn split_quote_markers(line: &str) -> (usize, &str) {
+    let mut depth = 0;
+    let mut rest = line.trim_start_matches([' ', '\t']);
+    while let Some(after) = rest.strip_prefix('>') {
+        depth += 1;
+        rest = after.trim_start_matches([' ', '\t']);
+    }
+    (depth, rest)
+}
+
+/// Whether `line`, with its indentation and block quote markers taken off,
+/// is blank or starts a block that can end a paragraph: a list item, an ATX
+/// heading, a code fence, a thematic break, or a setext heading underline.
+/// Ordered list items count whatever their number, so that a footnote is
+/// only found where it can't run into another block.
+fn starts_block(line: &str) -> bool {
+    let line = line.trim_end();
+    let after_run = |ch: char| &line[line.len() - line.trim_start_matches(ch).len()..];
+    let ends_marker = |rest: &str| rest.is_empty() || rest.starts_with([' ', '\t']);
+    let hashes = line.len() - after_run('#').len();
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rule = line
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '-' | '*' | '_' | '='));
+    line.is_empty()
+        || (line.starts_with(['-', '+', '*']) && ends_marker(&line[1..]))
+        || ((1..=6).contains(&hashes) && ends_marker(after_run('#')))
+        || ((1..=9).contains(&digits)
+            && line[digits..]
+                .strip_prefix(['.', ')'])
+                .is_some_and(ends_marker))
+        || code_fence(line).is_some()
+        || rule.is_some_and(|rule| line.chars().all(|c| c == rule || c == ' ' || c == '\t'))
+}
+
+/// Whether the line after the line break at `at` in `text` ends the
+/// paragraph that the line of `start` is in: it's blank, starts another
+/// block, or opens a block quote deeper than that line is in.
+fn ends_paragraph(text: &str, start: usize, at: usize) -> bool {
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let (depth, _) = split_quote_markers(&text[line_start..]);
+    match text[at + 1..].lines().next() {
+        Some(line) => {
+            let (next_depth, rest) = split_quote_markers(line);
+            next_depth > depth || starts_block(rest)
+        }
+        None => true,
+    }
+}
+
+/// The index of the `]` that closes the bracket opened just before `start`,
+/// past nested brackets, escaped characters, and code spans, or `None` if
+/// the paragraph ends first.
+fn closing_bracket(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'`' => {
+                i = skip_code_span(text, i);
+                continue;
+            }
+            b'[' => depth += 1,
+            b']' if depth == 0 => return Some(i),
+            b']' => depth -= 1,
+            b'\n' if ends_paragraph(text, start, i) => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The inline footnotes in `text` that aren't inside others, as the indices
+/// of their `^` and their `]`.
+fn footnote_spans(text: &str) -> Vec<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => i = skip_code_span(text, i),
+            b'^' if bytes.get(i + 1) == Some(&b'[') => match closing_bracket(text, i + 2) {
+                Some(close) if close > i + 2 => {
+                    spans.push((i, close));
+                    i = close + 1;
+                }
+                _ => i += 1,
+            },
+            _ => i += 1,
+        }
+    }
+    spans
+}
+
+/// `text` with the brackets of its inline footnotes, and of those inside
+/// them, swapped for markers.  `masked` is `text` with its code blocks
+/// masked (see [`mask_code_blocks`]).
+fn mark(text: &str, masked: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (open, close) in footnote_spans(masked) {
+        result.push_str(&text[copied..open]);
+        result.push(CARET_MARKER);
+        result.push(OPEN_MARKER);
+        result.push_str(&mark(&text[open + 2..close], &masked[open + 2..close]));
+        result.push(CLOSE_MARKER);
+        copied = close + 1;
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+/// The labels of the footnotes referenced or defined in `input`, like `1`
+/// for `[^1]`.
+fn footnote_labels(input: &str) -> HashSet<&str> {
+    input
+        .match_indices("[^")
+        .filter_map(|(start, _)| {
+            let rest = &input[start + 2..];
+            rest.find(']').map(|end| &rest[..end])
+        })
+        .collect()
+}
+
+/// The numbered footnotes that inline footnotes are expanded into.
+struct Expansion<'i> {
+    /// The labels taken by the footnotes of the document.
+    taken: HashSet<&'i str>,
+    /// The last number used for a label.
+    number: usize,
+    /// The labels and text of the definitions to add.
+    definitions: Vec<(String, String)>,
+}
+
+impl Expansion<'_> {
+    /// `text` with its inline footnotes replaced with references to new
+    /// numbered footnotes.  `masked` is `text` with its code blocks masked
+    /// (see [`mask_code_blocks`]).
+    fn expand(&mut self, text: &str, masked: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut copied = 0;
+        for (open, close) in footnote_spans(masked) {
+            result.push_str(&text[copied..open]);
+            let label = self.next_label();
+            result.push_str(&format!("[^{label}]"));
+            // The definitions of footnotes inside this one come after it
+            let index = self.definitions.len();
+            self.definitions.push((label, String::new()));
+            let content = self.expand(&text[open + 2..close], &masked[open + 2..close]);
+            let content: Vec<_> = content.lines().map(str::trim).collect();
+            self.definitions[index].1 = content.join(" ");
+            copied = close + 1;
+        }
+        result.push_str(&text[copied..]);
+        result
+    }
+
+    /// The smallest number not yet used as a label.
+    fn next_label(&mut self) -> String {
+        loop {
+            self.number += 1;
+            let label = self.number.to_string();
+            if !self.taken.contains(label.as_str()) {
+                return label;
+            }
+        }
+    }
+}
+
+/// `input` made ready to be parsed with the inline footnotes in it, if any,
+/// expanded or marked as `mode` says.
+pub(crate) fn prepare(input: &str, mode: InlineFootnotes) -> Cow<'_, str> {
+    if !input.contains("^[") {
+        return Cow::Borrowed(input);
+    }
+    let masked = mask_code_blocks(input);
+    if footnote_spans(&masked).is_empty() {
+        return Cow::Borrowed(input);
+    }
+    match mode {
+        InlineFootnotes::Preserve => Cow::Owned(mark(input, &masked)),
+        InlineFootnotes::Expand => {
+            let mut expansion = Expansion {
+                taken: footnote_labels(input),
+                number: 0,
+                definitions: Vec::new(),
+            };
+            let mut result = expansion.expand(input, &masked);
+            if !result.ends_with('\n') {
+                result.push('\n');
+            }
+            for (label, content) in expansion.definitions {
+                result.push_str(&format!("\n[^{label}]: {content}\n"));
+            }
+            Cow::Owned(result)
+        }
+    }
+}
+
+impl<'a> Serializer<'a> {
+    /// Put back the brackets of inline footnotes (see [`CARET_MARKER`]).
+    pub(super) fn restore_inline_footnotes(&mut self) {
+        if self.output.contains(CARET_MARKER) {
+            self.output = self
+                .output
+                .chars()
+                .map(|c| match c {
+                    CARET_MARKER => '^',
+                    OPEN_MARKER => '[',
+                    CLOSE_MARKER => ']',
+                    c => c,
+                })
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_footnote_spans() {
+        let text = "A^[note] b^[with [link](/) and `]`] c^[] d^[open";
+        assert_eq!(footnote_spans(text), [(1, 7), (10, 34)]);
+        assert_eq!(footnote_spans("`^[code]` and \\^[escaped]"), []);
+        assert_eq!(footnote_spans("A^[one\n\ntwo]"), []);
+        assert_eq!(footnote_spans("A^[one\ntwo]"), [(1, 10)]);
+        assert_eq!(footnote_spans("> A^[one\n> two]"), [(3, 14)]);
+        assert_eq!(footnote_spans("> A^[one\ntwo]"), [(3, 12)]);
+        for text in [
+            "A^[one\n+ two]",
+            "A^[one\n2) two]",
+            "A^[one\n## two]",
+            "A^[one\n===\ntwo]",
+            "A^[one\n> two]",
+            "> A^[one\n>\n> two]",
+            "- A^[one\n  - two]",
+        ] {
+            assert_eq!(footnote_spans(text), [], "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_mask_code_blocks() {
+        let input = "A^[a]\n\n~~~~\nB^[b]\n~~~~\n\n > ```\n > C^[c]\n > ```\n";
+        let masked = mask_code_blocks(input);
+        assert_eq!(masked.len(), input.len());
+        assert_eq!(footnote_spans(&masked), [(1, 4)]);
+    }
+
+    #[test]
+    fn test_prepare_expand() {
+        let input = "A^[one] and [^1] B^[two ^[three]\nlines].\n\n[^1]: Taken.\n";
+        assert_eq!(
+            prepare(input, InlineFootnotes::Expand),
+            "A[^2] and [^1] B[^3].\n\n[^1]: Taken.\n\n[^2]: one\n\n\
+             [^3]: two [^4] lines\n\n[^4]: three\n"
+        );
+    }
+
+    #[test]
+    fn test_prepare_unchanged() {
+        let input = "No footnotes, but `^[code]`.\n";
+        assert!(matches!(
+            prepare(input, InlineFootnotes::Preserve),
+            Cow::Borrowed(_)
+        ));
+    }
+}
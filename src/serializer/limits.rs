@@ -0,0 +1,136 @@
+//! Limits on what is formatted, so that pathological documents, like one
+//! with thousands of nested block quotes, are written back as they are
+//! instead of overflowing the stack or taking forever.
+//!
+//! The serializer walks the AST recursively, so a top-level block nested
+//! too deeply is replaced, right after parsing, with a raw node holding its
+//! source, which the rest of the formatter leaves alone.
+
+use comrak::arena_tree::NodeEdge;
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::Warning;
+
+/// The deepest a node may be nested in a top-level block for the block to
+/// be formatted.
+pub(crate) const MAX_NESTING_DEPTH: usize = 200;
+
+/// The deepest emphasis may be nested in other emphasis for its top-level
+/// block to be formatted.  The content of each emphasis is written once for
+/// each delimiter tried, so the time taken grows exponentially with it.
+pub(crate) const MAX_EMPHASIS_DEPTH: usize = 10;
+
+/// The most bytes the rows of a table may take once their cells are padded
+/// to line up.  A table that would take more, like one with a very wide
+/// cell in a column of many rows, is written with compact padding instead.
+pub(crate) const MAX_ALIGNED_TABLE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Replace each top-level block of `root` that is nested too deeply with
+/// a [`NodeValue::Raw`] node holding its lines of `source`, returning
+/// a warning for each.
+pub(crate) fn flatten_deep_blocks<'a>(root: &'a AstNode<'a>, source: &str) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut lines = None;
+    for block in root.children() {
+        let Some(reason) = excess_nesting(block) else {
+            continue;
+        };
+        let sourcepos = block.data.borrow().sourcepos;
+        let lines = lines.get_or_insert_with(|| source.lines().collect::<Vec<_>>());
+        let start = sourcepos.start.line.max(1) - 1;
+        let end = sourcepos.end.line.min(lines.len());
+        let text = lines[start.min(end)..end].join("\n");
+        while let Some(child) = block.first_child() {
+            child.detach();
+        }
+        block.data.borrow_mut().value = NodeValue::Raw(text);
+        warnings.push(Warning {
+            line: sourcepos.start.line,
+            message: format!("{}, so the block is left as it is", reason),
+            fixed: false,
+        });
+    }
+    warnings
+}
+
+/// What in `block` is nested too deeply to be formatted, if anything.
+fn excess_nesting<'a>(block: &'a AstNode<'a>) -> Option<String> {
+    let mut depth = 0;
+    let mut emphasis = 0;
+    for edge in block.traverse() {
+        match edge {
+            NodeEdge::Start(node) => {
+                depth += 1;
+                emphasis += usize::from(is_emphasis(node));
+                if depth > MAX_NESTING_DEPTH {
+                    return Some(format!(
+                        "nested more than {} levels deep",
+                        MAX_NESTING_DEPTH
+                    ));
+                }
+                if emphasis > MAX_EMPHASIS_DEPTH {
+                    return Some(format!(
+                        "emphasis nested more than {} levels deep",
+                        MAX_EMPHASIS_DEPTH
+                    ));
+                }
+            }
+            NodeEdge::End(node) => {
+                depth -= 1;
+                emphasis -= usize::from(is_emphasis(node));
+            }
+        }
+    }
+    None
+}
+
+fn is_emphasis<'a>(node: &'a AstNode<'a>) -> bool {
+    matches!(
+        node.data.borrow().value,
+        NodeValue::Emph | NodeValue::Strong
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrak::{Arena, parse_document};
+
+    fn flatten(input: &str) -> (Vec<String>, Vec<Warning>) {
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &crate::base_comrak_options());
+        let warnings = flatten_deep_blocks(root, input);
+        let raw = root
+            .children()
+            .filter_map(|block| match &block.data.borrow().value {
+                NodeValue::Raw(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect();
+        (raw, warnings)
+    }
+
+    #[test]
+    fn test_flatten_deep_blocks() {
+        let deep = format!("{}deep", "> ".repeat(MAX_NESTING_DEPTH));
+        let input = format!("> shallow\n\n{}\n\nAfter.\n", deep);
+        let (raw, warnings) = flatten(&input);
+        assert_eq!(raw, [deep]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 3);
+        assert_eq!(
+            warnings[0].message,
+            "nested more than 200 levels deep, so the block is left as it is"
+        );
+    }
+
+    #[test]
+    fn test_flatten_deep_emphasis() {
+        let strong = |depth: usize| format!("{}x{}", "**".repeat(depth), "**".repeat(depth));
+        let (shallow, deep) = (strong(MAX_EMPHASIS_DEPTH), strong(MAX_EMPHASIS_DEPTH + 1));
+        let (raw, warnings) = flatten(&format!("{}\n\n{}\n", shallow, deep));
+        assert_eq!(raw, [deep]);
+        assert_eq!(warnings[0].line, 3);
+        assert!(warnings[0].message.starts_with("emphasis nested"));
+    }
+}
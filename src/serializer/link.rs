@@ -1,11 +1,51 @@
 //! Link and image serialization logic.
 
+use std::borrow::Cow;
+
 use comrak::nodes::{AstNode, NodeValue};
 
-use super::Serializer;
+use super::state::reference_key;
+use super::{Serializer, url};
+use crate::{EmptyLinkText, ReferenceSyntax};
+
+/// Split a label returned by `get_reference_style_info` into the syntax the
+/// reference was written in and the label itself.
+///
+/// Labels that differ from the link text carry no marker; they can only be
+/// written in full, which the callers check for themselves.
+pub(super) fn split_reference_label(label: &str) -> (ReferenceSyntax, &str) {
+    if let Some(label) = label.strip_prefix('\x01') {
+        (ReferenceSyntax::Collapsed, label)
+    } else if let Some(label) = label.strip_prefix('\x02') {
+        (ReferenceSyntax::Full, label)
+    } else {
+        (ReferenceSyntax::Shortcut, label)
+    }
+}
 
 impl<'a> Serializer<'a> {
+    /// The URL of a link or image as it is written, with its percent-encoding
+    /// normalized as `link_url_encoding` says.
+    pub(super) fn link_url<'u>(&self, url: &'u str) -> Cow<'u, str> {
+        url::normalize_url(url, self.options.link_url_encoding)
+    }
+
+    /// Write the destination of an inline link or image, `(url "title")`.
+    pub(super) fn push_destination(output: &mut String, url: &str, title: &str) {
+        output.push('(');
+        output.push_str(&url::destination(url));
+        if !title.is_empty() {
+            output.push_str(" \"");
+            output.push_str(&url::title(title));
+            output.push('"');
+        }
+        output.push(')');
+    }
+
     /// Format a reference-style link and write to output buffer.
+    ///
+    /// `follows_bracket` tells whether the link is immediately followed by
+    /// something that starts with `[` or `(`.
     pub(super) fn format_reference_link(
         &mut self,
         output: &mut String,
@@ -13,46 +53,61 @@ impl<'a> Serializer<'a> {
         label: &str,
         url: &str,
         title: &str,
+        follows_bracket: bool,
     ) {
-        if label.starts_with('\x01') {
-            // Collapsed reference: [text][]
-            let actual_label = label.strip_prefix('\x01').unwrap();
-            output.push('[');
-            output.push_str(text);
-            output.push_str("][]");
-
-            self.add_reference(actual_label.to_string(), url.to_string(), title.to_string());
-        } else if text == label {
-            // Shortcut reference: [text]
-            output.push('[');
-            output.push_str(text);
-            output.push(']');
-
-            self.add_reference(label.to_string(), url.to_string(), title.to_string());
+        let label = self.write_reference_brackets(output, text, label, follows_bracket);
+        self.add_reference(label.to_string(), url.to_string(), title.to_string());
+    }
+
+    /// Write `[text]`, `[text][]`, or `[text][label]` for a reference link or
+    /// image, and return the label to define.
+    ///
+    /// The `label` comes from `get_reference_style_info`.  When it matches
+    /// the text, ignoring case as labels do, the syntax is chosen by
+    /// `link_reference_syntax`; otherwise the full syntax is the only
+    /// choice, with the label written as [`reference_key`] normalizes it.
+    /// A shortcut immediately followed by `[` or `(` would read as a longer
+    /// link, so the collapsed syntax is used instead.
+    fn write_reference_brackets<'l>(
+        &self,
+        output: &mut String,
+        text: &str,
+        label: &'l str,
+        follows_bracket: bool,
+    ) -> &'l str {
+        let (written, label) = split_reference_label(label);
+        let syntax = if reference_key(text) != reference_key(label) {
+            ReferenceSyntax::Full
         } else {
-            // Full reference: [text][label]
-            output.push('[');
-            output.push_str(text);
-            output.push_str("][");
-            output.push_str(label);
-            output.push(']');
-
-            self.add_reference(label.to_string(), url.to_string(), title.to_string());
+            match self.options.link_reference_syntax {
+                ReferenceSyntax::Preserve => written,
+                syntax => syntax,
+            }
+        };
+        output.push('[');
+        output.push_str(text);
+        output.push(']');
+        match syntax {
+            ReferenceSyntax::Shortcut | ReferenceSyntax::Preserve if follows_bracket => {
+                output.push_str("[]");
+            }
+            ReferenceSyntax::Shortcut | ReferenceSyntax::Preserve => {}
+            ReferenceSyntax::Collapsed => output.push_str("[]"),
+            ReferenceSyntax::Full => {
+                output.push('[');
+                output.push_str(&reference_key(label));
+                output.push(']');
+            }
         }
+        label
     }
 
     /// Format an inline-style link and write to output buffer.
     pub(super) fn format_inline_link(output: &mut String, text: &str, url: &str, title: &str) {
         output.push('[');
         output.push_str(text);
-        output.push_str("](");
-        output.push_str(url);
-        if !title.is_empty() {
-            output.push_str(" \"");
-            output.push_str(title);
-            output.push('"');
-        }
-        output.push(')');
+        output.push(']');
+        Self::push_destination(output, url, title);
     }
 
     /// Format an autolink and write to output buffer.
@@ -67,6 +122,13 @@ impl<'a> Serializer<'a> {
     /// If `use_collapsed` is true, outputs `[text][]` (collapsed reference) instead of
     /// `[text]` (shortcut reference). This is needed to disambiguate when the link is
     /// immediately followed by something that starts with `[`, like a footnote reference.
+    ///
+    /// If another link with the same text already claimed the label for a
+    /// different URL or title, the link stays inline so that neither link
+    /// changes its destination.  So does a link whose text has a hard line
+    /// break, which can't be in the label of a definition.  A link whose
+    /// text is empty or only whitespace can't have a label at all, and is
+    /// written as [`Self::format_empty_link`] says.
     pub(super) fn format_external_link_as_reference(
         &mut self,
         output: &mut String,
@@ -77,31 +139,58 @@ impl<'a> Serializer<'a> {
     ) {
         // Normalize: replace SoftBreak markers with spaces for shortcut refs
         let normalized_text = text.replace('\x00', " ");
-        output.push('[');
-        output.push_str(&normalized_text);
-        output.push(']');
-        if use_collapsed {
-            output.push_str("[]");
+        if normalized_text.trim().is_empty() {
+            self.format_empty_link(output, &normalized_text, url, title);
+            return;
+        }
+        if normalized_text.contains('\n') || self.reference_conflicts(&normalized_text, url, title)
+        {
+            Self::format_inline_link(output, &normalized_text, url, title);
+            return;
         }
+        let label = self.write_reference_brackets(
+            output,
+            &normalized_text,
+            &normalized_text,
+            use_collapsed,
+        );
+        self.add_reference(label.to_string(), url.to_string(), title.to_string());
+    }
 
-        self.add_reference(normalized_text, url.to_string(), title.to_string());
+    /// Format a link to an external URL whose text is empty or only
+    /// whitespace as an autolink, if it is empty, has no title, and
+    /// `link_empty_text` says so, or as an inline link otherwise.
+    fn format_empty_link(&self, output: &mut String, text: &str, url: &str, title: &str) {
+        if text.is_empty()
+            && title.is_empty()
+            && self.options.link_empty_text == EmptyLinkText::Autolink
+            && url::is_autolink_url(url)
+        {
+            Self::format_autolink(output, url);
+        } else {
+            Self::format_inline_link(output, text, url, title);
+        }
     }
 
-    /// Check if the next sibling of a node starts with `[`.
-    /// This includes footnote references, link references, and images.
+    /// Check if the next sibling of a node starts with `[` or `(`.
+    /// This includes footnote references, link references, images, and text
+    /// starting with either bracket.
     pub(super) fn next_sibling_starts_with_bracket<'b>(node: &'b AstNode<'b>) -> bool {
         if let Some(next) = node.next_sibling() {
-            let value = &next.data.borrow().value;
-            matches!(
-                value,
-                NodeValue::FootnoteReference(_) | NodeValue::Link(_) | NodeValue::Image(_)
-            )
+            match &next.data.borrow().value {
+                NodeValue::FootnoteReference(_) | NodeValue::Link(_) | NodeValue::Image(_) => true,
+                NodeValue::Text(text) => text.starts_with(['[', '(']),
+                _ => false,
+            }
         } else {
             false
         }
     }
 
     /// Format a reference-style image and write to output buffer.
+    ///
+    /// `follows_bracket` has the same meaning as for
+    /// [`format_reference_link`](Self::format_reference_link).
     pub(super) fn format_reference_image(
         &mut self,
         output: &mut String,
@@ -109,46 +198,19 @@ impl<'a> Serializer<'a> {
         label: &str,
         url: &str,
         title: &str,
+        follows_bracket: bool,
     ) {
-        if label.starts_with('\x01') {
-            // Collapsed reference: ![alt][]
-            let actual_label = label.strip_prefix('\x01').unwrap();
-            output.push_str("![");
-            output.push_str(text);
-            output.push_str("][]");
-
-            self.add_reference(actual_label.to_string(), url.to_string(), title.to_string());
-        } else if text == label {
-            // Shortcut reference: ![alt]
-            output.push_str("![");
-            output.push_str(text);
-            output.push(']');
-
-            self.add_reference(label.to_string(), url.to_string(), title.to_string());
-        } else {
-            // Full reference: ![alt][label]
-            output.push_str("![");
-            output.push_str(text);
-            output.push_str("][");
-            output.push_str(label);
-            output.push(']');
-
-            self.add_reference(label.to_string(), url.to_string(), title.to_string());
-        }
+        output.push('!');
+        let label = self.write_reference_brackets(output, text, label, follows_bracket);
+        self.add_reference(label.to_string(), url.to_string(), title.to_string());
     }
 
     /// Format an inline-style image and write to output buffer.
     pub(super) fn format_inline_image(output: &mut String, alt_text: &str, url: &str, title: &str) {
         output.push_str("![");
         output.push_str(alt_text);
-        output.push_str("](");
-        output.push_str(url);
-        if !title.is_empty() {
-            output.push_str(" \"");
-            output.push_str(title);
-            output.push('"');
-        }
-        output.push(')');
+        output.push(']');
+        Self::push_destination(output, url, title);
     }
 
     pub(super) fn serialize_link<'b>(&mut self, node: &'b AstNode<'b>, url: &str, title: &str) {
@@ -159,7 +221,8 @@ impl<'a> Serializer<'a> {
 
         // Check if this is an autolink (link text equals URL)
         let raw_text = self.collect_raw_text(node);
-        let is_autolink = title.is_empty() && raw_text == url;
+        let is_autolink = title.is_empty() && !url.is_empty() && raw_text == url;
+        let url = &*self.link_url(url);
 
         // Check if original was reference style
         if let Some((text, label)) = self.get_reference_style_info(node) {
@@ -171,14 +234,15 @@ impl<'a> Serializer<'a> {
                     self.serialize_node(child);
                 }
                 self.output.push_str("][");
-                let actual_label = label.strip_prefix('\x01').unwrap_or(&label);
-                self.output.push_str(actual_label);
+                let actual_label = split_reference_label(&label).1;
+                self.output.push_str(&reference_key(actual_label));
                 self.output.push(']');
                 self.add_reference(actual_label.to_string(), url.to_string(), title.to_string());
             } else {
                 // Use helper for non-badge reference links
                 let mut output = String::new();
-                self.format_reference_link(&mut output, &text, &label, url, title);
+                let follows_bracket = Self::next_sibling_starts_with_bracket(node);
+                self.format_reference_link(&mut output, &text, &label, url, title, follows_bracket);
                 self.output.push_str(&output);
             }
         } else if contains_image {
@@ -187,17 +251,11 @@ impl<'a> Serializer<'a> {
             for child in node.children() {
                 self.serialize_node(child);
             }
-            self.output.push_str("](");
-            self.output.push_str(url);
-            if !title.is_empty() {
-                self.output.push_str(" \"");
-                self.output.push_str(title);
-                self.output.push('"');
-            }
-            self.output.push(')');
+            self.output.push(']');
+            Self::push_destination(&mut self.output, url, title);
         } else if is_autolink {
             Self::format_autolink(&mut self.output, url);
-        } else if Self::is_external_url(url) {
+        } else if self.links_by_reference(url) {
             let link_text = self.collect_text(node);
             let mut output = String::new();
             let use_collapsed = Self::next_sibling_starts_with_bracket(node);
@@ -217,6 +275,7 @@ impl<'a> Serializer<'a> {
     }
 
     pub(super) fn serialize_image<'b>(&mut self, node: &'b AstNode<'b>, url: &str, title: &str) {
+        let url = &*self.link_url(url);
         // Collect the alt text
         let alt_text = self.collect_text(node);
 
@@ -224,7 +283,8 @@ impl<'a> Serializer<'a> {
         if let Some((text, label)) = self.get_reference_style_info(node) {
             // Use a temporary buffer to avoid double borrow
             let mut output = String::new();
-            self.format_reference_image(&mut output, &text, &label, url, title);
+            let follows_bracket = Self::next_sibling_starts_with_bracket(node);
+            self.format_reference_image(&mut output, &text, &label, url, title, follows_bracket);
             self.output.push_str(&output);
             return;
         }
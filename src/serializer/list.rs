@@ -3,6 +3,27 @@
 use comrak::nodes::{AstNode, ListType, NodeValue};
 
 use super::Serializer;
+use crate::{Dialect, ListContinuation, ListSeparation, ListSpacing, OrderedNumbering};
+
+/// Return the type of `node` if it is a list.
+fn list_type_of<'b>(node: &'b AstNode<'b>) -> Option<ListType> {
+    match &node.data.borrow().value {
+        NodeValue::List(list) => Some(list.list_type),
+        _ => None,
+    }
+}
+
+/// Count the lists of the same type as `node` that immediately precede it.
+///
+/// Such lists would be read back as one list with `node` if they were
+/// written one after another with the same marker.
+fn adjacent_lists_before<'b>(node: &'b AstNode<'b>, list_type: ListType) -> usize {
+    std::iter::successors(node.previous_sibling(), |sibling| {
+        sibling.previous_sibling()
+    })
+    .take_while(|sibling| list_type_of(sibling) == Some(list_type))
+    .count()
+}
 
 impl<'a> Serializer<'a> {
     /// Count the number of items in a list node.
@@ -12,23 +33,114 @@ impl<'a> Serializer<'a> {
             .count()
     }
 
-    /// Calculate the width of a list item marker.
+    /// Whether `node`, a list, immediately follows another list of the same
+    /// type, and would merge with it unless kept apart.
+    fn follows_same_type_list<'b>(node: &'b AstNode<'b>) -> bool {
+        list_type_of(node).is_some_and(|list_type| adjacent_lists_before(node, list_type) > 0)
+    }
+
+    /// Whether there is a blank line before the block at `index` among the
+    /// `children` of a list item, which isn't the first.
+    ///
+    /// A nested list has one only if it has one in the source, as it is
+    /// often written right below the text of the item, and only if the
+    /// list spacing isn't tight, as the blank line would make the enclosing
+    /// list loose; a separator comment needs one all the same.  For the same
+    /// reason, a tight list has none after a heading, or before a block quote
+    /// or a code fence that can't be read as part of the block before it.
+    /// Every other block has one, which also keeps a thematic break after a
+    /// paragraph from making it a setext heading.
+    fn blank_line_before_item_block<'b>(&self, children: &[&'b AstNode<'b>], index: usize) -> bool {
+        let child = children[index];
+        if !matches!(child.data.borrow().value, NodeValue::List(_)) {
+            if !self.list_tight {
+                return true;
+            }
+            let previous = &children[index - 1].data.borrow().value;
+            return match child.data.borrow().value {
+                _ if matches!(previous, NodeValue::Heading(_)) => false,
+                NodeValue::BlockQuote => {
+                    !matches!(previous, NodeValue::Paragraph | NodeValue::CodeBlock(_))
+                }
+                NodeValue::CodeBlock(_) => !matches!(
+                    previous,
+                    NodeValue::Paragraph | NodeValue::CodeBlock(_) | NodeValue::BlockQuote
+                ),
+                _ => true,
+            };
+        }
+        let previous_end_line = children[index - 1].data.borrow().sourcepos.end.line;
+        let has_blank_line_before =
+            child.data.borrow().sourcepos.start.line > previous_end_line + 1;
+        let tight = matches!(self.options.list_spacing, ListSpacing::Tight);
+        let separated = matches!(self.options.list_separation, ListSeparation::Comment)
+            && Self::follows_same_type_list(child);
+        (has_blank_line_before && !tight) || separated
+    }
+
+    /// The marker character for items of the current bullet list.
+    pub(super) fn bullet_marker(&self) -> char {
+        let marker = self.options.unordered_marker.as_char();
+        match (self.list_alternate_marker, marker) {
+            (false, _) => marker,
+            (true, '*') => '-',
+            (true, _) => '*',
+        }
+    }
+
+    /// The number of spaces after a bullet marker: `trailing_spaces`, but at
+    /// least one, as a marker right before the text isn't one, and would run
+    /// into text starting with emphasis, like `***term**`.
+    pub(super) fn bullet_trailing_spaces(&self) -> usize {
+        self.options.trailing_spaces.get().max(1)
+    }
+
+    /// The delimiter for items of the current ordered list.
+    fn ordered_delimiter(&self) -> char {
+        let marker = if self.list_depth % 2 == 1 {
+            self.options.odd_level_marker
+        } else {
+            self.options.even_level_marker
+        };
+        match (self.list_alternate_marker, marker.as_char()) {
+            (false, delimiter) => delimiter,
+            (true, '.') => ')',
+            (true, _) => '.',
+        }
+    }
+
+    /// Calculate the width of the current list item's marker.
     /// This is used to determine the indentation for continuation lines.
     fn calculate_marker_width(&self) -> usize {
         match self.list_type {
             Some(ListType::Bullet) => {
                 // " -  " = leading_spaces + 1 (marker) + trailing_spaces
-                self.options.leading_spaces.get() + 1 + self.options.trailing_spaces.get()
-            }
-            Some(ListType::Ordered) => {
-                // Fixed width based on ordered_list_indent_width (default 4)
-                // e.g., "1.  " (4), "10. " (4), "100." (4, min 1 trailing space)
-                self.options.ordered_list_indent_width.get()
+                self.options.leading_spaces.get() + 1 + self.bullet_trailing_spaces()
             }
+            Some(ListType::Ordered) => self.ordered_marker_width(self.ordered_item_number()),
             None => 0,
         }
     }
 
+    /// The number of the current item of an ordered list, as
+    /// `ordered_list_numbering` says.
+    fn ordered_item_number(&self) -> usize {
+        match self.options.ordered_list_numbering {
+            OrderedNumbering::Sequential => self.list_item_index,
+            OrderedNumbering::Ones => 1,
+        }
+    }
+
+    /// Calculate the width of an ordered list item marker for item `number`.
+    ///
+    /// Markers have a fixed width of `ordered_list_indent_width` (default 4),
+    /// e.g., "1.  " and "10. ", unless the number is too wide to fit with a
+    /// trailing space, e.g., "100. " (5).
+    fn ordered_marker_width(&self, number: usize) -> usize {
+        let digits = number.to_string().len();
+        self.options.ordered_list_indent_width.get().max(digits + 2)
+    }
+
     pub(super) fn serialize_list<'b>(
         &mut self,
         node: &'b AstNode<'b>,
@@ -39,9 +151,31 @@ impl<'a> Serializer<'a> {
         let old_list_tight = self.list_tight;
         let old_index = self.list_item_index;
         let old_max_items = self.ordered_list_max_items;
+        let old_alternate_marker = self.list_alternate_marker;
+
+        // A list right after another list of the same type is kept apart from
+        // it by a comment, or by using the other marker for every other list
+        let adjacent = adjacent_lists_before(node, list_type);
+        self.list_alternate_marker = false;
+        if adjacent > 0 {
+            match self.options.list_separation {
+                ListSeparation::Comment => {
+                    self.begin_line();
+                    self.output.push_str("<!-- -->\n");
+                    self.write_line("");
+                }
+                ListSeparation::AlternateMarker => {
+                    self.list_alternate_marker = adjacent % 2 == 1;
+                }
+            }
+        }
 
         self.list_type = Some(list_type);
-        self.list_tight = tight;
+        self.list_tight = match self.options.list_spacing {
+            ListSpacing::Preserve => tight,
+            ListSpacing::Tight => true,
+            ListSpacing::Loose => false,
+        };
         self.list_item_index = 0;
         self.list_depth += 1;
 
@@ -57,6 +191,7 @@ impl<'a> Serializer<'a> {
         self.list_tight = old_list_tight;
         self.list_item_index = old_index;
         self.ordered_list_max_items = old_max_items;
+        self.list_alternate_marker = old_alternate_marker;
     }
 
     /// Serialize a list item, optionally with a task list checkbox.
@@ -75,13 +210,12 @@ impl<'a> Serializer<'a> {
 
         // For loose lists, add a blank line before items (except the first)
         if !self.list_tight && self.list_item_index > 1 {
-            self.output.push('\n');
+            self.write_line("");
         }
 
-        // Add block quote prefix if we're inside a block quote
-        if self.in_block_quote {
-            self.output.push_str("> ");
-        }
+        // The prefix of the enclosing block quote or footnote definition;
+        // the marker itself is indented by list depth below
+        let mut line_start = self.container_prefix();
 
         // Check if this is the first item of a list that starts on the same line as `:` in
         // definition details. In that case, skip base indentation for the first item only.
@@ -102,27 +236,51 @@ impl<'a> Serializer<'a> {
         // Level 1: " -  " (leading space + hyphen + trailing spaces)
         // Level 2+: indent_width spaces per nesting level, then " -  " prefix
         // Use different indent_width for ordered vs unordered lists
+        // Nested lists are also indented by however much wider than usual the
+        // markers of the enclosing items are (e.g., "100. "), so that they
+        // stay inside those items
         let indent_width = match self.list_type {
             Some(ListType::Ordered) => self.options.ordered_list_indent_width.get(),
             _ => self.options.indent_width.get(),
         };
-        if self.list_depth > 1 {
-            let indent = format!(
-                "{}{}",
-                desc_base_indent,
-                " ".repeat(indent_width * (self.list_depth - 1))
-            );
-            self.output.push_str(&indent);
+        let nested_indent = if self.list_depth > 1 {
+            indent_width * (self.list_depth - 1) + self.list_marker_overflow
         } else {
-            self.output.push_str(desc_base_indent);
-        }
+            0
+        };
+        line_start.push_str(desc_base_indent);
+        line_start.push_str(&" ".repeat(nested_indent));
+        // As the first block of the enclosing item, the list starts on the
+        // line of that item's marker, right at its content column, where the
+        // marker is written without its leading spaces; the columns it is
+        // short of its usual place are added after it instead, so that the
+        // content of the item is in the column its continuation lines are
+        let on_marker_line = self.list_marker_end == Some(self.output.len());
+        let line_begin = self.output.rfind('\n').map_or(0, |i| i + 1);
+        self.push_line_start(&line_start);
+        let shift = if on_marker_line {
+            let column = self.output.len() - line_begin;
+            line_start.len().saturating_sub(column)
+        } else {
+            0
+        };
 
         match self.list_type {
             Some(ListType::Bullet) => {
-                let marker = self.options.unordered_marker.as_char();
-                let leading = " ".repeat(self.options.leading_spaces.get());
-                let trailing = " ".repeat(self.options.trailing_spaces.get());
-                if self.in_description_details && self.list_depth == 1 {
+                let marker = self.bullet_marker();
+                let leading_spaces = self.options.leading_spaces.get();
+                let leading = " ".repeat(leading_spaces);
+                let trailing = " ".repeat(self.bullet_trailing_spaces());
+                if on_marker_line {
+                    self.output.push(marker);
+                    self.output.push_str(&trailing);
+                    let leading = if self.in_description_details && self.list_depth == 1 {
+                        0
+                    } else {
+                        leading_spaces
+                    };
+                    self.output.push_str(&" ".repeat(shift + leading));
+                } else if self.in_description_details && self.list_depth == 1 {
                     // Inside description details at top level: "-  " (no leading space)
                     self.output.push(marker);
                     self.output.push_str(&trailing);
@@ -135,30 +293,29 @@ impl<'a> Serializer<'a> {
             }
             Some(ListType::Ordered) => {
                 // Determine marker based on nesting level (odd=1,3,5..., even=2,4,6...)
-                let marker = if self.list_depth % 2 == 1 {
-                    self.options.odd_level_marker.as_char()
-                } else {
-                    self.options.even_level_marker.as_char()
-                };
+                let marker = self.ordered_delimiter();
 
-                let current_num = self.list_item_index.to_string();
-                let current_num_width = current_num.len();
+                let number = self.ordered_item_number();
+                let current_num = number.to_string();
 
-                // Calculate trailing spaces to maintain fixed marker width
-                // marker_width = number + marker_char + trailing
-                // trailing = marker_width - number - 1 (minimum 1)
-                let marker_width = self.options.ordered_list_indent_width.get();
-                let trailing_count = marker_width.saturating_sub(current_num_width + 1).max(1);
+                // Pad with trailing spaces to the marker width
+                // marker_width = number + marker_char + trailing (minimum 1)
+                let marker_width = self.ordered_marker_width(number);
+                let trailing_count = marker_width - current_num.len() - 1;
 
                 self.output.push_str(&current_num);
                 self.output.push(marker);
-                self.output.push_str(&" ".repeat(trailing_count));
+                self.output.push_str(&" ".repeat(trailing_count + shift));
             }
             None => {}
         }
 
-        // Add task list checkbox if this is a task item
+        // Add task list checkbox if this is a task item; CommonMark has no
+        // task lists, so there the bracket is escaped to keep it literal text
         if let Some(checked) = task_marker {
+            if self.options.dialect == Dialect::CommonMark {
+                self.output.push('\\');
+            }
             if checked.is_some() {
                 self.output.push_str("[x] ");
             } else {
@@ -177,134 +334,71 @@ impl<'a> Serializer<'a> {
             match self.list_type {
                 Some(ListType::Bullet) => {
                     // "-  " = 1 (marker) + trailing_spaces (no leading space)
-                    1 + self.options.trailing_spaces.get()
-                }
-                Some(ListType::Ordered) => {
-                    // For ordered lists in description details, still use full width
-                    self.options.ordered_list_indent_width.get()
+                    1 + self.bullet_trailing_spaces()
                 }
+                // For ordered lists in description details, still use full width
+                Some(ListType::Ordered) => marker_width,
                 None => 0,
             }
         } else {
             marker_width
         };
-        let base_indent = if self.in_description_details {
-            // Inside description details, add extra 5-space indent
-            format!(
-                "{}{}",
-                " ".repeat(5 + indent_width * (self.list_depth - 1)),
-                " ".repeat(marker_width_for_indent)
-            )
-        } else if self.list_depth > 1 {
-            // Nested list: outer indent + marker width
-            format!(
-                "{}{}",
-                " ".repeat(indent_width * (self.list_depth - 1)),
-                " ".repeat(marker_width)
-            )
+        // The content column: nesting indent + marker width, plus an extra
+        // 5-space indent inside description details
+        let content_column = if self.in_description_details {
+            5 + nested_indent + marker_width_for_indent
         } else {
-            // Top-level list: just marker width
-            " ".repeat(marker_width)
+            nested_indent + marker_width
         };
+        // With a fixed continuation, wrapped lines of the item's paragraphs
+        // hang from the marker, where CommonMark reads them as lazy
+        // continuation lines.  Other blocks can't start left of the content
+        // column, nor more than three columns right of it, where they would
+        // be read as indented code, so they move only within that range.
+        let hang = match self.options.list_continuation {
+            ListContinuation::Fixed if !self.in_description_details => {
+                Some(nested_indent + self.options.list_fixed_width)
+            }
+            _ => None,
+        };
+        let block_column = hang.map_or(content_column, |hang| {
+            hang.clamp(content_column, content_column + 3)
+        });
+        let base_indent = " ".repeat(block_column);
+        let old_list_hang = std::mem::replace(&mut self.list_hang, hang);
 
         // Store the base indent for use by nested block elements (blockquotes, alerts, etc.)
+        let continuation = self.list_continuation(base_indent.len());
+        self.line_prefix.push(continuation);
+        self.list_marker_end = Some(self.output.len());
         let old_list_item_indent =
             std::mem::replace(&mut self.list_item_indent, base_indent.clone());
+        let old_list_marker_overflow = self.list_marker_overflow;
+        if matches!(self.list_type, Some(ListType::Ordered)) {
+            self.list_marker_overflow +=
+                marker_width - self.options.ordered_list_indent_width.get();
+        }
 
+        // The first block goes on the line of the marker, as a blank line
+        // right after it would end the item
+        let mut written = false;
         for (i, child) in children.iter().enumerate() {
-            let is_first = i == 0;
-            match &child.data.borrow().value {
-                NodeValue::List(_) => {
-                    // Check if there's a blank line before this nested list in the original
-                    let has_blank_line_before = if i > 0 {
-                        let prev_child = children[i - 1];
-                        let prev_end_line = prev_child.data.borrow().sourcepos.end.line;
-                        let curr_start_line = child.data.borrow().sourcepos.start.line;
-                        // More than one line difference means there's a blank line
-                        curr_start_line > prev_end_line + 1
-                    } else {
-                        false
-                    };
-
-                    if has_blank_line_before {
-                        // Blank line to separate from preceding paragraph
-                        self.output.push_str("\n\n");
-                    } else {
-                        self.output.push('\n');
-                    }
-                    self.serialize_node(child);
-                }
-                NodeValue::Paragraph => {
-                    // For paragraphs after the first, add blank line with proper indentation
-                    if !is_first {
-                        // Check if previous child ends with a newline (code blocks, nested lists)
-                        let prev_ends_with_newline = i > 0
-                            && matches!(
-                                &children[i - 1].data.borrow().value,
-                                NodeValue::CodeBlock(_) | NodeValue::List(_)
-                            );
-                        if prev_ends_with_newline {
-                            // Previous element already ends with \n, so just add one more \n
-                            self.output.push('\n');
-                        } else {
-                            // First \n ends the previous paragraph, second \n creates blank line
-                            self.output.push_str("\n\n");
-                        }
-                        if self.in_block_quote {
-                            self.output.push_str("> ");
-                        }
-                        self.output.push_str(&base_indent);
-                    }
-                    self.serialize_node(child);
-                }
-                NodeValue::CodeBlock(code_block) => {
-                    // Code blocks in list items need blank line and indentation
-                    self.output.push_str("\n\n");
-                    if self.in_block_quote {
-                        self.output.push_str("> ");
-                    }
-                    self.output.push_str(&base_indent);
-                    self.serialize_code_block_indented(
-                        &code_block.info,
-                        &code_block.literal,
-                        &base_indent,
-                    );
-                }
-                NodeValue::BlockQuote | NodeValue::Alert(_) => {
-                    // Block quotes and alerts in list items need blank line
-                    // The indentation is handled by the blockquote/alert serialization itself
-                    if !is_first {
-                        self.output.push_str("\n\n");
-                    } else {
-                        self.output.push('\n');
-                    }
-                    if self.in_block_quote {
-                        self.output.push_str("> ");
-                    }
-                    self.serialize_node(child);
-                }
-                _ => {
-                    self.serialize_node(child);
-                }
-            }
+            let blank_lines =
+                written.then(|| usize::from(self.blank_line_before_item_block(&children, i)));
+            written |= self.serialize_block(child, blank_lines);
         }
+        self.list_marker_end = None;
 
         // Restore the old list item indent
         self.list_item_indent = old_list_item_indent;
+        self.list_marker_overflow = old_list_marker_overflow;
+        self.list_hang = old_list_hang;
+        self.line_prefix.pop();
 
         // Only add newline if the last child doesn't already end with one
-        // (nested lists, code blocks, and blockquotes add their own newlines)
-        let last_child = node.children().last();
-        let last_child_ends_with_newline = last_child.is_some_and(|child| {
-            matches!(
-                &child.data.borrow().value,
-                NodeValue::List(_)
-                    | NodeValue::CodeBlock(_)
-                    | NodeValue::BlockQuote
-                    | NodeValue::Alert(_)
-            )
-        });
-        if !last_child_ends_with_newline {
+        // (nested lists, code blocks, headings, and blockquotes add their own
+        // newlines, while paragraphs don't)
+        if !self.output.ends_with('\n') {
             self.output.push('\n');
         }
     }
@@ -0,0 +1,57 @@
+//! The rules of particular languages, for the `locale` option.
+//!
+//! Where sentences end, with `wrap = "sentence"`, is told by the rules of
+//! English unless the paragraph is in a language with rules of its own
+//! here.  With `locale = "auto"`, the language of each paragraph is told by
+//! the script most of its words are in.
+
+use crate::Locale;
+
+/// Whether `ch` is a Hangul syllable or jamo.
+pub(super) fn is_hangul(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{1100}'..='\u{11FF}'
+            | '\u{3130}'..='\u{318F}'
+            | '\u{A960}'..='\u{A97F}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{D7B0}'..='\u{D7FF}'
+    )
+}
+
+/// Whether the paragraph `text` is written by the rules of Korean, with
+/// `locale`: always with `ko`, and with `auto`, if most of its words have
+/// Hangul in them, as `Markdown은` does.
+pub(super) fn is_korean(locale: Locale, text: &str) -> bool {
+    match locale {
+        Locale::None => false,
+        Locale::Ko => true,
+        Locale::Auto => {
+            let words: Vec<&str> = text
+                .split_whitespace()
+                .filter(|word| word.chars().any(char::is_alphabetic))
+                .collect();
+            let korean = words
+                .iter()
+                .filter(|word| word.chars().any(is_hangul))
+                .count();
+            korean * 2 > words.len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_korean() {
+        let korean = "Hongdown은 Markdown 문서를 정리하는 도구입니다.";
+        let english = "Hongdown formats Markdown 문서 documents.";
+        assert!(is_korean(Locale::Auto, korean));
+        assert!(!is_korean(Locale::Auto, english));
+        assert!(!is_korean(Locale::Auto, "1. 2. 3."));
+        assert!(is_korean(Locale::Ko, english));
+        assert!(!is_korean(Locale::None, korean));
+    }
+}
@@ -0,0 +1,46 @@
+//! Math (`$` and `$$`) serialization logic.
+//!
+//! With the `math` option on, comrak parses TeX math between dollar signs.
+//! Math isn't Markdown, so it is written as it is in the source, and dollar
+//! signs in text are escaped instead (see [`super::escape::escape_dollars`]),
+//! so that prose like "costs $5 and $10" isn't read back as math.
+
+use comrak::nodes::{AstNode, NodeMath, NodeValue};
+
+use super::Serializer;
+
+impl<'a> Serializer<'a> {
+    /// The math span `node` as it is in the source, or rebuilt from its
+    /// contents when there is no source.  Like code spans, math that spans
+    /// several lines is put on one, as it is part of a wrapped paragraph.
+    pub(super) fn math_source<'b>(&self, node: &'b AstNode<'b>, math: &NodeMath) -> String {
+        match self.extract_source(node) {
+            Some(source) if !source.contains('\n') => source,
+            _ => {
+                let delimiter = if math.display_math { "$$" } else { "$" };
+                let literal = math.literal.replace(['\r', '\n'], " ");
+                format!("{delimiter}{literal}{delimiter}")
+            }
+        }
+    }
+
+    /// Write the paragraph `node` as it is in the source if it is nothing
+    /// but display math, like a `$$` block on lines of its own, returning
+    /// whether it was.
+    pub(super) fn serialize_display_math_paragraph<'b>(&mut self, node: &'b AstNode<'b>) -> bool {
+        let mut children = node.children();
+        let only_math = children.next().is_some_and(|child| {
+            matches!(&child.data.borrow().value, NodeValue::Math(math) if math.display_math)
+        }) && children.next().is_none();
+        if !only_math {
+            return false;
+        }
+        let Some(source) = self.block_source(node) else {
+            return false;
+        };
+        for line in source.lines() {
+            self.write_line(line.trim_end());
+        }
+        true
+    }
+}
@@ -0,0 +1,91 @@
+//! MDX tolerance: JSX and expressions kept as they are.
+//!
+//! MDX mixes Markdown with JSX components, JavaScript expressions in braces,
+//! and `import` and `export` statements, none of which CommonMark knows
+//! about.  Single-line tags are already parsed as HTML blocks, which are kept
+//! as they are, but a tag whose attributes span several lines, an expression,
+//! or a statement is parsed as a paragraph, which would be reflowed and
+//! escaped.  With the `mdx` option on, such paragraphs are written as they
+//! are in the source instead.  JSX is not parsed; whether a paragraph is one
+//! of these is told from how its first line starts.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::Serializer;
+
+/// Whether a paragraph whose first line is `line` is JSX, an expression, or
+/// (at the top level of the document, where `top_level` is true) an `import`
+/// or `export` statement, rather than Markdown.
+///
+/// JSX is told apart from HTML by its capitalized component names, like
+/// `<Tabs>` and `</TabItem>`, and by fragments (`<>` and `</>`).
+fn is_opaque(line: &str, top_level: bool) -> bool {
+    let line = line.trim_start();
+    if line.starts_with('{') {
+        return true;
+    }
+    if top_level && (line.starts_with("import ") || line.starts_with("export ")) {
+        return true;
+    }
+    let Some(tag) = line.strip_prefix('<') else {
+        return false;
+    };
+    let name = tag.strip_prefix('/').unwrap_or(tag);
+    name.starts_with('>') || name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+/// Whether `text` has a `{` expression, which MDX evaluates as JavaScript.
+pub(super) fn has_expression(text: &str) -> bool {
+    text.find('{')
+        .is_some_and(|open| text[open..].contains('}'))
+}
+
+impl<'a> Serializer<'a> {
+    /// Write the paragraph `node` as it is in the source if it is JSX, an
+    /// expression, or a statement rather than Markdown (see [`is_opaque`]),
+    /// returning whether it was.
+    pub(super) fn serialize_opaque_paragraph<'b>(&mut self, node: &'b AstNode<'b>) -> bool {
+        let top_level = node
+            .parent()
+            .is_some_and(|parent| matches!(parent.data.borrow().value, NodeValue::Document));
+        let Some(source) = self.block_source(node) else {
+            return false;
+        };
+        if !source
+            .lines()
+            .next()
+            .is_some_and(|line| is_opaque(line, top_level))
+        {
+            return false;
+        }
+        for line in source.lines() {
+            self.write_line(line);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_opaque() {
+        assert!(is_opaque("<Tabs", false));
+        assert!(is_opaque("</TabItem>", false));
+        assert!(is_opaque("<>", false));
+        assert!(is_opaque("{/* A comment. */}", false));
+        assert!(is_opaque("import Tabs from '@theme/Tabs';", true));
+        assert!(!is_opaque("import Tabs from '@theme/Tabs';", false));
+        assert!(!is_opaque("<span>text</span>", false));
+        assert!(!is_opaque("5 < 6", false));
+        assert!(!is_opaque("Important things first.", true));
+    }
+
+    #[test]
+    fn test_has_expression() {
+        assert!(has_expression("Version {props.version}."));
+        assert!(!has_expression("A lone { brace."));
+        assert!(!has_expression("A lone } brace."));
+    }
+}
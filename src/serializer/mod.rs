@@ -1,28 +1,87 @@
 //! Serializer for converting comrak AST to formatted Markdown.
 
+pub(crate) mod autolink;
 mod block;
 mod code;
+pub(crate) mod container;
+pub(crate) mod details;
 mod document;
-mod escape;
+mod embedded;
+pub(crate) mod escape;
+pub(crate) mod fix;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod formatter;
 pub mod heading;
+mod html;
 mod inline;
+pub(crate) mod inline_footnote;
+pub(crate) mod limits;
 mod link;
 mod list;
+mod locale;
+mod math;
+mod mdx;
+mod plain_text;
+#[cfg(feature = "plugins")]
+mod plugin;
+pub(crate) mod protect;
+mod pseudo_list;
 pub mod punctuation;
+pub(crate) mod rewrite;
+mod spacing;
 mod state;
 mod table;
+mod template;
+mod toc;
+mod url;
+mod whitespace;
 mod wrap;
 
+pub use plain_text::plain_text_of;
 #[cfg(feature = "wasm")]
 pub use state::CodeFormatterCallback;
-pub use state::{ReferenceLink, Serializer, Warning};
+pub use state::{LinePrefix, ReferenceLink, Serializer, Warning};
 
 use comrak::nodes::{AstNode, NodeValue};
 use unicode_width::UnicodeWidthStr;
 
-use crate::Options;
+use crate::{ByteOrderMark, DefinitionOrder, Dialect, Options, trace};
+pub(crate) use state::Directive;
+
+/// Compare reference labels or footnote names for
+/// [`DefinitionOrder::Alpha`]: ignoring case, and with runs of digits
+/// compared by their numeric value, so that `2` comes before `10`.  Labels
+/// that tie are compared as they are, so that the order is always the same.
+fn compare_labels(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        let ordering = match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |chars: &mut std::iter::Peekable<std::str::Chars<'_>>| {
+                    let mut run = String::new();
+                    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                        run.push(digit);
+                    }
+                    run.trim_start_matches('0').to_string()
+                };
+                let (x, y) = (digits(&mut a_chars), digits(&mut b_chars));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(&y))
+            }
+            (Some(x), Some(y)) => {
+                a_chars.next();
+                b_chars.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
 
 /// Result of serialization including output and any warnings.
 pub struct SerializeResult {
@@ -49,10 +108,69 @@ pub fn serialize_with_source_and_warnings<'a>(
     options: &Options,
     source: Option<&str>,
 ) -> SerializeResult {
+    serialize_into(node, options, source, String::new(), Vec::new())
+}
+
+/// Serializes a comrak AST node into existing output and warning buffers.
+///
+/// The buffers are cleared first, so that their allocations can be reused
+/// across documents without carrying over any of their contents.
+pub fn serialize_into<'a>(
+    node: &'a AstNode<'a>,
+    options: &Options,
+    source: Option<&str>,
+    mut output: String,
+    mut warnings: Vec<Warning>,
+) -> SerializeResult {
+    output.clear();
+    warnings.clear();
+    let (byte_order_mark, source) = split_byte_order_mark(source);
+    let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
+    let source_ends_with_newline = source.is_some_and(|s| s.ends_with('\n'));
+    let mut serializer = Serializer::new(options, source_lines, source_ends_with_newline);
+    serializer.output = output;
+    serializer.warnings = warnings;
+    serializer.serialize_node(node);
+    if byte_order_mark && options.bom == ByteOrderMark::Preserve {
+        serializer.output.insert(0, '\u{FEFF}');
+    }
+    SerializeResult {
+        output: serializer.output,
+        warnings: serializer.warnings,
+    }
+}
+
+/// Split a byte order mark off the start of `source`, returning whether there
+/// was one.
+///
+/// The parser drops the mark, and its source positions don't count it, so it
+/// is left out of the source lines and put back at the start of the output
+/// unless the `bom` option is `remove`.
+fn split_byte_order_mark(source: Option<&str>) -> (bool, Option<&str>) {
+    match source.and_then(|s| s.strip_prefix('\u{FEFF}')) {
+        Some(rest) => (true, Some(rest)),
+        None => (false, source),
+    }
+}
+
+/// Serializes with block handlers that get the first chance to write each
+/// block (`plugins` feature only).
+#[cfg(feature = "plugins")]
+pub fn serialize_with_block_handlers<'a>(
+    node: &'a AstNode<'a>,
+    options: &Options,
+    source: Option<&str>,
+    block_handlers: &[crate::plugins::BlockHandler],
+) -> SerializeResult {
+    let (byte_order_mark, source) = split_byte_order_mark(source);
     let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
     let source_ends_with_newline = source.is_some_and(|s| s.ends_with('\n'));
     let mut serializer = Serializer::new(options, source_lines, source_ends_with_newline);
+    serializer.block_handlers = block_handlers;
     serializer.serialize_node(node);
+    if byte_order_mark && options.bom == ByteOrderMark::Preserve {
+        serializer.output.insert(0, '\u{FEFF}');
+    }
     SerializeResult {
         output: serializer.output,
         warnings: serializer.warnings,
@@ -67,6 +185,7 @@ pub fn serialize_with_code_formatter<'a>(
     source: Option<&str>,
     code_formatter: CodeFormatterCallback,
 ) -> SerializeResult {
+    let (byte_order_mark, source) = split_byte_order_mark(source);
     let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
     let source_ends_with_newline = source.is_some_and(|s| s.ends_with('\n'));
     let mut serializer = Serializer::with_code_formatter_callback(
@@ -76,6 +195,9 @@ pub fn serialize_with_code_formatter<'a>(
         code_formatter,
     );
     serializer.serialize_node(node);
+    if byte_order_mark && options.bom == ByteOrderMark::Preserve {
+        serializer.output.insert(0, '\u{FEFF}');
+    }
     SerializeResult {
         output: serializer.output,
         warnings: serializer.warnings,
@@ -103,14 +225,24 @@ impl<'a> Serializer<'a> {
         // Remove leading ! for images
         let source = source.strip_prefix('!').unwrap_or(&source);
 
-        // Find the position of the first '[' and track brackets to find the matching ']'
-        let first_bracket = source.find('[')?;
+        // Track brackets from the first '[' to find the matching ']'; an autolink,
+        // like `<https://example.com/[a]>`, doesn't start with one
+        if !source.starts_with('[') {
+            return None;
+        }
         let chars: Vec<char> = source.chars().collect();
 
-        // Find the closing bracket at depth 0 (the one that closes the text/content part)
+        // Find the closing bracket at depth 0 (the one that closes the text/content part);
+        // escaped brackets, like the one in `[foo\]]`, don't count
         let mut depth = 0;
         let mut text_end_pos = None;
-        for (i, &ch) in chars.iter().enumerate().skip(first_bracket) {
+        let mut escaped = false;
+        for (i, &ch) in chars.iter().enumerate() {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            escaped = ch == '\\';
             match ch {
                 '[' => depth += 1,
                 ']' => {
@@ -128,7 +260,7 @@ impl<'a> Serializer<'a> {
 
         // Convert char indices to strings for safe UTF-8 handling
         let after_close: String = chars[text_end_pos + 1..].iter().collect();
-        let text: String = chars[first_bracket + 1..text_end_pos].iter().collect();
+        let text: String = chars[1..text_end_pos].iter().collect();
 
         // Normalize newlines to spaces in the text (for idempotency when text spans lines)
         let text = escape::normalize_whitespace(&text);
@@ -141,15 +273,18 @@ impl<'a> Serializer<'a> {
         // If followed by "[", it's full or collapsed reference style
         if let Some(label_content) = after_close.strip_prefix('[') {
             // Find the label between [ and ]
-            if let Some(label_end) = label_content.find(']') {
+            if let Some(label_end) = escape::closing_bracket(label_content) {
                 let label = label_content[..label_end].to_string();
                 // Normalize label too
                 let label = escape::normalize_whitespace(&label);
 
                 // If label is empty, it's collapsed reference - mark with special prefix
-                // to distinguish from shortcut reference
+                // to distinguish from shortcut reference.  A full reference whose label
+                // repeats the text, in any case, is marked too.
                 let final_label = if label.is_empty() {
                     format!("\x01{}", text) // Use \x01 as marker for collapsed reference
+                } else if state::reference_key(&label) == state::reference_key(&text) {
+                    format!("\x02{}", label) // Use \x02 as marker for [text][text]
                 } else {
                     label
                 };
@@ -185,9 +320,9 @@ impl<'a> Serializer<'a> {
         // Filter out references that have already been emitted
         let refs: Vec<ReferenceLink> = self
             .pending_references
-            .values()
-            .filter(|r| !self.emitted_references.contains(&r.label))
-            .cloned()
+            .iter()
+            .filter(|(key, _)| !self.emitted_references.contains_key(*key))
+            .map(|(_, r)| r.clone())
             .collect();
         self.pending_references.clear();
 
@@ -203,39 +338,41 @@ impl<'a> Serializer<'a> {
 
         self.ensure_blank_line();
 
-        if numeric_count < 2 {
-            // Less than 2 numeric refs: output all in insertion order
-            for reference in &refs {
-                Self::write_reference(&mut self.output, reference);
-                self.emitted_references.insert(reference.label.clone());
+        let ordered: Vec<&ReferenceLink> = match self.options.link_sort_definitions {
+            // References are pending in the order they are first used in
+            DefinitionOrder::Usage => refs.iter().collect(),
+            DefinitionOrder::Alpha => {
+                let mut sorted: Vec<&ReferenceLink> = refs.iter().collect();
+                sorted.sort_by(|a, b| compare_labels(&a.label, &b.label));
+                sorted
             }
-        } else {
-            // 2+ numeric refs: separate, sort numeric ones, output regular first
-            let mut regular_refs: Vec<&ReferenceLink> = Vec::new();
-            let mut numeric_refs: Vec<(u64, &ReferenceLink)> = Vec::new();
-
-            for reference in &refs {
-                if let Some(num) = Self::extract_numeric_label(&reference.label) {
-                    numeric_refs.push((num, reference));
-                } else {
-                    regular_refs.push(reference);
+            // Less than 2 numeric refs: output all in insertion order
+            DefinitionOrder::None if numeric_count < 2 => refs.iter().collect(),
+            DefinitionOrder::None => {
+                // 2+ numeric refs: separate, sort numeric ones, output regular first
+                let mut regular_refs: Vec<&ReferenceLink> = Vec::new();
+                let mut numeric_refs: Vec<(u64, &ReferenceLink)> = Vec::new();
+
+                for reference in &refs {
+                    if let Some(num) = Self::extract_numeric_label(&reference.label) {
+                        numeric_refs.push((num, reference));
+                    } else {
+                        regular_refs.push(reference);
+                    }
                 }
-            }
 
-            // Sort numeric references by their numeric value
-            numeric_refs.sort_by_key(|(num, _)| *num);
+                // Sort numeric references by their numeric value
+                numeric_refs.sort_by_key(|(num, _)| *num);
 
-            // Output regular references first (in insertion order)
-            for reference in regular_refs {
-                Self::write_reference(&mut self.output, reference);
-                self.emitted_references.insert(reference.label.clone());
+                // Output regular references first (in insertion order), then
+                // numeric references (sorted by number)
+                regular_refs.extend(numeric_refs.into_iter().map(|(_, reference)| reference));
+                regular_refs
             }
+        };
 
-            // Output numeric references (sorted by number)
-            for (_, reference) in numeric_refs {
-                Self::write_reference(&mut self.output, reference);
-                self.emitted_references.insert(reference.label.clone());
-            }
+        for reference in ordered {
+            self.emit_reference(reference);
         }
     }
 
@@ -245,20 +382,38 @@ impl<'a> Serializer<'a> {
         label.parse::<u64>().ok()
     }
 
-    /// Write a single reference definition to output
-    fn write_reference(output: &mut String, reference: &ReferenceLink) {
-        output.push('[');
-        // Replace SoftBreak marker with space for reference labels
-        // (comrak normalizes whitespace in labels, so this ensures idempotency)
-        output.push_str(&reference.label.replace('\x00', " "));
-        output.push_str("]: ");
-        output.push_str(&reference.url);
+    /// Write a single reference definition to output and remember it as
+    /// emitted.
+    ///
+    /// The definition goes on one line, unless it has a title and the line
+    /// would exceed `line_width`; then the title moves to the next line,
+    /// indented by four spaces.
+    fn emit_reference(&mut self, reference: &ReferenceLink) {
+        let label = state::reference_key(&reference.label);
+        self.output.push('[');
+        self.output.push_str(&label);
+        self.output.push_str("]: ");
+        let url = url::normalize_url(&reference.url, self.options.link_url_encoding);
+        let url = url::destination(&url);
+        if url.is_empty() {
+            // A definition can't leave out its destination like a link can
+            self.output.push_str("<>");
+        } else {
+            self.output.push_str(&url);
+        }
         if !reference.title.is_empty() {
-            output.push_str(" \"");
-            output.push_str(&reference.title);
-            output.push('"');
+            let width = label.width() + url.width() + reference.title.width() + 7;
+            if width > self.options.line_width.get() {
+                self.output.push_str("\n    \"");
+            } else {
+                self.output.push_str(" \"");
+            }
+            self.output.push_str(&url::title(&reference.title));
+            self.output.push('"');
         }
-        output.push('\n');
+        self.output.push('\n');
+        self.emitted_references
+            .insert(state::reference_key(&reference.label), reference.clone());
     }
 
     /// Output pending footnote definitions that were referenced before the given line.
@@ -304,39 +459,57 @@ impl<'a> Serializer<'a> {
             .filter(|f| Self::extract_numeric_footnote_name(&f.name).is_some())
             .count();
 
-        if numeric_count < 2 {
-            // Less than 2 numeric footnotes: output all in insertion order
-            for footnote in &to_emit {
-                self.write_footnote(footnote);
-                self.footnotes.emitted.insert(footnote.name.clone());
+        let ordered: Vec<&state::FootnoteDefinition> = match self.options.footnote_sort_definitions
+        {
+            DefinitionOrder::Usage => {
+                // By the line they are first referenced on, with the ones
+                // never referenced last; the sort is stable, so footnotes
+                // referenced on the same line keep their insertion order
+                let mut sorted: Vec<&state::FootnoteDefinition> = to_emit.iter().collect();
+                sorted.sort_by_key(|f| (f.reference_line == 0, f.reference_line));
+                sorted
+            }
+            DefinitionOrder::Alpha => {
+                let mut sorted: Vec<&state::FootnoteDefinition> = to_emit.iter().collect();
+                sorted.sort_by(|a, b| compare_labels(&a.name, &b.name));
+                sorted
             }
-        } else {
-            // 2+ numeric footnotes: separate, sort numeric ones, output regular first
-            let mut regular_footnotes: Vec<&state::FootnoteDefinition> = Vec::new();
-            let mut numeric_footnotes: Vec<(u64, &state::FootnoteDefinition)> = Vec::new();
-
-            for footnote in &to_emit {
-                if let Some(num) = Self::extract_numeric_footnote_name(&footnote.name) {
-                    numeric_footnotes.push((num, footnote));
-                } else {
-                    regular_footnotes.push(footnote);
+            // Less than 2 numeric footnotes: output all in insertion order
+            DefinitionOrder::None if numeric_count < 2 => to_emit.iter().collect(),
+            DefinitionOrder::None => {
+                // 2+ numeric footnotes: separate, sort numeric ones, output regular first
+                let mut regular_footnotes: Vec<&state::FootnoteDefinition> = Vec::new();
+                let mut numeric_footnotes: Vec<(u64, &state::FootnoteDefinition)> = Vec::new();
+
+                for footnote in &to_emit {
+                    if let Some(num) = Self::extract_numeric_footnote_name(&footnote.name) {
+                        numeric_footnotes.push((num, footnote));
+                    } else {
+                        regular_footnotes.push(footnote);
+                    }
                 }
-            }
 
-            // Sort numeric footnotes by their numeric value
-            numeric_footnotes.sort_by_key(|(num, _)| *num);
+                // Sort numeric footnotes by their numeric value
+                numeric_footnotes.sort_by_key(|(num, _)| *num);
 
-            // Output regular footnotes first (in insertion order)
-            for footnote in regular_footnotes {
-                self.write_footnote(footnote);
-                self.footnotes.emitted.insert(footnote.name.clone());
+                // Output regular footnotes first (in insertion order), then
+                // numeric footnotes (sorted by number)
+                regular_footnotes
+                    .extend(numeric_footnotes.into_iter().map(|(_, footnote)| footnote));
+                regular_footnotes
             }
+        };
 
-            // Output numeric footnotes (sorted by number)
-            for (_, footnote) in numeric_footnotes {
-                self.write_footnote(footnote);
-                self.footnotes.emitted.insert(footnote.name.clone());
+        let mut previous_has_blocks = false;
+        for footnote in ordered {
+            // A definition right after block content could be taken for a
+            // lazy continuation of its last paragraph or list item
+            if previous_has_blocks {
+                self.ensure_blank_line();
             }
+            self.write_footnote(footnote);
+            self.footnotes.emitted.insert(footnote.name.clone());
+            previous_has_blocks = footnote.has_blocks;
         }
     }
 
@@ -362,7 +535,7 @@ impl<'a> Serializer<'a> {
         let mut to_keep: Vec<(String, (ReferenceLink, usize))> = Vec::new();
 
         for (label, (reference, footnote_ref_line)) in self.footnotes.pending_references.drain(..) {
-            if self.emitted_references.contains(&label) {
+            if self.emitted_references.contains_key(&label) {
                 continue;
             }
             let should_emit = match before_line {
@@ -387,10 +560,12 @@ impl<'a> Serializer<'a> {
 
         self.ensure_blank_line();
 
-        // Output references in insertion order
+        // Output references in insertion order, unless they are sorted
+        if self.options.link_sort_definitions == DefinitionOrder::Alpha {
+            to_emit.sort_by(|a, b| compare_labels(&a.label, &b.label));
+        }
         for reference in &to_emit {
-            Self::write_reference(&mut self.output, reference);
-            self.emitted_references.insert(reference.label.clone());
+            self.emit_reference(reference);
         }
     }
 
@@ -405,33 +580,20 @@ impl<'a> Serializer<'a> {
         // Continuation indent matches prefix length for alignment
         let continuation_indent = " ".repeat(prefix.len());
 
-        // If footnote has block elements, output pre-serialized content with indentation
+        // If footnote has block elements, output the pre-serialized definition
         if footnote.has_blocks {
-            let lines: Vec<&str> = footnote.content.lines().collect();
-            for (i, line) in lines.iter().enumerate() {
-                if i == 0 {
-                    self.output.push_str(&prefix);
-                    self.output.push_str(line);
-                } else if line.is_empty() {
-                    // Empty line - just add newline
-                    self.output.push_str("");
-                } else {
-                    // Add continuation indent
-                    self.output.push_str(&continuation_indent);
-                    self.output.push_str(line);
-                }
-                self.output.push('\n');
-            }
+            self.output.push_str(&footnote.content);
+            self.output.push('\n');
             return;
         }
 
         // Wrap content at line_width, accounting for prefix on first line
-        let line_width = self.options.line_width.get();
-        let first_line_width = line_width - prefix.width();
-        let continuation_width = line_width - continuation_indent.len();
+        let line_width = self.wrap_width();
+        let first_line_width = wrap::text_width(prefix.width(), line_width);
+        let continuation_width = wrap::text_width(continuation_indent.len(), line_width);
 
         // Replace SoftBreak marker (\x00) with space before processing
-        let content = footnote.content.replace('\x00', " ");
+        let content = wrap::join_soft_breaks(&footnote.content);
         let words: Vec<&str> = content.split_whitespace().collect();
         if words.is_empty() {
             self.output.push_str(&prefix);
@@ -481,38 +643,86 @@ impl<'a> Serializer<'a> {
     }
 
     /// Serialize footnote content that contains block elements.
-    /// Returns the serialized content without the footnote prefix.
-    fn serialize_footnote_blocks<'b>(&mut self, node: &'b AstNode<'b>) -> String {
-        let mut result = String::new();
-        let mut first_block = true;
-
-        for child in node.children() {
-            let block_content = self.serialize_single_block(child);
-
-            if !first_block && !block_content.is_empty() {
-                // Add blank line between blocks (need two newlines for a blank line)
-                result.push_str("\n\n");
+    ///
+    /// Returns the whole definition, starting with the `[^name]: ` prefix.
+    /// Every following line is indented four spaces, the indentation a
+    /// footnote definition's continuation needs, except blank lines between
+    /// blocks, which are left truly blank.
+    fn serialize_footnote_blocks<'b>(&mut self, node: &'b AstNode<'b>, name: &str) -> String {
+        let old_output = std::mem::take(&mut self.output);
+        let prefix = format!("[^{}]: ", name);
+        self.line_prefix.push(LinePrefix::Indent(4));
+
+        for (i, child) in node.children().enumerate() {
+            if i == 0 && matches!(child.data.borrow().value, NodeValue::Paragraph) {
+                // The first paragraph continues the prefix line
+                let mut content = String::new();
+                self.collect_inline_content(child, &mut content);
+                let continuation = self.line_prefix();
+                let wrapped = wrap::wrap_text_first_line(
+                    content.trim(),
+                    &prefix,
+                    &continuation,
+                    self.wrap_width(),
+                    self.hard_break(),
+                );
+                let wrapped = escape::escape_block_lookalikes(&wrapped, &prefix, &continuation);
+                self.output.push_str(&wrapped);
+                self.output.push('\n');
+                continue;
+            }
+            if i == 0 {
+                self.output.push_str(prefix.trim_end());
+                self.output.push('\n');
+            } else {
+                self.start_block(1);
             }
-            result.push_str(&block_content);
-            first_block = false;
+            self.serialize_node(child);
         }
 
-        result.trim_end().to_string()
+        self.line_prefix.pop();
+        let content = std::mem::replace(&mut self.output, old_output);
+        content.trim_end().to_string()
     }
 
-    /// Serialize a single block element for footnote content.
-    fn serialize_single_block<'b>(&mut self, node: &'b AstNode<'b>) -> String {
-        // Use the existing serializer infrastructure by temporarily capturing output
-        let old_output = std::mem::take(&mut self.output);
-        self.serialize_node(node);
-        let block_output = std::mem::replace(&mut self.output, old_output);
-        block_output.trim_end().to_string()
+    pub fn serialize_node<'b>(&mut self, node: &'b AstNode<'b>) {
+        let _span = trace::span!(
+            "block",
+            kind = node.data.borrow().value.xml_node_name(),
+            line = node.data.borrow().sourcepos.start.line,
+        );
+        #[cfg(feature = "plugins")]
+        if self.run_block_handlers(node) {
+            return;
+        }
+        // A block right after a `hongdown-no-wrap` directive keeps the line
+        // breaks of its paragraphs, including those nested in it, and so does
+        // a paragraph of lines like `a. first` and `b. second`
+        let follows_no_wrap = node.previous_sibling().is_some_and(|previous| {
+            matches!(
+                &previous.data.borrow().value,
+                NodeValue::HtmlBlock(html) if Directive::parse(&html.literal) == Some(Directive::NoWrap)
+            )
+        }) || Self::is_line_in_disabled_ranges(
+            node.data.borrow().sourcepos.start.line,
+            &self.no_wrap_ranges,
+        ) || (self.options.list_preserve_pseudo_lists
+            && pseudo_list::is_pseudo_list(node));
+        if follows_no_wrap && !self.no_wrap {
+            self.no_wrap = true;
+            self.serialize_node_value(node);
+            self.no_wrap = false;
+        } else {
+            self.serialize_node_value(node);
+        }
     }
 
-    pub fn serialize_node<'b>(&mut self, node: &'b AstNode<'b>) {
+    fn serialize_node_value<'b>(&mut self, node: &'b AstNode<'b>) {
         match &node.data.borrow().value {
             NodeValue::Document => {
                 self.serialize_document(node);
+                self.strip_trailing_whitespace();
+                self.restore_inline_footnotes();
             }
             NodeValue::Heading(heading) => {
                 self.serialize_heading(node, heading.level);
@@ -521,24 +731,37 @@ impl<'a> Serializer<'a> {
                 self.serialize_list(node, list.list_type, list.tight);
             }
             NodeValue::CodeBlock(code_block) => {
-                self.serialize_code_block(&code_block.info, &code_block.literal);
+                let line = node.data.borrow().sourcepos.start.line;
+                self.serialize_code_block(&code_block.info, &code_block.literal, line);
             }
             NodeValue::BlockQuote => {
                 self.serialize_block_quote(node);
             }
+            NodeValue::MultilineBlockQuote(_) => {
+                self.serialize_container(node);
+            }
             NodeValue::HtmlBlock(html_block) => {
-                // Preserve HTML blocks (like comments) as-is
-                self.output.push_str(&html_block.literal);
+                self.serialize_html_block(&html_block.literal);
+            }
+            NodeValue::Raw(source) => {
+                // A block nested too deeply to format; see `limits`
+                self.output
+                    .push_str(&whitespace::keep_trailing_whitespace(source));
+                self.output.push('\n');
             }
             NodeValue::HtmlInline(html) => {
-                // Preserve inline HTML as-is
-                self.output.push_str(html);
+                let html = self.inline_html(html);
+                self.output.push_str(&html);
             }
             NodeValue::FrontMatter(content) => {
                 self.serialize_front_matter(content);
             }
             NodeValue::Table(table) => {
-                self.serialize_table(node, table);
+                if self.options.dialect == Dialect::Gfm {
+                    self.serialize_table(node, table);
+                } else {
+                    self.serialize_table_fallback(node, table);
+                }
             }
             NodeValue::TableRow(is_header) => {
                 self.serialize_table_row(node, *is_header);
@@ -547,23 +770,12 @@ impl<'a> Serializer<'a> {
                 self.serialize_children(node);
             }
             NodeValue::DescriptionList => {
-                // Serialize description list items with blank lines between them
-                let children: Vec<_> = node.children().collect();
-                for (i, child) in children.iter().enumerate() {
-                    if i > 0 {
-                        // Add blank line between description items
-                        // If inside a blockquote, we need to add the > prefix on the blank line
-                        // to keep items within the same blockquote
-                        if self.in_block_quote {
-                            self.output.push_str(&self.list_item_indent);
-                            // Use blockquote_prefix without trailing space for blank lines
-                            self.output.push_str(self.blockquote_prefix.trim_end());
-                            self.output.push('\n');
-                        } else {
-                            self.output.push('\n');
-                        }
-                    }
-                    self.serialize_node(child);
+                // Serialize description list items with blank lines between
+                // them, which have the prefix of the block quote the list is
+                // in, if any, to keep the items in it
+                let mut written = false;
+                for child in node.children() {
+                    written |= self.serialize_block(child, written.then_some(1));
                 }
             }
             NodeValue::DescriptionItem(_) => {
@@ -593,7 +805,16 @@ impl<'a> Serializer<'a> {
                 self.serialize_thematic_break();
             }
             NodeValue::Text(text) => {
-                self.output.push_str(&escape::escape_text(text));
+                let escaped = escape::escape_text(text);
+                if self.options.math {
+                    self.output.push_str(&escape::escape_dollars(&escaped));
+                } else {
+                    self.output.push_str(&escaped);
+                }
+            }
+            NodeValue::Math(math) => {
+                let source = self.math_source(node, math);
+                self.output.push_str(&source);
             }
             NodeValue::SoftBreak => {
                 self.output.push(' ');
@@ -670,7 +891,7 @@ impl<'a> Serializer<'a> {
 
                 let content = if has_blocks {
                     // Serialize block elements properly
-                    self.serialize_footnote_blocks(node)
+                    self.serialize_footnote_blocks(node, &footnote_def.name)
                 } else {
                     // Simple inline content - use existing approach
                     let mut content = String::new();
@@ -0,0 +1,163 @@
+//! The plain text of inline content, as the table of contents, lints, and
+//! other tools that show or compare headings need it.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+/// The text of the inline content of `node`, such as a heading, without any
+/// markup.
+///
+/// Code spans and emphasis are replaced with their content, links with
+/// their text, and images with their alternative text.  Line breaks become
+/// spaces, the tags of inline HTML are stripped, keeping any text around
+/// them, and footnote references are dropped.
+///
+/// # Example
+///
+/// ```
+/// use comrak::{Arena, Options, parse_document};
+///
+/// let arena = Arena::new();
+/// let root = parse_document(&arena, "# The *`format`* [function](/api)\n", &Options::default());
+/// let heading = root.first_child().unwrap();
+/// assert_eq!(hongdown::plain_text_of(heading), "The format function");
+/// ```
+pub fn plain_text_of<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    collect(node, &mut text);
+    text
+}
+
+fn collect<'a>(node: &'a AstNode<'a>, text: &mut String) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(t) => text.push_str(t),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::Math(math) => text.push_str(&math.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            NodeValue::HtmlInline(html) => text.push_str(&strip_tags(html)),
+            NodeValue::FootnoteReference(_) => {}
+            _ => collect(child, text),
+        }
+    }
+}
+
+/// `html` without its tags and comments.  A `<` that isn't closed is kept.
+fn strip_tags(html: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start + end + 1..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrak::Arena;
+
+    /// The plain text of the first heading of `input`.
+    fn heading_text(input: &str) -> String {
+        let arena = Arena::new();
+        let mut options = crate::base_comrak_options();
+        options.extension.math_dollars = true;
+        options.extension.strikethrough = true;
+        let root = comrak::parse_document(&arena, input, &options);
+        let heading = root
+            .descendants()
+            .find(|node| matches!(node.data.borrow().value, NodeValue::Heading(_)))
+            .unwrap();
+        plain_text_of(heading)
+    }
+
+    #[test]
+    fn test_plain_text_of_text() {
+        assert_eq!(heading_text("# Getting started\n"), "Getting started");
+        assert_eq!(heading_text("# A \\*literal\\* star\n"), "A *literal* star");
+        assert_eq!(heading_text("# Fish &amp; chips\n"), "Fish & chips");
+    }
+
+    #[test]
+    fn test_plain_text_of_code_span() {
+        assert_eq!(
+            heading_text("# The `format()` function\n"),
+            "The format() function"
+        );
+        assert_eq!(heading_text("# ``a ` b``\n"), "a ` b");
+    }
+
+    #[test]
+    fn test_plain_text_of_emphasis() {
+        assert_eq!(
+            heading_text("# *Very* **important** ~~old~~\n"),
+            "Very important old"
+        );
+        assert_eq!(heading_text("# ***Both*** *`code`*\n"), "Both code");
+    }
+
+    #[test]
+    fn test_plain_text_of_link() {
+        assert_eq!(
+            heading_text("# See [the *docs*](/docs \"Docs\")\n"),
+            "See the docs"
+        );
+        assert_eq!(
+            heading_text("# Go to <https://example.com/>\n"),
+            "Go to https://example.com/"
+        );
+        assert_eq!(
+            heading_text("# A [reference][ref]\n\n[ref]: /url\n"),
+            "A reference"
+        );
+    }
+
+    #[test]
+    fn test_plain_text_of_image() {
+        assert_eq!(
+            heading_text("# ![Logo](logo.png) Hongdown\n"),
+            "Logo Hongdown"
+        );
+        assert_eq!(heading_text("# [![*Badge*](badge.svg)](/ci)\n"), "Badge");
+    }
+
+    #[test]
+    fn test_plain_text_of_footnote_reference() {
+        assert_eq!(heading_text("# Title[^1]\n\n[^1]: Note.\n"), "Title");
+    }
+
+    #[test]
+    fn test_plain_text_of_inline_html() {
+        assert_eq!(heading_text("# Press <kbd>Ctrl</kbd>\n"), "Press Ctrl");
+        assert_eq!(heading_text("# A<!-- comment --> B\n"), "A B");
+        assert_eq!(heading_text("# One<br>two\n"), "Onetwo");
+    }
+
+    #[test]
+    fn test_plain_text_of_math() {
+        assert_eq!(heading_text("# Euler's $e^{i\\pi}$\n"), "Euler's e^{i\\pi}");
+    }
+
+    #[test]
+    fn test_plain_text_of_line_break() {
+        assert_eq!(
+            heading_text("Line one  \nline two\n===\n"),
+            "Line one line two"
+        );
+        assert_eq!(
+            heading_text("Line one\nline two\n===\n"),
+            "Line one line two"
+        );
+    }
+
+    #[test]
+    fn test_strip_tags() {
+        assert_eq!(strip_tags("<span class=\"x\">"), "");
+        assert_eq!(strip_tags("<!-- c -->"), "");
+        assert_eq!(strip_tags("a < b"), "a < b");
+    }
+}
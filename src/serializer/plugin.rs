@@ -0,0 +1,72 @@
+//! Running the block handlers of the `plugins` feature.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::Serializer;
+use crate::plugins::{BlockSink, Handled};
+
+/// The [`BlockSink`] given to block handlers, which writes through the
+/// serializer so that each line gets the current line prefix.
+struct SerializerSink<'s, 'a> {
+    serializer: &'s mut Serializer<'a>,
+    source: Option<String>,
+}
+
+impl BlockSink for SerializerSink<'_, '_> {
+    fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    fn write_line(&mut self, line: &str) {
+        for line in line.split('\n') {
+            self.serializer
+                .write_line(line.strip_suffix('\r').unwrap_or(line));
+        }
+    }
+}
+
+impl<'a> Serializer<'a> {
+    /// Give the block handlers the chance to write `node`, returning whether
+    /// one of them did.
+    ///
+    /// Whatever a handler that declines the block wrote is taken back out of
+    /// the output.
+    pub(super) fn run_block_handlers<'b>(&mut self, node: &'b AstNode<'b>) -> bool {
+        if self.block_handlers.is_empty() || !is_handled_block(&node.data.borrow().value) {
+            return false;
+        }
+        let source = self.block_source(node);
+        let start = self.output.len();
+        let handlers = self.block_handlers;
+        for handler in handlers {
+            let mut sink = SerializerSink {
+                serializer: self,
+                source: source.clone(),
+            };
+            if handler(node, &mut sink) == Handled::Yes {
+                return true;
+            }
+            self.output.truncate(start);
+        }
+        false
+    }
+}
+
+/// Whether block handlers are given nodes of this kind: blocks that stand on
+/// lines of their own, rather than the parts of lists, tables, and
+/// description lists, or footnote definitions, which are moved around.
+fn is_handled_block(value: &NodeValue) -> bool {
+    value.block()
+        && !matches!(
+            value,
+            NodeValue::Document
+                | NodeValue::Item(_)
+                | NodeValue::TaskItem(_)
+                | NodeValue::TableRow(_)
+                | NodeValue::TableCell
+                | NodeValue::DescriptionItem(_)
+                | NodeValue::DescriptionTerm
+                | NodeValue::DescriptionDetails
+                | NodeValue::FootnoteDefinition(_)
+        )
+}
@@ -0,0 +1,136 @@
+//! Protected ranges: parts of a document that are copied as they are
+//! instead of being formatted, for
+//! [`format_with_protected_ranges`](crate::format_with_protected_ranges).
+//!
+//! A range of bytes is widened to the lines it touches, and then to the
+//! top-level blocks on those lines, which are replaced right after parsing
+//! with a raw node holding their source, like the blocks [`super::limits`]
+//! gives up on.
+
+use std::ops::Range;
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::details::original_line;
+
+/// The first and last lines, from 1, of each of `ranges` of bytes of
+/// `input`, in order, or a message saying why they can't be protected:
+/// a range that reaches past the end of `input`, is reversed, or overlaps
+/// another.
+pub(crate) fn line_ranges(
+    input: &str,
+    ranges: &[Range<usize>],
+) -> Result<Vec<(usize, usize)>, String> {
+    let mut sorted: Vec<&Range<usize>> = ranges.iter().collect();
+    sorted.sort_by_key(|range| (range.start, range.end));
+    for range in &sorted {
+        if range.start > range.end || range.end > input.len() {
+            return Err(format!(
+                "range {:?} is out of bounds of the input, which is {} bytes long",
+                range,
+                input.len()
+            ));
+        }
+    }
+    if let Some(pair) = sorted.windows(2).find(|pair| pair[1].start < pair[0].end) {
+        return Err(format!("ranges {:?} and {:?} overlap", pair[0], pair[1]));
+    }
+    let bytes = input.as_bytes();
+    let line_at = |offset: usize| 1 + bytes[..offset].iter().filter(|&&b| b == b'\n').count();
+    Ok(sorted
+        .into_iter()
+        .map(|range| {
+            let last = if range.is_empty() {
+                range.start
+            } else {
+                range.end - 1
+            };
+            (line_at(range.start), line_at(last))
+        })
+        .collect())
+}
+
+/// Replace each run of top-level blocks of `root` that touch any of the
+/// `protected` lines of `source` with a [`NodeValue::Raw`] node holding
+/// those lines of `source`, as they are.
+///
+/// `root` is parsed from `source` with blank lines put in at the lines
+/// `inserted`; see [`super::details::prepare`].
+pub(crate) fn protect_blocks<'a>(
+    root: &'a AstNode<'a>,
+    source: &str,
+    protected: &[(usize, usize)],
+    inserted: &[usize],
+) {
+    if protected.is_empty() {
+        return;
+    }
+    let source = source.strip_prefix('\u{FEFF}').unwrap_or(source);
+    let lines: Vec<&str> = source.lines().collect();
+    let touches = |block: &'a AstNode<'a>| {
+        let sourcepos = block.data.borrow().sourcepos;
+        let start = original_line(sourcepos.start.line, inserted);
+        let end = original_line(sourcepos.end.line, inserted);
+        protected
+            .iter()
+            .any(|&(first, last)| start <= last && end >= first)
+    };
+    let mut block = root.first_child();
+    while let Some(first) = block {
+        block = first.next_sibling();
+        if !touches(first) {
+            continue;
+        }
+        // Take in the following blocks that are protected too, so that the
+        // blank lines between them are kept as well
+        let mut end = first.data.borrow().sourcepos.end;
+        while let Some(next) = block.filter(|&next| touches(next)) {
+            block = next.next_sibling();
+            end = next.data.borrow().sourcepos.end;
+            next.detach();
+        }
+        while let Some(child) = first.first_child() {
+            child.detach();
+        }
+        let mut data = first.data.borrow_mut();
+        let start = original_line(data.sourcepos.start.line, inserted).max(1) - 1;
+        let last = original_line(end.line, inserted).min(lines.len());
+        data.value = NodeValue::Raw(lines[start.min(last)..last].join("\n"));
+        data.sourcepos.end = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(input: &str, ranges: &[(usize, usize)]) -> Result<Vec<(usize, usize)>, String> {
+        let ranges: Vec<_> = ranges.iter().map(|&(start, end)| start..end).collect();
+        line_ranges(input, &ranges)
+    }
+
+    #[test]
+    fn test_line_ranges() {
+        let input = "one\ntwo\nthree\n";
+        assert_eq!(lines(input, &[(0, 3)]).unwrap(), [(1, 1)]);
+        assert_eq!(lines(input, &[(0, 4)]).unwrap(), [(1, 1)]);
+        assert_eq!(lines(input, &[(5, 9)]).unwrap(), [(2, 3)]);
+        assert_eq!(lines(input, &[(4, 4)]).unwrap(), [(2, 2)]);
+        assert_eq!(lines(input, &[(8, 14), (0, 2)]).unwrap(), [(1, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn test_line_ranges_invalid() {
+        let input = "one\ntwo\n";
+        assert_eq!(
+            lines(input, &[(4, 9)]).unwrap_err(),
+            "range 4..9 is out of bounds of the input, which is 8 bytes long"
+        );
+        assert!(lines(input, &[(5, 2)]).is_err());
+        assert_eq!(
+            lines(input, &[(4, 8), (0, 5)]).unwrap_err(),
+            "ranges 0..5 and 4..8 overlap"
+        );
+        assert!(lines(input, &[(0, 4), (4, 8)]).is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+//! Pseudo-lists: lines like `a. first` and `b. second` that read as the
+//! items of a lettered or roman-numbered list, which CommonMark doesn't
+//! have, and parses as a single paragraph.  With
+//! `list_preserve_pseudo_lists`, such a paragraph keeps its line breaks
+//! instead of being wrapped into one run of text.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+/// Whether the paragraph `node` has two or more lines in a row that start
+/// with the marker of a lettered or roman-numbered item, like `a.`, `B)`, or
+/// `iv.`.
+pub(super) fn is_pseudo_list<'a>(node: &'a AstNode<'a>) -> bool {
+    if !matches!(node.data.borrow().value, NodeValue::Paragraph) {
+        return false;
+    }
+    let mut line_start = true;
+    let mut run = 0;
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::SoftBreak | NodeValue::LineBreak => line_start = true,
+            value if line_start => {
+                line_start = false;
+                run = match value {
+                    NodeValue::Text(text) if starts_with_marker(text) => run + 1,
+                    _ => 0,
+                };
+                if run >= 2 {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Whether `text` starts with a letter or a roman numeral followed by `.` or
+/// `)`, and then a space or nothing.
+fn starts_with_marker(text: &str) -> bool {
+    let label_len = text.bytes().take_while(u8::is_ascii_alphabetic).count();
+    let (label, rest) = text.split_at(label_len);
+    let Some(rest) = rest.strip_prefix(['.', ')']) else {
+        return false;
+    };
+    if !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return false;
+    }
+    label.len() == 1 || is_roman_numeral(label)
+}
+
+/// Whether `label` is a roman numeral written in the usual way, all in
+/// lowercase or all in uppercase, like `iv` or `XII`, but not `IIII` or
+/// `Vi`.
+fn is_roman_numeral(label: &str) -> bool {
+    let lower = label.to_ascii_lowercase();
+    if label != lower && label != label.to_ascii_uppercase() {
+        return false;
+    }
+    let value_of = |c: char| match c {
+        'i' => Some(1),
+        'v' => Some(5),
+        'x' => Some(10),
+        'l' => Some(50),
+        'c' => Some(100),
+        'd' => Some(500),
+        'm' => Some(1000),
+        _ => None,
+    };
+    let Some(values) = lower.chars().map(value_of).collect::<Option<Vec<u32>>>() else {
+        return false;
+    };
+    let mut total = 0;
+    for (i, &value) in values.iter().enumerate() {
+        if values.get(i + 1).is_some_and(|&next| next > value) {
+            total -= value as i64;
+        } else {
+            total += value as i64;
+        }
+    }
+    total > 0 && total < 4000 && to_roman_numeral(total as u32) == lower
+}
+
+/// `value`, from 1 to 3999, as a lowercase roman numeral.
+fn to_roman_numeral(mut value: u32) -> String {
+    const DIGITS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut numeral = String::new();
+    for (digit, letters) in DIGITS {
+        while value >= digit {
+            numeral.push_str(letters);
+            value -= digit;
+        }
+    }
+    numeral
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrak::Arena;
+
+    fn first_paragraph_is_pseudo_list(input: &str) -> bool {
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, input, &crate::base_comrak_options());
+        let paragraph = root
+            .descendants()
+            .find(|node| matches!(node.data.borrow().value, NodeValue::Paragraph))
+            .unwrap();
+        is_pseudo_list(paragraph)
+    }
+
+    #[test]
+    fn test_is_pseudo_list() {
+        assert!(first_paragraph_is_pseudo_list("a. first\nb. second\n"));
+        assert!(first_paragraph_is_pseudo_list(
+            "Steps:\nA) one\nB) two\nC) three\n"
+        ));
+        assert!(first_paragraph_is_pseudo_list(
+            "i. one\nii. two\niii. three\n"
+        ));
+        assert!(first_paragraph_is_pseudo_list("*Note*\nx. one  \ny. two\n"));
+        assert!(first_paragraph_is_pseudo_list("a. one *b*\nb. two\n"));
+    }
+
+    #[test]
+    fn test_is_not_pseudo_list() {
+        assert!(!first_paragraph_is_pseudo_list("a. first\n"));
+        assert!(!first_paragraph_is_pseudo_list(
+            "a. first\nand more\nb. second\n"
+        ));
+        assert!(!first_paragraph_is_pseudo_list(
+            "See p.\nA. Smith, and\nthe rest.\n"
+        ));
+        assert!(!first_paragraph_is_pseudo_list("word. one\nwords. two\n"));
+        assert!(!first_paragraph_is_pseudo_list("a.b one\nb.c two\n"));
+    }
+
+    #[test]
+    fn test_is_roman_numeral() {
+        for numeral in ["i", "iv", "ix", "xii", "XIV", "mmxxvi", "xl"] {
+            assert!(is_roman_numeral(numeral), "{numeral}");
+        }
+        for label in ["iiii", "vx", "Iv", "ic", "abc", "", "vv"] {
+            assert!(!is_roman_numeral(label), "{label}");
+        }
+    }
+}
@@ -504,14 +504,15 @@ mod tests {
         en_dash: DashSetting,
         em_dash: DashSetting,
     ) -> Options {
-        let mut options = Options::default();
-        options.curly_double_quotes = curly_double_quotes;
-        options.curly_single_quotes = curly_single_quotes;
-        options.curly_apostrophes = curly_apostrophes;
-        options.ellipsis = ellipsis;
-        options.en_dash = en_dash;
-        options.em_dash = em_dash;
-        options
+        Options {
+            curly_double_quotes,
+            curly_single_quotes,
+            curly_apostrophes,
+            ellipsis,
+            en_dash,
+            em_dash,
+            ..Options::default()
+        }
     }
 
     // ========== Ellipsis tests ==========
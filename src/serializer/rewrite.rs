@@ -0,0 +1,90 @@
+//! Rewriting the destinations of links and images before they are written,
+//! for [`rewrite_links`](crate::rewrite_links).
+
+use std::collections::HashMap;
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::Serializer;
+use super::details::original_line;
+use super::link::split_reference_label;
+use super::state::reference_key;
+use super::url;
+use crate::{LinkKind, LinkRef, Options};
+
+/// Pass each link and image of `root`, parsed from `source`, to `rewrite`,
+/// and replace its destination with the one `rewrite` returns, if any.
+///
+/// The links that share a reference definition are passed once, as the
+/// first of them, and all get the same destination, so that the definition
+/// is rewritten once.  `source` has blank lines put in at the lines
+/// `inserted`; see [`super::details::prepare`].
+pub(crate) fn rewrite_links<'a>(
+    root: &'a AstNode<'a>,
+    options: &Options,
+    source: &str,
+    inserted: &[usize],
+    rewrite: &mut dyn FnMut(LinkRef) -> Option<String>,
+) {
+    let (_, source) = super::split_byte_order_mark(Some(source));
+    let source = source.unwrap_or_default();
+    let serializer = Serializer::new(options, source.lines().collect(), source.ends_with('\n'));
+    // The destinations of the definitions rewritten so far, by label
+    let mut definitions: HashMap<String, Option<String>> = HashMap::new();
+    for node in root.descendants() {
+        let (destination, title, image) = match &node.data.borrow().value {
+            NodeValue::Link(link) => (link.url.clone(), link.title.clone(), false),
+            NodeValue::Image(link) => (link.url.clone(), link.title.clone(), true),
+            _ => continue,
+        };
+        let label = serializer
+            .get_reference_style_info(node)
+            .map(|(_, label)| reference_key(split_reference_label(&label).1));
+        let autolink = !image
+            && label.is_none()
+            && title.is_empty()
+            && !destination.is_empty()
+            && serializer.collect_raw_text(node) == destination;
+        let kind = match () {
+            _ if image => LinkKind::Image,
+            _ if label.is_some() => LinkKind::Reference,
+            _ if autolink => LinkKind::Autolink,
+            _ => LinkKind::Inline,
+        };
+        let sourcepos = node.data.borrow().sourcepos;
+        let link = LinkRef {
+            destination: destination.clone(),
+            title,
+            kind,
+            start_line: original_line(sourcepos.start.line, inserted),
+            start_column: sourcepos.start.column,
+            end_line: original_line(sourcepos.end.line, inserted),
+            end_column: sourcepos.end.column,
+        };
+        let rewritten = match label {
+            Some(label) => definitions
+                .entry(label)
+                .or_insert_with(|| rewrite(link))
+                .clone(),
+            None => rewrite(link),
+        };
+        let Some(rewritten) = rewritten else {
+            continue;
+        };
+        // An autolink stays one if it can, with its text changed along with
+        // its destination, or becomes an inline link with the old text
+        if autolink && url::is_autolink_url(&rewritten) {
+            for child in node.children() {
+                if let NodeValue::Text(text) = &mut child.data.borrow_mut().value
+                    && **text == destination
+                {
+                    *text = rewritten.clone().into();
+                }
+            }
+        }
+        match &mut node.data.borrow_mut().value {
+            NodeValue::Link(link) | NodeValue::Image(link) => link.url = rewritten,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! The blank lines between blocks.
+//!
+//! Every block starts with [`Serializer::start_block`], which ends the line
+//! the block before it left open and writes the blank lines that separate
+//! the two, so that how many there are depends only on the kinds of the two
+//! blocks and on the container they are in, not on how the previous block
+//! was written.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use super::Serializer;
+
+/// The number of blank lines between two blocks of a document, the
+/// `previous` one and the `next` one.
+///
+/// Blocks are separated by one blank line, but for a level 2 heading, which
+/// starts a section and so gets two unless it follows another heading, and
+/// for blocks after front matter, which get none.
+pub(super) fn blank_lines_between(previous: &NodeValue, next: &NodeValue) -> usize {
+    match (previous, next) {
+        (NodeValue::FrontMatter(_), _) => 0,
+        (NodeValue::Heading(_), NodeValue::Heading(heading)) if heading.level == 2 => 1,
+        (_, NodeValue::Heading(heading)) if heading.level == 2 => 2,
+        _ => 1,
+    }
+}
+
+impl<'a> Serializer<'a> {
+    /// Start a block after the one before it in the same container: end the
+    /// line that block left open, if any, and write `blank_lines` blank
+    /// lines, but no more than `max_consecutive_blank_lines`, with the line
+    /// prefix of the container, like `>` in a block quote.
+    pub(super) fn start_block(&mut self, blank_lines: usize) {
+        if !(self.output.is_empty() || self.output.ends_with('\n')) {
+            self.output.push('\n');
+        }
+        let blank_lines = blank_lines.min(self.options.max_consecutive_blank_lines.get());
+        for _ in 0..blank_lines {
+            self.write_line("");
+        }
+    }
+
+    /// Write `node`, a block in a container, after `blank_lines` blank lines
+    /// if blocks were written before it in the container, and return whether
+    /// it wrote anything.
+    ///
+    /// A block that writes nothing, like a block quote with nothing but link
+    /// reference definitions, gets no blank lines either, so that the next
+    /// block is separated from the one before it as if it weren't there.
+    pub(super) fn serialize_block<'b>(
+        &mut self,
+        node: &'b AstNode<'b>,
+        blank_lines: Option<usize>,
+    ) -> bool {
+        let start = self.output.len();
+        if let Some(blank_lines) = blank_lines {
+            self.start_block(blank_lines);
+        }
+        let content_start = self.output.len();
+        self.serialize_node(node);
+        if self.output.len() == content_start {
+            self.output.truncate(start);
+            return false;
+        }
+        true
+    }
+
+    /// Start the `next` block of a document, after the `previous` one
+    /// written, if any; the first block of a document starts right at the
+    /// start of the output.
+    pub(super) fn start_document_block<'b>(
+        &mut self,
+        previous: Option<&'b AstNode<'b>>,
+        next: &'b AstNode<'b>,
+    ) {
+        if let Some(previous) = previous {
+            let blank_lines =
+                blank_lines_between(&previous.data.borrow().value, &next.data.borrow().value);
+            self.start_block(blank_lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use comrak::nodes::NodeHeading;
+
+    fn heading(level: u8) -> NodeValue {
+        NodeValue::Heading(NodeHeading {
+            level,
+            ..NodeHeading::default()
+        })
+    }
+
+    #[test]
+    fn test_blank_lines_between() {
+        assert_eq!(
+            blank_lines_between(&NodeValue::Paragraph, &NodeValue::ThematicBreak),
+            1
+        );
+        assert_eq!(blank_lines_between(&NodeValue::Paragraph, &heading(2)), 2);
+        assert_eq!(blank_lines_between(&NodeValue::Paragraph, &heading(3)), 1);
+        assert_eq!(blank_lines_between(&heading(1), &heading(2)), 1);
+        assert_eq!(
+            blank_lines_between(&NodeValue::FrontMatter(String::new()), &heading(2)),
+            0
+        );
+    }
+}
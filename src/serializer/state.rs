@@ -4,7 +4,8 @@ use indexmap::IndexMap;
 
 use comrak::nodes::{AstNode, ListType, NodeValue};
 
-use crate::Options;
+use super::escape;
+use crate::{Options, WrapMode};
 
 /// The current formatting skip mode.
 ///
@@ -26,6 +27,43 @@ pub enum FormatSkipMode {
     Disabled,
 }
 
+/// One level of the prefix written at the start of every output line.
+///
+/// The serializer keeps a stack of these, pushed when entering a container
+/// and popped when leaving it, so that the prefix of a line is simply the
+/// concatenation of the entries from the outermost container inwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinePrefix {
+    /// A block quote marker (`> `).
+    Quote,
+    /// A fixed number of spaces, such as the indentation of description
+    /// details.
+    Indent(usize),
+    /// The continuation indentation of a list item's content, relative to
+    /// the enclosing entries.
+    ListContinuation(String),
+}
+
+impl LinePrefix {
+    /// Append this entry's text to `output`.
+    fn push_to(&self, output: &mut String) {
+        match self {
+            LinePrefix::Quote => output.push_str("> "),
+            LinePrefix::Indent(width) => output.extend(std::iter::repeat_n(' ', *width)),
+            LinePrefix::ListContinuation(indent) => output.push_str(indent),
+        }
+    }
+
+    /// The number of columns this entry takes up.
+    fn width(&self) -> usize {
+        match self {
+            LinePrefix::Quote => 2,
+            LinePrefix::Indent(width) => *width,
+            LinePrefix::ListContinuation(indent) => indent.len(),
+        }
+    }
+}
+
 /// Formatting directives that can be embedded in HTML comments.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Directive {
@@ -43,6 +81,20 @@ pub enum Directive {
     ProperNouns(Vec<String>),
     /// Define common nouns for sentence case (case-sensitive).
     CommonNouns(Vec<String>),
+    /// Start a table of contents, which is regenerated up to the matching
+    /// `TocEnd`.
+    TocStart,
+    /// End a table of contents.
+    TocEnd,
+    /// Keep the line breaks of the paragraphs in the next block element.
+    NoWrap,
+    /// Disable the named rules (see [`crate::lint::RULES`]) from this point
+    /// until an `Enable` or an `EnableRules` naming them.
+    DisableRules(Vec<String>),
+    /// Re-enable the named rules after `DisableRules`.
+    EnableRules(Vec<String>),
+    /// Disable the named rules for the entire file.
+    DisableFileRules(Vec<String>),
 }
 
 impl Directive {
@@ -64,10 +116,29 @@ impl Directive {
             "hongdown-disable-next-section" => return Some(Directive::DisableNextSection),
             "hongdown-disable" => return Some(Directive::Disable),
             "hongdown-enable" => return Some(Directive::Enable),
+            "toc" | "hongdown-toc" => return Some(Directive::TocStart),
+            "/toc" | "/hongdown-toc" => return Some(Directive::TocEnd),
+            "hongdown-no-wrap" => return Some(Directive::NoWrap),
             _ => {}
         }
 
         // Check for directives with arguments
+        let rules = |args: &str| {
+            args.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        if let Some(args) = content.strip_prefix("hongdown-disable-file ") {
+            return Some(Directive::DisableFileRules(rules(args)));
+        }
+        if let Some(args) = content.strip_prefix("hongdown-disable ") {
+            return Some(Directive::DisableRules(rules(args)));
+        }
+        if let Some(args) = content.strip_prefix("hongdown-enable ") {
+            return Some(Directive::EnableRules(rules(args)));
+        }
+
         if let Some(args) = content.strip_prefix("hongdown-proper-nouns:") {
             let nouns = args
                 .split(',')
@@ -98,6 +169,28 @@ pub struct ReferenceLink {
     pub title: String,
 }
 
+impl ReferenceLink {
+    /// Check if this reference points to `url` with the same `title`.
+    pub fn has_target(&self, url: &str, title: &str) -> bool {
+        self.url == url && self.title == title
+    }
+}
+
+/// Normalize a reference label the way CommonMark matches labels:
+/// case-insensitively, with runs of whitespace collapsed to a single space.
+///
+/// This is also how labels are written, in definitions and in full
+/// references alike, so that a definition and all the references to it
+/// spell its label the same way.
+pub fn reference_key(label: &str) -> String {
+    label
+        .replace('\x00', " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
 /// A footnote definition: name -> content
 #[derive(Debug, Clone)]
 pub struct FootnoteDefinition {
@@ -106,7 +199,8 @@ pub struct FootnoteDefinition {
     /// Line number where the footnote was referenced (1-indexed)
     pub reference_line: usize,
     /// Whether the footnote contains block elements (code blocks, etc.)
-    /// When true, content contains pre-serialized block content with proper indentation.
+    /// When true, content contains the whole pre-serialized definition,
+    /// including its `[^name]: ` prefix and continuation indentation.
     pub has_blocks: bool,
 }
 
@@ -180,9 +274,11 @@ impl FootnoteSet {
     }
 
     /// Add a reference link found within footnote content.
-    pub fn add_reference(&mut self, label: String, reference: ReferenceLink) {
+    pub fn add_reference(&mut self, reference: ReferenceLink) {
+        let line = self.current_reference_line;
         self.pending_references
-            .insert(label, (reference, self.current_reference_line));
+            .entry(reference_key(&reference.label))
+            .or_insert((reference, line));
     }
 }
 
@@ -193,6 +289,9 @@ pub struct Warning {
     pub line: usize,
     /// Warning message
     pub message: String,
+    /// Whether this reports a fix made because of [`crate::Options::fix`],
+    /// rather than a problem
+    pub fixed: bool,
 }
 
 /// Safely slice a string, ensuring the indices are valid UTF-8 boundaries.
@@ -224,6 +323,18 @@ fn safe_str_slice(s: &str, start: usize, end: usize) -> &str {
     &s[safe_start..safe_end]
 }
 
+/// Strip the block quote markers and indentation in the first `indent`
+/// columns of `line`.  A lazy continuation line, which lacks them, is kept
+/// as it is.
+fn strip_container_markers(line: &str, indent: usize) -> &str {
+    let end = line
+        .char_indices()
+        .take(indent)
+        .find(|(_, c)| !matches!(c, ' ' | '\t' | '>'))
+        .map_or(line.len().min(indent), |(i, _)| i);
+    &line[end..]
+}
+
 /// Code formatter callback type for WASM builds.
 ///
 /// The callback receives the language identifier and code content,
@@ -231,6 +342,17 @@ fn safe_str_slice(s: &str, start: usize, end: usize) -> &str {
 #[cfg(feature = "wasm")]
 pub type CodeFormatterCallback = Option<Box<dyn Fn(&str, &str) -> Option<String>>>;
 
+/// The delimiter for emphasis (`strong` false) or strong emphasis written
+/// with `ch`, which is `*` or `_`.
+pub fn emphasis_delimiter(ch: char, strong: bool) -> &'static str {
+    match (ch, strong) {
+        ('_', true) => "__",
+        ('_', false) => "_",
+        (_, true) => "**",
+        _ => "*",
+    }
+}
+
 /// The main serializer state for converting comrak AST to formatted Markdown.
 pub struct Serializer<'a> {
     pub output: String,
@@ -245,19 +367,32 @@ pub struct Serializer<'a> {
     pub list_tight: bool,
     /// Whether we're inside a block quote
     pub in_block_quote: bool,
+    /// The prefix written at the start of each line, innermost last
+    pub line_prefix: Vec<LinePrefix>,
     /// Accumulated blockquote prefix for nested blockquotes (e.g., "> " or "> > ")
     pub blockquote_prefix: String,
     /// Reference links collected for the current section
-    /// Key: label, Value: ReferenceLink (insertion order preserved)
+    /// Key: normalized label, Value: ReferenceLink (insertion order preserved)
     pub pending_references: IndexMap<String, ReferenceLink>,
-    /// Reference labels that have already been emitted (to avoid duplicates)
-    pub emitted_references: std::collections::HashSet<String>,
+    /// Reference definitions that have already been emitted (to avoid
+    /// duplicates), keyed by normalized label
+    pub emitted_references: std::collections::HashMap<String, ReferenceLink>,
+    /// Definitions found in the source but never used, kept when
+    /// `link_remove_unused_definitions` is off
+    pub unused_definitions: Vec<ReferenceLink>,
     /// Footnote definitions and their reference tracking
     pub footnotes: FootnoteSet,
     /// Current list nesting depth (0 = not in list, 1 = top-level, 2+ = nested)
     pub list_depth: usize,
     /// Current formatting skip mode
     pub skip_mode: FormatSkipMode,
+    /// Whether the block being serialized follows a `hongdown-no-wrap`
+    /// directive, so that its paragraphs keep their line breaks
+    pub no_wrap: bool,
+    /// Line ranges where the `line-length` rule is disabled by a directive,
+    /// whose paragraphs keep their line breaks as if they followed
+    /// `hongdown-no-wrap`
+    pub no_wrap_ranges: Vec<(usize, usize)>,
     /// Whether we're inside a description details block (for indentation)
     pub in_description_details: bool,
     /// Whether we're serializing the first list inside description details on the same line as `:`.
@@ -267,11 +402,26 @@ pub struct Serializer<'a> {
     pub warnings: Vec<Warning>,
     /// Maximum number of items in the current ordered list (for padding calculation)
     pub ordered_list_max_items: usize,
+    /// Whether the current list uses the alternate marker, because it follows
+    /// another list of the same kind (see `ListSeparation::AlternateMarker`)
+    pub list_alternate_marker: bool,
     /// Whether the original source ends with a newline
     pub source_ends_with_newline: bool,
     /// Current indentation prefix for list item content (e.g., "     " for ` 1.  `)
     /// Used by blockquotes and other block elements inside list items.
     pub list_item_indent: String,
+    /// The indentation of the wrapped lines of the current list item's
+    /// paragraphs, past the innermost enclosing container, if it differs
+    /// from the content column (see `ListContinuation::Fixed`)
+    pub list_hang: Option<usize>,
+    /// Where the output was right after the marker of the current list item
+    /// was written, until the first block of the item is started on the
+    /// marker's line
+    pub list_marker_end: Option<usize>,
+    /// How many columns the markers of the enclosing ordered list items are
+    /// wider than `ordered_list_indent_width` in total (e.g., 1 for `100. `).
+    /// Nested lists are indented by this much more than usual.
+    pub list_marker_overflow: usize,
     /// Indentation prefix for content inside a blockquote that's nested inside a list.
     /// This is the outer list's indent that should appear before each `>` in the blockquote.
     pub blockquote_outer_indent: String,
@@ -282,9 +432,18 @@ pub struct Serializer<'a> {
     pub directive_proper_nouns: Vec<String>,
     /// Common nouns defined via directives for sentence case (merged with config)
     pub directive_common_nouns: Vec<String>,
+    /// The preferred delimiter character of the emphasis whose content is
+    /// being serialized, so that emphasis nested in it can prefer the other
+    pub enclosing_emphasis: Option<char>,
+    /// Whether emphasis is written with the delimiters of the source, as
+    /// for a run of nested emphasis whose own delimiters don't fit
+    pub keep_source_emphasis: bool,
     /// Code formatter callback for WASM builds.
     #[cfg(feature = "wasm")]
     pub code_formatter_callback: CodeFormatterCallback,
+    /// Handlers that get the first chance to write each block.
+    #[cfg(feature = "plugins")]
+    pub block_handlers: &'a [crate::plugins::BlockHandler],
 }
 
 impl<'a> Serializer<'a> {
@@ -301,24 +460,36 @@ impl<'a> Serializer<'a> {
             list_type: None,
             list_tight: true,
             in_block_quote: false,
+            line_prefix: Vec::new(),
             blockquote_prefix: String::new(),
             pending_references: IndexMap::new(),
-            emitted_references: std::collections::HashSet::new(),
+            emitted_references: std::collections::HashMap::new(),
+            unused_definitions: Vec::new(),
             footnotes: FootnoteSet::new(),
+            enclosing_emphasis: None,
+            keep_source_emphasis: false,
             list_depth: 0,
             skip_mode: FormatSkipMode::None,
+            no_wrap: false,
+            no_wrap_ranges: Vec::new(),
             in_description_details: false,
             description_details_first_list: false,
             warnings: Vec::new(),
             ordered_list_max_items: 0,
+            list_alternate_marker: false,
             source_ends_with_newline,
             list_item_indent: String::new(),
+            list_hang: None,
+            list_marker_end: None,
+            list_marker_overflow: 0,
             blockquote_outer_indent: String::new(),
             blockquote_entry_list_depth: 0,
             directive_proper_nouns: Vec::new(),
             directive_common_nouns: Vec::new(),
             #[cfg(feature = "wasm")]
             code_formatter_callback: None,
+            #[cfg(feature = "plugins")]
+            block_handlers: &[],
         }
     }
 
@@ -338,29 +509,170 @@ impl<'a> Serializer<'a> {
             list_type: None,
             list_tight: true,
             in_block_quote: false,
+            line_prefix: Vec::new(),
             blockquote_prefix: String::new(),
             pending_references: IndexMap::new(),
-            emitted_references: std::collections::HashSet::new(),
+            emitted_references: std::collections::HashMap::new(),
+            unused_definitions: Vec::new(),
             footnotes: FootnoteSet::new(),
+            enclosing_emphasis: None,
+            keep_source_emphasis: false,
             list_depth: 0,
             skip_mode: FormatSkipMode::None,
+            no_wrap: false,
+            no_wrap_ranges: Vec::new(),
             in_description_details: false,
             description_details_first_list: false,
             warnings: Vec::new(),
             ordered_list_max_items: 0,
+            list_alternate_marker: false,
             source_ends_with_newline,
             list_item_indent: String::new(),
+            list_hang: None,
+            list_marker_end: None,
+            list_marker_overflow: 0,
             blockquote_outer_indent: String::new(),
             blockquote_entry_list_depth: 0,
             directive_proper_nouns: Vec::new(),
             directive_common_nouns: Vec::new(),
             code_formatter_callback: callback,
+            #[cfg(feature = "plugins")]
+            block_handlers: &[],
+        }
+    }
+
+    /// The width at which paragraphs should be wrapped.
+    ///
+    /// Returns `usize::MAX` when wrapping is disabled, for the whole document
+    /// or by a `hongdown-no-wrap` directive, so that every line fits and the
+    /// soft breaks collected for the paragraph decide where lines end.
+    pub fn wrap_width(&self) -> usize {
+        if self.no_wrap {
+            return usize::MAX;
+        }
+        match self.options.wrap {
+            WrapMode::Width => self.options.line_width.get(),
+            WrapMode::None | WrapMode::Preserve | WrapMode::Sentence => usize::MAX,
+        }
+    }
+
+    /// The prefix for the start of a line in the current context.
+    pub fn line_prefix(&self) -> String {
+        let mut prefix = String::new();
+        for entry in &self.line_prefix {
+            entry.push_to(&mut prefix);
+        }
+        prefix
+    }
+
+    /// The line prefix up to and including the innermost entry that is not
+    /// a list continuation, such as a block quote marker or the indentation
+    /// of a footnote definition.
+    ///
+    /// List markers are indented by list depth, which starts over inside
+    /// each such container, so their lines start with this instead of the
+    /// full line prefix.
+    pub fn container_prefix(&self) -> String {
+        let container_depth = self
+            .line_prefix
+            .iter()
+            .rposition(|entry| !matches!(entry, LinePrefix::ListContinuation(_)))
+            .map_or(0, |i| i + 1);
+        let mut prefix = String::new();
+        for entry in &self.line_prefix[..container_depth] {
+            entry.push_to(&mut prefix);
+        }
+        prefix
+    }
+
+    /// Start a new line by writing the current line prefix.
+    ///
+    /// Does nothing if the output is not at the start of a line, since the
+    /// line has then already been started (for example, by a list marker).
+    pub fn begin_line(&mut self) {
+        if self.list_marker_end == Some(self.output.len()) {
+            let mut prefix = String::new();
+            for entry in &self.line_prefix {
+                entry.push_to(&mut prefix);
+            }
+            self.push_line_start(&prefix);
+            return;
+        }
+        if !(self.output.is_empty() || self.output.ends_with('\n')) {
+            return;
+        }
+        for entry in &self.line_prefix {
+            entry.push_to(&mut self.output);
+        }
+    }
+
+    /// Write `text`, which starts a line with its prefix and indentation.
+    ///
+    /// Right after the marker of a list item, where the first block of the
+    /// item starts, the marker takes the place of the start of the prefix,
+    /// so only the part past the marker is written, like the `> ` of a block
+    /// quote in the item.
+    pub fn push_line_start(&mut self, text: &str) {
+        if self.list_marker_end.take() == Some(self.output.len()) {
+            let line = self.output.rfind('\n').map_or(0, |i| i + 1);
+            let width = self.output.len() - line;
+            let rest = text.get(width..).unwrap_or("");
+            self.output.push_str(rest.trim_start_matches(' '));
+        } else {
+            self.output.push_str(text);
+        }
+    }
+
+    /// Write a complete line with the current line prefix.
+    ///
+    /// An empty line gets the prefix without its trailing whitespace, so
+    /// that a blank line in a block quote is written as `>`.
+    pub fn write_line(&mut self, content: &str) {
+        let start = self.output.len();
+        self.begin_line();
+        if content.is_empty() {
+            let prefix_len = self.output[start..].trim_end().len();
+            self.output.truncate(start + prefix_len);
+        } else {
+            self.output.push_str(content);
         }
+        self.output.push('\n');
+    }
+
+    /// The list continuation entry that indents content to `indent` columns
+    /// past the innermost enclosing container, as in [`container_prefix`].
+    ///
+    /// [`container_prefix`]: Self::container_prefix
+    pub fn list_continuation(&self, indent: usize) -> LinePrefix {
+        let enclosing: usize = self
+            .line_prefix
+            .iter()
+            .rev()
+            .take_while(|entry| matches!(entry, LinePrefix::ListContinuation(_)))
+            .map(LinePrefix::width)
+            .sum();
+        LinePrefix::ListContinuation(" ".repeat(indent.saturating_sub(enclosing)))
     }
 
     /// Add a warning.
     pub fn add_warning(&mut self, line: usize, message: String) {
-        self.warnings.push(Warning { line, message });
+        self.warnings.push(Warning {
+            line,
+            message,
+            fixed: false,
+        });
+    }
+
+    /// Add a warning, unless the same one was already added, as it is when a
+    /// node is serialized more than once.
+    pub fn add_warning_once(&mut self, line: usize, message: String) {
+        if !self
+            .warnings
+            .iter()
+            .any(|w| w.line == line && w.message == message)
+        {
+            self.add_warning(line, message);
+        }
     }
 
     /// Extract original source text for a node using its sourcepos.
@@ -413,6 +725,48 @@ impl<'a> Serializer<'a> {
         Some(result)
     }
 
+    /// Extract the original source text of a text node.
+    ///
+    /// comrak starts the source position of a text node that begins a line
+    /// with an escaped character after the backslash, so the backslash is
+    /// put back in front of the source.
+    pub fn extract_text_source<'b>(&self, node: &'b AstNode<'b>) -> Option<String> {
+        let source = self.extract_source(node)?;
+        let start = node.data.borrow().sourcepos.start;
+        let line = self.source_lines[start.line - 1].as_bytes();
+        let before = &line[..start.column.saturating_sub(1).min(line.len())];
+        let backslashes = before.iter().rev().take_while(|&&b| b == b'\\').count();
+        let starts_with_punctuation = source.starts_with(|c: char| c.is_ascii_punctuation());
+        if backslashes % 2 == 1 && starts_with_punctuation {
+            Some(format!("\\{}", source))
+        } else {
+            Some(source)
+        }
+    }
+
+    /// The source of a block, without the block quote markers and list item
+    /// indentation in front of the lines after its first.
+    pub fn block_source<'b>(&self, node: &'b AstNode<'b>) -> Option<String> {
+        let sourcepos = node.data.borrow().sourcepos;
+        let (start, end) = (sourcepos.start, sourcepos.end);
+        if start.line == 0 || end.line < start.line || end.line > self.source_lines.len() {
+            return None;
+        }
+        let indent = start.column.saturating_sub(1);
+        let lines: Vec<&str> = self.source_lines[start.line - 1..end.line]
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                if i == 0 {
+                    line.get(indent..).unwrap_or_default()
+                } else {
+                    strip_container_markers(line, indent)
+                }
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
     /// Extract original source text from a given line to the end of the file.
     /// Line numbers are 1-indexed.
     pub fn extract_source_from_line(&self, start_line: usize) -> Option<String> {
@@ -445,56 +799,121 @@ impl<'a> Serializer<'a> {
     /// Add a reference link to the pending references.
     /// If collecting_footnote_content is true, adds to pending_footnote_references instead,
     /// along with the current footnote's reference line for proper flush timing.
+    ///
+    /// References whose labels differ only in case or whitespace share one
+    /// definition.
     pub fn add_reference(&mut self, label: String, url: String, title: String) {
-        let reference = ReferenceLink {
-            label: label.clone(),
-            url,
-            title,
-        };
+        let reference = ReferenceLink { label, url, title };
         if self.footnotes.collecting_content {
-            self.footnotes.add_reference(label, reference);
+            self.footnotes.add_reference(reference);
         } else {
-            self.pending_references.insert(label, reference);
+            self.pending_references
+                .entry(reference_key(&reference.label))
+                .or_insert(reference);
         }
     }
 
+    /// Check if a definition for `label` already exists, pending or emitted,
+    /// with a different destination or title.
+    pub fn reference_conflicts(&self, label: &str, url: &str, title: &str) -> bool {
+        let key = reference_key(label);
+        let existing = self
+            .pending_references
+            .get(&key)
+            .or_else(|| self.footnotes.pending_references.get(&key).map(|(r, _)| r))
+            .or_else(|| self.emitted_references.get(&key));
+        existing.is_some_and(|r| !r.has_target(url, title))
+    }
+
     /// Check if a URL is external (starts with http:// or https://).
     pub fn is_external_url(url: &str) -> bool {
         url.starts_with("http://") || url.starts_with("https://")
     }
 
-    /// Get the emphasis delimiter character.
+    /// Whether an inline link to `url` is turned into a reference link: it
+    /// is if the URL is external, unless `link_external_references` is off.
+    pub fn links_by_reference(&self, url: &str) -> bool {
+        self.options.link_external_references && Self::is_external_url(url)
+    }
+
+    /// Get the preferred delimiter character for an emphasis or strong
+    /// emphasis node.
     /// Uses '_' if the content contains '*' (to avoid escaping).
     /// Otherwise, preserves the original delimiter from source, defaulting to '*'.
-    pub fn get_emphasis_delimiter<'b>(&self, node: &'b AstNode<'b>) -> char {
+    pub fn preferred_emphasis_delimiter<'b>(&self, node: &'b AstNode<'b>) -> char {
         // If content contains '*', use '_' to avoid escaping
         if self.node_text_contains_char(node, '*') {
             return '_';
         }
         // Otherwise, preserve original delimiter or default to '*'
+        let underscores = match node.data.borrow().value {
+            NodeValue::Strong => "__",
+            _ => "_",
+        };
         if let Some(source) = self.extract_source(node)
-            && source.starts_with('_')
+            && source.starts_with(underscores)
         {
             return '_';
         }
         '*'
     }
 
-    /// Get the strong emphasis delimiter string.
-    /// Uses "__" if the content contains '*' (to avoid escaping).
-    /// Otherwise, preserves the original delimiter from source, defaulting to "**".
-    pub fn get_strong_delimiter<'b>(&self, node: &'b AstNode<'b>) -> &'static str {
-        // If content contains '*', use '__' to avoid escaping
-        if self.node_text_contains_char(node, '*') {
-            return "__";
-        }
-        // Otherwise, preserve original delimiter or default to '**'
-        if let Some(source) = self.extract_source(node)
-            && source.starts_with("__")
-        {
-            return "__";
+    /// Return the delimiters for emphasis (`strong` false) or strong emphasis
+    /// around `inner` that fit, `preferred` first.
+    ///
+    /// A delimiter has to be read back as emphasis where it is written:
+    /// `before` and `after` are the characters written just around the
+    /// emphasis and `inner` its serialized content.
+    pub fn fitting_emphasis_delimiters(
+        preferred: char,
+        strong: bool,
+        before: Option<char>,
+        inner: &str,
+        after: Option<char>,
+    ) -> impl Iterator<Item = &'static str> {
+        let other = if preferred == '_' { '*' } else { '_' };
+        [preferred, other]
+            .into_iter()
+            .map(move |c| emphasis_delimiter(c, strong))
+            .filter(move |delimiter| {
+                escape::emphasis_delimiter_fits(delimiter, before, inner, after)
+            })
+    }
+
+    /// The first character written after an inline node, or `None` at the
+    /// end of its block.  Only the kind of character matters for flanking,
+    /// so nodes other than text stand in with their opening character.
+    pub fn char_after_inline<'b>(&self, node: &'b AstNode<'b>) -> Option<char> {
+        let Some(next) = node.next_sibling() else {
+            // The closing delimiter of an enclosing inline comes next; an
+            // enclosing emphasis picks its own to fit after this one
+            return match &node.parent()?.data.borrow().value {
+                NodeValue::Emph | NodeValue::Strong | NodeValue::Link(_) | NodeValue::Image(_) => {
+                    Some(']')
+                }
+                _ => None,
+            };
+        };
+        match &next.data.borrow().value {
+            NodeValue::Text(text) => {
+                // Escapes and entities in the source are kept
+                if let Some(source) = self.extract_source(next)
+                    && source.starts_with(['\\', '&'])
+                {
+                    return source.chars().next();
+                }
+                // An escaped character is written after a backslash
+                match text.bytes().next() {
+                    Some(byte) if escape::needs_escape(byte) => Some('\\'),
+                    _ => text.chars().next(),
+                }
+            }
+            NodeValue::SoftBreak | NodeValue::LineBreak => Some(' '),
+            NodeValue::Code(_) => Some('`'),
+            NodeValue::HtmlInline(_) => Some('<'),
+            // Emphasis that follows picks a delimiter that fits after this one
+            _ => Some('['),
         }
-        "**"
     }
 
     /// Check if any text node within the given node contains the specified character.
@@ -514,7 +933,64 @@ impl<'a> Serializer<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::safe_str_slice;
+    use super::{LinePrefix, Serializer, safe_str_slice};
+    use crate::Options;
+
+    #[test]
+    fn test_line_prefix_composition() {
+        let options = Options::default();
+        let mut serializer = Serializer::new(&options, Vec::new(), false);
+        assert_eq!(serializer.line_prefix(), "");
+
+        // A quote inside a list item inside a quote
+        serializer.line_prefix.push(LinePrefix::Quote);
+        let continuation = serializer.list_continuation(4);
+        serializer.line_prefix.push(continuation);
+        serializer.line_prefix.push(LinePrefix::Quote);
+        assert_eq!(serializer.line_prefix(), ">     > ");
+
+        serializer.write_line("text");
+        serializer.write_line("");
+        serializer.line_prefix.pop();
+        serializer.write_line("");
+        assert_eq!(serializer.output, ">     > text\n>     >\n>\n");
+    }
+
+    #[test]
+    fn test_list_continuation_is_relative_to_enclosing_items() {
+        let options = Options::default();
+        let mut serializer = Serializer::new(&options, Vec::new(), false);
+        let outer = serializer.list_continuation(4);
+        assert_eq!(outer, LinePrefix::ListContinuation("    ".to_string()));
+        serializer.line_prefix.push(outer);
+        // A nested item whose content starts at column 8 adds 4 more columns
+        let inner = serializer.list_continuation(8);
+        assert_eq!(inner, LinePrefix::ListContinuation("    ".to_string()));
+        serializer.line_prefix.push(inner);
+        assert_eq!(serializer.line_prefix(), "        ");
+        // Inside a quote, indentation starts over from the quote marker
+        serializer.line_prefix.push(LinePrefix::Quote);
+        let quoted = serializer.list_continuation(4);
+        serializer.line_prefix.push(quoted);
+        assert_eq!(serializer.line_prefix(), "        >     ");
+    }
+
+    #[test]
+    fn test_list_continuation_inside_indent() {
+        let options = Options::default();
+        let mut serializer = Serializer::new(&options, Vec::new(), false);
+        serializer.line_prefix.push(LinePrefix::Indent(4));
+        assert_eq!(serializer.container_prefix(), "    ");
+        // Indentation starts over inside the indent, as inside a quote
+        let continuation = serializer.list_continuation(4);
+        assert_eq!(
+            continuation,
+            LinePrefix::ListContinuation("    ".to_string())
+        );
+        serializer.line_prefix.push(continuation);
+        assert_eq!(serializer.container_prefix(), "    ");
+        assert_eq!(serializer.line_prefix(), "        ");
+    }
 
     #[test]
     fn test_safe_str_slice_ascii() {
@@ -1,13 +1,61 @@
 //! Table serialization logic.
 
-use comrak::nodes::{AstNode, NodeTable, TableAlignment};
+use std::borrow::Cow;
+
+use comrak::nodes::{AstNode, NodeTable, NodeValue, TableAlignment};
 use unicode_width::UnicodeWidthStr;
 
 use super::Serializer;
 use super::escape;
+use super::limits;
+use super::whitespace;
+use super::wrap;
+use crate::{CellWrap, TableFallback, TableFormat, TablePadding, TableSeparator, TableWidthGrowth};
 
 impl<'a> Serializer<'a> {
+    /// Serialize a table in the `commonmark` dialect, which has no tables,
+    /// as an HTML `<table>` block or as the table's source text, as the
+    /// `commonmark_tables` option says.  The text fallback writes a pipe
+    /// table when there is no source to take it from.
+    pub(super) fn serialize_table_fallback<'b>(
+        &mut self,
+        node: &'b AstNode<'b>,
+        table: &NodeTable,
+    ) {
+        if self.options.commonmark_tables == TableFallback::Text {
+            match self.block_source(node) {
+                Some(source) => {
+                    for line in source.lines() {
+                        self.write_line(line.trim_end());
+                    }
+                }
+                None => self.serialize_table(node, table),
+            }
+            return;
+        }
+        let mut options = crate::base_comrak_options();
+        options.render.r#unsafe = true;
+        let mut html = String::new();
+        if comrak::format_html(node, &options, &mut html).is_err() {
+            self.serialize_table(node, table);
+            return;
+        }
+        for line in html.lines() {
+            self.write_line(line);
+        }
+    }
+
     pub(super) fn serialize_table<'b>(&mut self, node: &'b AstNode<'b>, table: &NodeTable) {
+        if self.options.table_format == TableFormat::Preserve
+            && let Some(source) = self.block_source(node)
+        {
+            // Copied as it is, but with the line prefix of where it is now
+            for line in source.lines() {
+                self.write_line(&whitespace::keep_trailing_whitespace(line));
+            }
+            self.keep_table_references(node);
+            return;
+        }
         let alignments = &table.alignments;
         let expected_cols = alignments.len();
         // Collect all rows and cells first to calculate column widths
@@ -23,74 +71,96 @@ impl<'a> Serializer<'a> {
         // comrak normalizes column counts, so we need to check the source directly
         self.check_table_source_columns(node, expected_cols, table_line);
 
-        // Collect cell contents (with full inline formatting) and calculate max widths
-        let mut all_cells: Vec<Vec<String>> = Vec::new();
+        // Collect cell contents (with full inline formatting) and calculate max widths.
+        // All cell contents are stored back to back in a single buffer, and each
+        // cell keeps its byte range and display width, so that even huge tables
+        // need only a handful of allocations.
+        let mut cells = TableCells::default();
         let mut col_widths: Vec<usize> = vec![0; alignments.len()];
+        let mut scratch = String::new();
 
         for row in &rows {
-            let mut row_cells: Vec<String> = Vec::new();
-
             for (i, cell) in row.children().enumerate() {
                 // Use collect_inline_content to preserve links and formatting
-                let mut content = String::new();
-                self.collect_inline_content(cell, &mut content);
+                scratch.clear();
+                self.collect_inline_content(cell, &mut scratch);
                 // Escape pipe characters in table cells to prevent cell boundary confusion
-                let content = escape::escape_table_cell(&content);
+                let line = cell.data.borrow().sourcepos.start.line;
+                let (content, column_width) =
+                    self.fit_table_cell(escape::escape_table_cell(&scratch), line);
+                cells.push(&content);
                 if i < col_widths.len() {
-                    col_widths[i] = col_widths[i].max(content.width());
+                    col_widths[i] = col_widths[i].max(column_width);
                 }
-                row_cells.push(content);
             }
-
-            all_cells.push(row_cells);
+            cells.end_row();
         }
 
-        // Ensure minimum column width for alignment markers
-        for width in &mut col_widths {
-            *width = (*width).max(3);
+        // Ensure minimum column width for alignment markers; compact cells
+        // aren't padded at all, and get the shortest delimiters.  A table
+        // whose padded rows would take too much memory is written compact too
+        let mut compact = self.options.table_padding == TablePadding::Compact;
+        let row_width: usize = col_widths.iter().map(|width| width.max(&3) + 3).sum();
+        if !compact && rows.len().saturating_mul(row_width + 1) > limits::MAX_ALIGNED_TABLE_SIZE {
+            self.add_warning(
+                table_line,
+                "table is too large to align, so its cells are not padded".to_string(),
+            );
+            compact = true;
+        }
+        // Sticky columns are rounded up, and kept at least as wide as the
+        // delimiter row says they were, so that small edits don't re-pad them
+        let sticky = self.options.table_width_growth == TableWidthGrowth::Sticky;
+        let previous_widths = if sticky && !compact {
+            self.table_delimiter_widths(node)
+        } else {
+            Vec::new()
+        };
+        let step = self.options.table_width_step.get();
+        for (i, width) in col_widths.iter_mut().enumerate() {
+            *width = if compact {
+                3
+            } else if sticky {
+                let previous = previous_widths.get(i).copied().unwrap_or(0);
+                (*width).max(3).next_multiple_of(step).max(previous)
+            } else {
+                (*width).max(3)
+            };
         }
 
         // Output header row
-        if let Some(header_cells) = all_cells.first() {
-            if self.in_block_quote {
-                self.output.push_str("> ");
-            }
-            self.output.push('|');
-            for (i, cell) in header_cells.iter().enumerate() {
-                self.output.push(' ');
-                let width = col_widths.get(i).copied().unwrap_or(3);
-                let alignment = alignments.get(i).copied().unwrap_or(TableAlignment::None);
-                let formatted = format_cell_aligned(cell, width, alignment);
-                self.output.push_str(&formatted);
-                self.output.push_str(" |");
-            }
-            self.output.push('\n');
+        if let Some(header_cells) = cells.rows().next() {
+            self.write_table_row(header_cells, &col_widths, alignments, compact);
         }
 
-        // Output separator row with alignment
-        if self.in_block_quote {
-            self.output.push_str("> ");
-        }
+        // Output separator row with alignment; minimal delimiters are the
+        // shortest ones GFM allows, three characters, even for `:-:`
+        let minimal = self.options.table_separator == TableSeparator::Minimal;
+        self.begin_line();
         self.output.push('|');
         for (i, alignment) in alignments.iter().enumerate() {
             self.output.push(' ');
-            let width = col_widths.get(i).copied().unwrap_or(3);
+            let width = match col_widths.get(i) {
+                _ if minimal => 3,
+                Some(&width) => width,
+                None => 3,
+            };
             match alignment {
                 TableAlignment::Left => {
                     self.output.push(':');
-                    self.output.push_str(&"-".repeat(width - 1));
+                    push_repeated(&mut self.output, '-', width - 1);
                 }
                 TableAlignment::Right => {
-                    self.output.push_str(&"-".repeat(width - 1));
+                    push_repeated(&mut self.output, '-', width - 1);
                     self.output.push(':');
                 }
                 TableAlignment::Center => {
                     self.output.push(':');
-                    self.output.push_str(&"-".repeat(width - 2));
+                    push_repeated(&mut self.output, '-', width - 2);
                     self.output.push(':');
                 }
                 TableAlignment::None => {
-                    self.output.push_str(&"-".repeat(width));
+                    push_repeated(&mut self.output, '-', width);
                 }
             }
             self.output.push_str(" |");
@@ -98,21 +168,115 @@ impl<'a> Serializer<'a> {
         self.output.push('\n');
 
         // Output data rows (skip header)
-        for row_cells in all_cells.iter().skip(1) {
-            if self.in_block_quote {
-                self.output.push_str("> ");
+        for row_cells in cells.rows().skip(1) {
+            self.write_table_row(row_cells, &col_widths, alignments, compact);
+        }
+    }
+
+    /// The widths of the delimiters of the delimiter row of the table `node`
+    /// in the source, which are the widths of its columns if it was
+    /// formatted before.
+    fn table_delimiter_widths<'b>(&self, node: &'b AstNode<'b>) -> Vec<usize> {
+        let Some(source) = self.block_source(node) else {
+            return Vec::new();
+        };
+        let Some(row) = source.lines().nth(1) else {
+            return Vec::new();
+        };
+        let row = row.trim();
+        let row = row.strip_prefix('|').unwrap_or(row);
+        let row = row.strip_suffix('|').unwrap_or(row);
+        row.split('|').map(|cell| cell.trim().len()).collect()
+    }
+
+    /// Keep the definitions of the reference links and images in the table
+    /// `node`, which is copied from the source, since they are otherwise
+    /// only kept when the links are written.
+    fn keep_table_references<'b>(&mut self, node: &'b AstNode<'b>) {
+        for descendant in node.descendants() {
+            let (url, title) = match &descendant.data.borrow().value {
+                NodeValue::Link(link) | NodeValue::Image(link) => {
+                    (self.link_url(&link.url).into_owned(), link.title.clone())
+                }
+                _ => continue,
+            };
+            if let Some((_, label)) = self.get_reference_style_info(descendant) {
+                self.add_reference(label, url, title);
             }
-            self.output.push('|');
-            for (i, cell) in row_cells.iter().enumerate() {
-                self.output.push(' ');
-                let width = col_widths.get(i).copied().unwrap_or(3);
-                let alignment = alignments.get(i).copied().unwrap_or(TableAlignment::None);
-                let formatted = format_cell_aligned(cell, width, alignment);
-                self.output.push_str(&formatted);
-                self.output.push_str(" |");
+        }
+    }
+
+    /// Write one row of a table, padding each cell to its column's width
+    /// unless it is `compact`.
+    fn write_table_row<'c>(
+        &mut self,
+        row_cells: impl Iterator<Item = (&'c str, usize)>,
+        col_widths: &[usize],
+        alignments: &[TableAlignment],
+        compact: bool,
+    ) {
+        self.begin_line();
+        self.output.push('|');
+        for (i, (content, content_width)) in row_cells.enumerate() {
+            self.output.push(' ');
+            let width = match col_widths.get(i) {
+                _ if compact => 0,
+                Some(&width) => width,
+                None => 3,
+            };
+            let alignment = alignments.get(i).copied().unwrap_or(TableAlignment::None);
+            push_cell_aligned(&mut self.output, content, content_width, width, alignment);
+            self.output.push_str(" |");
+        }
+        self.output.push('\n');
+    }
+
+    /// Fit the text of a table cell, `content`, to `table_max_column_width`,
+    /// if there is one, returning it and the width its column needs.
+    ///
+    /// With `CellWrap::Br`, a line of the text wider than the maximum is
+    /// broken with `<br>` between words, as paragraphs are wrapped, and the
+    /// column only needs to be as wide as the widest line.  A warning at
+    /// `line` is reported for a cell with a line that is still wider.
+    fn fit_table_cell<'c>(&mut self, content: Cow<'c, str>, line: usize) -> (Cow<'c, str>, usize) {
+        let Some(max_width) = self.options.table_max_column_width else {
+            let width = content.width();
+            return (content, width);
+        };
+        let content = match self.options.table_wrap_cells {
+            CellWrap::Br if content.width() > max_width => {
+                let lines: Vec<_> = content
+                    .split("<br>")
+                    .map(|text| {
+                        if text.width() > max_width {
+                            Cow::Owned(
+                                wrap::wrap_single_segment(text, "", "", max_width)
+                                    .replace('\n', "<br>"),
+                            )
+                        } else {
+                            Cow::Borrowed(text)
+                        }
+                    })
+                    .collect();
+                Cow::Owned(lines.join("<br>"))
             }
-            self.output.push('\n');
+            _ => content,
+        };
+        let width = match self.options.table_wrap_cells {
+            CellWrap::Br => content.split("<br>").map(|text| text.width()).max(),
+            CellWrap::None => Some(content.width()),
+        }
+        .unwrap_or(0);
+        if width > max_width {
+            self.add_warning(
+                line,
+                format!(
+                    "table cell is {} columns wide, wider than the maximum of {}",
+                    width, max_width
+                ),
+            );
         }
+        (content, width)
     }
 
     pub(super) fn serialize_table_row<'b>(&mut self, _node: &'b AstNode<'b>, _is_header: bool) {
@@ -183,30 +347,78 @@ impl<'a> Serializer<'a> {
     }
 }
 
-/// Format a cell's content with alignment, accounting for display width.
+/// The cells of a table, stored in a single buffer.
+#[derive(Default)]
+struct TableCells {
+    /// The contents of all cells, back to back.
+    text: String,
+    /// The end offset in `text` and the display width of each cell.
+    cells: Vec<(usize, usize)>,
+    /// The end index in `cells` of each row.
+    row_ends: Vec<usize>,
+}
+
+impl TableCells {
+    /// Append a cell to the current row, returning its display width.
+    fn push(&mut self, content: &str) -> usize {
+        let width = content.width();
+        self.text.push_str(content);
+        self.cells.push((self.text.len(), width));
+        width
+    }
+
+    /// End the current row.
+    fn end_row(&mut self) {
+        self.row_ends.push(self.cells.len());
+    }
+
+    /// Iterate over the rows, each yielding its cells' contents and widths.
+    fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = (&str, usize)>> {
+        self.row_ends.iter().enumerate().map(move |(r, &end)| {
+            let start = if r == 0 { 0 } else { self.row_ends[r - 1] };
+            (start..end).map(move |c| {
+                let text_start = if c == 0 { 0 } else { self.cells[c - 1].0 };
+                let (text_end, width) = self.cells[c];
+                (&self.text[text_start..text_end], width)
+            })
+        })
+    }
+}
+
+/// Append `count` copies of `ch` to `output`.
+fn push_repeated(output: &mut String, ch: char, count: usize) {
+    output.extend(std::iter::repeat_n(ch, count));
+}
+
+/// Append a cell's content with alignment, accounting for display width.
 ///
-/// The `width` parameter is the target display width (in terminal columns).
-/// Full-width characters (CJK, emoji, etc.) take 2 display columns each.
-fn format_cell_aligned(content: &str, width: usize, alignment: TableAlignment) -> String {
-    let content_width = content.width();
+/// The `width` parameter is the target display width (in terminal columns),
+/// and `content_width` is the display width of `content`.  Full-width
+/// characters (CJK, emoji, etc.) take 2 display columns each.
+fn push_cell_aligned(
+    output: &mut String,
+    content: &str,
+    content_width: usize,
+    width: usize,
+    alignment: TableAlignment,
+) {
     let padding = width.saturating_sub(content_width);
 
     match alignment {
         TableAlignment::Right => {
-            format!("{}{}", " ".repeat(padding), content)
+            push_repeated(output, ' ', padding);
+            output.push_str(content);
         }
         TableAlignment::Center => {
             let left_pad = padding / 2;
             let right_pad = padding - left_pad;
-            format!(
-                "{}{}{}",
-                " ".repeat(left_pad),
-                content,
-                " ".repeat(right_pad)
-            )
+            push_repeated(output, ' ', left_pad);
+            output.push_str(content);
+            push_repeated(output, ' ', right_pad);
         }
         TableAlignment::Left | TableAlignment::None => {
-            format!("{}{}", content, " ".repeat(padding))
+            output.push_str(content);
+            push_repeated(output, ' ', padding);
         }
     }
 }
@@ -0,0 +1,173 @@
+//! Template tag (`{{ }}`, `{% %}`, and the like) handling.
+//!
+//! Documentation built with Jekyll, Hugo, or MkDocs has template tags in its
+//! Markdown, which the template engine expands before the Markdown is
+//! rendered.  CommonMark knows nothing about them, so with the `templates`
+//! option on, tags in text are written as they are in the source, and the
+//! spaces inside them are swapped for [`SPACE_MARKER`] until the document is
+//! written, so that wrapping doesn't break a tag across lines.  Tags aren't
+//! parsed: they are found by their delimiters only, and an opening delimiter
+//! without a closing one within [`MAX_TAG_LEN`] bytes isn't a tag.
+
+use comrak::nodes::AstNode;
+
+use super::Serializer;
+use super::escape;
+use crate::TemplateSyntax;
+
+/// The longest a tag can be, delimiters included, in bytes.
+const MAX_TAG_LEN: usize = 256;
+
+/// What the spaces inside tags are written as until the whole document is,
+/// as the wrapping code breaks lines at spaces.  It is a private use
+/// character, which documents don't use, and is as wide as a space.
+pub(super) const SPACE_MARKER: char = '\u{10FFFD}';
+
+/// The opening and closing delimiters of the tags of `syntax`, with longer
+/// openings before the ones they start with.
+fn delimiters(syntax: TemplateSyntax) -> &'static [(&'static str, &'static str)] {
+    match syntax {
+        TemplateSyntax::None => &[],
+        TemplateSyntax::Liquid => &[("{{", "}}"), ("{%", "%}")],
+        TemplateSyntax::Hugo => &[("{{<", ">}}"), ("{{%", "%}}"), ("{{", "}}")],
+        TemplateSyntax::Jinja => &[("{{", "}}"), ("{%", "%}"), ("{#", "#}")],
+    }
+}
+
+/// The byte ranges of the tags of `syntax` in `text`.
+fn tag_spans(text: &str, syntax: TemplateSyntax) -> Vec<(usize, usize)> {
+    let delimiters = delimiters(syntax);
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while let Some(offset) = text[i..].find('{') {
+        let start = i + offset;
+        let end = delimiters.iter().find_map(|(open, close)| {
+            let rest = text[start..].strip_prefix(open)?;
+            let end = start + open.len() + rest.find(close)? + close.len();
+            (end - start <= MAX_TAG_LEN).then_some(end)
+        });
+        match end {
+            Some(end) => {
+                spans.push((start, end));
+                i = end;
+            }
+            None => i = start + 1,
+        }
+    }
+    spans
+}
+
+/// Whether `line` is nothing but a tag of `syntax`, like `{% raw %}`.
+fn is_tag_line(line: &str, syntax: TemplateSyntax) -> bool {
+    let line = line.trim();
+    tag_spans(line, syntax) == [(0, line.len())]
+}
+
+/// `text`, with the spaces inside the tags at `spans` swapped for
+/// [`SPACE_MARKER`] and, if `escape` is given, what is outside them escaped
+/// with it.
+fn protect_tags(
+    text: &str,
+    spans: &[(usize, usize)],
+    escape: Option<fn(&str) -> String>,
+) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    for &(start, end) in spans {
+        match escape {
+            Some(escape) => result.push_str(&escape(&text[copied..start])),
+            None => result.push_str(&text[copied..start]),
+        }
+        result.extend(
+            text[start..end]
+                .chars()
+                .map(|c| if c == ' ' { SPACE_MARKER } else { c }),
+        );
+        copied = end;
+    }
+    match escape {
+        Some(escape) => result.push_str(&escape(&text[copied..])),
+        None => result.push_str(&text[copied..]),
+    }
+    result
+}
+
+impl<'a> Serializer<'a> {
+    /// The text node `node`, whose text is `text`, as it is to be written if
+    /// it has template tags: as it is in the source, or escaped outside the
+    /// tags when there is no source.  Returns `None` if it has no tags.
+    pub(super) fn template_text<'b>(&self, node: &'b AstNode<'b>, text: &str) -> Option<String> {
+        let syntax = self.options.templates;
+        if tag_spans(text, syntax).is_empty() {
+            return None;
+        }
+        if let Some(source) = self.extract_source(node) {
+            let spans = tag_spans(&source, syntax);
+            if !spans.is_empty() {
+                return Some(protect_tags(&source, &spans, None));
+            }
+        }
+        let spans = tag_spans(text, syntax);
+        let escape = if self.options.math {
+            |text: &str| escape::escape_dollars(&escape::escape_text(text)).into_owned()
+        } else {
+            |text: &str| escape::escape_text(text).into_owned()
+        };
+        Some(protect_tags(text, &spans, Some(escape)))
+    }
+
+    /// Write the paragraph `node` as it is in the source if one of its lines
+    /// is nothing but a template tag, like the `{% raw %}` and
+    /// `{% endraw %}` around a Jinja raw block, returning whether it was.
+    pub(super) fn serialize_template_paragraph<'b>(&mut self, node: &'b AstNode<'b>) -> bool {
+        let Some(source) = self.block_source(node) else {
+            return false;
+        };
+        if !source
+            .lines()
+            .any(|line| is_tag_line(line, self.options.templates))
+        {
+            return false;
+        }
+        for line in source.lines() {
+            self.write_line(line.trim_end());
+        }
+        true
+    }
+
+    /// Put back the spaces inside template tags (see [`SPACE_MARKER`]).
+    pub(super) fn restore_template_spaces(&mut self) {
+        if self.output.contains(SPACE_MARKER) {
+            self.output = self.output.replace(SPACE_MARKER, " ");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_spans() {
+        let text = "Hi {{ page.title }}, {% if x %}yes{% endif %}.";
+        assert_eq!(
+            tag_spans(text, TemplateSyntax::Liquid),
+            [(3, 19), (21, 31), (34, 45)]
+        );
+        assert_eq!(tag_spans("{{< note >}}", TemplateSyntax::Hugo), [(0, 12)]);
+        assert_eq!(tag_spans("{# a #}", TemplateSyntax::Liquid), []);
+        assert_eq!(tag_spans("{# a #}", TemplateSyntax::Jinja), [(0, 7)]);
+        assert_eq!(tag_spans("{{ unclosed", TemplateSyntax::Jinja), []);
+        assert_eq!(tag_spans("{{ a }}", TemplateSyntax::None), []);
+        let long = format!("{{{{ {} }}}}", "x".repeat(MAX_TAG_LEN));
+        assert_eq!(tag_spans(&long, TemplateSyntax::Liquid), []);
+    }
+
+    #[test]
+    fn test_is_tag_line() {
+        assert!(is_tag_line("{% raw %}", TemplateSyntax::Jinja));
+        assert!(is_tag_line("  {{< note >}}  ", TemplateSyntax::Hugo));
+        assert!(!is_tag_line("Text {% raw %}", TemplateSyntax::Jinja));
+        assert!(!is_tag_line("{% a %}{% b %}", TemplateSyntax::Jinja));
+    }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use crate::{LineWidth, ThematicBreakStyle};
+use crate::{LineWidth, ReferenceSyntax, ThematicBreakStyle, UrlEncoding, WrapMode};
 use comrak::{Arena, Options as ComrakOptions, parse_document};
 
 fn comrak_options() -> ComrakOptions<'static> {
@@ -44,6 +44,34 @@ fn parse_and_serialize_with_warnings(input: &str) -> SerializeResult {
     serialize_with_source_and_warnings(root, &format_options, Some(input))
 }
 
+/// The content of the first paragraph or table cell of `input`, as comrak
+/// reads it: its text, with escapes and entities resolved, if it is all
+/// text, or the literal of its code span, if that is all it has.  `None` if
+/// it has anything else, like emphasis or a link.
+pub(super) fn sole_inline_content(input: &str) -> Option<String> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &comrak_options());
+    let container = root.descendants().find(|node| {
+        matches!(
+            node.data.borrow().value,
+            NodeValue::Paragraph | NodeValue::TableCell
+        )
+    })?;
+    let children: Vec<_> = container.children().collect();
+    if let [child] = children[..]
+        && let NodeValue::Code(code) = &child.data.borrow().value
+    {
+        return Some(code.literal.clone());
+    }
+    children
+        .iter()
+        .map(|child| match &child.data.borrow().value {
+            NodeValue::Text(text) => Some(text.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
 #[test]
 fn test_serialize_plain_text() {
     let result = parse_and_serialize("Hello, world!");
@@ -222,6 +250,208 @@ fn test_serialize_strong() {
     assert_eq!(result, "This is **strong** text.\n");
 }
 
+#[test]
+fn test_intraword_emphasis_keeps_asterisks() {
+    // Underscores would be preferred for content with an asterisk, but they
+    // can't open or close emphasis within a word
+    let result = parse_and_serialize_with_source(r"foo*a\*b*baz and foo**a\*b**baz");
+    assert_eq!(result, "foo*a\\*b*baz and foo**a\\*b**baz\n");
+}
+
+#[test]
+fn test_underscore_emphasis_next_to_word_uses_asterisks() {
+    let arena = Arena::new();
+    let root = parse_document(&arena, "__foo__ bar", &comrak_options());
+    // Join the text that follows the emphasis to it, as a document built by
+    // a program might; underscores can't close emphasis within a word
+    let strong = root.first_child().unwrap().first_child().unwrap();
+    if let NodeValue::Text(text) = &mut strong.next_sibling().unwrap().data.borrow_mut().value {
+        *text = "bar".into();
+    }
+    let result = serialize_with_source(root, &Options::default(), Some("__foo__ bar"));
+    assert_eq!(result, "**foo**bar\n");
+}
+
+#[test]
+fn test_emphasis_spaces_moved_outside_delimiters() {
+    let arena = Arena::new();
+    let root = parse_document(&arena, "a *b* c", &comrak_options());
+    // Give the emphasis content a trailing space, as a document built
+    // by a program might
+    let emph = root
+        .first_child()
+        .unwrap()
+        .first_child()
+        .unwrap()
+        .next_sibling()
+        .unwrap();
+    if let NodeValue::Text(text) = &mut emph.first_child().unwrap().data.borrow_mut().value {
+        text.to_mut().push(' ');
+    }
+    let result = serialize_with_source(root, &Options::default(), None);
+    assert_eq!(result, "a *b*  c\n");
+}
+
+#[test]
+fn test_strong_followed_by_emphasis_uses_other_delimiter() {
+    let result = parse_and_serialize_with_source("**bold***italic*");
+    assert_eq!(result, "**bold**_italic_\n");
+    let result = parse_and_serialize_with_source("**a**_b_");
+    assert_eq!(result, "**a**_b_\n");
+}
+
+#[test]
+fn test_emphasis_followed_by_strong_uses_other_delimiter() {
+    let result = parse_and_serialize_with_source("*italic***bold**");
+    assert_eq!(result, "*italic*__bold__\n");
+    let result = parse_and_serialize_with_source("_b_**a**");
+    assert_eq!(result, "_b_**a**\n");
+}
+
+#[test]
+fn test_strong_within_emphasis_uses_other_delimiter() {
+    let result = parse_and_serialize_with_source("***both***");
+    assert_eq!(result, "*__both__*\n");
+    let result = parse_and_serialize_with_source("___both___");
+    assert_eq!(result, "_**both**_\n");
+}
+
+#[test]
+fn test_strong_within_emphasis_shares_run_within_word() {
+    // Underscores can't close within a word, so the delimiters keep
+    // sharing runs
+    let result = parse_and_serialize_with_source("***both***word");
+    assert_eq!(result, "***both***word\n");
+}
+
+#[test]
+fn test_emphasis_before_word_decides_delimiter_of_strong() {
+    // The emphasis can only use asterisks within the word, so the strong
+    // emphasis before it uses underscores
+    let result = parse_and_serialize_with_source("**bold***italic*s");
+    assert_eq!(result, "__bold__*italic*s\n");
+}
+
+#[test]
+fn test_nested_emphasis_run_keeps_source_delimiters() {
+    // No alternation of delimiters reads back as the same nesting, so the
+    // runs are written as in the source
+    for input in ["_____foo_____\n", "foo******bar*********baz\n"] {
+        let expected = input.replace("*********", "******\\*\\*\\*");
+        assert_round_trip(input, &expected, &Options::default());
+        assert_renders_same(input, &expected);
+    }
+}
+
+/// Describe the emphasis in a document: its text, with emphasis and strong
+/// emphasis marked by tags and runs of whitespace collapsed.
+fn emphasis_structure(input: &str) -> String {
+    fn walk<'a>(node: &'a AstNode<'a>, out: &mut String) {
+        let (open, close) = match &node.data.borrow().value {
+            NodeValue::Text(text) => {
+                out.push_str(text);
+                return;
+            }
+            NodeValue::SoftBreak | NodeValue::LineBreak => {
+                out.push(' ');
+                return;
+            }
+            NodeValue::Emph => ("<em>", "</em>"),
+            NodeValue::Strong => ("<strong>", "</strong>"),
+            _ => ("", " "),
+        };
+        out.push_str(open);
+        for child in node.children() {
+            walk(child, out);
+        }
+        out.push_str(close);
+    }
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &comrak_options());
+    let mut out = String::new();
+    walk(root, &mut out);
+    escape::normalize_whitespace(&out)
+}
+
+/// Paragraph text made of words, spaces, and punctuation, parts of which
+/// are wrapped in emphasis delimiters, possibly nested, along with the
+/// emphasis structure it is meant to have (see [`emphasis_structure`]).
+fn emphasis_soup() -> impl proptest::strategy::Strategy<Value = (String, String)> {
+    use proptest::prelude::*;
+    let text = prop::sample::select(vec![
+        ("foo", "foo"),
+        ("bar", "bar"),
+        (" ", " "),
+        ("(", "("),
+        (")", ")"),
+        (".", "."),
+        (",", ","),
+        ("\\*", "*"),
+        ("&#x61;", "a"),
+    ])
+    .prop_map(|(source, text)| (source.to_owned(), text.to_owned()));
+    let words = prop::collection::vec(text.clone(), 1..4).prop_map(concat_soup);
+    let delimiter = prop::sample::select(vec!["*", "_", "**", "__"]);
+    let wrap = |delimiter: &str, (source, structure): (String, String)| {
+        let tag = if delimiter.len() == 1 { "em" } else { "strong" };
+        (
+            format!("{delimiter}{source}{delimiter}"),
+            format!("<{tag}>{structure}</{tag}>"),
+        )
+    };
+    let emphasis = (delimiter.clone(), words.clone())
+        .prop_map(move |(delimiter, words)| wrap(delimiter, words));
+    let nested = (delimiter, words.clone(), emphasis.clone(), words).prop_map(
+        move |(delimiter, before, emphasis, after)| {
+            wrap(delimiter, concat_soup(vec![before, emphasis, after]))
+        },
+    );
+    // Text follows nested emphasis, as emphasis right next to it may only
+    // fit by sharing a delimiter run with it, which isn't done
+    let nested = (nested, text.clone()).prop_map(|(nested, text)| concat_soup(vec![nested, text]));
+    prop::collection::vec(prop_oneof![2 => text, 2 => emphasis, 1 => nested], 1..8).prop_map(
+        |parts| {
+            concat_soup(
+                std::iter::once(("x ".to_owned(), "x ".to_owned()))
+                    .chain(parts)
+                    .collect(),
+            )
+        },
+    )
+}
+
+fn concat_soup(parts: Vec<(String, String)>) -> (String, String) {
+    parts.into_iter().fold(
+        (String::new(), String::new()),
+        |(source, structure), (s, t)| (source + &s, structure + &t),
+    )
+}
+
+proptest::proptest! {
+    // Most generated delimiters don't read back as the emphasis they were
+    // generated for, and those inputs are skipped
+    #![proptest_config(proptest::prelude::ProptestConfig {
+        max_global_rejects: 100_000,
+        ..proptest::prelude::ProptestConfig::default()
+    })]
+
+    #[test]
+    fn prop_emphasis_structure_survives_formatting((input, structure) in emphasis_soup()) {
+        proptest::prop_assume!(emphasis_structure(&input) == escape::normalize_whitespace(&structure));
+        let options = Options {
+            ellipsis: false,
+            ..Options::default()
+        };
+        let output = parse_and_serialize_with_options_and_warnings(&input, &options).output;
+        proptest::prop_assert_eq!(
+            emphasis_structure(&output),
+            emphasis_structure(&input),
+            "formatted as {:?}",
+            output
+        );
+    }
+}
+
 #[test]
 fn test_serialize_inline_code() {
     let result = parse_and_serialize("Use the `format()` function.");
@@ -233,7 +463,7 @@ fn test_serialize_external_link_becomes_reference() {
     // External links (https://) are converted to reference style
     let result = parse_and_serialize("Visit [Rust](https://www.rust-lang.org/).");
     assert!(result.contains("Visit [Rust]."));
-    assert!(result.contains("[Rust]: https://www.rust-lang.org/"));
+    assert!(result.contains("[rust]: https://www.rust-lang.org/"));
 }
 
 #[test]
@@ -242,7 +472,7 @@ fn test_serialize_external_link_with_title_becomes_reference() {
     let result =
         parse_and_serialize("Visit [Rust](https://www.rust-lang.org/ \"The Rust Language\").");
     assert!(result.contains("Visit [Rust]."));
-    assert!(result.contains("[Rust]: https://www.rust-lang.org/ \"The Rust Language\""));
+    assert!(result.contains("[rust]: https://www.rust-lang.org/ \"The Rust Language\""));
 }
 
 #[test]
@@ -672,6 +902,16 @@ fn test_serialize_definition_list_multiple_definitions() {
     assert!(result.contains(":   Second definition"));
 }
 
+#[test]
+fn test_serialize_definition_list_code_block_with_long_inner_fence() {
+    let input = "Term\n:   ~~~~~~~\n    ~~~~~~\n    inner\n    ~~~~~~\n    ~~~~~~~";
+    let result = parse_and_serialize_with_description_list(input);
+    assert!(result.contains("~~~~~~~\n"), "{}", result);
+    // The fence must be longer than the inner run so the block isn't closed early
+    let reparsed = parse_and_serialize_with_description_list(&result);
+    assert_eq!(reparsed, result);
+}
+
 fn parse_and_serialize_with_alerts(input: &str) -> String {
     let arena = Arena::new();
     let mut options = ComrakOptions::default();
@@ -817,6 +1057,115 @@ fn test_blockquote_inside_list_item() {
     assert!(result.contains("2.  Next item."));
 }
 
+#[test]
+fn test_blocks_in_tight_list_items_stay_tight() {
+    for (input, expected) in [
+        ("- a\n  > b\n- c\n", " -  a\n    > b\n -  c\n"),
+        (
+            "- a\n  > b\n  ```\n  c\n  ```\n- d\n",
+            " -  a\n    > b\n    ~~~~\n    c\n    ~~~~\n -  d\n",
+        ),
+        ("- Bar\n  ---\n  baz\n", " -  Bar\n    ---\n    baz\n"),
+    ] {
+        assert_round_trip(input, expected, &Options::default());
+        assert_renders_same(input, expected);
+    }
+}
+
+/// A paragraph (with a lazy continuation line), a list, and a fenced code
+/// block, quoted `depth` times, in both input and expected output forms.
+fn nested_quote_documents(depth: usize) -> (String, String) {
+    let quote = "> ".repeat(depth);
+    let blank = quote.trim_end();
+    let input = format!(
+        "{q}Paragraph in a quote\nthat continues lazily.\n{b}\n{q}-  First item\n{q}-  Second item\n\
+         {b}\n{q}```rust\n{q}fn main() {{}}\n{q}```\n",
+        q = quote,
+        b = blank
+    );
+    let expected = format!(
+        "{q}Paragraph in a quote\n{q}that continues lazily.\n{b}\n{q} -  First item\n\
+         {q} -  Second item\n{b}\n{q}~~~~ rust\n{q}fn main() {{}}\n{q}~~~~\n",
+        q = quote,
+        b = blank
+    );
+    (input, expected)
+}
+
+#[test]
+fn test_nested_block_quote_depth_two_round_trip() {
+    let (input, expected) = nested_quote_documents(2);
+    let result = parse_and_serialize(&input);
+    assert_eq!(result, expected);
+    assert_eq!(parse_and_serialize(&result), expected);
+}
+
+#[test]
+fn test_nested_block_quote_depth_three_round_trip() {
+    let (input, expected) = nested_quote_documents(3);
+    let result = parse_and_serialize(&input);
+    assert_eq!(result, expected);
+    assert_eq!(parse_and_serialize(&result), expected);
+}
+
+#[test]
+fn test_empty_block_quotes_kept() {
+    for (input, expected) in [(">\n>  \n> \n", ">\n"), ("> a\n>\n> >\n", "> a\n>\n> >\n")] {
+        assert_round_trip(input, expected, &Options::default());
+        assert_renders_same(input, expected);
+    }
+}
+
+#[test]
+fn test_nested_block_quote_loose_list_and_heading() {
+    let input = "> > ## Heading\n> >\n> > 1.  One\n> >\n> >     More text\n> >\n> > 2.  Two\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        "> > Heading\n> > -------\n> >\n> > 1.  One\n> >\n> >     More text\n> >\n> > 2.  Two\n"
+    );
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_html_block_inside_block_quote() {
+    let input = "> <div>\n> html\n> </div>\n>\n> text\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_quote_inside_list_inside_quote() {
+    let input = "> -  Item\n>\n>    > Nested quote\n>    >\n>    > Second paragraph.\n";
+    let result = parse_and_serialize(input);
+    assert!(
+        result.contains(">     > Nested quote\n>     >\n>     > Second paragraph.\n"),
+        "{}",
+        result
+    );
+}
+
+#[test]
+fn test_code_block_and_table_inside_quote_inside_list_item() {
+    let input =
+        "-  Item\n\n   > ```\n   > code\n   > ```\n   >\n   > | a |\n   > |---|\n   > | 1 |\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        " -  Item\n\n    > ~~~~\n    > code\n    > ~~~~\n    >\n    > | a   |\n    > | --- |\n    > | 1   |\n"
+    );
+}
+
+#[test]
+fn test_table_inside_list_item() {
+    let input = "-  Item\n\n   | a | b |\n   |---|---|\n   | 1 | 2 |\n\n-  Next\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        " -  Item\n\n    | a   | b   |\n    | --- | --- |\n    | 1   | 2   |\n\n -  Next\n"
+    );
+}
+
 #[test]
 fn test_alert_inside_list_item() {
     // Alerts inside list items should have proper indentation
@@ -847,7 +1196,7 @@ fn test_serialize_external_link_as_reference() {
     // Should use reference style, not inline
     assert!(result.contains("[Rust]"));
     assert!(!result.contains("](https://"));
-    assert!(result.contains("[Rust]: https://www.rust-lang.org/"));
+    assert!(result.contains("[rust]: https://www.rust-lang.org/"));
 }
 
 #[test]
@@ -876,11 +1225,11 @@ Check [Python](https://python.org/) too.
 "#;
     let result = parse_and_serialize(input);
     // Each section should have its references at the end
-    assert!(result.contains("[Rust]: https://www.rust-lang.org/"));
-    assert!(result.contains("[Cargo]: https://doc.rust-lang.org/cargo/"));
-    assert!(result.contains("[Python]: https://python.org/"));
+    assert!(result.contains("[rust]: https://www.rust-lang.org/"));
+    assert!(result.contains("[cargo]: https://doc.rust-lang.org/cargo/"));
+    assert!(result.contains("[python]: https://python.org/"));
     // References should come before the next section
-    let rust_def_pos = result.find("[Rust]: ").unwrap();
+    let rust_def_pos = result.find("[rust]: ").unwrap();
     let section_two_pos = result.find("Section Two").unwrap();
     assert!(rust_def_pos < section_two_pos);
 }
@@ -1165,7 +1514,7 @@ fn test_preserve_reference_style_badge() {
     let result = parse_and_serialize_with_source(input);
     // Should preserve reference style, not convert to inline
     assert!(
-        result.contains("[![JSR][JSR badge]][JSR]"),
+        result.contains("[![JSR][jsr badge]][jsr]"),
         "Reference-style badge should be preserved, got:\n{}",
         result
     );
@@ -1178,7 +1527,7 @@ fn test_preserve_reference_style_image() {
     let result = parse_and_serialize_with_source(input);
     // Should preserve reference style
     assert!(
-        result.contains("![Logo][logo]"),
+        result.contains("![Logo]"),
         "Reference-style image should be preserved, got:\n{}",
         result
     );
@@ -1433,6 +1782,101 @@ fn test_blockquote_with_multiple_paragraphs() {
     assert!(result.contains("> Second paragraph"));
 }
 
+fn format_preserving_marker_spacing(input: &str) -> String {
+    let options = Options {
+        block_quote_preserve_marker_spacing: true,
+        ..Options::default()
+    };
+    parse_and_serialize_with_options_and_warnings(input, &options).output
+}
+
+#[test]
+fn test_blockquote_marker_spacing_normalized_by_default() {
+    let input = ">    Aligned text\n>   and more.\n";
+    assert_eq!(parse_and_serialize(input), "> Aligned text\n> and more.\n");
+}
+
+#[test]
+fn test_blockquote_preserve_marker_spacing_round_trips() {
+    let input = "Intro.\n\n>    Aligned text here\n> and a normal line.\n>\n> Second paragraph\n>   with spaces.\n>\n>    > Nested quote\n>    >   with spaces.\n";
+    assert_eq!(format_preserving_marker_spacing(input), input);
+}
+
+#[test]
+fn test_blockquote_preserve_marker_spacing_only_unchanged_lines() {
+    let input = ">    Short line.\n>    And a line that is long enough that it has to be wrapped at the eighty column limit.\n>\n>    -  item\n";
+    assert_eq!(
+        format_preserving_marker_spacing(input),
+        ">    Short line.\n> And a line that is long enough that it has to be wrapped at the eighty column\n> limit.\n>\n>  -  item\n"
+    );
+}
+
+/// Assert that `input`, which has `count` block quotes, is formatted into
+/// `expected` with `options`, which is formatted into itself and still has
+/// `count` block quotes.
+fn assert_block_quotes_kept_apart(input: &str, expected: &str, count: usize, options: &Options) {
+    let block_quotes = |text: &str| {
+        let arena = Arena::new();
+        let root = parse_document(&arena, text, &crate::base_comrak_options());
+        root.descendants()
+            .filter(|node| matches!(node.data.borrow().value, NodeValue::BlockQuote))
+            .count()
+    };
+    assert_eq!(block_quotes(input), count);
+    assert_round_trip(input, expected, options);
+    assert_eq!(block_quotes(expected), count);
+}
+
+#[test]
+fn test_adjacent_block_quotes_kept_apart() {
+    let options = Options::default();
+    assert_block_quotes_kept_apart(
+        "> First quote.\n>\n> Its second paragraph.\n\n> Second quote.\n>\n> Its second\nparagraph.\n",
+        "> First quote.\n>\n> Its second paragraph.\n\n> Second quote.\n>\n> Its second\n> paragraph.\n",
+        2,
+        &options,
+    );
+    assert_block_quotes_kept_apart(
+        "> > One.\n> >\n> > Two.\n>\n> > Three.\n> >\n> > Four.\n",
+        "> > One.\n> >\n> > Two.\n>\n> > Three.\n> >\n> > Four.\n",
+        3,
+        &options,
+    );
+    assert_block_quotes_kept_apart(
+        " -  Item.\n\n    > One.\n    >\n    > Two.\n\n    > Three.\n    >\n    > Four.\n",
+        " -  Item.\n\n    > One.\n    >\n    > Two.\n\n    > Three.\n    >\n    > Four.\n",
+        2,
+        &options,
+    );
+}
+
+#[test]
+fn test_adjacent_block_quotes_separated_by_comment() {
+    let options = Options {
+        block_quote_separation: crate::BlockQuoteSeparation::Comment,
+        ..Options::default()
+    };
+    assert_block_quotes_kept_apart(
+        "> First quote.\n>\n> Its second paragraph.\n\n> Second quote.\n>\n> Its second paragraph.\n",
+        "> First quote.\n>\n> Its second paragraph.\n\n<!-- -->\n\n> Second quote.\n>\n> Its second paragraph.\n",
+        2,
+        &options,
+    );
+    assert_block_quotes_kept_apart(
+        "> > One.\n> >\n> > Two.\n>\n> > Three.\n> >\n> > Four.\n",
+        "> > One.\n> >\n> > Two.\n>\n> <!-- -->\n>\n> > Three.\n> >\n> > Four.\n",
+        3,
+        &options,
+    );
+    assert_round_trip(
+        "> Quote.\n\n> [!NOTE]\n> Alert.\n",
+        "> Quote.\n\n<!-- -->\n\n> [!NOTE]\n> Alert.\n",
+        &options,
+    );
+    // A quote after another block gets no comment
+    assert_round_trip("Text.\n\n> Quote.\n", "Text.\n\n> Quote.\n", &options);
+}
+
 #[test]
 fn test_link_text_with_emphasis() {
     let input = "[*emphasized* link](https://example.com)";
@@ -1509,9 +1953,11 @@ fn test_thematic_break_default_leading_spaces() {
 #[test]
 fn test_thematic_break_custom_style() {
     let input = "Before\n\n---\n\nAfter";
-    let mut options = Options::default();
-    options.thematic_break_style = ThematicBreakStyle::new("---".to_string()).unwrap();
-    options.thematic_break_leading_spaces = LeadingSpaces::new(0).unwrap();
+    let options = Options {
+        thematic_break_style: ThematicBreakStyle::new("---".to_string()).unwrap(),
+        thematic_break_leading_spaces: LeadingSpaces::new(0).unwrap(),
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert!(
         result.contains("\n---\n"),
@@ -1523,9 +1969,11 @@ fn test_thematic_break_custom_style() {
 #[test]
 fn test_thematic_break_leading_spaces() {
     let input = "Before\n\n---\n\nAfter";
-    let mut options = Options::default();
-    options.thematic_break_style = ThematicBreakStyle::new("*  *  *".to_string()).unwrap();
-    options.thematic_break_leading_spaces = LeadingSpaces::new(3).unwrap();
+    let options = Options {
+        thematic_break_style: ThematicBreakStyle::new("*  *  *".to_string()).unwrap(),
+        thematic_break_leading_spaces: LeadingSpaces::new(3).unwrap(),
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     // 3 leading spaces should be applied
     assert!(
@@ -1565,6 +2013,128 @@ fn test_thematic_break_various_input_styles() {
     }
 }
 
+/// Assert that `text` parses to the same block structure as `expected`.
+fn assert_same_blocks(text: &str, expected: &str) {
+    fn blocks(input: &str) -> Vec<(usize, String)> {
+        let arena = Arena::new();
+        let root = parse_document(&arena, input, &comrak_options());
+        root.descendants()
+            .filter(|node| node.data.borrow().value.block())
+            .map(|node| {
+                let depth = node.ancestors().count();
+                let value = &node.data.borrow().value;
+                (depth, value.xml_node_name().to_string())
+            })
+            .collect()
+    }
+    assert_eq!(blocks(text), blocks(expected), "got:\n{}", text);
+}
+
+#[test]
+fn test_thematic_break_after_paragraph_in_list_item() {
+    // Without a blank line, `---` would turn the paragraph into a heading
+    let options = Options {
+        thematic_break_style: ThematicBreakStyle::new("---".to_string()).unwrap(),
+        thematic_break_leading_spaces: LeadingSpaces::new(0).unwrap(),
+        ..Options::default()
+    };
+    let input = "- a\n  ***\n  b\n";
+    let result = parse_and_serialize_with_options(input, &options);
+    assert_eq!(result, " -  a\n\n    ---\n\n    b\n");
+    assert_same_blocks(&result, input);
+    assert_eq!(parse_and_serialize_with_options(&result, &options), result);
+}
+
+#[test]
+fn test_thematic_break_first_in_bullet_list_item() {
+    // The default style uses the same character as the default `-` marker,
+    // which would make the marker part of a top-level thematic break
+    let input = "- ***\n- b\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        " -  * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * * *\n -  b\n"
+    );
+    assert_same_blocks(&result, input);
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_thematic_break_between_asterisk_lists() {
+    let options = Options {
+        unordered_marker: crate::UnorderedMarker::Asterisk,
+        thematic_break_style: ThematicBreakStyle::new("* * *".to_string()).unwrap(),
+        ..Options::default()
+    };
+    let input = "* a\n* b\n\n* * *\n\n* c\n";
+    let result = parse_and_serialize_with_options(input, &options);
+    assert_eq!(result, " *  a\n *  b\n\n   * * *\n\n *  c\n");
+    assert_same_blocks(&result, input);
+    let result = parse_and_serialize_with_options("* - - -\n", &options);
+    assert_eq!(result, " *  - - -\n");
+}
+
+#[test]
+fn test_underline_lookalike_lines_stay_paragraph_text() {
+    // Dash transformation would turn `---` into something else
+    let options = Options {
+        em_dash: crate::DashSetting::Disabled,
+        ..Options::default()
+    };
+    for line in ["---", "=", "- - -"] {
+        for (input, expected) in [
+            (
+                format!("Signature\n\\{}\n", line),
+                format!("Signature\n\\{}\n", line),
+            ),
+            (
+                format!("> Signature\n> \\{}\n", line),
+                format!("> Signature\n> \\{}\n", line),
+            ),
+            (
+                format!("- Signature\n  \\{}\n", line),
+                format!(" -  Signature\n    \\{}\n", line),
+            ),
+        ] {
+            let result = parse_and_serialize_with_options(&input, &options);
+            assert_eq!(result, expected);
+            assert_same_blocks(&result, &input);
+            assert_eq!(parse_and_serialize_with_options(&result, &options), result);
+        }
+    }
+}
+
+#[test]
+fn test_indented_continuation_lines_stay_paragraph_text() {
+    // Indented by four spaces, these lines continue the paragraph instead
+    // of starting a block
+    for (input, expected) in [
+        ("foo\n    # bar\n", "foo\n\\# bar\n"),
+        ("foo\n    - bar\n", "foo\n\\- bar\n"),
+        ("foo\n    > bar\n", "foo\n\\> bar\n"),
+        ("> foo\n    - bar\n", "> foo\n> \\- bar\n"),
+    ] {
+        assert_round_trip(input, expected, &Options::default());
+        assert_renders_same(input, expected);
+    }
+}
+
+#[test]
+fn test_wrapped_dashes_do_not_start_a_line_as_underline() {
+    let options = Options {
+        em_dash: crate::DashSetting::Disabled,
+        ..Options::default()
+    };
+    let input = format!("{} ---\n", "word ".repeat(16).trim_end());
+    assert_eq!(input.trim_end().len(), 83);
+    let result = parse_and_serialize_with_options(&input, &options);
+    assert_eq!(
+        result,
+        format!("{}\n\\---\n", "word ".repeat(16).trim_end())
+    );
+    assert_same_blocks(&result, &input);
+}
+
 #[test]
 fn test_unicode_in_heading() {
     let input = "# 한글 제목";
@@ -1709,53 +2279,329 @@ More text with [link].
 }
 
 #[test]
-fn test_heading_with_reference_link() {
-    let input = r#"[BotKit] by Fedify
-==================
-
-[BotKit]: https://botkit.fedify.dev/
-"#;
+fn test_case_variant_references_share_one_definition() {
+    let input = "See [Foo], [foo], and [FOO].\n\n[Foo]: https://example.com/\n";
     let result = parse_and_serialize_with_source(input);
-    // The reference link in the heading should be preserved
-    assert!(
-        result.contains("[BotKit] by Fedify"),
-        "Heading should contain reference link syntax, got:\n{}",
-        result
-    );
-    assert!(
-        result.contains("[BotKit]: https://botkit.fedify.dev/"),
-        "Reference definition should be preserved, got:\n{}",
-        result
+    assert_eq!(
+        result,
+        "See [Foo], [foo], and [FOO].\n\n[foo]: https://example.com/\n"
     );
+    assert_eq!(parse_and_serialize_with_source(&result), result);
 }
 
 #[test]
-fn test_footnote_at_section_end_before_subheading() {
-    let input = r#"Section
--------
-
-Text with footnote[^1].
-
-### Subsection
+fn test_single_spelling_reference_label_lowercased() {
+    let input = "See [Foo] and [Foo].\n\n[Foo]: https://example.com/\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(
+        result,
+        "See [Foo] and [Foo].\n\n[foo]: https://example.com/\n"
+    );
+}
 
-More text here.
+#[test]
+fn test_full_reference_labels_lowercased_with_definition() {
+    let input = "See [the docs][Foo] and [the API][API  Docs].\n\n\
+                 [foo]: https://example.com/\n\
+                 [Api docs]: https://example.com/api\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(
+        result,
+        "See [the docs][foo] and [the API][api docs].\n\n\
+         [foo]: https://example.com/\n\
+         [api docs]: https://example.com/api\n"
+    );
+    assert_eq!(parse_and_serialize_with_source(&result), result);
+}
 
-[^1]: This is a footnote.
-"#;
+#[test]
+fn test_external_links_with_conflicting_urls_stay_apart() {
+    let input = "[Docs](https://a.example/) and [docs](https://b.example/).\n";
     let result = parse_and_serialize_with_source(input);
-    // Footnote should appear before the subsection, not at document end
-    let footnote_pos = result.find("[^1]: This is a footnote.").unwrap();
-    let subsection_pos = result.find("### Subsection").unwrap();
-    assert!(
-        footnote_pos < subsection_pos,
-        "Footnote should appear before subsection, got:\n{}",
-        result
+    assert_eq!(
+        result,
+        "[Docs] and [docs](https://b.example/).\n\n[docs]: https://a.example/\n"
     );
 }
 
 #[test]
-fn test_footnote_definition_wrapped_at_80_chars() {
-    let input = r#"Text[^1].
+fn test_escaped_bracket_in_reference_label() {
+    let input = "[Foo\\]] and [x][a\\]b]\n\n[Foo\\]]: /u\n[a\\]b]: /v\n";
+    let expected = "[Foo\\]] and [x][a\\]b]\n\n[foo\\]]: /u\n[a\\]b]: /v\n";
+    assert_round_trip(input, expected, &Options::default());
+    assert_renders_same(input, expected);
+}
+
+#[test]
+fn test_autolink_with_brackets_not_reference_link() {
+    let input = "[foo<https://example.com/?search=][ref]>\n\n[ref]: /uri\n";
+    let expected = "[foo<https://example.com/?search=][ref]>\n";
+    assert_round_trip(input, expected, &Options::default());
+    assert_renders_same(input, expected);
+}
+
+#[test]
+fn test_empty_definition_destination_kept() {
+    let input = "[foo]\n\n[foo]: <>\n";
+    assert_round_trip(input, input, &Options::default());
+}
+
+#[test]
+fn test_unused_definition_removed_by_default() {
+    let input =
+        "See [used].\n\n[used]: https://example.com/used\n[unused]: https://example.com/unused\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(result, "See [used].\n\n[used]: https://example.com/used\n");
+}
+
+#[test]
+fn test_unused_definition_kept_when_not_removing() {
+    let input = "[unused]: https://example.com/unused \"Unused\"\n\nSee [used].\n\n[used]: https://example.com/used\n";
+    let options = Options {
+        link_remove_unused_definitions: false,
+        ..Options::default()
+    };
+    let result = parse_and_serialize_with_options_and_warnings(input, &options).output;
+    assert_eq!(
+        result,
+        "See [used].\n\n[used]: https://example.com/used\n\
+         [unused]: https://example.com/unused \"Unused\"\n"
+    );
+    let again = parse_and_serialize_with_options_and_warnings(&result, &options).output;
+    assert_eq!(again, result);
+}
+
+#[test]
+fn test_definitions_differing_only_in_title_warn() {
+    let input =
+        "See [foo].\n\n[foo]: https://example.com/ \"One\"\n[FOO]: https://example.com/ \"Two\"\n";
+    let result = parse_and_serialize_with_warnings(input);
+    assert_eq!(
+        result.output,
+        "See [foo].\n\n[foo]: https://example.com/ \"One\"\n"
+    );
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].line, 4);
+    assert!(result.warnings[0].message.contains("only in title"));
+}
+
+#[test]
+fn test_long_definition_title_moves_to_next_line() {
+    let url = format!("https://example.com/{}", "a".repeat(60));
+    let input = format!("See [long].\n\n[long]: {} \"A title\"\n", url);
+    let result = parse_and_serialize_with_source(&input);
+    assert_eq!(
+        result,
+        format!("See [long].\n\n[long]: {}\n    \"A title\"\n", url)
+    );
+    assert_eq!(parse_and_serialize_with_source(&result), result);
+}
+
+#[test]
+fn test_definition_title_with_quotes_escaped() {
+    let input = "See [q].\n\n[q]: https://example.com/ 'Say \"hi\"'\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(
+        result,
+        "See [q].\n\n[q]: https://example.com/ \"Say \\\"hi\\\"\"\n"
+    );
+    assert_eq!(parse_and_serialize_with_source(&result), result);
+}
+
+#[test]
+fn test_inline_link_title_escaped() {
+    for (input, expected) in [
+        (
+            "[a](/u 'Say \"hi\"') and ![b](/v \"x \\\\\\\" &quot;\")\n",
+            "[a](/u \"Say \\\"hi\\\"\") and ![b](/v \"x \\\\\\\" \\\"\")\n",
+        ),
+        ("[a](/u \"&amp;copy;\")\n", "[a](/u \"&amp;copy;\")\n"),
+    ] {
+        assert_round_trip(input, expected, &Options::default());
+        assert_renders_same(input, expected);
+    }
+}
+
+#[test]
+fn test_external_references_disabled() {
+    let options = Options {
+        link_external_references: false,
+        ..Options::default()
+    };
+    let input = "# [Hongdown](https://github.com/dahlia/hongdown)\n\n\
+                 See [the docs](https://example.com/docs \"Docs\") and [Foo].\n\n\
+                 [Foo]: https://example.com/foo\n";
+    let result = parse_and_serialize_with_options_and_warnings(input, &options).output;
+    assert_eq!(
+        result,
+        "[Hongdown](https://github.com/dahlia/hongdown)\n\
+         ==============================================\n\n\
+         See [the docs](https://example.com/docs \"Docs\") and [Foo].\n\n\
+         [foo]: https://example.com/foo\n"
+    );
+}
+
+fn parse_and_serialize_with_reference_syntax(input: &str, syntax: ReferenceSyntax) -> String {
+    let options = Options {
+        link_reference_syntax: syntax,
+        ..Options::default()
+    };
+    parse_and_serialize_with_options_and_warnings(input, &options).output
+}
+
+#[test]
+fn test_reference_syntax_round_trips() {
+    let forms = ["[Foo]", "[Foo][]", "[Foo][Foo]"];
+    let cases = [
+        (ReferenceSyntax::Shortcut, ["[Foo]", "[Foo]", "[Foo]"]),
+        (
+            ReferenceSyntax::Collapsed,
+            ["[Foo][]", "[Foo][]", "[Foo][]"],
+        ),
+        (
+            ReferenceSyntax::Full,
+            ["[Foo][foo]", "[Foo][foo]", "[Foo][foo]"],
+        ),
+        (
+            ReferenceSyntax::Preserve,
+            ["[Foo]", "[Foo][]", "[Foo][foo]"],
+        ),
+    ];
+    for (syntax, outputs) in cases {
+        for (form, output) in forms.iter().zip(outputs) {
+            let input = format!("See {} for details.\n\n[Foo]: /foo\n", form);
+            let result = parse_and_serialize_with_reference_syntax(&input, syntax);
+            assert_eq!(
+                result,
+                format!("See {} for details.\n\n[foo]: /foo\n", output),
+                "{:?} with {}",
+                syntax,
+                form
+            );
+            assert_eq!(
+                parse_and_serialize_with_reference_syntax(&result, syntax),
+                result,
+                "{:?} with {} is not idempotent",
+                syntax,
+                form
+            );
+        }
+    }
+}
+
+#[test]
+fn test_reference_syntax_applies_to_images() {
+    let input = "An image: ![Logo][] here.\n\n[Logo]: /logo.png\n";
+    let result = parse_and_serialize_with_reference_syntax(input, ReferenceSyntax::Full);
+    assert_eq!(
+        result,
+        "An image: ![Logo][logo] here.\n\n[logo]: /logo.png\n"
+    );
+    let result = parse_and_serialize_with_reference_syntax(input, ReferenceSyntax::Shortcut);
+    assert_eq!(result, "An image: ![Logo] here.\n\n[logo]: /logo.png\n");
+}
+
+#[test]
+fn test_reference_syntax_full_when_label_differs() {
+    let input = "See [the docs][docs] for details.\n\n[docs]: /docs\n";
+    for syntax in [ReferenceSyntax::Shortcut, ReferenceSyntax::Collapsed] {
+        let result = parse_and_serialize_with_reference_syntax(input, syntax);
+        assert_eq!(result, input);
+    }
+}
+
+#[test]
+fn test_reference_syntax_for_external_links() {
+    let input = "Read the [Docs](https://example.com/) first.\n";
+    let result = parse_and_serialize_with_reference_syntax(input, ReferenceSyntax::Full);
+    assert_eq!(
+        result,
+        "Read the [Docs][docs] first.\n\n[docs]: https://example.com/\n"
+    );
+    let result = parse_and_serialize_with_reference_syntax(input, ReferenceSyntax::Preserve);
+    assert_eq!(
+        result,
+        "Read the [Docs] first.\n\n[docs]: https://example.com/\n"
+    );
+}
+
+#[test]
+fn test_shortcut_before_parenthesis_becomes_collapsed() {
+    let input = "See [Foo][](with a note) for details.\n\n[foo]: /foo\n";
+    let result = parse_and_serialize_with_reference_syntax(input, ReferenceSyntax::Shortcut);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_escaped_bracket_after_reference_kept() {
+    let input = "See [Foo]\\[1] for details.\n\n[Foo]: /foo\n[1]: /one\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(result, "See [Foo][]\\[1] for details.\n\n[foo]: /foo\n");
+    assert_eq!(parse_and_serialize_with_source(&result), result);
+}
+
+#[test]
+fn test_escaped_punctuation_kept() {
+    let input = "Not a heading: \\# one, and \\[two].\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_escaped_punctuation_at_line_start_kept() {
+    let input = "\\# Not a heading\n\n\\> Not a quote\nand\n\\- not a list.\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_heading_with_reference_link() {
+    let input = r#"[BotKit] by Fedify
+==================
+
+[BotKit]: https://botkit.fedify.dev/
+"#;
+    let result = parse_and_serialize_with_source(input);
+    // The reference link in the heading should be preserved
+    assert!(
+        result.contains("[BotKit] by Fedify"),
+        "Heading should contain reference link syntax, got:\n{}",
+        result
+    );
+    assert!(
+        result.contains("[botkit]: https://botkit.fedify.dev/"),
+        "Reference definition should be preserved, got:\n{}",
+        result
+    );
+}
+
+#[test]
+fn test_footnote_at_section_end_before_subheading() {
+    let input = r#"Section
+-------
+
+Text with footnote[^1].
+
+### Subsection
+
+More text here.
+
+[^1]: This is a footnote.
+"#;
+    let result = parse_and_serialize_with_source(input);
+    // Footnote should appear before the subsection, not at document end
+    let footnote_pos = result.find("[^1]: This is a footnote.").unwrap();
+    let subsection_pos = result.find("### Subsection").unwrap();
+    assert!(
+        footnote_pos < subsection_pos,
+        "Footnote should appear before subsection, got:\n{}",
+        result
+    );
+}
+
+#[test]
+fn test_footnote_definition_wrapped_at_80_chars() {
+    let input = r#"Text[^1].
 
 [^1]: This is a very long footnote definition that definitely exceeds eighty characters and should be wrapped.
 "#;
@@ -1793,6 +2639,103 @@ fn test_footnote_continuation_indent_matches_prefix() {
     );
 }
 
+#[test]
+fn test_footnote_with_nested_blocks_referenced_from_block_quote() {
+    let input = r#"> Quoted claim.[^1]
+
+[^1]: First paragraph of the note.
+
+    Second paragraph.
+
+    ~~~ rust
+    fn main() {}
+    ~~~
+
+     -  Item one
+     -  Item two
+
+         -  Nested
+"#;
+    let expected = r#"> Quoted claim.[^1]
+
+[^1]: First paragraph of the note.
+
+    Second paragraph.
+
+    ~~~~ rust
+    fn main() {}
+    ~~~~
+
+     -  Item one
+
+     -  Item two
+
+         -  Nested
+"#;
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(result, expected);
+    assert_eq!(parse_and_serialize_with_source(&result), expected);
+}
+
+#[test]
+fn test_footnote_with_blocks_separated_from_next_definition() {
+    let input = r#"Text[^1] and more[^2].
+
+[^1]: Note.
+
+     -  Item
+
+[^2]: Next note.
+"#;
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(
+        result,
+        "Text[^1] and more[^2].\n\n[^1]: Note.\n\n     -  Item\n\n[^2]: Next note.\n"
+    );
+}
+
+#[test]
+fn test_footnote_with_blocks_uses_four_space_indent_for_long_name() {
+    let input = r#"Text[^long-name].
+
+[^long-name]: First paragraph.
+
+    ```
+    code
+    ```
+"#;
+    let result = parse_and_serialize_with_source(input);
+    assert!(
+        result.contains("\n    ~~~~\n    code\n    ~~~~\n"),
+        "Code block should be indented four spaces, got:\n{}",
+        result
+    );
+}
+
+#[test]
+fn test_footnote_references_in_table_cell_and_heading() {
+    let input = r#"| A        | B   |
+| -------- | --- |
+| cell[^1] | x   |
+
+Heading[^2]
+-----------
+
+[^1]: Table note.
+[^2]: Heading note.
+"#;
+    let result = parse_and_serialize_with_source(input);
+    assert!(result.contains("| cell[^1] |"), "got:\n{}", result);
+    assert!(
+        result.contains("Heading[^2]\n-----------"),
+        "got:\n{}",
+        result
+    );
+    assert!(result.contains("[^1]: Table note."), "got:\n{}", result);
+    assert!(result.contains("[^2]: Heading note."), "got:\n{}", result);
+    assert_eq!(parse_and_serialize_with_source(&result), result);
+}
+
 #[test]
 fn test_table_warns_on_unescaped_pipe_in_cell() {
     use crate::format_with_warnings;
@@ -1806,6 +2749,53 @@ fn test_table_warns_on_unescaped_pipe_in_cell() {
     assert_eq!(result.warnings[0].line, 3);
 }
 
+#[test]
+fn test_heading_whitespace_collapsed() {
+    let result = parse_and_serialize_with_source("## Getting   started  with  `a  b`   ##");
+    assert_eq!(
+        result,
+        "Getting started with `a  b`\n---------------------------\n"
+    );
+}
+
+#[test]
+fn test_heading_trailing_colon_kept_by_default() {
+    let result = parse_and_serialize_with_source("## Changes:");
+    assert_eq!(result, "Changes:\n--------\n");
+}
+
+#[test]
+fn test_heading_strip_trailing_punctuation() {
+    let options = Options {
+        heading_strip_trailing_punctuation: vec![":".to_string(), ".".to_string()],
+        ..Options::default()
+    };
+    let result =
+        parse_and_serialize_with_options_and_warnings("## Changes in [v1](v1.md):", &options)
+            .output;
+    assert_eq!(result, "Changes in [v1](v1.md)\n----------------------\n");
+    let result = parse_and_serialize_with_options_and_warnings("### Notes.", &options).output;
+    assert_eq!(result, "### Notes\n");
+}
+
+#[test]
+fn test_heading_entirely_code_span() {
+    let options = Options {
+        heading_strip_trailing_punctuation: vec![":".to_string()],
+        ..Options::default()
+    };
+    let result =
+        parse_and_serialize_with_options_and_warnings("## `key:  value:`", &options).output;
+    assert_eq!(result, "`key:  value:`\n--------------\n");
+}
+
+#[test]
+fn test_empty_headings_kept() {
+    let input = "## \n#\n### ###\n";
+    assert_round_trip(input, "##\n\n#\n\n###\n", &Options::default());
+    assert_renders_same(input, "##\n\n#\n\n###\n");
+}
+
 #[test]
 fn test_heading_setext_h1_disabled() {
     let options = Options {
@@ -2077,19 +3067,184 @@ fn test_ordered_list_nested_long() {
     assert!(result.contains("    10) Child ten"), "got:\n{}", result);
 }
 
-// Tests for undefined reference warnings
-
 #[test]
-fn test_undefined_reference_warning() {
-    // When a reference link is used but not defined, a warning should be emitted
-    let input = "See [undefined reference] for details.";
-    let result = parse_and_serialize_with_warnings(input);
-    assert_eq!(result.warnings.len(), 1);
-    assert!(result.warnings[0].message.contains("undefined reference"));
+fn test_ordered_list_wide_marker_contains_blocks() {
+    // Items numbered 100 and beyond have 5-char markers ("120. "), so their
+    // content, including code blocks and nested lists, is indented by 5
+    let mut input = String::new();
+    for n in 1..=150 {
+        input.push_str(&format!("{}. Item {}\n", n, n));
+        if n == 120 {
+            input.push_str(
+                "     continued\n\n     ```rust\n     fn main() {}\n     ```\n\n     - nested\n",
+            );
+        }
+    }
+    let result = parse_and_serialize(&input);
     assert!(
-        result.warnings[0]
-            .message
-            .contains("undefined reference link")
+        result.contains(
+            "120. Item 120\n     continued\n\n     ~~~~ rust\n     fn main() {}\n     ~~~~\n\n      -  nested\n\n121. Item 121\n"
+        ),
+        "got:\n{}",
+        result
+    );
+    assert!(
+        result.contains("99. Item 99\n\n100. Item 100\n"),
+        "got:\n{}",
+        result
+    );
+    assert_eq!(parse_and_serialize(&result), result);
+
+    // The code block and the nested list stay inside item 120
+    let arena = Arena::new();
+    let root = parse_document(&arena, &result, &comrak_options());
+    let list = root.first_child().unwrap();
+    assert_eq!(list.children().count(), 150);
+    let item = list.children().nth(119).unwrap();
+    assert_eq!(item.children().count(), 3);
+}
+
+/// Count the list nodes in a document.
+fn count_lists(input: &str) -> usize {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &comrak_options());
+    root.descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::List(_)))
+        .count()
+}
+
+#[test]
+fn test_adjacent_bullet_lists_separated_by_comment() {
+    let input = "- a\n- b\n\n\n* c\n";
+    assert_eq!(count_lists(input), 2);
+    let result = parse_and_serialize(input);
+    assert_eq!(result, " -  a\n -  b\n\n<!-- -->\n\n -  c\n");
+    assert_eq!(count_lists(&result), 2);
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_adjacent_ordered_lists_separated_by_comment() {
+    let input = "1. a\n2. b\n\n1) c\n";
+    assert_eq!(count_lists(input), 2);
+    let result = parse_and_serialize(input);
+    assert_eq!(result, "1.  a\n2.  b\n\n<!-- -->\n\n1.  c\n");
+    assert_eq!(count_lists(&result), 2);
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_adjacent_lists_in_list_item_separated_by_comment() {
+    let input = "- x\n  - a\n  * b\n";
+    assert_eq!(count_lists(input), 3);
+    let result = parse_and_serialize(input);
+    assert_eq!(result, " -  x\n     -  a\n\n    <!-- -->\n\n     -  b\n");
+    assert_eq!(count_lists(&result), 3);
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_adjacent_lists_separated_by_alternate_marker() {
+    let options = Options {
+        list_separation: crate::ListSeparation::AlternateMarker,
+        ..Options::default()
+    };
+    let input = "- a\n\n* b\n\n+ c\n\n1. d\n\n1) e\n";
+    assert_eq!(count_lists(input), 5);
+    let result = parse_and_serialize_with_options(input, &options);
+    assert_eq!(result, " -  a\n\n *  b\n\n -  c\n\n1.  d\n\n1)  e\n");
+    assert_eq!(count_lists(&result), 5);
+    assert_eq!(parse_and_serialize_with_options(&result, &options), result);
+}
+
+#[test]
+fn test_list_spacing() {
+    let input = " -  a\n\n -  b\n\n     -  c\n     -  d\n\n -  e\n\n    More of e.\n";
+    let tight = Options {
+        list_spacing: crate::ListSpacing::Tight,
+        ..Options::default()
+    };
+    let result = parse_and_serialize_with_options(input, &tight);
+    assert_eq!(
+        result,
+        " -  a\n -  b\n     -  c\n     -  d\n -  e\n\n    More of e.\n"
+    );
+    assert_eq!(parse_and_serialize_with_options(&result, &tight), result);
+
+    let loose = Options {
+        list_spacing: crate::ListSpacing::Loose,
+        ..Options::default()
+    };
+    let result = parse_and_serialize_with_options(" -  a\n -  b\n", &loose);
+    assert_eq!(result, " -  a\n\n -  b\n");
+    assert_eq!(parse_and_serialize_with_options(&result, &loose), result);
+}
+
+fn format_with_list_continuation(input: &str, continuation: crate::ListContinuation) -> String {
+    let options = Options {
+        line_width: LineWidth::new(30).unwrap(),
+        list_continuation: continuation,
+        ..Options::default()
+    };
+    parse_and_serialize_with_options(input, &options)
+}
+
+/// A tight ordered list of ten items, the last of which is long
+fn long_tenth_item() -> String {
+    let mut input: String = (1..10).map(|i| format!("{}. Item\n", i)).collect();
+    input.push_str("10. This item has text long enough to wrap onto more lines.\n");
+    input
+}
+
+#[test]
+fn test_list_continuation_align_text() {
+    let result =
+        format_with_list_continuation(&long_tenth_item(), crate::ListContinuation::AlignText);
+    assert!(
+        result.ends_with("10. This item has text long\n    enough to wrap onto more\n    lines.\n")
+    );
+}
+
+#[test]
+fn test_list_continuation_fixed() {
+    let result = format_with_list_continuation(&long_tenth_item(), crate::ListContinuation::Fixed);
+    assert!(
+        result.ends_with("10. This item has text long\n  enough to wrap onto more\n  lines.\n")
+    );
+    assert_eq!(
+        format_with_list_continuation(&result, crate::ListContinuation::Fixed),
+        result
+    );
+}
+
+#[test]
+fn test_list_continuation_fixed_nested_blocks() {
+    let input =
+        "- This item has text long enough to wrap.\n\n  ~~~~\n  code\n  ~~~~\n\n  > A quote\n";
+    let result = format_with_list_continuation(input, crate::ListContinuation::Fixed);
+    assert_eq!(
+        result,
+        " -  This item has text long\n  enough to wrap.\n\n    ~~~~\n    code\n    ~~~~\n\n    > A quote\n"
+    );
+    assert_eq!(
+        format_with_list_continuation(&result, crate::ListContinuation::Fixed),
+        result
+    );
+}
+
+// Tests for undefined reference warnings
+
+#[test]
+fn test_undefined_reference_warning() {
+    // When a reference link is used but not defined, a warning should be emitted
+    let input = "See [undefined reference] for details.";
+    let result = parse_and_serialize_with_warnings(input);
+    assert_eq!(result.warnings.len(), 1);
+    assert!(result.warnings[0].message.contains("undefined reference"));
+    assert!(
+        result.warnings[0]
+            .message
+            .contains("undefined reference link")
     );
 }
 
@@ -2129,6 +3284,40 @@ fn test_undefined_full_reference_warning() {
     assert!(result.warnings[0].message.contains("undefined-label"));
 }
 
+#[test]
+fn test_undefined_link_fragment_warning() {
+    let input = "Usage\n-----\n\nSee [usage](#usage) and [x](#missing-section).\n";
+    let result = parse_and_serialize_with_warnings(input);
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].line, 4);
+    assert_eq!(
+        result.warnings[0].message,
+        "undefined link fragment: #missing-section"
+    );
+}
+
+#[test]
+fn test_link_fragment_matches_duplicate_and_unicode_headings() {
+    let input = r#"## Setup
+
+## Setup
+
+## 설치 방법
+
+See [one](#setup), [two](#setup-1), [three](#설치-방법),
+[encoded](#%EC%84%A4%EC%B9%98-%EB%B0%A9%EB%B2%95), and [case](#Setup).
+"#;
+    let result = parse_and_serialize_with_warnings(input);
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+}
+
+#[test]
+fn test_link_fragment_matches_html_id() {
+    let input = "<a id=\"custom\"></a>\n\nSee [custom](#custom).\n";
+    let result = parse_and_serialize_with_warnings(input);
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+}
+
 #[test]
 fn test_abbreviation_definition_no_warning() {
     // PHP Markdown Extra abbreviation definitions (*[ABBR]: Full Text)
@@ -2388,7 +3577,9 @@ fn test_definition_list_with_alert_as_first_child() {
     // Alert as first child in definition list (note: `:   >` format required)
     let input = "term\n:   > [!TIP]\n    > This is a tip.";
     let result = parse_and_serialize(input);
-    assert_eq!(result, "term\n:\n    > [!TIP]\n    > This is a tip.\n");
+    // The spaces after the marker keep the alert in the definition
+    assert_eq!(result, "term\n:   \n    > [!TIP]\n    > This is a tip.\n");
+    assert_eq!(parse_and_serialize(&result), result);
 }
 
 #[test]
@@ -2506,6 +3697,17 @@ fn test_multiple_trailing_html_comments() {
     );
 }
 
+#[test]
+fn test_line_break_in_inline_html_not_hard_break() {
+    for input in [
+        "foo <!-- this is a --\ncomment - with hyphens -->\n",
+        "<a  /><b2\ndata=\"foo\" >\n",
+        "<a href=\"foo  \nbar\">\n",
+    ] {
+        assert_round_trip(input, input, &Options::default());
+    }
+}
+
 #[test]
 fn test_html_comment_not_at_end_stays_in_place() {
     // HTML comments that are not at the end should stay in their original position
@@ -2658,10 +3860,10 @@ Blocks are usually used for paragraphs.
     // The footnote definition should come before the reference definitions
     let footnote_pos = result.find("[^1]:").expect("footnote not found");
     let ref1_pos = result
-        .find("[`TContextData`]:")
+        .find("[`tcontextdata`]:")
         .expect("TContextData ref not found");
     let ref2_pos = result
-        .find("[Fedify context data]:")
+        .find("[fedify context data]:")
         .expect("Fedify context data ref not found");
 
     assert!(
@@ -2713,8 +3915,8 @@ More content here.
         .find("Second section")
         .expect("Second section not found");
     let footnote_pos = result.find("[^1]:").expect("footnote not found");
-    let ref1_pos = result.find("[`Link1`]:").expect("Link1 ref not found");
-    let ref2_pos = result.find("[Link2]:").expect("Link2 ref not found");
+    let ref1_pos = result.find("[`link1`]:").expect("Link1 ref not found");
+    let ref2_pos = result.find("[link2]:").expect("Link2 ref not found");
 
     // All should be between first and second section
     assert!(
@@ -2781,6 +3983,17 @@ fn test_preserve_numeric_html_entity() {
     assert_eq!(result, "Entity: &#60;tag&#62;\n");
 }
 
+#[test]
+fn test_preserve_long_and_multi_character_entities() {
+    // &ngE; stands for two code points, and &ClockwiseContourIntegral; is
+    // longer than most names
+    let input = "&ClockwiseContourIntegral; &ngE; &fjlig;\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(result, input);
+    let result = parse_and_serialize_with_entities(input, crate::EntityMode::Decode);
+    assert_eq!(result, "\u{2232} \u{2267}\u{338} fj\n");
+}
+
 #[test]
 fn test_preserve_actual_html_tags() {
     // Actual HTML tags should be kept as-is (not escaped)
@@ -2800,6 +4013,70 @@ fn test_mixed_html_and_entities() {
     assert_eq!(result, "Use <code>&lt;div&gt;</code> for containers.\n");
 }
 
+fn parse_and_serialize_with_entities(input: &str, entities: crate::EntityMode) -> String {
+    let options = Options {
+        entities,
+        ..Options::default()
+    };
+    parse_and_serialize_with_options_and_warnings(input, &options).output
+}
+
+#[test]
+fn test_entities_decode() {
+    let input = "Hello&nbsp;world, &copy; 2025, 2&#215;3\n";
+    let result = parse_and_serialize_with_entities(input, crate::EntityMode::Decode);
+    assert_eq!(result, "Hello\u{a0}world, \u{a9} 2025, 2\u{d7}3\n");
+}
+
+#[test]
+fn test_entities_decode_keeps_punctuation_entities() {
+    // Decoded, these would read as an HTML tag and an entity
+    let input = "Write &lt;div&gt; or &amp;lt; for it.\n";
+    let result = parse_and_serialize_with_entities(input, crate::EntityMode::Decode);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_entities_encode_invisible() {
+    let input = "Hello&#160;world, soft\u{ad}hyphen, &copy; 2025\n";
+    let result = parse_and_serialize_with_entities(input, crate::EntityMode::EncodeInvisible);
+    assert_eq!(result, "Hello&nbsp;world, soft&shy;hyphen, \u{a9} 2025\n");
+}
+
+#[test]
+fn test_entities_encode_invisible_not_in_code() {
+    let input = "A `no\u{a0}break` space\n";
+    let result = parse_and_serialize_with_entities(input, crate::EntityMode::EncodeInvisible);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_entities_escaped_ampersand() {
+    for entities in [
+        crate::EntityMode::Preserve,
+        crate::EntityMode::Decode,
+        crate::EntityMode::EncodeInvisible,
+    ] {
+        let input = "Write \\&copy; or &amp;lt; for it.\n";
+        assert_eq!(parse_and_serialize_with_entities(input, entities), input);
+    }
+}
+
+#[test]
+fn test_ampersand_before_text_not_escaped() {
+    let input = "AT&T and R&D, &copy without a semicolon, &bogus;\n";
+    let result = parse_and_serialize_with_source(input);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_ampersand_starting_entity_escaped_without_source() {
+    // Text built without a source, like decoded `&amp;copy;`, would read
+    // back as an entity
+    let result = parse_and_serialize("Write &amp;copy; or &amp;#215; for it.");
+    assert_eq!(result, "Write \\&copy; or \\&#215; for it.\n");
+}
+
 #[test]
 fn test_footnote_definitions_before_reference_definitions() {
     // When a section has both footnote definitions and link reference definitions,
@@ -2928,6 +4205,17 @@ fn test_multiple_hard_line_breaks_in_blockquote() {
     );
 }
 
+#[test]
+fn test_hard_line_break_in_nested_list_item() {
+    let input = "- Outer\n  - Middle\n    - First line  \n      second line\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        " -  Outer\n     -  Middle\n         -  First line  \n            second line\n"
+    );
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
 #[test]
 fn test_hard_line_break_in_alert() {
     // Hard line breaks in GitHub alerts should preserve the `>` prefix.
@@ -3208,8 +4496,8 @@ fn test_wrap_korean_in_list_item() {
     assert_eq!(
         result,
         r#"
- -  이것은 매우 긴 한국어 문장입니다 여러
-    줄로 나누어져야 합니다
+ -  이것은 매우 긴 한국어 문장입니다
+    여러 줄로 나누어져야 합니다
 "#
         .trim_start_matches('\n')
     );
@@ -3264,6 +4552,68 @@ fn test_setext_h2_with_fullwidth_characters() {
     );
 }
 
+#[test]
+fn test_hard_line_break_in_h2() {
+    let input = "First line\\\nsecond line\n---\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(result, "First line  \nsecond line\n-----------\n");
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_hard_line_break_in_h2_forces_setext() {
+    let options = Options {
+        setext_h2: false,
+        ..Options::default()
+    };
+    let input = "First line\\\nsecond line\n---\n";
+    let result = parse_and_serialize_with_options(input, &options);
+    assert_eq!(result, "First line  \nsecond line\n-----------\n");
+}
+
+#[test]
+fn test_hard_line_break_in_heading_collapsed() {
+    let options = Options {
+        heading_allow_multiline: false,
+        setext_h2: false,
+        ..Options::default()
+    };
+    let input = "First line\\\nsecond line\n---\n";
+    let result = parse_and_serialize_with_options(input, &options);
+    assert_eq!(result, "## First line second line\n");
+}
+
+#[test]
+fn test_setext_headings_in_block_quotes() {
+    let input = "> Title\n> =====\n>\n> > Section\n> > -------\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(result, input);
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_atx_headings_in_block_quotes() {
+    let options = Options {
+        setext_h1: false,
+        setext_h2: false,
+        ..Options::default()
+    };
+    let input = "> Title\n> =====\n>\n> > Section\n> > -------\n";
+    let result = parse_and_serialize_with_options(input, &options);
+    assert_eq!(result, "> # Title\n>\n> > ## Section\n");
+}
+
+#[test]
+fn test_hard_line_break_in_heading_in_block_quote() {
+    let input =
+        "> First line\\\n> second line\n> ===\n>\n> > First line\\\n> > second line\n> > ---\n";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        "> # First line second line\n>\n> > ## First line second line\n"
+    );
+}
+
 // =============================================================================
 // Punctuation Transformation Tests (SmartyPants-style)
 // =============================================================================
@@ -3291,8 +4641,10 @@ fn test_punctuation_curly_double_quotes_in_paragraph() {
 
 #[test]
 fn test_punctuation_curly_double_quotes_disabled() {
-    let mut options = Options::default();
-    options.curly_double_quotes = false;
+    let options = Options {
+        curly_double_quotes: false,
+        ..Options::default()
+    };
     let input = "He said \"hello\" to her.";
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "He said \"hello\" to her.\n");
@@ -3312,8 +4664,10 @@ fn test_punctuation_curly_single_quotes_in_paragraph() {
 
 #[test]
 fn test_punctuation_curly_single_quotes_disabled() {
-    let mut options = Options::default();
-    options.curly_single_quotes = false;
+    let options = Options {
+        curly_single_quotes: false,
+        ..Options::default()
+    };
     let input = "She said 'hello' to him.";
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "She said 'hello' to him.\n");
@@ -3330,8 +4684,10 @@ fn test_punctuation_ellipsis_in_paragraph() {
 
 #[test]
 fn test_punctuation_ellipsis_disabled() {
-    let mut options = Options::default();
-    options.ellipsis = false;
+    let options = Options {
+        ellipsis: false,
+        ..Options::default()
+    };
     let input = "Wait for it...";
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "Wait for it...\n");
@@ -3348,8 +4704,10 @@ fn test_punctuation_em_dash_default() {
 
 #[test]
 fn test_punctuation_em_dash_disabled() {
-    let mut options = Options::default();
-    options.em_dash = crate::DashSetting::Disabled;
+    let options = Options {
+        em_dash: crate::DashSetting::Disabled,
+        ..Options::default()
+    };
     let input = "Hello--world";
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "Hello--world\n");
@@ -3357,9 +4715,10 @@ fn test_punctuation_em_dash_disabled() {
 
 #[test]
 fn test_punctuation_em_dash_triple_hyphen() {
-    let mut options = Options::default();
-    options.em_dash =
-        crate::DashSetting::Pattern(crate::DashPattern::new("---".to_string()).unwrap());
+    let options = Options {
+        em_dash: crate::DashSetting::Pattern(crate::DashPattern::new("---".to_string()).unwrap()),
+        ..Options::default()
+    };
     let input = "Hello---world";
     let result = parse_and_serialize_with_options(input, &options);
     let expected = format!("Hello{}world\n", EM_DASH);
@@ -3378,11 +4737,11 @@ fn test_punctuation_en_dash_disabled_by_default() {
 
 #[test]
 fn test_punctuation_en_dash_enabled() {
-    let mut options = Options::default();
-    options.em_dash =
-        crate::DashSetting::Pattern(crate::DashPattern::new("---".to_string()).unwrap());
-    options.en_dash =
-        crate::DashSetting::Pattern(crate::DashPattern::new("--".to_string()).unwrap());
+    let options = Options {
+        em_dash: crate::DashSetting::Pattern(crate::DashPattern::new("---".to_string()).unwrap()),
+        en_dash: crate::DashSetting::Pattern(crate::DashPattern::new("--".to_string()).unwrap()),
+        ..Options::default()
+    };
     let input = "Pages 10--20 and a long---dash";
     let result = parse_and_serialize_with_options(input, &options);
     let expected = format!("Pages 10{}20 and a long{}dash\n", EN_DASH, EM_DASH);
@@ -3399,8 +4758,10 @@ fn test_punctuation_apostrophes_disabled_by_default() {
 
 #[test]
 fn test_punctuation_apostrophes_enabled() {
-    let mut options = Options::default();
-    options.curly_apostrophes = true;
+    let options = Options {
+        curly_apostrophes: true,
+        ..Options::default()
+    };
     let input = "It's a test";
     let result = parse_and_serialize_with_options(input, &options);
     let expected = format!("It{}s a test\n", RIGHT_SINGLE_QUOTE);
@@ -3486,8 +4847,10 @@ fn test_punctuation_preserve_existing_curly_quotes() {
 fn test_punctuation_all_transforms_combined() {
     // Test multiple punctuation transforms in one paragraph
     let input = "He said \"It's... amazing--isn't it?\"";
-    let mut options = Options::default();
-    options.curly_apostrophes = true;
+    let options = Options {
+        curly_apostrophes: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
 
     // Should have curly double quotes
@@ -3518,13 +4881,15 @@ fn test_punctuation_all_transforms_combined() {
 
 #[test]
 fn test_punctuation_all_disabled() {
-    let mut options = Options::default();
-    options.curly_double_quotes = false;
-    options.curly_single_quotes = false;
-    options.curly_apostrophes = false;
-    options.ellipsis = false;
-    options.em_dash = crate::DashSetting::Disabled;
-    options.en_dash = crate::DashSetting::Disabled;
+    let options = Options {
+        curly_double_quotes: false,
+        curly_single_quotes: false,
+        curly_apostrophes: false,
+        ellipsis: false,
+        em_dash: crate::DashSetting::Disabled,
+        en_dash: crate::DashSetting::Disabled,
+        ..Options::default()
+    };
 
     let input = "He said \"It's... amazing--isn't it?\"";
     let result = parse_and_serialize_with_options(input, &options);
@@ -3542,7 +4907,7 @@ fn test_punctuation_bracket_possessive_stays_straight() {
     // The apostrophe should remain straight
     assert_eq!(
         result,
-        "This package provides [Fedify]'s API.\n\n[Fedify]: https://fedify.dev/\n"
+        "This package provides [Fedify]'s API.\n\n[fedify]: https://fedify.dev/\n"
     );
 }
 
@@ -3550,13 +4915,15 @@ fn test_punctuation_bracket_possessive_stays_straight() {
 fn test_punctuation_bracket_possessive_curly_when_enabled() {
     // Possessive apostrophe after closing bracket should become curly
     // when curly_apostrophes is enabled
-    let mut options = Options::default();
-    options.curly_apostrophes = true;
+    let options = Options {
+        curly_apostrophes: true,
+        ..Options::default()
+    };
 
     let input = "This package provides [Fedify]'s API.\n\n[Fedify]: https://fedify.dev/\n";
     let result = parse_and_serialize_with_options(input, &options);
     let expected = format!(
-        "This package provides [Fedify]{}s API.\n\n[Fedify]: https://fedify.dev/\n",
+        "This package provides [Fedify]{}s API.\n\n[fedify]: https://fedify.dev/\n",
         RIGHT_SINGLE_QUOTE
     );
     assert_eq!(result, expected);
@@ -3575,9 +4942,10 @@ fn test_punctuation_decade_abbreviation() {
 #[test]
 fn test_punctuation_single_hyphen_em_dash_with_spaces() {
     // Single hyphen with spaces should transform when em_dash = "-"
-    let mut options = Options::default();
-    options.em_dash =
-        crate::DashSetting::Pattern(crate::DashPattern::new("-".to_string()).unwrap());
+    let options = Options {
+        em_dash: crate::DashSetting::Pattern(crate::DashPattern::new("-".to_string()).unwrap()),
+        ..Options::default()
+    };
     let input = "word - word";
     let result = parse_and_serialize_with_options(input, &options);
     let expected = format!("word {} word\n", EM_DASH);
@@ -3587,9 +4955,10 @@ fn test_punctuation_single_hyphen_em_dash_with_spaces() {
 #[test]
 fn test_punctuation_single_hyphen_em_dash_without_spaces() {
     // Single hyphen without spaces should NOT transform when em_dash = "-"
-    let mut options = Options::default();
-    options.em_dash =
-        crate::DashSetting::Pattern(crate::DashPattern::new("-".to_string()).unwrap());
+    let options = Options {
+        em_dash: crate::DashSetting::Pattern(crate::DashPattern::new("-".to_string()).unwrap()),
+        ..Options::default()
+    };
     let input = "word-word";
     let result = parse_and_serialize_with_options(input, &options);
     // Hyphen should remain because it's not surrounded by spaces
@@ -3623,8 +4992,10 @@ fn test_nested_list_followed_by_paragraph_no_extra_blank_line() {
 #[test]
 fn test_heading_sentence_case_basic() {
     let input = "# Hello World";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "Hello world\n===========\n");
 }
@@ -3632,8 +5003,10 @@ fn test_heading_sentence_case_basic() {
 #[test]
 fn test_heading_sentence_case_with_acronyms() {
     let input = "# Working With HTTP APIs";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "Working with HTTP APIs\n======================\n");
 }
@@ -3641,8 +5014,10 @@ fn test_heading_sentence_case_with_acronyms() {
 #[test]
 fn test_heading_sentence_case_with_proper_nouns() {
     let input = "# Introduction To JavaScript";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(
         result,
@@ -3653,9 +5028,11 @@ fn test_heading_sentence_case_with_proper_nouns() {
 #[test]
 fn test_heading_sentence_case_with_user_proper_nouns() {
     let input = "# Getting Started With MyAPI";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
-    options.heading_proper_nouns = vec!["MyAPI".to_string()];
+    let options = Options {
+        heading_sentence_case: true,
+        heading_proper_nouns: vec!["MyAPI".to_string()],
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(
         result,
@@ -3666,8 +5043,10 @@ fn test_heading_sentence_case_with_user_proper_nouns() {
 #[test]
 fn test_heading_sentence_case_with_code_spans() {
     let input = "# Using `MyClass` In Your Code";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(
         result,
@@ -3686,8 +5065,10 @@ fn test_heading_sentence_case_disabled() {
 #[test]
 fn test_heading_sentence_case_atx_style() {
     let input = "### Working With APIs";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "### Working with APIs\n");
 }
@@ -3695,8 +5076,10 @@ fn test_heading_sentence_case_atx_style() {
 #[test]
 fn test_heading_sentence_case_with_quotes() {
     let input = "# Smart Suggestion: \"Did You Mean?\"";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(
         result,
@@ -3707,8 +5090,10 @@ fn test_heading_sentence_case_with_quotes() {
 #[test]
 fn test_heading_sentence_case_non_latin() {
     let input = "# \u{D55C}\u{AE00} \u{C81C}\u{BAA9} With English";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(
         result,
@@ -3722,8 +5107,10 @@ fn test_heading_sentence_case_starting_with_code_span() {
     // following the code span should NOT be capitalized (the code span itself
     // counts as the first word).
     let input = "# `Foo` object";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "`Foo` object\n============\n");
 }
@@ -3734,8 +5121,10 @@ fn test_heading_sentence_case_proper_noun_in_parentheses() {
     // Even though "Deno" is in the built-in proper nouns list, it was being
     // lowercased because find_proper_noun() didn't strip leading punctuation.
     let input = "# Test (Deno only)";
-    let mut options = Options::default();
-    options.heading_sentence_case = true;
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
     let result = parse_and_serialize_with_options(input, &options);
     assert_eq!(result, "Test (Deno only)\n================\n");
 }
@@ -3882,8 +5271,10 @@ fn test_code_block_formatter_timeout() {
 fn test_code_block_formatter_with_default_language() {
     use crate::CodeFormatter;
 
-    let mut options = Options::default();
-    options.default_language = "text".to_string();
+    let mut options = Options {
+        default_language: "text".to_string(),
+        ..Options::default()
+    };
     options.code_formatters.insert(
         "text".to_string(),
         CodeFormatter {
@@ -3992,6 +5383,86 @@ fn test_code_block_no_format_multiple_metadata() {
     assert!(result.contains("some-other-info"));
 }
 
+// Built-in JSON and TOML formatting
+
+fn format_embedded_options() -> Options {
+    Options {
+        code_block_format_embedded: vec![
+            crate::EmbeddedLanguage::Json,
+            crate::EmbeddedLanguage::Toml,
+        ],
+        ..Options::default()
+    }
+}
+
+#[test]
+fn test_format_embedded_json() {
+    let input = "~~~~ json title=\"package.json\"\n{\"name\":\"x\", \"version\": \"1.0.0\",\n    \"files\": [\"a\"]}\n~~~~\n";
+    let result = parse_and_serialize_with_options(input, &format_embedded_options());
+    assert_eq!(
+        result,
+        "~~~~ json title=\"package.json\"\n{\n  \"name\": \"x\",\n  \"version\": \"1.0.0\",\n  \
+         \"files\": [\n    \"a\"\n  ]\n}\n~~~~\n"
+    );
+    assert_eq!(
+        parse_and_serialize_with_options(&result, &format_embedded_options()),
+        result
+    );
+}
+
+#[test]
+fn test_format_embedded_json_sort_keys() {
+    let options = Options {
+        code_block_json_key_order: crate::JsonKeyOrder::Sort,
+        ..format_embedded_options()
+    };
+    let input = "~~~~ json\n{\"b\": 1, \"a\": 2}\n~~~~\n";
+    let result = parse_and_serialize_with_options(input, &options);
+    assert_eq!(result, "~~~~ json\n{\n  \"a\": 2,\n  \"b\": 1\n}\n~~~~\n");
+}
+
+#[test]
+fn test_format_embedded_invalid() {
+    for input in [
+        "Text.\n\n~~~~ json\n{\"a\": 1,}\n~~~~\n",
+        "Text.\n\n~~~~ toml\na = \n~~~~\n",
+    ] {
+        let result =
+            parse_and_serialize_with_options_and_warnings(input, &format_embedded_options());
+        assert_eq!(result.output, input);
+        assert_eq!(result.warnings.len(), 1, "{:?}", result.warnings);
+        assert_eq!(result.warnings[0].line, 3);
+        assert!(result.warnings[0].message.contains("doesn't parse"));
+    }
+}
+
+#[test]
+fn test_format_embedded_toml() {
+    let input = "~~~~ toml\n[code_block]\n  format_embedded=[\"json\"]   # JSON only\n\n\nkey  =  1\n~~~~\n";
+    let result = parse_and_serialize_with_options(input, &format_embedded_options());
+    assert_eq!(
+        result,
+        "~~~~ toml\n[code_block]\nformat_embedded = [\"json\"]   # JSON only\n\nkey = 1\n~~~~\n"
+    );
+}
+
+#[test]
+fn test_format_embedded_already_formatted() {
+    let input = "~~~~ json\n{\n  \"a\": [\n    1,\n    2\n  ]\n}\n~~~~\n\n~~~~ toml\n# Comment\n[a]\nb = \"c\"\n~~~~\n";
+    let result = parse_and_serialize_with_options_and_warnings(input, &format_embedded_options());
+    assert_eq!(result.output, input);
+    assert!(result.warnings.is_empty());
+}
+
+#[test]
+fn test_format_embedded_off_by_default() {
+    let input = "~~~~ json\n{\"a\":1}\n~~~~\n";
+    assert_eq!(
+        parse_and_serialize_with_options(input, &Options::default()),
+        input
+    );
+}
+
 // =============================================================================
 // Regression tests for hongdown-disable directive with footnotes/references
 // =============================================================================
@@ -4048,7 +5519,7 @@ Some content here.
 
     // The reference definition should appear BEFORE the disable directive
     let reference_pos = result
-        .find("[Example]:")
+        .find("[example]:")
         .expect("reference definition not found");
     let disable_pos = result
         .find("<!-- hongdown-disable -->")
@@ -4315,8 +5786,10 @@ fn test_possessive_apostrophe_after_digit_stays_straight() {
 fn test_possessive_apostrophe_after_digit_curly_when_enabled() {
     // Possessive apostrophe after a digit should become curly
     // when curly_apostrophes is enabled
-    let mut options = Options::default();
-    options.curly_apostrophes = true;
+    let options = Options {
+        curly_apostrophes: true,
+        ..Options::default()
+    };
     let input = "Version 1.2.3's highlight.";
     let result = parse_and_serialize_with_options(input, &options);
     let expected = format!("Version 1.2.3{}s highlight.\n", RIGHT_SINGLE_QUOTE);
@@ -4338,8 +5811,8 @@ fn test_serialize_windows_path_in_italic() {
 fn test_serialize_windows_path_idempotent() {
     let input = r"*C:\Users\Alice\Documents*";
     let first_pass = parse_and_serialize_with_source(input);
-    let second_pass = parse_and_serialize_with_source(&first_pass.trim_end());
-    let third_pass = parse_and_serialize_with_source(&second_pass.trim_end());
+    let second_pass = parse_and_serialize_with_source(first_pass.trim_end());
+    let third_pass = parse_and_serialize_with_source(second_pass.trim_end());
 
     assert_eq!(
         first_pass, second_pass,
@@ -4366,7 +5839,7 @@ fn test_serialize_multiple_backslashes() {
     assert_eq!(result, "*path\\\\to\\\\file*\n");
 
     // Test idempotency
-    let second_pass = parse_and_serialize_with_source(&result.trim_end());
+    let second_pass = parse_and_serialize_with_source(result.trim_end());
     assert_eq!(result, second_pass);
 }
 
@@ -4734,3 +6207,2960 @@ fn test_footnote_respects_custom_line_width_with_ordered_list() {
         result
     );
 }
+
+// Tests for the wrap option
+
+fn parse_and_serialize_with_wrap(input: &str, wrap: WrapMode) -> String {
+    let options = Options {
+        wrap,
+        line_width: LineWidth::new(20).unwrap(),
+        ..Options::default()
+    };
+    parse_and_serialize_with_options(input, &options)
+}
+
+#[test]
+fn test_wrap_none_joins_paragraph() {
+    let input = "First line\nsecond line that is quite a bit longer than twenty.\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::None);
+    assert_eq!(
+        result,
+        "First line second line that is quite a bit longer than twenty.\n"
+    );
+}
+
+#[test]
+fn test_wrap_none_joins_list_item() {
+    let input = "- item\n  continued over several lines\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::None);
+    assert_eq!(result, " -  item continued over several lines\n");
+}
+
+#[test]
+fn test_wrap_preserve_keeps_line_breaks() {
+    let input = "A line that is longer than twenty columns\nshort\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Preserve);
+    assert_eq!(result, "A line that is longer than twenty columns\nshort\n");
+}
+
+#[test]
+fn test_wrap_width_rewraps_long_lines() {
+    let input = "A line that is longer than twenty columns\nshort\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Width);
+    assert_eq!(
+        result,
+        "A line that is\nlonger than twenty\ncolumns short\n"
+    );
+}
+
+#[test]
+fn test_wrap_sentence_breaks_after_sentences() {
+    let input = "The first sentence is long enough to pass the line width.  The second\none! Is this the third? Yes.\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Sentence);
+    assert_eq!(
+        result,
+        "The first sentence is long enough to pass the line width.\nThe second one!\nIs this the third?\nYes.\n"
+    );
+}
+
+#[test]
+fn test_wrap_sentence_abbreviations() {
+    let input =
+        "Ask Dr. Kim, e.g. About it.  See J. R. R. Tolkien and version 1.2. Done. and more.\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Sentence);
+    assert_eq!(
+        result,
+        "Ask Dr. Kim, e.g. About it.\nSee J. R. R. Tolkien and version 1.2. Done. and more.\n"
+    );
+}
+
+#[test]
+fn test_wrap_sentence_quotes_and_parentheses() {
+    let input = "He said (\"It works.\") Then he left. \"Really?\" Yes.\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Sentence);
+    assert_eq!(
+        result,
+        "He said (“It works.”)\nThen he left.\n“Really?”\nYes.\n"
+    );
+}
+
+#[test]
+fn test_wrap_sentence_keeps_code_spans_and_links() {
+    let input = "Run `a. B` now. Read [Dr. Who. Season 1](doctor.md) too. Fine.\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Sentence);
+    assert_eq!(
+        result,
+        "Run `a. B` now.\nRead [Dr. Who. Season 1](doctor.md) too.\nFine.\n"
+    );
+}
+
+#[test]
+fn test_wrap_sentence_korean() {
+    let input = "홍다운은 마크다운 포매터입니다. 한국어 문서도 잘 다룹니다.\n\n日本語の文です。次の文です。\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Sentence);
+    assert_eq!(
+        result,
+        "홍다운은 마크다운 포매터입니다.\n한국어 문서도 잘 다룹니다.\n\n日本語の文です。\n次の文です。\n"
+    );
+}
+
+#[test]
+fn test_wrap_sentence_avoids_block_markers() {
+    let input = "Count to 10. 1. That is not a list.\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Sentence);
+    assert_eq!(result, "Count to 10. 1.\nThat is not a list.\n");
+}
+
+#[test]
+fn test_wrap_sentence_in_list_item_is_idempotent() {
+    let input = " -  One sentence. Another sentence.\n";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::Sentence);
+    assert_eq!(result, " -  One sentence.\n    Another sentence.\n");
+    assert_eq!(
+        parse_and_serialize_with_wrap(&result, WrapMode::Sentence),
+        result
+    );
+}
+
+#[test]
+fn test_wrap_accounts_for_quote_and_list_prefixes() {
+    let words = "lorem ipsum dolor sit amet ".repeat(12);
+    let input = format!(" -  Item\n\n     -  Nested\n\n        > > > {}\n", words);
+    let result = parse_and_serialize(&input);
+    let quoted: Vec<&str> = result
+        .lines()
+        .filter(|line| line.starts_with("        > > > "))
+        .collect();
+    assert!(quoted.len() > 1);
+    for line in &quoted {
+        assert!(line.width() <= 80, "line too long: {:?}", line);
+    }
+    assert!(quoted.iter().any(|line| line.width() > 70));
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_wrap_first_line_of_list_item_accounts_for_marker() {
+    let input = format!(" -  {}\n", "ab ".repeat(30));
+    let result = parse_and_serialize(&input);
+    assert_eq!(
+        result.lines().next().unwrap(),
+        format!(" -  {}", ["ab"; 25].join(" "))
+    );
+}
+
+#[test]
+fn test_wrap_deep_nesting_keeps_minimum_width() {
+    let input = format!("> > > > > > > > > > {}\n", "word ".repeat(12));
+    let result = parse_and_serialize_with_width(&input, 30);
+    let prefix = "> ".repeat(10);
+    assert_eq!(
+        result,
+        format!(
+            "{prefix}word word word word\n{prefix}word word word word\n{prefix}word word word word\n"
+        )
+    );
+}
+
+#[test]
+fn test_wrap_counts_zero_width_space_as_zero_columns() {
+    // Without the zero width space counting as zero columns, the first line
+    // would be one column too long
+    let input = "aaaa bbbb cccc dd\u{200B}d eeee\n";
+    let result = parse_and_serialize_with_width(input, 17);
+    assert_eq!(result, "aaaa bbbb cccc\ndd\u{200B}d eeee\n");
+    let input = "aaaa bbbb \u{200B}cccc eeee\n";
+    let result = parse_and_serialize_with_width(input, 14);
+    assert_eq!(result, "aaaa bbbb\n\u{200B}cccc eeee\n");
+}
+
+#[test]
+fn test_table_cells_with_bidi_marks_align() {
+    let input = "| Name | Note |\n| --- | --- |\n| \u{200F}שלום\u{200F} | a |\n| abcde | b |\n";
+    let result = parse_and_serialize_with_table(input);
+    assert_eq!(
+        result,
+        "| Name  | Note |\n| ----- | ---- |\n| \u{200F}שלום\u{200F}  | a    |\n| abcde | b    |\n"
+    );
+}
+
+#[test]
+fn test_byte_order_mark_kept() {
+    let input = "\u{FEFF}Title\n=====\n\nText.\n";
+    assert_eq!(parse_and_serialize_with_source(input), input);
+}
+
+fn format_toc(input: &str, options: &Options) -> String {
+    parse_and_serialize_with_options_and_warnings(input, options).output
+}
+
+#[test]
+fn test_toc_generated_between_markers() {
+    let input = r#"Title
+=====
+
+<!-- toc -->
+<!-- /toc -->
+
+Installation
+------------
+
+### From source
+
+Usage
+-----
+"#;
+    let expected = r#"Title
+=====
+
+<!-- toc -->
+
+ -  [Installation](#installation)
+     -  [From source](#from-source)
+ -  [Usage](#usage)
+
+<!-- /toc -->
+
+
+Installation
+------------
+
+### From source
+
+Usage
+-----
+"#;
+    let result = format_toc(input, &Options::default());
+    assert_eq!(result, expected);
+    assert_eq!(format_toc(&result, &Options::default()), expected);
+}
+
+#[test]
+fn test_toc_nested_levels_skipping_a_level() {
+    let options = Options {
+        toc_min_level: crate::HeadingLevel::new(1).unwrap(),
+        toc_max_level: crate::HeadingLevel::new(6).unwrap(),
+        ..Options::default()
+    };
+    let input = r#"<!-- hongdown-toc -->
+<!-- /hongdown-toc -->
+
+# Guide
+
+#### Deep note
+
+### Section
+
+## Chapter
+
+#### Another deep note
+"#;
+    let result = format_toc(input, &options);
+    assert!(
+        result.starts_with(
+            r#"<!-- hongdown-toc -->
+
+ -  [Guide](#guide)
+     -  [Deep note](#deep-note)
+     -  [Section](#section)
+     -  [Chapter](#chapter)
+         -  [Another deep note](#another-deep-note)
+
+<!-- /hongdown-toc -->
+"#
+        ),
+        "got:\n{}",
+        result
+    );
+    assert_eq!(format_toc(&result, &options), result);
+}
+
+#[test]
+fn test_toc_replaces_previous_content() {
+    let input = r#"<!-- toc -->
+
+ -  [Old entry](#old-entry)
+
+Old heading
+-----------
+
+<!-- /toc -->
+
+Setup
+-----
+
+~~~~ markdown
+## Not a heading
+~~~~
+
+Setup
+-----
+"#;
+    let result = format_toc(input, &Options::default());
+    assert!(
+        result.starts_with(
+            "<!-- toc -->\n\n -  [Setup](#setup)\n -  [Setup](#setup-1)\n\n<!-- /toc -->\n"
+        ),
+        "got:\n{}",
+        result
+    );
+    assert!(!result.contains("Old"), "got:\n{}", result);
+}
+
+#[test]
+fn test_toc_entry_text_follows_heading_options() {
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
+    let input = r#"<!-- toc -->
+<!-- /toc -->
+
+## The `format()` *Function* -- Explained
+"#;
+    let result = format_toc(input, &options);
+    assert!(
+        result.contains(
+            " -  [The `format()` function — Explained](#the-format-function--explained)\n"
+        ),
+        "got:\n{}",
+        result
+    );
+    assert_eq!(format_toc(&result, &options), result);
+}
+
+#[test]
+fn test_toc_without_end_marker_left_alone() {
+    let input = "<!-- toc -->\n\n\nSetup\n-----\n";
+    let result = format_toc(input, &Options::default());
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_toc_without_headings() {
+    let input = "<!-- toc -->\n - [Stale](#stale)\n<!-- /toc -->\n\nText.\n";
+    let result = format_toc(input, &Options::default());
+    assert_eq!(result, "<!-- toc -->\n\n<!-- /toc -->\n\nText.\n");
+}
+
+fn parse_and_serialize_with_url_encoding(input: &str, encoding: UrlEncoding) -> String {
+    let options = Options {
+        link_url_encoding: encoding,
+        ..Options::default()
+    };
+    parse_and_serialize_with_options_and_warnings(input, &options).output
+}
+
+#[test]
+fn test_url_encoding_korean_path() {
+    let input = "See [the guide](문서/시작하기.md).\n";
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::Encode);
+    assert_eq!(
+        result,
+        "See [the guide](%EB%AC%B8%EC%84%9C/%EC%8B%9C%EC%9E%91%ED%95%98%EA%B8%B0.md).\n"
+    );
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::DecodeUnreserved);
+    assert_eq!(result, input);
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::Preserve);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_url_encoding_space() {
+    let input = "![Diagram](<images/flow chart.png>)\n";
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::Encode);
+    assert_eq!(result, "![Diagram](images/flow%20chart.png)\n");
+    // A URL with a space can only be written in angle brackets
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::Preserve);
+    assert_eq!(result, input);
+    let result = parse_and_serialize(input);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_url_encoding_already_encoded() {
+    let input = "See [the guide](%EB%AC%B8%EC%84%9C/a%20b.md).\n";
+    for encoding in [
+        UrlEncoding::Preserve,
+        UrlEncoding::Encode,
+        UrlEncoding::DecodeUnreserved,
+    ] {
+        let result = parse_and_serialize_with_url_encoding(input, encoding);
+        assert_eq!(result, input, "{:?}", encoding);
+    }
+}
+
+#[test]
+fn test_url_encoding_decode_unreserved() {
+    let input = "Read the [API docs](https://example.com/%41PI%2Dv1/%7Euser).\n";
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::DecodeUnreserved);
+    assert_eq!(
+        result,
+        "Read the [API docs].\n\n[api docs]: https://example.com/API-v1/~user\n"
+    );
+}
+
+#[test]
+fn test_url_encoding_reference_definition() {
+    let input = "See [the notes].\n\n[the notes]: <release notes.md>\n";
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::Encode);
+    assert_eq!(
+        result,
+        "See [the notes].\n\n[the notes]: release%20notes.md\n"
+    );
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::Preserve);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_url_encoding_keeps_fragments_and_mailto() {
+    let input = "Mail [me](mailto:홍민희@example.com) or see [usage](#사용법).\n\n\n\
+                 사용법\n------\n";
+    let result = parse_and_serialize_with_url_encoding(input, UrlEncoding::Encode);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_no_wrap_directive_paragraph() {
+    let input = "A normal paragraph that is long enough to be wrapped by the formatter when it exceeds the width.
+
+<!-- hongdown-no-wrap -->
+Roses are red,
+violets are blue, and this line is *long* enough to go well beyond the line width
+of __the__ formatter.
+
+Another normal paragraph that is long enough to be wrapped by the formatter when it exceeds the width.
+";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        "A normal paragraph that is long enough to be wrapped by the formatter when it
+exceeds the width.
+
+<!-- hongdown-no-wrap -->
+
+Roses are red,
+violets are blue, and this line is *long* enough to go well beyond the line width
+of **the** formatter.
+
+Another normal paragraph that is long enough to be wrapped by the formatter
+when it exceeds the width.
+"
+    );
+    assert_eq!(parse_and_serialize(&result), result);
+}
+
+#[test]
+fn test_no_wrap_directive_with_wrap_none() {
+    let input = "<!-- hongdown-no-wrap -->
+Keep
+these lines.
+
+Join
+these lines.
+";
+    let result = parse_and_serialize_with_wrap(input, WrapMode::None);
+    assert_eq!(
+        result,
+        "<!-- hongdown-no-wrap -->\n\nKeep\nthese lines.\n\nJoin these lines.\n"
+    );
+}
+
+#[test]
+fn test_no_wrap_directive_list() {
+    let input = "<!-- hongdown-no-wrap -->
+ -  An item whose first line is long enough to go well beyond the line width of the
+    formatter.
+ -  Short.
+
+A paragraph whose first line is long enough to go well beyond the line width of the
+formatter.
+";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        "<!-- hongdown-no-wrap -->
+
+ -  An item whose first line is long enough to go well beyond the line width of the
+    formatter.
+ -  Short.
+
+A paragraph whose first line is long enough to go well beyond the line width of
+the formatter.
+"
+    );
+}
+
+#[test]
+fn test_no_wrap_directive_inside_list_item() {
+    let input = " -  First item.
+
+    <!-- hongdown-no-wrap -->
+    A paragraph in the item whose line is long enough to go beyond the line width.
+";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        " -  First item.
+
+    <!-- hongdown-no-wrap -->
+
+    A paragraph in the item whose line is long enough to go beyond the line width.
+"
+    );
+}
+
+#[test]
+fn test_no_wrap_directive_table() {
+    let input = "<!-- hongdown-no-wrap -->
+
+| A | B |
+|---|---|
+| 1 | 2 |
+";
+    let result = parse_and_serialize(input);
+    assert_eq!(
+        result,
+        "<!-- hongdown-no-wrap -->\n\n| A   | B   |\n| --- | --- |\n| 1   | 2   |\n"
+    );
+}
+
+// =============================================================================
+// Container block tests
+// =============================================================================
+
+fn format_with_containers(input: &str) -> String {
+    let options = Options {
+        containers: true,
+        ..Options::default()
+    };
+    crate::format(input, &options).unwrap()
+}
+
+#[test]
+fn test_container_with_list_and_code_block() {
+    let input = "::: warning
+Be careful with these:
+- first item
+- second item
+```sh
+rm -rf build/
+```
+:::
+";
+    let result = format_with_containers(input);
+    assert_eq!(
+        result,
+        "::: warning
+
+Be careful with these:
+
+ -  first item
+ -  second item
+
+~~~~ sh
+rm -rf build/
+~~~~
+
+:::
+"
+    );
+    assert_eq!(format_with_containers(&result), result);
+}
+
+#[test]
+fn test_nested_containers() {
+    let input = "::: outer
+::: {.inner #id}
+Nested text.
+:::
+:::
+";
+    let result = format_with_containers(input);
+    assert_eq!(
+        result,
+        ":::: outer
+
+::: {.inner #id}
+
+Nested text.
+
+:::
+
+::::
+"
+    );
+    assert_eq!(format_with_containers(&result), result);
+}
+
+#[test]
+fn test_container_fence_attributes_kept() {
+    let input = ":::::note\nDocusaurus style.\n:::::\n";
+    assert_eq!(
+        format_with_containers(input),
+        ":::note\n\nDocusaurus style.\n\n:::\n"
+    );
+}
+
+#[test]
+fn test_containers_disabled() {
+    let input = "::: warning\nText.\n:::\n";
+    assert_eq!(parse_and_serialize(input), input);
+}
+
+// MDX
+
+fn format_mdx(input: &str) -> String {
+    let options = Options {
+        mdx: true,
+        ..Options::default()
+    };
+    crate::format(input, &options).unwrap()
+}
+
+#[test]
+fn test_mdx_docusaurus_page() {
+    let input = r#"---
+title: Installation
+---
+
+import Tabs from '@theme/Tabs';
+import TabItem from '@theme/TabItem';
+
+# Installation
+
+You can install the package with your favorite package manager, using one of the commands below.
+
+<Tabs
+  groupId="package-manager"
+  defaultValue="npm">
+  <TabItem value="npm" label="npm">
+
+```bash
+npm install my-package
+```
+
+  </TabItem>
+  <TabItem value="yarn" label="Yarn">
+
+```bash
+yarn add my-package
+```
+
+  </TabItem>
+</Tabs>
+
+{/* This comment is an MDX expression. */}
+
+The current version is {props.version}, and *emphasis* still works.
+
+<Admonition type="tip" title="Don't forget">
+Check the [changelog](https://example.com/changelog) before upgrading.
+</Admonition>
+"#;
+    let result = format_mdx(input);
+    assert_eq!(
+        result,
+        r#"---
+title: Installation
+---
+
+import Tabs from '@theme/Tabs';
+import TabItem from '@theme/TabItem';
+
+Installation
+============
+
+You can install the package with your favorite package manager, using one of
+the commands below.
+
+<Tabs
+  groupId="package-manager"
+  defaultValue="npm">
+  <TabItem value="npm" label="npm">
+
+~~~~ bash
+npm install my-package
+~~~~
+
+  </TabItem>
+  <TabItem value="yarn" label="Yarn">
+
+~~~~ bash
+yarn add my-package
+~~~~
+
+  </TabItem>
+</Tabs>
+
+{/* This comment is an MDX expression. */}
+
+The current version is {props.version}, and *emphasis* still works.
+
+<Admonition type="tip" title="Don't forget">
+Check the [changelog](https://example.com/changelog) before upgrading.
+</Admonition>
+"#
+    );
+    assert_eq!(format_mdx(&result), result);
+}
+
+#[test]
+fn test_mdx_expressions_in_prose_not_escaped() {
+    let input = "Use {snake_case_name} and {a * b * c} in props.\n";
+    assert_eq!(format_mdx(input), input);
+}
+
+#[test]
+fn test_mdx_jsx_in_list_item() {
+    let input = " -  <Badge\n      text=\"new\" />\n -  Plain item.\n";
+    assert_eq!(format_mdx(input), input);
+}
+
+#[test]
+fn test_mdx_disabled() {
+    let input = "import Tabs from '@theme/Tabs';\n\n{/* A comment. */}\n";
+    assert_eq!(
+        parse_and_serialize(input),
+        "import Tabs from ‘@theme/Tabs’;\n\n{/\\* A comment. \\*/}\n"
+    );
+}
+
+// Heading numbering
+
+fn format_with_numbering(input: &str) -> String {
+    let options = Options {
+        heading_numbering: true,
+        ..Options::default()
+    };
+    crate::format(input, &options).unwrap()
+}
+
+#[test]
+fn test_heading_numbering() {
+    let input = "# Manual\n\n## Introduction\n\n### Goals\n\n### Non-goals\n\n## Setup\n\n\
+                 #### Details\n\n# Appendix\n\n## Notes\n";
+    let result = format_with_numbering(input);
+    assert_eq!(
+        result,
+        "Manual\n======\n\n1 Introduction\n--------------\n\n### 1.1 Goals\n\n\
+         ### 1.2 Non-goals\n\n2 Setup\n-------\n\n#### Details\n\nAppendix\n========\n\n\
+         1 Notes\n-------\n"
+    );
+    assert_eq!(format_with_numbering(&result), result);
+}
+
+#[test]
+fn test_heading_numbering_after_inserted_section() {
+    let input = "## 1 Introduction\n\n## Upgrading\n\n## 2 Configuration\n\n\
+                 ### 2.1. Files\n\n### 2.2 Environment\n\n## 3 Usage\n";
+    let result = format_with_numbering(input);
+    assert_eq!(
+        result,
+        "1 Introduction\n--------------\n\n2 Upgrading\n-----------\n\n\
+         3 Configuration\n---------------\n\n### 3.1 Files\n\n### 3.2 Environment\n\n\
+         4 Usage\n-------\n"
+    );
+    assert_eq!(format_with_numbering(&result), result);
+}
+
+#[test]
+fn test_heading_numbering_levels() {
+    let options = Options {
+        heading_numbering: true,
+        heading_numbering_levels: vec![
+            crate::HeadingLevel::new(1).unwrap(),
+            crate::HeadingLevel::new(2).unwrap(),
+        ],
+        setext_h1: false,
+        setext_h2: false,
+        ..Options::default()
+    };
+    let input = "# Guide\n\n## 7 Basics\n\n### Aside\n\n# Reference\n\n## Options\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "# 1 Guide\n\n## 1.1 Basics\n\n### Aside\n\n# 2 Reference\n\n## 2.1 Options\n"
+    );
+}
+
+#[test]
+fn test_heading_numbering_with_code_span_and_sentence_case() {
+    let options = Options {
+        heading_numbering: true,
+        heading_sentence_case: true,
+        ..Options::default()
+    };
+    let input = "## 5 Getting Started\n\n## `hongdown` Command Line\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "1 Getting started\n-----------------\n\n2 `hongdown` command line\n\
+         -------------------------\n"
+    );
+}
+
+#[test]
+fn test_heading_numbering_toc_and_anchors() {
+    let options = Options {
+        heading_numbering: true,
+        ..Options::default()
+    };
+    let input = "<!-- toc -->\n<!-- /toc -->\n\n## Installation\n\n### From source\n\n\
+                 ## 1 Usage\n\nSee [installing from source](#11-from-source).\n";
+    let result = crate::format_with_warnings(input, &options).unwrap();
+    assert_eq!(
+        result.output,
+        "<!-- toc -->\n\n -  [1 Installation](#1-installation)\n     -  \
+         [1.1 From source](#11-from-source)\n -  [2 Usage](#2-usage)\n\n<!-- /toc -->\n\n\n\
+         1 Installation\n--------------\n\n### 1.1 From source\n\n2 Usage\n-------\n\n\
+         See [installing from source](#11-from-source).\n"
+    );
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    assert_eq!(
+        crate::format(&result.output, &options).unwrap(),
+        result.output
+    );
+}
+
+// Dialect
+
+fn format_commonmark(input: &str, tables: crate::TableFallback) -> String {
+    let options = Options {
+        dialect: crate::Dialect::CommonMark,
+        commonmark_tables: tables,
+        ..Options::default()
+    };
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+    output
+}
+
+#[test]
+fn test_dialect_tables() {
+    let input = "Text before.\n\n| Name | Value |\n|------|:-----:|\n| *a*  | `1`   |\n";
+    let gfm = crate::format(input, &Options::default()).unwrap();
+    assert!(gfm.lines().any(|line| line.starts_with('|')), "{gfm}");
+
+    let commonmark = format_commonmark(input, crate::TableFallback::Html);
+    assert!(
+        !commonmark.lines().any(|line| line.starts_with('|')),
+        "{commonmark}"
+    );
+    assert_eq!(
+        commonmark,
+        "Text before.\n\n<table>\n<thead>\n<tr>\n<th>Name</th>\n\
+         <th align=\"center\">Value</th>\n</tr>\n</thead>\n<tbody>\n<tr>\n\
+         <td><em>a</em></td>\n<td align=\"center\"><code>1</code></td>\n\
+         </tr>\n</tbody>\n</table>\n"
+    );
+}
+
+#[test]
+fn test_dialect_table_in_list_item() {
+    let input = " -  Item\n\n    | A |\n    |---|\n    | b |\n";
+    let output = format_commonmark(input, crate::TableFallback::Html);
+    assert!(output.contains("\n    <table>\n"), "{output}");
+    assert!(output.contains("\n    <td>b</td>\n"), "{output}");
+}
+
+#[test]
+fn test_dialect_table_text_fallback() {
+    let input = "| A |  B |\n|---|---|\n| 1 |  2 |\n";
+    assert_eq!(format_commonmark(input, crate::TableFallback::Text), input);
+}
+
+#[test]
+fn test_dialect_strikethrough() {
+    let input = "Some ~~struck~~ text.\n";
+    assert_eq!(
+        crate::format(input, &Options::default()).unwrap(),
+        "Some ~~struck~~ text.\n"
+    );
+    assert_eq!(
+        format_commonmark(input, crate::TableFallback::Html),
+        "Some <del>struck</del> text.\n"
+    );
+}
+
+#[test]
+fn test_dialect_task_list() {
+    let input = " -  [x] Done\n -  [ ] To do\n";
+    assert_eq!(
+        format_commonmark(input, crate::TableFallback::Html),
+        " -  \\[x] Done\n -  \\[ ] To do\n"
+    );
+}
+
+// Math
+
+fn format_math(input: &str) -> String {
+    let options = Options {
+        math: true,
+        ..Options::default()
+    };
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+    output
+}
+
+#[test]
+fn test_math_dollar_amounts_escaped() {
+    let input = "It costs $5 and $10.\n";
+    assert_eq!(format_math(input), "It costs \\$5 and \\$10.\n");
+    assert_eq!(crate::format(input, &Options::default()).unwrap(), input);
+}
+
+#[test]
+fn test_math_formula_next_to_dollar_amount() {
+    let input = "The area is $\\pi r^2$, which costs $3 per unit.\n";
+    assert_eq!(
+        format_math(input),
+        "The area is $\\pi r^2$, which costs \\$3 per unit.\n"
+    );
+}
+
+#[test]
+fn test_math_table_cells() {
+    let input = "| Price | Formula |\n|---|---|\n| $5 | $x_1$ |\n| $6 | y |\n";
+    assert_eq!(
+        format_math(input),
+        "| Price | Formula |\n| ----- | ------- |\n| \\$5   | $x_1$   |\n| \\$6   | y       |\n"
+    );
+}
+
+#[test]
+fn test_math_display_block_kept() {
+    let input = "Text.\n\n$$\nx^2 + y^2\n$$\n";
+    assert_eq!(format_math(input), input);
+}
+
+// Template tags
+
+fn format_templates(input: &str, templates: crate::TemplateSyntax) -> String {
+    let options = Options {
+        templates,
+        line_width: crate::LineWidth::new(40).unwrap(),
+        ..Options::default()
+    };
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+    output
+}
+
+#[test]
+fn test_templates_hugo_shortcode_not_wrapped() {
+    let input = "See the guide {{< ref \"docs/getting-started.md\" >}} for details.\n";
+    assert_eq!(
+        format_templates(input, crate::TemplateSyntax::Hugo),
+        "See the guide\n{{< ref \"docs/getting-started.md\" >}}\nfor details.\n"
+    );
+    assert_eq!(
+        format_templates(input, crate::TemplateSyntax::None),
+        "See the guide {{< ref\n\u{201C}docs/getting-started.md\u{201D} >}} for\ndetails.\n"
+    );
+}
+
+#[test]
+fn test_templates_tags_not_escaped() {
+    let input = "Hello {{ user_name }},\n{% if is_admin %}welcome{% endif %}.\n";
+    assert_eq!(
+        format_templates(input, crate::TemplateSyntax::Liquid),
+        input
+    );
+    assert_eq!(
+        format_templates(input, crate::TemplateSyntax::None),
+        "Hello {{ user\\_name }},\n{% if is\\_admin %}welcome{% endif %}.\n"
+    );
+}
+
+#[test]
+fn test_templates_jinja_raw_block() {
+    let input = "{% raw %}\nThe {{ name }} variable is written *as is* and is not wrapped.\n\
+                 {% endraw %}\n";
+    assert_eq!(format_templates(input, crate::TemplateSyntax::Jinja), input);
+}
+
+#[test]
+fn test_templates_in_heading() {
+    let input = "Docs for {{ site.name }}\n========================\n";
+    assert_eq!(format_templates(input, crate::TemplateSyntax::Jinja), input);
+}
+
+// Joining lines at soft breaks next to punctuation
+
+fn format_joined(input: &str) -> String {
+    let options = Options {
+        wrap: crate::WrapMode::None,
+        ..Options::default()
+    };
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+    output
+}
+
+#[test]
+fn test_soft_break_before_comma() {
+    assert_eq!(
+        format_joined("A word\n, then more.\n"),
+        "A word, then more.\n"
+    );
+    assert_eq!(
+        format_joined("An *emphasis*\n; then more.\n"),
+        "An *emphasis*; then more.\n"
+    );
+}
+
+#[test]
+fn test_soft_break_before_closing_paren() {
+    assert_eq!(
+        format_joined("Some (parenthesized\n) text.\n"),
+        "Some (parenthesized) text.\n"
+    );
+    assert_eq!(
+        format_joined("Some (\nparenthesized) text.\n"),
+        "Some (parenthesized) text.\n"
+    );
+}
+
+#[test]
+fn test_soft_break_after_opening_quote() {
+    assert_eq!(
+        format_joined("He said \u{201C}\nhello\u{201D} to me.\n"),
+        "He said \u{201C}hello\u{201D} to me.\n"
+    );
+}
+
+#[test]
+fn test_soft_break_before_image_keeps_space() {
+    assert_eq!(
+        format_joined("A badge\n![alt](https://example.com/badge.svg) here.\n"),
+        "A badge ![alt](https://example.com/badge.svg) here.\n"
+    );
+}
+
+#[test]
+fn test_soft_break_before_punctuation_rewrapped() {
+    let input = "This line is long enough that the formatter has to rewrap it with the \
+                 next one or two\n, which starts with a comma.\n";
+    assert_eq!(
+        crate::format(input, &Options::default()).unwrap(),
+        "This line is long enough that the formatter has to rewrap it with the next one\n\
+         or two, which starts with a comma.\n"
+    );
+}
+
+#[test]
+fn test_soft_break_before_punctuation_starting_word_keeps_space() {
+    assert_eq!(
+        format_joined("See\n.gitignore here.\n"),
+        "See .gitignore here.\n"
+    );
+    assert_eq!(format_joined("Use x\n!important.\n"), "Use x !important.\n");
+    assert_eq!(format_joined("See\n.`gitignore`.\n"), "See .`gitignore`.\n");
+    assert_eq!(format_joined("The end\n).\n"), "The end).\n");
+}
+
+#[test]
+fn test_soft_break_before_closing_bracket_idempotent() {
+    assert_eq!(format_joined("a\n](/u)\n"), "a ](/u)\n");
+    assert_eq!(format_joined("a [\nb\n"), "a [b\n");
+    assert_eq!(format_joined("a\n]\n"), "a]\n");
+    let heading = "a\n](/u)\n===\n";
+    let output = crate::format(heading, &Options::default()).unwrap();
+    assert_eq!(output, "a ](/u)\n=======\n");
+    assert_eq!(crate::format(&output, &Options::default()).unwrap(), output);
+}
+
+// Wrapping code spans and links with the punctuation after them
+
+fn format_width_40(input: &str) -> String {
+    let options = Options {
+        line_width: crate::LineWidth::new(40).unwrap(),
+        ..Options::default()
+    };
+    let output = crate::format_with_warnings(input, &options).unwrap().output;
+    assert_eq!(
+        crate::format_with_warnings(&output, &options)
+            .unwrap()
+            .output,
+        output
+    );
+    output
+}
+
+#[test]
+fn test_wrap_code_span_with_punctuation_at_boundary() {
+    // The code span and the period after it just fit
+    let input = "Use the function that is named `foo()`. It works.\n";
+    assert_eq!(
+        format_width_40(input),
+        "Use the function that is named `foo()`.\nIt works.\n"
+    );
+    // The code span would fit, but not the period after it, so both go to
+    // the next line
+    let input = "Use the function that is named `fooo()`. It works.\n";
+    assert_eq!(
+        format_width_40(input),
+        "Use the function that is named\n`fooo()`. It works.\n"
+    );
+}
+
+#[test]
+fn test_wrap_double_backtick_code_span() {
+    let input = "Write this code span as ``a ` b``, with two backticks here.\n";
+    assert_eq!(
+        format_width_40(input),
+        "Write this code span as ``a ` b``, with\ntwo backticks here.\n"
+    );
+    let input = "Write this longer code span as ``a ` b``, with two backticks.\n";
+    assert_eq!(
+        format_width_40(input),
+        "Write this longer code span as\n``a ` b``, with two backticks.\n"
+    );
+}
+
+#[test]
+fn test_wrap_link_tail_with_punctuation_at_boundary() {
+    let input = "Read it all in [the guide](./guide.md). It helps.\n";
+    assert_eq!(
+        format_width_40(input),
+        "Read it all in [the guide](./guide.md).\nIt helps.\n"
+    );
+    let input = "Read it all in [the guide](./guides.md). It helps.\n";
+    assert_eq!(
+        format_width_40(input),
+        "Read it all in\n[the guide](./guides.md). It helps.\n"
+    );
+    // A title doesn't break the link across lines either
+    let input = "See the long [guide](./guide.md \"The guide\"). It helps.\n";
+    assert_eq!(
+        format_width_40(input),
+        "See the long\n[guide](./guide.md \"The guide\"). It\nhelps.\n"
+    );
+}
+
+// Sorting definitions
+
+const SORT_DEFINITIONS_INPUT: &str = "See [Zeta], [alpha], [10], [2], and [Beta].\n\n\
+     Text[^note] and[^10] and[^2].\n\n\
+     [2]: https://example.com/2\n\
+     [10]: https://example.com/10\n\
+     [alpha]: https://example.com/alpha\n\
+     [beta]: https://example.com/beta\n\
+     [zeta]: https://example.com/zeta\n\n\
+     [^2]: Two.\n[^10]: Ten.\n[^note]: Note.\n";
+
+fn format_sorted(order: crate::DefinitionOrder) -> String {
+    let options = Options {
+        link_sort_definitions: order,
+        footnote_sort_definitions: order,
+        ..Options::default()
+    };
+    let output = crate::format(SORT_DEFINITIONS_INPUT, &options).unwrap();
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+    output
+}
+
+#[test]
+fn test_sort_definitions_none() {
+    assert_eq!(
+        format_sorted(crate::DefinitionOrder::None),
+        "See [Zeta], [alpha], [10], [2], and [Beta].\n\n\
+         Text[^note] and[^10] and[^2].\n\n\
+         [^note]: Note.\n[^2]: Two.\n[^10]: Ten.\n\n\
+         [zeta]: https://example.com/zeta\n\
+         [alpha]: https://example.com/alpha\n\
+         [beta]: https://example.com/beta\n\
+         [2]: https://example.com/2\n\
+         [10]: https://example.com/10\n"
+    );
+}
+
+#[test]
+fn test_sort_definitions_usage() {
+    assert_eq!(
+        format_sorted(crate::DefinitionOrder::Usage),
+        "See [Zeta], [alpha], [10], [2], and [Beta].\n\n\
+         Text[^note] and[^10] and[^2].\n\n\
+         [^note]: Note.\n[^10]: Ten.\n[^2]: Two.\n\n\
+         [zeta]: https://example.com/zeta\n\
+         [alpha]: https://example.com/alpha\n\
+         [10]: https://example.com/10\n\
+         [2]: https://example.com/2\n\
+         [beta]: https://example.com/beta\n"
+    );
+}
+
+#[test]
+fn test_sort_definitions_alpha() {
+    assert_eq!(
+        format_sorted(crate::DefinitionOrder::Alpha),
+        "See [Zeta], [alpha], [10], [2], and [Beta].\n\n\
+         Text[^note] and[^10] and[^2].\n\n\
+         [^2]: Two.\n[^10]: Ten.\n[^note]: Note.\n\n\
+         [2]: https://example.com/2\n\
+         [10]: https://example.com/10\n\
+         [alpha]: https://example.com/alpha\n\
+         [beta]: https://example.com/beta\n\
+         [zeta]: https://example.com/zeta\n"
+    );
+}
+
+#[test]
+fn test_compare_labels() {
+    use std::cmp::Ordering;
+    assert_eq!(super::compare_labels("2", "10"), Ordering::Less);
+    assert_eq!(
+        super::compare_labels("item 10", "Item 9"),
+        Ordering::Greater
+    );
+    assert_eq!(super::compare_labels("alpha", "Beta"), Ordering::Less);
+    assert_eq!(super::compare_labels("v02", "v2"), Ordering::Less);
+    assert_eq!(super::compare_labels("Same", "same"), Ordering::Less);
+    assert_eq!(super::compare_labels("a", "a"), Ordering::Equal);
+}
+
+// Disabling rules with directives
+
+#[test]
+fn test_disable_line_length_region() {
+    let input =
+        "A paragraph that is long enough to be wrapped by the formatter when it exceeds the width.
+
+<!-- hongdown-disable line-length -->
+
+Roses are red,
+violets are blue, and this line is long enough to go well beyond the line width of the formatter.
+
+ *  A list item that is long enough to go well beyond the line width of the formatter.
+
+<!-- hongdown-enable -->
+
+Another paragraph that is long enough to be wrapped by the formatter when it exceeds the width.
+";
+    let result = crate::format_with_warnings(input, &crate::Options::default()).unwrap();
+    assert_eq!(
+        result.output,
+        "A paragraph that is long enough to be wrapped by the formatter when it exceeds
+the width.
+
+<!-- hongdown-disable line-length -->
+
+Roses are red,
+violets are blue, and this line is long enough to go well beyond the line width of the formatter.
+
+ -  A list item that is long enough to go well beyond the line width of the formatter.
+
+<!-- hongdown-enable -->
+
+Another paragraph that is long enough to be wrapped by the formatter when it
+exceeds the width.
+"
+    );
+    assert!(result.warnings.is_empty());
+}
+
+#[test]
+fn test_disable_line_length_by_markdownlint_id() {
+    let input = "<!-- hongdown-disable-file MD013 -->
+
+A paragraph that is long enough to go well beyond the line width of the formatter.
+";
+    assert_eq!(
+        crate::format(input, &crate::Options::default()).unwrap(),
+        input
+    );
+}
+
+#[test]
+fn test_enable_named_rule() {
+    let input = "<!-- hongdown-disable line-length, single-h1 -->
+
+A paragraph that is long enough to go well beyond the line width of the formatter.
+
+<!-- hongdown-enable single-h1 -->
+
+Still kept, since line-length is still disabled, beyond the line width of the formatter.
+
+<!-- hongdown-enable line-length -->
+
+A paragraph that is long enough to go well beyond the line width of the formatter.
+";
+    assert_eq!(
+        crate::format(input, &crate::Options::default()).unwrap(),
+        "<!-- hongdown-disable line-length, single-h1 -->
+
+A paragraph that is long enough to go well beyond the line width of the formatter.
+
+<!-- hongdown-enable single-h1 -->
+
+Still kept, since line-length is still disabled, beyond the line width of the formatter.
+
+<!-- hongdown-enable line-length -->
+
+A paragraph that is long enough to go well beyond the line width of the
+formatter.
+"
+    );
+}
+
+#[test]
+fn test_disable_unknown_rule() {
+    let input = "Title\n=====\n\n<!-- hongdown-disable line-length no-such-rule -->\n\nText.\n";
+    let result = crate::format_with_warnings(input, &crate::Options::default()).unwrap();
+    assert_eq!(result.output, input);
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].line, 4);
+    assert_eq!(result.warnings[0].message, "unknown rule: no-such-rule");
+}
+
+#[test]
+fn test_stray_enable_rule() {
+    let input =
+        "A paragraph that is long enough to be wrapped by the formatter when it exceeds the width.
+
+<!-- hongdown-enable line-length -->
+
+Another paragraph that is long enough to be wrapped by the formatter when it exceeds the width.
+";
+    let result = crate::format_with_warnings(input, &crate::Options::default()).unwrap();
+    assert_eq!(
+        result.output,
+        "A paragraph that is long enough to be wrapped by the formatter when it exceeds
+the width.
+
+<!-- hongdown-enable line-length -->
+
+Another paragraph that is long enough to be wrapped by the formatter when it
+exceeds the width.
+"
+    );
+    assert!(result.warnings.is_empty());
+}
+
+#[test]
+fn test_disable_fix_rule() {
+    let options = crate::Options {
+        fix: vec![crate::FixRule::NoTrailingPunctuation],
+        ..crate::Options::default()
+    };
+    let input = "Usage:\n======\n\n<!-- hongdown-disable no-trailing-punctuation -->\n\nOptions:\n--------\n\n<!-- hongdown-enable -->\n\nNotes:\n------\n";
+    let result = crate::format_with_warnings(input, &options).unwrap();
+    assert_eq!(
+        result.output,
+        "Usage\n=====\n\n<!-- hongdown-disable no-trailing-punctuation -->\n\n\nOptions:\n--------\n\n<!-- hongdown-enable -->\n\n\nNotes\n-----\n"
+    );
+    assert_eq!(
+        result.warnings.iter().map(|w| w.line).collect::<Vec<_>>(),
+        [1, 11]
+    );
+}
+
+// Byte order marks
+
+#[test]
+fn test_byte_order_mark_removed() {
+    let options = Options {
+        bom: crate::ByteOrderMark::Remove,
+        ..Options::default()
+    };
+    let input = "\u{FEFF}Title\n=====\n\nText.\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "Title\n=====\n\nText.\n"
+    );
+}
+
+#[test]
+fn test_byte_order_mark_before_setext_heading() {
+    let options = Options {
+        heading_numbering: true,
+        heading_numbering_levels: vec![crate::HeadingLevel::new(1).unwrap()],
+        fix: vec![crate::FixRule::NoTrailingPunctuation],
+        ..Options::default()
+    };
+    let input = "\u{FEFF}7 Title:\n========\n\nText.\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "\u{FEFF}1 Title\n=======\n\nText.\n"
+    );
+}
+
+// Adjacent lists with different markers
+
+#[test]
+fn test_adjacent_lists_with_different_markers_round_trip() {
+    let input = "- a\n- b\n* c\n* d\n+ e\n";
+    let output = crate::format(input, &Options::default()).unwrap();
+    assert_eq!(
+        output,
+        " -  a\n -  b\n\n<!-- -->\n\n -  c\n -  d\n\n<!-- -->\n\n -  e\n"
+    );
+    assert_eq!(crate::format(&output, &Options::default()).unwrap(), output);
+}
+
+#[test]
+fn test_adjacent_lists_alternate_marker_round_trip() {
+    let options = Options {
+        list_separation: crate::ListSeparation::AlternateMarker,
+        ..Options::default()
+    };
+    let input = "- a\n- b\n* c\n+ d\n\n1. e\n2) f\n";
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(output, " -  a\n -  b\n\n *  c\n\n -  d\n\n1.  e\n\n1)  f\n");
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+}
+
+#[test]
+fn test_adjacent_nested_lists_with_different_markers() {
+    let input = "- a\n    - b\n    * c\n";
+    let output = crate::format(input, &Options::default()).unwrap();
+    assert_eq!(output, crate::format(&output, &Options::default()).unwrap());
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, &output, &comrak::Options::default());
+    let item = root.first_child().unwrap().first_child().unwrap();
+    let nested_lists = item
+        .children()
+        .filter(|child| matches!(child.data.borrow().value, NodeValue::List(_)))
+        .count();
+    assert_eq!(nested_lists, 2);
+}
+
+#[test]
+fn test_bullet_marker_before_strong_text() {
+    let options = Options {
+        unordered_marker: crate::UnorderedMarker::Asterisk,
+        leading_spaces: crate::LeadingSpaces::new(0).unwrap(),
+        trailing_spaces: crate::TrailingSpaces::new(0).unwrap(),
+        ..Options::default()
+    };
+    let input = "* **term** – desc\n* *em* text\n";
+    assert_eq!(crate::format(input, &options).unwrap(), input);
+}
+
+// Inline footnotes
+
+#[test]
+fn test_inline_footnote_preserved_in_wrapped_paragraph() {
+    let input = "This paragraph has an inline footnote^[Which is a note that is long \
+                 enough to be wrapped with the rest of the paragraph.] in the middle of it.\n";
+    let output = crate::format(input, &Options::default()).unwrap();
+    assert_eq!(
+        output,
+        "This paragraph has an inline footnote^[Which is a note that is long enough to\n\
+         be wrapped with the rest of the paragraph.] in the middle of it.\n"
+    );
+    assert_eq!(crate::format(&output, &Options::default()).unwrap(), output);
+}
+
+#[test]
+fn test_inline_footnote_preserved_with_link() {
+    let options = Options {
+        link_external_references: false,
+        ..Options::default()
+    };
+    let input = "Text^[See [the *docs*](https://example.com/) and `^[code]`.] here.\n";
+    assert_eq!(crate::format(input, &options).unwrap(), input);
+}
+
+#[test]
+fn test_inline_footnote_not_in_code() {
+    let input = "Code `^[not a note]` here.\n\n~~~~ markdown\nA^[b]\n~~~~\n";
+    assert_eq!(crate::format(input, &Options::default()).unwrap(), input);
+}
+
+#[test]
+fn test_inline_footnote_expanded_with_link() {
+    let options = Options {
+        footnote_inline: crate::InlineFootnotes::Expand,
+        ..Options::default()
+    };
+    let input = "Intro\n-----\n\nText^[See [example](https://example.com/).] and a \
+                 regular note[^1].\n\n[^1]: Regular.\n\nNext\n----\n\nMore.\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "Intro\n-----\n\nText[^2] and a regular note[^1].\n\n\
+         [^1]: Regular.\n[^2]: See [example].\n\n\
+         [example]: https://example.com/\n\n\nNext\n----\n\nMore.\n"
+    );
+}
+
+#[test]
+fn test_inline_footnote_expanded_in_wrapped_paragraph() {
+    let options = Options {
+        footnote_inline: crate::InlineFootnotes::Expand,
+        ..Options::default()
+    };
+    let input = "This paragraph has an inline footnote^[Which is a note that\n\
+                 spans lines.] in the middle of it, and it is long enough to wrap.\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "This paragraph has an inline footnote[^1] in the middle of it, and it is long\n\
+         enough to wrap.\n\n[^1]: Which is a note that spans lines.\n"
+    );
+}
+
+#[test]
+fn test_inline_footnote_not_across_blocks() {
+    for input in [
+        "text ^[a\n+ b]\n",
+        "text ^[a\n\n> b]\n",
+        "> text ^[a\n>\n> b]\n",
+    ] {
+        let output = crate::format(input, &Options::default()).unwrap();
+        assert!(output.contains("^\\[a"), "{input:?} gave {output:?}");
+        assert_eq!(
+            crate::format(&output, &Options::default()).unwrap(),
+            output,
+            "{input:?}"
+        );
+    }
+}
+
+// Image galleries
+
+#[test]
+fn test_image_gallery_wraps_between_images() {
+    let input = "![Alpha screenshot][img-a] ![Beta screenshot][img-b] \
+                 ![Gamma screenshot][img-c] ![Delta screenshot][img-d] \
+                 ![Epsilon screenshot][img-e] ![Zeta screenshot][img-f]\n\n\
+                 [img-c]: ./c.png\n[img-a]: ./a.png\n[img-f]: ./f.png\n\
+                 [img-b]: ./b.png\n[img-e]: ./e.png\n[img-d]: ./d.png\n";
+    let output = crate::format(input, &Options::default()).unwrap();
+    assert_eq!(
+        output,
+        "![Alpha screenshot][img-a] ![Beta screenshot][img-b] ![Gamma screenshot][img-c]\n\
+         ![Delta screenshot][img-d] ![Epsilon screenshot][img-e]\n\
+         ![Zeta screenshot][img-f]\n\n\
+         [img-a]: ./a.png\n[img-b]: ./b.png\n[img-c]: ./c.png\n\
+         [img-d]: ./d.png\n[img-e]: ./e.png\n[img-f]: ./f.png\n"
+    );
+    assert_eq!(crate::format(&output, &Options::default()).unwrap(), output);
+}
+
+#[test]
+fn test_image_gallery_keeps_alt_text_and_titles_whole() {
+    let options = Options {
+        line_width: crate::LineWidth::new(30).unwrap(),
+        ..Options::default()
+    };
+    let input = "![Fig. 1. The app][img-a] ![Fig. 2. The app again][img-b] ![Fig. 3][img-c]\n\n\
+                 [img-a]: ./a.png\n[img-b]: ./b.png\n[img-c]: ./c.png\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "![Fig. 1. The app][img-a]\n![Fig. 2. The app again][img-b]\n![Fig. 3][img-c]\n\n\
+         [img-a]: ./a.png\n[img-b]: ./b.png\n[img-c]: ./c.png\n"
+    );
+
+    let input = "![Alpha](./a.png \"The alpha screenshot\") ![Beta](./b.png \"The beta one\")\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "![Alpha](./a.png \"The alpha screenshot\")\n![Beta](./b.png \"The beta one\")\n"
+    );
+}
+
+// Trailing whitespace
+
+#[test]
+fn test_trailing_whitespace_stripped() {
+    let input = "- \n- Item\n\n<div>  \nText\t\n</div>\n\n> Quote\n>   \n> More\n";
+    assert_eq!(
+        crate::format(input, &Options::default()).unwrap(),
+        " -\n -  Item\n\n<div>\nText\n</div>\n\n> Quote\n>\n> More\n"
+    );
+}
+
+#[test]
+fn test_trailing_whitespace_code_block() {
+    let input = "> ~~~~\n> code  \n>    \n> ~~~~\n";
+    let output = crate::format(input, &Options::default()).unwrap();
+    assert_eq!(output, "> ~~~~\n> code  \n>    \n> ~~~~\n");
+
+    let options = Options {
+        code_block_trim_trailing_whitespace: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "> ~~~~\n> code\n>\n> ~~~~\n"
+    );
+}
+
+#[test]
+fn test_trailing_whitespace_hard_break() {
+    let input = "One  \ntwo\\\nthree\n\n> Quoted  \n> line\n";
+    assert_eq!(
+        crate::format(input, &Options::default()).unwrap(),
+        "One  \ntwo  \nthree\n\n> Quoted  \n> line\n"
+    );
+
+    let options = Options {
+        hard_break: crate::HardBreak::Backslash,
+        ..Options::default()
+    };
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(output, "One\\\ntwo\\\nthree\n\n> Quoted\\\n> line\n");
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+}
+
+#[test]
+fn test_trailing_whitespace_hard_break_in_setext_heading() {
+    let options = Options {
+        hard_break: crate::HardBreak::Backslash,
+        ..Options::default()
+    };
+    let input = "First  \nsecond\n======\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "First\\\nsecond\n======\n"
+    );
+}
+
+// HTML void elements
+
+fn html_options() -> Options {
+    Options {
+        html_normalize_void_tags: true,
+        html_br_to_hard_break: true,
+        html_hr_to_thematic_break: true,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn test_html_void_tags_kept_by_default() {
+    let input = "One<BR/>\ntwo\n\n<hr />\n";
+    assert_eq!(crate::format(input, &Options::default()).unwrap(), input);
+}
+
+#[test]
+fn test_html_normalize_void_tags() {
+    let options = Options {
+        html_normalize_void_tags: true,
+        ..Options::default()
+    };
+    let input = "A<BR/>b <br >c <img src=\"a.png\" alt=\"A\"/>\n\n<br  />\n";
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(
+        output,
+        "A<br>b <br>c <img src=\"a.png\" alt=\"A\">\n\n<br>\n"
+    );
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+
+    let options = Options {
+        html_void_tag_style: crate::VoidTagStyle::Xhtml,
+        ..options
+    };
+    let output = crate::format(input, &options).unwrap();
+    assert_eq!(
+        output,
+        "A<br />b <br />c <img src=\"a.png\" alt=\"A\" />\n\n<br />\n"
+    );
+    assert_eq!(crate::format(&output, &options).unwrap(), output);
+}
+
+#[test]
+fn test_html_br_to_hard_break() {
+    let input = "One<br>\ntwo <br>\nthree<br>\n\n> Quoted<br>\n> line\n";
+    let output = crate::format(input, &html_options()).unwrap();
+    assert_eq!(output, "One  \ntwo  \nthree<br>\n\n> Quoted  \n> line\n");
+    assert_eq!(crate::format(&output, &html_options()).unwrap(), output);
+
+    let options = Options {
+        hard_break: crate::HardBreak::Backslash,
+        ..html_options()
+    };
+    assert_eq!(
+        crate::format("One<br>\ntwo\n", &options).unwrap(),
+        "One\\\ntwo\n"
+    );
+}
+
+#[test]
+fn test_html_br_in_table_kept() {
+    let input = "| A        | B    |\n| -------- | ---- |\n| One<br>two | <br> |\n";
+    let output = crate::format(input, &html_options()).unwrap();
+    assert_eq!(
+        output,
+        "| A          | B    |\n| ---------- | ---- |\n| One<br>two | <br> |\n"
+    );
+    assert_eq!(crate::format(&output, &html_options()).unwrap(), output);
+}
+
+#[test]
+fn test_html_hr_to_thematic_break() {
+    let input = "Before\n\n<hr>\n\nAfter\n\n<hr class=\"fancy\">\n";
+    let output = crate::format(input, &html_options()).unwrap();
+    assert_eq!(
+        output,
+        format!(
+            "Before\n\n   {}\n\nAfter\n\n<hr class=\"fancy\">\n",
+            Options::default().thematic_break_style.as_str()
+        )
+    );
+    assert_eq!(crate::format(&output, &html_options()).unwrap(), output);
+}
+
+// Escape round trips
+
+/// Text dense in the characters [`escape::escape_text`] looks at, mixed with
+/// letters, spaces, and arbitrary non-ASCII characters.  Characters it
+/// leaves to others, like a `#` or `-` starting a line or the `<` of HTML,
+/// don't start it, and it has no line breaks or other ASCII controls.
+fn escapable_text() -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::prelude::*;
+    let ascii = prop::sample::select(vec![
+        '*', '_', '[', ']', '(', ')', '\\', '`', '&', '#', ';', '!', 'x', '1', ' ',
+    ]);
+    let other = any::<char>().prop_map(|c| {
+        if c.is_ascii() || c.is_whitespace() || c.is_control() {
+            'é'
+        } else {
+            c
+        }
+    });
+    prop::collection::vec(prop_oneof![4 => ascii, 1 => other], 0..24).prop_map(|chars| {
+        let text: String = chars.into_iter().collect();
+        format!("x {}", text).trim_end().to_string()
+    })
+}
+
+/// Code span content dense in backticks and spaces, without line breaks.
+fn code_literal() -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::prelude::*;
+    let ascii = prop::sample::select(vec!['`', ' ', 'x', '\\', '|']);
+    let other = any::<char>().prop_map(|c| if c.is_control() { 'é' } else { c });
+    prop::collection::vec(prop_oneof![4 => ascii, 1 => other], 1..16)
+        .prop_map(|chars| chars.into_iter().collect())
+}
+
+proptest::proptest! {
+    #[test]
+    fn prop_escape_text_round_trips(text in escapable_text()) {
+        let escaped = escape::escape_text(&text);
+        proptest::prop_assert_eq!(
+            sole_inline_content(&escaped),
+            Some(text.clone()),
+            "escaped as {:?}",
+            escaped
+        );
+    }
+
+    #[test]
+    fn prop_escape_table_cell_round_trips(text in escapable_text()) {
+        let cell = escape::escape_table_cell(&escape::escape_text(&text)).into_owned();
+        let table = format!("| {} |\n| --- |\n", cell);
+        proptest::prop_assert_eq!(
+            sole_inline_content(&table),
+            Some(text.clone()),
+            "written as {:?}",
+            table
+        );
+    }
+
+    #[test]
+    fn prop_format_code_span_round_trips(literal in code_literal()) {
+        let span = escape::format_code_span(&literal);
+        proptest::prop_assert_eq!(
+            sole_inline_content(&span),
+            Some(literal.clone()),
+            "written as {:?}",
+            span
+        );
+    }
+}
+
+// Line breaks in inline elements
+
+/// Assert that `input` is formatted into `expected`, which is formatted
+/// into itself.
+fn assert_round_trip(input: &str, expected: &str, options: &Options) {
+    let result = crate::format(input, options).unwrap();
+    assert_eq!(result, expected);
+    assert_eq!(crate::format(&result, options).unwrap(), result);
+}
+
+/// A numbered list item with a lettered list of three items in its
+/// paragraph, which CommonMark reads as lines of text.
+const PSEUDO_LIST: &str = "\
+1.  Before building the project for the first time, do the following:
+    a. install the toolchain
+    b. clone the repository
+    c. run the tests
+2.  Build it.
+";
+
+#[test]
+fn test_preserve_pseudo_lists() {
+    let options = Options {
+        list_preserve_pseudo_lists: true,
+        ..Options::default()
+    };
+    assert_round_trip(PSEUDO_LIST, PSEUDO_LIST, &options);
+    let options = Options {
+        wrap: WrapMode::None,
+        ..options
+    };
+    assert_round_trip(PSEUDO_LIST, PSEUDO_LIST, &options);
+    let options = Options {
+        wrap: WrapMode::Sentence,
+        ..options
+    };
+    assert_round_trip(
+        "I. Plan. Write it down.\nII. Build.\nIII. Ship.\n",
+        "I. Plan. Write it down.\nII. Build.\nIII. Ship.\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_pseudo_lists_joined_by_default() {
+    let options = Options {
+        wrap: WrapMode::None,
+        ..Options::default()
+    };
+    assert_round_trip(
+        PSEUDO_LIST,
+        "\
+1.  Before building the project for the first time, do the following: a. install the toolchain b. clone the repository c. run the tests
+2.  Build it.
+",
+        &options,
+    );
+}
+
+/// The number of ordered lists in `text`.
+fn ordered_lists(text: &str) -> usize {
+    let arena = Arena::new();
+    let root = parse_document(&arena, text, &crate::base_comrak_options());
+    root.descendants()
+        .filter(|node| match &node.data.borrow().value {
+            NodeValue::List(list) => list.list_type == comrak::nodes::ListType::Ordered,
+            _ => false,
+        })
+        .count()
+}
+
+#[test]
+fn test_number_at_start_of_bullet_item() {
+    // Without the source, the escape of the period is not copied from it
+    let result = parse_and_serialize("- 1991\\. was a good year\n");
+    assert_eq!(result, " -  1991\\. was a good year\n");
+    assert_eq!(ordered_lists(&result), 0);
+    assert_round_trip(&result, &result, &Options::default());
+    let result = parse_and_serialize("- a\n- 3\\) b\n- 2024\\.\n");
+    assert_eq!(result, " -  a\n -  3\\) b\n -  2024\\.\n");
+    assert_eq!(ordered_lists(&result), 0);
+}
+
+#[test]
+fn test_number_at_start_of_paragraph() {
+    let result = parse_and_serialize("12\\. Angry Men\n");
+    assert_eq!(result, "12\\. Angry Men\n");
+    assert_eq!(ordered_lists(&result), 0);
+    assert_round_trip(&result, &result, &Options::default());
+    // Too long a number, or one without a space after it, is no marker
+    assert_eq!(
+        parse_and_serialize("1234567890. Digits\n"),
+        "1234567890. Digits\n"
+    );
+    assert_eq!(parse_and_serialize("3.14 is pi\n"), "3.14 is pi\n");
+}
+
+#[test]
+fn test_number_wrapped_to_start_of_line() {
+    let options = Options {
+        line_width: LineWidth::new(20).unwrap(),
+        ..Options::default()
+    };
+    assert_round_trip(
+        "Aaaa bbbb cccc dddd 1. Liftoff\n",
+        "Aaaa bbbb cccc dddd\n1\\. Liftoff\n",
+        &options,
+    );
+    assert_round_trip(
+        " -  Aaaa bbbb cccc dddd 1) Liftoff\n",
+        " -  Aaaa bbbb cccc dddd\n    1\\) Liftoff\n",
+        &options,
+    );
+    // Only an item numbered 1 can interrupt a paragraph
+    assert_round_trip(
+        "Aaaa bbbb cccc dddd 2. Liftoff\n",
+        "Aaaa bbbb cccc dddd\n2. Liftoff\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_hard_break_in_emphasis() {
+    let options = Options::default();
+    assert_round_trip(
+        "*line one\\\nline two*\n",
+        "*line one  \nline two*\n",
+        &options,
+    );
+    assert_round_trip("**a  \nb** c\n", "**a  \nb** c\n", &options);
+    assert_round_trip("> - *a\\\n>   b*\n", ">  -  *a  \n>     b*\n", &options);
+}
+
+#[test]
+fn test_hard_break_in_emphasis_with_backslash() {
+    let options = Options {
+        hard_break: crate::HardBreak::Backslash,
+        ..Options::default()
+    };
+    assert_round_trip(
+        "*a  \nb* and **c  \nd**\n",
+        "*a\\\nb* and **c\\\nd**\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_hard_break_in_link_text() {
+    let options = Options::default();
+    assert_round_trip(
+        "[link one\\\ntwo](https://example.com/ \"Title\")\n",
+        "[link one  \ntwo](https://example.com/ \"Title\")\n",
+        &options,
+    );
+    assert_round_trip("[*a\\\nb*](/u)\n", "[*a  \nb*](/u)\n", &options);
+    let options = Options {
+        hard_break: crate::HardBreak::Backslash,
+        ..Options::default()
+    };
+    assert_round_trip(
+        "[a  \nb](https://example.com/)\n",
+        "[a\\\nb](https://example.com/)\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_soft_break_in_emphasis_and_link() {
+    let options = Options::default();
+    assert_round_trip(
+        "Some *emphasized\ntext* and [a\nlink](https://example.com/).\n",
+        "Some *emphasized\ntext* and [a link].\n\n[a link]: https://example.com/\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_hard_break_in_heading_emphasis() {
+    let options = Options::default();
+    assert_round_trip("*a\\\nb*\n===\n", "*a  \nb*\n==\n", &options);
+    let options = Options {
+        heading_allow_multiline: false,
+        ..Options::default()
+    };
+    assert_round_trip("*a\\\nb*\n===\n", "*a b*\n=====\n", &options);
+}
+
+// Empty inline elements
+
+#[test]
+fn test_empty_link_text_becomes_autolink() {
+    let options = Options::default();
+    assert_round_trip(
+        "[](https://example.com/)\n",
+        "<https://example.com/>\n",
+        &options,
+    );
+    assert_round_trip(
+        "# [](https://example.com/)\n",
+        "<https://example.com/>\n======================\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_empty_link_text_preserved() {
+    let options = Options {
+        link_empty_text: crate::EmptyLinkText::Preserve,
+        ..Options::default()
+    };
+    assert_round_trip(
+        "[](https://example.com/)\n",
+        "[](https://example.com/)\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_empty_link_text_kept_inline() {
+    let options = Options::default();
+    // A title would be lost in an autolink, and whitespace can't be a label
+    assert_round_trip(
+        "[](https://example.com/ \"Title\")\n",
+        "[](https://example.com/ \"Title\")\n",
+        &options,
+    );
+    assert_round_trip(
+        "[ ](https://example.com/)\n",
+        "[ ](https://example.com/)\n",
+        &options,
+    );
+    assert_round_trip("[](/local)\n", "[](/local)\n", &options);
+    assert_round_trip("[]()\n", "[]()\n", &options);
+}
+
+#[test]
+fn test_empty_image_alt_kept() {
+    let options = Options::default();
+    assert_round_trip(
+        "![](https://example.com/a.png)\n",
+        "![](https://example.com/a.png)\n",
+        &options,
+    );
+    assert_round_trip("![](a.png \"Title\")\n", "![](a.png \"Title\")\n", &options);
+}
+
+#[test]
+fn test_empty_emphasis_dropped_with_warning() {
+    let options = Options {
+        entities: crate::EntityMode::Decode,
+        ..Options::default()
+    };
+    let result = crate::format_with_warnings("a *&nbsp;* b **&nbsp;** c\n", &options).unwrap();
+    assert_eq!(result.output, "a \u{a0} b \u{a0} c\n");
+    let messages: Vec<_> = result.warnings.iter().map(|w| w.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        ["empty emphasis dropped", "empty strong emphasis dropped"]
+    );
+    assert!(result.warnings.iter().all(|w| w.line == 1));
+}
+
+#[test]
+fn test_unclosed_delimiters_stay_text() {
+    let options = Options::default();
+    assert_round_trip("a **** b ** c\n", "a \\*\\*\\*\\* b \\*\\* c\n", &options);
+}
+
+// Bare URLs and autolinks
+
+#[test]
+fn test_autolink_at_wrap_boundary_kept_whole() {
+    let options = Options::default();
+    let input = "Some prose that goes on until the line is nearly full, then \
+                 <https://example.com/path_with_many_under_scores> ends it.\n";
+    assert_round_trip(
+        input,
+        "Some prose that goes on until the line is nearly full, then\n\
+         <https://example.com/path_with_many_under_scores> ends it.\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_bare_url_underscores_not_escaped() {
+    let options = Options::default();
+    let input = "Some prose that goes on until the line is nearly full, then \
+                 https://example.com/path_with_many_under_scores ends it.\n";
+    assert_round_trip(
+        input,
+        "Some prose that goes on until the line is nearly full, then\n\
+         https://example.com/path_with_many_under_scores ends it.\n",
+        &options,
+    );
+    // Outside URLs, underscores are still escaped
+    assert_round_trip(
+        "Use ALL_CAPS for www.example.com/ALL_CAPS.\n",
+        "Use ALL\\_CAPS for www.example.com/ALL_CAPS.\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_urls_at_end_of_table_cell() {
+    let options = Options::default();
+    assert_round_trip(
+        "| a | b |\n|---|---|\n| <https://example.com/a_b> | see https://example.com/c_d |\n",
+        "| a                         | b                           |\n\
+         | ------------------------- | --------------------------- |\n\
+         | <https://example.com/a_b> | see https://example.com/c_d |\n",
+        &options,
+    );
+}
+
+// Block spacing
+
+#[test]
+fn test_block_spacing_list_then_code_block() {
+    let options = Options::default();
+    assert_round_trip(
+        "- a\n- b\n```\ncode\n```\n",
+        " -  a\n -  b\n\n~~~~\ncode\n~~~~\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_table_then_section_heading() {
+    let options = Options::default();
+    assert_round_trip(
+        "| a |\n|---|\n| 1 |\n## Next\n",
+        "| a   |\n| --- |\n| 1   |\n\n\nNext\n----\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_heading_then_table_in_block_quote() {
+    let options = Options::default();
+    assert_round_trip(
+        "> # Title\n> | a |\n> |---|\n> | 1 |\n",
+        "> Title\n> =====\n>\n> | a   |\n> | --- |\n> | 1   |\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_code_block_then_paragraph_in_list_item() {
+    let options = Options::default();
+    assert_round_trip(
+        "- ```\n  code\n  ```\n\n  text\n",
+        " -  ~~~~\n    code\n    ~~~~\n\n    text\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_code_block_then_block_quote_in_list_item() {
+    let options = Options::default();
+    assert_round_trip(
+        "- text\n\n  ```\n  code\n  ```\n  > quote\n",
+        " -  text\n\n    ~~~~\n    code\n    ~~~~\n\n    > quote\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_block_quote_then_paragraph_in_list_item() {
+    let options = Options::default();
+    assert_round_trip(
+        "- > quote\n\n  text\n",
+        " -  > quote\n\n    text\n",
+        &options,
+    );
+    assert_round_trip(
+        "- > ***\n",
+        " -  > - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_nested_list_then_html_block_in_list_item() {
+    let options = Options::default();
+    assert_round_trip(
+        "1. - a\n   - b\n\n   <div>\n   html\n   </div>\n",
+        "1.  -   a\n     -  b\n\n    <div>\n    html\n    </div>\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_paragraph_then_description_list_in_list_item() {
+    let options = Options::default();
+    assert_round_trip(
+        "- text\n\n  term\n  : details\n\n    more\n",
+        " -  text\n\n    term\n    :   details\n\n        more\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_description_details_in_block_quote() {
+    let options = Options::default();
+    assert_round_trip(
+        "> term\n> : details\n>\n>     more\n",
+        "> term\n> :   details\n>\n>     more\n",
+        &options,
+    );
+}
+
+#[test]
+fn test_block_spacing_around_blocks_writing_nothing() {
+    let options = Options::default();
+    // The definitions are moved to the end, and the block quote they were
+    // in is gone, along with the blank lines around it
+    assert_round_trip(
+        "text\n\n> [x]: https://example.com/\n\n- a\n",
+        "text\n\n -  a\n",
+        &options,
+    );
+    // Footnote definitions are moved too, so nothing precedes the list
+    assert_round_trip(
+        "[^1]: Note.\n\n- a[^1]\n",
+        " -  a[^1]\n\n[^1]: Note.\n",
+        &options,
+    );
+}
+
+// Escaped characters in the input
+
+/// Assert that `output` is rendered into the same HTML as `input`.
+fn assert_renders_same(input: &str, output: &str) {
+    let options = crate::comrak_options(&crate::config::Config::default());
+    assert_eq!(
+        comrak::markdown_to_html(output, &options),
+        comrak::markdown_to_html(input, &options),
+        "output:\n{}",
+        output
+    );
+}
+
+#[test]
+fn test_escaped_asterisks_not_escaped_twice() {
+    let options = Options::default();
+    let input = "\\*not emphasis\\* and \\_not either\\_\n";
+    assert_round_trip(input, input, &options);
+    assert_renders_same(input, input);
+    // Without the source, the escapes are made again from the text alone
+    assert_eq!(parse_and_serialize(input), input);
+    // A literal backslash before an asterisk needs its own escape, and so
+    // does the asterisk, but only one each
+    let input = "a \\\\* b\n";
+    assert_round_trip(input, "a \\\\\\* b\n", &options);
+    assert_renders_same(input, "a \\\\\\* b\n");
+}
+
+#[test]
+fn test_escaped_backslashes_not_escaped_twice() {
+    let options = Options::default();
+    let input = "One \\\\ backslash, *and \\\\ another*.\n";
+    assert_round_trip(input, "One \\\\ backslash, *and \\\\ another*.\n", &options);
+    assert_renders_same(input, "One \\\\ backslash, *and \\\\ another*.\n");
+}
+
+#[test]
+fn test_escaped_pipe_in_table_cell_not_escaped_twice() {
+    let options = Options::default();
+    let input = "| a | b |\n|---|---|\n| x \\| y | \\*z\\* |\n";
+    let expected = "| a      | b     |\n\
+                    | ------ | ----- |\n\
+                    | x \\| y | \\*z\\* |\n";
+    assert_round_trip(input, expected, &options);
+    assert_renders_same(input, expected);
+}
+
+#[test]
+fn test_windows_path_in_prose_read_back_the_same() {
+    let options = Options::default();
+    let input = "Open C:\\Users\\foo and then C:\\\\Users\\\\bar.\n";
+    let expected = "Open C:\\\\Users\\\\foo and then C:\\\\Users\\\\bar.\n";
+    assert_round_trip(input, expected, &options);
+    assert_renders_same(input, expected);
+}
+
+#[test]
+fn test_nested_brackets_in_link_text_not_escaped() {
+    let options = Options::default();
+    let input = "[link [foo [bar]]](/uri) and [a [b] c](/d)\n";
+    assert_round_trip(input, input, &options);
+    assert_renders_same(input, input);
+}
+
+#[test]
+fn test_bracketed_prose_not_escaped() {
+    let options = Options::default();
+    for input in [
+        "text with [brackets] here\n",
+        "Checkbox [x] done, and [[wiki]] too\n",
+        "A lone [ and a lone ] stay.\n",
+    ] {
+        assert_round_trip(input, input, &options);
+        assert_renders_same(input, input);
+    }
+    // Brackets that would make a link are still escaped
+    let input = "\\[not a link\\](/u) and \\[nor this\\][x]\n";
+    assert_round_trip(input, input, &options);
+    assert_renders_same(input, input);
+}
+
+// Lazy backslash escapes
+
+fn lazy_backslash_options() -> Options {
+    Options {
+        escape_backslash: crate::BackslashEscape::Lazy,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn test_lazy_backslash_windows_paths() {
+    let options = lazy_backslash_options();
+    let input = "Open C:\\Program Files\\App and then C:\\\\Users\\\\bar.\n";
+    let expected = "Open C:\\Program Files\\App and then C:\\Users\\bar.\n";
+    assert_round_trip(input, expected, &options);
+    assert_renders_same(input, expected);
+}
+
+#[test]
+fn test_lazy_backslash_regexes() {
+    let options = lazy_backslash_options();
+    // Before punctuation, like the `.` of `\.`, a backslash is an escape, so
+    // a literal one still needs its own
+    let input = "Match \\\\d+\\\\.\\\\d and \\\\w\\*\\\\s.\n";
+    let expected = "Match \\d+\\\\.\\d and \\w\\*\\s.\n";
+    assert_round_trip(input, expected, &options);
+    assert_renders_same(input, expected);
+}
+
+#[test]
+fn test_lazy_backslash_latex() {
+    let options = lazy_backslash_options();
+    let input = "Write \\\\alpha, \\\\frac{a}{b}, and \\\\\\\\ for a new row.\n";
+    let expected = "Write \\alpha, \\frac{a}{b}, and \\\\\\\\ for a new row.\n";
+    assert_round_trip(input, expected, &options);
+    assert_renders_same(input, expected);
+}
+
+#[test]
+fn test_lazy_backslash_before_whitespace_and_at_end() {
+    let options = lazy_backslash_options();
+    // A literal backslash at the end of a line would become a hard line
+    // break, and one at the end of the text may end a line once rewrapped
+    let input = "a\\\\ b\\\\\nc `d` e\\\\`f` \\\\\n";
+    assert_round_trip(input, input, &options);
+    assert_renders_same(input, input);
+}
+
+#[test]
+fn test_lazy_backslash_without_source() {
+    let options = lazy_backslash_options();
+    let input = "C:\\Users\\foo\n";
+    assert_eq!(
+        parse_and_serialize_with_options(input, &options),
+        "C:\\Users\\foo\n"
+    );
+}
+
+// Table cell width
+
+fn table_cell_options(wrap_cells: crate::CellWrap) -> Options {
+    Options {
+        table_max_column_width: Some(40),
+        table_wrap_cells: wrap_cells,
+        ..Options::default()
+    }
+}
+
+/// A sentence of 300 characters, for a table cell.
+fn long_cell_sentence() -> String {
+    let words = "Tables are hard to read when a single cell runs on for much \
+                 longer than the line, so the whole table grows as wide as it";
+    let mut sentence = String::new();
+    for word in words.split(' ').cycle() {
+        if sentence.len() + 1 + word.len() > 299 {
+            break;
+        }
+        if !sentence.is_empty() {
+            sentence.push(' ');
+        }
+        sentence.push_str(word);
+    }
+    while sentence.len() < 299 {
+        sentence.push('a');
+    }
+    sentence.push('.');
+    sentence
+}
+
+#[test]
+fn test_table_wrap_cells_br() {
+    let options = table_cell_options(crate::CellWrap::Br);
+    let sentence = long_cell_sentence();
+    assert_eq!(sentence.len(), 300);
+    let input = format!("| Name | Description |\n|---|---|\n| a | {} |\n", sentence);
+    let result = parse_and_serialize_with_options_and_warnings(&input, &options);
+    assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    let output = result.output;
+    let row = output.lines().nth(2).unwrap();
+    let cell = row
+        .strip_prefix("| a    | ")
+        .and_then(|row| row.strip_suffix(" |"))
+        .unwrap();
+    // The text is broken where a paragraph 40 columns wide would be
+    let lines: Vec<_> = cell.split("<br>").collect();
+    assert_eq!(
+        lines,
+        parse_and_serialize_with_width(&sentence, 40)
+            .lines()
+            .collect::<Vec<_>>()
+    );
+    assert!(lines.len() > 7);
+    assert!(lines.iter().all(|line| line.len() <= 40), "{:?}", lines);
+    // The column is padded to the widest line, not to the whole text
+    let widest = lines.iter().map(|line| line.len()).max().unwrap();
+    assert_eq!(
+        output.lines().nth(1).unwrap(),
+        format!("| ---- | {} |", "-".repeat(widest))
+    );
+    // Read back, the text is still in one cell of the row
+    let arena = Arena::new();
+    let root = parse_document(&arena, &output, &comrak_options());
+    let table = root.first_child().unwrap();
+    let row = table.children().nth(1).unwrap();
+    assert_eq!(row.children().count(), 2);
+    let cell = row.children().nth(1).unwrap();
+    let breaks = cell
+        .children()
+        .filter(|child| matches!(&child.data.borrow().value, NodeValue::HtmlInline(html) if html == "<br>"))
+        .count();
+    assert_eq!(breaks, lines.len() - 1);
+    assert_eq!(parse_and_serialize_with_options(&output, &options), output);
+}
+
+#[test]
+fn test_table_wrap_cells_br_keeps_code_spans_and_links() {
+    let options = table_cell_options(crate::CellWrap::Br);
+    let input = "| a |\n|---|\n| Run `cargo test --workspace --all-features` \
+                 and read [the guide to testing](docs/testing.md) first. |\n";
+    let output = parse_and_serialize_with_options(input, &options);
+    assert_eq!(
+        output,
+        "| a                                       |\n\
+         | --------------------------------------- |\n\
+         | Run<br>`cargo test --workspace --all-features`<br>and read<br>\
+         [the guide to testing](docs/testing.md)<br>first. |\n"
+    );
+}
+
+#[test]
+fn test_table_max_column_width_warning() {
+    let options = table_cell_options(crate::CellWrap::None);
+    let sentence = long_cell_sentence();
+    let input = format!("| a | b |\n|---|---|\n| c | {} |\n", sentence);
+    let result = parse_and_serialize_with_options_and_warnings(&input, &options);
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].line, 3);
+    assert!(
+        result.warnings[0].message.contains("300 columns wide"),
+        "{}",
+        result.warnings[0].message
+    );
+    assert!(result.output.contains(&sentence));
+    // Without the maximum, there is no warning
+    let result = parse_and_serialize_with_options_and_warnings(&input, &Options::default());
+    assert!(result.warnings.is_empty());
+}
+
+// Links to GitHub references
+
+fn format_with_github_autolinks(input: &str, strip: bool) -> String {
+    let options = Options {
+        autolink_github_repo: Some(crate::GitHubRepo::new("dahlia/hongdown".to_string()).unwrap()),
+        autolink_github_strip: strip,
+        ..Options::default()
+    };
+    crate::format(input, &options).unwrap()
+}
+
+#[test]
+fn test_github_autolinks() {
+    let input = "Fixes #123, GH-45, and other/repo#6, thanks to @octo-cat.\n";
+    let output = format_with_github_autolinks(input, false);
+    assert_eq!(
+        output,
+        "Fixes [#123], [GH-45], and [other/repo#6], thanks to [@octo-cat].\n\n\
+         [#123]: https://github.com/dahlia/hongdown/issues/123\n\
+         [gh-45]: https://github.com/dahlia/hongdown/issues/45\n\
+         [other/repo#6]: https://github.com/other/repo/issues/6\n\
+         [@octo-cat]: https://github.com/octo-cat\n"
+    );
+    assert_eq!(format_with_github_autolinks(&output, false), output);
+    assert_eq!(format_with_github_autolinks(&output, true), input);
+}
+
+#[test]
+fn test_github_autolinks_skip_code_links_and_headings() {
+    let input = "Fix #1\n======\n\nNot `#2`, [#3](https://example.com/), or #fff, #4abc, a/b/c#5, \
+                 or me@example.com.\n\n~~~~\n#6\n~~~~\n";
+    assert_eq!(
+        format_with_github_autolinks(input, false),
+        "Fix #1\n======\n\nNot `#2`, [#3], or #fff, #4abc, a/b/c#5, or me@example.com.\n\n\
+         ~~~~\n#6\n~~~~\n\n[#3]: https://example.com/\n"
+    );
+}
+
+#[test]
+fn test_github_autolinks_disabled_region() {
+    let input = "<!-- hongdown-disable -->\n\nSee #1.\n";
+    assert_eq!(format_with_github_autolinks(input, false), input);
+}
+
+#[test]
+fn test_github_autolinks_strip_keeps_other_links() {
+    let input = "See [#1](https://github.com/dahlia/hongdown/pull/1), \
+                 [#2](https://github.com/other/repo/issues/2), and \
+                 [issue 3](https://github.com/dahlia/hongdown/issues/3).\n";
+    assert_eq!(
+        format_with_github_autolinks(input, true),
+        "See #1, [#2], and [issue 3].\n\n\
+         [#2]: https://github.com/other/repo/issues/2\n\
+         [issue 3]: https://github.com/dahlia/hongdown/issues/3\n"
+    );
+}
+
+// `<details>` blocks
+
+#[test]
+fn test_details_without_blank_lines() {
+    let input = "<details>\n<summary>Example</summary>\n* First\n* Second\n```rust\nfn main() {}\n```\n</details>\n";
+    let output = crate::format(input, &crate::Options::default()).unwrap();
+    assert_eq!(
+        output,
+        "<details>\n<summary>Example</summary>\n\n -  First\n -  Second\n\n~~~~ rust\nfn main() {}\n~~~~\n\n</details>\n"
+    );
+    assert_eq!(
+        crate::format(&output, &crate::Options::default()).unwrap(),
+        output
+    );
+}
+
+#[test]
+fn test_details_with_blank_lines() {
+    let input = "Before.\n\n<details open>\n<summary>Example</summary>\n\n\n* First\n* Second\n\n```rust\nfn main() {}\n```\n\n</details>\n\nAfter.\n";
+    assert_eq!(
+        crate::format(input, &crate::Options::default()).unwrap(),
+        "Before.\n\n<details open>\n<summary>Example</summary>\n\n -  First\n -  Second\n\n~~~~ rust\nfn main() {}\n~~~~\n\n</details>\n\nAfter.\n"
+    );
+}
+
+#[test]
+fn test_details_nested() {
+    let input = "<details>\n<summary>Outer</summary>\n<details>\n<summary>Inner</summary>\n* Inner item\n</details>\n* Outer item\n</details>\n";
+    assert_eq!(
+        crate::format(input, &crate::Options::default()).unwrap(),
+        "<details>\n<summary>Outer</summary>\n\n<details>\n<summary>Inner</summary>\n\n -  Inner item\n\n</details>\n\n -  Outer item\n\n</details>\n"
+    );
+}
+
+#[test]
+fn test_details_preserve() {
+    let options = crate::Options {
+        html_details: crate::DetailsHandling::Preserve,
+        ..Default::default()
+    };
+    let input =
+        "<details>\n<summary>Example</summary>\n* First\n```rust\nfn main() {}\n```\n</details>\n";
+    assert_eq!(crate::format(input, &options).unwrap(), input);
+}
+
+#[test]
+fn test_details_warning_lines() {
+    let input = "<details>\n<summary>Example</summary>\n* First\n</details>\n\n<!-- hongdown-disable no-such-rule -->\n";
+    let result = crate::format_with_warnings(input, &crate::Options::default()).unwrap();
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].line, 6);
+}
+
+// Footnote references next to punctuation
+
+fn format_with_width(input: &str, line_width: usize) -> String {
+    let options = Options {
+        line_width: crate::LineWidth::new(line_width).unwrap(),
+        ..Options::default()
+    };
+    crate::format(input, &options).unwrap()
+}
+
+#[test]
+fn test_footnote_references_stay_glued_at_every_width() {
+    let input = "A sentence with a reference at its end[^1].  Two references \
+                 word[^1][^2], then *emphasis with one[^2]*.  And **strong[^1]**; \
+                 `code`[^2], done.\n\n[^1]: One.\n\n[^2]: Two.\n";
+    for width in 20..=90 {
+        let output = format_with_width(input, width);
+        let prose = output.split("\n\n[^1]:").next().unwrap();
+        for glued in [
+            "end[^1].",
+            "word[^1][^2],",
+            "one[^2]*.",
+            "**strong[^1]**;",
+            "`code`[^2],",
+        ] {
+            assert!(
+                prose.contains(glued),
+                "{:?} not in output at width {}:\n{}",
+                glued,
+                width,
+                output
+            );
+        }
+        assert!(!prose.contains('\\'), "width {}:\n{}", width, output);
+        assert_eq!(format_with_width(&output, width), output, "width {}", width);
+    }
+}
+
+#[test]
+fn test_footnote_reference_with_colon_never_starts_line() {
+    let input = "Some text here that is long enough to push the reference to the \
+                 boundary xx [^1]: more words\n\n[^1]: One.\n";
+    for width in 60..=90 {
+        let output = format_with_width(input, width);
+        assert!(
+            output.starts_with("Some text") && output.matches("\n[^1]:").count() == 1,
+            "width {}:\n{}",
+            width,
+            output
+        );
+        assert_eq!(format_with_width(&output, width), output, "width {}", width);
+    }
+    assert_eq!(
+        format_with_width(input, 76),
+        "Some text here that is long enough to push the reference to the boundary xx [^1]:\n\
+         more words\n\n[^1]: One.\n"
+    );
+}
+
+#[test]
+fn test_footnote_reference_at_line_start() {
+    let input = "Some text that is long enough to wrap right before the reference \
+                 [^1] that follows.\n\n[^1]: One.\n";
+    assert_eq!(
+        format_with_width(input, 66),
+        "Some text that is long enough to wrap right before the reference\n\
+         [^1] that follows.\n\n[^1]: One.\n"
+    );
+}
+
+#[test]
+fn test_wrap_sentence_after_footnote_reference() {
+    let options = Options {
+        wrap: crate::WrapMode::Sentence,
+        ..Options::default()
+    };
+    let input = "First sentence.[^1] Second one[^2]. Third one[^1][^2]. \
+                 [^2]: Not a definition.\n\n[^1]: One.\n\n[^2]: Two.\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "First sentence.[^1]\nSecond one[^2].\nThird one[^1][^2]. [^2]: Not a definition.\n\n\
+         [^1]: One.\n[^2]: Two.\n"
+    );
+}
+
+// Diff-friendly output
+
+#[test]
+fn test_ordered_list_numbering_ones() {
+    let options = Options {
+        ordered_list_numbering: crate::OrderedNumbering::Ones,
+        ..Options::default()
+    };
+    assert_eq!(
+        crate::format("1. One\n2. Two\n3. Three\n", &options).unwrap(),
+        "1.  One\n1.  Two\n1.  Three\n"
+    );
+}
+
+#[test]
+fn test_table_padding_compact() {
+    let options = Options {
+        table_padding: crate::TablePadding::Compact,
+        ..Options::default()
+    };
+    assert_eq!(
+        crate::format(
+            "| Name | Value | Note |\n|:-----|------:|:----:|\n| a | 1 | long note |\n",
+            &options
+        )
+        .unwrap(),
+        "| Name | Value | Note |\n| :-- | --: | :-: |\n| a | 1 | long note |\n"
+    );
+}
+
+/// The alignments of the columns of the first table of `input`.
+fn table_alignments(input: &str) -> Vec<comrak::nodes::TableAlignment> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, input, &comrak_options());
+    root.descendants()
+        .find_map(|node| match &node.data.borrow().value {
+            NodeValue::Table(table) => Some(table.alignments.clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+/// A table with a column of each alignment, and cells wider than the
+/// shortest delimiters.
+const ALIGNMENT_TABLE: &str = "\
+| Plain | Left | Right | Center |
+|-------|:-----|------:|:------:|
+| one | two | three | four |
+";
+
+#[test]
+fn test_table_separator_full() {
+    let output = crate::format(ALIGNMENT_TABLE, &Options::default()).unwrap();
+    assert_eq!(
+        output,
+        "\
+| Plain | Left | Right | Center |
+| ----- | :--- | ----: | :----: |
+| one   | two  | three |  four  |
+"
+    );
+    assert_eq!(table_alignments(&output), table_alignments(ALIGNMENT_TABLE));
+}
+
+#[test]
+fn test_table_separator_minimal() {
+    let options = Options {
+        table_separator: crate::TableSeparator::Minimal,
+        ..Options::default()
+    };
+    let output = crate::format(ALIGNMENT_TABLE, &options).unwrap();
+    assert_eq!(
+        output,
+        "\
+| Plain | Left | Right | Center |
+| --- | :-- | --: | :-: |
+| one   | two  | three |  four  |
+"
+    );
+    assert_eq!(table_alignments(&output), table_alignments(ALIGNMENT_TABLE));
+    let options = Options {
+        table_padding: crate::TablePadding::Compact,
+        ..options
+    };
+    let output = crate::format(ALIGNMENT_TABLE, &options).unwrap();
+    assert_eq!(
+        output,
+        "\
+| Plain | Left | Right | Center |
+| --- | :-- | --: | :-: |
+| one | two | three | four |
+"
+    );
+    assert_eq!(table_alignments(&output), table_alignments(ALIGNMENT_TABLE));
+}
+
+/// The numbers of the lines that differ between `before` and `after`, which
+/// have the same number of lines.
+fn changed_lines(before: &str, after: &str) -> Vec<usize> {
+    assert_eq!(before.lines().count(), after.lines().count());
+    before
+        .lines()
+        .zip(after.lines())
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// A table whose second column is as wide as its widest cell, `maybe`.
+const STICKY_TABLE: &str = "\
+| Feature | Ok |
+|---|---|
+| Tables | yes |
+| Lists | maybe |
+| Quotes | no |
+";
+
+#[test]
+fn test_table_width_growth_exact() {
+    let options = Options::default();
+    let before = crate::format(STICKY_TABLE, &options).unwrap();
+    let edited = before.replace("maybe", "no");
+    let after = crate::format(&edited, &options).unwrap();
+    // The column gets narrower, so every row changes
+    assert_eq!(changed_lines(&before, &after), [1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_table_width_growth_sticky() {
+    let options = Options {
+        table_width_growth: crate::TableWidthGrowth::Sticky,
+        ..Options::default()
+    };
+    let before = crate::format(STICKY_TABLE, &options).unwrap();
+    assert_eq!(
+        before,
+        "\
+| Feature  | Ok       |
+| -------- | -------- |
+| Tables   | yes      |
+| Lists    | maybe    |
+| Quotes   | no       |
+"
+    );
+    let edited = before.replace("maybe", "no");
+    let after = crate::format(&edited, &options).unwrap();
+    assert_eq!(changed_lines(&before, &after), [4]);
+    assert_eq!(crate::format(&after, &options).unwrap(), after);
+    // A cell wider than the column makes it grow to the next step
+    let edited = before.replace("maybe", "sometimes");
+    let after = crate::format(&edited, &options).unwrap();
+    assert_eq!(after.lines().nth(1), Some("| -------- | ------------ |"));
+}
+
+#[test]
+fn test_table_width_growth_sticky_step() {
+    let options = Options {
+        table_width_growth: crate::TableWidthGrowth::Sticky,
+        table_width_step: crate::TableWidthStep::new(1).unwrap(),
+        ..Options::default()
+    };
+    // With a step of 1, columns are only kept from getting narrower
+    let before = crate::format(STICKY_TABLE, &options).unwrap();
+    assert_eq!(
+        before,
+        crate::format(STICKY_TABLE, &Options::default()).unwrap()
+    );
+    let after = crate::format(&before.replace("maybe", "no"), &options).unwrap();
+    assert_eq!(changed_lines(&before, &after), [4]);
+}
+
+/// A hand-aligned table with uneven spacing, a trailing space, an escaped
+/// pipe, and a reference link.
+const HAND_ALIGNED_TABLE: &str = "\
+|  Name   |  Min |  Max |  Unit |
+|:--------|-----:|-----:|:-----:|
+|  width  |    0 |  100 |   px  |
+|  a \\| b |   -1 |    1 |  [em] | \n";
+
+#[test]
+fn test_table_format_preserve() {
+    let options = Options {
+        table_format: crate::TableFormat::Preserve,
+        ..Options::default()
+    };
+    let input = format!(
+        "Text before.\n\n{}\nText after.\n\n[em]: https://example.com/em\n",
+        HAND_ALIGNED_TABLE
+    );
+    assert_eq!(crate::format(&input, &options).unwrap(), input);
+    let input = format!("Text before.\n{}", HAND_ALIGNED_TABLE);
+    let output = crate::format(&input, &options).unwrap();
+    assert_eq!(output, format!("Text before.\n\n{}", HAND_ALIGNED_TABLE));
+}
+
+#[test]
+fn test_table_format_align() {
+    let input = format!("{}\n[em]: https://example.com/em\n", HAND_ALIGNED_TABLE);
+    assert_eq!(
+        crate::format(&input, &Options::default()).unwrap(),
+        "\
+| Name   | Min | Max | Unit |
+| :----- | --: | --: | :--: |
+| width  |   0 | 100 |  px  |
+| a \\| b |  -1 |   1 | [em] |
+
+[em]: https://example.com/em
+"
+    );
+}
+
+#[test]
+fn test_table_format_preserve_in_containers() {
+    let options = Options {
+        table_format: crate::TableFormat::Preserve,
+        ..Options::default()
+    };
+    let input = ">  | a  |b|\n>  |----|-|\n>  | 1  |2|\n\n- Item\n\n  |  a |b |\n  |---:|--|\n  |  1 |2 |\n";
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "> | a  |b|\n> |----|-|\n> | 1  |2|\n\n -  Item\n\n    |  a |b |\n    |---:|--|\n    |  1 |2 |\n"
+    );
+}
+
+#[test]
+fn test_table_too_large_to_align() {
+    // Padding every row to the width of one very wide cell would take
+    // more memory than the limit
+    let wide = "x".repeat(super::limits::MAX_ALIGNED_TABLE_SIZE / 100);
+    let mut input = format!("| {} | b |\n|---|---|\n", wide);
+    input.push_str(&"| a | b |\n".repeat(200));
+    let result = crate::format_with_warnings(&input, &Options::default()).unwrap();
+    let lines: Vec<&str> = result.output.lines().collect();
+    assert_eq!(lines[1], "| --- | --- |");
+    assert_eq!(lines[2], "| a | b |");
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].line, 1);
+    assert_eq!(
+        result.warnings[0].message,
+        "table is too large to align, so its cells are not padded"
+    );
+}
+
+#[test]
+fn test_max_consecutive_blank_lines() {
+    let input = "Intro.\n\nSection\n-------\n\nText.\n";
+    let options = Options {
+        max_consecutive_blank_lines: crate::MaxBlankLines::new(1).unwrap(),
+        ..Options::default()
+    };
+    assert_eq!(
+        crate::format(input, &options).unwrap(),
+        "Intro.\n\nSection\n-------\n\nText.\n"
+    );
+    assert_eq!(
+        crate::format(input, &Options::default()).unwrap(),
+        "Intro.\n\n\nSection\n-------\n\nText.\n"
+    );
+}
+
+// Locales
+
+fn format_sentences(input: &str, locale: crate::Locale) -> String {
+    let options = Options {
+        wrap: WrapMode::Sentence,
+        locale,
+        ..Options::default()
+    };
+    crate::format(input, &options).unwrap()
+}
+
+#[test]
+fn test_locale_ko_sentences() {
+    let input = "설치는 간단합니다. npm을 실행하세요. 네. 그럴까? yes라고 답했다.\n";
+    assert_eq!(
+        format_sentences(input, crate::Locale::Ko),
+        "설치는 간단합니다.\nnpm을 실행하세요.\n네.\n그럴까?\nyes라고 답했다.\n"
+    );
+    assert_eq!(
+        format_sentences(input, crate::Locale::None),
+        "설치는 간단합니다. npm을 실행하세요.\n네. 그럴까? yes라고 답했다.\n"
+    );
+}
+
+#[test]
+fn test_locale_ko_keeps_english_rules_after_latin() {
+    // A sentence that ends in Latin script keeps the rules of English
+    assert_eq!(
+        format_sentences("Rust를 씁니다 e.g. cargo를 씁니다.\n", crate::Locale::Ko),
+        "Rust를 씁니다 e.g. cargo를 씁니다.\n"
+    );
+}
+
+#[test]
+fn test_locale_auto() {
+    let input = "네. npm을 실행하세요.\n\nYes. npm is run. 네. done.\n";
+    assert_eq!(
+        format_sentences(input, crate::Locale::Auto),
+        "네.\nnpm을 실행하세요.\n\nYes. npm is run.\n네. done.\n"
+    );
+}
+
+#[test]
+fn test_locale_ko_keeps_spaces_and_punctuation() {
+    let options = Options {
+        locale: crate::Locale::Ko,
+        ..Options::default()
+    };
+    // A line break between Hangul words is rendered as a space, so it
+    // stays one; middle dots and tildes aren't escaped
+    assert_eq!(
+        crate::format(
+            "한국어 문장이 길게 이어지는 경우에는 줄을 바꾸어도 단어 사이의 공백이 그대로\n\
+             남아야 합니다.  Node.js·Deno 환경에서 약 1~2분, 또는 3～4분이 걸립니다.\n",
+            &options
+        )
+        .unwrap(),
+        "한국어 문장이 길게 이어지는 경우에는 줄을 바꾸어도 단어 사이의 공백이 그대로\n\
+         남아야 합니다.  Node.js·Deno 환경에서 약 1~2분, 또는 3～4분이 걸립니다.\n"
+    );
+}
@@ -0,0 +1,116 @@
+//! Table of contents generation.
+
+use std::ops::Range;
+
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{Arena, parse_document};
+
+use super::{Serializer, escape, heading};
+use crate::slug::{Slugger, heading_anchor_text};
+
+impl<'a> Serializer<'a> {
+    /// Write a table of contents of the headings of the document `root` as
+    /// a list of links to their anchors.  Returns whether any entry was
+    /// written.
+    ///
+    /// Headings on the `excluded` lines, the previous content of the table
+    /// of contents, are left out and take no anchors.  Only headings at the
+    /// top level of the document are listed, but all headings take anchors,
+    /// so that the suffixes of duplicates match the ones GitHub adds.
+    ///
+    /// Entries are nested by heading level.  A heading that skips a level
+    /// is nested only one level deeper than the heading before it.
+    pub(super) fn serialize_toc<'b>(
+        &mut self,
+        root: &'b AstNode<'b>,
+        excluded: Range<usize>,
+    ) -> bool {
+        let min_level = self.options.toc_min_level.get();
+        let max_level = self.options.toc_max_level.get();
+        let mut slugger = Slugger::default();
+        // Levels of the entries enclosing the next one
+        let mut levels: Vec<u8> = Vec::new();
+        let mut lines = Vec::new();
+
+        for node in root.descendants() {
+            let level = match &node.data.borrow().value {
+                NodeValue::Heading(heading) => heading.level,
+                _ => continue,
+            };
+            if excluded.contains(&node.data.borrow().sourcepos.start.line) {
+                continue;
+            }
+            let text = self.toc_entry_text(node);
+            let slug = slugger.slug(&Self::anchor_text(&text));
+            let at_top_level = node
+                .parent()
+                .is_some_and(|parent| matches!(parent.data.borrow().value, NodeValue::Document));
+            if !at_top_level || level < min_level || level > max_level || text.is_empty() {
+                continue;
+            }
+
+            while levels.last().is_some_and(|&last| last >= level) {
+                levels.pop();
+            }
+            let indent = levels.len() * self.options.indent_width.get();
+            levels.push(level);
+            lines.push(format!(
+                "{}{}{}{}[{}](#{})",
+                " ".repeat(indent),
+                " ".repeat(self.options.leading_spaces.get()),
+                self.options.unordered_marker.as_char(),
+                " ".repeat(self.bullet_trailing_spaces()),
+                text,
+                slug
+            ));
+        }
+
+        for line in &lines {
+            self.write_line(line);
+        }
+        !lines.is_empty()
+    }
+
+    /// The text of the table of contents entry for `node`: the heading text
+    /// as it is formatted, but with only its code spans kept as markup, so
+    /// that it can be the text of a link.
+    fn toc_entry_text<'b>(&self, node: &'b AstNode<'b>) -> String {
+        let mut text = String::new();
+        self.collect_toc_entry_text(node, &mut text);
+        let text =
+            heading::normalize_heading(&text, &self.options.heading_strip_trailing_punctuation);
+        if self.options.heading_sentence_case {
+            self.to_sentence_case(&text)
+        } else {
+            text
+        }
+    }
+
+    fn collect_toc_entry_text<'b>(&self, node: &'b AstNode<'b>, text: &mut String) {
+        for child in node.children() {
+            match &child.data.borrow().value {
+                NodeValue::Text(t) => self.push_text(text, child, t),
+                NodeValue::Code(code) => text.push_str(&escape::format_code_span(&code.literal)),
+                NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+                NodeValue::Image(_)
+                | NodeValue::FootnoteReference(_)
+                | NodeValue::HtmlInline(_) => {}
+                _ => self.collect_toc_entry_text(child, text),
+            }
+        }
+    }
+
+    /// The text GitHub makes the anchor of a heading with the Markdown
+    /// `text` from, as it is read back.
+    fn anchor_text(text: &str) -> String {
+        let arena = Arena::new();
+        let root = parse_document(
+            &arena,
+            &format!("# {}", text),
+            &crate::base_comrak_options(),
+        );
+        root.first_child()
+            .map(heading_anchor_text)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,284 @@
+//! Link and image URL normalization.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use super::escape;
+use crate::UrlEncoding;
+
+/// Normalize the percent-encoding of a link or image URL.
+///
+/// Links to fragments of the document and `mailto:` and `tel:` URLs are
+/// returned as they are, whatever `encoding` says.
+pub(super) fn normalize_url(url: &str, encoding: UrlEncoding) -> Cow<'_, str> {
+    let lowercase_start = url.get(..7).unwrap_or(url).to_ascii_lowercase();
+    if url.starts_with('#')
+        || lowercase_start.starts_with("mailto:")
+        || lowercase_start.starts_with("tel:")
+    {
+        return Cow::Borrowed(url);
+    }
+    match encoding {
+        UrlEncoding::Preserve => Cow::Borrowed(url),
+        UrlEncoding::Encode => encode(url),
+        UrlEncoding::DecodeUnreserved => decode_unreserved(url),
+    }
+}
+
+/// Percent-encode the characters of `url` that are not allowed in a URL,
+/// except in its host name.  A `%` that starts an escape is kept.
+fn encode(url: &str) -> Cow<'_, str> {
+    let authority_end = authority_end(url);
+    let (head, rest) = url.split_at(authority_end);
+    let bytes = rest.as_bytes();
+    let needs_encoding = |i: usize| match bytes[i] {
+        b'%' => !is_escape(bytes, i),
+        byte => !is_allowed(byte),
+    };
+    if !(0..bytes.len()).any(needs_encoding) {
+        return Cow::Borrowed(url);
+    }
+    let mut encoded = String::with_capacity(url.len() + 16);
+    encoded.push_str(head);
+    for (i, c) in rest.char_indices() {
+        if c.is_ascii() && !needs_encoding(i) {
+            encoded.push(c);
+        } else {
+            let mut buffer = [0; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+/// Decode the escapes in `url` of unreserved characters (letters, digits,
+/// `-`, `.`, `_`, and `~`), which mean the same with or without an escape.
+fn decode_unreserved(url: &str) -> Cow<'_, str> {
+    let bytes = url.as_bytes();
+    let decodable = |i: usize| is_escape(bytes, i) && is_unreserved(escaped_byte(bytes, i));
+    if !(0..bytes.len()).any(decodable) {
+        return Cow::Borrowed(url);
+    }
+    let mut decoded = String::with_capacity(url.len());
+    let mut last = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if decodable(i) {
+            decoded.push_str(&url[last..i]);
+            decoded.push(escaped_byte(bytes, i) as char);
+            i += 3;
+            last = i;
+        } else {
+            i += 1;
+        }
+    }
+    decoded.push_str(&url[last..]);
+    Cow::Owned(decoded)
+}
+
+/// The byte offset where the scheme and host of `url` end, or 0 if it
+/// doesn't start with a scheme followed by `//`.
+fn authority_end(url: &str) -> usize {
+    let Some(colon) = url.find("://") else {
+        return 0;
+    };
+    let scheme = &url[..colon];
+    let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !is_scheme {
+        return 0;
+    }
+    let authority_start = colon + 3;
+    url[authority_start..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |end| authority_start + end)
+}
+
+/// Whether `bytes[i]` starts a `%XX` escape.
+fn is_escape(bytes: &[u8], i: usize) -> bool {
+    bytes[i] == b'%'
+        && bytes.len() > i + 2
+        && bytes[i + 1].is_ascii_hexdigit()
+        && bytes[i + 2].is_ascii_hexdigit()
+}
+
+/// The byte encoded by the `%XX` escape at `bytes[i]`.
+fn escaped_byte(bytes: &[u8], i: usize) -> u8 {
+    let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+    u8::from_str_radix(hex, 16).unwrap_or_default()
+}
+
+/// Whether `byte` is an unreserved character in a URL.
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+/// Whether `byte` may appear in a URL as it is: an unreserved or a reserved
+/// character.
+fn is_allowed(byte: u8) -> bool {
+    is_unreserved(byte) || b":/?#[]@!$&'()*+,;=".contains(&byte)
+}
+
+/// Write `url` as the destination of an inline link or image, or of
+/// a reference definition.
+///
+/// A URL that can't be written bare, because it contains spaces or unbalanced
+/// parentheses or starts with `<`, is enclosed in angle brackets.
+pub(super) fn destination(url: &str) -> Cow<'_, str> {
+    let mut depth: usize = 0;
+    let mut balanced = true;
+    for c in url.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => balanced = false,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    let bare = balanced
+        && depth == 0
+        && !url.starts_with('<')
+        && !url.contains(|c: char| c == ' ' || c.is_ascii_control());
+    if bare {
+        return Cow::Borrowed(url);
+    }
+    let escaped = url.replace('<', "\\<").replace('>', "\\>");
+    Cow::Owned(format!("<{}>", escaped))
+}
+
+/// Write `title` as the title of a link, an image, or a reference
+/// definition, which goes in double quotes.
+///
+/// Double quotes in it are escaped with a backslash, and so are backslashes
+/// that would escape the character after them.  An `&` that starts an entity
+/// is written as `&amp;`, since entities in titles are decoded before
+/// backslash escapes are.
+pub(super) fn title(title: &str) -> Cow<'_, str> {
+    let bytes = title.as_bytes();
+    let needs_escape = |i: usize| match bytes[i] {
+        b'"' => true,
+        b'\\' => bytes.get(i + 1).is_none_or(u8::is_ascii_punctuation),
+        b'&' => escape::entity_len(&title[i..]).is_some(),
+        _ => false,
+    };
+    if !(0..bytes.len()).any(needs_escape) {
+        return Cow::Borrowed(title);
+    }
+    let mut result = String::with_capacity(title.len() + 4);
+    for (i, ch) in title.char_indices() {
+        match ch {
+            '&' if needs_escape(i) => result.push_str("&amp;"),
+            '"' | '\\' if needs_escape(i) => {
+                result.push('\\');
+                result.push(ch);
+            }
+            _ => result.push(ch),
+        }
+    }
+    Cow::Owned(result)
+}
+
+/// Whether `url` can be written as an autolink, `<url>`: it is absolute, and
+/// has no spaces, control characters, or angle brackets.
+pub(super) fn is_autolink_url(url: &str) -> bool {
+    let Some((scheme, rest)) = url.split_once(':') else {
+        return false;
+    };
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+        && !rest.contains(|c: char| c == ' ' || c == '<' || c == '>' || c.is_ascii_control())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_autolink_url() {
+        assert!(is_autolink_url("https://example.com/a?b=c&d"));
+        assert!(is_autolink_url("irc://foo.bar:2233/baz"));
+        assert!(!is_autolink_url("/local/path"));
+        assert!(!is_autolink_url("https://example.com/a b"));
+        assert!(!is_autolink_url("https://example.com/<a>"));
+        assert!(!is_autolink_url("x:y"));
+    }
+
+    #[test]
+    fn test_encode() {
+        let encode = |url| normalize_url(url, UrlEncoding::Encode);
+        assert_eq!(encode("docs/usage.md"), "docs/usage.md");
+        assert_eq!(encode("my file.md"), "my%20file.md");
+        assert_eq!(encode("say \"hi\".md"), "say%20%22hi%22.md");
+        assert_eq!(
+            encode("한국어/문서.md"),
+            "%ED%95%9C%EA%B5%AD%EC%96%B4/%EB%AC%B8%EC%84%9C.md"
+        );
+        assert_eq!(encode("a%20b.md"), "a%20b.md");
+        assert_eq!(encode("100%.md"), "100%25.md");
+        assert_eq!(
+            encode("https://한국.kr/문서"),
+            "https://한국.kr/%EB%AC%B8%EC%84%9C"
+        );
+        assert_eq!(encode("?q=a b#frag"), "?q=a%20b#frag");
+    }
+
+    #[test]
+    fn test_encode_is_idempotent() {
+        for url in ["my file.md", "한국어/문서.md", "100%.md", "a%2"] {
+            let once = normalize_url(url, UrlEncoding::Encode).into_owned();
+            assert_eq!(normalize_url(&once, UrlEncoding::Encode), once);
+        }
+    }
+
+    #[test]
+    fn test_decode_unreserved() {
+        let decode = |url| normalize_url(url, UrlEncoding::DecodeUnreserved);
+        assert_eq!(decode("%41%62c.md"), "Abc.md");
+        assert_eq!(decode("a%2Db%5f%7E"), "a-b_~");
+        assert_eq!(decode("a%20b.md"), "a%20b.md");
+        assert_eq!(decode("a%2Fb"), "a%2Fb");
+        assert_eq!(decode("%ED%95%9C.md"), "%ED%95%9C.md");
+        assert_eq!(decode("%2541"), "%2541");
+        assert_eq!(decode("100%"), "100%");
+    }
+
+    #[test]
+    fn test_fragments_and_schemes_kept() {
+        for encoding in [UrlEncoding::Encode, UrlEncoding::DecodeUnreserved] {
+            for url in [
+                "#한국어 제목",
+                "#%41",
+                "mailto:홍민희 <hong@minhee.org>",
+                "MAILTO:%41@example.com",
+                "tel:+82 2 1234 5678",
+            ] {
+                assert_eq!(normalize_url(url, encoding), url);
+            }
+        }
+    }
+
+    #[test]
+    fn test_destination() {
+        assert_eq!(destination("docs/usage.md"), "docs/usage.md");
+        assert_eq!(destination("wiki/Foo_(bar)"), "wiki/Foo_(bar)");
+        assert_eq!(destination("my file.md"), "<my file.md>");
+        assert_eq!(destination("smile:)"), "<smile:)>");
+        assert_eq!(destination("<a>b"), "<\\<a\\>b>");
+    }
+
+    #[test]
+    fn test_title() {
+        assert_eq!(title("plain"), "plain");
+        assert_eq!(title("a \"b\" c"), "a \\\"b\\\" c");
+        assert_eq!(title("a\\b"), "a\\b");
+        assert_eq!(title("a\\*b\\"), "a\\\\*b\\\\");
+        assert_eq!(title("&amp; & co"), "&amp;amp; & co");
+    }
+}
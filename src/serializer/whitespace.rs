@@ -0,0 +1,105 @@
+//! The trailing whitespace policy of the output.
+//!
+//! Trailing whitespace is written in many places on the way, like after the
+//! marker of an empty list item or at the end of an HTML block line, and
+//! would be invisible in the output, so it is all stripped in one pass when
+//! the whole document is written.  The few lines whose trailing whitespace
+//! means something, like hard line breaks written as two spaces and the
+//! lines of code blocks, end with [`KEEP_MARKER`] until then.
+
+use std::borrow::Cow;
+
+use super::Serializer;
+use crate::HardBreak;
+
+/// What the lines whose trailing whitespace is kept end with until the whole
+/// document is written.  Like the markers of inline footnotes, it is a
+/// private use character, which documents don't use.
+pub(super) const KEEP_MARKER: char = '\u{10FFF8}';
+
+/// `text` with [`KEEP_MARKER`] at the end of each of its lines that ends
+/// with whitespace, so that the whitespace is kept.
+pub(super) fn keep_trailing_whitespace(text: &str) -> Cow<'_, str> {
+    let ends_with_whitespace = |line: &str| line.ends_with([' ', '\t']);
+    if !text.lines().any(ends_with_whitespace) {
+        return Cow::Borrowed(text);
+    }
+    let mut result = String::with_capacity(text.len() + 8);
+    for line in text.split_inclusive('\n') {
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+        result.push_str(content);
+        if ends_with_whitespace(content) {
+            result.push(KEEP_MARKER);
+        }
+        result.push_str(newline);
+    }
+    Cow::Owned(result)
+}
+
+impl<'a> Serializer<'a> {
+    /// What a hard line break is written as at the end of a line.
+    pub(super) fn hard_break(&self) -> &'static str {
+        match self.options.hard_break {
+            HardBreak::Spaces => "  \u{10FFF8}",
+            HardBreak::Backslash => "\\",
+        }
+    }
+
+    /// A line of a code block as it is written.
+    pub(super) fn code_line<'l>(&self, line: &'l str) -> Cow<'l, str> {
+        if self.options.code_block_trim_trailing_whitespace {
+            Cow::Borrowed(line.trim_end_matches([' ', '\t']))
+        } else {
+            keep_trailing_whitespace(line)
+        }
+    }
+
+    /// Strip the trailing whitespace of every line of the output but those
+    /// ending with [`KEEP_MARKER`], and remove the markers, along with those
+    /// that wrapping has joined with the line after them.
+    pub(super) fn strip_trailing_whitespace(&mut self) {
+        let has_trailing_whitespace =
+            |line: &str| line.ends_with([' ', '\t']) || line.contains(KEEP_MARKER);
+        if !self.output.lines().any(has_trailing_whitespace) {
+            return;
+        }
+        let mut result = String::with_capacity(self.output.len());
+        for line in self.output.split_inclusive('\n') {
+            let (content, newline) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            let content = match content.strip_suffix(KEEP_MARKER) {
+                Some(kept) => kept,
+                None => content.trim_end_matches([' ', '\t']),
+            };
+            if content.contains(KEEP_MARKER) {
+                result.push_str(&content.replace(KEEP_MARKER, ""));
+            } else {
+                result.push_str(content);
+            }
+            result.push_str(newline);
+        }
+        self.output = result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_trailing_whitespace() {
+        assert_eq!(
+            keep_trailing_whitespace("a  \nb\n\t\nc "),
+            "a  \u{10FFF8}\nb\n\t\u{10FFF8}\nc \u{10FFF8}"
+        );
+        assert!(matches!(
+            keep_trailing_whitespace("a\nb\n"),
+            Cow::Borrowed(_)
+        ));
+    }
+}
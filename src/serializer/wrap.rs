@@ -2,17 +2,38 @@
 
 use unicode_width::UnicodeWidthStr;
 
+use super::locale::is_hangul;
+use crate::trace;
+
+/// The fewest columns left for the text of a line, however wide the prefix
+/// of a deeply nested block is, so that its text isn't put one word per line.
+/// Such lines are longer than the line width instead.
+pub const MIN_TEXT_WIDTH: usize = 20;
+
+/// The number of columns left for text after a prefix `prefix_width`
+/// columns wide, on lines `line_width` columns wide.
+///
+/// This is never less than `MIN_TEXT_WIDTH`, unless `line_width` itself is.
+pub fn text_width(prefix_width: usize, line_width: usize) -> usize {
+    line_width
+        .saturating_sub(prefix_width)
+        .max(MIN_TEXT_WIDTH.min(line_width))
+}
+
 /// Wrap text at the specified line width.
 ///
 /// This function handles soft break markers (`\x00`) which represent where
 /// the original document had line breaks. Short lines are preserved as-is,
 /// while long lines are merged and rewrapped.
 ///
-/// Hard line breaks (`\n`) are preserved with two trailing spaces before the
+/// Hard line breaks (`\n`) are preserved with `hard_break` before the
 /// newline, and the prefix is added to the continuation line.
-pub fn wrap_text(text: &str, prefix: &str, line_width: usize) -> String {
+pub fn wrap_text(text: &str, prefix: &str, line_width: usize, hard_break: &str) -> String {
+    let line_width = prefix.width() + text_width(prefix.width(), line_width);
+    trace::debug!(line_width, prefix_width = prefix.width(), "wrapping text");
+
     // First, split by hard line breaks (actual newlines)
-    // These must be preserved with two trailing spaces
+    // These must be preserved with the hard break marker
     let hard_break_segments: Vec<&str> = text.split('\n').collect();
 
     if hard_break_segments.len() == 1 {
@@ -24,8 +45,9 @@ pub fn wrap_text(text: &str, prefix: &str, line_width: usize) -> String {
     let mut result = String::new();
     for (idx, segment) in hard_break_segments.iter().enumerate() {
         if idx > 0 {
-            // Add two trailing spaces for hard line break, then newline and prefix
-            result.push_str("  \n");
+            // Add the hard line break, then newline and prefix
+            result.push_str(hard_break);
+            result.push('\n');
         }
         // First segment uses the normal prefix, subsequent segments also need prefix
         // (wrap_text_segment handles adding the prefix to the first line)
@@ -70,7 +92,9 @@ fn wrap_text_segment(text: &str, prefix: &str, line_width: usize) -> String {
             i += 1;
             while i < original_lines.len() {
                 let next_line = original_lines[i].trim();
-                merged.push(' ');
+                if joins_with_space(&merged, next_line, false) {
+                    merged.push(' ');
+                }
                 merged.push_str(next_line);
                 i += 1;
             }
@@ -93,16 +117,20 @@ fn wrap_text_segment(text: &str, prefix: &str, line_width: usize) -> String {
 /// This is used for list items where the marker is already output and continuation
 /// lines need indentation.
 ///
-/// Hard line breaks (`\n`) are preserved with two trailing spaces before the
+/// Hard line breaks (`\n`) are preserved with `hard_break` before the
 /// newline, and the continuation prefix is added to the continuation line.
 pub fn wrap_text_first_line(
     text: &str,
     first_prefix: &str,
     continuation_prefix: &str,
     line_width: usize,
+    hard_break: &str,
 ) -> String {
+    let prefix_width = first_prefix.width().max(continuation_prefix.width());
+    let line_width = prefix_width + text_width(prefix_width, line_width);
+
     // First, split by hard line breaks (actual newlines)
-    // These must be preserved with two trailing spaces
+    // These must be preserved with the hard break marker
     let hard_break_segments: Vec<&str> = text.split('\n').collect();
 
     if hard_break_segments.len() == 1 {
@@ -115,8 +143,9 @@ pub fn wrap_text_first_line(
     let mut is_first_segment = true;
     for segment in hard_break_segments {
         if !is_first_segment {
-            // Add two trailing spaces for hard line break, then newline
-            result.push_str("  \n");
+            // Add the hard line break, then newline
+            result.push_str(hard_break);
+            result.push('\n');
             result.push_str(continuation_prefix);
         }
         let (current_first, current_cont) = if is_first_segment {
@@ -178,7 +207,9 @@ fn wrap_text_first_line_segment(
             i += 1;
             while i < original_lines.len() {
                 let next_line = original_lines[i].trim();
-                merged.push(' ');
+                if joins_with_space(&merged, next_line, false) {
+                    merged.push(' ');
+                }
                 merged.push_str(next_line);
                 i += 1;
             }
@@ -198,6 +229,48 @@ fn wrap_text_first_line_segment(
     result
 }
 
+/// Characters that a soft line break before them is joined without a space,
+/// so that `word\n, next` becomes `word, next` rather than `word , next`.
+const NO_SPACE_BEFORE: &[char] = &[',', '.', ';', ':', ')', ']', '}', '!', '?'];
+
+/// Characters that a soft line break after them is joined without a space,
+/// like opening brackets and quotes.
+const NO_SPACE_AFTER: &[char] = &['(', '[', '{', '\u{201C}', '\u{2018}', '\u{00AB}'];
+
+/// Whether a soft line break between `before` and `after` becomes a space
+/// when the lines around it are joined: not after opening brackets and
+/// quotes, and not before closing punctuation (see [`NO_SPACE_AFTER`] and
+/// [`NO_SPACE_BEFORE`]).  The punctuation has to end a word, followed by
+/// whitespace or by the end of `after`, so that `.gitignore` or `!important`
+/// isn't glued to the word before it; `continued` says that more of the line
+/// follows `after`, so that its end doesn't count.
+///
+/// Joined this way, the punctuation never needs escaping where it didn't
+/// before: a `]` only closes a link right before `(` or `[`.
+pub fn joins_with_space(before: &str, after: &str, continued: bool) -> bool {
+    let rest = after.trim_start_matches(NO_SPACE_BEFORE);
+    let closes = rest.len() < after.len()
+        && match rest.chars().next() {
+            Some(next) => next.is_whitespace(),
+            None => !continued,
+        };
+    let opens = before.ends_with(NO_SPACE_AFTER);
+    !(closes || opens)
+}
+
+/// Join the lines of `text` at its soft break markers (`\x00`), with or
+/// without a space as [`joins_with_space`] says.
+pub fn join_soft_breaks(text: &str) -> String {
+    let mut joined = String::with_capacity(text.len());
+    for (i, line) in text.split('\x00').enumerate() {
+        if i > 0 && joins_with_space(&joined, line, false) {
+            joined.push(' ');
+        }
+        joined.push_str(line);
+    }
+    joined
+}
+
 /// Wrap a single segment of text (no original line break markers).
 ///
 /// Handles special tokens like backtick-delimited code spans and bracketed
@@ -219,20 +292,35 @@ pub fn wrap_single_segment(
     // Split into "tokens" where each token is either:
     // - A word (non-space characters) followed by optional spaces
     // - Content inside backticks (treated as a single unbreakable unit)
-    // - Content inside brackets (treated as a single unbreakable unit for links)
-    // We preserve double spaces after periods.
-    let chars = text.chars();
+    // - Content inside brackets (treated as a single unbreakable unit for links),
+    //   along with the destination in parentheses right after them
+    // Punctuation right after a code span or a link has no space before it,
+    // so it stays in the same token.  We preserve double spaces after periods.
+    let mut chars = text.chars().peekable();
     let mut current_token = String::new();
     let mut trailing_spaces = String::new();
-    let mut in_backticks = false;
+    // The length of the backtick run that opened the current code span, which
+    // only a run of the same length closes, or 0 outside code spans
+    let mut backtick_run = 0;
     let mut bracket_depth = 0;
-
-    for ch in chars {
-        if ch == '`' && bracket_depth == 0 {
-            if in_backticks {
-                // End of backtick region
-                current_token.push(ch);
-                in_backticks = false;
+    let mut paren_depth = 0;
+    let mut escaped = false;
+
+    while let Some(ch) = chars.next() {
+        let was_escaped = std::mem::replace(&mut escaped, false);
+        if ch == '`' && bracket_depth == 0 && paren_depth == 0 && !was_escaped {
+            let mut run = 1;
+            while chars.next_if_eq(&'`').is_some() {
+                run += 1;
+            }
+            let ticks = "`".repeat(run);
+            if backtick_run > 0 {
+                // Inside a code span, only a run as long as the opening one
+                // ends it
+                current_token.push_str(&ticks);
+                if run == backtick_run {
+                    backtick_run = 0;
+                }
             } else {
                 // Start of backtick region - include any accumulated content first
                 if !current_token.is_empty() && !trailing_spaces.is_empty() {
@@ -250,12 +338,25 @@ pub fn wrap_single_segment(
                     current_token.clear();
                     trailing_spaces.clear();
                 }
-                current_token.push(ch);
-                in_backticks = true;
+                current_token.push_str(&ticks);
+                backtick_run = run;
             }
-        } else if in_backticks {
+        } else if backtick_run > 0 {
             // Inside backticks, everything is part of the token
             current_token.push(ch);
+        } else if paren_depth > 0 {
+            // Inside the destination of a link, everything is part of the
+            // token, as a title may have spaces
+            current_token.push(ch);
+            if was_escaped {
+                // An escaped parenthesis doesn't nest
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '(' {
+                paren_depth += 1;
+            } else if ch == ')' {
+                paren_depth -= 1;
+            }
         } else if ch == '[' {
             // Start of bracket region
             if bracket_depth == 0 && !current_token.is_empty() && !trailing_spaces.is_empty() {
@@ -279,6 +380,10 @@ pub fn wrap_single_segment(
             // End of bracket region (or nested bracket)
             current_token.push(ch);
             bracket_depth -= 1;
+            if bracket_depth == 0 && chars.next_if_eq(&'(').is_some() {
+                current_token.push('(');
+                paren_depth = 1;
+            }
         } else if bracket_depth > 0 {
             // Inside brackets, everything is part of the token
             current_token.push(ch);
@@ -302,6 +407,8 @@ pub fn wrap_single_segment(
                 trailing_spaces.clear();
             }
             current_token.push(ch);
+            // An escaped backtick doesn't start a code span
+            escaped = ch == '\\' && !was_escaped;
         }
     }
 
@@ -351,12 +458,20 @@ fn add_token_to_line_with_prefix(
         // First word on this line (prefix already added)
         current_line.push_str(token);
         current_line.push_str(trailing_spaces);
-    } else if current_line.width() + token_width + spaces_len <= line_width {
-        // Token fits on current line
+    } else if current_line.width() + token_width + spaces_len <= line_width
+        || looks_like_definition(token)
+    {
+        // Token fits on current line, or can't start one
         current_line.push_str(token);
         current_line.push_str(trailing_spaces);
     } else {
         // Start a new line - trim trailing spaces from previous line
+        trace::debug!(
+            before = token,
+            column = current_line.width(),
+            line_width,
+            "line broken"
+        );
         let trimmed = current_line.trim_end();
         result.push_str(trimmed);
         result.push('\n');
@@ -366,3 +481,232 @@ fn add_token_to_line_with_prefix(
         *is_first_line = false;
     }
 }
+
+/// Whether `token`, at the start of a line, would be read as the label of
+/// a link reference definition or a footnote definition, like a footnote
+/// reference followed by a colon (`[^1]:`).
+fn looks_like_definition(token: &str) -> bool {
+    token.starts_with('[') && token.ends_with("]:")
+}
+
+/// The length of the footnote references, like `[^1][^2]`, at the end of
+/// `before`.
+fn trailing_footnote_references(before: &[char]) -> usize {
+    let mut end = before.len();
+    while before[..end].ends_with(&[']']) {
+        let Some(open) = before[..end - 1]
+            .iter()
+            .rposition(|c| matches!(c, '[' | ']') || c.is_whitespace())
+        else {
+            break;
+        };
+        if before[open] != '[' || before.get(open + 1) != Some(&'^') || open + 2 == end - 1 {
+            break;
+        }
+        end = open;
+    }
+    before.len() - end
+}
+
+/// Abbreviations whose period doesn't end a sentence, in lowercase and
+/// without the final period.
+const ABBREVIATIONS: &[&str] = &[
+    "al", "approx", "cf", "dr", "e.g", "fig", "i.e", "jr", "mr", "mrs", "ms", "prof", "sr", "st",
+    "vs",
+];
+
+/// Characters that may close a sentence after its final punctuation, like
+/// closing quotes and parentheses.
+const SENTENCE_CLOSERS: &[char] = &[
+    ')', '"', '\'', '\u{201D}', '\u{2019}', '\u{00BB}', '\u{300D}', '\u{300F}', '\u{FF09}', '*',
+    '_',
+];
+
+/// Characters that may open a sentence before its first letter.
+const SENTENCE_OPENERS: &[char] = &[
+    '(', '"', '\'', '\u{201C}', '\u{2018}', '\u{00AB}', '\u{300C}', '\u{300E}', '\u{FF08}', '*',
+    '_', '[',
+];
+
+/// Insert soft break markers (`\x00`) between the sentences of a paragraph,
+/// for `WrapMode::Sentence`.
+///
+/// A sentence ends at `.`, `?`, or `!` followed by a space, or at a CJK
+/// full stop (`。`, `？`, `！`), including any closing quotes or parentheses
+/// right after it.  The spaces between two sentences are replaced by
+/// a single marker.  Code spans and the text and destination of links are
+/// left alone, just as `wrap_single_segment` never breaks them.
+///
+/// In a `korean` paragraph, a sentence that ends in Hangul, like `합니다.`,
+/// ends there even if the next one starts with a lowercase word in Latin
+/// script, like `npm`, and a word of a single syllable isn't an initial.
+pub fn break_sentences(text: &str, korean: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len() + 8);
+    let mut code_fence = 0;
+    let mut bracket_depth = 0;
+    let mut paren_depth = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch == '\\' && code_fence == 0 {
+            result.push(ch);
+            if let Some(&next) = chars.get(i + 1) {
+                result.push(next);
+            }
+            i += 2;
+            continue;
+        }
+        if ch == '`' {
+            let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+            if code_fence == 0 {
+                code_fence = run;
+            } else if run == code_fence {
+                code_fence = 0;
+            }
+            result.extend(&chars[i..i + run]);
+            i += run;
+            continue;
+        }
+        if code_fence > 0 {
+            result.push(ch);
+            i += 1;
+            continue;
+        }
+        match ch {
+            '[' => bracket_depth += 1,
+            ']' if bracket_depth > 0 => {
+                bracket_depth -= 1;
+                if bracket_depth == 0 && chars.get(i + 1) == Some(&'(') {
+                    result.push_str("](");
+                    paren_depth = 1;
+                    i += 2;
+                    continue;
+                }
+            }
+            '(' if paren_depth > 0 => paren_depth += 1,
+            ')' if paren_depth > 0 => paren_depth -= 1,
+            _ => {}
+        }
+        if bracket_depth > 0 || paren_depth > 0 {
+            result.push(ch);
+            i += 1;
+            continue;
+        }
+
+        if ch == ' ' && ends_sentence(&chars[..i], korean) {
+            let next = i + chars[i..].iter().take_while(|&&c| c == ' ').count();
+            let lowercase = korean && ends_in_hangul(&chars[..i]);
+            if starts_sentence(&chars[next..], lowercase) {
+                result.push('\x00');
+                i = next;
+                continue;
+            }
+        }
+        result.push(ch);
+        i += 1;
+        if matches!(ch, '。' | '？' | '！') {
+            while let Some(&closer) = chars.get(i)
+                && SENTENCE_CLOSERS.contains(&closer)
+            {
+                result.push(closer);
+                i += 1;
+            }
+            if starts_sentence(&chars[i..], false) {
+                result.push('\x00');
+            }
+        }
+    }
+
+    result
+}
+
+/// `before`, the text before a space, without the footnote references and
+/// the closing quotes and parentheses it ends with.
+fn trim_sentence_closers(before: &[char]) -> &[char] {
+    let before = &before[..before.len() - trailing_footnote_references(before)];
+    let end = before.len()
+        - before
+            .iter()
+            .rev()
+            .take_while(|c| SENTENCE_CLOSERS.contains(c))
+            .count();
+    &before[..end]
+}
+
+/// Whether `before`, the text before a space, ends with the end of
+/// a sentence, which may be followed by footnote references.
+fn ends_sentence(before: &[char], korean: bool) -> bool {
+    let Some((&punctuation, before)) = trim_sentence_closers(before).split_last() else {
+        return false;
+    };
+    match punctuation {
+        '?' | '!' | '。' | '？' | '！' => true,
+        // A word in Hangul is never an abbreviation or an initial
+        '.' if korean && before.last().is_some_and(|&c| is_hangul(c)) => true,
+        '.' => {
+            let word_start = before
+                .iter()
+                .rposition(|c| c.is_whitespace())
+                .map_or(0, |i| i + 1);
+            let word: String = before[word_start..]
+                .iter()
+                .skip_while(|c| SENTENCE_OPENERS.contains(c))
+                .collect();
+            !is_abbreviation(&word)
+        }
+        _ => false,
+    }
+}
+
+/// Whether `word`, followed by a period, is an abbreviation, an initial,
+/// or a version number like `1.2.` rather than the end of a sentence.
+fn is_abbreviation(word: &str) -> bool {
+    let mut letters = word.chars();
+    let is_initial = matches!(
+        (letters.next(), letters.next()),
+        (Some(c), None) if c.is_alphabetic()
+    );
+    let is_version = word.contains('.')
+        && word.chars().any(|c| c.is_ascii_digit())
+        && word.chars().all(|c| c.is_ascii_digit() || c == '.');
+    is_initial || is_version || ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
+
+/// Whether `before`, the text before a space that ends a sentence, ends
+/// with a sentence in Hangul, like `합니다.`.
+fn ends_in_hangul(before: &[char]) -> bool {
+    let before = trim_sentence_closers(before);
+    before.len() >= 2 && is_hangul(before[before.len() - 2])
+}
+
+/// Whether a new sentence may start a line with `rest`: it isn't empty,
+/// doesn't start with a lowercase letter unless `lowercase` says it may, and
+/// wouldn't be read as the start of a block, like a list item or a heading.
+fn starts_sentence(rest: &[char], lowercase: bool) -> bool {
+    let Some(&first) = rest.first() else {
+        return false;
+    };
+    if first.is_whitespace() {
+        return false;
+    }
+    let letter = rest.iter().find(|c| !SENTENCE_OPENERS.contains(c));
+    if !lowercase && letter.is_some_and(|c| c.is_lowercase()) {
+        return false;
+    }
+    let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+    let label_end = rest.iter().position(|&c| c == ']');
+    let is_definition = rest.first() == Some(&'[')
+        && label_end.is_some_and(|end| {
+            rest.get(end + 1) == Some(&':') && rest.get(end + 2).is_none_or(|c| *c == ' ')
+        });
+    let is_block_start = is_definition
+        || matches!(rest, ['-' | '+' | '*', ' ', ..] | ['>' | '#' | '|', ..])
+        || rest.starts_with(&['`'; 3])
+        || rest.starts_with(&['~'; 3])
+        || (digits > 0
+            && matches!(rest.get(digits), Some('.' | ')'))
+            && rest.get(digits + 1).is_none_or(|c| *c == ' '));
+    !is_block_start
+}
@@ -0,0 +1,227 @@
+//! Reusable state for formatting many documents in a row.
+
+use comrak::nodes::AstNode;
+
+use crate::serializer::{self, SerializeResult, Warning};
+use crate::{FormatError, Options, PipelineHooks, push_with_line_ending, run_pipeline};
+
+/// A formatting session that reuses allocations across documents.
+///
+/// Formatting a document allocates a parse arena, an output buffer, and
+/// scratch buffers.  When many documents are formatted in a row, as in a
+/// batch run over a directory, a session keeps those buffers between
+/// documents instead of returning them to the allocator:
+///
+/// - The output and warning buffers are cleared and reused, so they only
+///   grow when a document is larger than every previous one.
+/// - The parse arena borrows from the document being parsed, so it cannot
+///   outlive it; instead, each new arena is preallocated to hold as many
+///   nodes as the previous document had, which avoids growing it chunk by
+///   chunk for documents of similar shape.
+///
+/// Nothing but capacity is carried over from one document to the next: the
+/// result of [`FormatSession::format`] is always the same as that of
+/// [`crate::format`].
+///
+/// # Example
+///
+/// ```
+/// use hongdown::{FormatSession, Options};
+///
+/// let options = Options::default();
+/// let mut session = FormatSession::new();
+/// for input in ["# Hello\n", "*  one\n*  two\n"] {
+///     let output = session.format(input, &options).unwrap();
+///     println!("{}", output);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct FormatSession {
+    /// The output of the most recent document.
+    output: String,
+    /// The warnings for the most recent document.
+    warnings: Vec<Warning>,
+    /// Scratch buffer for line ending conversion.
+    scratch: String,
+    /// The number of AST nodes in the most recent document.
+    node_capacity: usize,
+}
+
+/// The output of a document formatted in a [`FormatSession`].
+///
+/// This borrows the session's buffers, so it has to be dropped before the
+/// next document is formatted.
+#[derive(Debug)]
+pub struct SessionResult<'s> {
+    /// The formatted Markdown output.
+    pub output: &'s str,
+    /// Warnings generated during formatting.
+    pub warnings: &'s [Warning],
+}
+
+impl FormatSession {
+    /// Creates a new session with empty buffers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Formats a Markdown document, reusing the session's buffers.
+    ///
+    /// This is the session counterpart to [`crate::format`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input cannot be parsed or formatted.
+    pub fn format(&mut self, input: &str, options: &Options) -> Result<&str, FormatError> {
+        self.format_with_warnings(input, options)
+            .map(|result| result.output)
+    }
+
+    /// Formats a Markdown document and returns both output and warnings,
+    /// reusing the session's buffers.
+    ///
+    /// This is the session counterpart to [`crate::format_with_warnings`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input cannot be parsed or formatted.
+    pub fn format_with_warnings(
+        &mut self,
+        input: &str,
+        options: &Options,
+    ) -> Result<SessionResult<'_>, FormatError> {
+        if input.is_empty() {
+            self.output.clear();
+            self.warnings.clear();
+        } else {
+            // The buffers are moved into the serializer, so if it panics
+            // they are simply lost and the session starts over with empty
+            // ones.
+            let mut hooks = SessionHooks {
+                node_capacity: self.node_capacity,
+                output: std::mem::take(&mut self.output),
+                warnings: std::mem::take(&mut self.warnings),
+                scratch: &mut self.scratch,
+            };
+            let result = run_pipeline(input, options, &mut hooks)?;
+            self.node_capacity = result.nodes;
+            self.output = result.output;
+            self.warnings = result.warnings;
+        }
+        Ok(self.last_result())
+    }
+
+    /// Returns the output and warnings of the most recent document, as
+    /// [`FormatSession::format_with_warnings`] did.
+    ///
+    /// This is empty if no document has been formatted yet.
+    pub fn last_result(&self) -> SessionResult<'_> {
+        SessionResult {
+            output: &self.output,
+            warnings: &self.warnings,
+        }
+    }
+}
+
+/// The hooks of [`FormatSession::format_with_warnings`], which parse into
+/// an arena as large as the previous document's, and serialize into the
+/// session's buffers.
+struct SessionHooks<'s> {
+    node_capacity: usize,
+    output: String,
+    warnings: Vec<Warning>,
+    scratch: &'s mut String,
+}
+
+impl PipelineHooks for SessionHooks<'_> {
+    fn node_capacity(&self) -> Option<usize> {
+        Some(self.node_capacity)
+    }
+
+    fn serialize<'a>(
+        &mut self,
+        root: &'a AstNode<'a>,
+        options: &Options,
+        input: &str,
+    ) -> SerializeResult {
+        let output = std::mem::take(&mut self.output);
+        let warnings = std::mem::take(&mut self.warnings);
+        serializer::serialize_into(root, options, Some(input), output, warnings)
+    }
+
+    fn convert_line_endings(&mut self, output: String, ending: &str) -> String {
+        self.scratch.clear();
+        push_with_line_ending(self.scratch, &output, ending);
+        std::mem::replace(self.scratch, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LineEnding, format_with_warnings};
+
+    /// Two documents that share as little as possible: the first has
+    /// references, footnotes, a table with a warning, and nested block
+    /// quotes; the second is a short list.
+    const RICH: &str = "\
+Title
+=====
+
+See [the docs][docs] and a footnote.[^1]
+
+> > Nested quote with a [link][docs].
+
+| a | b |
+|---|---|
+| 1 | 2 | 3 |
+
+[docs]: https://example.com/docs
+[^1]: The footnote.
+";
+
+    const PLAIN: &str = "*  one\n*  two\n";
+
+    #[test]
+    fn test_session_does_not_leak_between_documents() {
+        let options = Options::default();
+        let mut session = FormatSession::new();
+
+        let rich = format_with_warnings(RICH, &options).unwrap();
+        let plain = format_with_warnings(PLAIN, &options).unwrap();
+        assert!(!rich.warnings.is_empty());
+        assert!(plain.warnings.is_empty());
+
+        for (input, expected) in [
+            (RICH, &rich),
+            (PLAIN, &plain),
+            (RICH, &rich),
+            (PLAIN, &plain),
+        ] {
+            let result = session.format_with_warnings(input, &options).unwrap();
+            assert_eq!(result.output, expected.output);
+            assert_eq!(result.warnings.len(), expected.warnings.len());
+        }
+    }
+
+    #[test]
+    fn test_session_empty_input_clears_output() {
+        let options = Options::default();
+        let mut session = FormatSession::new();
+        session.format(RICH, &options).unwrap();
+        let result = session.format_with_warnings("", &options).unwrap();
+        assert_eq!(result.output, "");
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_session_line_ending() {
+        let options = Options {
+            line_ending: LineEnding::Auto,
+            ..Options::default()
+        };
+        let mut session = FormatSession::new();
+        assert_eq!(session.format("# a\r\n", &options).unwrap(), "a\r\n=\r\n");
+        assert_eq!(session.format("# a\n", &options).unwrap(), "a\n=\n");
+    }
+}
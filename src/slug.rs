@@ -0,0 +1,175 @@
+//! Heading anchors (slugs) as generated by GitHub.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+
+/// Characters GitHub removes from heading text when making an anchor:
+/// anything but letters, marks, numbers, connector punctuation (such as
+/// `_`), hyphens, and spaces.
+static REMOVED_CHARACTERS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[^\p{L}\p{M}\p{N}\p{Pc}\- ]").unwrap());
+
+/// Generates anchors for headings, keeping track of the anchors already
+/// generated so that duplicates get a numeric suffix.
+#[derive(Debug, Default)]
+pub(crate) struct Slugger {
+    occurrences: HashMap<String, usize>,
+}
+
+impl Slugger {
+    /// The anchor for a heading with the plain text `text`.
+    ///
+    /// The first heading with a given anchor keeps it, and the following
+    /// ones get `-1`, `-2`, and so on appended, skipping any suffixed anchor
+    /// that is already taken by another heading.
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut slug = base.clone();
+        while self.occurrences.contains_key(&slug) {
+            let count = self.occurrences.entry(base.clone()).or_insert(0);
+            *count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+        self.occurrences.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// Turn heading text into an anchor the way GitHub does: lowercase it,
+/// remove punctuation and symbols, and replace each space with a hyphen.
+///
+/// Runs of spaces are not collapsed, so `Foo & Bar` becomes `foo--bar`.
+pub(crate) fn slugify(text: &str) -> String {
+    REMOVED_CHARACTERS
+        .replace_all(&text.to_lowercase(), "")
+        .replace(' ', "-")
+}
+
+/// The text of a heading as GitHub sees it when making its anchor: the
+/// content of text and code spans, without any markup.  Line breaks and
+/// the alternative text of images are dropped, as they are from the
+/// rendered heading's text.
+pub(crate) fn heading_anchor_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    collect_text(node, &mut text);
+    text
+}
+
+/// Collect the text of the inline nodes under `node`.
+fn collect_text<'a>(node: &'a AstNode<'a>, text: &mut String) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(t) => text.push_str(t),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::Image(_) => {}
+            _ => collect_text(child, text),
+        }
+    }
+}
+
+/// The level, plain text, and anchor of every heading under `root`, in
+/// document order.
+pub(crate) fn heading_slugs<'a>(root: &'a AstNode<'a>) -> Vec<(u8, String, String)> {
+    let mut slugger = Slugger::default();
+    let mut headings = Vec::new();
+    for node in root.descendants() {
+        let NodeValue::Heading(heading) = &node.data.borrow().value else {
+            continue;
+        };
+        let anchor_text = heading_anchor_text(node);
+        let slug = slugger.slug(&anchor_text);
+        let text = crate::plain_text_of(node);
+        headings.push((heading.level, text, slug));
+    }
+    headings
+}
+
+/// Decode the percent-encoded bytes in a URL fragment.  Invalid escapes and
+/// byte sequences that aren't UTF-8 are kept as they are.
+pub(crate) fn decode_fragment(fragment: &str) -> String {
+    let bytes = fragment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| fragment.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Heading texts and the anchors GitHub generates for them.
+    const GITHUB_SLUGS: &[(&str, &str)] = &[
+        ("Hello World", "hello-world"),
+        ("Getting started", "getting-started"),
+        ("API Reference", "api-reference"),
+        ("Foo & Bar", "foo--bar"),
+        ("What's new?", "whats-new"),
+        ("C++ is fun!", "c-is-fun"),
+        ("1.2.3 Release", "123-release"),
+        ("snake_case_name", "snake_case_name"),
+        ("kebab-case-name", "kebab-case-name"),
+        ("Version 0.4.0 (2025-01-01)", "version-040-2025-01-01"),
+        ("Über Größe", "über-größe"),
+        ("Café résumé", "café-résumé"),
+        ("한국어 제목", "한국어-제목"),
+        ("홍다운: 마크다운 포매터", "홍다운-마크다운-포매터"),
+        ("日本語の見出し", "日本語の見出し"),
+        ("Emoji 🎉 party", "emoji--party"),
+        ("Ελληνικά", "ελληνικά"),
+        ("Привет мир", "привет-мир"),
+    ];
+
+    #[test]
+    fn test_slugify_matches_github() {
+        for (text, expected) in GITHUB_SLUGS {
+            assert_eq!(slugify(text), *expected, "slug of {:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_slugger_suffixes_duplicates() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("Intro"), "intro");
+        assert_eq!(slugger.slug("Intro"), "intro-1");
+        assert_eq!(slugger.slug("Intro"), "intro-2");
+        assert_eq!(slugger.slug("Other"), "other");
+    }
+
+    #[test]
+    fn test_slugger_skips_taken_suffixes() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("Intro 1"), "intro-1");
+        assert_eq!(slugger.slug("Intro"), "intro");
+        assert_eq!(slugger.slug("Intro"), "intro-2");
+        assert_eq!(slugger.slug("Intro 1"), "intro-1-1");
+    }
+
+    #[test]
+    fn test_decode_fragment() {
+        assert_eq!(decode_fragment("plain"), "plain");
+        assert_eq!(decode_fragment("%C3%BCber"), "über");
+        assert_eq!(decode_fragment("%ED%95%9C%EA%B5%AD%EC%96%B4"), "한국어");
+        assert_eq!(decode_fragment("100%"), "100%");
+        assert_eq!(decode_fragment("%zz"), "%zz");
+        assert_eq!(decode_fragment("%FF"), "%FF");
+    }
+}
@@ -0,0 +1,150 @@
+//! Statistics about Markdown documents, as [`crate::stats`] reports them.
+
+use comrak::nodes::{AstNode, NodeValue};
+use serde::{Deserialize, Serialize};
+
+/// The words per minute the reading time of prose is estimated with.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// The CJK characters per minute the reading time of prose in Chinese,
+/// Japanese, and Korean is estimated with.
+const CJK_CHARACTERS_PER_MINUTE: usize = 500;
+
+/// Statistics about a Markdown document.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocStats {
+    /// The number of words of prose, which leaves out code spans, code
+    /// blocks, HTML, front matter, and the alternative text of images.  Each
+    /// CJK character counts as a word, as words in Chinese and Japanese
+    /// aren't separated by spaces.
+    pub words: usize,
+    /// The number of headings of each level, from 1 to 6.
+    pub headings: [usize; 6],
+    /// The number of links, including autolinks.
+    pub links: usize,
+    /// The number of images.
+    pub images: usize,
+    /// The number of code blocks.
+    pub code_blocks: usize,
+    /// The number of tables.
+    pub tables: usize,
+    /// The estimated time it takes to read the prose, in whole minutes,
+    /// rounded up: 200 words or 500 CJK characters a minute.
+    pub reading_minutes: usize,
+    /// The display width of the longest line of the formatted document.
+    pub longest_line: usize,
+}
+
+/// Collect the statistics of the document at `root`, but for its
+/// `longest_line`, which is left 0.
+pub(crate) fn collect<'a>(root: &'a AstNode<'a>) -> DocStats {
+    let mut stats = DocStats::default();
+    let mut prose = String::new();
+    walk(root, &mut stats, &mut prose);
+    let (words, cjk_characters) = count_words(&prose);
+    stats.words = words + cjk_characters;
+    let minutes = words as f64 / WORDS_PER_MINUTE as f64
+        + cjk_characters as f64 / CJK_CHARACTERS_PER_MINUTE as f64;
+    stats.reading_minutes = minutes.ceil() as usize;
+    stats
+}
+
+/// Count the nodes of `node` and its descendants into `stats`, and append
+/// their prose to `prose`, with a space between blocks and at line breaks.
+fn walk<'a>(node: &'a AstNode<'a>, stats: &mut DocStats, prose: &mut String) {
+    match &node.data.borrow().value {
+        NodeValue::Heading(heading) => {
+            stats.headings[usize::from(heading.level.clamp(1, 6)) - 1] += 1
+        }
+        NodeValue::Link(_) => stats.links += 1,
+        NodeValue::Image(_) => {
+            // The alternative text isn't read, but it may have links
+            stats.images += 1;
+            let mut alt = String::new();
+            for child in node.children() {
+                walk(child, stats, &mut alt);
+            }
+            return;
+        }
+        NodeValue::CodeBlock(_) => {
+            stats.code_blocks += 1;
+            return;
+        }
+        NodeValue::Table(_) => stats.tables += 1,
+        NodeValue::Text(text) => {
+            prose.push_str(text);
+            return;
+        }
+        NodeValue::SoftBreak | NodeValue::LineBreak => {
+            prose.push(' ');
+            return;
+        }
+        NodeValue::Code(_)
+        | NodeValue::HtmlBlock(_)
+        | NodeValue::HtmlInline(_)
+        | NodeValue::FrontMatter(_)
+        | NodeValue::Math(_) => return,
+        _ => {}
+    }
+    if node.data.borrow().value.block() {
+        prose.push(' ');
+    }
+    for child in node.children() {
+        walk(child, stats, prose);
+    }
+}
+
+/// Count the words of `text` that aren't CJK, and its CJK characters.
+///
+/// A word is a run of characters between whitespace with at least one
+/// letter or digit in it, so that a dash between spaces isn't one.
+/// A CJK character also ends the word before it, as in `Rust로`.
+fn count_words(text: &str) -> (usize, usize) {
+    let mut words = 0;
+    let mut cjk_characters = 0;
+    let mut in_word = false;
+    for ch in text.chars() {
+        if is_cjk(ch) {
+            cjk_characters += 1;
+            in_word = false;
+        } else if ch.is_whitespace() {
+            in_word = false;
+        } else if ch.is_alphanumeric() && !in_word {
+            words += 1;
+            in_word = true;
+        }
+    }
+    (words, cjk_characters)
+}
+
+/// Whether `ch` is a Chinese, Japanese, or Korean character: a Han
+/// ideograph, kana, or Hangul.
+fn is_cjk(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{1100}'..='\u{11FF}'
+            | '\u{3040}'..='\u{30FF}'
+            | '\u{3130}'..='\u{318F}'
+            | '\u{31F0}'..='\u{31FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_words() {
+        assert_eq!(count_words("Hello, world — again"), (3, 0));
+        assert_eq!(count_words("don't re-use e.g. this"), (4, 0));
+        assert_eq!(count_words("마크다운 포매터"), (0, 7));
+        assert_eq!(count_words("日本語の文章です。"), (0, 8));
+        assert_eq!(count_words("Rust로 만든 formatter"), (2, 3));
+        assert_eq!(count_words("  "), (0, 0));
+    }
+}
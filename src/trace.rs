@@ -0,0 +1,48 @@
+//! Optional instrumentation with `tracing`, for debugging why a document
+//! is formatted the way it is.
+//!
+//! With the `trace` feature, the macros here emit spans for the blocks
+//! written and debug events for where paragraphs are wrapped, which
+//! characters are escaped, and which configuration file or override each
+//! option comes from.  Without it, they expand to nothing, and their
+//! arguments aren't even evaluated, so `tracing` isn't a dependency.
+
+/// A span that stays entered until it is dropped, as [`span!`] returns it.
+#[must_use]
+pub(crate) struct Guard {
+    #[cfg(feature = "trace")]
+    pub(crate) _span: tracing::span::EnteredSpan,
+}
+
+/// Enter a debug span with the name and fields of `tracing::debug_span!`,
+/// returning a [`Guard`] that exits it when dropped.
+#[cfg(feature = "trace")]
+macro_rules! span {
+    ($($arg:tt)*) => {
+        $crate::trace::Guard {
+            _span: ::tracing::debug_span!($($arg)*).entered(),
+        }
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! span {
+    ($($arg:tt)*) => {
+        $crate::trace::Guard {}
+    };
+}
+
+/// Emit a debug event with the fields and message of `tracing::debug!`.
+#[cfg(feature = "trace")]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        ::tracing::debug!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {};
+}
+
+pub(crate) use {debug, span};
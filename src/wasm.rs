@@ -7,8 +7,14 @@ use wasm_bindgen::prelude::*;
 
 use crate::Options;
 use crate::config::{
-    DashPattern, DashSetting, FenceChar, IndentWidth, LeadingSpaces, LineWidth, MinFenceLength,
-    OrderedListPad, OrderedMarker, ThematicBreakStyle, TrailingSpaces, UnorderedMarker,
+    BackslashEscape, BlockQuoteSeparation, ByteOrderMark, CellWrap, DashPattern, DashSetting,
+    DefinitionOrder, DetailsHandling, Dialect, EmbeddedLanguage, EmptyLinkText, EntityMode,
+    FenceChar, FixRule, GitHubRepo, HardBreak, HeadingLevel, IndentWidth, InlineFootnotes,
+    JsonKeyOrder, LeadingSpaces, LineEnding, LineWidth, ListContinuation, ListSeparation,
+    ListSpacing, Locale, MaxBlankLines, MinFenceLength, OrderedListPad, OrderedMarker,
+    OrderedNumbering, ReferenceSyntax, TableFallback, TableFormat, TablePadding, TableSeparator,
+    TableWidthGrowth, TableWidthStep, TemplateSyntax, ThematicBreakStyle, TrailingSpaces,
+    UnorderedMarker, UrlEncoding, VoidTagStyle, WrapMode,
 };
 
 /// JavaScript-friendly options struct.
@@ -20,12 +26,69 @@ pub struct JsOptions {
     /// Line width for wrapping (default: 80).
     pub line_width: Option<usize>,
 
+    /// How paragraphs are wrapped: "width", "none", "preserve", or "sentence"
+    /// (default: "width").
+    pub wrap: Option<String>,
+
+    /// The most blank lines between two blocks (default: 2).
+    pub max_consecutive_blank_lines: Option<usize>,
+
+    /// The language whose rules paragraphs are written by: "none", "ko",
+    /// or "auto" (default: "none").
+    pub locale: Option<String>,
+
+    /// Line ending style: "lf", "crlf", or "auto" (default: "lf").
+    pub line_ending: Option<String>,
+
+    /// Whether a byte order mark at the start of the input is kept:
+    /// "preserve" or "remove" (default: "preserve").
+    pub bom: Option<String>,
+
+    /// How hard line breaks are written: "spaces" or "backslash" (default:
+    /// "spaces").
+    pub hard_break: Option<String>,
+
+    /// How character entity references are written: "preserve", "decode",
+    /// or "encode_invisible" (default: "preserve").
+    pub entities: Option<String>,
+
+    /// The Markdown dialect of the output: "gfm" or "commonmark"
+    /// (default: "gfm").
+    pub dialect: Option<String>,
+
+    /// How tables are written in the commonmark dialect: "html" or "text"
+    /// (default: "html").
+    pub commonmark_tables: Option<String>,
+
+    /// Recognize `:::` container blocks (default: false).
+    pub containers: Option<bool>,
+
+    /// Recognize `$` and `$$` math and escape dollar signs in text
+    /// (default: false).
+    pub math: Option<bool>,
+
+    /// The template syntax whose tags are kept as they are: "none",
+    /// "liquid", "hugo", or "jinja" (default: "none").
+    pub templates: Option<String>,
+
+    /// The lint rules whose problems are fixed while formatting:
+    /// "heading-increment", "no-trailing-punctuation", "no-empty-links", and
+    /// "code-language" (default: none).  Unknown rule names are ignored.
+    pub fix: Option<Vec<String>>,
+
+    /// Keep MDX syntax (JSX, expressions, and `import` and `export`
+    /// statements) as it is (default: false).
+    pub mdx: Option<bool>,
+
     /// Use setext-style for h1 headings (default: true).
     pub setext_h1: Option<bool>,
 
     /// Use setext-style for h2 headings (default: true).
     pub setext_h2: Option<bool>,
 
+    /// Keep hard line breaks in headings (default: true).
+    pub heading_allow_multiline: Option<bool>,
+
     /// Convert headings to sentence case (default: false).
     pub heading_sentence_case: Option<bool>,
 
@@ -37,6 +100,16 @@ pub struct JsOptions {
     /// These are excluded from built-in proper nouns.
     pub heading_common_nouns: Option<Vec<String>>,
 
+    /// Trailing characters to strip from headings (default: none).
+    pub heading_strip_trailing_punctuation: Option<Vec<String>>,
+
+    /// Number the headings at the top level of the document (default:
+    /// false).
+    pub heading_numbering: Option<bool>,
+
+    /// Heading levels that get numbers (default: [2, 3]).
+    pub heading_numbering_levels: Option<Vec<u8>>,
+
     /// Marker for unordered lists: "-", "*", or "+" (default: "-").
     pub unordered_marker: Option<String>,
 
@@ -58,9 +131,33 @@ pub struct JsOptions {
     /// Padding style for ordered list numbers: "start" or "end" (default: "start").
     pub ordered_list_pad: Option<String>,
 
+    /// How ordered list items are numbered: "sequential" or "ones"
+    /// (default: "sequential").
+    pub ordered_list_numbering: Option<String>,
+
     /// Indent width for nested ordered lists (default: 4).
     pub ordered_list_indent_width: Option<usize>,
 
+    /// How adjacent lists of the same kind are kept apart: "comment" or
+    /// "alternate_marker" (default: "comment").
+    pub list_separation: Option<String>,
+
+    /// Whether lists have blank lines between their items: "preserve",
+    /// "tight", or "loose" (default: "preserve").
+    pub list_spacing: Option<String>,
+
+    /// Where the wrapped lines of a list item's text start: "align_text" or
+    /// "fixed" (default: "align_text").
+    pub list_continuation: Option<String>,
+
+    /// Indentation of wrapped list item lines from the marker when
+    /// `list_continuation` is "fixed" (default: 2).
+    pub list_fixed_width: Option<usize>,
+
+    /// Whether paragraphs with lines like `a.` or `iv)` keep their line
+    /// breaks (default: false).
+    pub list_preserve_pseudo_lists: Option<bool>,
+
     /// Fence character: "~" or "`" (default: "~").
     pub fence_char: Option<String>,
 
@@ -73,12 +170,126 @@ pub struct JsOptions {
     /// Default language for code blocks (default: "").
     pub default_language: Option<String>,
 
+    /// Strip trailing whitespace from code block lines (default: false).
+    pub code_block_trim_trailing_whitespace: Option<bool>,
+
+    /// Languages whose code blocks are formatted by Hongdown itself:
+    /// `"json"`, `"toml"` (default: none).
+    pub code_block_format_embedded: Option<Vec<String>>,
+
+    /// Order of the keys of formatted JSON: "preserve" or "sort" (default:
+    /// "preserve").
+    pub code_block_json_key_order: Option<String>,
+
+    /// Keep the spaces after `>` on unchanged block quote lines (default:
+    /// false).
+    pub block_quote_preserve_marker_spacing: Option<bool>,
+
+    /// How adjacent block quotes are kept apart: "blank" or "comment"
+    /// (default: "blank").
+    pub block_quote_separation: Option<String>,
+
     /// Thematic break style (default: spaced dashes).
     pub thematic_break_style: Option<String>,
 
     /// Leading spaces for thematic breaks (default: 3).
     pub thematic_break_leading_spaces: Option<usize>,
 
+    /// Display width of table cells above which a warning is reported
+    /// (default: none).
+    pub table_max_column_width: Option<usize>,
+
+    /// How table cells wider than the maximum are broken: "none" or "br"
+    /// (default: "none").
+    pub table_wrap_cells: Option<String>,
+
+    /// Whether table cells are padded: "aligned" or "compact" (default:
+    /// "aligned").
+    pub table_padding: Option<String>,
+
+    /// Whether tables are formatted: "align" or "preserve" (default:
+    /// "align").
+    pub table_format: Option<String>,
+
+    /// How wide the delimiters of tables are: "full" or "minimal" (default:
+    /// "full").
+    pub table_separator: Option<String>,
+
+    /// How the widths of table columns follow their contents: "exact" or
+    /// "sticky" (default: "exact").
+    pub table_width_growth: Option<String>,
+
+    /// The multiple that sticky table columns are rounded up to (default:
+    /// 4).
+    pub table_width_step: Option<usize>,
+
+    /// Normalize the tags of HTML void elements (default: false).
+    pub html_normalize_void_tags: Option<bool>,
+
+    /// How normalized HTML void element tags are closed: "html" or "xhtml"
+    /// (default: "html").
+    pub html_void_tag_style: Option<String>,
+
+    /// Turn `<br>` at the end of paragraph lines into hard line breaks
+    /// (default: false).
+    pub html_br_to_hard_break: Option<bool>,
+
+    /// Turn `<hr>` blocks into thematic breaks (default: false).
+    pub html_hr_to_thematic_break: Option<bool>,
+
+    /// How `<details>` blocks are handled: "format" or "preserve"
+    /// (default: "format").
+    pub html_details: Option<String>,
+
+    /// Which literal backslashes in text are escaped: "always" or "lazy"
+    /// (default: "always").
+    pub escape_backslash: Option<String>,
+
+    /// The GitHub repository, as "owner/name", that bare references like
+    /// `#123` are turned into links to (default: none).
+    pub autolink_github_repo: Option<String>,
+
+    /// Turn links to GitHub references back into bare references (default:
+    /// false).
+    pub autolink_github_strip: Option<bool>,
+
+    /// Drop unused reference definitions (default: true).
+    pub link_remove_unused_definitions: Option<bool>,
+
+    /// Reference link syntax: "shortcut", "collapsed", "full", or
+    /// "preserve" (default: "shortcut").
+    pub link_reference_syntax: Option<String>,
+
+    /// Turn inline links to external URLs into reference links (default:
+    /// true).
+    pub link_external_references: Option<bool>,
+
+    /// URL encoding: "preserve", "encode", or "decode_unreserved" (default:
+    /// "preserve").
+    pub link_url_encoding: Option<String>,
+
+    /// Order of reference definitions: "none", "alpha", or "usage"
+    /// (default: "none").
+    pub link_sort_definitions: Option<String>,
+
+    /// Links to external URLs with empty text: "autolink" or "preserve"
+    /// (default: "autolink").
+    pub link_empty_text: Option<String>,
+
+    /// Order of footnote definitions: "none", "alpha", or "usage"
+    /// (default: "none").
+    pub footnote_sort_definitions: Option<String>,
+
+    /// What is done with inline footnotes: "preserve" or "expand"
+    /// (default: "preserve").
+    pub footnote_inline: Option<String>,
+
+    /// Shallowest heading level in a table of contents (default: 2).
+    pub toc_min_level: Option<u8>,
+
+    /// Deepest heading level in a table of contents (default: 3).
+    pub toc_max_level: Option<u8>,
+
     /// Convert straight double quotes to curly (default: true).
     pub curly_double_quotes: Option<bool>,
 
@@ -127,10 +338,97 @@ impl JsOptions {
     fn to_options(&self) -> Options {
         let mut opts = Options::default();
 
-        if let Some(v) = self.line_width {
-            if let Ok(lw) = LineWidth::new(v) {
-                opts.line_width = lw;
-            }
+        if let Some(v) = self.line_width
+            && let Ok(lw) = LineWidth::new(v)
+        {
+            opts.line_width = lw;
+        }
+        if let Some(ref v) = self.wrap {
+            opts.wrap = match v.as_str() {
+                "none" => WrapMode::None,
+                "preserve" => WrapMode::Preserve,
+                "sentence" => WrapMode::Sentence,
+                _ => WrapMode::Width,
+            };
+        }
+        if let Some(v) = self.max_consecutive_blank_lines
+            && let Ok(max) = MaxBlankLines::new(v)
+        {
+            opts.max_consecutive_blank_lines = max;
+        }
+        if let Some(ref v) = self.line_ending {
+            opts.line_ending = match v.as_str() {
+                "crlf" => LineEnding::Crlf,
+                "auto" => LineEnding::Auto,
+                _ => LineEnding::Lf,
+            };
+        }
+        if let Some(ref v) = self.bom {
+            opts.bom = match v.as_str() {
+                "remove" => ByteOrderMark::Remove,
+                _ => ByteOrderMark::Preserve,
+            };
+        }
+        if let Some(ref v) = self.hard_break {
+            opts.hard_break = match v.as_str() {
+                "backslash" => HardBreak::Backslash,
+                _ => HardBreak::Spaces,
+            };
+        }
+        if let Some(ref v) = self.entities {
+            opts.entities = match v.as_str() {
+                "decode" => EntityMode::Decode,
+                "encode_invisible" => EntityMode::EncodeInvisible,
+                _ => EntityMode::Preserve,
+            };
+        }
+        if let Some(ref v) = self.locale {
+            opts.locale = match v.as_str() {
+                "ko" => Locale::Ko,
+                "auto" => Locale::Auto,
+                _ => Locale::None,
+            };
+        }
+        if let Some(ref v) = self.dialect {
+            opts.dialect = match v.as_str() {
+                "commonmark" => Dialect::CommonMark,
+                _ => Dialect::Gfm,
+            };
+        }
+        if let Some(ref v) = self.commonmark_tables {
+            opts.commonmark_tables = match v.as_str() {
+                "text" => TableFallback::Text,
+                _ => TableFallback::Html,
+            };
+        }
+        if let Some(v) = self.containers {
+            opts.containers = v;
+        }
+        if let Some(v) = self.math {
+            opts.math = v;
+        }
+        if let Some(ref v) = self.templates {
+            opts.templates = match v.as_str() {
+                "liquid" => TemplateSyntax::Liquid,
+                "hugo" => TemplateSyntax::Hugo,
+                "jinja" => TemplateSyntax::Jinja,
+                _ => TemplateSyntax::None,
+            };
+        }
+        if let Some(ref v) = self.fix {
+            opts.fix = v
+                .iter()
+                .filter_map(|rule| match rule.as_str() {
+                    "heading-increment" => Some(FixRule::HeadingIncrement),
+                    "no-trailing-punctuation" => Some(FixRule::NoTrailingPunctuation),
+                    "no-empty-links" => Some(FixRule::NoEmptyLinks),
+                    "code-language" => Some(FixRule::CodeLanguage),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Some(v) = self.mdx {
+            opts.mdx = v;
         }
         if let Some(v) = self.setext_h1 {
             opts.setext_h1 = v;
@@ -138,6 +436,9 @@ impl JsOptions {
         if let Some(v) = self.setext_h2 {
             opts.setext_h2 = v;
         }
+        if let Some(v) = self.heading_allow_multiline {
+            opts.heading_allow_multiline = v;
+        }
         if let Some(v) = self.heading_sentence_case {
             opts.heading_sentence_case = v;
         }
@@ -147,6 +448,18 @@ impl JsOptions {
         if let Some(ref v) = self.heading_common_nouns {
             opts.heading_common_nouns = v.clone();
         }
+        if let Some(ref v) = self.heading_strip_trailing_punctuation {
+            opts.heading_strip_trailing_punctuation = v.clone();
+        }
+        if let Some(v) = self.heading_numbering {
+            opts.heading_numbering = v;
+        }
+        if let Some(ref v) = self.heading_numbering_levels {
+            opts.heading_numbering_levels = v
+                .iter()
+                .filter_map(|&level| HeadingLevel::new(level).ok())
+                .collect();
+        }
         if let Some(ref v) = self.unordered_marker {
             opts.unordered_marker = match v.as_str() {
                 "*" => UnorderedMarker::Asterisk,
@@ -154,20 +467,20 @@ impl JsOptions {
                 _ => UnorderedMarker::Hyphen,
             };
         }
-        if let Some(v) = self.leading_spaces {
-            if let Ok(leading) = LeadingSpaces::new(v) {
-                opts.leading_spaces = leading;
-            }
+        if let Some(v) = self.leading_spaces
+            && let Ok(leading) = LeadingSpaces::new(v)
+        {
+            opts.leading_spaces = leading;
         }
-        if let Some(v) = self.trailing_spaces {
-            if let Ok(trailing) = TrailingSpaces::new(v) {
-                opts.trailing_spaces = trailing;
-            }
+        if let Some(v) = self.trailing_spaces
+            && let Ok(trailing) = TrailingSpaces::new(v)
+        {
+            opts.trailing_spaces = trailing;
         }
-        if let Some(v) = self.indent_width {
-            if let Ok(width) = IndentWidth::new(v) {
-                opts.indent_width = width;
-            }
+        if let Some(v) = self.indent_width
+            && let Ok(width) = IndentWidth::new(v)
+        {
+            opts.indent_width = width;
         }
         if let Some(ref v) = self.odd_level_marker {
             opts.odd_level_marker = match v.as_str() {
@@ -187,10 +500,41 @@ impl JsOptions {
                 _ => OrderedListPad::Start,
             };
         }
-        if let Some(v) = self.ordered_list_indent_width {
-            if let Ok(width) = IndentWidth::new(v) {
-                opts.ordered_list_indent_width = width;
-            }
+        if let Some(ref v) = self.ordered_list_numbering {
+            opts.ordered_list_numbering = match v.as_str() {
+                "ones" => OrderedNumbering::Ones,
+                _ => OrderedNumbering::Sequential,
+            };
+        }
+        if let Some(v) = self.ordered_list_indent_width
+            && let Ok(width) = IndentWidth::new(v)
+        {
+            opts.ordered_list_indent_width = width;
+        }
+        if let Some(ref v) = self.list_separation {
+            opts.list_separation = match v.as_str() {
+                "alternate_marker" => ListSeparation::AlternateMarker,
+                _ => ListSeparation::Comment,
+            };
+        }
+        if let Some(ref v) = self.list_spacing {
+            opts.list_spacing = match v.as_str() {
+                "tight" => ListSpacing::Tight,
+                "loose" => ListSpacing::Loose,
+                _ => ListSpacing::Preserve,
+            };
+        }
+        if let Some(ref v) = self.list_continuation {
+            opts.list_continuation = match v.as_str() {
+                "fixed" => ListContinuation::Fixed,
+                _ => ListContinuation::AlignText,
+            };
+        }
+        if let Some(v) = self.list_fixed_width {
+            opts.list_fixed_width = v;
+        }
+        if let Some(v) = self.list_preserve_pseudo_lists {
+            opts.list_preserve_pseudo_lists = v;
         }
         if let Some(ref v) = self.fence_char {
             opts.fence_char = match v.as_str() {
@@ -198,10 +542,10 @@ impl JsOptions {
                 _ => FenceChar::Tilde,
             };
         }
-        if let Some(v) = self.min_fence_length {
-            if let Ok(min_len) = MinFenceLength::new(v) {
-                opts.min_fence_length = min_len;
-            }
+        if let Some(v) = self.min_fence_length
+            && let Ok(min_len) = MinFenceLength::new(v)
+        {
+            opts.min_fence_length = min_len;
         }
         if let Some(v) = self.space_after_fence {
             opts.space_after_fence = v;
@@ -209,15 +553,165 @@ impl JsOptions {
         if let Some(ref v) = self.default_language {
             opts.default_language = v.clone();
         }
-        if let Some(ref v) = self.thematic_break_style {
-            if let Ok(style) = ThematicBreakStyle::new(v.clone()) {
-                opts.thematic_break_style = style;
-            }
+        if let Some(v) = self.code_block_trim_trailing_whitespace {
+            opts.code_block_trim_trailing_whitespace = v;
         }
-        if let Some(v) = self.thematic_break_leading_spaces {
-            if let Ok(leading) = LeadingSpaces::new(v) {
-                opts.thematic_break_leading_spaces = leading;
-            }
+        if let Some(ref v) = self.code_block_format_embedded {
+            opts.code_block_format_embedded = v
+                .iter()
+                .filter_map(|language| match language.as_str() {
+                    "json" => Some(EmbeddedLanguage::Json),
+                    "toml" => Some(EmbeddedLanguage::Toml),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Some(ref v) = self.code_block_json_key_order {
+            opts.code_block_json_key_order = match v.as_str() {
+                "sort" => JsonKeyOrder::Sort,
+                _ => JsonKeyOrder::Preserve,
+            };
+        }
+        if let Some(v) = self.block_quote_preserve_marker_spacing {
+            opts.block_quote_preserve_marker_spacing = v;
+        }
+        if let Some(ref v) = self.block_quote_separation {
+            opts.block_quote_separation = match v.as_str() {
+                "comment" => BlockQuoteSeparation::Comment,
+                _ => BlockQuoteSeparation::Blank,
+            };
+        }
+        if let Some(ref v) = self.thematic_break_style
+            && let Ok(style) = ThematicBreakStyle::new(v.clone())
+        {
+            opts.thematic_break_style = style;
+        }
+        if let Some(v) = self.thematic_break_leading_spaces
+            && let Ok(leading) = LeadingSpaces::new(v)
+        {
+            opts.thematic_break_leading_spaces = leading;
+        }
+        if let Some(v) = self.table_max_column_width {
+            opts.table_max_column_width = Some(v);
+        }
+        if let Some(ref v) = self.table_wrap_cells {
+            opts.table_wrap_cells = match v.as_str() {
+                "br" => CellWrap::Br,
+                _ => CellWrap::None,
+            };
+        }
+        if let Some(ref v) = self.table_padding {
+            opts.table_padding = match v.as_str() {
+                "compact" => TablePadding::Compact,
+                _ => TablePadding::Aligned,
+            };
+        }
+        if let Some(ref v) = self.table_format {
+            opts.table_format = match v.as_str() {
+                "preserve" => TableFormat::Preserve,
+                _ => TableFormat::Align,
+            };
+        }
+        if let Some(ref v) = self.table_separator {
+            opts.table_separator = match v.as_str() {
+                "minimal" => TableSeparator::Minimal,
+                _ => TableSeparator::Full,
+            };
+        }
+        if let Some(ref v) = self.table_width_growth {
+            opts.table_width_growth = match v.as_str() {
+                "sticky" => TableWidthGrowth::Sticky,
+                _ => TableWidthGrowth::Exact,
+            };
+        }
+        if let Some(v) = self.table_width_step
+            && let Ok(step) = TableWidthStep::new(v)
+        {
+            opts.table_width_step = step;
+        }
+        if let Some(v) = self.html_normalize_void_tags {
+            opts.html_normalize_void_tags = v;
+        }
+        if let Some(ref v) = self.html_void_tag_style {
+            opts.html_void_tag_style = match v.as_str() {
+                "xhtml" => VoidTagStyle::Xhtml,
+                _ => VoidTagStyle::Html,
+            };
+        }
+        if let Some(v) = self.html_br_to_hard_break {
+            opts.html_br_to_hard_break = v;
+        }
+        if let Some(v) = self.html_hr_to_thematic_break {
+            opts.html_hr_to_thematic_break = v;
+        }
+        if let Some(ref v) = self.html_details {
+            opts.html_details = match v.as_str() {
+                "preserve" => DetailsHandling::Preserve,
+                _ => DetailsHandling::Format,
+            };
+        }
+        if let Some(ref v) = self.escape_backslash {
+            opts.escape_backslash = match v.as_str() {
+                "lazy" => BackslashEscape::Lazy,
+                _ => BackslashEscape::Always,
+            };
+        }
+        if let Some(ref v) = self.autolink_github_repo
+            && let Ok(repo) = GitHubRepo::new(v.clone())
+        {
+            opts.autolink_github_repo = Some(repo);
+        }
+        if let Some(v) = self.autolink_github_strip {
+            opts.autolink_github_strip = v;
+        }
+        if let Some(v) = self.link_remove_unused_definitions {
+            opts.link_remove_unused_definitions = v;
+        }
+        if let Some(ref v) = self.link_reference_syntax {
+            opts.link_reference_syntax = match v.as_str() {
+                "collapsed" => ReferenceSyntax::Collapsed,
+                "full" => ReferenceSyntax::Full,
+                "preserve" => ReferenceSyntax::Preserve,
+                _ => ReferenceSyntax::Shortcut,
+            };
+        }
+        if let Some(v) = self.link_external_references {
+            opts.link_external_references = v;
+        }
+        if let Some(ref v) = self.link_url_encoding {
+            opts.link_url_encoding = match v.as_str() {
+                "encode" => UrlEncoding::Encode,
+                "decode_unreserved" => UrlEncoding::DecodeUnreserved,
+                _ => UrlEncoding::Preserve,
+            };
+        }
+        if let Some(ref v) = self.link_sort_definitions {
+            opts.link_sort_definitions = parse_definition_order(v);
+        }
+        if let Some(ref v) = self.link_empty_text {
+            opts.link_empty_text = match v.as_str() {
+                "preserve" => EmptyLinkText::Preserve,
+                _ => EmptyLinkText::Autolink,
+            };
+        }
+        if let Some(ref v) = self.footnote_sort_definitions {
+            opts.footnote_sort_definitions = parse_definition_order(v);
+        }
+        if let Some(ref v) = self.footnote_inline {
+            opts.footnote_inline = match v.as_str() {
+                "expand" => InlineFootnotes::Expand,
+                _ => InlineFootnotes::Preserve,
+            };
+        }
+        if let Some(v) = self.toc_min_level
+            && let Ok(level) = HeadingLevel::new(v)
+        {
+            opts.toc_min_level = level;
+        }
+        if let Some(v) = self.toc_max_level
+            && let Ok(level) = HeadingLevel::new(v)
+        {
+            opts.toc_max_level = level;
         }
         if let Some(v) = self.curly_double_quotes {
             opts.curly_double_quotes = v;
@@ -242,6 +736,16 @@ impl JsOptions {
     }
 }
 
+/// Parse an order of definitions: "alpha", "usage", or anything else for
+/// "none".
+fn parse_definition_order(value: &str) -> DefinitionOrder {
+    match value {
+        "alpha" => DefinitionOrder::Alpha,
+        "usage" => DefinitionOrder::Usage,
+        _ => DefinitionOrder::None,
+    }
+}
+
 /// Format result with warnings.
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -259,6 +763,9 @@ pub struct JsWarning {
     pub line: usize,
     /// Warning message.
     pub message: String,
+    /// Whether this reports a fix made because of `fix`, rather than
+    /// a problem.
+    pub fixed: bool,
 }
 
 /// Format Markdown according to Hong Minhee's style conventions.
@@ -313,6 +820,7 @@ pub fn format_with_warnings(input: &str, options: JsValue) -> Result<JsValue, Js
             .map(|w| JsWarning {
                 line: w.line,
                 message: w.message,
+                fixed: w.fixed,
             })
             .collect(),
     };
@@ -338,8 +846,6 @@ pub fn format_with_code_formatter(
     options: JsValue,
     code_formatter: Option<js_sys::Function>,
 ) -> Result<JsValue, JsError> {
-    use comrak::{Arena, Options as ComrakOptions, parse_document};
-
     let js_opts: JsOptions = if options.is_undefined() || options.is_null() {
         JsOptions::default()
     } else {
@@ -356,17 +862,6 @@ pub fn format_with_code_formatter(
         return serde_wasm_bindgen::to_value(&js_result).map_err(|e| JsError::new(&e.to_string()));
     }
 
-    let arena = Arena::new();
-    let mut comrak_options = ComrakOptions::default();
-    comrak_options.extension.front_matter_delimiter = Some("---".to_string());
-    comrak_options.extension.table = true;
-    comrak_options.extension.description_lists = true;
-    comrak_options.extension.alerts = true;
-    comrak_options.extension.footnotes = true;
-    comrak_options.extension.tasklist = true;
-
-    let root = parse_document(&arena, input, &comrak_options);
-
     // Create callback closure if provided
     let callback: crate::serializer::CodeFormatterCallback = code_formatter.map(|func| {
         Box::new(move |language: &str, code: &str| -> Option<String> {
@@ -387,8 +882,8 @@ pub fn format_with_code_formatter(
         }) as Box<dyn Fn(&str, &str) -> Option<String>>
     });
 
-    let result =
-        crate::serializer::serialize_with_code_formatter(root, &opts, Some(input), callback);
+    let result = crate::run_pipeline(input, &opts, &mut CodeFormatterHooks(callback))
+        .map_err(|e| JsError::new(&e.to_string()))?;
 
     let js_result = JsFormatResult {
         output: result.output,
@@ -398,6 +893,7 @@ pub fn format_with_code_formatter(
             .map(|w| JsWarning {
                 line: w.line,
                 message: w.message,
+                fixed: w.fixed,
             })
             .collect(),
     };
@@ -405,6 +901,21 @@ pub fn format_with_code_formatter(
     serde_wasm_bindgen::to_value(&js_result).map_err(|e| JsError::new(&e.to_string()))
 }
 
+/// The hooks of [`format_with_code_formatter`], which serialize with the
+/// JavaScript code formatter callback.
+struct CodeFormatterHooks(crate::serializer::CodeFormatterCallback);
+
+impl crate::PipelineHooks for CodeFormatterHooks {
+    fn serialize<'a>(
+        &mut self,
+        root: &'a comrak::nodes::AstNode<'a>,
+        options: &Options,
+        input: &str,
+    ) -> crate::serializer::SerializeResult {
+        crate::serializer::serialize_with_code_formatter(root, options, Some(input), self.0.take())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -476,6 +987,367 @@ mod tests {
         assert_eq!(opts.heading_common_nouns, vec!["react"]);
     }
 
+    #[test]
+    fn test_js_options_heading_strip_trailing_punctuation() {
+        let js_opts = JsOptions {
+            heading_strip_trailing_punctuation: Some(vec![":".to_string()]),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.heading_strip_trailing_punctuation, vec![":"]);
+    }
+
+    #[test]
+    fn test_js_options_heading_numbering() {
+        let js_opts = JsOptions {
+            heading_numbering: Some(true),
+            heading_numbering_levels: Some(vec![1, 2, 9]),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(opts.heading_numbering);
+        let levels: Vec<u8> = opts
+            .heading_numbering_levels
+            .iter()
+            .map(|l| l.get())
+            .collect();
+        assert_eq!(levels, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_js_options_list_spacing() {
+        let js_opts = JsOptions {
+            list_spacing: Some("tight".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.list_spacing, ListSpacing::Tight);
+    }
+
+    #[test]
+    fn test_js_options_link_external_references() {
+        let js_opts = JsOptions {
+            link_external_references: Some(false),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(!opts.link_external_references);
+    }
+
+    #[test]
+    fn test_js_options_link_url_encoding() {
+        let js_opts = JsOptions {
+            link_url_encoding: Some("decode_unreserved".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.link_url_encoding, UrlEncoding::DecodeUnreserved);
+    }
+
+    #[test]
+    fn test_js_options_sort_definitions() {
+        let js_opts = JsOptions {
+            link_sort_definitions: Some("alpha".to_string()),
+            footnote_sort_definitions: Some("usage".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.link_sort_definitions, DefinitionOrder::Alpha);
+        assert_eq!(opts.footnote_sort_definitions, DefinitionOrder::Usage);
+    }
+
+    #[test]
+    fn test_js_options_wrap_sentence() {
+        let js_opts = JsOptions {
+            wrap: Some("sentence".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.wrap, WrapMode::Sentence);
+    }
+
+    #[test]
+    fn test_js_options_list_continuation() {
+        let js_opts = JsOptions {
+            list_continuation: Some("fixed".to_string()),
+            list_fixed_width: Some(3),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.list_continuation, ListContinuation::Fixed);
+        assert_eq!(opts.list_fixed_width, 3);
+    }
+
+    #[test]
+    fn test_js_options_list_preserve_pseudo_lists() {
+        let js_opts = JsOptions {
+            list_preserve_pseudo_lists: Some(true),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(opts.list_preserve_pseudo_lists);
+    }
+
+    #[test]
+    fn test_js_options_block_quote_preserve_marker_spacing() {
+        let js_opts = JsOptions {
+            block_quote_preserve_marker_spacing: Some(true),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(opts.block_quote_preserve_marker_spacing);
+    }
+
+    #[test]
+    fn test_js_options_block_quote_separation() {
+        let js_opts = JsOptions {
+            block_quote_separation: Some("comment".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.block_quote_separation, BlockQuoteSeparation::Comment);
+    }
+
+    #[test]
+    fn test_js_options_containers() {
+        let js_opts = JsOptions {
+            containers: Some(true),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(opts.containers);
+    }
+
+    #[test]
+    fn test_js_options_math() {
+        let js_opts = JsOptions {
+            math: Some(true),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(opts.math);
+    }
+
+    #[test]
+    fn test_js_options_bom() {
+        let js_opts = JsOptions {
+            bom: Some("remove".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.bom, ByteOrderMark::Remove);
+    }
+
+    #[test]
+    fn test_js_options_footnote_inline() {
+        let js_opts = JsOptions {
+            footnote_inline: Some("expand".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.footnote_inline, InlineFootnotes::Expand);
+    }
+
+    #[test]
+    fn test_js_options_hard_break() {
+        let js_opts = JsOptions {
+            hard_break: Some("backslash".to_string()),
+            code_block_trim_trailing_whitespace: Some(true),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.hard_break, HardBreak::Backslash);
+        assert!(opts.code_block_trim_trailing_whitespace);
+    }
+
+    #[test]
+    fn test_js_options_html() {
+        let js_opts = JsOptions {
+            html_normalize_void_tags: Some(true),
+            html_void_tag_style: Some("xhtml".to_string()),
+            html_br_to_hard_break: Some(true),
+            html_details: Some("preserve".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(opts.html_normalize_void_tags);
+        assert_eq!(opts.html_void_tag_style, VoidTagStyle::Xhtml);
+        assert!(opts.html_br_to_hard_break);
+        assert!(!opts.html_hr_to_thematic_break);
+        assert_eq!(opts.html_details, DetailsHandling::Preserve);
+    }
+
+    #[test]
+    fn test_js_options_table() {
+        let js_opts = JsOptions {
+            table_max_column_width: Some(40),
+            table_wrap_cells: Some("br".to_string()),
+            table_padding: Some("compact".to_string()),
+            table_format: Some("preserve".to_string()),
+            table_separator: Some("minimal".to_string()),
+            table_width_growth: Some("sticky".to_string()),
+            table_width_step: Some(8),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.table_max_column_width, Some(40));
+        assert_eq!(opts.table_wrap_cells, CellWrap::Br);
+        assert_eq!(opts.table_padding, TablePadding::Compact);
+        assert_eq!(opts.table_format, TableFormat::Preserve);
+        assert_eq!(opts.table_separator, TableSeparator::Minimal);
+        assert_eq!(opts.table_width_growth, TableWidthGrowth::Sticky);
+        assert_eq!(opts.table_width_step.get(), 8);
+    }
+
+    #[test]
+    fn test_js_options_diff_friendly() {
+        let js_opts = JsOptions {
+            ordered_list_numbering: Some("ones".to_string()),
+            max_consecutive_blank_lines: Some(1),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.ordered_list_numbering, OrderedNumbering::Ones);
+        assert_eq!(opts.max_consecutive_blank_lines.get(), 1);
+        let opts = JsOptions {
+            max_consecutive_blank_lines: Some(0),
+            ..Default::default()
+        }
+        .to_options();
+        assert_eq!(opts.max_consecutive_blank_lines.get(), 2);
+    }
+
+    #[test]
+    fn test_js_options_format_embedded() {
+        let js_opts = JsOptions {
+            code_block_format_embedded: Some(vec!["json".to_string(), "yaml".to_string()]),
+            code_block_json_key_order: Some("sort".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(
+            opts.code_block_format_embedded,
+            vec![EmbeddedLanguage::Json]
+        );
+        assert_eq!(opts.code_block_json_key_order, JsonKeyOrder::Sort);
+    }
+
+    #[test]
+    fn test_js_options_autolink_github() {
+        let js_opts = JsOptions {
+            autolink_github_repo: Some("dahlia/hongdown".to_string()),
+            autolink_github_strip: Some(true),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(
+            opts.autolink_github_repo.as_ref().map(GitHubRepo::as_str),
+            Some("dahlia/hongdown")
+        );
+        assert!(opts.autolink_github_strip);
+        let js_opts = JsOptions {
+            autolink_github_repo: Some("hongdown".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(js_opts.to_options().autolink_github_repo, None);
+    }
+
+    #[test]
+    fn test_js_options_escape() {
+        let opts = JsOptions::default().to_options();
+        assert_eq!(opts.escape_backslash, BackslashEscape::Always);
+        let js_opts = JsOptions {
+            escape_backslash: Some("lazy".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.escape_backslash, BackslashEscape::Lazy);
+    }
+
+    #[test]
+    fn test_js_options_link_empty_text() {
+        let js_opts = JsOptions {
+            link_empty_text: Some("preserve".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.link_empty_text, EmptyLinkText::Preserve);
+    }
+
+    #[test]
+    fn test_js_options_templates() {
+        let js_opts = JsOptions {
+            templates: Some("hugo".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.templates, TemplateSyntax::Hugo);
+    }
+
+    #[test]
+    fn test_js_options_fix() {
+        let js_opts = JsOptions {
+            fix: Some(vec![
+                "no-empty-links".to_string(),
+                "single-h1".to_string(),
+                "heading-increment".to_string(),
+            ]),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.fix, [FixRule::NoEmptyLinks, FixRule::HeadingIncrement]);
+    }
+
+    #[test]
+    fn test_js_options_locale() {
+        let opts = JsOptions {
+            locale: Some("ko".to_string()),
+            ..Default::default()
+        }
+        .to_options();
+        assert_eq!(opts.locale, Locale::Ko);
+        let opts = JsOptions {
+            locale: Some("auto".to_string()),
+            ..Default::default()
+        }
+        .to_options();
+        assert_eq!(opts.locale, Locale::Auto);
+    }
+
+    #[test]
+    fn test_js_options_dialect() {
+        let js_opts = JsOptions {
+            dialect: Some("commonmark".to_string()),
+            commonmark_tables: Some("text".to_string()),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.dialect, Dialect::CommonMark);
+        assert_eq!(opts.commonmark_tables, TableFallback::Text);
+    }
+
+    #[test]
+    fn test_js_options_mdx() {
+        let js_opts = JsOptions {
+            mdx: Some(true),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert!(opts.mdx);
+    }
+
+    #[test]
+    fn test_js_options_toc_levels() {
+        let js_opts = JsOptions {
+            toc_min_level: Some(1),
+            toc_max_level: Some(7),
+            ..Default::default()
+        };
+        let opts = js_opts.to_options();
+        assert_eq!(opts.toc_min_level.get(), 1);
+        assert_eq!(opts.toc_max_level.get(), 3);
+    }
+
     #[test]
     fn test_js_options_heading_all() {
         let js_opts = JsOptions {
@@ -0,0 +1,153 @@
+//! CommonMark spec compliance tests.
+//!
+//! Each example of the CommonMark spec 0.31.2, in *tests/spec/spec.json*, is
+//! formatted, and the HTML that comrak renders for the output must be the
+//! same as the HTML it renders for the example, so that formatting never
+//! changes what a document means.  The examples that are rendered
+//! differently on purpose, by the style Hongdown writes, are listed in
+//! *tests/spec/allowlist.toml* with the reason why.
+//!
+//! The tests are only built with the `commonmark-spec` feature:
+//!
+//! ~~~~ bash
+//! cargo test --features commonmark-spec --test commonmark_spec -- --nocapture
+//! ~~~~
+//!
+//! The compliance percentage is printed, and the test fails if an example
+//! that isn't allowlisted fails.
+
+#![cfg(feature = "commonmark-spec")]
+
+use std::collections::BTreeMap;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::Path;
+
+use hongdown::{DashSetting, Options, comrak_options, config::Config, format};
+use serde::Deserialize;
+
+/// An example of the spec, as written in *spec.json*.
+#[derive(Debug, Deserialize)]
+struct Example {
+    markdown: String,
+    example: u32,
+    section: String,
+}
+
+/// The allowlist of examples that are known to fail.
+#[derive(Debug, Default, Deserialize)]
+struct Allowlist {
+    #[serde(default)]
+    deviation: Vec<Deviation>,
+}
+
+/// An example that is known to fail, and why.
+#[derive(Debug, Deserialize)]
+struct Deviation {
+    example: u32,
+    reason: String,
+}
+
+/// Read a file in *tests/spec/*.
+fn read_spec_file(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/spec")
+        .join(name);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e))
+}
+
+/// The options examples are formatted with: the defaults, but without the
+/// punctuation transformations, which change the text on purpose.
+fn options() -> Options {
+    Options {
+        curly_double_quotes: false,
+        curly_single_quotes: false,
+        curly_apostrophes: false,
+        ellipsis: false,
+        en_dash: DashSetting::Disabled,
+        em_dash: DashSetting::Disabled,
+        ..Options::default()
+    }
+}
+
+/// Render `markdown` into HTML with the options Hongdown parses with, and
+/// with raw HTML kept, so that changes to it show.
+fn render(markdown: &str) -> String {
+    let mut comrak = comrak_options(&Config::default());
+    comrak.render.r#unsafe = true;
+    comrak::markdown_to_html(markdown, &comrak)
+}
+
+/// Check an example, returning what is wrong with it, if anything.
+fn check_example(example: &Example, options: &Options) -> Option<String> {
+    let formatted = match catch_unwind(AssertUnwindSafe(|| format(&example.markdown, options))) {
+        Ok(Ok(formatted)) => formatted,
+        Ok(Err(e)) => return Some(format!("formatting failed: {}", e)),
+        Err(_) => return Some("formatting panicked".to_string()),
+    };
+    let expected = render(&example.markdown);
+    let actual = render(&formatted);
+    if actual == expected {
+        return None;
+    }
+    Some(format!(
+        "input:\n{}\noutput:\n{}\nexpected HTML:\n{}\nactual HTML:\n{}",
+        example.markdown, formatted, expected, actual
+    ))
+}
+
+/// Test that formatting the spec examples doesn't change how they are
+/// rendered, but for the allowlisted ones.
+#[test]
+fn test_commonmark_spec() {
+    let examples: Vec<Example> =
+        serde_json::from_str(&read_spec_file("spec.json")).expect("Invalid spec.json");
+    let allowlist: Allowlist =
+        toml::from_str(&read_spec_file("allowlist.toml")).expect("Invalid allowlist.toml");
+    let allowed: BTreeMap<u32, &str> = allowlist
+        .deviation
+        .iter()
+        .map(|deviation| (deviation.example, deviation.reason.as_str()))
+        .collect();
+
+    let options = options();
+    let mut passed = 0;
+    let mut failures = Vec::new();
+    let mut stale = Vec::new();
+    for example in &examples {
+        match check_example(example, &options) {
+            None => {
+                passed += 1;
+                if allowed.contains_key(&example.example) {
+                    stale.push(example.example);
+                }
+            }
+            Some(_) if allowed.contains_key(&example.example) => {}
+            Some(failure) => failures.push(format!(
+                "Example {} ({}):\n{}",
+                example.example, example.section, failure
+            )),
+        }
+    }
+
+    println!(
+        "CommonMark spec compliance: {}/{} examples ({:.1}%), {} allowlisted",
+        passed,
+        examples.len(),
+        passed as f64 * 100.0 / examples.len().max(1) as f64,
+        allowed.len()
+    );
+    if !stale.is_empty() {
+        println!(
+            "Allowlisted examples that now pass, which can be removed from \
+             allowlist.toml: {:?}",
+            stale
+        );
+    }
+    assert!(
+        failures.is_empty(),
+        "{} spec examples failed:\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
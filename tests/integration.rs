@@ -2,6 +2,9 @@
 
 use hongdown::{LineWidth, Options, format};
 
+#[path = "../benches/corpus.rs"]
+mod corpus;
+
 /// Test that formatting is idempotent (formatting twice produces same result).
 #[test]
 fn test_idempotent_formatting() {
@@ -125,6 +128,21 @@ fn test_whitespace_only() {
     assert!(result.trim().is_empty() || result.is_empty());
 }
 
+/// Test that every benchmark corpus document formats, so that the
+/// benchmarks measure realistic work rather than error paths.
+#[test]
+fn test_corpus_documents_format() {
+    let options = Options::default();
+    for (name, doc) in corpus::all() {
+        let output = format(&doc, &options).unwrap();
+        assert!(
+            output.lines().count() > 100,
+            "corpus document {} is too small",
+            name
+        );
+    }
+}
+
 mod cli_tests {
     use std::io::Write;
     use std::process::{Command, Stdio};
@@ -185,7 +203,75 @@ mod cli_tests {
             stdout.contains("+====="),
             "Diff should show added underline"
         );
-        assert_eq!(exit_code, 0);
+        // Like --check, --diff fails when the input is not formatted
+        assert_eq!(exit_code, 1);
+    }
+
+    /// Test --diff output matches a unified diff snapshot with two
+    /// separated changes, `a/`/`b/` prefixes, and three context lines.
+    #[test]
+    fn test_diff_snapshot_two_hunks() {
+        let input = "\
+# Title
+
+Line one.
+
+Line two.
+
+Line three.
+
+Line four.
+
+Line five.
+
+*   Item
+";
+        let (stdout, _stderr, exit_code) =
+            run_hongdown(&["--diff", "--color", "never", "--stdin"], Some(input));
+        let expected = "\
+--- a/<stdin>
++++ b/<stdin>
+@@ -1,4 +1,5 @@
+-# Title
++Title
++=====
+ 
+ Line one.
+ 
+@@ -10,4 +11,4 @@
+ 
+ Line five.
+ 
+-*   Item
++ -  Item
+";
+        assert_eq!(stdout, expected);
+        assert_eq!(exit_code, 1);
+    }
+
+    /// Test --color always emits ANSI escapes in --diff output.
+    #[test]
+    fn test_diff_color_always() {
+        let (stdout, _stderr, _exit_code) = run_hongdown(
+            &["--diff", "--color", "always", "--stdin"],
+            Some("# Title\n"),
+        );
+        assert!(
+            stdout.contains("\x1b[31m-# Title\x1b[0m"),
+            "got: {:?}",
+            stdout
+        );
+        assert!(
+            stdout.contains("\x1b[32m+Title\x1b[0m"),
+            "got: {:?}",
+            stdout
+        );
+
+        let (stdout, _stderr, _exit_code) = run_hongdown(
+            &["--diff", "--color", "never", "--stdin"],
+            Some("# Title\n"),
+        );
+        assert!(!stdout.contains('\x1b'), "got: {:?}", stdout);
     }
 
     /// Test --diff with file input.
@@ -206,7 +292,7 @@ mod cli_tests {
         // Should show diff with filename in header
         assert!(stdout.contains("---"), "Diff should contain --- header");
         assert!(stdout.contains("+++"), "Diff should contain +++ header");
-        assert_eq!(exit_code, 0);
+        assert_eq!(exit_code, 1);
 
         // File should not be modified
         let content = fs::read_to_string(temp_file.path()).expect("Failed to read temp file");
@@ -319,6 +405,40 @@ mod cli_tests {
         );
     }
 
+    /// Test --write replaces changed files without leaving temporary files
+    /// behind, and doesn't touch the modification time of unchanged ones.
+    #[test]
+    fn test_write_replaces_files_atomically() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let formatted_path = temp_dir.path().join("formatted.md");
+        fs::write(&formatted_path, "Title\n=====\n\nA paragraph.\n").unwrap();
+        let unformatted_path = temp_dir.path().join("unformatted.md");
+        fs::write(&unformatted_path, "# Title\n\nA paragraph.\n").unwrap();
+        let mtime = fs::metadata(&formatted_path).unwrap().modified().unwrap();
+
+        let (_stdout, stderr, exit_code) =
+            run_hongdown(&["--write", temp_dir.path().to_str().unwrap()], None);
+
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(
+            fs::read_to_string(&unformatted_path).unwrap(),
+            "Title\n=====\n\nA paragraph.\n"
+        );
+        let mut names: Vec<_> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["formatted.md", "unformatted.md"]);
+        assert_eq!(
+            fs::metadata(&formatted_path).unwrap().modified().unwrap(),
+            mtime
+        );
+    }
+
     /// Test that running hongdown without files and without --stdin fails.
     #[test]
     fn test_no_input_error() {
@@ -462,6 +582,114 @@ mod cli_tests {
         );
     }
 
+    /// Test that directory arguments skip the files .hongdownignore files
+    /// ignore, while files given by name are formatted anyway.
+    #[test]
+    fn test_directory_argument_hongdownignore() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("docs/generated")).unwrap();
+        for file in [
+            "docs/guide.md",
+            "docs/generated/INDEX.md",
+            "docs/generated/api.md",
+        ] {
+            fs::write(root.join(file), "# Test\n\nParagraph.").unwrap();
+        }
+        fs::write(
+            root.join(".hongdownignore"),
+            "docs/generated/\n!docs/generated/INDEX.md\n",
+        )
+        .unwrap();
+
+        let (_stdout, stderr, exit_code) = run_hongdown(&["--check", root.to_str().unwrap()], None);
+        assert_eq!(exit_code, 1);
+        assert!(stderr.contains("guide.md"), "stderr: {stderr}");
+        assert!(stderr.contains("INDEX.md"), "stderr: {stderr}");
+        assert!(!stderr.contains("api.md"), "stderr: {stderr}");
+
+        let api = root.join("docs/generated/api.md");
+        let (_stdout, stderr, exit_code) = run_hongdown(&["--check", api.to_str().unwrap()], None);
+        assert_eq!(exit_code, 1);
+        assert!(stderr.contains("api.md"), "stderr: {stderr}");
+    }
+
+    /// Test that a UTF-16 file is rejected with an error naming the file and
+    /// its encoding.
+    #[test]
+    fn test_utf16_file_rejected() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("utf16.md");
+        let content: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("Title\n=====\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        fs::write(&path, content).unwrap();
+
+        let (_stdout, stderr, exit_code) = run_hongdown(&["--check", path.to_str().unwrap()], None);
+        assert_eq!(exit_code, 2);
+        assert!(stderr.contains("utf16.md"), "stderr: {stderr}");
+        assert!(stderr.contains("UTF-16LE"), "stderr: {stderr}");
+    }
+
+    /// Test that .mdx files are collected from directories and formatted in
+    /// MDX mode, while .md files are not.
+    #[test]
+    fn test_directory_argument_mdx_files() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let input = "import Tabs from '@theme/Tabs';\n\n{/* A comment. */}\n";
+        fs::write(temp_dir.path().join("page.mdx"), input).expect("Failed to write page.mdx");
+        fs::write(temp_dir.path().join("page.md"), input).expect("Failed to write page.md");
+
+        let (stdout, _stderr, exit_code) =
+            run_hongdown(&["--write", temp_dir.path().to_str().unwrap()], None);
+
+        assert_eq!(exit_code, 0);
+        assert!(
+            !stdout.contains("page.mdx"),
+            "page.mdx should be left as it is"
+        );
+        assert!(stdout.contains("page.md"), "page.md should be changed");
+        let mdx =
+            fs::read_to_string(temp_dir.path().join("page.mdx")).expect("Failed to read page.mdx");
+        assert_eq!(mdx, input);
+    }
+
+    /// Test that `mdx = false` in the configuration turns off MDX mode for
+    /// .mdx files.
+    #[test]
+    fn test_mdx_disabled_by_config() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let config_path = temp_dir.path().join(".hongdown.toml");
+        fs::write(&config_path, "mdx = false\n").expect("Failed to write config");
+        let file = temp_dir.path().join("page.mdx");
+        fs::write(&file, "{/* A comment. */}\n").expect("Failed to write page.mdx");
+
+        let (_stdout, _stderr, exit_code) = run_hongdown(
+            &[
+                "--check",
+                "--config",
+                config_path.to_str().unwrap(),
+                file.to_str().unwrap(),
+            ],
+            None,
+        );
+
+        assert_eq!(exit_code, 1, "page.mdx should need formatting");
+    }
+
     /// Test that directory argument with --check fails when files need formatting.
     #[test]
     fn test_directory_argument_check_fails_on_unformatted() {
@@ -534,125 +762,1200 @@ mod cli_tests {
         assert_eq!(exit_code, 0, "All files should pass check");
         assert!(stdout.is_empty());
     }
-}
-
-/// Test proper nouns directive in sentence case.
-#[test]
-fn test_sentence_case_proper_nouns_directive() {
-    let input = r#"<!-- hongdown-proper-nouns: Swift, Go -->
 
-# Using Swift And Go Programming
-
-Some content.
-"#;
+    /// Test the `--report json` output for a small tree containing one
+    /// malformed (non-UTF-8) file.
+    #[test]
+    fn test_report_json_snapshot() {
+        use std::fs;
+        use tempfile::TempDir;
 
-    let options = Options {
-        heading_sentence_case: true,
-        ..Options::default()
-    };
-    let result = format(input, &options).unwrap();
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::write(root.join("a.md"), "Formatted\n=========\n\nText.\n").unwrap();
+        fs::write(root.join("b.md"), "# Unformatted\n\n*   Item\n").unwrap();
+        fs::write(root.join("c.md"), b"Broken \xff\xfe text.\n").unwrap();
+        fs::write(root.join("d.md"), "| A | B |\n|---|---|\n| 1 | 2 | 3 |\n").unwrap();
+
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &["--check", "--report", "json", root.to_str().unwrap()],
+            None,
+        );
+        // c.md cannot be read, which is reported with exit code 2
+        assert_eq!(exit_code, 2);
+        assert!(stderr.contains("b.md: not formatted"), "stderr: {}", stderr);
+
+        let mut report: hongdown::report::Report =
+            serde_json::from_str(&stdout).expect("stdout should be a single JSON document");
+        for file in &mut report.files {
+            assert!(file.duration_ms >= 0.0);
+            file.duration_ms = 0.0;
+            let name = std::path::Path::new(&file.path).file_name().unwrap();
+            file.path = name.to_str().unwrap().to_string();
+        }
+        let expected = r#"{
+  "version": 1,
+  "files": [
+    {
+      "path": "a.md",
+      "status": "unchanged",
+      "diagnostics": [],
+      "duration_ms": 0.0
+    },
+    {
+      "path": "b.md",
+      "status": "would_reformat",
+      "diagnostics": [],
+      "duration_ms": 0.0
+    },
+    {
+      "path": "c.md",
+      "status": "error",
+      "diagnostics": [
+        {
+          "severity": "error",
+          "line": null,
+          "column": null,
+          "message": "failed to read file: stream did not contain valid UTF-8."
+        }
+      ],
+      "duration_ms": 0.0
+    },
+    {
+      "path": "d.md",
+      "status": "would_reformat",
+      "diagnostics": [
+        {
+          "severity": "warning",
+          "line": 3,
+          "column": null,
+          "message": "table row has 4 pipe characters, expected 3 for 2 columns; unescaped `|` in cell content? (table starts at line 1)"
+        }
+      ],
+      "duration_ms": 0.0
+    }
+  ]
+}"#;
+        assert_eq!(serde_json::to_string_pretty(&report).unwrap(), expected);
+    }
 
-    // Swift and Go should be preserved as proper nouns
-    assert!(
-        result.contains("Using Swift and Go programming"),
-        "Swift and Go should be preserved as proper nouns via directive"
-    );
-}
+    /// Test that `--report` is rejected outside --write and --check.
+    /// Test --quiet and --verbose in check mode.
+    #[test]
+    fn test_check_verbosity_levels() {
+        use std::fs;
+        use tempfile::TempDir;
 
-/// Test common nouns directive in sentence case.
-#[test]
-fn test_sentence_case_common_nouns_directive() {
-    let input = r#"<!-- hongdown-common-nouns: Python, JavaScript -->
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("ok.md"), "Text.\n").unwrap();
+        fs::write(root.join("bad.md"), "# Bad\n").unwrap();
+        fs::write(
+            root.join("warn.md"),
+            "| A | B |\n|---|---|\n| 1 | 2 | 3 |\n",
+        )
+        .unwrap();
+        let ok = root.join("ok.md");
+        let bad = root.join("bad.md");
+        let warn = root.join("warn.md");
+        let files = [
+            ok.to_str().unwrap(),
+            bad.to_str().unwrap(),
+            warn.to_str().unwrap(),
+        ];
+        let run = |flag: Option<&str>| {
+            let mut args = vec!["--no-config", "--check"];
+            args.extend(flag);
+            args.extend(files);
+            run_hongdown(&args, None)
+        };
 
-# Learning Python And JavaScript Programming
+        let (stdout, stderr, exit_code) = run(None);
+        assert_eq!(exit_code, 1);
+        assert!(stdout.is_empty());
+        assert!(
+            stderr.contains("bad.md: not formatted"),
+            "stderr: {}",
+            stderr
+        );
+        assert!(stderr.contains("warning:"), "stderr: {}", stderr);
+        assert!(!stderr.contains("ok.md"), "stderr: {}", stderr);
+        // Not a terminal, so no progress line
+        assert!(!stderr.contains('\r'), "stderr: {:?}", stderr);
 
-Some content.
-"#;
+        let (stdout, stderr, exit_code) = run(Some("--quiet"));
+        assert_eq!(exit_code, 1);
+        assert!(stdout.is_empty());
+        assert!(stderr.is_empty(), "stderr: {}", stderr);
 
-    let options = Options {
-        heading_sentence_case: true,
-        ..Options::default()
-    };
-    let result = format(input, &options).unwrap();
+        let (_stdout, stderr, exit_code) = run(Some("--verbose"));
+        assert_eq!(exit_code, 1);
+        assert!(stderr.contains("ok.md: unchanged"), "stderr: {}", stderr);
+        assert!(
+            stderr.contains("bad.md: not formatted"),
+            "stderr: {}",
+            stderr
+        );
+    }
 
-    // Python and JavaScript should NOT be preserved (treated as common nouns)
-    assert!(
-        result.contains("Learning python and javascript programming"),
-        "Python and JavaScript should be lowercased via common-nouns directive"
-    );
-}
+    /// Test that --quiet still reports errors but not changed files.
+    #[test]
+    fn test_write_quiet_reports_only_errors() {
+        use std::fs;
+        use tempfile::TempDir;
 
-/// Test both directives together.
-#[test]
-fn test_sentence_case_both_directives() {
-    let input = r#"<!-- hongdown-proper-nouns: Swift, Go -->
-<!-- hongdown-common-nouns: Python -->
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("bad.md"), "# Bad\n").unwrap();
+        fs::write(root.join("binary.md"), [0xff, 0xfe, 0x00]).unwrap();
 
-# Using Swift, Go, And Python
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &["--no-config", "--write", "-q", root.to_str().unwrap()],
+            None,
+        );
+        assert_eq!(exit_code, 2);
+        assert!(stdout.is_empty(), "stdout: {}", stdout);
+        assert!(stderr.contains("binary.md"), "stderr: {}", stderr);
+        assert!(!stderr.contains("bad.md"), "stderr: {}", stderr);
+        assert_eq!(
+            fs::read_to_string(root.join("bad.md")).unwrap(),
+            "Bad\n===\n"
+        );
+    }
 
-Some content.
-"#;
+    /// Test that --write only writes files whose content changes, down to
+    /// the line endings and the trailing newline, and that --verbose reports
+    /// the comparison for each file.
+    #[test]
+    fn test_write_touches_only_changed_files() {
+        use std::fs::{self, File};
+        use std::time::{Duration, SystemTime};
+        use tempfile::TempDir;
 
-    let options = Options {
-        heading_sentence_case: true,
-        ..Options::default()
-    };
-    let result = format(input, &options).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("crlf.md"), "Title\r\n=====\r\n\r\nText.\r\n").unwrap();
+        fs::write(root.join("lf.md"), "Title\n=====\n\nText.\n").unwrap();
+        fs::write(root.join("no-newline.md"), "Text.").unwrap();
+        // A time long past, so that a write can't go unnoticed however
+        // coarse the filesystem's timestamps are
+        let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        for name in ["crlf.md", "lf.md", "no-newline.md"] {
+            let file = File::options().write(true).open(root.join(name)).unwrap();
+            file.set_modified(past).unwrap();
+        }
+        let modified = |name: &str| fs::metadata(root.join(name)).unwrap().modified().unwrap();
 
-    // Swift and Go preserved, Python lowercased
-    assert!(
-        result.contains("Using Swift, Go, and python"),
-        "Swift and Go should be proper nouns, Python should be common noun"
-    );
-}
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &[
+                "--no-config",
+                "--write",
+                "--verbose",
+                "--line-ending",
+                "crlf",
+                root.to_str().unwrap(),
+            ],
+            None,
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(modified("crlf.md"), past);
+        assert_ne!(modified("lf.md"), past);
+        assert_ne!(modified("no-newline.md"), past);
+        assert_eq!(
+            fs::read_to_string(root.join("no-newline.md")).unwrap(),
+            "Text.\r\n"
+        );
+        assert!(!stdout.contains("crlf.md"), "stdout: {}", stdout);
+        assert!(stdout.contains("lf.md"), "stdout: {}", stdout);
+        assert!(stderr.contains("crlf.md: unchanged"), "stderr: {}", stderr);
+        assert!(
+            stderr.contains("lf.md: changed (8 lines differ)"),
+            "stderr: {}",
+            stderr
+        );
+        assert!(
+            stderr.contains("no-newline.md: changed (2 lines differ)"),
+            "stderr: {}",
+            stderr
+        );
+    }
 
-// ============================================================================
-// Code block formatter integration tests
-// ============================================================================
+    /// Create a tree with a binary file masquerading as Markdown and a good
+    /// (but unformatted) file.
+    fn create_error_fixture(root: &std::path::Path) {
+        use std::fs;
 
-#[cfg(not(target_arch = "wasm32"))]
-mod code_formatter_tests {
-    use hongdown::{CodeFormatter, Options, format, format_with_warnings};
-    use std::collections::HashMap;
+        fs::write(
+            root.join("binary.md"),
+            [0x89, b'P', b'N', b'G', 0xff, 0x00, 0xfe],
+        )
+        .unwrap();
+        fs::write(root.join("good.md"), "# Good\n").unwrap();
+    }
 
-    /// Test code formatter with a real external command (cat).
+    /// Test that a file error does not abort the run, and exits with 2.
     #[test]
-    fn test_code_formatter_integration() {
-        let mut formatters = HashMap::new();
-        formatters.insert(
-            "text".to_string(),
-            CodeFormatter {
-                command: vec!["cat".to_string()],
-                timeout_secs: 5,
-            },
-        );
+    fn test_file_error_isolated_in_write_mode() {
+        use std::fs;
+        use tempfile::TempDir;
 
-        let options = Options {
-            code_formatters: formatters,
-            ..Options::default()
-        };
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        create_error_fixture(root);
 
-        let input = "~~~~ text\nhello world\n~~~~\n";
-        let result = format(input, &options).unwrap();
-        assert_eq!(result, "~~~~ text\nhello world\n~~~~\n");
+        let (_stdout, stderr, exit_code) =
+            run_hongdown(&["--no-config", "--write", root.to_str().unwrap()], None);
+        assert_eq!(exit_code, 2, "stderr: {}", stderr);
+        assert!(stderr.contains("binary.md"), "stderr: {}", stderr);
+        assert_eq!(
+            fs::read_to_string(root.join("good.md")).unwrap(),
+            "Good\n====\n"
+        );
     }
 
-    /// Test code formatter transformation with tr command.
+    /// Test that file errors take precedence over unformatted files in
+    /// check mode, and that every file is still reported.
     #[test]
-    fn test_code_formatter_transforms() {
-        let mut formatters = HashMap::new();
-        formatters.insert(
-            "upper".to_string(),
-            CodeFormatter {
-                command: vec!["tr".to_string(), "a-z".to_string(), "A-Z".to_string()],
-                timeout_secs: 5,
-            },
-        );
+    fn test_file_error_isolated_in_check_mode() {
+        use tempfile::TempDir;
 
-        let options = Options {
-            code_formatters: formatters,
-            ..Options::default()
-        };
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        create_error_fixture(root);
+
+        let (_stdout, stderr, exit_code) =
+            run_hongdown(&["--no-config", "--check", root.to_str().unwrap()], None);
+        assert_eq!(exit_code, 2, "stderr: {}", stderr);
+        assert!(stderr.contains("binary.md"), "stderr: {}", stderr);
+        assert!(
+            stderr.contains("good.md: not formatted"),
+            "stderr: {}",
+            stderr
+        );
+    }
+
+    /// Test that stdout mode keeps printing later files after an error.
+    #[test]
+    fn test_file_error_isolated_in_stdout_mode() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        create_error_fixture(root);
+
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &[
+                "--no-config",
+                root.join("binary.md").to_str().unwrap(),
+                root.join("good.md").to_str().unwrap(),
+            ],
+            None,
+        );
+        assert_eq!(exit_code, 2, "stderr: {}", stderr);
+        assert_eq!(stdout, "Good\n====\n");
+    }
+
+    /// Test that usage and configuration errors exit with 3.
+    #[test]
+    fn test_usage_error_exit_code() {
+        let (_stdout, _stderr, exit_code) = run_hongdown(&["--no-such-flag"], None);
+        assert_eq!(exit_code, 3);
+
+        let (_stdout, _stderr, exit_code) =
+            run_hongdown(&["--config", "/nonexistent/hongdown.toml", "x.md"], None);
+        assert_eq!(exit_code, 3);
+
+        let (stdout, _stderr, exit_code) = run_hongdown(&["--version"], None);
+        assert_eq!(exit_code, 0);
+        assert!(stdout.starts_with("hongdown "));
+    }
+
+    /// Create a fixture tree with a mix of formatted and unformatted files.
+    fn create_list_different_fixture(root: &std::path::Path) {
+        use std::fs;
+
+        fs::create_dir_all(root.join("docs/nested")).unwrap();
+        fs::write(root.join("formatted.md"), "Formatted\n=========\n").unwrap();
+        fs::write(root.join("unformatted.md"), "# Unformatted\n").unwrap();
+        fs::write(root.join("docs/ok.md"), "Text.\n").unwrap();
+        fs::write(root.join("docs/nested/bad.md"), "*   Item\n").unwrap();
+    }
+
+    /// Test --list-different prints only the relative paths of files that
+    /// would change.
+    #[test]
+    fn test_list_different() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        create_list_different_fixture(temp_dir.path());
+
+        let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .args(["--no-config", "--list-different", "."])
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let mut lines: Vec<&str> = stdout.lines().collect();
+        lines.sort();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(lines, ["docs/nested/bad.md", "unformatted.md"]);
+    }
+
+    /// Test --list-different exits 0 and prints nothing when all files are
+    /// formatted.
+    #[test]
+    fn test_list_different_all_formatted() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        create_list_different_fixture(temp_dir.path());
+
+        let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .args(["--no-config", "-l", "formatted.md", "docs/ok.md"])
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        assert!(output.stdout.is_empty());
+    }
+
+    /// Test --list-different with --stdin-filelist, keeping input order.
+    #[test]
+    fn test_list_different_with_stdin_filelist() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        create_list_different_fixture(temp_dir.path());
+        let absolute = temp_dir.path().join("docs/nested/bad.md");
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .args(["--no-config", "--list-different", "--stdin-filelist"])
+            .current_dir(temp_dir.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let list = format!(
+            "unformatted.md\n\nformatted.md\n{}\n./docs/ok.md\n",
+            absolute.canonicalize().unwrap().display()
+        );
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(list.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "unformatted.md\ndocs/nested/bad.md\n"
+        );
+    }
+
+    #[test]
+    fn test_report_requires_write_or_check() {
+        let (stdout, stderr, exit_code) =
+            run_hongdown(&["--report", "json", "--stdin"], Some("Text.\n"));
+        assert_ne!(exit_code, 0);
+        assert!(stdout.is_empty());
+        assert!(stderr.contains("--report requires"), "stderr: {}", stderr);
+    }
+
+    #[test]
+    fn test_stats() {
+        let input =
+            "Title\n=====\n\nSome *prose* and [a link](x.md).\n\n## 한국어\n\n~~~~\ncode\n~~~~\n";
+        let (stdout, stderr, exit_code) = run_hongdown(&["--stats", "-"], Some(input));
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(
+            stdout,
+            "<stdin>:\n\
+             \x20 Words         9\n\
+             \x20 Headings      h1 1, h2 1\n\
+             \x20 Links         1\n\
+             \x20 Images        0\n\
+             \x20 Code blocks   1\n\
+             \x20 Tables        0\n\
+             \x20 Reading time  1 min\n\
+             \x20 Longest line  32\n"
+        );
+    }
+
+    #[test]
+    fn test_stats_report_json() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("a.md");
+        fs::write(&path, "# Title\n\n![Alt](a.png)\n\n| a |\n|---|\n| b |\n").unwrap();
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &["--stats", "--report", "json", path.to_str().unwrap()],
+            None,
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        let report: hongdown::report::StatsReport =
+            serde_json::from_str(&stdout).expect("stdout should be a single JSON document");
+        assert_eq!(report.version, hongdown::report::REPORT_VERSION);
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].path, path.display().to_string());
+        let stats = &report.files[0].stats;
+        assert_eq!(stats.words, 3);
+        assert_eq!(stats.headings, [1, 0, 0, 0, 0, 0]);
+        assert_eq!((stats.images, stats.tables), (1, 1));
+    }
+
+    /// Test that --migrate-config writes a .hongdown.toml next to the
+    /// Prettier configuration, and won't overwrite one.
+    #[test]
+    fn test_migrate_config() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join(".prettierrc.json"),
+            r#"{"printWidth": 100, "proseWrap": "always", "useTabs": true}"#,
+        )
+        .unwrap();
+        let sub = temp_dir.path().join("docs");
+        fs::create_dir(&sub).unwrap();
+
+        let migrate = || {
+            Command::new(env!("CARGO_BIN_EXE_hongdown"))
+                .args(["--migrate-config", "prettier"])
+                .current_dir(&sub)
+                .output()
+                .unwrap()
+        };
+        let output = migrate();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+        assert!(
+            stderr.contains("printWidth -> line_width = 100"),
+            "{}",
+            stderr
+        );
+        assert!(
+            stderr.contains("Not translated:\n  useTabs: "),
+            "{}",
+            stderr
+        );
+        let config = fs::read_to_string(temp_dir.path().join(".hongdown.toml")).unwrap();
+        assert!(config.contains("#  -  useTabs: "), "{}", config);
+        assert!(
+            config.contains("\nline_width = 100\nwrap = \"width\"\n"),
+            "{}",
+            config
+        );
+
+        let output = migrate();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    }
+
+    /// Test that --no-embedded-formatters leaves code blocks as they are even
+    /// though formatters are configured for them.
+    #[test]
+    fn test_no_embedded_formatters() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let config_path = temp_dir.path().join(".hongdown.toml");
+        fs::write(
+            &config_path,
+            "[code_block.formatters]\nupper = [\"tr\", \"a-z\", \"A-Z\"]\n",
+        )
+        .expect("Failed to write config");
+        let config = config_path.to_str().unwrap();
+        let input = "~~~~ upper\nhello\n~~~~\n";
+
+        let (stdout, _stderr, exit_code) = run_hongdown(&["--config", config, "-"], Some(input));
+        assert_eq!(exit_code, 0);
+        assert_eq!(stdout, "~~~~ upper\nHELLO\n~~~~\n");
+
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &["--config", config, "--no-embedded-formatters", "-"],
+            Some(input),
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, input);
+    }
+
+    #[test]
+    fn test_list_code_blocks() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let input = "# Title\n\n    indented\n\n - Item:\n\n   ~~~~ rust\n   fn main() {}\n   ~~~~\n\n\
+                     > ```sh\n> echo hi\n> ```\n";
+        let (stdout, stderr, exit_code) = run_hongdown(&["--list-code-blocks", "-"], Some(input));
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(
+            stdout,
+            "<stdin>:3-3\t\n<stdin>:7-9\trust\n<stdin>:11-13\tsh\n"
+        );
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("doc.md");
+        fs::write(&path, input).unwrap();
+        let dir = temp_dir.path().join("blocks");
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &[
+                "--list-code-blocks",
+                "--lang",
+                "rust",
+                "--code-block-dir",
+                dir.to_str().unwrap(),
+                path.to_str().unwrap(),
+            ],
+            None,
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        let written = dir.join("1-doc-7.rust");
+        assert_eq!(
+            stdout,
+            format!("{}:7-9\trust\t{}\n", path.display(), written.display())
+        );
+        assert_eq!(fs::read_to_string(&written).unwrap(), "fn main() {}\n");
+    }
+
+    #[test]
+    fn test_stats_conflicts_with_check() {
+        let (_, stderr, exit_code) = run_hongdown(&["--stats", "--check", "-"], Some("Text.\n"));
+        assert_eq!(exit_code, 3);
+        assert!(stderr.contains("cannot be used with"), "stderr: {}", stderr);
+    }
+
+    /// Write a config whose `sh` code formatter logs each invocation, so
+    /// that tests can count how many files were actually formatted.
+    #[cfg(unix)]
+    fn write_logging_config(dir: &std::path::Path, line_width: usize) -> std::path::PathBuf {
+        use std::fs;
+
+        let log_path = dir.join("formatter.log");
+        let config_path = dir.join(".hongdown.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "line_width = {}\n\n[code_block.formatters]\nsh = [\"sh\", \"-c\", \"echo run >> '{}'; cat\"]\n",
+                line_width,
+                log_path.display()
+            ),
+        )
+        .expect("Failed to write config");
+        config_path
+    }
+
+    /// Count the formatter invocations logged by [`write_logging_config`].
+    #[cfg(unix)]
+    fn count_formatter_runs(dir: &std::path::Path) -> usize {
+        std::fs::read_to_string(dir.join("formatter.log"))
+            .map(|log| log.lines().count())
+            .unwrap_or(0)
+    }
+
+    /// Test that a second `--cache` run over an unchanged tree formats nothing.
+    #[test]
+    #[cfg(unix)]
+    fn test_cache_skips_unchanged_files() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let config_path = write_logging_config(temp_dir.path(), 80);
+        let cache_path = temp_dir.path().join("cache.json");
+        let docs = temp_dir.path().join("docs");
+        fs::create_dir(&docs).expect("Failed to create docs dir");
+        fs::write(docs.join("a.md"), "# A\n\n~~~~ sh\necho a\n~~~~\n").unwrap();
+        fs::write(docs.join("b.md"), "B\n=\n\n~~~~ sh\necho b\n~~~~\n").unwrap();
+
+        let args = [
+            "--write",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--cache",
+            cache_path.to_str().unwrap(),
+            docs.to_str().unwrap(),
+        ];
+
+        let (stdout, stderr, exit_code) = run_hongdown(&args, None);
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert!(stdout.contains("a.md"));
+        assert_eq!(count_formatter_runs(temp_dir.path()), 2);
+        assert!(cache_path.exists(), "Cache file should be written");
+
+        let (stdout, stderr, exit_code) = run_hongdown(&args, None);
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert!(stdout.is_empty(), "got stdout: {}", stdout);
+        assert_eq!(
+            count_formatter_runs(temp_dir.path()),
+            2,
+            "Second run should not format any file"
+        );
+
+        // Editing one file only reformats that file
+        fs::write(docs.join("b.md"), "B\n=\n\n~~~~ sh\necho changed\n~~~~\n").unwrap();
+        let (_stdout, stderr, exit_code) = run_hongdown(&args, None);
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(count_formatter_runs(temp_dir.path()), 3);
+    }
+
+    /// Test that changing the configuration invalidates the cache.
+    #[test]
+    #[cfg(unix)]
+    fn test_cache_invalidated_by_config_change() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let config_path = write_logging_config(temp_dir.path(), 80);
+        let cache_path = temp_dir.path().join("cache.json");
+        let doc = temp_dir.path().join("doc.md");
+        fs::write(&doc, "Doc\n===\n\n~~~~ sh\necho doc\n~~~~\n").unwrap();
+
+        let args = [
+            "--check",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--cache",
+            cache_path.to_str().unwrap(),
+            doc.to_str().unwrap(),
+        ];
+
+        run_hongdown(&args, None);
+        run_hongdown(&args, None);
+        assert_eq!(count_formatter_runs(temp_dir.path()), 1);
+
+        write_logging_config(temp_dir.path(), 72);
+        let (_stdout, stderr, exit_code) = run_hongdown(&args, None);
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(count_formatter_runs(temp_dir.path()), 2);
+    }
+
+    /// Test that a corrupt cache produces a warning and is rebuilt.
+    #[test]
+    fn test_cache_corrupt_file_warns() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let cache_path = temp_dir.path().join("cache.json");
+        fs::write(&cache_path, "{ not json").unwrap();
+        let doc = temp_dir.path().join("doc.md");
+        fs::write(&doc, "Doc\n===\n\nContent.\n").unwrap();
+
+        let (_stdout, stderr, exit_code) = run_hongdown(
+            &[
+                "--check",
+                "--cache",
+                cache_path.to_str().unwrap(),
+                doc.to_str().unwrap(),
+            ],
+            None,
+        );
+        assert_eq!(exit_code, 0);
+        assert!(
+            stderr.contains("corrupt cache file"),
+            "got stderr: {}",
+            stderr
+        );
+        let rebuilt = fs::read_to_string(&cache_path).unwrap();
+        assert!(rebuilt.contains("doc.md"), "got cache: {}", rebuilt);
+    }
+
+    /// Test that `--cache-clear` removes the cache file.
+    #[test]
+    fn test_cache_clear_removes_file() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let cache_path = temp_dir.path().join("cache.json");
+        fs::write(&cache_path, "{}").unwrap();
+
+        let (_stdout, stderr, exit_code) = run_hongdown(
+            &["--cache-clear", "--cache", cache_path.to_str().unwrap()],
+            None,
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert!(!cache_path.exists());
+    }
+
+    /// Create a tree whose `docs/index.md` has one broken relative link and
+    /// one broken anchor, along with links that are fine.
+    fn create_links_fixture(root: &std::path::Path) {
+        use std::fs;
+
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("README.md"), "Readme\n======\n").unwrap();
+        fs::write(
+            root.join("docs/index.md"),
+            "Index\n=====\n\n\
+             See the [readme](../README.md), [usage](#usage), and\n\
+             [setup](setup.md).\n\n\n\
+             Usage\n-----\n\n\
+             Jump to [the top](#index) or [nowhere](#nowhere).\n",
+        )
+        .unwrap();
+    }
+
+    /// Test that `--check-links` reports the broken links with their lines
+    /// and columns, and that they are only warnings.
+    #[test]
+    fn test_check_links() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        create_links_fixture(root);
+        let index = root.join("docs/index.md");
+
+        let (_stdout, stderr, exit_code) = run_hongdown(
+            &[
+                "--no-config",
+                "--check",
+                "--check-links",
+                index.to_str().unwrap(),
+            ],
+            None,
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        let name = index.display();
+        assert!(
+            stderr.contains(&format!(
+                "{}:5:1: warning: broken relative link: setup.md",
+                name
+            )),
+            "stderr: {}",
+            stderr
+        );
+        assert!(
+            stderr.contains(&format!(
+                "{}:11:30: warning: undefined link fragment: #nowhere",
+                name
+            )),
+            "stderr: {}",
+            stderr
+        );
+        assert_eq!(stderr.matches("warning:").count(), 2, "stderr: {}", stderr);
+    }
+
+    /// Test that relative links are not checked without `--check-links`.
+    #[test]
+    fn test_check_links_off_by_default() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        create_links_fixture(root);
+        let index = root.join("docs/index.md");
+
+        let (_stdout, stderr, exit_code) =
+            run_hongdown(&["--no-config", "--check", index.to_str().unwrap()], None);
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert!(!stderr.contains("setup.md"), "stderr: {}", stderr);
+    }
+
+    /// Test that `lint.links` in the configuration file enables the link
+    /// checks, and that `--deny-warnings` makes them fail the run.
+    #[test]
+    fn test_check_links_config_and_deny_warnings() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        create_links_fixture(root);
+        let config = root.join(".hongdown.toml");
+        fs::write(&config, "[lint]\nlinks = true\n").unwrap();
+        let index = root.join("docs/index.md");
+
+        let (_stdout, stderr, exit_code) = run_hongdown(
+            &[
+                "--config",
+                config.to_str().unwrap(),
+                "--check",
+                "--deny-warnings",
+                index.to_str().unwrap(),
+            ],
+            None,
+        );
+        assert_eq!(exit_code, 1, "stderr: {}", stderr);
+        assert!(
+            stderr.contains("broken relative link: setup.md"),
+            "stderr: {}",
+            stderr
+        );
+    }
+
+    /// Test that `--deny-warnings` passes when there are no warnings.
+    #[test]
+    fn test_deny_warnings_without_warnings() {
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &["--no-config", "--deny-warnings", "--stdin"],
+            Some("Title\n=====\n"),
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, "Title\n=====\n");
+    }
+
+    /// Test that a block nested too deeply to format is written as it is,
+    /// with a warning, and that `--timeout` leaves files that format in
+    /// time alone.
+    #[test]
+    fn test_deeply_nested_input_with_timeout() {
+        let input = format!("{}deep\n", "> ".repeat(100_000));
+        let (stdout, stderr, exit_code) =
+            run_hongdown(&["--no-config", "--timeout", "60", "--stdin"], Some(&input));
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, input);
+        assert!(
+            stderr.contains("<stdin>:1: warning: nested more than 200 levels deep"),
+            "stderr: {}",
+            stderr
+        );
+    }
+
+    /// Test that `lint.invisible_characters` reports invisible characters
+    /// and leaves them in the output.
+    #[test]
+    fn test_lint_invisible_characters() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = temp_dir.path().join(".hongdown.toml");
+        fs::write(&config, "[lint]\ninvisible_characters = true\n").unwrap();
+        let input = "Title\n=====\n\nשלום\u{200F} world\n";
+
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &["--config", config.to_str().unwrap(), "--stdin"],
+            Some(input),
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, input);
+        assert!(
+            stderr.contains(":4:9: warning: invisible character U+200F RIGHT-TO-LEFT MARK"),
+            "stderr: {}",
+            stderr
+        );
+
+        let (_stdout, stderr, _exit_code) = run_hongdown(&["--no-config", "--stdin"], Some(input));
+        assert!(
+            !stderr.contains("invisible character"),
+            "stderr: {}",
+            stderr
+        );
+    }
+
+    /// Test that the fixes of `fix` are reported as notices, which
+    /// `--deny-warnings` doesn't fail on.
+    #[test]
+    fn test_fix_notices() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = temp_dir.path().join(".hongdown.toml");
+        fs::write(&config, "fix = [\"heading-increment\"]\n").unwrap();
+
+        let (stdout, stderr, exit_code) = run_hongdown(
+            &[
+                "--config",
+                config.to_str().unwrap(),
+                "--deny-warnings",
+                "--stdin",
+            ],
+            Some("Title\n=====\n\n### Usage\n"),
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, "Title\n=====\n\nUsage\n-----\n");
+        assert!(
+            stderr.contains(":4: info: changed heading level 3 to 2 (heading-increment)"),
+            "stderr: {}",
+            stderr
+        );
+    }
+
+    /// Test that `lint.code_language` reports code blocks without
+    /// a language, which are given `code_block.default_language` only if
+    /// the `code-language` fix is on.
+    #[test]
+    fn test_lint_code_language() {
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = temp_dir.path().join(".hongdown.toml");
+        let input = "Title\n=====\n\n~~~~\ncode\n~~~~\n";
+        let run = |toml: &str| {
+            fs::write(&config, toml).unwrap();
+            run_hongdown(
+                &["--config", config.to_str().unwrap(), "--stdin"],
+                Some(input),
+            )
+        };
+
+        let (stdout, stderr, exit_code) = run("[code_block]\ndefault_language = \"text\"\n");
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, "Title\n=====\n\n~~~~ text\ncode\n~~~~\n");
+        assert!(!stderr.contains("code-language"), "stderr: {}", stderr);
+
+        let (stdout, stderr, exit_code) =
+            run("[code_block]\ndefault_language = \"text\"\n\n[lint]\ncode_language = true\n");
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, input);
+        assert!(
+            stderr.contains(":4:1: warning: code block has no language (code-language)"),
+            "stderr: {}",
+            stderr
+        );
+
+        let (stdout, stderr, exit_code) = run(
+            "fix = [\"code-language\"]\n\n[code_block]\ndefault_language = \"text\"\n\n\
+             [lint]\ncode_language = true\n",
+        );
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, "Title\n=====\n\n~~~~ text\ncode\n~~~~\n");
+        assert!(
+            stderr.contains(":4: info: gave code block the language text (code-language)"),
+            "stderr: {}",
+            stderr
+        );
+        assert!(!stderr.contains("warning"), "stderr: {}", stderr);
+    }
+}
+
+/// Test proper nouns directive in sentence case.
+#[test]
+fn test_sentence_case_proper_nouns_directive() {
+    let input = r#"<!-- hongdown-proper-nouns: Swift, Go -->
+
+# Using Swift And Go Programming
+
+Some content.
+"#;
+
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
+    let result = format(input, &options).unwrap();
+
+    // Swift and Go should be preserved as proper nouns
+    assert!(
+        result.contains("Using Swift and Go programming"),
+        "Swift and Go should be preserved as proper nouns via directive"
+    );
+}
+
+/// Test common nouns directive in sentence case.
+#[test]
+fn test_sentence_case_common_nouns_directive() {
+    let input = r#"<!-- hongdown-common-nouns: Python, JavaScript -->
+
+# Learning Python And JavaScript Programming
+
+Some content.
+"#;
+
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
+    let result = format(input, &options).unwrap();
+
+    // Python and JavaScript should NOT be preserved (treated as common nouns)
+    assert!(
+        result.contains("Learning python and javascript programming"),
+        "Python and JavaScript should be lowercased via common-nouns directive"
+    );
+}
+
+/// Test both directives together.
+#[test]
+fn test_sentence_case_both_directives() {
+    let input = r#"<!-- hongdown-proper-nouns: Swift, Go -->
+<!-- hongdown-common-nouns: Python -->
+
+# Using Swift, Go, And Python
+
+Some content.
+"#;
+
+    let options = Options {
+        heading_sentence_case: true,
+        ..Options::default()
+    };
+    let result = format(input, &options).unwrap();
+
+    // Swift and Go preserved, Python lowercased
+    assert!(
+        result.contains("Using Swift, Go, and python"),
+        "Swift and Go should be proper nouns, Python should be common noun"
+    );
+}
+
+// ============================================================================
+// Pathological input tests
+// ============================================================================
+
+mod pathological_input_tests {
+    use std::time::{Duration, Instant};
+
+    use hongdown::{Options, format_with_warnings};
+
+    /// How long any of these inputs may take to format, even in a debug
+    /// build; they take well under a second in a release build.
+    const BOUND: Duration = Duration::from_secs(60);
+
+    /// Format `input` with the default options, asserting that it takes no
+    /// longer than [`BOUND`], and return the output and warning messages.
+    fn format_bounded(input: &str) -> (String, Vec<String>) {
+        let start = Instant::now();
+        let result = format_with_warnings(input, &Options::default()).unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed < BOUND, "formatting took {:?}", elapsed);
+        let messages = result.warnings.into_iter().map(|w| w.message).collect();
+        (result.output, messages)
+    }
+
+    /// Test that 100,000 nested block quotes are written as they are instead
+    /// of overflowing the stack.
+    #[test]
+    fn test_deeply_nested_block_quotes() {
+        let deep = format!("{}deep\n", "> ".repeat(100_000));
+        let input = format!("Before\n======\n\n{}\nAfter.\n", deep);
+        let (output, warnings) = format_bounded(&input);
+        assert_eq!(output, input);
+        assert_eq!(
+            warnings,
+            ["nested more than 200 levels deep, so the block is left as it is"]
+        );
+        // Those nested less deeply are formatted, without overflowing the
+        // smaller stack of a thread
+        let shallow = format!("{}shallow\n", "> ".repeat(190));
+        let (output, warnings) = format_bounded(&shallow);
+        assert_eq!(output, shallow);
+        assert!(warnings.is_empty());
+    }
+
+    /// Test that deeply nested lists are written as they are, and that those
+    /// nested less deeply are formatted as usual.
+    #[test]
+    fn test_deeply_nested_lists() {
+        let list = |depth: usize| {
+            (0..depth)
+                .map(|i| format!("{}- item\n", "  ".repeat(i)))
+                .collect::<String>()
+        };
+        let (output, warnings) = format_bounded(&list(5_000));
+        assert_eq!(output, list(5_000));
+        assert_eq!(warnings.len(), 1);
+        let (output, warnings) = format_bounded(&list(3));
+        assert_eq!(output, " -  item\n     -  item\n         -  item\n");
+        assert!(warnings.is_empty());
+    }
+
+    /// Test that deeply nested emphasis, whose delimiters take time
+    /// exponential in its depth to pick, is written as it is.
+    #[test]
+    fn test_deeply_nested_emphasis() {
+        let input = format!("{}x{}\n", "*".repeat(1_000), "*".repeat(1_000));
+        let (output, warnings) = format_bounded(&input);
+        assert_eq!(output, input);
+        assert_eq!(
+            warnings,
+            ["emphasis nested more than 10 levels deep, so the block is left as it is"]
+        );
+    }
+
+    /// Test that a paragraph of 5 MB on one line is wrapped.
+    #[test]
+    fn test_huge_single_line_paragraph() {
+        let input = "Lorem ipsum dolor sit amet. ".repeat(5 * 1024 * 1024 / 28);
+        let (output, warnings) = format_bounded(&input);
+        assert!(warnings.is_empty());
+        assert!(output.lines().all(|line| line.len() <= 80));
+        assert_eq!(
+            output.split_whitespace().count(),
+            input.split_whitespace().count()
+        );
+    }
+
+    /// Test that a table of 100,000 cells is formatted.
+    #[test]
+    fn test_huge_table() {
+        let columns = 100;
+        let mut input = String::new();
+        input.push_str(&"| h ".repeat(columns));
+        input.push_str("|\n");
+        input.push_str(&"|---".repeat(columns));
+        input.push_str("|\n");
+        for row in 0..1_000 {
+            input.push_str(&format!("| {} ", row).repeat(columns));
+            input.push_str("|\n");
+        }
+        let (output, warnings) = format_bounded(&input);
+        assert!(warnings.is_empty());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1_002);
+        assert!(lines[2].starts_with("| 0   | 0   |"));
+        assert!(lines[1_001].starts_with("| 999 | 999 |"));
+    }
+}
+
+// ============================================================================
+// Code block formatter integration tests
+// ============================================================================
+
+#[cfg(not(target_arch = "wasm32"))]
+mod code_formatter_tests {
+    use hongdown::{CodeFormatter, Options, format, format_with_warnings};
+    use std::collections::HashMap;
+
+    /// Test code formatter with a real external command (cat).
+    #[test]
+    fn test_code_formatter_integration() {
+        let mut formatters = HashMap::new();
+        formatters.insert(
+            "text".to_string(),
+            CodeFormatter {
+                command: vec!["cat".to_string()],
+                timeout_secs: 5,
+            },
+        );
+
+        let options = Options {
+            code_formatters: formatters,
+            ..Options::default()
+        };
+
+        let input = "~~~~ text\nhello world\n~~~~\n";
+        let result = format(input, &options).unwrap();
+        assert_eq!(result, "~~~~ text\nhello world\n~~~~\n");
+    }
+
+    /// Test code formatter transformation with tr command.
+    #[test]
+    fn test_code_formatter_transforms() {
+        let mut formatters = HashMap::new();
+        formatters.insert(
+            "upper".to_string(),
+            CodeFormatter {
+                command: vec!["tr".to_string(), "a-z".to_string(), "A-Z".to_string()],
+                timeout_secs: 5,
+            },
+        );
+
+        let options = Options {
+            code_formatters: formatters,
+            ..Options::default()
+        };
 
         let input = "~~~~ upper\nhello world\n~~~~\n";
         let result = format(input, &options).unwrap();
@@ -748,6 +2051,62 @@ fn main() {}
         let result = format(input, &options).unwrap();
         assert_eq!(result, "~~~~ text\nHELLO\n~~~~\n");
     }
+
+    /// Options with `script` as the formatter for `fake`, written to a file
+    /// in `dir` and run with `sh`.
+    fn fake_formatter(dir: &std::path::Path, script: &str) -> Options {
+        let path = dir.join("fake-formatter.sh");
+        std::fs::write(&path, script).unwrap();
+        let mut formatters = HashMap::new();
+        formatters.insert(
+            "fake".to_string(),
+            CodeFormatter {
+                command: vec!["sh".to_string(), path.to_str().unwrap().to_string()],
+                timeout_secs: 5,
+            },
+        );
+        Options {
+            code_formatters: formatters,
+            ..Options::default()
+        }
+    }
+
+    /// Test that a formatter that fails leaves the code as it is, with a
+    /// warning at the line of the code block that has its stderr.
+    #[test]
+    fn test_code_formatter_failure_stderr() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let options = fake_formatter(
+            temp_dir.path(),
+            "cat >/dev/null\necho 'fake: unexpected token' >&2\nexit 2\n",
+        );
+
+        let input = "Some code:\n\n~~~~ fake\nlet x = ;\n~~~~\n";
+        let result = format_with_warnings(input, &options).unwrap();
+        assert_eq!(result.output, input);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].line, 3);
+        assert!(
+            result.warnings[0]
+                .message
+                .contains("fake: unexpected token"),
+            "{}",
+            result.warnings[0].message
+        );
+    }
+
+    /// Test that the fence is made longer than any line of tildes the
+    /// formatter writes.
+    #[test]
+    fn test_code_formatter_output_with_fence() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let options = fake_formatter(temp_dir.path(), "cat\necho '~~~~~'\n");
+
+        let input = "~~~~ fake\ncode\n~~~~\n";
+        let result = format_with_warnings(input, &options).unwrap();
+        assert!(result.warnings.is_empty());
+        assert_eq!(result.output, "~~~~~~ fake\ncode\n~~~~~\n~~~~~~\n");
+    }
 }
 
 // ============================================================================
@@ -861,6 +2220,65 @@ line_width = 40
         );
     }
 
+    /// Test that the search for a project config stops at the root of the
+    /// repository, that the user configs come under the project config, and
+    /// that --no-user-config leaves them out.
+    #[test]
+    fn test_user_config_and_repository_boundary() {
+        let temp_dir = TempDir::new().unwrap();
+        let home = temp_dir.path().join("home");
+        let xdg = temp_dir.path().join("xdg");
+        let work = home.join("work");
+        let repo = work.join("repo");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(xdg.join("hongdown")).unwrap();
+        create_config(&home, "line_width = 30\n\n[heading]\nsetext_h1 = false\n");
+        fs::write(xdg.join("hongdown/config.toml"), "line_width = 40\n").unwrap();
+        // Above the repository, so not the repository's
+        create_config(&work, "line_width = 120\n");
+        let paragraph = "word ".repeat(40);
+        create_markdown_file(&repo, "doc.md", &format!("# Title\n\n{}\n", paragraph));
+
+        let run = |args: &[&str]| {
+            let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+                .args(args)
+                .arg("doc.md")
+                .current_dir(&repo)
+                .env("HOME", &home)
+                .env("XDG_CONFIG_HOME", &xdg)
+                .output()
+                .expect("Failed to execute hongdown");
+            assert!(
+                output.status.success(),
+                "hongdown failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let widest = |output: &str| output.lines().map(str::len).max().unwrap();
+
+        // The user configs, the XDG one over ~/.hongdown.toml
+        let output = run(&[]);
+        assert!(output.starts_with("# Title\n"), "{}", output);
+        assert_eq!(widest(&output), 39);
+
+        // The project config over the user configs
+        create_config(&repo, "line_width = 60\n");
+        let output = run(&[]);
+        assert!(output.starts_with("# Title\n"), "{}", output);
+        assert_eq!(widest(&output), 59);
+
+        let output = run(&["--no-user-config"]);
+        assert!(output.starts_with("Title\n=====\n"), "{}", output);
+        assert_eq!(widest(&output), 59);
+
+        // Neither the user configs nor the one above the repository
+        fs::remove_file(repo.join(".hongdown.toml")).unwrap();
+        let output = run(&["--no-user-config"]);
+        assert!(output.starts_with("Title\n=====\n"), "{}", output);
+        assert_eq!(widest(&output), 79);
+    }
+
     /// Test that explicit --config bypasses cascading.
     #[test]
     fn test_explicit_config_bypasses_cascading() {
@@ -985,4 +2403,391 @@ unordered_marker = "*"
             "List should use * marker"
         );
     }
+
+    /// Helper to run hongdown with content piped to stdin, from `cwd`.
+    fn run_hongdown_stdin(args: &[&str], cwd: &Path, input: &str) -> (String, String, i32) {
+        use std::io::Write;
+        use std::process::Stdio;
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .args(args)
+            .current_dir(cwd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to execute hongdown");
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        let output = child.wait_with_output().unwrap();
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+            output.status.code().unwrap_or(-1),
+        )
+    }
+
+    /// Test that `--stdin-filepath` discovers a config two directories above
+    /// the (nonexistent) path, regardless of the working directory.
+    #[test]
+    fn test_stdin_filepath_discovers_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        let cwd = temp_dir.path().join("elsewhere");
+        fs::create_dir_all(project.join("docs/guide")).unwrap();
+        fs::create_dir_all(&cwd).unwrap();
+        create_config(&project, "no_inherit = true\nline_width = 40\n");
+
+        let fake_path = project.join("docs/guide/page.md");
+        let input =
+            "This is a very long line that should definitely be wrapped at 40 characters.\n";
+        let (stdout, stderr, exit_code) = run_hongdown_stdin(
+            &["--stdin-filepath", fake_path.to_str().unwrap()],
+            &cwd,
+            input,
+        );
+
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert!(!fake_path.exists(), "The fake path must not be created");
+        assert!(stdout.lines().count() > 1, "got: {}", stdout);
+        assert!(
+            stdout.lines().all(|line| line.len() <= 40),
+            "got: {}",
+            stdout
+        );
+    }
+
+    /// Test that piped stdin is formatted when no files are given.
+    #[test]
+    fn test_piped_stdin_without_arguments() {
+        let temp_dir = TempDir::new().unwrap();
+        create_config(temp_dir.path(), "no_inherit = true\n");
+
+        let (stdout, stderr, exit_code) =
+            run_hongdown_stdin(&[], temp_dir.path(), "# Hello\n\nWorld\n");
+
+        assert_eq!(exit_code, 0, "stderr: {}", stderr);
+        assert_eq!(stdout, "Hello\n=====\n\nWorld\n");
+    }
+
+    /// Test that a configuration error writes nothing to stdout and exits
+    /// nonzero, so that editors keep the original buffer.
+    #[test]
+    fn test_stdin_filepath_error_writes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        create_config(
+            temp_dir.path(),
+            "no_inherit = true\n\n[punctuation]\nen_dash = \"--\"\nem_dash = \"--\"\n",
+        );
+
+        let fake_path = temp_dir.path().join("page.md");
+        let (stdout, stderr, exit_code) = run_hongdown_stdin(
+            &["--stdin-filepath", fake_path.to_str().unwrap()],
+            temp_dir.path(),
+            "# Hello\n",
+        );
+
+        assert_ne!(exit_code, 0);
+        assert!(stdout.is_empty(), "got stdout: {}", stdout);
+        assert!(stderr.contains("Error"), "got stderr: {}", stderr);
+    }
+
+    /// Plant decoy configuration files in the project and in the user's
+    /// home directory, which would make headings ATX-style if loaded.
+    fn plant_decoy_configs(root: &Path) -> std::path::PathBuf {
+        let home = root.join("home");
+        fs::create_dir_all(home.join(".config/hongdown")).unwrap();
+        let decoy = "line_width = 20\n\n[heading]\nsetext_h1 = false\n";
+        fs::write(home.join(".hongdown.toml"), decoy).unwrap();
+        fs::write(home.join(".config/hongdown/config.toml"), decoy).unwrap();
+        create_config(root, decoy);
+        home
+    }
+
+    /// Test that `--no-config` ignores every configuration file and
+    /// environment variable, but still applies CLI overrides.
+    #[test]
+    fn test_no_config_ignores_decoys() {
+        let temp_dir = TempDir::new().unwrap();
+        let home = plant_decoy_configs(temp_dir.path());
+        create_markdown_file(temp_dir.path(), "test.md", "# Title\n\nSome text here.\n");
+
+        let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .args(["--no-config", "--fence-char", "`", "test.md"])
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("HONGDOWN_SETEXT", "false")
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "Title\n=====\n\nSome text here.\n"
+        );
+    }
+
+    /// Test that `--config` loads exactly the given file, or the
+    /// `.hongdown.toml` in the given directory, and ignores decoys.
+    #[test]
+    fn test_explicit_config_ignores_decoys() {
+        let temp_dir = TempDir::new().unwrap();
+        let home = plant_decoy_configs(temp_dir.path());
+        let ci = temp_dir.path().join("ci");
+        fs::create_dir_all(&ci).unwrap();
+        create_config(&ci, "[unordered_list]\nunordered_marker = \"*\"\n");
+        create_markdown_file(temp_dir.path(), "test.md", "# Title\n\n- item\n");
+
+        for config_arg in [ci.clone(), ci.join(".hongdown.toml")] {
+            let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+                .arg("--config")
+                .arg(&config_arg)
+                .arg("test.md")
+                .env("HOME", &home)
+                .env("XDG_CONFIG_HOME", home.join(".config"))
+                .current_dir(temp_dir.path())
+                .output()
+                .unwrap();
+
+            assert!(
+                output.status.success(),
+                "stderr: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap(),
+                "Title\n=====\n\n *  item\n"
+            );
+        }
+    }
+
+    /// Test that a missing `--config` path is an error rather than silently
+    /// falling back to the defaults.
+    #[test]
+    fn test_explicit_config_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        create_markdown_file(temp_dir.path(), "test.md", "Hello\n");
+
+        for missing in ["missing.toml", "."] {
+            let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+                .args(["--config", missing, "test.md"])
+                .current_dir(temp_dir.path())
+                .output()
+                .unwrap();
+
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!output.status.success());
+            assert!(output.stdout.is_empty());
+            assert!(
+                stderr.contains("configuration file not found"),
+                "stderr: {}",
+                stderr
+            );
+        }
+    }
+
+    /// Test that CLI overrides replace only the options they name, and take
+    /// precedence over environment variables, which in turn take precedence
+    /// over the configuration file.
+    #[test]
+    fn test_cli_and_env_overrides_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        create_config(
+            temp_dir.path(),
+            "no_inherit = true\nline_width = 40\n\n[heading]\nsetext_h1 = true\n",
+        );
+        let input =
+            "# Title\n\nThis paragraph is long enough to wrap at forty columns but not at sixty.\n";
+
+        let run = |args: &[&str], env: &[(&str, &str)]| {
+            let mut command = Command::new(env!("CARGO_BIN_EXE_hongdown"));
+            command
+                .args(args)
+                .arg("test.md")
+                .current_dir(temp_dir.path());
+            for (key, value) in env {
+                command.env(key, value);
+            }
+            fs::write(temp_dir.path().join("test.md"), input).unwrap();
+            let output = command.output().unwrap();
+            assert!(
+                output.status.success(),
+                "stderr: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        // Configuration file only
+        let result = run(&[], &[]);
+        assert!(result.starts_with("Title\n=====\n"));
+        assert!(
+            result.lines().all(|line| line.len() <= 40),
+            "got: {}",
+            result
+        );
+
+        // Environment variable beats the file; --no-setext keeps line_width
+        let result = run(&["--no-setext"], &[("HONGDOWN_LINE_WIDTH", "60")]);
+        assert!(result.starts_with("# Title\n"), "got: {}", result);
+        assert!(
+            result.lines().any(|line| line.len() > 40),
+            "got: {}",
+            result
+        );
+        assert!(
+            result.lines().all(|line| line.len() <= 60),
+            "got: {}",
+            result
+        );
+
+        // CLI option beats the environment variable
+        let result = run(&["--line-width", "100"], &[("HONGDOWN_LINE_WIDTH", "60")]);
+        assert_eq!(result.lines().count(), 4, "got: {}", result);
+    }
+
+    /// Test that invalid override values are rejected with the same message
+    /// as configuration files, naming the flag or variable.
+    #[test]
+    fn test_invalid_override_values() {
+        let temp_dir = TempDir::new().unwrap();
+        create_config(temp_dir.path(), "no_inherit = true\n");
+        create_markdown_file(temp_dir.path(), "test.md", "Hello\n");
+
+        let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .args(["--line-width", "5", "test.md"])
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(stderr.contains("--line-width"), "stderr: {}", stderr);
+        assert!(
+            stderr.contains("line_width must be at least 8, got 5."),
+            "stderr: {}",
+            stderr
+        );
+
+        let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .arg("test.md")
+            .env("HONGDOWN_FENCE_CHAR", "x")
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("invalid value for HONGDOWN_FENCE_CHAR"),
+            "stderr: {}",
+            stderr
+        );
+    }
+
+    /// Test that `--print-config` shows the effective configuration for
+    /// a path after all layers.
+    #[test]
+    fn test_print_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let docs = temp_dir.path().join("docs");
+        fs::create_dir_all(&docs).unwrap();
+        create_config(
+            temp_dir.path(),
+            "no_inherit = true\nline_width = 100\n\n[code_block]\nmin_fence_length = 5\n",
+        );
+        create_config(&docs, "no_inherit = true\nline_width = 72\n");
+
+        let output = Command::new(env!("CARGO_BIN_EXE_hongdown"))
+            .args(["--print-config", "docs/page.md", "--fence-char", "`"])
+            .env("HONGDOWN_WRAP", "preserve")
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let config = hongdown::config::Config::from_toml(&stdout).unwrap();
+        assert_eq!(config.line_width.get(), 72);
+        assert_eq!(config.wrap, hongdown::WrapMode::Preserve);
+        assert_eq!(config.code_block.fence_char, hongdown::FenceChar::Backtick);
+        assert_eq!(config.code_block.min_fence_length.get(), 4);
+    }
+
+    /// The numbers of lines removed and added from `before` to `after`.
+    fn changed_lines(before: &str, after: &str) -> (usize, usize) {
+        let diff = similar::TextDiff::from_lines(before, after);
+        let count = |tag| {
+            diff.iter_all_changes()
+                .filter(|change| change.tag() == tag)
+                .count()
+        };
+        (
+            count(similar::ChangeTag::Delete),
+            count(similar::ChangeTag::Insert),
+        )
+    }
+
+    const DIFF_FRIENDLY_DOCUMENT: &str = "\
+Release notes
+=============
+
+This release brings a new parser.  It is [faster] than the old one, and it
+handles nested lists better.  See the [manual] for details.
+
+Steps to upgrade:
+
+1. Back up your configuration.
+2. Install the new version.
+3. Run the migration tool.
+4. Restart the service.
+
+| Option | Default | Description |
+|--------|--------:|-------------|
+| `jobs` | 4 | Number of workers. |
+| `cache` | on | Whether to cache results. |
+
+[faster]: https://example.com/benchmarks
+[manual]: https://example.com/manual
+";
+
+    /// Format `document` in a directory whose configuration is the
+    /// `diff-friendly` preset.
+    fn format_diff_friendly(document: &str) -> String {
+        let temp_dir = TempDir::new().unwrap();
+        create_config(temp_dir.path(), "preset = \"diff-friendly\"\n");
+        create_markdown_file(temp_dir.path(), "test.md", document);
+        run_hongdown(&temp_dir.path().join("test.md"))
+    }
+
+    /// Test that editing a sentence with the `diff-friendly` preset changes
+    /// only the line of that sentence.
+    #[test]
+    fn test_diff_friendly_preset_edit_sentence() {
+        let before = format_diff_friendly(DIFF_FRIENDLY_DOCUMENT);
+        let after = format_diff_friendly(&DIFF_FRIENDLY_DOCUMENT.replace(
+            "It is [faster] than the old one",
+            "It is much [faster] than the old one, uses less memory, and starts up quicker",
+        ));
+        assert_eq!(changed_lines(&before, &after), (1, 1), "{}", after);
+        assert_eq!(format_diff_friendly(&after), after);
+    }
+
+    /// Test that adding an item in the middle of an ordered list, or making
+    /// a table cell wider, with the `diff-friendly` preset changes only the
+    /// line of that item or that row.
+    #[test]
+    fn test_diff_friendly_preset_add_list_item() {
+        let before = format_diff_friendly(DIFF_FRIENDLY_DOCUMENT);
+        let after = format_diff_friendly(&DIFF_FRIENDLY_DOCUMENT.replace(
+            "3. Run the migration tool.\n",
+            "3. Stop the service.\n4. Run the migration tool.\n",
+        ));
+        assert_eq!(changed_lines(&before, &after), (0, 1), "{}", after);
+        let after = format_diff_friendly(
+            &DIFF_FRIENDLY_DOCUMENT.replace("| 4 |", "| the number of CPUs |"),
+        );
+        assert_eq!(changed_lines(&before, &after), (1, 1), "{}", after);
+    }
 }
@@ -0,0 +1,128 @@
+//! Golden-file tests for the formatter.
+//!
+//! Each directory in *tests/corpus/* is a case with an *input.md* file, the
+//! *expected.md* file it must be formatted into, and optionally the
+//! *config.toml* file it is formatted with; without one, the defaults are
+//! used.  The cases are formatted with the `hongdown` binary, so that the
+//! configuration is read the way it is for users, and the test reports every
+//! case that fails, with a diff, rather than stopping at the first.
+//!
+//! Run the test with `UPDATE_SNAPSHOTS=1` to rewrite the *expected.md* files
+//! of the failing cases with the actual output instead, and review the
+//! changes with `git diff` before committing them.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use similar::TextDiff;
+
+/// Format `input` with the `hongdown` binary, using the configuration file
+/// at `config` if there is one, and return its output or its error message.
+fn run_hongdown(input: &str, config: Option<&Path>) -> Result<String, String> {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_hongdown"));
+    match config {
+        Some(path) => cmd.arg("--config").arg(path),
+        None => cmd.arg("--no-config"),
+    };
+    cmd.arg("-");
+    for (key, _) in std::env::vars() {
+        if key.starts_with("HONGDOWN_") {
+            cmd.env_remove(key);
+        }
+    }
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn hongdown");
+    let mut stdin = child.stdin.take().expect("Failed to get stdin");
+    stdin
+        .write_all(input.as_bytes())
+        .expect("Failed to write stdin");
+    drop(stdin);
+    let output = child.wait_with_output().expect("Failed to wait for output");
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+/// The directories of the cases, sorted by name.
+fn cases() -> Vec<PathBuf> {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut cases: Vec<_> = fs::read_dir(corpus)
+        .expect("Failed to read tests/corpus")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    cases.sort();
+    cases
+}
+
+/// Check the case in `dir`, returning a description of what is wrong with
+/// it, if anything.  With `update`, a wrong *expected.md* file is rewritten
+/// instead.
+fn check_case(dir: &Path, update: bool) -> Option<String> {
+    let input = match fs::read_to_string(dir.join("input.md")) {
+        Ok(input) => input,
+        Err(e) => return Some(format!("cannot read input.md: {}", e)),
+    };
+    let config = dir.join("config.toml");
+    let config = config.exists().then_some(config.as_path());
+    let actual = match run_hongdown(&input, config) {
+        Ok(actual) => actual,
+        Err(e) => return Some(format!("formatting failed:\n{}", e)),
+    };
+    let expected_path = dir.join("expected.md");
+    let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+    if actual != expected {
+        if update {
+            fs::write(&expected_path, &actual).expect("Failed to write expected.md");
+            return None;
+        }
+        let diff = TextDiff::from_lines(&expected, &actual);
+        return Some(format!(
+            "output differs from expected.md:\n{}",
+            diff.unified_diff().header("expected.md", "actual")
+        ));
+    }
+    match run_hongdown(&expected, config) {
+        Ok(again) if again == expected => None,
+        Ok(again) => {
+            let diff = TextDiff::from_lines(&expected, &again);
+            Some(format!(
+                "formatting expected.md changes it:\n{}",
+                diff.unified_diff().header("expected.md", "reformatted")
+            ))
+        }
+        Err(e) => Some(format!("formatting expected.md failed:\n{}", e)),
+    }
+}
+
+/// Test that every case in the corpus is formatted as expected, and that
+/// the expected output is stable.
+#[test]
+fn test_corpus() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|v| v == "1");
+    let cases = cases();
+    assert!(!cases.is_empty(), "tests/corpus has no cases");
+    let failures: Vec<_> = cases
+        .iter()
+        .filter_map(|dir| {
+            let name = dir.file_name().unwrap().to_string_lossy();
+            check_case(dir, update).map(|failure| format!("{}: {}", name, failure))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} corpus cases failed (run with UPDATE_SNAPSHOTS=1 to \
+         update expected.md files):\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}